@@ -30,9 +30,15 @@
 //!
 //! It provides the following modules:
 //!   * functions for establishing a connection consisting of [aggregated TCP links](net),
-//!   * [transport implementations](transport) for TCP and Bluetooth RFCOMM sockets,
+//!   * [transport implementations](transport) for TCP, UDP, SCTP, WebSocket, QUIC, Unix domain, vsock,
+//!     Bluetooth RFCOMM and L2CAP sockets,
+//!     Windows named pipes, serial ports, USB, SSH channels, Tor onion services, standard input and output,
+//!     child processes and in-memory streams for testing,
+//!   * [discovery](transport::discovery) of peers on the local network using mDNS,
 //!   * optional TLS link authentication and encryption,
+//!   * optional end-to-end [encryption](transport::encryption) of the aggregated connection,
 //!   * a text-based, interactive [connection and link montor](monitor),
+//!   * a [Prometheus metrics exporter](metrics),
 //!   * a [speed test](speed).
 //!
 //! The following command line tools are included:
@@ -49,6 +55,9 @@
 #[cfg(feature = "cli")]
 #[doc(hidden)]
 pub mod cli;
+#[cfg(feature = "metrics")]
+#[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+pub mod metrics;
 #[cfg(feature = "monitor")]
 #[cfg_attr(docsrs, doc(cfg(feature = "monitor")))]
 pub mod monitor;
@@ -0,0 +1,212 @@
+//! Prometheus metrics exporter.
+//!
+//! [`register`] wires the state of a connection into a [Prometheus registry](Registry).
+//! The metrics are derived from the [connection control](Control) each time the
+//! registry is gathered, for example when Prometheus scrapes it.
+//!
+//! The following metrics are provided:
+//!   * `aggligator_links` — number of active links,
+//!   * `aggligator_link_sent_bytes_total` — bytes sent over a link,
+//!   * `aggligator_link_received_bytes_total` — bytes received over a link,
+//!   * `aggligator_link_resent_packets_total` — packets resent over a link,
+//!   * `aggligator_link_roundtrip_seconds` — round trip time of a link,
+//!   * `aggligator_send_throughput_bytes` — aggregate send throughput in bytes per second,
+//!   * `aggligator_receive_throughput_bytes` — aggregate receive throughput in bytes per second.
+//!
+//! All metrics carry the constant label `conn` containing the connection id,
+//! so that multiple connections can be registered with the same registry.
+//! Link metrics are labeled with the `transport` name and link `direction`
+//! taken from the [link tag](crate::transport::LinkTag).
+//! Per-link metrics additionally carry the `link` label containing the link tag.
+//! Since the counters of a link start from zero when it is reconnected,
+//! Prometheus treats this as a counter reset.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use aggligator_util::transport::{Connector, tcp::TcpConnector};
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let mut connector = Connector::new();
+//!     connector.add(TcpConnector::new(["server".to_string()], 5900).await?);
+//!
+//!     let registry = prometheus::Registry::new();
+//!     aggligator_util::metrics::register(&connector.control(), &registry).unwrap();
+//!
+//!     // serve the registry for scraping
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use prometheus::{
+    core::{Collector, Desc},
+    proto::MetricFamily,
+    Gauge, GaugeVec, IntCounterVec, IntGaugeVec, Opts, Registry, Result,
+};
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use crate::transport::LinkTagBox;
+use aggligator::{
+    control::{Direction, LinkIntervalStats, LinkStats},
+    Control,
+};
+
+/// Registers metrics for the connection `control` with the Prometheus `registry`.
+///
+/// Fails if metrics for the same connection have already been registered.
+pub fn register<TX, RX>(control: &Control<TX, RX, LinkTagBox>, registry: &Registry) -> Result<()>
+where
+    TX: Send + 'static,
+    RX: Send + 'static,
+{
+    registry.register(Box::new(ControlCollector::new(control.clone())?))
+}
+
+/// Labels of link count metrics.
+const LINK_COUNT_LABELS: &[&str] = &["transport", "direction"];
+
+/// Labels of per-link metrics.
+const LINK_LABELS: &[&str] = &["transport", "direction", "link"];
+
+/// Collects metrics from a connection control.
+struct ControlCollector<TX, RX> {
+    control: Control<TX, RX, LinkTagBox>,
+    links: IntGaugeVec,
+    sent: IntCounterVec,
+    recved: IntCounterVec,
+    resent: IntCounterVec,
+    roundtrip: GaugeVec,
+    send_throughput: Gauge,
+    recv_throughput: Gauge,
+    update: Mutex<()>,
+}
+
+impl<TX, RX> ControlCollector<TX, RX> {
+    fn new(control: Control<TX, RX, LinkTagBox>) -> Result<Self> {
+        let opts = |name: &str, help: &str| Opts::new(name, help).const_label("conn", control.id().to_string());
+
+        Ok(Self {
+            links: IntGaugeVec::new(opts("aggligator_links", "Number of active links."), LINK_COUNT_LABELS)?,
+            sent: IntCounterVec::new(
+                opts("aggligator_link_sent_bytes_total", "Bytes sent over the link."),
+                LINK_LABELS,
+            )?,
+            recved: IntCounterVec::new(
+                opts("aggligator_link_received_bytes_total", "Bytes received over the link."),
+                LINK_LABELS,
+            )?,
+            resent: IntCounterVec::new(
+                opts("aggligator_link_resent_packets_total", "Packets resent over the link."),
+                LINK_LABELS,
+            )?,
+            roundtrip: GaugeVec::new(
+                opts("aggligator_link_roundtrip_seconds", "Round trip time of the link."),
+                LINK_LABELS,
+            )?,
+            send_throughput: Gauge::with_opts(opts(
+                "aggligator_send_throughput_bytes",
+                "Aggregate send throughput of all links in bytes per second.",
+            ))?,
+            recv_throughput: Gauge::with_opts(opts(
+                "aggligator_receive_throughput_bytes",
+                "Aggregate receive throughput of all links in bytes per second.",
+            ))?,
+            update: Mutex::new(()),
+            control,
+        })
+    }
+
+    /// Updates the metrics from the current state of the connection.
+    fn update(&self) {
+        self.links.reset();
+        self.sent.reset();
+        self.recved.reset();
+        self.resent.reset();
+        self.roundtrip.reset();
+
+        let mut link_counts: HashMap<(String, &str), i64> = HashMap::new();
+        let mut send_throughput = 0.;
+        let mut recv_throughput = 0.;
+
+        for link in self.control.links() {
+            let tag = link.tag();
+            let transport = tag.transport_name().to_string();
+            let direction = match tag.direction() {
+                Direction::Incoming => "incoming",
+                Direction::Outgoing => "outgoing",
+            };
+            let name = tag.to_string();
+            let labels = [transport.as_str(), direction, name.as_str()];
+
+            let stats = link.stats();
+            self.sent.with_label_values(&labels).inc_by(stats.total_sent);
+            self.recved.with_label_values(&labels).inc_by(stats.total_recved);
+            self.resent.with_label_values(&labels).inc_by(stats.total_resent);
+            self.roundtrip.with_label_values(&labels).set(stats.roundtrip.as_secs_f64());
+
+            if let Some(ts) = throughput_stats(&stats) {
+                send_throughput += ts.send_speed();
+                recv_throughput += ts.recv_speed();
+            }
+
+            *link_counts.entry((transport, direction)).or_default() += 1;
+        }
+
+        for ((transport, direction), count) in link_counts {
+            self.links.with_label_values(&[&transport, direction]).set(count);
+        }
+        self.send_throughput.set(send_throughput);
+        self.recv_throughput.set(recv_throughput);
+    }
+}
+
+/// Selects the statistics interval used for calculating throughput.
+///
+/// This is the shortest interval of at least one second or, if there is none,
+/// the longest interval.
+fn throughput_stats(stats: &LinkStats) -> Option<&LinkIntervalStats> {
+    let min = Duration::from_secs(1);
+    stats
+        .time_stats
+        .iter()
+        .filter(|ts| ts.interval >= min)
+        .min_by_key(|ts| ts.interval)
+        .or_else(|| stats.time_stats.iter().max_by_key(|ts| ts.interval))
+}
+
+impl<TX, RX> Collector for ControlCollector<TX, RX>
+where
+    TX: Send + 'static,
+    RX: Send + 'static,
+{
+    fn desc(&self) -> Vec<&Desc> {
+        [
+            self.links.desc(),
+            self.sent.desc(),
+            self.recved.desc(),
+            self.resent.desc(),
+            self.roundtrip.desc(),
+            self.send_throughput.desc(),
+            self.recv_throughput.desc(),
+        ]
+        .concat()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let _update = self.update.lock().unwrap();
+        self.update();
+
+        [
+            self.links.collect(),
+            self.sent.collect(),
+            self.recved.collect(),
+            self.resent.collect(),
+            self.roundtrip.collect(),
+            self.send_throughput.collect(),
+            self.recv_throughput.collect(),
+        ]
+        .concat()
+    }
+}
@@ -312,12 +312,29 @@ where
                         link.set_blocked(!link.is_blocked());
                     }
 
-                    if link.is_blocked() {
+                    if link.is_draining() {
+                        queue!(stdout(), Print(" draining".dark_yellow())).unwrap();
+                    } else if link.is_blocked() {
                         queue!(stdout(), Print(" blocked".dark_red())).unwrap();
                     } else if link.is_remotely_blocked() {
                         queue!(stdout(), Print(" remotely blocked".dark_red())).unwrap();
                     }
 
+                    let missed_pings = link.stats().missed_pings;
+                    if missed_pings > 0 {
+                        queue!(stdout(), Print(format!(" suspect ({missed_pings} missed pings)").dark_yellow()))
+                            .unwrap();
+                    }
+
+                    if let Some(rate_limit) = stats.rate_limit {
+                        queue!(
+                            stdout(),
+                            Print(" limited to".dark_yellow()),
+                            Print(format_speed(rate_limit as f64))
+                        )
+                        .unwrap();
+                    }
+
                     let hangs = link.stats().hangs;
                     if hangs > 0 {
                         queue!(stdout(), Print(format!(" ({hangs})").grey())).unwrap();
@@ -6,14 +6,20 @@ use std::{
     fmt::{self},
     future::IntoFuture,
     io::{Error, ErrorKind, Result},
-    sync::{Arc, Weak},
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
     time::Duration,
 };
 use tokio::{
     io::{AsyncRead, AsyncWrite},
-    sync::{broadcast, mpsc, oneshot, watch, Mutex, RwLock},
-    time::{sleep_until, Instant},
+    sync::{broadcast, mpsc, oneshot, watch, Mutex, RwLock, Semaphore},
+    task::JoinHandle,
+    time::{sleep_until, timeout, Instant},
 };
+use tracing::Instrument;
 
 use super::{
     BoxControl, BoxLink, BoxLinkError, BoxListener, BoxServer, BoxTask, IoBox, LinkError, LinkTag, LinkTagBox,
@@ -54,6 +60,16 @@ pub trait AcceptingTransport: Send + Sync + 'static {
     async fn link_filter(&self, _new: &BoxLink, _existing: &[BoxLink]) -> bool {
         true
     }
+
+    /// Preferred size of data segments sent over links of this transport.
+    ///
+    /// It is applied to each link when it is added to the connection and can be changed
+    /// afterwards using [`Link::set_segment_size`](aggligator::Link::set_segment_size).
+    /// By default `None` is returned and data is sent in segments of the
+    /// [IO write size](aggligator::cfg::Cfg::io_write_size).
+    fn segment_size(&self) -> Option<NonZeroUsize> {
+        None
+    }
 }
 
 type ArcAcceptingTransport = Arc<dyn AcceptingTransport>;
@@ -61,6 +77,31 @@ type ArcAcceptingTransport = Arc<dyn AcceptingTransport>;
 /// Function configuring the connection task of each incoming connection.
 type TaskCfgFn = Box<dyn Fn(&mut BoxTask) + Send + Sync + 'static>;
 
+/// Function authorizing incoming links.
+type LinkAuthorizerFn = Arc<dyn Fn(&LinkTagBox, &[u8]) -> LinkAuthorization + Send + Sync + 'static>;
+
+/// Decision of the [link authorizer](AcceptorBuilder::set_link_authorizer) about an incoming link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkAuthorization {
+    /// Accept the link.
+    Accept,
+    /// Accept the link, but only aggregate it with links accepted using the same group key.
+    AcceptGrouped(Vec<u8>),
+    /// Reject the link.
+    Reject,
+}
+
+impl LinkAuthorization {
+    /// The group key of an accepted link.
+    fn into_group(self) -> Option<Option<Vec<u8>>> {
+        match self {
+            Self::Accept => Some(None),
+            Self::AcceptGrouped(group) => Some(Some(group)),
+            Self::Reject => None,
+        }
+    }
+}
+
 /// A wrapper for an incoming link.
 #[async_trait]
 pub trait AcceptingWrapper: Send + Sync + fmt::Debug + 'static {
@@ -69,10 +110,75 @@ pub trait AcceptingWrapper: Send + Sync + fmt::Debug + 'static {
 
     /// Wraps the incoming IO stream.
     async fn wrap(&self, io: IoBox) -> Result<IoBox>;
+
+    /// Wraps the incoming IO stream and its link tag.
+    ///
+    /// This allows the wrapper to attach information about the wrapped link to its tag.
+    /// The returned link tag must compare equal to the passed link tag.
+    ///
+    /// By default this calls [`wrap`](Self::wrap) and returns the link tag unchanged.
+    async fn wrap_tagged(&self, io: IoBox, tag: LinkTagBox) -> Result<(IoBox, LinkTagBox)> {
+        Ok((self.wrap(io).await?, tag))
+    }
 }
 
 type BoxAcceptingWrapper = Box<dyn AcceptingWrapper>;
 
+/// Statistics of the handshakes performed by the connection wrappers of incoming links,
+/// for example the TLS handshake.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HandshakeStats {
+    /// Number of handshakes currently in progress.
+    pub pending: usize,
+    /// Number of completed handshakes.
+    pub completed: u64,
+    /// Number of failed handshakes.
+    pub failed: u64,
+    /// Number of handshakes that did not complete within the
+    /// [timeout](AcceptorBuilder::set_handshake_timeout).
+    pub timed_out: u64,
+    /// Number of incoming links dropped because the
+    /// [maximum number of pending handshakes](AcceptorBuilder::set_max_pending_handshakes)
+    /// was reached.
+    pub rejected: u64,
+}
+
+/// Limits and counters of the handshakes of incoming links.
+struct Handshakes {
+    timeout: Duration,
+    max_pending: usize,
+    permits: Arc<Semaphore>,
+    completed: AtomicU64,
+    failed: AtomicU64,
+    timed_out: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl Handshakes {
+    fn new(timeout: Duration, max_pending: usize) -> Self {
+        Self {
+            timeout,
+            max_pending,
+            permits: Arc::new(Semaphore::new(max_pending)),
+            completed: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            timed_out: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    fn stats(&self) -> HandshakeStats {
+        HandshakeStats {
+            pending: self.max_pending - self.permits.available_permits(),
+            completed: self.completed.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            timed_out: self.timed_out.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
 struct AcceptingTransportPack {
     transport: ArcAcceptingTransport,
     result_tx: oneshot::Sender<Result<()>>,
@@ -85,6 +191,11 @@ pub struct AcceptorBuilder {
     task_cfg: TaskCfgFn,
     wrappers: Vec<BoxAcceptingWrapper>,
     no_transport_timeout: Duration,
+    handshake_timeout: Duration,
+    max_pending_handshakes: usize,
+    link_authorizer: Option<LinkAuthorizerFn>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<super::encryption::Encryption>,
 }
 
 impl AcceptorBuilder {
@@ -92,7 +203,17 @@ impl AcceptorBuilder {
     pub fn new(cfg: Cfg) -> Self {
         let server = Server::new(cfg);
         let task_cfg: TaskCfgFn = Box::new(|_| ());
-        Self { server, task_cfg, wrappers: Vec::new(), no_transport_timeout: Duration::from_secs(30) }
+        Self {
+            server,
+            task_cfg,
+            wrappers: Vec::new(),
+            no_transport_timeout: Duration::from_secs(30),
+            handshake_timeout: Duration::from_secs(10),
+            max_pending_handshakes: 1024,
+            link_authorizer: None,
+            #[cfg(feature = "encryption")]
+            encryption: None,
+        }
     }
 
     /// Sets the function configuring the connection task of each incoming connection.
@@ -105,14 +226,95 @@ impl AcceptorBuilder {
         self.no_transport_timeout = no_transport_timeout;
     }
 
+    /// Sets the timeout for the handshakes performed by the connection wrappers of
+    /// an incoming link, for example the TLS handshake.
+    ///
+    /// The handshake of each incoming link is performed in a separate task, so that a slow
+    /// or stalled remote endpoint does not delay the acceptance of other links.
+    /// If the wrappers do not complete within this time, the link is dropped and a link error
+    /// of kind [`TimedOut`](ErrorKind::TimedOut) is reported.
+    /// Timeouts configured on individual wrappers apply within this timeout.
+    /// The default is 10 seconds.
+    pub fn set_handshake_timeout(&mut self, handshake_timeout: Duration) {
+        self.handshake_timeout = handshake_timeout;
+    }
+
+    /// Sets the maximum number of handshakes of incoming links that may be in progress
+    /// at the same time.
+    ///
+    /// This bounds the resources used when many connections are opened without completing
+    /// their handshakes.
+    /// When the limit is reached, further incoming links are dropped immediately and a link
+    /// error of kind [`ConnectionRefused`](ErrorKind::ConnectionRefused) is reported.
+    /// The default is 1024.
+    ///
+    /// # Panics
+    /// Panics if `max_pending_handshakes` is zero.
+    pub fn set_max_pending_handshakes(&mut self, max_pending_handshakes: usize) {
+        assert!(max_pending_handshakes > 0, "maximum number of pending handshakes must not be zero");
+        self.max_pending_handshakes = max_pending_handshakes;
+    }
+
+    /// Sets the function authorizing incoming links.
+    ///
+    /// It is called with the link tag and the [user data](super::LinkTag::user_data)
+    /// sent by the remote endpoint before the link is added to a connection.
+    /// If no link of a new incoming connection is accepted, the connection is refused.
+    /// Otherwise rejected links are refused by the connection.
+    /// In both cases the remote endpoint is notified of the refusal.
+    ///
+    /// A link accepted using [`LinkAuthorization::AcceptGrouped`] is only aggregated with
+    /// links accepted using the same group key.
+    /// The group key of a connection is determined by its first accepted link.
+    ///
+    /// This allows multiplexing many clients through one listening port by deriving the
+    /// group key from an application-provided value, such as a session token sent as user data.
+    /// Links are assigned to connections by the connecting side, thus each client connection
+    /// is accepted as a separate connection.
+    /// A link is never moved to another connection; a link whose group key differs from the
+    /// key of its connection is refused.
+    /// To route an accepted connection within the application, apply the same function to
+    /// any of the [links](aggligator::control::Control::links) of its control handle.
+    ///
+    /// While the function is being executed, the connection is blocked.
+    /// It should thus execute quickly.
+    pub fn set_link_authorizer(
+        &mut self, link_authorizer: impl Fn(&LinkTagBox, &[u8]) -> LinkAuthorization + Send + Sync + 'static,
+    ) {
+        self.link_authorizer = Some(Arc::new(link_authorizer));
+    }
+
     /// Adds a connection wrapper to the wrapper stack.
     pub fn wrap(&mut self, wrapper: impl AcceptingWrapper) {
         self.wrappers.push(Box::new(wrapper))
     }
 
+    /// Enables [connection-level encryption](super::encryption) of the aggregated stream.
+    ///
+    /// The encrypted stream is obtained using [`Acceptor::accept_stream`], while
+    /// [`Acceptor::accept`] fails.
+    /// The connector must be configured with the same pre-shared key.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub fn set_encryption(&mut self, encryption: super::encryption::Encryption) {
+        self.encryption = Some(encryption);
+    }
+
     /// Builds the acceptor.
     pub fn build(self) -> Acceptor {
-        let Self { server, task_cfg, wrappers, no_transport_timeout } = self;
+        let Self {
+            server,
+            task_cfg,
+            wrappers,
+            no_transport_timeout,
+            handshake_timeout,
+            max_pending_handshakes,
+            link_authorizer,
+            #[cfg(feature = "encryption")]
+            encryption,
+        } = self;
+
+        let handshakes = Arc::new(Handshakes::new(handshake_timeout, max_pending_handshakes));
 
         let active_transports = Arc::new(RwLock::new(Vec::<Weak<dyn AcceptingTransport>>::new()));
         let (transport_tx, transport_rx) = mpsc::unbounded_channel();
@@ -127,6 +329,7 @@ impl AcceptorBuilder {
             error_tx,
             transports_present_tx,
             wrappers,
+            handshakes.clone(),
         ));
 
         Acceptor {
@@ -138,6 +341,10 @@ impl AcceptorBuilder {
             error_rx,
             active_transports,
             no_transport_timeout,
+            handshakes,
+            link_authorizer,
+            #[cfg(feature = "encryption")]
+            encryption,
         }
     }
 }
@@ -154,6 +361,10 @@ pub struct Acceptor {
     active_transports: Arc<RwLock<Vec<Weak<dyn AcceptingTransport>>>>,
     error_rx: broadcast::Receiver<BoxLinkError>,
     no_transport_timeout: Duration,
+    handshakes: Arc<Handshakes>,
+    link_authorizer: Option<LinkAuthorizerFn>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<super::encryption::Encryption>,
 }
 
 impl fmt::Debug for Acceptor {
@@ -200,8 +411,44 @@ impl Acceptor {
     ///
     /// Returns the aggregated link channel and control handle.
     ///
+    /// This fails if [connection encryption](AcceptorBuilder::set_encryption) is enabled.
+    ///
     /// This function is cancel-safe.
     pub async fn accept(&self) -> Result<(Channel, BoxControl)> {
+        #[cfg(feature = "encryption")]
+        if self.encryption.is_some() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "connection encryption is enabled, use accept_stream instead",
+            ));
+        }
+
+        self.accept_channel().await
+    }
+
+    /// Waits for an incoming connection and accepts it.
+    ///
+    /// Returns the aggregated stream and control handle.
+    ///
+    /// If [connection encryption](AcceptorBuilder::set_encryption) is enabled, the encryption
+    /// handshake is performed and the encrypted stream is returned.
+    /// If the handshake fails, the connection is closed and the error is returned.
+    ///
+    /// This function is cancel-safe until a connection has been accepted.
+    pub async fn accept_stream(&self) -> Result<(IoBox, BoxControl)> {
+        let (channel, control) = self.accept_channel().await?;
+
+        #[cfg(feature = "encryption")]
+        if let Some(encryption) = &self.encryption {
+            return Ok((encryption.accept(channel).await?, control));
+        }
+
+        let (rx, tx) = channel.into_stream().into_split();
+        Ok((IoBox::new(rx, tx), control))
+    }
+
+    /// Waits for an incoming connection and accepts its channel.
+    async fn accept_channel(&self) -> Result<(Channel, BoxControl)> {
         // Set up timeout for no available transports.
         let mut transports_present_rx = self.transports_present_rx.clone();
         let no_transport_timeout = self.no_transport_timeout;
@@ -235,9 +482,38 @@ impl Acceptor {
 
         // Accept incoming connection.
         let mut listener = self.listener.lock().await;
-        let (mut task, channel, control) = tokio::select! {
-            res = listener.accept() => res?,
-            err = &mut timeout => return Err(err),
+        let (mut task, channel, control, group) = loop {
+            let mut incoming = tokio::select! {
+                res = listener.next() => res?,
+                err = &mut timeout => return Err(err),
+            };
+
+            // Authorize links of incoming connection.
+            let group = match &self.link_authorizer {
+                Some(link_authorizer) => {
+                    let tags: Vec<_> = incoming.link_tags().into_iter().cloned().collect();
+                    let user_datas = incoming.link_remote_user_datas();
+                    match tags
+                        .iter()
+                        .zip(user_datas)
+                        .find_map(|(tag, user_data)| link_authorizer(tag, user_data).into_group())
+                    {
+                        Some(group) => Some(group),
+                        None => {
+                            tracing::debug!(
+                                "refusing incoming connection {} with no authorized link",
+                                incoming.id()
+                            );
+                            incoming.refuse().await;
+                            continue;
+                        }
+                    }
+                }
+                None => None,
+            };
+
+            let (task, channel, control) = incoming.accept();
+            break (task, channel, control, group);
         };
 
         // Configure connection task.
@@ -245,9 +521,20 @@ impl Acceptor {
 
         // Configure link filter.
         let active_transports = self.active_transports.clone();
+        let link_authorization = self.link_authorizer.clone().zip(group);
         task.set_link_filter(move |link, others| {
             let active_transports = active_transports.clone();
+            let authorized = match &link_authorization {
+                Some((link_authorizer, group)) => {
+                    link_authorizer(link.tag(), link.remote_user_data()).into_group().as_ref() == Some(group)
+                }
+                None => true,
+            };
             async move {
+                if !authorized {
+                    tracing::debug!("link {} was not authorized", link.tag());
+                    return false;
+                }
                 let transports = active_transports.read_owned().await;
                 for transport in &*transports {
                     let Some(transport) = transport.upgrade() else { continue };
@@ -271,12 +558,19 @@ impl Acceptor {
         self.error_rx.resubscribe()
     }
 
+    /// Statistics of the handshakes performed by the connection wrappers of incoming links.
+    ///
+    /// Failed and timed out handshakes are also reported as [link errors](Self::link_errors).
+    pub fn handshake_stats(&self) -> HandshakeStats {
+        self.handshakes.stats()
+    }
+
     /// Task managing all listening transports.
     async fn task(
         server: BoxServer, active_transports: Arc<RwLock<Vec<Weak<dyn AcceptingTransport>>>>,
         mut transport_rx: mpsc::UnboundedReceiver<AcceptingTransportPack>,
         link_error_tx: broadcast::Sender<BoxLinkError>, transports_present_tx: watch::Sender<bool>,
-        wrappers: Vec<BoxAcceptingWrapper>,
+        wrappers: Vec<BoxAcceptingWrapper>, handshakes: Arc<Handshakes>,
     ) {
         let wrappers = Arc::new(wrappers);
         let mut transport_tasks = FuturesUnordered::new();
@@ -315,6 +609,7 @@ impl Acceptor {
                         transport_pack,
                         link_error_tx.clone(),
                         wrappers.clone(),
+                        handshakes.clone(),
                     ));
                 }
                 ListenerEvent::TaskEnded => (),
@@ -326,7 +621,7 @@ impl Acceptor {
     #[tracing::instrument(level="debug", skip_all, fields(id=%server.id(), transport=transport.transport.name()))]
     async fn transport_task(
         server: BoxServer, transport: AcceptingTransportPack, link_error_tx: broadcast::Sender<BoxLinkError>,
-        wrappers: Arc<Vec<BoxAcceptingWrapper>>,
+        wrappers: Arc<Vec<BoxAcceptingWrapper>>, handshakes: Arc<Handshakes>,
     ) {
         let AcceptingTransportPack { transport, result_tx, mut remove_rx } = transport;
 
@@ -337,7 +632,7 @@ impl Acceptor {
 
         let res = loop {
             // Accept incoming transport connection.
-            let AcceptedIoBox { io: mut io_box, tag } = tokio::select! {
+            let AcceptedIoBox { io: io_box, tag } = tokio::select! {
                 Some(accepted) = rx.recv() => accepted,
                 Some(()) = accepting_tasks.next() => continue,
                 res = &mut listener => break res,
@@ -349,25 +644,67 @@ impl Acceptor {
                 break Err(Error::new(ErrorKind::Other, "link tag transport name mismatch".to_string()));
             }
 
+            // Limit number of pending handshakes.
+            let Ok(handshake_permit) = handshakes.permits.clone().try_acquire_owned() else {
+                tracing::warn!(
+                    "dropping transport connection for tag {tag} because too many handshakes are pending"
+                );
+                handshakes.rejected.fetch_add(1, Ordering::Relaxed);
+                let err = Error::new(ErrorKind::ConnectionRefused, "too many pending handshakes");
+                let _ = link_error_tx.send(BoxLinkError::incoming(&tag, err));
+                continue;
+            };
+
             // Handle incoming connection in separate task.
-            let wrappers = &*wrappers;
+            let span = tracing::debug_span!("accept", %tag);
+            let segment_size = transport.segment_size();
+            let wrappers = wrappers.clone();
+            let handshakes = &*handshakes;
             let server = &server;
             let link_error_tx = &link_error_tx;
             let task = async move {
-                // Apply wrappers to IO stream.
-                for wrapper in wrappers {
-                    let name = wrapper.name();
-                    tracing::debug!("wrapping tag {tag} in {name}");
-
-                    match wrapper.wrap(io_box).await {
-                        Ok(wrapped) => io_box = wrapped,
-                        Err(err) => {
-                            tracing::debug!("wrapping tag {tag} in {name} failed: {err}");
-                            let _ = link_error_tx.send(BoxLinkError::incoming(&tag, err));
-                            return;
-                        }
+                // Apply wrappers to IO stream in separate task, so that a stalled handshake
+                // does not delay other links.
+                // The handshake is aborted when it times out or the transport is removed.
+                struct AbortHandshake<T>(JoinHandle<T>);
+                impl<T> Drop for AbortHandshake<T> {
+                    fn drop(&mut self) {
+                        self.0.abort();
                     }
                 }
+                let mut handshake = AbortHandshake(tokio::spawn(
+                    Self::handshake(wrappers, io_box, tag.clone()).in_current_span(),
+                ));
+
+                let res = timeout(handshakes.timeout, &mut handshake.0).await;
+                drop(handshake);
+                drop(handshake_permit);
+
+                let (io_box, tag) = match res {
+                    Ok(Ok(Ok(wrapped))) => {
+                        handshakes.completed.fetch_add(1, Ordering::Relaxed);
+                        wrapped
+                    }
+                    Ok(Ok(Err((tag, err)))) => {
+                        handshakes.failed.fetch_add(1, Ordering::Relaxed);
+                        let _ = link_error_tx.send(BoxLinkError::incoming(&tag, err));
+                        return;
+                    }
+                    Ok(Err(err)) => {
+                        tracing::warn!("handshake for tag {tag} failed: {err}");
+                        handshakes.failed.fetch_add(1, Ordering::Relaxed);
+                        let _ =
+                            link_error_tx.send(BoxLinkError::incoming(&tag, Error::new(ErrorKind::Other, err)));
+                        return;
+                    }
+                    Err(_) => {
+                        tracing::debug!("handshake for tag {tag} timed out");
+                        handshakes.timed_out.fetch_add(1, Ordering::Relaxed);
+                        let err = Error::new(ErrorKind::TimedOut, "handshake timeout");
+                        let _ = link_error_tx.send(BoxLinkError::incoming(&tag, err));
+                        return;
+                    }
+                };
 
                 // Add link to aggregated connection.
                 tracing::debug!("adding link for tag {tag} to connection");
@@ -381,6 +718,7 @@ impl Acceptor {
                         return;
                     }
                 };
+                link.set_segment_size(segment_size);
                 tracing::debug!("link for tag {tag} connected");
 
                 // Disconnect link when transport is removed.
@@ -397,11 +735,34 @@ impl Acceptor {
                 tracing::debug!("link for tag {tag} disconnected: {reason}");
                 let _ = link_error_tx.send(BoxLinkError::incoming(&tag, reason.into()));
             };
-            accepting_tasks.push(task);
+            accepting_tasks.push(task.instrument(span));
         };
 
         let _ = result_tx.send(res);
     }
+
+    /// Applies the connection wrappers to the IO stream of an incoming link.
+    ///
+    /// On failure the error is returned together with the tag of the last wrapper
+    /// that succeeded.
+    async fn handshake(
+        wrappers: Arc<Vec<BoxAcceptingWrapper>>, mut io_box: IoBox, mut tag: LinkTagBox,
+    ) -> std::result::Result<(IoBox, LinkTagBox), (LinkTagBox, Error)> {
+        for wrapper in &*wrappers {
+            let name = wrapper.name();
+            tracing::debug!("wrapping tag {tag} in {name}");
+
+            match wrapper.wrap_tagged(io_box, tag.clone()).await {
+                Ok((wrapped, wrapped_tag)) => (io_box, tag) = (wrapped, wrapped_tag),
+                Err(err) => {
+                    tracing::debug!("wrapping tag {tag} in {name} failed: {err}");
+                    return Err((tag, err));
+                }
+            }
+        }
+
+        Ok((io_box, tag))
+    }
 }
 
 /// A handle to a listening transport.
@@ -8,21 +8,28 @@ use futures::{
     FutureExt, StreamExt,
 };
 use std::{
-    collections::HashSet,
+    collections::{hash_map::RandomState, HashMap, HashSet},
+    error,
     fmt::{self, Debug},
-    future::IntoFuture,
+    future::{Future, IntoFuture},
+    hash::{BuildHasher, Hasher},
     io::{Error, ErrorKind, Result},
     iter,
-    sync::{Arc, Weak},
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
     time::Duration,
 };
 use tokio::{
-    sync::{broadcast, mpsc, oneshot, watch, RwLock},
-    time::sleep,
+    sync::{broadcast, broadcast::error::TryRecvError, mpsc, oneshot, watch, RwLock},
+    time::{sleep, timeout},
 };
+use tracing::Instrument;
 
-use super::{BoxControl, BoxLink, BoxLinkError, IoBox, LinkTag, LinkTagBox};
-use aggligator::{connect, Cfg, IoRxBox, IoTxBox, Link, Outgoing, Task};
+use super::{BoxControl, BoxLink, BoxLinkError, IoBox, LinkError, LinkTag, LinkTagBox};
+use aggligator::{alc::Channel, connect, id::ConnId, Cfg, IoRxBox, IoTxBox, Link, Outgoing, Task};
 
 /// A transport for connecting to remote endpoints.
 #[async_trait]
@@ -39,6 +46,16 @@ pub trait ConnectingTransport: Send + Sync + 'static {
     /// Connects a link tag.
     async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox>;
 
+    /// Connects a link tag and returns the link tag of the established link.
+    ///
+    /// This allows the transport to attach information about the established link to its tag.
+    /// The returned link tag must compare equal to the passed link tag.
+    ///
+    /// By default this calls [`connect`](Self::connect) and returns the link tag unchanged.
+    async fn connect_tagged(&self, tag: &dyn LinkTag) -> Result<(IoBox, LinkTagBox)> {
+        Ok((self.connect(tag).await?, tag.box_clone()))
+    }
+
     /// Checks whether a new link can be added given existing links.
     async fn link_filter(&self, _new: &Link<LinkTagBox>, _existing: &[Link<LinkTagBox>]) -> bool {
         true
@@ -48,6 +65,38 @@ pub trait ConnectingTransport: Send + Sync + 'static {
     ///
     /// This includes links by other transports as well.
     async fn connected_links(&self, _links: &[Link<LinkTagBox>]) {}
+
+    /// Groups link tags that are alternatives for establishing the same link.
+    ///
+    /// This is only used when [happy eyeballs](ConnectorBuilder::set_happy_eyeballs)
+    /// is enabled on the connector or the transport specifies a
+    /// [race attempt delay](Self::race_attempt_delay).
+    /// Connection attempts for the tags of a group are raced in the returned order
+    /// and only the first tag to connect successfully is used.
+    ///
+    /// By default each tag forms a group on its own.
+    fn race_groups(&self, tags: HashSet<LinkTagBox>) -> Vec<Vec<LinkTagBox>> {
+        tags.into_iter().map(|tag| vec![tag]).collect()
+    }
+
+    /// Delay between staggered connection attempts for the tags of a [race group](Self::race_groups).
+    ///
+    /// This is used when [happy eyeballs](ConnectorBuilder::set_happy_eyeballs) is not enabled
+    /// on the connector.
+    /// By default `None` is returned and all link tags are connected independently.
+    fn race_attempt_delay(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Preferred size of data segments sent over links of this transport.
+    ///
+    /// It is applied to each link when it is added to the connection and can be changed
+    /// afterwards using [`Link::set_segment_size`].
+    /// By default `None` is returned and data is sent in segments of the
+    /// [IO write size](Cfg::io_write_size).
+    fn segment_size(&self) -> Option<NonZeroUsize> {
+        None
+    }
 }
 
 type ArcConnectingTransport = Arc<dyn ConnectingTransport>;
@@ -60,6 +109,16 @@ pub trait ConnectingWrapper: Send + Sync + fmt::Debug + 'static {
 
     /// Wraps the outgoing IO stream.
     async fn wrap(&self, io: IoBox) -> Result<IoBox>;
+
+    /// Wraps the outgoing IO stream and its link tag.
+    ///
+    /// This allows the wrapper to attach information about the wrapped link to its tag.
+    /// The returned link tag must compare equal to the passed link tag.
+    ///
+    /// By default this calls [`wrap`](Self::wrap) and returns the link tag unchanged.
+    async fn wrap_tagged(&self, io: IoBox, tag: LinkTagBox) -> Result<(IoBox, LinkTagBox)> {
+        Ok((self.wrap(io).await?, tag))
+    }
 }
 
 type BoxConnectingWrapper = Box<dyn ConnectingWrapper>;
@@ -77,14 +136,30 @@ pub struct ConnectorBuilder {
     outgoing: Outgoing,
     control: BoxControl,
     reconnect_delay: Duration,
+    happy_eyeballs: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    link_attempt_timeout: Option<Duration>,
     wrappers: Vec<BoxConnectingWrapper>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<super::encryption::Encryption>,
 }
 
 impl ConnectorBuilder {
     /// Creates a new builder.
     pub fn new(cfg: Cfg) -> Self {
         let (task, outgoing, control) = connect(cfg);
-        Self { task, outgoing, control, reconnect_delay: Duration::from_secs(10), wrappers: Vec::new() }
+        Self {
+            task,
+            outgoing,
+            control,
+            reconnect_delay: Duration::from_secs(10),
+            happy_eyeballs: None,
+            connect_timeout: None,
+            link_attempt_timeout: None,
+            wrappers: Vec::new(),
+            #[cfg(feature = "encryption")]
+            encryption: None,
+        }
     }
 
     /// Accesses the connection manager task.
@@ -97,14 +172,81 @@ impl ConnectorBuilder {
         self.reconnect_delay = reconnect_delay
     }
 
+    /// Enables racing of connection attempts for alternative link tags (happy eyeballs).
+    ///
+    /// When enabled, link tags that a transport [groups](ConnectingTransport::race_groups)
+    /// as alternatives for the same link, for example the resolved IPv6 and IPv4 addresses
+    /// of a host, are connected in a staggered fashion as described in RFC 8305.
+    /// The next attempt is started when the previous one fails or after `attempt_delay` has
+    /// elapsed, whichever comes first.
+    /// The first attempt to succeed is used for the link and all others are cancelled.
+    /// Failed attempts are reported as [link errors](Connector::link_errors).
+    ///
+    /// RFC 8305 recommends an attempt delay of 250 ms.
+    ///
+    /// By default this is disabled and link tags are only raced if the transport
+    /// specifies a [race attempt delay](ConnectingTransport::race_attempt_delay) itself.
+    /// If enabled, `attempt_delay` takes precedence over the delay specified by the transport.
+    pub fn set_happy_eyeballs(&mut self, attempt_delay: Option<Duration>) {
+        self.happy_eyeballs = attempt_delay;
+    }
+
+    /// Sets the timeout for establishing the connection.
+    ///
+    /// If no link has been established when the timeout elapses, waiting for the
+    /// [channel](Connector::channel_with_timeout) or [stream](Connector::stream) fails with an error
+    /// of kind [`TimedOut`](ErrorKind::TimedOut) containing a [`ConnectTimeoutError`],
+    /// which lists the link errors that occured while connecting.
+    ///
+    /// By default there is no timeout and establishing the connection only fails once the
+    /// [no link timeout](Cfg::no_link_timeout) of the connection has elapsed.
+    pub fn set_connect_timeout(&mut self, connect_timeout: Option<Duration>) {
+        self.connect_timeout = connect_timeout;
+    }
+
+    /// Sets the timeout for each attempt of establishing a link.
+    ///
+    /// An attempt consists of connecting the transport, applying the connection wrappers
+    /// and performing the link handshake.
+    /// When it does not complete within the timeout, it is aborted, reported as a
+    /// [link error](Connector::link_errors) and retried after the reconnect delay.
+    /// Thus a transport that stalls cannot hold up a link tag indefinitely.
+    ///
+    /// By default there is no timeout.
+    pub fn set_link_attempt_timeout(&mut self, link_attempt_timeout: Option<Duration>) {
+        self.link_attempt_timeout = link_attempt_timeout;
+    }
+
     /// Adds a connection wrapper to the wrapper stack.
     pub fn wrap(&mut self, wrapper: impl ConnectingWrapper) {
         self.wrappers.push(Box::new(wrapper))
     }
 
+    /// Enables [connection-level encryption](super::encryption) of the aggregated stream.
+    ///
+    /// The encrypted stream is obtained using [`Connector::stream`], while
+    /// [`Connector::channel`] becomes unavailable.
+    /// The acceptor must be configured with the same pre-shared key.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub fn set_encryption(&mut self, encryption: super::encryption::Encryption) {
+        self.encryption = Some(encryption);
+    }
+
     /// Builds the connector.
     pub fn build(self) -> Connector {
-        let Self { mut task, outgoing, control, reconnect_delay, wrappers } = self;
+        let Self {
+            mut task,
+            outgoing,
+            control,
+            reconnect_delay,
+            happy_eyeballs,
+            connect_timeout,
+            link_attempt_timeout,
+            wrappers,
+            #[cfg(feature = "encryption")]
+            encryption,
+        } = self;
 
         // Configure link filter.
         let active_transports = Arc::new(RwLock::new(Vec::<Weak<dyn ConnectingTransport>>::new()));
@@ -130,7 +272,11 @@ impl ConnectorBuilder {
         let (transport_tx, transport_rx) = mpsc::unbounded_channel();
         let (tags_tx, tags_rx) = watch::channel(HashSet::new());
         let (error_tx, error_rx) = broadcast::channel(1024);
+        let connect_errors_rx = error_tx.subscribe();
         let (disabled_tags_tx, disabled_tags_rx) = watch::channel(HashSet::new());
+        let (maintain_tx, maintain_rx) = watch::channel(None);
+        let active_links_tx = Arc::new(watch::channel(0).0);
+        let wrappers = Arc::new(wrappers);
 
         // Start connector task managing all transports.
         tokio::spawn(Connector::task(
@@ -139,12 +285,28 @@ impl ConnectorBuilder {
             transport_rx,
             tags_tx,
             disabled_tags_rx,
+            maintain_rx,
+            active_links_tx,
             error_tx,
             reconnect_delay,
-            wrappers,
+            happy_eyeballs,
+            link_attempt_timeout,
+            wrappers.clone(),
         ));
 
-        Connector { control, outgoing: Some(outgoing), transport_tx, tags_rx, error_rx, disabled_tags_tx }
+        Connector {
+            control,
+            outgoing: Some((outgoing, connect_errors_rx)),
+            connect_timeout,
+            transport_tx,
+            tags_rx,
+            error_rx,
+            disabled_tags_tx,
+            maintain_tx: Arc::new(maintain_tx),
+            wrappers,
+            #[cfg(feature = "encryption")]
+            encryption,
+        }
     }
 }
 
@@ -154,11 +316,16 @@ impl ConnectorBuilder {
 /// connection task.
 pub struct Connector {
     control: BoxControl,
-    outgoing: Option<Outgoing>,
+    outgoing: Option<(Outgoing, broadcast::Receiver<BoxLinkError>)>,
+    connect_timeout: Option<Duration>,
     transport_tx: mpsc::UnboundedSender<TransportPack>,
     tags_rx: watch::Receiver<HashSet<LinkTagBox>>,
     disabled_tags_tx: watch::Sender<HashSet<LinkTagBox>>,
     error_rx: broadcast::Receiver<BoxLinkError>,
+    maintain_tx: Arc<watch::Sender<Option<Maintain>>>,
+    wrappers: Arc<Vec<BoxConnectingWrapper>>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<super::encryption::Encryption>,
 }
 
 impl fmt::Debug for Connector {
@@ -201,11 +368,112 @@ impl Connector {
         ConnectingTransportHandle { name, result_rx, remove_tx }
     }
 
+    /// Adds a link using an already established IO stream.
+    ///
+    /// This allows using a connection that has been established outside of a transport,
+    /// for example a socket passed in by socket activation.
+    /// The [connection wrappers](ConnectorBuilder::wrap) are applied to the stream before
+    /// it is added to the connection under the specified link tag.
+    ///
+    /// The link is not reconnected when it fails.
+    pub async fn add_io(&self, io: IoBox, tag: impl LinkTag) -> Result<BoxLink> {
+        let (mut io, mut tag): (IoBox, LinkTagBox) = (io, Box::new(tag));
+        for wrapper in &*self.wrappers {
+            tracing::debug!("wrapping tag {tag} in {}", wrapper.name());
+            (io, tag) = wrapper.wrap_tagged(io, tag).await?;
+        }
+
+        tracing::debug!("adding link for tag {tag} to connection");
+        let IoBox { read, write } = io;
+        Ok(self.control.add_io(read, write, tag.clone(), &tag.user_data()).await?)
+    }
+
     /// Waits for the connection to be established and obtains the aggregated link channel.
     ///
-    /// If this has been called before `None` is returned.
+    /// This does not observe the [connect timeout](ConnectorBuilder::set_connect_timeout);
+    /// use [`channel_with_timeout`](Self::channel_with_timeout) for that.
+    ///
+    /// If this has been called before or [connection encryption](ConnectorBuilder::set_encryption)
+    /// is enabled, `None` is returned.
     pub fn channel(&mut self) -> Option<Outgoing> {
-        self.outgoing.take()
+        #[cfg(feature = "encryption")]
+        if self.encryption.is_some() {
+            return None;
+        }
+
+        self.outgoing.take().map(|(outgoing, _)| outgoing)
+    }
+
+    /// Waits for the connection to be established and obtains the aggregated link channel,
+    /// observing the connect timeout.
+    ///
+    /// If the [connect timeout](ConnectorBuilder::set_connect_timeout) elapses before
+    /// the connection is established, an error containing a [`ConnectTimeoutError`] is returned.
+    ///
+    /// If this or [`channel`](Self::channel) has been called before or
+    /// [connection encryption](ConnectorBuilder::set_encryption) is enabled, `None` is returned.
+    pub fn channel_with_timeout(&mut self) -> Option<BoxFuture<'static, Result<Channel>>> {
+        #[cfg(feature = "encryption")]
+        if self.encryption.is_some() {
+            return None;
+        }
+
+        Some(self.establish()?.boxed())
+    }
+
+    /// Waits for the outgoing connection to be established, observing the connect timeout.
+    fn establish(&mut self) -> Option<impl Future<Output = Result<Channel>>> {
+        let (outgoing, mut errors_rx) = self.outgoing.take()?;
+        let connect_timeout = self.connect_timeout;
+
+        Some(async move {
+            let Some(connect_timeout) = connect_timeout else { return Ok(outgoing.await?) };
+            match timeout(connect_timeout, outgoing.into_future()).await {
+                Ok(res) => Ok(res?),
+                Err(_) => {
+                    let mut link_errors: Vec<BoxLinkError> = Vec::new();
+                    loop {
+                        match errors_rx.try_recv() {
+                            Ok(err) => {
+                                link_errors.retain(|e| *e.tag != *err.tag);
+                                link_errors.push(err);
+                            }
+                            Err(TryRecvError::Lagged(_)) => (),
+                            Err(_) => break,
+                        }
+                    }
+                    Err(ConnectTimeoutError { link_errors }.into())
+                }
+            }
+        })
+    }
+
+    /// Waits for the connection to be established and obtains the aggregated stream.
+    ///
+    /// If [connection encryption](ConnectorBuilder::set_encryption) is enabled, the encryption
+    /// handshake is performed and the encrypted stream is returned.
+    ///
+    /// If this has been called before `None` is returned.
+    pub fn stream(&mut self) -> Option<BoxFuture<'static, Result<IoBox>>> {
+        let outgoing = self.establish()?;
+
+        #[cfg(feature = "encryption")]
+        let encryption = self.encryption.clone();
+
+        Some(
+            async move {
+                let channel = outgoing.await?;
+
+                #[cfg(feature = "encryption")]
+                if let Some(encryption) = encryption {
+                    return encryption.connect(channel).await;
+                }
+
+                let (rx, tx) = channel.into_stream().into_split();
+                Ok(IoBox::new(rx, tx))
+            }
+            .boxed(),
+        )
     }
 
     /// Obtains the connection control of the aggregated connection.
@@ -237,16 +505,47 @@ impl Connector {
         self.error_rx.resubscribe()
     }
 
+    /// Maintains the specified number of links.
+    ///
+    /// Instead of connecting all available link tags, new links are only established
+    /// while fewer than `target_links` links are connected or being connected.
+    /// When a link fails or a transport goes away, a link using another available tag
+    /// is established to take its place.
+    ///
+    /// Failed connection attempts of a tag are retried with exponential backoff, starting
+    /// at the [reconnect delay](ConnectorBuilder::set_reconnect_delay) and capped at the
+    /// [maximum reconnect delay](MaintainHandle::set_max_reconnect_delay).
+    /// Each retry delay is randomly jittered.
+    /// Each failed attempt is reported as a [link error](Self::link_errors).
+    ///
+    /// Links exceeding the target are not disconnected.
+    ///
+    /// The link count is maintained as long as the returned handle exists.
+    /// Afterwards the connector connects all available link tags again.
+    pub fn maintain(&self, target_links: usize) -> MaintainHandle {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        let maintain = Maintain {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            target_links,
+            max_reconnect_delay: DEFAULT_MAX_RECONNECT_DELAY,
+        };
+        self.maintain_tx.send_replace(Some(maintain));
+
+        MaintainHandle { id: maintain.id, maintain_tx: self.maintain_tx.clone() }
+    }
+
     /// Task for handling all transports.
     #[allow(clippy::too_many_arguments)]
     #[tracing::instrument(level="debug", skip_all, fields(id=%control.id()))]
     async fn task(
         control: BoxControl, active_transports: Arc<RwLock<Vec<Weak<dyn ConnectingTransport>>>>,
         mut transport_rx: mpsc::UnboundedReceiver<TransportPack>, tags_tx: watch::Sender<HashSet<LinkTagBox>>,
-        disabled_tags_rx: watch::Receiver<HashSet<LinkTagBox>>, link_error_tx: broadcast::Sender<BoxLinkError>,
-        reconnect_delay: Duration, wrappers: Vec<BoxConnectingWrapper>,
+        disabled_tags_rx: watch::Receiver<HashSet<LinkTagBox>>, maintain_rx: watch::Receiver<Option<Maintain>>,
+        active_links_tx: Arc<watch::Sender<usize>>, link_error_tx: broadcast::Sender<BoxLinkError>,
+        reconnect_delay: Duration, happy_eyeballs: Option<Duration>, link_attempt_timeout: Option<Duration>,
+        wrappers: Arc<Vec<BoxConnectingWrapper>>,
     ) {
-        let wrappers = Arc::new(wrappers);
         let mut transport_tasks = FuturesUnordered::new();
         let mut transport_tags: Vec<watch::Receiver<HashSet<LinkTagBox>>> = Vec::new();
 
@@ -312,8 +611,12 @@ impl Connector {
                         control.clone(),
                         transport_tags_tx,
                         disabled_tags_rx.clone(),
+                        maintain_rx.clone(),
+                        active_links_tx.clone(),
                         link_error_tx.clone(),
                         reconnect_delay,
+                        happy_eyeballs,
+                        link_attempt_timeout,
                         wrappers.clone(),
                     ));
                 }
@@ -324,16 +627,22 @@ impl Connector {
     }
 
     /// Task for handling a transport.
+    #[allow(clippy::too_many_arguments)]
     #[tracing::instrument(level="debug", skip_all, fields(id=%control.id(), transport=transport_pack.transport.name()))]
     async fn transport_task(
         transport_pack: TransportPack, control: BoxControl, tags_fw_tx: watch::Sender<HashSet<LinkTagBox>>,
         mut disabled_tags_rx: watch::Receiver<HashSet<LinkTagBox>>,
+        mut maintain_rx: watch::Receiver<Option<Maintain>>, active_links_tx: Arc<watch::Sender<usize>>,
         link_error_tx: broadcast::Sender<BoxLinkError>, reconnect_delay: Duration,
+        happy_eyeballs: Option<Duration>, link_attempt_timeout: Option<Duration>,
         wrappers: Arc<Vec<BoxConnectingWrapper>>,
     ) {
         let TransportPack { transport, result_tx, mut remove_rx } = transport_pack;
         let conn_id = control.id();
         let mut changed_control = control.clone();
+        let mut active_links_rx = active_links_tx.subscribe();
+        let mut failures: HashMap<LinkTagBox, u32> = HashMap::new();
+        let attempt_delay = happy_eyeballs.or_else(|| transport.race_attempt_delay());
 
         // Set up channel for getting tags.
         let (tags_tx, mut tags_rx) = watch::channel(HashSet::new());
@@ -350,6 +659,10 @@ impl Connector {
                 let links = control.links();
                 transport.connected_links(&links).await;
 
+                // Get link count to maintain.
+                let maintain = *maintain_rx.borrow_and_update();
+                active_links_rx.borrow_and_update();
+
                 // Get disabled tags and disconnect them.
                 let disabled_tags = disabled_tags_rx.borrow_and_update();
                 for link in &links {
@@ -359,6 +672,7 @@ impl Connector {
                 }
 
                 // Get and forward available tags from transport.
+                tags_changed |= tags_rx.has_changed().unwrap_or_default();
                 let tags = tags_rx.borrow_and_update().clone();
                 if tags_changed {
                     tracing::debug!(
@@ -369,66 +683,123 @@ impl Connector {
                     tags_changed = false;
                 }
 
-                // Connect available but unconnected tags.
-                for tag in tags {
-                    if tag.transport_name() != transport.name() {
-                        break 'outer Err(Error::new(
-                            ErrorKind::Other,
-                            "link tag transport name mismatch".to_string(),
-                        ));
-                    }
+                failures.retain(|tag, _| tags.contains(tag));
 
-                    if connecting_tags.contains(&tag)
-                        || disabled_tags.contains(&tag)
-                        || link_filter_rejected_tags.contains(&tag)
-                        || links.iter().any(|link| link.tag() == &tag)
+                if tags.iter().any(|tag| tag.transport_name() != transport.name()) {
+                    break 'outer Err(Error::new(
+                        ErrorKind::Other,
+                        "link tag transport name mismatch".to_string(),
+                    ));
+                }
+
+                // Group tags that are alternatives for the same link.
+                let groups = match attempt_delay {
+                    Some(_) => transport.race_groups(tags),
+                    None => tags.into_iter().map(|tag| vec![tag]).collect(),
+                };
+
+                // Connect available but unconnected link groups.
+                for group in groups {
+                    if group
+                        .iter()
+                        .any(|tag| connecting_tags.contains(tag) || links.iter().any(|link| link.tag() == tag))
                     {
                         continue;
                     }
 
-                    tracing::debug!("connecting tag: {tag}");
-                    connecting_tags.insert(tag.clone());
+                    let candidates: Vec<_> = group
+                        .into_iter()
+                        .filter(|tag| !disabled_tags.contains(tag) && !link_filter_rejected_tags.contains(tag))
+                        .collect();
+                    if candidates.is_empty() {
+                        continue;
+                    }
+
+                    // Obtain a slot within the link count to maintain.
+                    let Some(slot) = LinkSlot::acquire(&active_links_tx, maintain.map(|m| m.target_links)) else {
+                        continue;
+                    };
+
+                    // Back off exponentially from repeatedly failing tags.
+                    let retry_delay = match maintain {
+                        Some(maintain) => {
+                            let failures = candidates
+                                .iter()
+                                .filter_map(|tag| failures.get(tag))
+                                .max()
+                                .copied()
+                                .unwrap_or_default();
+                            backoff_delay(reconnect_delay, maintain.max_reconnect_delay, failures)
+                        }
+                        None => reconnect_delay,
+                    };
+                    let attempt = Attempt { slot, retry_delay };
+
+                    let tags = candidates.iter().map(|tag| tag.to_string()).collect::<Vec<_>>().join(" | ");
+                    tracing::debug!("connecting tag: {tags}");
+                    let span = tracing::debug_span!("connect", %tags);
+                    connecting_tags.extend(candidates.iter().cloned());
 
                     let connect_task = async {
-                        // Establish transport connection.
-                        tracing::debug!("establishing transport connection for tag {tag}");
-                        let mut io_box = match transport.connect(&*tag).await {
-                            Ok(io_box) => io_box,
-                            Err(err) => {
-                                tracing::debug!("connecting transport for tag {tag} failed: {err}");
-                                let _ = link_error_tx.send(BoxLinkError::outgoing(conn_id, &tag, err));
-                                sleep(reconnect_delay).await;
-                                return (tag, None);
+                        let establish = async {
+                            // Establish transport connection.
+                            let (mut tag, mut io_box) = Self::race_connect(
+                                &*transport,
+                                &candidates,
+                                attempt_delay.unwrap_or_default(),
+                                conn_id,
+                                &link_error_tx,
+                            )
+                            .await?;
+
+                            // Apply wrappers to IO stream.
+                            for wrapper in &*wrappers {
+                                let name = wrapper.name();
+                                tracing::debug!("wrapping tag {tag} in {name}");
+
+                                match wrapper.wrap_tagged(io_box, tag.clone()).await {
+                                    Ok((wrapped, wrapped_tag)) => (io_box, tag) = (wrapped, wrapped_tag),
+                                    Err(err) => {
+                                        tracing::debug!("wrapping tag {tag} in {name} failed: {err}");
+                                        let _ = link_error_tx.send(BoxLinkError::outgoing(conn_id, &tag, err));
+                                        return None;
+                                    }
+                                }
                             }
-                        };
-
-                        // Apply wrappers to IO stream.
-                        for wrapper in &*wrappers {
-                            let name = wrapper.name();
-                            tracing::debug!("wrapping tag {tag} in {name}");
 
-                            match wrapper.wrap(io_box).await {
-                                Ok(wrapped) => io_box = wrapped,
+                            // Add link to aggregated connection.
+                            tracing::debug!("adding link for tag {tag} to connection");
+                            let IoBox { read, write } = io_box;
+                            match control.add_io(read, write, tag.clone(), &tag.user_data()).await {
+                                Ok(link) => {
+                                    link.set_segment_size(transport.segment_size());
+                                    Some((tag, link))
+                                }
                                 Err(err) => {
-                                    tracing::debug!("wrapping tag {tag} in {name} failed: {err}");
-                                    let _ = link_error_tx.send(BoxLinkError::outgoing(conn_id, &tag, err));
-                                    sleep(reconnect_delay).await;
-                                    return (tag, None);
+                                    tracing::debug!("adding link for tag {tag} to connection failed: {err}");
+                                    let _ = link_error_tx.send(BoxLinkError::outgoing(conn_id, &tag, err.into()));
+                                    None
                                 }
                             }
-                        }
+                        };
 
-                        // Add link to aggregated connection.
-                        tracing::debug!("adding link for tag {tag} to connection");
-                        let IoBox { read, write } = io_box;
-                        let link = match control.add_io(read, write, tag.clone(), &tag.user_data()).await {
-                            Ok(link) => link,
-                            Err(err) => {
-                                tracing::debug!("adding link for tag {tag} to connection failed: {err}");
-                                let _ = link_error_tx.send(BoxLinkError::outgoing(conn_id, &tag, err.into()));
-                                sleep(reconnect_delay).await;
-                                return (tag, None);
-                            }
+                        let established = match link_attempt_timeout {
+                            Some(link_attempt_timeout) => match timeout(link_attempt_timeout, establish).await {
+                                Ok(established) => established,
+                                Err(_) => {
+                                    tracing::debug!("link attempt timed out");
+                                    for tag in &candidates {
+                                        let err = Error::new(ErrorKind::TimedOut, "link attempt timed out");
+                                        let _ = link_error_tx.send(BoxLinkError::outgoing(conn_id, tag, err));
+                                    }
+                                    None
+                                }
+                            },
+                            None => establish.await,
+                        };
+                        let Some((tag, link)) = established else {
+                            attempt.failed().await;
+                            return (candidates, None);
                         };
                         tracing::debug!("link for tag {tag} connected");
 
@@ -446,11 +817,12 @@ impl Connector {
                         let reason = link.disconnected().await;
                         tracing::debug!("link for tag {tag} disconnected: {reason}");
                         let _ = link_error_tx.send(BoxLinkError::outgoing(conn_id, &tag, reason.clone().into()));
+                        drop(attempt);
                         sleep_until.await;
 
-                        (tag, Some(reason))
+                        (candidates, Some((tag, reason)))
                     };
-                    connecting_tasks.push(connect_task);
+                    connecting_tasks.push(connect_task.instrument(span));
                 }
             }
 
@@ -459,13 +831,21 @@ impl Connector {
                 res = &mut tags_task => break res,
                 Ok(()) = &mut remove_rx => break Ok(()),
                 Ok(()) = disabled_tags_rx.changed() => (),
+                Ok(()) = maintain_rx.changed() => (),
+                Ok(()) = active_links_rx.changed() => (),
                 Ok(()) = tags_rx.changed() => tags_changed = true,
                 () = changed_control.links_changed() => (),
                 _ = control.terminated() => break Ok(()),
-                Some((tag, reason)) = connecting_tasks.next() => {
-                    connecting_tags.remove(&tag);
-                    match reason {
-                        Some(DisconnectReason::LinkFilter) => {
+                Some((tags, disconnected)) = connecting_tasks.next() => {
+                    for tag in &tags {
+                        connecting_tags.remove(tag);
+                        match &disconnected {
+                            Some(_) => failures.remove(tag),
+                            None => failures.insert(tag.clone(), failures.get(tag).copied().unwrap_or_default() + 1),
+                        };
+                    }
+                    match disconnected {
+                        Some((tag, DisconnectReason::LinkFilter)) => {
                             tracing::debug!("blocking tag {tag}");
                             link_filter_rejected_tags.insert(tag);
                         }
@@ -486,6 +866,207 @@ impl Connector {
         }
         let _ = result_tx.send(res);
     }
+
+    /// Connects to the first of the specified alternative link tags that succeeds.
+    ///
+    /// Connection attempts are started in order, each after the previous attempt has
+    /// failed or `attempt_delay` has elapsed.
+    /// Failed attempts are reported as link errors.
+    /// Once an attempt succeeds, all attempts still in progress are cancelled by dropping them,
+    /// which closes their sockets.
+    async fn race_connect(
+        transport: &dyn ConnectingTransport, tags: &[LinkTagBox], attempt_delay: Duration, conn_id: ConnId,
+        link_error_tx: &broadcast::Sender<BoxLinkError>,
+    ) -> Option<(LinkTagBox, IoBox)> {
+        let mut remaining = tags.iter().peekable();
+        let mut attempts = FuturesUnordered::new();
+
+        loop {
+            // Start next connection attempt.
+            if let Some(tag) = remaining.next() {
+                tracing::debug!("establishing transport connection for tag {tag}");
+                attempts.push(async move { (tag, transport.connect_tagged(&**tag).await) });
+            }
+
+            let next_attempt = sleep(attempt_delay);
+            tokio::pin!(next_attempt);
+
+            loop {
+                let more = remaining.peek().is_some();
+                if attempts.is_empty() {
+                    if more {
+                        break;
+                    }
+                    return None;
+                }
+
+                tokio::select! {
+                    Some((tag, res)) = attempts.next() => match res {
+                        Ok((io_box, connected_tag)) => return Some((connected_tag, io_box)),
+                        Err(err) => {
+                            tracing::debug!("connecting transport for tag {tag} failed: {err}");
+                            let _ = link_error_tx.send(BoxLinkError::outgoing(conn_id, tag, err));
+                            if more {
+                                break;
+                            }
+                        }
+                    },
+                    () = &mut next_attempt, if more => break,
+                }
+            }
+        }
+    }
+}
+
+/// The connection could not be established within the
+/// [connect timeout](ConnectorBuilder::set_connect_timeout).
+///
+/// This is returned as the inner error of an [`std::io::Error`] of kind [`TimedOut`](ErrorKind::TimedOut).
+#[derive(Debug, Clone)]
+pub struct ConnectTimeoutError {
+    /// The most recent error of each link tag that failed to connect.
+    pub link_errors: Vec<LinkError<LinkTagBox>>,
+}
+
+impl fmt::Display for ConnectTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "connect timeout")?;
+        for (n, err) in self.link_errors.iter().enumerate() {
+            write!(f, "{} {err}", if n == 0 { ":" } else { ";" })?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ConnectTimeoutError {}
+
+impl From<ConnectTimeoutError> for Error {
+    fn from(err: ConnectTimeoutError) -> Self {
+        Error::new(ErrorKind::TimedOut, err)
+    }
+}
+
+/// Default maximum delay between reconnection attempts when maintaining a link count.
+const DEFAULT_MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// Link count to maintain.
+#[derive(Debug, Clone, Copy)]
+struct Maintain {
+    id: u64,
+    target_links: usize,
+    max_reconnect_delay: Duration,
+}
+
+/// Delay before retrying a link tag that failed `failures` times in a row.
+///
+/// The delay doubles with each failure, is capped at `max` and then randomly
+/// jittered into the range from half of it to its full value.
+fn backoff_delay(base: Duration, max: Duration, failures: u32) -> Duration {
+    let delay = base.saturating_mul(2u32.saturating_pow(failures)).min(max);
+    let jitter = RandomState::new().build_hasher().finish() % 1024;
+    delay / 2 + (delay / 2).mul_f64(jitter as f64 / 1023.)
+}
+
+/// A link being connected or connected, counting towards the link count to maintain.
+struct LinkSlot(Arc<watch::Sender<usize>>);
+
+impl LinkSlot {
+    /// Acquires a slot, unless `target_links` links are already active.
+    fn acquire(active_links_tx: &Arc<watch::Sender<usize>>, target_links: Option<usize>) -> Option<Self> {
+        let acquired = active_links_tx.send_if_modified(|active| match target_links {
+            Some(target_links) if *active >= target_links => false,
+            _ => {
+                *active += 1;
+                true
+            }
+        });
+        acquired.then(|| Self(active_links_tx.clone()))
+    }
+}
+
+impl Drop for LinkSlot {
+    fn drop(&mut self) {
+        self.0.send_modify(|active| *active -= 1);
+    }
+}
+
+/// A connection attempt or link occupying a link slot.
+struct Attempt {
+    slot: LinkSlot,
+    retry_delay: Duration,
+}
+
+impl Attempt {
+    /// Releases the link slot and waits before the link tags may be retried.
+    async fn failed(self) {
+        let Self { slot, retry_delay } = self;
+        drop(slot);
+        sleep(retry_delay).await;
+    }
+}
+
+/// A handle for maintaining a number of links, returned by [`Connector::maintain`].
+///
+/// Dropping this stops maintaining the link count and the connector connects
+/// all available link tags again.
+#[must_use = "the link count is only maintained while the handle exists"]
+pub struct MaintainHandle {
+    id: u64,
+    maintain_tx: Arc<watch::Sender<Option<Maintain>>>,
+}
+
+impl fmt::Debug for MaintainHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MaintainHandle").field("target_links", &self.target_links()).finish()
+    }
+}
+
+impl MaintainHandle {
+    /// Modifies the maintained link count, if this handle is still current.
+    fn modify(&self, f: impl FnOnce(&mut Maintain)) {
+        self.maintain_tx.send_if_modified(|maintain| match maintain {
+            Some(maintain) if maintain.id == self.id => {
+                f(maintain);
+                true
+            }
+            _ => false,
+        });
+    }
+
+    /// The number of links to maintain.
+    ///
+    /// Returns zero if the link count has been superseded by another call of
+    /// [`Connector::maintain`].
+    pub fn target_links(&self) -> usize {
+        match *self.maintain_tx.borrow() {
+            Some(maintain) if maintain.id == self.id => maintain.target_links,
+            _ => 0,
+        }
+    }
+
+    /// Sets the number of links to maintain.
+    pub fn set_target_links(&self, target_links: usize) {
+        self.modify(|maintain| maintain.target_links = target_links);
+    }
+
+    /// Sets the maximum delay between reconnection attempts of a failing link tag.
+    ///
+    /// The default is 60 seconds.
+    pub fn set_max_reconnect_delay(&self, max_reconnect_delay: Duration) {
+        self.modify(|maintain| maintain.max_reconnect_delay = max_reconnect_delay);
+    }
+}
+
+impl Drop for MaintainHandle {
+    fn drop(&mut self) {
+        self.maintain_tx.send_if_modified(|maintain| match maintain {
+            Some(current) if current.id == self.id => {
+                *maintain = None;
+                true
+            }
+            _ => false,
+        });
+    }
 }
 
 /// A handle to a transport.
@@ -0,0 +1,718 @@
+//! Local service discovery using mDNS and DNS-SD.
+//!
+//! A [`DiscoveryAcceptor`] accepts TCP links and advertises itself as an instance
+//! of a DNS-SD service type on the local network using multicast DNS (RFC 6762, RFC 6763).
+//! A [`DiscoveryConnector`] periodically queries the local network for instances of
+//! the service type and connects to the discovered peers.
+//!
+//! Queries are sent on each local network interface and a link is established
+//! to each address of a peer that is reachable through an interface.
+//! This allows aggregating links over all networks shared by two devices.
+//! If the same address of a peer is discovered on several interfaces, only one
+//! link is established to it.
+//!
+//! Discovery is performed over IPv4 only.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use aggligator_util::transport::{Acceptor, Connector};
+//! use aggligator_util::transport::discovery::{DiscoveryAcceptor, DiscoveryConnector, DEFAULT_SERVICE_TYPE};
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     // On the first device.
+//!     let acceptor = Acceptor::new();
+//!     acceptor.add(DiscoveryAcceptor::new(DEFAULT_SERVICE_TYPE, "device-a", 0).await?);
+//!
+//!     // On the second device.
+//!     let mut connector = Connector::new();
+//!     let mut discovery = DiscoveryConnector::new(DEFAULT_SERVICE_TYPE)?;
+//!     discovery.set_instance_filter(|instance| instance == "device-a");
+//!     connector.add(discovery);
+//!     let ch = connector.channel().unwrap().await?;
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use async_trait::async_trait;
+use futures::future;
+use network_interface::Addr;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::{BTreeMap, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    net::{TcpListener, TcpSocket, UdpSocket},
+    sync::{mpsc, watch},
+    time::{interval, sleep, timeout_at, Instant},
+};
+
+use super::{
+    ip::{local_interfaces, use_proper_ipv4},
+    AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox,
+};
+use aggligator::control::Direction;
+
+static NAME: &str = "discovery";
+
+/// Default DNS-SD service type.
+pub const DEFAULT_SERVICE_TYPE: &str = "_aggligator._tcp";
+
+/// mDNS multicast group.
+const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+/// mDNS port.
+const MDNS_PORT: u16 = 5353;
+
+/// Time to wait for responses after sending a query.
+const RESPONSE_WAIT: Duration = Duration::from_secs(1);
+/// Interval for joining the mDNS multicast group on new interfaces.
+const JOIN_INTERVAL: Duration = Duration::from_secs(10);
+/// Time to live of advertised records in seconds.
+const TTL: u32 = 120;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Mask removing the unicast-response and cache-flush bits from a class.
+const CLASS_MASK: u16 = 0x7fff;
+
+/// Link tag for discovered link.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DiscoveryLinkTag {
+    /// Service instance name of the acceptor.
+    pub instance: String,
+    /// Local interface name.
+    ///
+    /// This is only set for outgoing links.
+    pub interface: Option<String>,
+    /// Local address.
+    pub local: Option<IpAddr>,
+    /// Remote address.
+    pub remote: SocketAddr,
+    /// Link direction.
+    pub direction: Direction,
+}
+
+impl fmt::Display for DiscoveryLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        match &self.interface {
+            Some(interface) => write!(f, "{}: {interface} {dir} {}", self.instance, self.remote),
+            None => write!(f, "{}: {dir} {}", self.instance, self.remote),
+        }
+    }
+}
+
+impl LinkTag for DiscoveryLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// Splits a service type, such as `_aggligator._tcp`, into the labels of its domain name.
+fn service_name(service_type: &str) -> Result<Vec<String>> {
+    let labels: Vec<String> = service_type.trim_end_matches('.').split('.').map(|s| s.to_string()).collect();
+    if labels.len() != 2 || !labels.iter().all(|label| label.starts_with('_') && label.len() <= 63) {
+        return Err(Error::new(ErrorKind::InvalidInput, "invalid DNS-SD service type"));
+    }
+    Ok(labels.into_iter().chain(["local".to_string()]).collect())
+}
+
+/// Local IPv4 interfaces with their address and netmask, excluding loopback interfaces.
+fn ipv4_interfaces() -> Result<Vec<(String, Ipv4Addr, Ipv4Addr)>> {
+    let mut ifaces: Vec<_> = local_interfaces()?
+        .into_iter()
+        .filter_map(|iface| match iface.addr {
+            Some(Addr::V4(addr)) if !addr.ip.is_loopback() => {
+                Some((iface.name, addr.ip, addr.netmask.unwrap_or(Ipv4Addr::BROADCAST)))
+            }
+            _ => None,
+        })
+        .collect();
+    ifaces.sort();
+    Ok(ifaces)
+}
+
+/// Returns whether `addr` is within the subnet of the interface address `ip`.
+fn in_subnet(addr: Ipv4Addr, ip: Ipv4Addr, netmask: Ipv4Addr) -> bool {
+    u32::from(addr) & u32::from(netmask) == u32::from(ip) & u32::from(netmask)
+}
+
+/// A peer discovered on an interface.
+struct Peer {
+    instance: String,
+    port: u16,
+    addrs: Vec<Ipv4Addr>,
+}
+
+/// Service discovery transport for outgoing connections.
+///
+/// Links are established over TCP to the discovered instances of a service type.
+#[derive(Clone)]
+pub struct DiscoveryConnector {
+    service: Vec<String>,
+    query_interval: Duration,
+    instance_filter: Arc<dyn Fn(&str) -> bool + Send + Sync>,
+}
+
+impl fmt::Debug for DiscoveryConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DiscoveryConnector")
+            .field("service", &self.service.join("."))
+            .field("query_interval", &self.query_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for DiscoveryConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.service.join("."))
+    }
+}
+
+impl DiscoveryConnector {
+    /// Creates a new service discovery transport for outgoing connections.
+    ///
+    /// It connects to all discovered instances of the DNS-SD service type `service_type`,
+    /// for example [`_aggligator._tcp`](DEFAULT_SERVICE_TYPE).
+    pub fn new(service_type: &str) -> Result<Self> {
+        Ok(Self {
+            service: service_name(service_type)?,
+            query_interval: Duration::from_secs(10),
+            instance_filter: Arc::new(|_| true),
+        })
+    }
+
+    /// Sets the interval for querying the local network for service instances.
+    pub fn set_query_interval(&mut self, query_interval: Duration) {
+        self.query_interval = query_interval;
+    }
+
+    /// Sets the filter function selecting which service instances to connect to.
+    ///
+    /// It receives the service instance name of a discovered acceptor.
+    /// By default all instances are connected to.
+    pub fn set_instance_filter(&mut self, instance_filter: impl Fn(&str) -> bool + Send + Sync + 'static) {
+        self.instance_filter = Arc::new(instance_filter);
+    }
+
+    /// Queries the service on the specified interface.
+    async fn query(&self, ip: Ipv4Addr, netmask: Ipv4Addr) -> Result<Vec<Peer>> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.bind(&SocketAddr::new(ip.into(), 0).into())?;
+        socket.set_multicast_if_v4(&ip)?;
+        socket.set_multicast_loop_v4(true)?;
+        socket.set_nonblocking(true)?;
+        let socket = UdpSocket::from_std(socket.into())?;
+
+        socket.send_to(&encode_query(&self.service), (MDNS_ADDR, MDNS_PORT)).await?;
+
+        let mut peers = Vec::new();
+        let deadline = Instant::now() + RESPONSE_WAIT;
+        let mut buf = vec![0; 9000];
+        while let Ok(res) = timeout_at(deadline, socket.recv_from(&mut buf)).await {
+            let (n, src) = res?;
+            let Some(msg) = Message::parse(&buf[..n]) else { continue };
+            if !msg.response {
+                continue;
+            }
+
+            let src = match src.ip() {
+                IpAddr::V4(src) => Some(src),
+                IpAddr::V6(_) => None,
+            };
+            for mut peer in msg.peers(&self.service) {
+                peer.addrs.retain(|addr| in_subnet(*addr, ip, netmask));
+                peer.addrs.extend(src);
+                peers.push(peer);
+            }
+        }
+
+        Ok(peers)
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for DiscoveryConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        loop {
+            let ifaces = ipv4_interfaces()?;
+            let results = future::join_all(ifaces.iter().map(|(_, ip, netmask)| self.query(*ip, *netmask))).await;
+
+            // De-duplicate peer addresses discovered on several interfaces.
+            let mut discovered = BTreeMap::new();
+            for ((name, ip, _), res) in ifaces.iter().zip(results) {
+                let peers = match res {
+                    Ok(peers) => peers,
+                    Err(err) => {
+                        tracing::debug!("querying {} on {name} failed: {err}", &self);
+                        continue;
+                    }
+                };
+
+                for peer in peers {
+                    if !(self.instance_filter)(&peer.instance) {
+                        continue;
+                    }
+
+                    for addr in peer.addrs {
+                        let remote = SocketAddr::new(addr.into(), peer.port);
+                        discovered.entry((peer.instance.clone(), remote)).or_insert_with(|| DiscoveryLinkTag {
+                            instance: peer.instance.clone(),
+                            interface: Some(name.clone()),
+                            local: Some((*ip).into()),
+                            remote,
+                            direction: Direction::Outgoing,
+                        });
+                    }
+                }
+            }
+
+            let tags: HashSet<LinkTagBox> =
+                discovered.into_values().map(|tag| Box::new(tag) as LinkTagBox).collect();
+            tx.send_if_modified(|v| {
+                if *v != tags {
+                    *v = tags;
+                    true
+                } else {
+                    false
+                }
+            });
+
+            sleep(self.query_interval).await;
+        }
+    }
+
+    async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
+        let tag: &DiscoveryLinkTag = tag.as_any().downcast_ref().unwrap();
+
+        let socket = TcpSocket::new_v4()?;
+        if let Some(local) = tag.local {
+            socket.bind(SocketAddr::new(local, 0))?;
+        }
+        let stream = socket.connect(tag.remote).await?;
+        let _ = stream.set_nodelay(true);
+
+        let (rh, wh) = stream.into_split();
+        Ok(IoBox::new(rh, wh))
+    }
+}
+
+/// Service discovery transport for incoming connections.
+///
+/// It accepts TCP links and advertises itself as an instance of a service type
+/// on all local network interfaces as long as it is part of an [acceptor](super::Acceptor).
+#[derive(Debug)]
+pub struct DiscoveryAcceptor {
+    service: Vec<String>,
+    instance: String,
+    listener: TcpListener,
+    mdns: UdpSocket,
+}
+
+impl fmt::Display for DiscoveryAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", &self.instance, self.service.join("."))
+    }
+}
+
+impl DiscoveryAcceptor {
+    /// Creates a new service discovery transport for incoming connections.
+    ///
+    /// It advertises itself as the instance `instance` of the DNS-SD service type
+    /// `service_type`, for example [`_aggligator._tcp`](DEFAULT_SERVICE_TYPE), and
+    /// listens for TCP connections on `port` on all IPv4 addresses.
+    /// If `port` is zero, a free port is chosen.
+    pub async fn new(service_type: &str, instance: &str, port: u16) -> Result<Self> {
+        let service = service_name(service_type)?;
+        if instance.is_empty() || instance.len() > 63 {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid DNS-SD service instance name"));
+        }
+
+        let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port)).await?;
+
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        socket.bind(&SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), MDNS_PORT).into())?;
+        socket.set_multicast_loop_v4(true)?;
+        socket.set_nonblocking(true)?;
+        let mdns = UdpSocket::from_std(socket.into())?;
+
+        Ok(Self { service, instance: instance.to_string(), listener, mdns })
+    }
+
+    /// The local port listening for TCP connections.
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().map(|addr| addr.port()).unwrap_or_default()
+    }
+
+    /// Joins the mDNS multicast group on all interfaces not joined yet.
+    fn join(&self, joined: &mut HashSet<Ipv4Addr>) -> Result<()> {
+        for (name, ip, _) in ipv4_interfaces()? {
+            if joined.contains(&ip) {
+                continue;
+            }
+            match self.mdns.join_multicast_v4(MDNS_ADDR, ip) {
+                Ok(()) => {
+                    tracing::debug!("advertising {} on {name}", &self);
+                    joined.insert(ip);
+                }
+                Err(err) if err.kind() == ErrorKind::AddrInUse => {
+                    joined.insert(ip);
+                }
+                Err(err) => tracing::debug!("joining mDNS group on {name} failed: {err}"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Responds to an mDNS query for the advertised service.
+    async fn respond(&self, query: &[u8], src: SocketAddr) -> Result<()> {
+        let Some(msg) = Message::parse(query) else { return Ok(()) };
+        if msg.response
+            || !msg.questions.iter().any(|(name, qtype)| {
+                (*qtype == TYPE_PTR || *qtype == TYPE_ANY) && names_equal(name, &self.service)
+            })
+        {
+            return Ok(());
+        }
+
+        let addrs: Vec<Ipv4Addr> = ipv4_interfaces()?.into_iter().map(|(_, ip, _)| ip).collect();
+        let host = vec![host_label(&self.instance), "local".to_string()];
+
+        // Queries not from the mDNS port are answered directly (legacy unicast).
+        let legacy = src.port() != MDNS_PORT;
+        let response = encode_response(
+            msg.id,
+            legacy.then_some(self.service.as_slice()),
+            &self.service,
+            &self.instance,
+            &host,
+            self.port(),
+            &addrs,
+        );
+        let dst = match legacy {
+            true => src,
+            false => SocketAddrV4::new(MDNS_ADDR, MDNS_PORT).into(),
+        };
+        self.mdns.send_to(&response, dst).await?;
+
+        tracing::trace!("answered query for {} from {src}", &self);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AcceptingTransport for DiscoveryAcceptor {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
+        let mut joined = HashSet::new();
+        let mut join_interval = interval(JOIN_INTERVAL);
+        let mut buf = vec![0; 9000];
+
+        loop {
+            tokio::select! {
+                res = self.listener.accept() => {
+                    let (stream, mut remote) = res?;
+                    let _ = stream.set_nodelay(true);
+                    use_proper_ipv4(&mut remote);
+
+                    let tag = DiscoveryLinkTag {
+                        instance: self.instance.clone(),
+                        interface: None,
+                        local: stream.local_addr().ok().map(|addr| addr.ip()),
+                        remote,
+                        direction: Direction::Incoming,
+                    };
+                    tracing::debug!("Accepted discovered connection {tag}");
+
+                    let (rh, wh) = stream.into_split();
+                    let _ = tx.send(AcceptedIoBox::new(rh, wh, tag)).await;
+                }
+                res = self.mdns.recv_from(&mut buf) => {
+                    let (n, src) = res?;
+                    if let Err(err) = self.respond(&buf[..n], src).await {
+                        tracing::debug!("answering query from {src} failed: {err}");
+                    }
+                }
+                _ = join_interval.tick() => self.join(&mut joined)?,
+            }
+        }
+    }
+}
+
+/// Derives a host name label from a service instance name.
+fn host_label(instance: &str) -> String {
+    let label: String =
+        instance.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' }).collect();
+    format!("{}-aggligator", label.trim_matches('-'))
+}
+
+/// Compares two domain names case-insensitively.
+fn names_equal(a: &[String], b: &[String]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eq_ignore_ascii_case(b))
+}
+
+/// Appends a domain name.
+fn encode_name(buf: &mut Vec<u8>, labels: &[impl AsRef<str>]) {
+    for label in labels {
+        let label = label.as_ref().as_bytes();
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label);
+    }
+    buf.push(0);
+}
+
+/// Appends a resource record.
+fn encode_record(buf: &mut Vec<u8>, name: &[impl AsRef<str>], rtype: u16, rdata: &[u8]) {
+    encode_name(buf, name);
+    buf.extend_from_slice(&rtype.to_be_bytes());
+    buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+    buf.extend_from_slice(&TTL.to_be_bytes());
+    buf.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    buf.extend_from_slice(rdata);
+}
+
+/// Encodes a DNS message header.
+fn encode_header(id: u16, flags: u16, questions: u16, answers: u16, additional: u16) -> Vec<u8> {
+    [id, flags, questions, answers, 0, additional].iter().flat_map(|v| v.to_be_bytes()).collect()
+}
+
+/// Encodes a query for instances of a service.
+fn encode_query(service: &[String]) -> Vec<u8> {
+    let mut buf = encode_header(0, 0, 1, 0, 0);
+    encode_name(&mut buf, service);
+    buf.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+    buf
+}
+
+/// Encodes a response advertising a service instance.
+fn encode_response(
+    id: u16, question: Option<&[String]>, service: &[String], instance: &str, host: &[String], port: u16,
+    addrs: &[Ipv4Addr],
+) -> Vec<u8> {
+    let instance_name: Vec<&str> = [instance].into_iter().chain(service.iter().map(|s| s.as_str())).collect();
+
+    let mut buf = encode_header(id, 0x8400, question.is_some().into(), 1, 2 + addrs.len() as u16);
+
+    if let Some(question) = question {
+        encode_name(&mut buf, question);
+        buf.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+    }
+
+    let mut ptr = Vec::new();
+    encode_name(&mut ptr, &instance_name);
+    encode_record(&mut buf, service, TYPE_PTR, &ptr);
+
+    let mut srv = [0u16, 0, port].iter().flat_map(|v| v.to_be_bytes()).collect();
+    encode_name(&mut srv, host);
+    encode_record(&mut buf, &instance_name, TYPE_SRV, &srv);
+
+    encode_record(&mut buf, &instance_name, TYPE_TXT, &[0]);
+
+    for addr in addrs {
+        encode_record(&mut buf, host, TYPE_A, &addr.octets());
+    }
+
+    buf
+}
+
+/// Record data of a parsed resource record.
+enum RecordData {
+    Ptr(Vec<String>),
+    Srv { port: u16, target: Vec<String> },
+    A(Ipv4Addr),
+    Other,
+}
+
+/// A parsed DNS message.
+struct Message {
+    id: u16,
+    response: bool,
+    questions: Vec<(Vec<String>, u16)>,
+    records: Vec<(Vec<String>, RecordData)>,
+}
+
+impl Message {
+    /// Parses a DNS message.
+    fn parse(msg: &[u8]) -> Option<Self> {
+        let mut r = MsgReader { msg, pos: 0 };
+
+        let id = r.u16()?;
+        let flags = r.u16()?;
+        let questions = r.u16()?;
+        let records = [r.u16()?, r.u16()?, r.u16()?].iter().map(|&n| usize::from(n)).sum::<usize>();
+
+        let mut this = Self { id, response: flags & 0x8000 != 0, questions: Vec::new(), records: Vec::new() };
+
+        for _ in 0..questions {
+            let name = r.name()?;
+            let qtype = r.u16()?;
+            let _class = r.u16()? & CLASS_MASK;
+            this.questions.push((name, qtype));
+        }
+
+        for _ in 0..records {
+            let name = r.name()?;
+            let rtype = r.u16()?;
+            let class = r.u16()? & CLASS_MASK;
+            let _ttl = r.u32()?;
+            let len = usize::from(r.u16()?);
+            let end = r.pos.checked_add(len).filter(|&end| end <= msg.len())?;
+
+            let data = match (class, rtype) {
+                (CLASS_IN, TYPE_PTR) => RecordData::Ptr(r.name()?),
+                (CLASS_IN, TYPE_SRV) => {
+                    let _priority = r.u16()?;
+                    let _weight = r.u16()?;
+                    RecordData::Srv { port: r.u16()?, target: r.name()? }
+                }
+                (CLASS_IN, TYPE_A) if len == 4 => RecordData::A(Ipv4Addr::from(r.u32()?)),
+                _ => RecordData::Other,
+            };
+            r.pos = end;
+
+            this.records.push((name, data));
+        }
+
+        Some(this)
+    }
+
+    /// Peers advertising the service in this message.
+    fn peers(&self, service: &[String]) -> Vec<Peer> {
+        let mut peers = Vec::new();
+
+        for (name, data) in &self.records {
+            let RecordData::Ptr(instance_name) = data else { continue };
+            if !names_equal(name, service) || !names_equal(instance_name.get(1..).unwrap_or_default(), service) {
+                continue;
+            }
+
+            for (name, data) in &self.records {
+                let RecordData::Srv { port, target } = data else { continue };
+                if !names_equal(name, instance_name) {
+                    continue;
+                }
+
+                let addrs = self
+                    .records
+                    .iter()
+                    .filter_map(|(name, data)| match data {
+                        RecordData::A(addr) if names_equal(name, target) => Some(*addr),
+                        _ => None,
+                    })
+                    .collect();
+                peers.push(Peer { instance: instance_name[0].clone(), port: *port, addrs });
+            }
+        }
+
+        peers
+    }
+}
+
+/// Reader for DNS messages.
+struct MsgReader<'a> {
+    msg: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> MsgReader<'a> {
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let data = self.msg.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(data)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Reads a possibly compressed domain name.
+    fn name(&mut self) -> Option<Vec<String>> {
+        let mut labels = Vec::new();
+        let mut pos = self.pos;
+        let mut jumped = false;
+
+        for _ in 0..128 {
+            let len = *self.msg.get(pos)?;
+            match len {
+                0 => {
+                    if !jumped {
+                        self.pos = pos + 1;
+                    }
+                    return Some(labels);
+                }
+                len if len & 0xc0 == 0xc0 => {
+                    let target = usize::from(u16::from_be_bytes([len & 0x3f, *self.msg.get(pos + 1)?]));
+                    if !jumped {
+                        self.pos = pos + 2;
+                        jumped = true;
+                    }
+                    pos = target;
+                }
+                len if len & 0xc0 == 0 => {
+                    let label = self.msg.get(pos + 1..pos + 1 + usize::from(len))?;
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    pos += 1 + usize::from(len);
+                }
+                _ => return None,
+            }
+        }
+
+        None
+    }
+}
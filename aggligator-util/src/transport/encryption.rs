@@ -0,0 +1,272 @@
+//! Connection-level encryption.
+//!
+//! This encrypts the data of an aggregated connection end-to-end, independently of the
+//! transports and wrappers used by its links.
+//! Thus data stays confidential even if individual links are plaintext or a single
+//! network path is compromised.
+//!
+//! Both endpoints must be configured with the same pre-shared key.
+//! Once per connection a handshake exchanging ephemeral X25519 keys is performed,
+//! providing forward secrecy.
+//! The session keys are derived using HKDF-SHA256 from the shared secret, the pre-shared
+//! key and the exchanged public keys.
+//! Each message sent over the connection is then encrypted and authenticated using
+//! ChaCha20-Poly1305.
+//!
+//! If the pre-shared keys of both endpoints do not match, the handshake fails with
+//! an error of kind [`PermissionDenied`](ErrorKind::PermissionDenied) and the connection
+//! is closed.
+//!
+//! Use [`ConnectorBuilder::set_encryption`](super::ConnectorBuilder::set_encryption) and
+//! [`AcceptorBuilder::set_encryption`](super::AcceptorBuilder::set_encryption) to enable
+//! encryption on a connector or acceptor.
+
+use bytes::Bytes;
+use futures::{ready, SinkExt, StreamExt};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305},
+    agreement::{agree_ephemeral, EphemeralPrivateKey, UnparsedPublicKey, X25519},
+    hkdf::{Salt, HKDF_SHA256},
+    rand::SystemRandom,
+};
+use std::{
+    fmt,
+    io::{Error, ErrorKind, Result},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::IoBox;
+use aggligator::alc::{Channel, ReceiverStream, SenderSink};
+
+/// Handshake message prefix identifying the encryption protocol and its version.
+const MAGIC: &[u8] = b"AGGENC\x00\x01";
+
+/// Length of an X25519 public key.
+const PUBLIC_KEY_LEN: usize = 32;
+
+/// Length of the authentication tag appended to each message.
+const TAG_LEN: usize = 16;
+
+/// Minimum length of the pre-shared key.
+const MIN_KEY_LEN: usize = 16;
+
+/// Connection-level encryption using a pre-shared key.
+#[derive(Clone)]
+pub struct Encryption {
+    key: Arc<Vec<u8>>,
+}
+
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Encryption").finish_non_exhaustive()
+    }
+}
+
+impl Encryption {
+    /// Creates a new connection encryption configuration using the specified pre-shared key.
+    ///
+    /// The key must be at least 16 bytes long and should be randomly generated.
+    pub fn new(key: impl AsRef<[u8]>) -> Result<Self> {
+        let key = key.as_ref();
+        if key.len() < MIN_KEY_LEN {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("pre-shared key must be at least {MIN_KEY_LEN} bytes long"),
+            ));
+        }
+        Ok(Self { key: Arc::new(key.to_vec()) })
+    }
+
+    /// Performs the encryption handshake on an outgoing connection.
+    ///
+    /// Returns the encrypted stream.
+    pub async fn connect(&self, channel: Channel) -> Result<IoBox> {
+        self.handshake(channel, true).await
+    }
+
+    /// Performs the encryption handshake on an incoming connection.
+    ///
+    /// Returns the encrypted stream.
+    pub async fn accept(&self, channel: Channel) -> Result<IoBox> {
+        self.handshake(channel, false).await
+    }
+
+    /// Performs the encryption handshake.
+    ///
+    /// The channel is dropped and thus the connection closed if the handshake fails.
+    async fn handshake(&self, channel: Channel, outgoing: bool) -> Result<IoBox> {
+        let (tx, mut rx) = channel.into_tx_rx();
+
+        // Exchange ephemeral public keys.
+        let rng = SystemRandom::new();
+        let private_key = EphemeralPrivateKey::generate(&X25519, &rng).map_err(|_| crypto_error())?;
+        let public_key = private_key.compute_public_key().map_err(|_| crypto_error())?;
+
+        let mut hello = MAGIC.to_vec();
+        hello.extend_from_slice(public_key.as_ref());
+        tx.send(hello.into()).await?;
+
+        let remote_hello = rx.recv().await?.ok_or_else(closed_error)?;
+        let remote_public_key = match remote_hello.strip_prefix(MAGIC) {
+            Some(key) if key.len() == PUBLIC_KEY_LEN => key,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "remote endpoint does not use connection encryption",
+                ))
+            }
+        };
+
+        // Derive session keys.
+        let (client_public_key, server_public_key) = match outgoing {
+            true => (public_key.as_ref(), remote_public_key),
+            false => (remote_public_key, public_key.as_ref()),
+        };
+        let (client_key, server_key) = agree_ephemeral(
+            private_key,
+            &UnparsedPublicKey::new(&X25519, remote_public_key),
+            crypto_error(),
+            |secret| {
+                let prk = Salt::new(HKDF_SHA256, &self.key).extract(secret);
+                let derive = |label: &[u8]| -> Result<LessSafeKey> {
+                    let info = [MAGIC, label, client_public_key, server_public_key];
+                    let okm = prk.expand(&info, &CHACHA20_POLY1305).map_err(|_| crypto_error())?;
+                    Ok(LessSafeKey::new(UnboundKey::from(okm)))
+                };
+                Ok((derive(b"client")?, derive(b"server")?))
+            },
+        )?;
+        let (mut sealer, mut opener) = match outgoing {
+            true => (Cipher::new(client_key), Cipher::new(server_key)),
+            false => (Cipher::new(server_key), Cipher::new(client_key)),
+        };
+
+        // Confirm that both endpoints derived the same keys.
+        tx.send(sealer.seal(&[])?).await?;
+        let confirmation = rx.recv().await?.ok_or_else(closed_error)?;
+        if opener.open(&confirmation).is_err() {
+            return Err(Error::new(ErrorKind::PermissionDenied, "connection encryption key mismatch"));
+        }
+
+        tracing::debug!("connection encryption established");
+
+        let max_size = tx.max_size().saturating_sub(TAG_LEN).max(1);
+        let reader = DecryptingReader { rx: rx.into_stream(), opener, buf: Bytes::new() };
+        let writer = EncryptingWriter { tx: tx.into_sink(), sealer, max_size };
+        Ok(IoBox::new(reader, writer))
+    }
+}
+
+fn crypto_error() -> Error {
+    Error::new(ErrorKind::Other, "cryptographic operation failed")
+}
+
+fn closed_error() -> Error {
+    Error::new(ErrorKind::UnexpectedEof, "connection closed during encryption handshake")
+}
+
+/// Authenticated cipher for one direction of a connection.
+///
+/// Messages are delivered reliably and in order by the connection,
+/// thus a message counter is used as nonce.
+struct Cipher {
+    key: LessSafeKey,
+    counter: u64,
+}
+
+impl Cipher {
+    fn new(key: LessSafeKey) -> Self {
+        Self { key, counter: 0 }
+    }
+
+    fn nonce(&mut self) -> Result<Nonce> {
+        let mut nonce = [0; 12];
+        nonce[4..].copy_from_slice(&self.counter.to_be_bytes());
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| Error::new(ErrorKind::Other, "message counter exhausted"))?;
+        Ok(Nonce::assume_unique_for_key(nonce))
+    }
+
+    fn seal(&mut self, data: &[u8]) -> Result<Bytes> {
+        let nonce = self.nonce()?;
+        let mut buf = Vec::with_capacity(data.len() + TAG_LEN);
+        buf.extend_from_slice(data);
+        self.key.seal_in_place_append_tag(nonce, Aad::empty(), &mut buf).map_err(|_| crypto_error())?;
+        Ok(buf.into())
+    }
+
+    fn open(&mut self, data: &[u8]) -> Result<Bytes> {
+        let nonce = self.nonce()?;
+        let mut buf = data.to_vec();
+        let len = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut buf)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "decryption of received data failed"))?
+            .len();
+        buf.truncate(len);
+        Ok(buf.into())
+    }
+}
+
+/// Decrypts received messages.
+struct DecryptingReader {
+    rx: ReceiverStream,
+    opener: Cipher,
+    buf: Bytes,
+}
+
+impl AsyncRead for DecryptingReader {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<Result<()>> {
+        let this = self.get_mut();
+
+        while this.buf.is_empty() {
+            match ready!(this.rx.poll_next_unpin(cx)) {
+                Some(Ok(data)) => this.buf = this.opener.open(&data)?,
+                Some(Err(err)) => return Poll::Ready(Err(err.into())),
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let len = this.buf.len().min(buf.remaining());
+        buf.put_slice(&this.buf.split_to(len));
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Encrypts messages for sending.
+struct EncryptingWriter {
+    tx: SenderSink,
+    sealer: Cipher,
+    max_size: usize,
+}
+
+impl AsyncWrite for EncryptingWriter {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        ready!(this.tx.poll_ready_unpin(cx))?;
+
+        let len = buf.len().min(this.max_size);
+        let data = this.sealer.seal(&buf[..len])?;
+        this.tx.start_send_unpin(data)?;
+
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        self.get_mut().tx.poll_flush_unpin(cx).map_err(|err| err.into())
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        self.get_mut().tx.poll_close_unpin(cx).map_err(|err| err.into())
+    }
+}
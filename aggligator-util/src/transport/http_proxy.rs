@@ -0,0 +1,132 @@
+//! HTTP CONNECT proxy client.
+
+use std::{
+    fmt,
+    io::{Error, ErrorKind, Result},
+    net::SocketAddr,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Maximum length of the response header of the proxy.
+const MAX_RESPONSE_LEN: usize = 8192;
+
+/// HTTP proxy that supports the `CONNECT` method.
+///
+/// Target host names are always passed to the proxy, which resolves them.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct HttpProxy {
+    addr: SocketAddr,
+    basic_auth: Option<(String, String)>,
+}
+
+impl fmt::Debug for HttpProxy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HttpProxy")
+            .field("addr", &self.addr)
+            .field("username", &self.basic_auth.as_ref().map(|(username, _)| username))
+            .finish()
+    }
+}
+
+impl fmt::Display for HttpProxy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "http://{}", self.addr)
+    }
+}
+
+impl HttpProxy {
+    /// Creates a new HTTP proxy configuration for the proxy at the specified address.
+    pub fn new(addr: SocketAddr) -> Self {
+        Self { addr, basic_auth: None }
+    }
+
+    /// Address of the proxy.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Sets the username and password for basic authentication with the proxy.
+    ///
+    /// By default no authentication is performed.
+    pub fn set_basic_auth(&mut self, username: impl Into<String>, password: impl Into<String>) {
+        self.basic_auth = Some((username.into(), password.into()));
+    }
+
+    /// Asks the proxy to open a tunnel to `target` using the `CONNECT` method.
+    ///
+    /// `target` is either a socket address or a host name with port.
+    /// Errors contain the address of the proxy.
+    pub(crate) async fn handshake<S>(&self, stream: &mut S, target: &str) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.handshake_inner(stream, target)
+            .await
+            .map_err(|err| Error::new(err.kind(), format!("HTTP proxy {}: {err}", self.addr)))
+    }
+
+    async fn handshake_inner<S>(&self, stream: &mut S, target: &str) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        // Send request.
+        let mut req = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+        if let Some((username, password)) = &self.basic_auth {
+            let credentials = base64_encode(format!("{username}:{password}").as_bytes());
+            req.push_str(&format!("Proxy-Authorization: Basic {credentials}\r\n"));
+        }
+        req.push_str("\r\n");
+        stream.write_all(req.as_bytes()).await?;
+
+        // Receive response header byte by byte, so that no tunnel data is consumed.
+        let mut resp = Vec::new();
+        while !resp.ends_with(b"\r\n\r\n") {
+            if resp.len() >= MAX_RESPONSE_LEN {
+                return Err(Error::new(ErrorKind::InvalidData, "response header too long"));
+            }
+            resp.push(stream.read_u8().await?);
+        }
+
+        // Parse status line.
+        let resp = String::from_utf8_lossy(&resp);
+        let status_line = resp.lines().next().unwrap_or_default();
+        let malformed = || Error::new(ErrorKind::InvalidData, format!("malformed status line: {status_line}"));
+        let mut parts = status_line.splitn(3, ' ');
+        let (Some(version), Some(code)) = (parts.next(), parts.next()) else { return Err(malformed()) };
+        if !version.starts_with("HTTP/1.") {
+            return Err(malformed());
+        }
+        let code: u16 = code.parse().map_err(|_| malformed())?;
+        let reason = parts.next().unwrap_or_default();
+
+        if !(200..300).contains(&code) {
+            let kind = match code {
+                403 | 407 => ErrorKind::PermissionDenied,
+                504 => ErrorKind::TimedOut,
+                _ => ErrorKind::Other,
+            };
+            return Err(Error::new(kind, format!("CONNECT to {target} failed: {code} {reason}")));
+        }
+
+        Ok(())
+    }
+}
+
+/// Encodes data using standard Base64 with padding.
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let n = u32::from(b[0]) << 16 | u32::from(b[1]) << 8 | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,324 @@
+//! Helper functions shared by IP-based transports.
+
+use async_trait::async_trait;
+#[cfg(any(feature = "tcp", feature = "udp", feature = "quic"))]
+use network_interface::{NetworkInterface, NetworkInterfaceConfig};
+use std::{
+    collections::HashSet,
+    fmt,
+    io::{Error, ErrorKind, Result},
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+};
+use tokio::net::lookup_host;
+
+/// IP protocol version.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IpVersion {
+    /// IP version 4.
+    IPv4,
+    /// IP version 6.
+    IPv6,
+    /// Both IP versions.
+    #[default]
+    Both,
+    /// Both IP versions, preferring IP version 6.
+    ///
+    /// Transports that do not support a preference treat this like [`Both`](Self::Both).
+    PreferIPv6,
+}
+
+impl IpVersion {
+    /// Create from "only" arguments.
+    pub fn from_only(only_ipv4: bool, only_ipv6: bool) -> Result<Self> {
+        match (only_ipv4, only_ipv6) {
+            (false, false) => Ok(Self::Both),
+            (true, false) => Ok(Self::IPv4),
+            (false, true) => Ok(Self::IPv6),
+            (true, true) => {
+                Err(Error::new(ErrorKind::InvalidInput, "IPv4 and IPv6 options are mutally exclusive"))
+            }
+        }
+    }
+
+    /// Whether only IPv4 should be supported.
+    pub fn is_only_ipv4(&self) -> bool {
+        matches!(self, Self::IPv4)
+    }
+
+    /// Whether only IPv6 should be supported.
+    pub fn is_only_ipv6(&self) -> bool {
+        matches!(self, Self::IPv6)
+    }
+}
+
+/// Gets the list of local network interfaces from the operating system.
+///
+/// Filters out interfaces that are most likely useless.
+#[cfg(any(feature = "tcp", feature = "udp", feature = "quic"))]
+pub(crate) fn local_interfaces() -> Result<Vec<NetworkInterface>> {
+    Ok(NetworkInterface::show()
+        .map_err(|err| Error::new(ErrorKind::Other, err.to_string()))?
+        .into_iter()
+        .filter(|iface| !iface.name.starts_with("ifb"))
+        .collect())
+}
+
+/// Returns the local interfaces and their addresses usable for connecting to target.
+///
+/// IPv6 link-local addresses are skipped, since they cannot be bound to
+/// without a scope id.
+#[cfg(any(feature = "udp", feature = "quic"))]
+pub(crate) fn local_addrs_for_target(target: SocketAddr) -> Result<Vec<(Vec<u8>, IpAddr)>> {
+    Ok(local_interfaces()?
+        .into_iter()
+        .filter_map(|iface| {
+            let ip = iface.addr?.ip();
+            let usable = match (ip, target.ip()) {
+                (IpAddr::V4(_), IpAddr::V4(_)) => true,
+                (IpAddr::V6(_), IpAddr::V6(_)) => !is_ipv6_link_local(ip),
+                _ => false,
+            };
+            (usable && !ip.is_unspecified() && ip.is_loopback() == target.ip().is_loopback())
+                .then_some((iface.name.into_bytes(), ip))
+        })
+        .collect())
+}
+
+/// Expands all hosts that do not specify a port number to one entry for each of the default ports.
+///
+/// Without default ports all hosts must specify a port number.
+pub(crate) fn hosts_with_default_ports(
+    hosts: impl IntoIterator<Item = String>, default_ports: &[u16],
+) -> Result<Vec<String>> {
+    let hosts: Vec<_> = hosts.into_iter().collect();
+
+    if hosts.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "at least one host is required"));
+    }
+    if default_ports.is_empty() && hosts.iter().any(|host| !host_has_port(host)) {
+        return Err(Error::new(ErrorKind::InvalidInput, "at least one port is required"));
+    }
+
+    Ok(hosts.into_iter().flat_map(|host| host_with_default_ports(host, default_ports)).collect())
+}
+
+/// Expands the host to one entry for each of the default ports if it does not specify a port number.
+pub(crate) fn host_with_default_ports(host: String, default_ports: &[u16]) -> Vec<String> {
+    if host_has_port(&host) {
+        return vec![host];
+    }
+
+    let mut hosts: Vec<String> = Vec::new();
+    for &port in default_ports {
+        let host = host_with_default_port(host.clone(), port);
+        if !hosts.contains(&host) {
+            hosts.push(host);
+        }
+    }
+    hosts
+}
+
+/// Appends the default port to the host if it does not specify a port number.
+///
+/// IPv6 addresses may be enclosed in brackets.
+pub(crate) fn host_with_default_port(mut host: String, default_port: u16) -> String {
+    if host.parse::<Ipv6Addr>().is_ok() {
+        host = format!("[{host}]");
+    }
+    if !host_has_port(&host) {
+        host.push_str(&format!(":{default_port}"));
+    }
+    host
+}
+
+/// Appends the port to the host, which must not specify a port number itself.
+#[cfg(feature = "tcp")]
+pub(crate) fn host_with_port(host: String, port: u16) -> Result<String> {
+    if host_has_port(&host) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("host {host} must not specify a port number when the port is given separately"),
+        ));
+    }
+    Ok(host_with_default_port(host, port))
+}
+
+/// Whether the host specifies a port number.
+///
+/// IPv6 addresses may be enclosed in brackets.
+fn host_has_port(host: &str) -> bool {
+    host.contains(':') && !host.ends_with(']') && host.parse::<Ipv6Addr>().is_err()
+}
+
+/// Resolves host names to socket addresses.
+///
+/// Implement this to use a custom name resolution mechanism, such as DNS-over-HTTPS,
+/// or to provide fixed addresses for testing.
+#[async_trait]
+pub trait Resolve: fmt::Debug + Send + Sync + 'static {
+    /// Resolves `host` to socket addresses.
+    ///
+    /// `host` is a host name or IP address including the port number,
+    /// for example `example.com:443`.
+    async fn resolve(&self, host: &str) -> Result<Vec<SocketAddr>>;
+}
+
+/// Resolves host names using the name resolution facilities of the operating system.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemResolver;
+
+#[async_trait]
+impl Resolve for SystemResolver {
+    async fn resolve(&self, host: &str) -> Result<Vec<SocketAddr>> {
+        Ok(lookup_host(host).await?.collect())
+    }
+}
+
+/// Resolve hosts to socket addresses of the specified IP version.
+#[cfg(any(feature = "udp", feature = "quic", feature = "websocket", feature = "sctp"))]
+pub(crate) async fn resolve_hosts(hosts: &[String], ip_version: IpVersion) -> Vec<SocketAddr> {
+    resolve_hosts_with(&SystemResolver, hosts, ip_version).await.0
+}
+
+/// Resolve hosts to socket addresses of the specified IP version using the specified resolver.
+///
+/// Also returns the hosts that could not be resolved together with the error.
+pub(crate) async fn resolve_hosts_with(
+    resolver: &dyn Resolve, hosts: &[String], ip_version: IpVersion,
+) -> (Vec<SocketAddr>, Vec<(String, Error)>) {
+    let mut all_addrs = HashSet::new();
+    let mut failed = Vec::new();
+
+    for host in hosts {
+        let addrs = match resolver.resolve(host).await {
+            Ok(addrs) => addrs,
+            Err(err) => {
+                failed.push((host.clone(), err));
+                continue;
+            }
+        };
+
+        let addrs: Vec<_> = addrs
+            .into_iter()
+            .filter(|addr| {
+                !((addr.is_ipv4() && ip_version.is_only_ipv6()) || (addr.is_ipv6() && ip_version.is_only_ipv4()))
+            })
+            .collect();
+        if addrs.is_empty() {
+            failed.push((host.clone(), Error::new(ErrorKind::NotFound, "no address of requested IP version")));
+        }
+        all_addrs.extend(addrs);
+    }
+
+    let mut all_addrs: Vec<_> = all_addrs.into_iter().collect();
+    all_addrs.sort();
+    (all_addrs, failed)
+}
+
+/// Maps an IPv4-mapped IPv6 address to a proper IPv4 address.
+pub(crate) fn use_proper_ipv4(addr: &mut SocketAddr) {
+    if let IpAddr::V6(ip) = addr.ip() {
+        if let Some(ip) = ip.to_ipv4_mapped() {
+            addr.set_ip(ip.into());
+        }
+    }
+}
+
+/// Finds the name of the local interface that has the specified IP address.
+#[cfg(any(feature = "tcp", feature = "udp", feature = "quic"))]
+pub(crate) fn interface_name_for_addr(addr: IpAddr) -> Result<Option<Vec<u8>>> {
+    Ok(local_interfaces()?.into_iter().find_map(|interface| {
+        interface.addr.map(|a| a.ip() == addr).unwrap_or_default().then_some(interface.name.into_bytes())
+    }))
+}
+
+/// Whether the IP address is an IPv6 link-local unicast address (`fe80::/10`).
+#[cfg(any(feature = "tcp", feature = "udp", feature = "quic"))]
+pub(crate) fn is_ipv6_link_local(addr: IpAddr) -> bool {
+    matches!(addr, IpAddr::V6(ip) if ip.segments()[0] & 0xffc0 == 0xfe80)
+}
+
+/// Removes the scope id from an IPv6 socket address.
+#[cfg(feature = "tcp")]
+pub(crate) fn without_scope(mut addr: SocketAddr) -> SocketAddr {
+    if let SocketAddr::V6(addr) = &mut addr {
+        addr.set_scope_id(0);
+    }
+    addr
+}
+
+/// Index of the local network interface with the specified name.
+///
+/// This is the scope id of IPv6 link-local addresses reachable over the interface.
+#[cfg(feature = "tcp")]
+pub(crate) fn interface_index(name: &[u8]) -> Option<u32> {
+    #[cfg(any(target_os = "linux", target_vendor = "apple"))]
+    {
+        nix::net::if_::if_nametoindex(&*String::from_utf8_lossy(name)).ok().filter(|&index| index != 0)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_vendor = "apple")))]
+    {
+        let _ = name;
+        None
+    }
+}
+
+/// Name of the local network interface with the specified index.
+#[cfg(feature = "tcp")]
+pub(crate) fn interface_name_for_index(index: u32) -> Option<Vec<u8>> {
+    #[cfg(any(target_os = "linux", target_vendor = "apple"))]
+    {
+        nix::net::if_::if_nameindex()
+            .ok()?
+            .iter()
+            .find(|iface| iface.index() == index)
+            .map(|iface| iface.name().to_bytes().to_vec())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_vendor = "apple")))]
+    {
+        let _ = index;
+        None
+    }
+}
+
+/// Finds the name of the local interface a socket bound to the specified local address uses.
+///
+/// For IPv6 link-local addresses the interface is determined by the scope id,
+/// since the same address may be assigned to multiple interfaces.
+#[cfg(feature = "tcp")]
+pub(crate) fn interface_name_for_local_addr(addr: SocketAddr) -> Result<Option<Vec<u8>>> {
+    match addr {
+        SocketAddr::V6(addr) if addr.scope_id() != 0 && is_ipv6_link_local(IpAddr::V6(*addr.ip())) => {
+            match interface_name_for_index(addr.scope_id()) {
+                Some(name) => Ok(Some(name)),
+                None => interface_name_for_addr(IpAddr::V6(*addr.ip())),
+            }
+        }
+        addr => interface_name_for_addr(addr.ip()),
+    }
+}
+
+/// Whether the IP address is within the network specified by address and prefix length.
+#[cfg(feature = "tcp")]
+pub(crate) fn addr_in_network(addr: IpAddr, network: IpAddr, prefix_len: u8) -> bool {
+    fn matches(addr: &[u8], network: &[u8], prefix_len: u8) -> bool {
+        let prefix_len = usize::from(prefix_len).min(addr.len() * 8);
+        let (bytes, bits) = (prefix_len / 8, prefix_len % 8);
+        if addr[..bytes] != network[..bytes] {
+            return false;
+        }
+        if bits == 0 {
+            return true;
+        }
+        let mask = 0xffu8 << (8 - bits);
+        addr[bytes] & mask == network[bytes] & mask
+    }
+
+    match (addr, network) {
+        (IpAddr::V4(addr), IpAddr::V4(network)) => matches(&addr.octets(), &network.octets(), prefix_len),
+        (IpAddr::V6(addr), IpAddr::V6(network)) => matches(&addr.octets(), &network.octets(), prefix_len),
+        _ => false,
+    }
+}
@@ -0,0 +1,347 @@
+//! Bluetooth L2CAP transport.
+//!
+//! L2CAP connection-oriented channels provide a much higher throughput
+//! than [RFCOMM](super::rfcomm).
+//! Both Bluetooth classic (BR/EDR) and Bluetooth Low Energy (LE) credit-based
+//! connection-oriented channels are supported; the [address type](AddressType)
+//! selects between them.
+//!
+//! Writes are split so that no write exceeds the MTU negotiated for the channel.
+//! The negotiated MTU is available from the [link tag](L2capLinkTag::mtu).
+//!
+//! Connecting to a PSM that is not registered on the remote device fails
+//! and is reported as a [link error](super::LinkError) by the connector.
+
+use async_trait::async_trait;
+use bluer::{
+    l2cap::{Listener, Socket, SocketAddr},
+    Adapter, Address, AddressType,
+};
+use futures::future;
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU16, Ordering as AtomicOrdering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::AsyncWrite,
+    sync::{mpsc, watch},
+    time::timeout,
+};
+
+use super::{AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox};
+use aggligator::control::Direction;
+
+static NAME: &str = "l2cap";
+
+/// Negotiated MTU of a link.
+///
+/// It is shared between all clones of a link tag and does not
+/// take part in comparing and hashing link tags.
+#[derive(Debug, Clone, Default)]
+struct NegotiatedMtu(Arc<AtomicU16>);
+
+impl NegotiatedMtu {
+    fn get(&self) -> Option<u16> {
+        match self.0.load(AtomicOrdering::SeqCst) {
+            0 => None,
+            mtu => Some(mtu),
+        }
+    }
+
+    fn set(&self, mtu: u16) {
+        self.0.store(mtu, AtomicOrdering::SeqCst);
+    }
+}
+
+impl PartialEq for NegotiatedMtu {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for NegotiatedMtu {}
+
+impl PartialOrd for NegotiatedMtu {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NegotiatedMtu {
+    fn cmp(&self, _other: &Self) -> Ordering {
+        Ordering::Equal
+    }
+}
+
+impl Hash for NegotiatedMtu {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+/// Link tag for Bluetooth L2CAP link.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct L2capLinkTag {
+    /// Name of local Bluetooth adapter.
+    pub adapter: String,
+    /// Remote Bluetooth address.
+    pub remote: Address,
+    /// Type of remote Bluetooth address.
+    pub addr_type: AddressType,
+    /// Protocol service multiplexer (PSM) of the channel.
+    pub psm: u16,
+    /// Link direction.
+    pub direction: Direction,
+    /// Negotiated send MTU.
+    mtu: NegotiatedMtu,
+}
+
+impl fmt::Display for L2capLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        let le = if self.is_le() { " LE" } else { "" };
+        write!(f, "{} {dir} {}{le} psm {}", self.adapter, self.remote, self.psm)?;
+        if let Some(mtu) = self.mtu() {
+            write!(f, " mtu {mtu}")?;
+        }
+        Ok(())
+    }
+}
+
+impl L2capLinkTag {
+    /// Creates a new link tag for a Bluetooth L2CAP link.
+    pub fn new(adapter: &str, remote: Address, addr_type: AddressType, psm: u16, direction: Direction) -> Self {
+        Self { adapter: adapter.to_string(), remote, addr_type, psm, direction, mtu: NegotiatedMtu::default() }
+    }
+
+    /// Whether this is a Bluetooth Low Energy link.
+    pub fn is_le(&self) -> bool {
+        self.addr_type != AddressType::BrEdr
+    }
+
+    /// The send MTU negotiated for the channel.
+    ///
+    /// For outgoing links this is `None` until the link has been connected
+    /// and is updated each time the link is reconnected.
+    pub fn mtu(&self) -> Option<u16> {
+        self.mtu.get()
+    }
+}
+
+impl LinkTag for L2capLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// Writer that limits each write to the MTU of the L2CAP channel.
+struct MtuWriter<W> {
+    inner: W,
+    mtu: usize,
+}
+
+impl<W> MtuWriter<W> {
+    fn new(inner: W, mtu: u16) -> Self {
+        Self { inner, mtu: mtu.max(1).into() }
+    }
+}
+
+impl<W> AsyncWrite for MtuWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        let len = buf.len().min(self.mtu);
+        Pin::new(&mut self.inner).poll_write(cx, &buf[..len])
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Bluetooth L2CAP transport for outgoing connections.
+#[derive(Debug, Clone)]
+pub struct L2capConnector {
+    adapter: String,
+    local: Address,
+    remote: Address,
+    addr_type: AddressType,
+    psm: u16,
+    connect_timeout: Duration,
+}
+
+impl L2capConnector {
+    /// Creates a new Bluetooth L2CAP transport for L2CAP connections.
+    ///
+    /// The transport establishes one connection from the specified adapter
+    /// to the L2CAP channel with the specified PSM on the remote device.
+    ///
+    /// By default a Bluetooth classic (BR/EDR) connection is established.
+    /// Use [`set_address_type`](Self::set_address_type) to connect to a
+    /// Bluetooth Low Energy device instead.
+    pub async fn new(adapter: &Adapter, remote: Address, psm: u16) -> Result<Self> {
+        Ok(Self {
+            adapter: adapter.name().to_string(),
+            local: adapter.address().await?,
+            remote,
+            addr_type: AddressType::BrEdr,
+            psm,
+            connect_timeout: Duration::from_secs(10),
+        })
+    }
+
+    /// Sets the type of the remote Bluetooth address.
+    ///
+    /// Use [`AddressType::LePublic`] or [`AddressType::LeRandom`] to establish an
+    /// LE credit-based connection-oriented channel.
+    /// In this case `psm` is the LE protocol service multiplexer (LE_PSM) of the remote device.
+    pub fn set_address_type(&mut self, addr_type: AddressType) {
+        self.addr_type = addr_type;
+    }
+
+    /// Sets the timeout for establishing a connection.
+    ///
+    /// The default is 10 seconds.
+    pub fn set_connect_timeout(&mut self, connect_timeout: Duration) {
+        self.connect_timeout = connect_timeout;
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for L2capConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        let tag = L2capLinkTag::new(&self.adapter, self.remote, self.addr_type, self.psm, Direction::Outgoing);
+        tx.send_replace([Box::new(tag) as Box<dyn LinkTag>].into_iter().collect());
+        future::pending().await
+    }
+
+    async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
+        let tag: &L2capLinkTag = tag.as_any().downcast_ref().unwrap();
+
+        let local_addr_type = if tag.is_le() { AddressType::LePublic } else { AddressType::BrEdr };
+        let socket = Socket::new_stream()?;
+        socket.bind(SocketAddr::new(self.local, local_addr_type, 0))?;
+
+        let remote = SocketAddr::new(tag.remote, tag.addr_type, tag.psm);
+        let stream = match timeout(self.connect_timeout, socket.connect(remote)).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(err)) if err.kind() == ErrorKind::ConnectionRefused => {
+                return Err(Error::new(
+                    ErrorKind::ConnectionRefused,
+                    format!("PSM {} is not registered on {}", tag.psm, tag.remote),
+                ))
+            }
+            Ok(Err(err)) => return Err(err),
+            Err(_) => return Err(Error::new(ErrorKind::TimedOut, "L2CAP connect timeout")),
+        };
+
+        let mtu = stream.as_ref().send_mtu()?;
+        tag.mtu.set(mtu);
+        tracing::debug!("L2CAP connection {tag} established");
+
+        let (rh, wh) = stream.into_split();
+        Ok(IoBox::new(rh, MtuWriter::new(wh, mtu)))
+    }
+}
+
+/// Bluetooth L2CAP transport for incoming connections.
+#[derive(Debug)]
+pub struct L2capAcceptor {
+    adapter: String,
+    psm: u16,
+    listener: Listener,
+}
+
+impl L2capAcceptor {
+    /// Creates a new Bluetooth L2CAP transport for incoming Bluetooth classic (BR/EDR) connections.
+    ///
+    /// It listens on the specified adapter for connections to the L2CAP channel with the specified PSM.
+    /// If `psm` is zero, a free dynamic PSM is allocated; use [`address`](Self::address)
+    /// to obtain it.
+    pub async fn new(adapter: &Adapter, psm: u16) -> Result<Self> {
+        Self::with_address_type(adapter, AddressType::BrEdr, psm).await
+    }
+
+    /// Creates a new Bluetooth L2CAP transport for incoming connections using the specified local address type.
+    ///
+    /// Use [`AddressType::LePublic`] to accept LE credit-based connection-oriented channels;
+    /// in this case `psm` is the LE protocol service multiplexer (LE_PSM).
+    pub async fn with_address_type(adapter: &Adapter, addr_type: AddressType, psm: u16) -> Result<Self> {
+        let listener = Listener::bind(SocketAddr::new(adapter.address().await?, addr_type, psm)).await?;
+        let psm = listener.as_ref().local_addr()?.psm;
+        Ok(Self { adapter: adapter.name().to_string(), psm, listener })
+    }
+
+    /// The local L2CAP socket address used for listening.
+    pub fn address(&self) -> Result<SocketAddr> {
+        self.listener.as_ref().local_addr()
+    }
+}
+
+#[async_trait]
+impl AcceptingTransport for L2capAcceptor {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
+        loop {
+            let (stream, remote) = self.listener.accept().await?;
+            let mtu = stream.as_ref().send_mtu()?;
+
+            let tag =
+                L2capLinkTag::new(&self.adapter, remote.addr, remote.addr_type, self.psm, Direction::Incoming);
+            tag.mtu.set(mtu);
+            tracing::debug!("Accepted L2CAP connection {tag}");
+
+            let (rh, wh) = stream.into_split();
+            let _ = tx.send(AcceptedIoBox::new(rh, MtuWriter::new(wh, mtu), tag)).await;
+        }
+    }
+}
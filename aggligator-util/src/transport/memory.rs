@@ -0,0 +1,358 @@
+//! In-memory transport.
+//!
+//! This transport connects a [`Connector`](super::Connector) and an
+//! [`Acceptor`](super::Acceptor) within the same process without using
+//! any sockets.
+//! It is intended for testing, in particular of link failover.
+//!
+//! A [`MemoryHub`] manages a set of named links.
+//! Links can be added and removed at runtime and the connection currently
+//! established over a link can be [killed](MemoryHub::kill_link) to simulate
+//! a link failure.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use aggligator_util::transport::{Acceptor, Connector};
+//! use aggligator_util::transport::memory::MemoryHub;
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let hub = MemoryHub::with_links(2);
+//!
+//!     let acceptor = Acceptor::new();
+//!     acceptor.add(hub.acceptor());
+//!
+//!     let mut connector = Connector::new();
+//!     connector.add(hub.connector());
+//!     let ch = connector.channel().unwrap().await?;
+//!
+//!     // Simulate failure of the first link.
+//!     hub.kill_link("link0");
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use async_trait::async_trait;
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{copy_bidirectional, duplex, split, DuplexStream},
+    sync::{mpsc, oneshot, watch, Mutex as AsyncMutex},
+};
+
+use super::{AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox};
+use aggligator::control::Direction;
+
+static NAME: &str = "memory";
+
+/// Default buffer size of the in-memory streams.
+const DEFAULT_BUFFER_SIZE: usize = 65_536;
+
+/// Link tag for in-memory link.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MemoryLinkTag {
+    /// Link name.
+    pub name: String,
+    /// Link direction.
+    pub direction: Direction,
+}
+
+impl fmt::Display for MemoryLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        write!(f, "{dir} {}", self.name)
+    }
+}
+
+impl MemoryLinkTag {
+    /// Creates a new link tag for an in-memory link.
+    pub fn new(name: &str, direction: Direction) -> Self {
+        Self { name: name.to_string(), direction }
+    }
+}
+
+impl LinkTag for MemoryLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// A connection that is waiting to be accepted.
+struct PendingConn {
+    name: String,
+    stream: DuplexStream,
+}
+
+struct Inner {
+    links: watch::Sender<BTreeSet<String>>,
+    kill_txs: Mutex<HashMap<String, Vec<oneshot::Sender<()>>>>,
+    buffer_size: usize,
+    accept_tx: mpsc::Sender<PendingConn>,
+    accept_rx: AsyncMutex<mpsc::Receiver<PendingConn>>,
+}
+
+/// Hub connecting in-memory transports.
+///
+/// Use [`connector`](Self::connector) and [`acceptor`](Self::acceptor) to
+/// obtain the transports for the outgoing and incoming side.
+/// The connector establishes one link for each link name registered with the hub.
+///
+/// Cloning the hub yields another handle to the same hub.
+#[derive(Clone)]
+pub struct MemoryHub {
+    inner: Arc<Inner>,
+}
+
+impl fmt::Debug for MemoryHub {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MemoryHub").field("links", &*self.inner.links.borrow()).finish()
+    }
+}
+
+impl Default for MemoryHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryHub {
+    /// Creates a new hub without any links.
+    pub fn new() -> Self {
+        Self::with_buffer_size(DEFAULT_BUFFER_SIZE)
+    }
+
+    /// Creates a new hub with `count` links named `link0`, `link1`, and so on.
+    pub fn with_links(count: usize) -> Self {
+        let hub = Self::new();
+        for i in 0..count {
+            hub.add_link(format!("link{i}"));
+        }
+        hub
+    }
+
+    /// Creates a new hub without any links using the specified buffer size for each link direction.
+    pub fn with_buffer_size(buffer_size: usize) -> Self {
+        let (accept_tx, accept_rx) = mpsc::channel(16);
+        Self {
+            inner: Arc::new(Inner {
+                links: watch::channel(BTreeSet::new()).0,
+                kill_txs: Mutex::new(HashMap::new()),
+                buffer_size,
+                accept_tx,
+                accept_rx: AsyncMutex::new(accept_rx),
+            }),
+        }
+    }
+
+    /// Returns the transport for outgoing connections.
+    pub fn connector(&self) -> MemoryConnector {
+        MemoryConnector { hub: self.clone() }
+    }
+
+    /// Returns the transport for incoming connections.
+    ///
+    /// All acceptors of a hub share the incoming connections.
+    pub fn acceptor(&self) -> MemoryAcceptor {
+        MemoryAcceptor { hub: self.clone() }
+    }
+
+    /// Names of the links currently registered with the hub.
+    pub fn links(&self) -> BTreeSet<String> {
+        self.inner.links.borrow().clone()
+    }
+
+    /// Adds a link.
+    ///
+    /// Returns `false` if a link with the same name is already present.
+    pub fn add_link(&self, name: impl Into<String>) -> bool {
+        let name = name.into();
+        self.inner.links.send_if_modified(|links| links.insert(name))
+    }
+
+    /// Removes a link and kills its connections.
+    ///
+    /// Returns `false` if no link with this name is present.
+    pub fn remove_link(&self, name: &str) -> bool {
+        let removed = self.inner.links.send_if_modified(|links| links.remove(name));
+        self.kill_link(name);
+        removed
+    }
+
+    /// Kills the connections currently established over the link with the specified name.
+    ///
+    /// Both ends observe the link as failed.
+    /// The link stays registered with the hub and is thus reconnected by the connector.
+    ///
+    /// Returns the number of connections that were killed.
+    pub fn kill_link(&self, name: &str) -> usize {
+        let kill_txs = self.inner.kill_txs.lock().unwrap().remove(name).unwrap_or_default();
+        kill_txs.into_iter().filter(|tx| !tx.is_closed()).count()
+    }
+
+    /// Creates a new connection over the named link and queues it for acceptance.
+    async fn connect(&self, name: &str) -> Result<DuplexStream> {
+        if !self.inner.links.borrow().contains(name) {
+            return Err(Error::new(ErrorKind::NotFound, format!("memory link {name} does not exist")));
+        }
+
+        let (local, near) = duplex(self.inner.buffer_size);
+        let (far, remote) = duplex(self.inner.buffer_size);
+
+        let (kill_tx, kill_rx) = oneshot::channel();
+        {
+            let mut kill_txs = self.inner.kill_txs.lock().unwrap();
+            let txs = kill_txs.entry(name.to_string()).or_default();
+            txs.retain(|tx| !tx.is_closed());
+            txs.push(kill_tx);
+        }
+
+        // Forward data until the link is killed, which drops both streams.
+        let task_name = name.to_string();
+        tokio::spawn(async move {
+            let (mut near, mut far) = (near, far);
+            tokio::select! {
+                res = copy_bidirectional(&mut near, &mut far) => {
+                    if let Err(err) = res {
+                        tracing::debug!("memory link {task_name} failed: {err}");
+                    }
+                }
+                _ = kill_rx => tracing::debug!("memory link {task_name} killed"),
+            }
+        });
+
+        self.inner
+            .accept_tx
+            .send(PendingConn { name: name.to_string(), stream: remote })
+            .await
+            .map_err(|_| Error::new(ErrorKind::ConnectionRefused, "memory hub closed"))?;
+
+        Ok(local)
+    }
+}
+
+/// In-memory transport for outgoing connections.
+///
+/// Obtain it from [`MemoryHub::connector`].
+#[derive(Debug, Clone)]
+pub struct MemoryConnector {
+    hub: MemoryHub,
+}
+
+impl MemoryConnector {
+    /// The hub this transport belongs to.
+    pub fn hub(&self) -> &MemoryHub {
+        &self.hub
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for MemoryConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        let mut links_rx = self.hub.inner.links.subscribe();
+
+        loop {
+            let tags: HashSet<LinkTagBox> = links_rx
+                .borrow_and_update()
+                .iter()
+                .map(|name| Box::new(MemoryLinkTag::new(name, Direction::Outgoing)) as Box<dyn LinkTag>)
+                .collect();
+            tx.send_if_modified(|v| {
+                if *v != tags {
+                    *v = tags;
+                    true
+                } else {
+                    false
+                }
+            });
+
+            // The hub keeps the sender alive.
+            links_rx.changed().await.unwrap();
+        }
+    }
+
+    async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
+        let tag: &MemoryLinkTag = tag.as_any().downcast_ref().unwrap();
+        let stream = self.hub.connect(&tag.name).await?;
+        let (rh, wh) = split(stream);
+        Ok(IoBox::new(rh, wh))
+    }
+}
+
+/// In-memory transport for incoming connections.
+///
+/// Obtain it from [`MemoryHub::acceptor`].
+#[derive(Debug, Clone)]
+pub struct MemoryAcceptor {
+    hub: MemoryHub,
+}
+
+impl MemoryAcceptor {
+    /// The hub this transport belongs to.
+    pub fn hub(&self) -> &MemoryHub {
+        &self.hub
+    }
+}
+
+#[async_trait]
+impl AcceptingTransport for MemoryAcceptor {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
+        loop {
+            let PendingConn { name, stream } = {
+                let mut accept_rx = self.hub.inner.accept_rx.lock().await;
+                // The hub keeps the sender alive.
+                accept_rx.recv().await.unwrap()
+            };
+
+            let tag = MemoryLinkTag::new(&name, Direction::Incoming);
+            tracing::debug!("Accepted memory connection {tag}");
+
+            let (rh, wh) = split(stream);
+            let _ = tx.send(AcceptedIoBox::new(rh, wh, tag)).await;
+        }
+    }
+}
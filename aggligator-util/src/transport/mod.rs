@@ -55,11 +55,12 @@
 use std::{
     any::Any,
     cmp::Ordering,
+    collections::BTreeMap,
     error::Error,
     fmt,
     fmt::{Debug, Display},
     hash::{Hash, Hasher},
-    io::Result,
+    io::{IoSlice, Result},
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
@@ -142,6 +143,33 @@ pub trait LinkTag: Debug + Display + Send + Sync + 'static {
 
     /// Hash this link tag.
     fn dyn_hash(&self, state: &mut dyn Hasher);
+
+    /// Information attached to this link tag by the connection wrapper with the specified name.
+    ///
+    /// Wrappers attach information to link tags when wrapping a link and provide
+    /// typed accessors for it.
+    /// Link tags created by transports return `None`.
+    fn wrapper_info(&self, _wrapper: &str) -> Option<&dyn Any> {
+        None
+    }
+
+    /// Application-specific metadata attached to the link.
+    ///
+    /// Use [`MetadataLinkTag`] to attach metadata to the link tags of a transport.
+    /// Link tags without metadata return `None`.
+    fn metadata(&self) -> Option<&LinkMetadata> {
+        None
+    }
+}
+
+impl dyn LinkTag {
+    /// Returns the link tag as the link tag type of a specific transport, if it is of that type.
+    ///
+    /// For example, the remote address of a TCP link can be obtained by downcasting
+    /// its tag to `TcpLinkTag`.
+    pub fn downcast_ref<T: LinkTag>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
 }
 
 impl PartialEq for dyn LinkTag {
@@ -185,7 +213,124 @@ impl Clone for LinkTagBox {
     }
 }
 
+/// Application-specific metadata of a link, mapping keys to values.
+pub type LinkMetadata = BTreeMap<String, String>;
+
+/// Link tag with attached application-specific metadata, such as region, cost or carrier name.
+///
+/// It behaves like the wrapped link tag, i.e. it can be downcast to the link tag type of the
+/// transport, and provides the metadata via [`LinkTag::metadata`].
+/// The metadata is appended to the displayed link tag and thus visible in the monitor.
+///
+/// By default the metadata is not considered when comparing and hashing link tags.
+/// Use [`set_compare_metadata`](Self::set_compare_metadata) to change this.
+#[derive(Debug, Clone)]
+pub struct MetadataLinkTag<T> {
+    tag: T,
+    metadata: LinkMetadata,
+    compare_metadata: bool,
+}
+
+impl<T> MetadataLinkTag<T>
+where
+    T: LinkTag + Clone,
+{
+    /// Attaches the specified metadata to a link tag.
+    pub fn new(tag: T, metadata: impl IntoIterator<Item = (String, String)>) -> Self {
+        Self { tag, metadata: metadata.into_iter().collect(), compare_metadata: false }
+    }
+
+    /// Sets whether the metadata is considered when comparing and hashing the link tag.
+    ///
+    /// If enabled, link tags of the same transport that differ only in their metadata
+    /// are distinct.
+    /// This should be set consistently for all link tags of a transport.
+    pub fn set_compare_metadata(&mut self, compare_metadata: bool) {
+        self.compare_metadata = compare_metadata;
+    }
+
+    /// The wrapped link tag.
+    pub fn tag(&self) -> &T {
+        &self.tag
+    }
+
+    /// The attached metadata.
+    pub fn metadata(&self) -> &LinkMetadata {
+        &self.metadata
+    }
+
+    /// Mutable access to the attached metadata.
+    pub fn metadata_mut(&mut self) -> &mut LinkMetadata {
+        &mut self.metadata
+    }
+}
+
+impl<T> Display for MetadataLinkTag<T>
+where
+    T: Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", &self.tag)?;
+        if !self.metadata.is_empty() {
+            let entries: Vec<_> = self.metadata.iter().map(|(key, value)| format!("{key}={value}")).collect();
+            write!(f, " {{{}}}", entries.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> LinkTag for MetadataLinkTag<T>
+where
+    T: LinkTag + Clone,
+{
+    fn transport_name(&self) -> &str {
+        self.tag.transport_name()
+    }
+
+    fn direction(&self) -> Direction {
+        self.tag.direction()
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        self.tag.user_data()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self.tag.as_any()
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let ord = self.tag.dyn_cmp(other);
+        if self.compare_metadata {
+            ord.then_with(|| Some(&self.metadata).cmp(&other.metadata()))
+        } else {
+            ord
+        }
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        self.tag.dyn_hash(state);
+        if self.compare_metadata {
+            Hash::hash(&self.metadata, &mut state);
+        }
+    }
+
+    fn wrapper_info(&self, wrapper: &str) -> Option<&dyn Any> {
+        self.tag.wrapper_info(wrapper)
+    }
+
+    fn metadata(&self) -> Option<&LinkMetadata> {
+        Some(&self.metadata)
+    }
+}
+
 /// A boxed IO stream.
+///
+/// Vectored writes are passed through to the writer, if it supports them.
 pub struct IoBox {
     /// Reader.
     pub read: ReadBox,
@@ -219,6 +364,14 @@ impl AsyncWrite for IoBox {
         Pin::new(&mut self.get_mut().write).poll_write(cx, buf)
     }
 
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context, bufs: &[IoSlice]) -> Poll<Result<usize>> {
+        Pin::new(&mut self.get_mut().write).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.write.is_write_vectored()
+    }
+
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
         Pin::new(&mut self.get_mut().write).poll_flush(cx)
     }
@@ -237,14 +390,95 @@ type BoxTask = Task<IoTxBox, IoRxBox, LinkTagBox>;
 type BoxLink = Link<LinkTagBox>;
 type BoxLinkError = LinkError<LinkTagBox>;
 
+#[cfg(any(feature = "tcp", feature = "udp", feature = "quic", feature = "sctp"))]
+mod ip;
+
+#[cfg(feature = "tcp")]
+mod http_proxy;
+
+#[cfg(feature = "tcp")]
+mod socks;
+
+#[cfg(any(feature = "tcp", feature = "udp"))]
+mod sockopt;
+
+#[cfg(all(feature = "netlink", target_os = "linux"))]
+mod netlink;
+
 #[cfg(feature = "tls")]
 #[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
 pub mod tls;
+#[cfg(feature = "tls")]
+mod x509;
+
+#[cfg(feature = "encryption")]
+#[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+pub mod encryption;
 
 #[cfg(feature = "tcp")]
 #[cfg_attr(docsrs, doc(cfg(feature = "tcp")))]
 pub mod tcp;
 
+#[cfg(feature = "udp")]
+#[cfg_attr(docsrs, doc(cfg(feature = "udp")))]
+pub mod udp;
+
+#[cfg(feature = "quic")]
+#[cfg_attr(docsrs, doc(cfg(feature = "quic")))]
+pub mod quic;
+
+#[cfg(feature = "websocket")]
+#[cfg_attr(docsrs, doc(cfg(feature = "websocket")))]
+pub mod websocket;
+
+#[cfg(all(feature = "unix", unix))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "unix", unix))))]
+pub mod unix;
+
+#[cfg(feature = "memory")]
+#[cfg_attr(docsrs, doc(cfg(feature = "memory")))]
+pub mod memory;
+
+#[cfg(feature = "sctp")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sctp")))]
+pub mod sctp;
+
+#[cfg(feature = "stdio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "stdio")))]
+pub mod stdio;
+
+#[cfg(feature = "process")]
+#[cfg_attr(docsrs, doc(cfg(feature = "process")))]
+pub mod process;
+
+#[cfg(feature = "discovery")]
+#[cfg_attr(docsrs, doc(cfg(feature = "discovery")))]
+pub mod discovery;
+
+#[cfg(all(feature = "named-pipe", windows))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "named-pipe", windows))))]
+pub mod named_pipe;
+
+#[cfg(all(feature = "vsock", target_os = "linux"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "vsock", target_os = "linux"))))]
+pub mod vsock;
+
+#[cfg(feature = "serial")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serial")))]
+pub mod serial;
+
+#[cfg(feature = "usb")]
+#[cfg_attr(docsrs, doc(cfg(feature = "usb")))]
+pub mod usb;
+
+#[cfg(feature = "ssh")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ssh")))]
+pub mod ssh;
+
+#[cfg(feature = "onion")]
+#[cfg_attr(docsrs, doc(cfg(feature = "onion")))]
+pub mod onion;
+
 #[cfg(feature = "rfcomm")]
 #[cfg_attr(docsrs, doc(cfg(feature = "rfcomm")))]
 pub mod rfcomm;
@@ -252,3 +486,7 @@ pub mod rfcomm;
 #[cfg(feature = "rfcomm-profile")]
 #[cfg_attr(docsrs, doc(cfg(feature = "rfcomm-profile")))]
 pub mod rfcomm_profile;
+
+#[cfg(feature = "l2cap")]
+#[cfg_attr(docsrs, doc(cfg(feature = "l2cap")))]
+pub mod l2cap;
@@ -0,0 +1,229 @@
+//! Windows named pipe transport.
+//!
+//! This is useful for local inter-process communication on Windows.
+//! Pipe names have the form `\\.\pipe\name`.
+
+use async_trait::async_trait;
+use futures::future;
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    sync::atomic::{AtomicU64, Ordering as AtomicOrdering},
+    time::Duration,
+};
+use tokio::{
+    io::split,
+    net::windows::named_pipe::{ClientOptions, NamedPipeServer, ServerOptions},
+    sync::{mpsc, watch, Mutex},
+    time::{sleep, Instant},
+};
+
+use super::{AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox};
+use aggligator::control::Direction;
+
+static NAME: &str = "named_pipe";
+
+/// Windows error code returned when all pipe instances are busy.
+const ERROR_PIPE_BUSY: i32 = 231;
+
+/// Interval for retrying to open a busy pipe.
+const BUSY_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Link tag for Windows named pipe link.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NamedPipeLinkTag {
+    /// Pipe name.
+    pub name: String,
+    /// Instance number.
+    ///
+    /// For outgoing links this is the index of the link to the pipe.
+    /// For incoming links this is the number of the accepted client.
+    pub instance: u64,
+    /// Link direction.
+    pub direction: Direction,
+}
+
+impl fmt::Display for NamedPipeLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        write!(f, "{dir} {} #{}", self.name, self.instance)
+    }
+}
+
+impl NamedPipeLinkTag {
+    /// Creates a new link tag for a Windows named pipe link.
+    pub fn new(name: &str, instance: u64, direction: Direction) -> Self {
+        Self { name: name.to_string(), instance, direction }
+    }
+}
+
+impl LinkTag for NamedPipeLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// Windows named pipe transport for outgoing connections.
+#[derive(Debug, Clone)]
+pub struct NamedPipeConnector {
+    name: String,
+    instances: u64,
+    busy_timeout: Duration,
+}
+
+impl fmt::Display for NamedPipeConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl NamedPipeConnector {
+    /// Create a new Windows named pipe transport for outgoing connections to the pipe `name`.
+    pub fn new(name: impl AsRef<str>) -> Self {
+        Self { name: name.as_ref().to_string(), instances: 1, busy_timeout: Duration::from_secs(5) }
+    }
+
+    /// Sets the number of links established to the pipe.
+    ///
+    /// The default is one.
+    ///
+    /// # Panics
+    /// Panics when `instances` is zero.
+    pub fn set_instances(&mut self, instances: u64) {
+        assert!(instances > 0, "at least one instance is required");
+        self.instances = instances;
+    }
+
+    /// Sets the time to wait for a pipe instance to become available
+    /// when all instances are busy.
+    ///
+    /// The default is 5 seconds.
+    pub fn set_busy_timeout(&mut self, busy_timeout: Duration) {
+        self.busy_timeout = busy_timeout;
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for NamedPipeConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        let tags = (0..self.instances).map(|instance| {
+            Box::new(NamedPipeLinkTag::new(&self.name, instance, Direction::Outgoing)) as LinkTagBox
+        });
+        tx.send_replace(tags.collect());
+        future::pending().await
+    }
+
+    async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
+        let tag: &NamedPipeLinkTag = tag.as_any().downcast_ref().unwrap();
+
+        let deadline = Instant::now() + self.busy_timeout;
+        let client = loop {
+            match ClientOptions::new().open(&tag.name) {
+                Ok(client) => break client,
+                Err(err) if err.raw_os_error() == Some(ERROR_PIPE_BUSY) && Instant::now() < deadline => (),
+                Err(err) => return Err(err),
+            }
+            sleep(BUSY_RETRY_INTERVAL).await;
+        };
+
+        let (rh, wh) = split(client);
+        Ok(IoBox::new(rh, wh))
+    }
+}
+
+/// Windows named pipe transport for incoming connections.
+///
+/// A listening pipe instance is available at all times, so that
+/// concurrent clients do not observe the pipe as missing.
+#[derive(Debug)]
+pub struct NamedPipeAcceptor {
+    name: String,
+    server: Mutex<NamedPipeServer>,
+    instance: AtomicU64,
+}
+
+impl fmt::Display for NamedPipeAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl NamedPipeAcceptor {
+    /// Create a new Windows named pipe transport listening for incoming connections on the pipe `name`.
+    ///
+    /// If the pipe already exists, for example because another process
+    /// is serving it, an error is returned.
+    pub fn new(name: impl AsRef<str>) -> Result<Self> {
+        let name = name.as_ref().to_string();
+        let server = ServerOptions::new().first_pipe_instance(true).create(&name).map_err(|err| {
+            Error::new(
+                if err.kind() == ErrorKind::PermissionDenied { ErrorKind::AddrInUse } else { err.kind() },
+                err,
+            )
+        })?;
+        Ok(Self { name, server: Mutex::new(server), instance: AtomicU64::new(0) })
+    }
+}
+
+#[async_trait]
+impl AcceptingTransport for NamedPipeAcceptor {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
+        let mut server = self.server.lock().await;
+
+        loop {
+            server.connect().await?;
+
+            // Create the next instance before handing off the connected one,
+            // so that a listening instance is always available.
+            let next = ServerOptions::new().create(&self.name)?;
+            let connected = std::mem::replace(&mut *server, next);
+
+            let instance = self.instance.fetch_add(1, AtomicOrdering::SeqCst);
+            let tag = NamedPipeLinkTag::new(&self.name, instance, Direction::Incoming);
+            tracing::debug!("Accepted named pipe connection {tag}");
+
+            let (rh, wh) = split(connected);
+            let _ = tx.send(AcceptedIoBox::new(rh, wh, tag)).await;
+        }
+    }
+}
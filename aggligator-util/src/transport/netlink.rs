@@ -0,0 +1,51 @@
+//! Network interface change notifications using rtnetlink.
+
+use futures::{future, stream::BoxStream, FutureExt, StreamExt};
+use netlink_sys::{AsyncSocket, SocketAddr};
+use rtnetlink::{
+    constants::{RTMGRP_IPV4_IFADDR, RTMGRP_IPV6_IFADDR, RTMGRP_LINK},
+    Handle,
+};
+use std::io::Result;
+use tokio::task::JoinHandle;
+
+/// Monitors network interfaces and their addresses for changes.
+///
+/// Dropping the monitor stops monitoring.
+pub(crate) struct InterfaceMonitor {
+    events: BoxStream<'static, ()>,
+    _handle: Handle,
+    conn_task: JoinHandle<()>,
+}
+
+impl InterfaceMonitor {
+    /// Subscribes to link and address events of the kernel.
+    pub fn new() -> Result<Self> {
+        let (mut conn, handle, messages) = rtnetlink::new_connection()?;
+        conn.socket_mut()
+            .socket_mut()
+            .bind(&SocketAddr::new(0, RTMGRP_LINK | RTMGRP_IPV4_IFADDR | RTMGRP_IPV6_IFADDR))?;
+        let conn_task = tokio::spawn(conn);
+
+        Ok(Self { events: messages.map(|_| ()).boxed(), _handle: handle, conn_task })
+    }
+
+    /// Waits until an interface or address has been added, changed or removed.
+    ///
+    /// Bursts of events are coalesced into one notification.
+    /// Never returns if the netlink connection has failed.
+    pub async fn changed(&mut self) {
+        if self.events.next().await.is_none() {
+            tracing::warn!("netlink connection for monitoring network interfaces failed");
+            return future::pending().await;
+        }
+
+        while let Some(Some(())) = self.events.next().now_or_never() {}
+    }
+}
+
+impl Drop for InterfaceMonitor {
+    fn drop(&mut self) {
+        self.conn_task.abort();
+    }
+}
@@ -0,0 +1,525 @@
+//! Tor onion service transport.
+//!
+//! Links are established through the Tor network using an embedded Tor client
+//! provided by [arti](https://arti.torproject.org/).
+//! The [`OnionAcceptor`] publishes an onion service, which is reachable even if the
+//! acceptor is located behind NAT or a firewall without any open ports.
+//! Thus an onion link is useful as a reachability fallback together with other transports.
+//!
+//! The Tor client must bootstrap before links can be established, which may take
+//! tens of seconds.
+//! Bootstrap progress can be observed using [`OnionConnector::bootstrap`] and
+//! [`OnionAcceptor::bootstrap`].
+//! Bootstrapping happens in the background and a failure only affects the links
+//! of the onion transport, so that other transports of the same
+//! [connector](super::Connector) or [acceptor](super::Acceptor) continue to work.
+//! Failed bootstrap attempts are retried periodically.
+//!
+//! Onion service support in arti is experimental.
+
+use arti_client::{config::BoolOrAuto, BootstrapBehavior, DataStream, StreamPrefs, TorClient, TorClientConfig};
+use async_trait::async_trait;
+use futures::{future, StreamExt};
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::{mpsc, watch},
+    time::sleep,
+};
+use tor_cell::relaycell::msg::Connected;
+use tor_hsservice::{config::OnionServiceConfigBuilder, HsNickname, RunningOnionService};
+use tor_proto::stream::IncomingStreamRequest;
+use tor_rtcompat::PreferredRuntime;
+
+use super::{AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox};
+use aggligator::control::Direction;
+
+static NAME: &str = "onion";
+
+/// Delay before retrying a failed bootstrap.
+const BOOTSTRAP_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Converts a Tor error into an IO error.
+fn tor_err(err: impl fmt::Display) -> Error {
+    Error::new(ErrorKind::Other, err.to_string())
+}
+
+/// Link tag for onion link.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OnionLinkTag {
+    /// Onion address of the service.
+    pub onion_addr: String,
+    /// Virtual port of the service.
+    pub port: u16,
+    /// Link number.
+    ///
+    /// This distinguishes multiple links over the same onion service.
+    pub link: usize,
+    /// Link direction.
+    pub direction: Direction,
+}
+
+impl fmt::Display for OnionLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        write!(f, "{dir} {}:{} #{}", &self.onion_addr, self.port, self.link)
+    }
+}
+
+impl OnionLinkTag {
+    /// Creates a new link tag for an onion link.
+    pub fn new(onion_addr: &str, port: u16, link: usize, direction: Direction) -> Self {
+        Self { onion_addr: onion_addr.to_string(), port, link, direction }
+    }
+}
+
+impl LinkTag for OnionLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// Bootstrap status of the embedded Tor client.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OnionBootstrap {
+    /// Bootstrapping is in progress.
+    InProgress {
+        /// Fraction of bootstrapping completed, between 0 and 1.
+        progress: f32,
+        /// Description of the current bootstrap step.
+        status: String,
+    },
+    /// The Tor client is ready for traffic.
+    Ready,
+    /// Bootstrapping failed and will be retried.
+    Failed(String),
+}
+
+impl OnionBootstrap {
+    /// Whether the Tor client is ready for traffic.
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Self::Ready)
+    }
+}
+
+impl fmt::Display for OnionBootstrap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InProgress { progress, status } => write!(f, "bootstrapping {:.0}%: {status}", progress * 100.),
+            Self::Ready => write!(f, "ready"),
+            Self::Failed(err) => write!(f, "bootstrap failed: {err}"),
+        }
+    }
+}
+
+/// Embedded Tor client that bootstraps in the background.
+struct Client {
+    tor: TorClient<PreferredRuntime>,
+    bootstrap_rx: watch::Receiver<OnionBootstrap>,
+}
+
+impl Client {
+    /// Creates a Tor client and starts bootstrapping it.
+    ///
+    /// Must be called from within a Tokio runtime.
+    fn new(config: TorClientConfig) -> Result<Arc<Self>> {
+        let tor = TorClient::builder()
+            .config(config)
+            .bootstrap_behavior(BootstrapBehavior::Manual)
+            .create_unbootstrapped()
+            .map_err(tor_err)?;
+
+        let (bootstrap_tx, bootstrap_rx) =
+            watch::channel(OnionBootstrap::InProgress { progress: 0., status: String::new() });
+        tokio::spawn(Self::bootstrap_task(tor.clone(), bootstrap_tx));
+
+        Ok(Arc::new(Self { tor, bootstrap_rx }))
+    }
+
+    /// Bootstraps the Tor client, retrying on failure.
+    async fn bootstrap_task(tor: TorClient<PreferredRuntime>, bootstrap_tx: watch::Sender<OnionBootstrap>) {
+        let mut events = tor.bootstrap_events();
+        let progress = async {
+            while let Some(status) = events.next().await {
+                if status.ready_for_traffic() {
+                    continue;
+                }
+                bootstrap_tx.send_if_modified(|bootstrap| match bootstrap {
+                    OnionBootstrap::Ready => false,
+                    _ => {
+                        *bootstrap =
+                            OnionBootstrap::InProgress { progress: status.as_frac(), status: status.to_string() };
+                        true
+                    }
+                });
+            }
+        };
+
+        let bootstrap = async {
+            loop {
+                match tor.bootstrap().await {
+                    Ok(()) => {
+                        tracing::info!("Tor client bootstrapped");
+                        bootstrap_tx.send_replace(OnionBootstrap::Ready);
+                        break;
+                    }
+                    Err(err) => {
+                        tracing::warn!("Tor client bootstrap failed: {err}");
+                        bootstrap_tx.send_replace(OnionBootstrap::Failed(err.to_string()));
+                        tokio::select! {
+                            () = sleep(BOOTSTRAP_RETRY_DELAY) => (),
+                            () = bootstrap_tx.closed() => return,
+                        }
+                    }
+                }
+            }
+        };
+
+        tokio::select! {
+            () = progress => (),
+            () = bootstrap => (),
+        }
+    }
+
+    /// Waits until the Tor client has bootstrapped.
+    ///
+    /// Fails if the current bootstrap attempt fails.
+    async fn ready(&self) -> Result<()> {
+        let mut bootstrap_rx = self.bootstrap_rx.clone();
+        loop {
+            match &*bootstrap_rx.borrow_and_update() {
+                OnionBootstrap::Ready => return Ok(()),
+                OnionBootstrap::Failed(err) => {
+                    return Err(Error::new(ErrorKind::NotConnected, format!("Tor bootstrap failed: {err}")))
+                }
+                OnionBootstrap::InProgress { .. } => (),
+            }
+            if bootstrap_rx.changed().await.is_err() {
+                return Err(Error::new(ErrorKind::NotConnected, "Tor client terminated"));
+            }
+        }
+    }
+}
+
+/// Splits a data stream into an IO box.
+fn data_stream_io(stream: DataStream) -> IoBox {
+    let (rh, wh) = stream.split();
+    IoBox::new(SyncIo::new(rh), SyncIo::new(wh))
+}
+
+/// Makes a stream half that is only [`Send`] usable in an [`IoBox`], which requires [`Sync`].
+///
+/// The stream is only ever accessed mutably, thus the mutex is never locked.
+struct SyncIo<T>(Mutex<T>);
+
+impl<T> SyncIo<T> {
+    fn new(io: T) -> Self {
+        Self(Mutex::new(io))
+    }
+
+    fn get(self: Pin<&mut Self>) -> Pin<&mut T>
+    where
+        T: Unpin,
+    {
+        Pin::new(self.get_mut().0.get_mut().unwrap())
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for SyncIo<T> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+        self.get().poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for SyncIo<T> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        self.get().poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get().poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get().poll_shutdown(cx)
+    }
+}
+
+/// Tor onion service transport for outgoing connections.
+///
+/// Connects to an onion service through an embedded Tor client.
+pub struct OnionConnector {
+    onion_addr: String,
+    port: u16,
+    links: usize,
+    client: Arc<Client>,
+}
+
+impl fmt::Debug for OnionConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OnionConnector")
+            .field("onion_addr", &self.onion_addr)
+            .field("port", &self.port)
+            .field("links", &self.links)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for OnionConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", &self.onion_addr, self.port)
+    }
+}
+
+impl OnionConnector {
+    /// Creates a new onion transport connecting to the virtual port `port`
+    /// of the onion service `onion_addr`.
+    ///
+    /// `onion_addr` must be of the form `<56 characters>.onion`.
+    /// The embedded Tor client uses the default configuration and directories of arti.
+    ///
+    /// Bootstrapping of the Tor client starts immediately in the background.
+    /// Must be called from within a Tokio runtime.
+    pub fn new(onion_addr: &str, port: u16) -> Result<Self> {
+        Self::with_config(onion_addr, port, TorClientConfig::default())
+    }
+
+    /// Creates a new onion transport using the specified Tor client configuration.
+    ///
+    /// See [`new`](Self::new) for details.
+    pub fn with_config(onion_addr: &str, port: u16, config: TorClientConfig) -> Result<Self> {
+        let onion_addr = onion_addr.trim_end_matches('.').to_ascii_lowercase();
+        if !onion_addr.ends_with(".onion") {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid onion address"));
+        }
+
+        Ok(Self { onion_addr, port, links: 1, client: Client::new(config)? })
+    }
+
+    /// Sets the number of links to establish through the Tor network.
+    ///
+    /// Each link uses a separate Tor stream.
+    /// The default is one link.
+    pub fn set_links(&mut self, links: usize) {
+        self.links = links;
+    }
+
+    /// Bootstrap status of the embedded Tor client.
+    pub fn bootstrap(&self) -> watch::Receiver<OnionBootstrap> {
+        self.client.bootstrap_rx.clone()
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for OnionConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        let tags: HashSet<LinkTagBox> = (0..self.links)
+            .map(|link| {
+                Box::new(OnionLinkTag::new(&self.onion_addr, self.port, link, Direction::Outgoing)) as LinkTagBox
+            })
+            .collect();
+        tx.send_replace(tags);
+
+        // Returning would remove the transport and thus disconnect its links.
+        future::pending().await
+    }
+
+    async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
+        let tag: &OnionLinkTag = tag.as_any().downcast_ref().unwrap();
+
+        self.client.ready().await?;
+
+        let mut prefs = StreamPrefs::new();
+        prefs.connect_to_onion_services(BoolOrAuto::Explicit(true));
+        let stream = self
+            .client
+            .tor
+            .connect_with_prefs((tag.onion_addr.as_str(), tag.port), &prefs)
+            .await
+            .map_err(|err| Error::new(ErrorKind::ConnectionRefused, err.to_string()))?;
+
+        Ok(data_stream_io(stream))
+    }
+}
+
+/// Tor onion service transport for incoming connections.
+///
+/// Publishes an onion service using an embedded Tor client.
+pub struct OnionAcceptor {
+    nickname: HsNickname,
+    port: u16,
+    client: Arc<Client>,
+    onion_addr_tx: watch::Sender<Option<String>>,
+}
+
+impl fmt::Debug for OnionAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OnionAcceptor")
+            .field("nickname", &self.nickname.to_string())
+            .field("port", &self.port)
+            .field("onion_addr", &*self.onion_addr_tx.borrow())
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for OnionAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &*self.onion_addr_tx.borrow() {
+            Some(onion_addr) => write!(f, "{onion_addr}:{}", self.port),
+            None => write!(f, "{}:{}", &self.nickname, self.port),
+        }
+    }
+}
+
+impl OnionAcceptor {
+    /// Creates a new onion transport publishing an onion service and accepting
+    /// streams to its virtual port `port`.
+    ///
+    /// The `nickname` identifies the onion service locally.
+    /// Its keys, and thus its onion address, are persisted in the state directory
+    /// of arti under this nickname.
+    /// The embedded Tor client uses the default configuration and directories of arti.
+    ///
+    /// Bootstrapping of the Tor client starts immediately in the background.
+    /// The onion service is published once the transport has been added to an
+    /// [acceptor](super::Acceptor).
+    /// Must be called from within a Tokio runtime.
+    pub fn new(nickname: &str, port: u16) -> Result<Self> {
+        Self::with_config(nickname, port, TorClientConfig::default())
+    }
+
+    /// Creates a new onion transport using the specified Tor client configuration.
+    ///
+    /// See [`new`](Self::new) for details.
+    pub fn with_config(nickname: &str, port: u16, config: TorClientConfig) -> Result<Self> {
+        let nickname: HsNickname =
+            nickname.parse().map_err(|err| Error::new(ErrorKind::InvalidInput, format!("{err}")))?;
+
+        Ok(Self { nickname, port, client: Client::new(config)?, onion_addr_tx: watch::channel(None).0 })
+    }
+
+    /// Bootstrap status of the embedded Tor client.
+    pub fn bootstrap(&self) -> watch::Receiver<OnionBootstrap> {
+        self.client.bootstrap_rx.clone()
+    }
+
+    /// Onion address of the published service.
+    ///
+    /// It is `None` until the onion service has been launched.
+    pub fn onion_addr(&self) -> watch::Receiver<Option<String>> {
+        self.onion_addr_tx.subscribe()
+    }
+
+    /// Launches the onion service.
+    async fn launch(
+        &self,
+    ) -> Result<(Arc<RunningOnionService>, impl futures::Stream<Item = tor_hsservice::RendRequest>)> {
+        loop {
+            match self.client.ready().await {
+                Ok(()) => break,
+                Err(err) => {
+                    tracing::debug!("waiting for Tor bootstrap: {err}");
+                    let mut bootstrap_rx = self.client.bootstrap_rx.clone();
+                    bootstrap_rx.changed().await.map_err(tor_err)?;
+                }
+            }
+        }
+
+        let config =
+            OnionServiceConfigBuilder::default().nickname(self.nickname.clone()).build().map_err(tor_err)?;
+        let (service, rend_requests) = self.client.tor.launch_onion_service(config).map_err(tor_err)?;
+
+        let onion_addr = service
+            .onion_name()
+            .ok_or_else(|| Error::new(ErrorKind::Other, "onion service has no onion address"))?
+            .to_string();
+        tracing::info!("published onion service {onion_addr}:{}", self.port);
+        self.onion_addr_tx.send_replace(Some(onion_addr));
+
+        Ok((service, rend_requests))
+    }
+}
+
+#[async_trait]
+impl AcceptingTransport for OnionAcceptor {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
+        let (_service, rend_requests) = self.launch().await?;
+        let onion_addr = self.onion_addr_tx.borrow().clone().unwrap_or_default();
+
+        let mut stream_requests = Box::pin(tor_hsservice::handle_rend_requests(rend_requests));
+        let mut link = 0;
+        while let Some(stream_request) = stream_requests.next().await {
+            match stream_request.request() {
+                IncomingStreamRequest::Begin(begin) if begin.port() == self.port => (),
+                _ => {
+                    tracing::debug!("rejecting onion stream request for other port");
+                    let _ = stream_request.shutdown_circuit();
+                    continue;
+                }
+            }
+
+            let stream = match stream_request.accept(Connected::new_empty()).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    tracing::debug!("accepting onion stream failed: {err}");
+                    continue;
+                }
+            };
+
+            let tag = OnionLinkTag::new(&onion_addr, self.port, link, Direction::Incoming);
+            link = link.wrapping_add(1);
+            tracing::debug!("Accepted onion link {tag}");
+
+            let _ = tx.send(AcceptedIoBox { io: data_stream_io(stream), tag: Box::new(tag) }).await;
+        }
+
+        Err(Error::new(ErrorKind::ConnectionAborted, "onion service terminated"))
+    }
+}
@@ -0,0 +1,383 @@
+//! Child process transport.
+//!
+//! This transport spawns a command for each link and uses its standard input
+//! and output as the link, similar to the `ProxyCommand` option of OpenSSH.
+//! This allows tunneling links through arbitrary external tools, such as
+//! `socat` or custom VPN clients.
+//!
+//! When the command exits, the link fails and the command is restarted
+//! by the [connector](super::Connector).
+//! If it exits unsuccessfully, its exit status and the beginning of its
+//! standard error output are reported as [link error](super::Connector::link_errors).
+
+use async_trait::async_trait;
+use futures::{
+    future::{self, BoxFuture, Shared},
+    ready, FutureExt,
+};
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fmt,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    pin::Pin,
+    process::Stdio,
+    sync::Mutex,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf},
+    process::{Child, ChildStderr, ChildStdin, ChildStdout, Command},
+    sync::{mpsc, watch},
+    time::sleep,
+};
+
+use super::{ConnectingTransport, IoBox, LinkTag, LinkTagBox};
+use aggligator::control::Direction;
+
+static NAME: &str = "process";
+
+/// Maximum amount of standard error output included in a link error.
+const MAX_STDERR: usize = 4096;
+
+/// Link tag for child process link.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProcessLinkTag {
+    /// Command line.
+    pub command: String,
+    /// Instance number.
+    pub instance: usize,
+}
+
+impl fmt::Display for ProcessLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "-> {} #{}", self.command, self.instance)
+    }
+}
+
+impl ProcessLinkTag {
+    /// Creates a new link tag for a child process link.
+    pub fn new(command: &str, instance: usize) -> Self {
+        Self { command: command.to_string(), instance }
+    }
+}
+
+impl LinkTag for ProcessLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        Direction::Outgoing
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// Restart state of a command instance.
+#[derive(Debug, Default)]
+struct Restarts {
+    /// Number of consecutive short-lived runs.
+    count: u32,
+    /// Time the instance was last started.
+    started: Option<Instant>,
+}
+
+/// Child process transport for outgoing connections.
+///
+/// Each link spawns a new instance of the command.
+#[derive(Debug)]
+pub struct ProcessConnector {
+    program: OsString,
+    args: Vec<OsString>,
+    command: String,
+    instances: usize,
+    restart_delay: Duration,
+    max_restart_delay: Duration,
+    restarts: Mutex<HashMap<usize, Restarts>>,
+}
+
+impl fmt::Display for ProcessConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", &self.command)
+    }
+}
+
+impl ProcessConnector {
+    /// Creates a new child process transport running `program` with the arguments `args`.
+    ///
+    /// By default one instance of the command is run.
+    pub fn new(program: impl Into<OsString>, args: impl IntoIterator<Item = impl Into<OsString>>) -> Self {
+        let program = program.into();
+        let args: Vec<OsString> = args.into_iter().map(|arg| arg.into()).collect();
+        let command = std::iter::once(&program)
+            .chain(&args)
+            .map(|part| part.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Self {
+            program,
+            args,
+            command,
+            instances: 1,
+            restart_delay: Duration::from_secs(1),
+            max_restart_delay: Duration::from_secs(60),
+            restarts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the number of command instances to run, each providing one link.
+    pub fn set_instances(&mut self, instances: usize) {
+        self.instances = instances;
+    }
+
+    /// Sets the delay before restarting a command instance that exited.
+    ///
+    /// The delay doubles with each consecutive restart, up to the
+    /// [maximum restart delay](Self::set_max_restart_delay).
+    /// It is applied in addition to the reconnect delay of the connector.
+    /// The default is one second.
+    pub fn set_restart_delay(&mut self, restart_delay: Duration) {
+        self.restart_delay = restart_delay;
+    }
+
+    /// Sets the maximum delay before restarting a command instance that exited.
+    ///
+    /// An instance that has run for at least this time is restarted without
+    /// delay and its restart delay is reset.
+    /// The default is 60 seconds.
+    pub fn set_max_restart_delay(&mut self, max_restart_delay: Duration) {
+        self.max_restart_delay = max_restart_delay;
+    }
+
+    /// Determines the delay before starting the specified instance.
+    fn start_delay(&self, instance: usize) -> Duration {
+        let mut restarts = self.restarts.lock().unwrap();
+        let restarts = restarts.entry(instance).or_default();
+
+        match restarts.started.take() {
+            Some(started) if started.elapsed() < self.max_restart_delay => {
+                let delay = self.restart_delay.saturating_mul(2u32.saturating_pow(restarts.count));
+                restarts.count = restarts.count.saturating_add(1);
+                delay.min(self.max_restart_delay)
+            }
+            _ => {
+                restarts.count = 0;
+                Duration::ZERO
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for ProcessConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        let tags: HashSet<LinkTagBox> = (0..self.instances)
+            .map(|instance| Box::new(ProcessLinkTag::new(&self.command, instance)) as LinkTagBox)
+            .collect();
+        tx.send_replace(tags);
+
+        future::pending().await
+    }
+
+    async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
+        let tag: &ProcessLinkTag = tag.as_any().downcast_ref().unwrap();
+
+        let delay = self.start_delay(tag.instance);
+        if !delay.is_zero() {
+            tracing::debug!("restarting {tag} in {delay:?}");
+            sleep(delay).await;
+        }
+
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| Error::new(err.kind(), format!("cannot run {}: {err}", &self.command)))?;
+        self.restarts.lock().unwrap().entry(tag.instance).or_default().started = Some(Instant::now());
+        tracing::debug!("started {tag}");
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+
+        let (alive_tx, alive_rx) = mpsc::channel(1);
+        let exit = tokio::spawn(supervise(self.command.clone(), child, stderr, alive_rx))
+            .map(|res| res.unwrap_or_default())
+            .boxed()
+            .shared();
+
+        let reader = ProcessReader { stdout, exit: exit.clone(), eof: false, _alive_tx: alive_tx.clone() };
+        let writer = ProcessWriter { stdin, exit, error: None, _alive_tx: alive_tx };
+        Ok(IoBox::new(reader, writer))
+    }
+}
+
+/// Waits for the child process to exit and captures its standard error output.
+///
+/// The child process is killed when the link is dropped, i.e. all senders of `alive_rx`
+/// have been dropped.
+///
+/// Returns an error message if the process exited unsuccessfully.
+async fn supervise(
+    command: String, mut child: Child, mut stderr: ChildStderr, mut alive_rx: mpsc::Receiver<()>,
+) -> Option<String> {
+    let capture_stderr = async {
+        let mut captured = Vec::new();
+        let mut buf = [0; 1024];
+        loop {
+            match stderr.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    let n = n.min(MAX_STDERR - captured.len());
+                    captured.extend_from_slice(&buf[..n]);
+                }
+            }
+        }
+        captured
+    };
+
+    let (status, captured) = tokio::select! {
+        res = async { tokio::join!(child.wait(), capture_stderr) } => res,
+        None = alive_rx.recv() => {
+            let _ = child.kill().await;
+            return None;
+        }
+    };
+
+    let mut msg = match status {
+        Ok(status) if status.success() => return None,
+        Ok(status) => format!("{command} exited with {status}"),
+        Err(err) => format!("waiting for {command} failed: {err}"),
+    };
+    let captured = String::from_utf8_lossy(&captured);
+    if !captured.trim().is_empty() {
+        msg.push_str(": ");
+        msg.push_str(captured.trim());
+    }
+
+    tracing::debug!("{msg}");
+    Some(msg)
+}
+
+/// Exit monitor of a child process, providing an error message on unsuccessful exit.
+type Exit = Shared<BoxFuture<'static, Option<String>>>;
+
+/// Reads from the standard output of a child process.
+///
+/// When the output ends, an unsuccessful exit of the process is reported as error.
+struct ProcessReader {
+    stdout: ChildStdout,
+    exit: Exit,
+    eof: bool,
+    _alive_tx: mpsc::Sender<()>,
+}
+
+impl AsyncRead for ProcessReader {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<Result<()>> {
+        let this = self.get_mut();
+
+        if !this.eof {
+            let before = buf.filled().len();
+            ready!(Pin::new(&mut this.stdout).poll_read(cx, buf))?;
+            if buf.filled().len() > before || buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            this.eof = true;
+        }
+
+        match ready!(this.exit.poll_unpin(cx)) {
+            Some(msg) => Poll::Ready(Err(Error::new(ErrorKind::Other, msg))),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+/// Writes to the standard input of a child process.
+///
+/// When writing fails, an unsuccessful exit of the process is reported instead
+/// of the write error.
+struct ProcessWriter {
+    stdin: ChildStdin,
+    exit: Exit,
+    error: Option<Error>,
+    _alive_tx: mpsc::Sender<()>,
+}
+
+impl ProcessWriter {
+    /// Converts a write error into the exit error of the process, if available.
+    fn poll_error<T>(&mut self, cx: &mut Context, res: Poll<Result<T>>) -> Poll<Result<T>> {
+        match res {
+            Poll::Ready(Err(err)) if self.error.is_none() => self.error = Some(err),
+            res if self.error.is_none() => return res,
+            _ => (),
+        }
+
+        let msg = ready!(self.exit.poll_unpin(cx));
+        let err = self.error.take().unwrap();
+        Poll::Ready(Err(match msg {
+            Some(msg) => Error::new(ErrorKind::Other, msg),
+            None => err,
+        }))
+    }
+}
+
+impl AsyncWrite for ProcessWriter {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        let res = match this.error {
+            Some(_) => Poll::Pending,
+            None => Pin::new(&mut this.stdin).poll_write(cx, buf),
+        };
+        this.poll_error(cx, res)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        let res = match this.error {
+            Some(_) => Poll::Pending,
+            None => Pin::new(&mut this.stdin).poll_flush(cx),
+        };
+        this.poll_error(cx, res)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        let res = match this.error {
+            Some(_) => Poll::Pending,
+            None => Pin::new(&mut this.stdin).poll_shutdown(cx),
+        };
+        this.poll_error(cx, res)
+    }
+}
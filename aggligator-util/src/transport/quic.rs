@@ -0,0 +1,531 @@
+//! QUIC transport.
+//!
+//! Each link uses its own QUIC connection, so that congestion control and
+//! path migration are handled independently for each link.
+//! Data is exchanged over a single bidirectional stream of the connection.
+//!
+//! Outgoing links can optionally be established using [0-RTT](QuicConnector::set_zero_rtt)
+//! by resuming a TLS session of a previous link to the same server.
+
+use async_trait::async_trait;
+use futures::{future, stream::FuturesUnordered, FutureExt, StreamExt};
+use quinn::{ClientConfig, Connecting, Endpoint, EndpointConfig, ServerConfig, TokioRuntime};
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::AsyncReadExt,
+    net::UdpSocket,
+    sync::{mpsc, watch},
+    time::{sleep, timeout},
+};
+
+use super::{
+    ip::{
+        hosts_with_default_ports, interface_name_for_addr, local_addrs_for_target, resolve_hosts, use_proper_ipv4,
+    },
+    AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox,
+};
+use aggligator::{control::Direction, Link};
+
+pub use super::ip::IpVersion;
+
+static NAME: &str = "quic";
+
+/// Data sent by the client to open the bidirectional stream.
+///
+/// QUIC streams only become visible to the peer once data is sent over them.
+const STREAM_HELLO: &[u8] = b"aggligator";
+
+/// Default timeout for the QUIC handshake.
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Link tag for QUIC link.
+#[derive(Debug, Clone)]
+pub struct QuicLinkTag {
+    /// Local interface name.
+    pub interface: Vec<u8>,
+    /// Remote address.
+    pub remote: SocketAddr,
+    /// ALPN protocol.
+    ///
+    /// For incoming links this is the negotiated protocol.
+    /// For outgoing links this is the protocol that must be negotiated,
+    /// as configured by [`QuicConnector::set_alpn`].
+    pub alpn: Option<Vec<u8>>,
+    /// Link direction.
+    pub direction: Direction,
+    /// Whether the link was established using 0-RTT.
+    ///
+    /// This is only set on the link tags of established outgoing links and
+    /// not taken into account when comparing link tags.
+    pub zero_rtt: bool,
+}
+
+impl PartialEq for QuicLinkTag {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for QuicLinkTag {}
+
+impl PartialOrd for QuicLinkTag {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QuicLinkTag {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Hash for QuicLinkTag {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+impl fmt::Display for QuicLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        write!(f, "{:16} {dir} {}", String::from_utf8_lossy(&self.interface), self.remote)?;
+        if let Some(alpn) = &self.alpn {
+            write!(f, " ({})", String::from_utf8_lossy(alpn))?;
+        }
+        if self.zero_rtt {
+            write!(f, " (0-RTT)")?;
+        }
+        Ok(())
+    }
+}
+
+impl QuicLinkTag {
+    /// Creates a new link tag for a QUIC link.
+    pub fn new(interface: &[u8], remote: SocketAddr, alpn: Option<Vec<u8>>, direction: Direction) -> Self {
+        Self { interface: interface.to_vec(), remote, alpn, direction, zero_rtt: false }
+    }
+
+    /// Fields identifying the link.
+    fn key(&self) -> (&[u8], SocketAddr, Option<&[u8]>, Direction) {
+        (&self.interface, self.remote, self.alpn.as_deref(), self.direction)
+    }
+}
+
+impl LinkTag for QuicLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        self.interface.clone()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// Gets the negotiated ALPN protocol of a QUIC connection.
+fn negotiated_alpn(conn: &quinn::Connection) -> Option<Vec<u8>> {
+    conn.handshake_data()?.downcast::<quinn::crypto::rustls::HandshakeData>().ok()?.protocol
+}
+
+/// QUIC transport for outgoing connections.
+///
+/// This transport is IP-protocol agnostic.
+/// A separate QUIC connection is established for each resolved IP address and
+/// local interface.
+#[derive(Debug, Clone)]
+pub struct QuicConnector {
+    hosts: Vec<String>,
+    server_name: String,
+    client_cfg: ClientConfig,
+    ip_version: IpVersion,
+    resolve_interval: Duration,
+    alpn: Option<Vec<u8>>,
+    handshake_timeout: Duration,
+    zero_rtt: bool,
+}
+
+impl fmt::Display for QuicConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.hosts.len() > 1 {
+            write!(f, "[{}]", self.hosts.join(", "))
+        } else {
+            write!(f, "{}", &self.hosts[0])
+        }
+    }
+}
+
+impl QuicConnector {
+    /// Create a new QUIC transport for outgoing connections.
+    ///
+    /// `hosts` can contain IP addresses and hostnames, including port numbers.
+    /// If an entry does not specify a port number, the `default_port` is used.
+    ///
+    /// The identity of the server is verified against `server_name` using the
+    /// TLS configuration `tls_client_cfg`.
+    ///
+    /// It is checked at creation that `hosts` resolves to at least one IP address.
+    ///
+    /// Host name resolution is retried periodically, thus DNS updates will be taken
+    /// into account without the need to recreate this transport.
+    pub async fn new(
+        hosts: impl IntoIterator<Item = String>, default_port: u16, server_name: impl Into<String>,
+        tls_client_cfg: Arc<rustls::ClientConfig>,
+    ) -> Result<Self> {
+        Self::with_quinn_cfg(hosts, default_port, server_name, ClientConfig::new(tls_client_cfg)).await
+    }
+
+    /// Create a new QUIC transport for outgoing connections using the specified QUIC client configuration.
+    ///
+    /// See [`new`](Self::new) for details.
+    pub async fn with_quinn_cfg(
+        hosts: impl IntoIterator<Item = String>, default_port: u16, server_name: impl Into<String>,
+        client_cfg: ClientConfig,
+    ) -> Result<Self> {
+        let this = Self {
+            hosts: hosts_with_default_ports(hosts, &[default_port])?,
+            server_name: server_name.into(),
+            client_cfg,
+            ip_version: IpVersion::Both,
+            resolve_interval: Duration::from_secs(10),
+            alpn: None,
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            zero_rtt: false,
+        };
+
+        let addrs = resolve_hosts(&this.hosts, this.ip_version).await;
+        if addrs.is_empty() {
+            return Err(Error::new(ErrorKind::NotFound, "cannot resolve IP address of host"));
+        }
+        tracing::info!("{} resolves to: {:?}", &this, addrs);
+
+        Ok(this)
+    }
+
+    /// Sets the IP version used for connecting.
+    pub fn set_ip_version(&mut self, ip_version: IpVersion) {
+        self.ip_version = ip_version;
+    }
+
+    /// Sets the interval for re-resolving the hostname and checking for changed network interfaces.
+    pub fn set_resolve_interval(&mut self, resolve_interval: Duration) {
+        self.resolve_interval = resolve_interval;
+    }
+
+    /// Sets the ALPN protocol that the server must negotiate.
+    ///
+    /// The protocol must also be present in the ALPN protocols of the TLS configuration
+    /// the client configuration was created from. It is included in the link tags
+    /// of outgoing links.
+    pub fn set_alpn(&mut self, alpn: Option<Vec<u8>>) {
+        self.alpn = alpn;
+    }
+
+    /// Sets the timeout for establishing a QUIC connection.
+    ///
+    /// The default is 10 seconds.
+    pub fn set_handshake_timeout(&mut self, handshake_timeout: Duration) {
+        self.handshake_timeout = handshake_timeout;
+    }
+
+    /// Sets whether links are established using 0-RTT, if possible.
+    ///
+    /// When a TLS session of a previous link to the server can be resumed, the link
+    /// handshake is sent as early data together with the QUIC handshake, saving a round trip
+    /// when links are reestablished, for example after a network change.
+    /// This requires early data to be enabled in the TLS client configuration
+    /// (`enable_early_data`); otherwise and if no resumable session is available, the
+    /// full handshake is performed.
+    ///
+    /// Early data is not protected against replay by an attacker.
+    /// Since it only contains the start of the link handshake, a replay can at most
+    /// cause the server to attempt to establish a duplicate link.
+    ///
+    /// If the server rejects the early data, the link fails and is reestablished
+    /// by the connector using a full handshake.
+    /// Whether a link was established using 0-RTT is indicated by [`QuicLinkTag::zero_rtt`].
+    /// The default is `false`.
+    pub fn set_zero_rtt(&mut self, zero_rtt: bool) {
+        self.zero_rtt = zero_rtt;
+    }
+
+    /// Creates a QUIC endpoint bound to the specified local interface.
+    async fn endpoint_for_interface(interface: &[u8], remote: SocketAddr) -> Result<Endpoint> {
+        let Some((_, ip)) = local_addrs_for_target(remote)?.into_iter().find(|(iface, _)| iface == interface)
+        else {
+            return Err(Error::new(ErrorKind::NotFound, "no IP address for interface"));
+        };
+
+        let socket = UdpSocket::bind(SocketAddr::new(ip, 0)).await?;
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        socket.bind_device(Some(interface))?;
+
+        Endpoint::new(EndpointConfig::default(), None, socket.into_std()?, TokioRuntime)
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for QuicConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        loop {
+            let mut tags: HashSet<LinkTagBox> = HashSet::new();
+            for addr in resolve_hosts(&self.hosts, self.ip_version).await {
+                for (iface, _) in local_addrs_for_target(addr)? {
+                    let tag = QuicLinkTag::new(&iface, addr, self.alpn.clone(), Direction::Outgoing);
+                    tags.insert(Box::new(tag));
+                }
+            }
+
+            tx.send_if_modified(|v| {
+                if *v != tags {
+                    *v = tags;
+                    true
+                } else {
+                    false
+                }
+            });
+
+            sleep(self.resolve_interval).await;
+        }
+    }
+
+    async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
+        let (io, _tag) = self.connect_tagged(tag).await?;
+        Ok(io)
+    }
+
+    async fn connect_tagged(&self, tag: &dyn LinkTag) -> Result<(IoBox, LinkTagBox)> {
+        let tag: &QuicLinkTag = tag.as_any().downcast_ref().unwrap();
+
+        let endpoint = Self::endpoint_for_interface(&tag.interface, tag.remote).await?;
+
+        let connecting = endpoint
+            .connect_with(self.client_cfg.clone(), tag.remote, &self.server_name)
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+
+        // Use 0-RTT if enabled and a resumable session is available.
+        let attempt = if self.zero_rtt { connecting.into_0rtt() } else { Err(connecting) };
+        let (conn, zero_rtt) = match attempt {
+            Ok((conn, _accepted)) => {
+                tracing::debug!("QUIC link to {} uses 0-RTT", tag.remote);
+                (conn, true)
+            }
+            Err(connecting) => {
+                let conn = timeout(self.handshake_timeout, connecting)
+                    .await
+                    .map_err(|_| Error::new(ErrorKind::TimedOut, "QUIC handshake timed out"))??;
+                (conn, false)
+            }
+        };
+
+        // The protocol is not yet negotiated when using 0-RTT, but early data is only
+        // accepted by the server if it selects the protocol of the resumed session.
+        if !zero_rtt && tag.alpn.is_some() && negotiated_alpn(&conn) != tag.alpn {
+            conn.close(0u32.into(), b"ALPN mismatch");
+            return Err(Error::new(ErrorKind::InvalidData, "server negotiated different ALPN protocol"));
+        }
+
+        let (mut tx, rx) = conn.open_bi().await?;
+        tx.write_all(STREAM_HELLO).await?;
+
+        let tag = QuicLinkTag { zero_rtt, ..tag.clone() };
+        Ok((IoBox::new(rx, tx), Box::new(tag)))
+    }
+
+    async fn link_filter(&self, new: &Link<LinkTagBox>, existing: &[Link<LinkTagBox>]) -> bool {
+        let Some(new_tag) = new.tag().as_any().downcast_ref::<QuicLinkTag>() else { return true };
+
+        let intro = format!(
+            "Judging {} QUIC link {} {} ({}) on {}",
+            new.direction(),
+            match new.direction() {
+                Direction::Incoming => "from",
+                Direction::Outgoing => "to",
+            },
+            new_tag.remote,
+            String::from_utf8_lossy(new.remote_user_data()),
+            String::from_utf8_lossy(&new_tag.interface)
+        );
+
+        match existing.iter().find(|link| {
+            let Some(tag) = link.tag().as_any().downcast_ref::<QuicLinkTag>() else { return false };
+            tag.interface == new_tag.interface && link.remote_user_data() == new.remote_user_data()
+        }) {
+            Some(other) => {
+                let other_tag = other.tag().as_any().downcast_ref::<QuicLinkTag>().unwrap();
+                tracing::debug!("{intro} => link {} is redundant, rejecting.", other_tag.remote);
+                false
+            }
+            None => {
+                tracing::debug!("{intro} => accepted.");
+                true
+            }
+        }
+    }
+}
+
+/// QUIC transport for incoming connections.
+///
+/// This transport is IP-protocol agnostic.
+#[derive(Debug)]
+pub struct QuicAcceptor {
+    endpoints: Vec<Endpoint>,
+    handshake_timeout: Duration,
+}
+
+impl fmt::Display for QuicAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let addrs: Vec<_> = self
+            .endpoints
+            .iter()
+            .filter_map(|endpoint| endpoint.local_addr().ok().map(|addr| addr.to_string()))
+            .collect();
+        if addrs.len() > 1 {
+            write!(f, "[{}]", addrs.join(", "))
+        } else {
+            write!(f, "{}", addrs[0])
+        }
+    }
+}
+
+impl QuicAcceptor {
+    /// Create a new QUIC transport listening for incoming connections.
+    ///
+    /// It listens on the local addresses specified in `addrs` and identifies
+    /// itself using the certificate chain `cert_chain` and the private key `key`.
+    pub fn new(
+        addrs: impl IntoIterator<Item = SocketAddr>, cert_chain: Vec<rustls::Certificate>,
+        key: rustls::PrivateKey,
+    ) -> Result<Self> {
+        let server_cfg = ServerConfig::with_single_cert(cert_chain, key)
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+        Self::with_quinn_cfg(addrs, server_cfg)
+    }
+
+    /// Create a new QUIC transport listening for incoming connections using the specified
+    /// QUIC server configuration.
+    ///
+    /// It listens on the local addresses specified in `addrs`.
+    pub fn with_quinn_cfg(addrs: impl IntoIterator<Item = SocketAddr>, server_cfg: ServerConfig) -> Result<Self> {
+        let endpoints = addrs
+            .into_iter()
+            .map(|addr| Endpoint::server(server_cfg.clone(), addr))
+            .collect::<Result<Vec<_>>>()?;
+
+        if endpoints.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "at least one listening address is required"));
+        }
+
+        Ok(Self { endpoints, handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT })
+    }
+
+    /// Sets the timeout for accepting a QUIC connection.
+    ///
+    /// This includes the time until the client has opened the stream.
+    /// The default is 10 seconds.
+    pub fn set_handshake_timeout(&mut self, handshake_timeout: Duration) {
+        self.handshake_timeout = handshake_timeout;
+    }
+
+    /// Accepts an incoming QUIC connection.
+    async fn accept(connecting: Connecting) -> Result<AcceptedIoBox> {
+        let conn = connecting.await?;
+        let alpn = negotiated_alpn(&conn);
+
+        // Use proper IPv4 addresses.
+        let mut remote = conn.remote_address();
+        use_proper_ipv4(&mut remote);
+        let Some(local_ip) = conn.local_ip() else {
+            return Err(Error::new(ErrorKind::Unsupported, "local IP address of connection is unknown"));
+        };
+        let mut local = SocketAddr::new(local_ip, 0);
+        use_proper_ipv4(&mut local);
+
+        // Find local interface.
+        let Some(interface) = interface_name_for_addr(local.ip())? else {
+            return Err(Error::new(ErrorKind::NotFound, format!("interface for {} not found", local.ip())));
+        };
+
+        let (tx, mut rx) = conn.accept_bi().await?;
+        let mut hello = [0; STREAM_HELLO.len()];
+        AsyncReadExt::read_exact(&mut rx, &mut hello).await?;
+        if hello != STREAM_HELLO {
+            conn.close(0u32.into(), b"invalid stream");
+            return Err(Error::new(ErrorKind::InvalidData, "invalid stream hello"));
+        }
+
+        let tag = QuicLinkTag::new(&interface, remote, alpn, Direction::Incoming);
+        Ok(AcceptedIoBox::new(rx, tx, tag))
+    }
+}
+
+#[async_trait]
+impl AcceptingTransport for QuicAcceptor {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
+        let mut handshakes = FuturesUnordered::new();
+
+        loop {
+            tokio::select! {
+                (connecting, _, _) = future::select_all(self.endpoints.iter().map(|endpoint| endpoint.accept().boxed())) => {
+                    let Some(connecting) = connecting else {
+                        return Err(Error::new(ErrorKind::BrokenPipe, "QUIC endpoint was closed"));
+                    };
+
+                    let remote = connecting.remote_address();
+                    handshakes.push(timeout(self.handshake_timeout, Self::accept(connecting)).map(move |res| (remote, res)));
+                }
+                Some((remote, res)) = handshakes.next() => {
+                    match res {
+                        Ok(Ok(accepted)) => {
+                            tracing::debug!("Accepted QUIC connection {}", &accepted.tag);
+                            let _ = tx.send(accepted).await;
+                        }
+                        Ok(Err(err)) => tracing::debug!("QUIC connection from {remote} failed: {err}"),
+                        Err(_) => tracing::debug!("QUIC handshake with {remote} timed out"),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -10,6 +10,7 @@ use std::{
     fmt,
     hash::{Hash, Hasher},
     io::Result,
+    num::NonZeroUsize,
 };
 use tokio::sync::{mpsc, watch};
 
@@ -18,6 +19,9 @@ use aggligator::control::Direction;
 
 static NAME: &str = "rfcomm";
 
+/// Preferred size of data segments sent over RFCOMM links, which use small frames.
+const SEGMENT_SIZE: usize = 1_024;
+
 /// Link tag for Bluetooth RFCOMM link.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct RfcommLinkTag {
@@ -104,6 +108,10 @@ impl ConnectingTransport for RfcommConnector {
         NAME
     }
 
+    fn segment_size(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(SEGMENT_SIZE)
+    }
+
     async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
         let tag = RfcommLinkTag::new(self.local, self.remote, Direction::Outgoing);
         tx.send_replace([Box::new(tag) as Box<dyn LinkTag>].into_iter().collect());
@@ -149,6 +157,10 @@ impl AcceptingTransport for RfcommAcceptor {
         NAME
     }
 
+    fn segment_size(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(SEGMENT_SIZE)
+    }
+
     async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
         loop {
             let (socket, remote) = self.listener.accept().await?;
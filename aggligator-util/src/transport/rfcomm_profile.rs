@@ -14,6 +14,7 @@ use std::{
     fmt, future,
     hash::{Hash, Hasher},
     io::{Error, ErrorKind, Result},
+    num::NonZeroUsize,
     time::Duration,
 };
 use tokio::{
@@ -26,6 +27,9 @@ use aggligator::{control::Direction, Link};
 
 static NAME: &str = "rfcomm_profile";
 
+/// Preferred size of data segments sent over RFCOMM links, which use small frames.
+const SEGMENT_SIZE: usize = 1_024;
+
 /// Link tag for Bluetooth RFCOMM profile link.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum RfcommProfileLinkTag {
@@ -158,6 +162,10 @@ impl ConnectingTransport for RfcommProfileConnector {
         NAME
     }
 
+    fn segment_size(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(SEGMENT_SIZE)
+    }
+
     async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
         let mut connected_rx = self.connected_rx.clone();
 
@@ -301,6 +309,10 @@ impl AcceptingTransport for RfcommProfileAcceptor {
         NAME
     }
 
+    fn segment_size(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(SEGMENT_SIZE)
+    }
+
     async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
         let mut hndl = self.profile_handle.lock().await;
 
@@ -0,0 +1,320 @@
+//! SCTP transport.
+//!
+//! Each link is a one-to-one style SCTP association.
+//! An association spans all addresses of the remote endpoint and, unless
+//! bound to a specific local address, all local addresses.
+//! This provides failover between network paths below Aggligator, while
+//! Aggligator aggregates the associations.
+//!
+//! SCTP is only supported on Linux and requires kernel support for the protocol.
+//! On other platforms creating a transport fails with an error of kind
+//! [`Unsupported`](ErrorKind::Unsupported).
+
+use async_trait::async_trait;
+use futures::{future, FutureExt};
+use socket2::{Domain, Socket};
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    net::SocketAddr,
+    time::Duration,
+};
+use tokio::{
+    net::{TcpListener, TcpSocket},
+    sync::{mpsc, watch},
+    time::sleep,
+};
+
+use super::{
+    ip::{hosts_with_default_ports, resolve_hosts, use_proper_ipv4},
+    AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox,
+};
+use aggligator::control::Direction;
+
+pub use super::ip::IpVersion;
+
+static NAME: &str = "sctp";
+
+/// Link tag for SCTP link.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SctpLinkTag {
+    /// Local address.
+    ///
+    /// For outgoing links this is only set when [bind addresses](SctpConnector::set_bind_addrs)
+    /// have been specified.
+    pub local: Option<SocketAddr>,
+    /// Primary address of the remote endpoint.
+    pub remote: SocketAddr,
+    /// Link direction.
+    pub direction: Direction,
+}
+
+impl fmt::Display for SctpLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        match self.local {
+            Some(local) => write!(f, "{local} {dir} {}", self.remote),
+            None => write!(f, "{dir} {}", self.remote),
+        }
+    }
+}
+
+impl SctpLinkTag {
+    /// Creates a new link tag for a SCTP link.
+    pub fn new(local: Option<SocketAddr>, remote: SocketAddr, direction: Direction) -> Self {
+        Self { local, remote, direction }
+    }
+}
+
+impl LinkTag for SctpLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// Creates a one-to-one style SCTP socket for the specified address family.
+#[cfg(target_os = "linux")]
+fn sctp_socket(domain: Domain) -> Result<Socket> {
+    use socket2::{Protocol, Type};
+
+    const EPROTONOSUPPORT: i32 = 93;
+
+    match Socket::new(domain, Type::STREAM, Some(Protocol::from(libc::IPPROTO_SCTP))) {
+        Ok(socket) => Ok(socket),
+        Err(err) if err.raw_os_error() == Some(EPROTONOSUPPORT) => {
+            Err(Error::new(ErrorKind::Unsupported, "SCTP is not supported by the kernel"))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Creates a one-to-one style SCTP socket for the specified address family.
+#[cfg(not(target_os = "linux"))]
+fn sctp_socket(_domain: Domain) -> Result<Socket> {
+    Err(Error::new(ErrorKind::Unsupported, "SCTP is not supported on this platform"))
+}
+
+/// SCTP transport for outgoing connections.
+///
+/// One association is established to each resolved address of the hosts.
+#[derive(Debug, Clone)]
+pub struct SctpConnector {
+    hosts: Vec<String>,
+    ip_version: IpVersion,
+    resolve_interval: Duration,
+    bind_addrs: Vec<SocketAddr>,
+}
+
+impl fmt::Display for SctpConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.hosts.len() > 1 {
+            write!(f, "[{}]", self.hosts.join(", "))
+        } else {
+            write!(f, "{}", &self.hosts[0])
+        }
+    }
+}
+
+impl SctpConnector {
+    /// Create a new SCTP transport for outgoing connections.
+    ///
+    /// `hosts` can contain IP addresses and hostnames, including port numbers.
+    /// If an entry does not specify a port number, the `default_port` is used.
+    ///
+    /// It is checked at creation that SCTP is supported and that `hosts` resolves
+    /// to at least one IP address.
+    ///
+    /// Host name resolution is retried periodically, thus DNS updates will be taken
+    /// into account without the need to recreate this transport.
+    pub async fn new(hosts: impl IntoIterator<Item = String>, default_port: u16) -> Result<Self> {
+        sctp_socket(Domain::IPV4)?;
+
+        let hosts = hosts_with_default_ports(hosts, &[default_port])?;
+        let this = Self {
+            hosts,
+            ip_version: IpVersion::Both,
+            resolve_interval: Duration::from_secs(10),
+            bind_addrs: Vec::new(),
+        };
+
+        let addrs = resolve_hosts(&this.hosts, this.ip_version).await;
+        if addrs.is_empty() {
+            return Err(Error::new(ErrorKind::NotFound, "cannot resolve IP address of host"));
+        }
+        tracing::info!("{} resolves to: {:?}", &this, addrs);
+
+        Ok(this)
+    }
+
+    /// Sets the IP version used for connecting.
+    pub fn set_ip_version(&mut self, ip_version: IpVersion) {
+        self.ip_version = ip_version;
+    }
+
+    /// Sets the interval for re-resolving the hostname.
+    pub fn set_resolve_interval(&mut self, resolve_interval: Duration) {
+        self.resolve_interval = resolve_interval;
+    }
+
+    /// Sets the local addresses that outgoing associations are bound to.
+    ///
+    /// A separate association is established from each bind address to each
+    /// resolved remote address of the same IP version.
+    /// The port of a bind address should usually be zero to let the operating system
+    /// choose a free port.
+    ///
+    /// By default a single association using all local addresses is established
+    /// to each remote address.
+    pub fn set_bind_addrs(&mut self, bind_addrs: impl IntoIterator<Item = SocketAddr>) {
+        self.bind_addrs = bind_addrs.into_iter().collect();
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for SctpConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        loop {
+            let mut tags: HashSet<LinkTagBox> = HashSet::new();
+            for remote in resolve_hosts(&self.hosts, self.ip_version).await {
+                if self.bind_addrs.is_empty() {
+                    tags.insert(Box::new(SctpLinkTag::new(None, remote, Direction::Outgoing)));
+                }
+                for local in self.bind_addrs.iter().filter(|local| local.is_ipv4() == remote.is_ipv4()) {
+                    tags.insert(Box::new(SctpLinkTag::new(Some(*local), remote, Direction::Outgoing)));
+                }
+            }
+
+            tx.send_if_modified(|v| {
+                if *v != tags {
+                    *v = tags;
+                    true
+                } else {
+                    false
+                }
+            });
+
+            sleep(self.resolve_interval).await;
+        }
+    }
+
+    async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
+        let tag: &SctpLinkTag = tag.as_any().downcast_ref().unwrap();
+
+        let socket = sctp_socket(Domain::for_address(tag.remote))?;
+        if let Some(local) = tag.local {
+            socket.bind(&local.into())?;
+        }
+        socket.set_nonblocking(true)?;
+
+        let stream = TcpSocket::from_std_stream(socket.into()).connect(tag.remote).await?;
+        let (rh, wh) = stream.into_split();
+        Ok(IoBox::new(rh, wh))
+    }
+}
+
+/// SCTP transport for incoming connections.
+#[derive(Debug)]
+pub struct SctpAcceptor {
+    listeners: Vec<TcpListener>,
+}
+
+impl fmt::Display for SctpAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let addrs: Vec<_> = self
+            .listeners
+            .iter()
+            .filter_map(|listener| listener.local_addr().ok().map(|addr| addr.to_string()))
+            .collect();
+        if addrs.len() > 1 {
+            write!(f, "[{}]", addrs.join(", "))
+        } else {
+            write!(f, "{}", addrs[0])
+        }
+    }
+}
+
+impl SctpAcceptor {
+    /// Create a new SCTP transport listening for incoming connections.
+    ///
+    /// It listens on the local addresses specified in `addrs`.
+    /// An association accepted on an unspecified address uses all local addresses.
+    pub async fn new(addrs: impl IntoIterator<Item = SocketAddr>) -> Result<Self> {
+        let mut listeners = Vec::new();
+
+        for addr in addrs {
+            let socket = sctp_socket(Domain::for_address(addr))?;
+            socket.set_reuse_address(true)?;
+            socket.bind(&addr.into())?;
+            socket.listen(1024)?;
+            socket.set_nonblocking(true)?;
+            listeners.push(TcpListener::from_std(socket.into())?);
+        }
+
+        if listeners.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "at least one address is required"));
+        }
+
+        Ok(Self { listeners })
+    }
+}
+
+#[async_trait]
+impl AcceptingTransport for SctpAcceptor {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
+        loop {
+            let (res, _, _) =
+                future::select_all(self.listeners.iter().map(|listener| listener.accept().boxed())).await;
+            let (stream, mut remote) = res?;
+            let mut local = stream.local_addr()?;
+
+            use_proper_ipv4(&mut remote);
+            use_proper_ipv4(&mut local);
+
+            tracing::debug!("Accepted SCTP association from {remote} on {local}");
+            let tag = SctpLinkTag::new(Some(local), remote, Direction::Incoming);
+
+            let (rh, wh) = stream.into_split();
+            let _ = tx.send(AcceptedIoBox::new(rh, wh, tag)).await;
+        }
+    }
+}
@@ -0,0 +1,468 @@
+//! Serial port transport.
+//!
+//! This is useful for embedded devices that are connected via
+//! RS-232 or USB-serial adapters.
+//!
+//! Since a serial line is a raw byte stream without any notion of
+//! a connection, both sides perform a short synchronization handshake
+//! after opening the port.
+//! This discards any stale data left in the buffers of the serial line
+//! before the link protocol starts.
+//! Afterwards the byte stream is passed through unmodified, since
+//! the link protocol performs its own framing.
+//!
+//! A serial port does not report when the remote side goes away.
+//! Thus, if no data is received within the [read timeout](SerialConnector::set_read_timeout),
+//! the link fails.
+//! The read timeout must be longer than the [link ping interval](aggligator::cfg::Cfg::link_ping)
+//! to avoid failing idle links.
+//!
+//! Device nodes of USB-serial adapters disappear when the adapter is
+//! unplugged or re-enumerated.
+//! Both sides wait for the device node to reappear and then reopen the
+//! serial port.
+
+use async_trait::async_trait;
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::HashSet,
+    fmt,
+    future::Future,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{split, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf, ReadHalf, WriteHalf},
+    sync::{mpsc, oneshot, watch},
+    time::{sleep, timeout, Instant, Sleep},
+};
+use tokio_serial::{ClearBuffer, SerialPort, SerialPortBuilderExt, SerialStream};
+
+use super::{AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox};
+use aggligator::control::Direction;
+
+static NAME: &str = "serial";
+
+/// Sent repeatedly by the connecting side until acknowledged.
+const HELLO: &[u8] = b"\0aggligator-serial-hello\n";
+/// Sent by the accepting side in response to each hello.
+const ACK: &[u8] = b"\0aggligator-serial-ack\n";
+/// Sent once by the connecting side after receiving an acknowledgement.
+const START: &[u8] = b"\0aggligator-serial-start\n";
+/// Sent once by the accepting side after receiving start.
+const READY: &[u8] = b"\0aggligator-serial-ready\n";
+
+/// Interval for resending hello.
+const HELLO_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Initial delay before reopening the serial port after a failure.
+const REOPEN_DELAY_MIN: Duration = Duration::from_millis(100);
+
+/// Maximum delay before reopening the serial port after a failure.
+const REOPEN_DELAY_MAX: Duration = Duration::from_secs(10);
+
+/// Interval for checking whether a device node exists.
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Default read timeout.
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Link tag for serial port link.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SerialLinkTag {
+    /// Device path.
+    pub device: String,
+    /// Baud rate.
+    pub baud: u32,
+    /// Link direction.
+    pub direction: Direction,
+}
+
+impl fmt::Display for SerialLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        write!(f, "{dir} {} @ {} baud", self.device, self.baud)
+    }
+}
+
+impl SerialLinkTag {
+    /// Creates a new link tag for a serial port link.
+    pub fn new(device: &str, baud: u32, direction: Direction) -> Self {
+        Self { device: device.to_string(), baud, direction }
+    }
+
+    /// Whether the device node exists.
+    fn device_exists(&self) -> bool {
+        Path::new(&self.device).exists()
+    }
+
+    /// Waits until the device node exists.
+    async fn wait_for_device(&self) {
+        if !self.device_exists() {
+            tracing::debug!("waiting for serial device {} to appear", &self.device);
+            while !self.device_exists() {
+                sleep(DEVICE_POLL_INTERVAL).await;
+            }
+        }
+    }
+
+    /// Opens the serial port and clears its buffers.
+    fn open(&self) -> Result<SerialStream> {
+        let port = tokio_serial::new(&self.device, self.baud).open_native_async()?;
+        port.clear(ClearBuffer::All)?;
+        Ok(port)
+    }
+}
+
+impl LinkTag for SerialLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// Reads from the serial port until one of the specified markers is received.
+///
+/// All other data is discarded.
+/// Returns the index of the received marker.
+async fn wait_for_marker(rx: &mut ReadHalf<SerialStream>, markers: &[&[u8]]) -> Result<usize> {
+    let max_len = markers.iter().map(|m| m.len()).max().unwrap_or_default();
+    let mut window = Vec::with_capacity(max_len);
+
+    loop {
+        let b = rx.read_u8().await?;
+        if window.len() == max_len {
+            window.remove(0);
+        }
+        window.push(b);
+
+        if let Some(idx) = markers.iter().position(|m| window.ends_with(m)) {
+            return Ok(idx);
+        }
+    }
+}
+
+/// Performs the synchronization handshake from the connecting side.
+async fn sync_connect(rx: &mut ReadHalf<SerialStream>, tx: &mut WriteHalf<SerialStream>) -> Result<()> {
+    loop {
+        tx.write_all(HELLO).await?;
+        tx.flush().await?;
+
+        match timeout(HELLO_INTERVAL, wait_for_marker(rx, &[ACK])).await {
+            Ok(res) => {
+                res?;
+                break;
+            }
+            Err(_) => continue,
+        }
+    }
+
+    tx.write_all(START).await?;
+    tx.flush().await?;
+
+    wait_for_marker(rx, &[READY]).await?;
+    Ok(())
+}
+
+/// Performs the synchronization handshake from the accepting side.
+async fn sync_accept(rx: &mut ReadHalf<SerialStream>, tx: &mut WriteHalf<SerialStream>) -> Result<()> {
+    while wait_for_marker(rx, &[HELLO, START]).await? == 0 {
+        tx.write_all(ACK).await?;
+        tx.flush().await?;
+    }
+
+    tx.write_all(READY).await?;
+    tx.flush().await?;
+    Ok(())
+}
+
+/// Reader that fails when no data is received within the read timeout.
+struct TimeoutReader<R> {
+    inner: R,
+    read_timeout: Duration,
+    timer: Pin<Box<Sleep>>,
+    _guard: Option<Arc<oneshot::Sender<()>>>,
+}
+
+impl<R> TimeoutReader<R> {
+    fn new(inner: R, read_timeout: Duration, guard: Option<Arc<oneshot::Sender<()>>>) -> Self {
+        Self { inner, read_timeout, timer: Box::pin(sleep(read_timeout)), _guard: guard }
+    }
+}
+
+impl<R> AsyncRead for TimeoutReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<()>> {
+        let this = &mut *self;
+
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(res) => {
+                let deadline = Instant::now() + this.read_timeout;
+                this.timer.as_mut().reset(deadline);
+                Poll::Ready(res)
+            }
+            Poll::Pending => {
+                ready!(this.timer.as_mut().poll(cx));
+                Poll::Ready(Err(Error::new(ErrorKind::TimedOut, "serial port read timeout")))
+            }
+        }
+    }
+}
+
+/// Writer that keeps the serial port guard alive.
+struct GuardedWriter<W> {
+    inner: W,
+    _guard: Option<Arc<oneshot::Sender<()>>>,
+}
+
+impl<W> AsyncWrite for GuardedWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Opens the serial port, performs the synchronization handshake and
+/// wraps the port for use by a link.
+///
+/// The optional guard is dropped once the link has released the port.
+async fn open_link(
+    tag: &SerialLinkTag, read_timeout: Duration, guard: Option<oneshot::Sender<()>>, accept: bool,
+) -> Result<(TimeoutReader<ReadHalf<SerialStream>>, GuardedWriter<WriteHalf<SerialStream>>)> {
+    let port = tag.open()?;
+    let (mut rx, mut tx) = split(port);
+
+    if accept {
+        sync_accept(&mut rx, &mut tx).await?;
+    } else {
+        match timeout(read_timeout, sync_connect(&mut rx, &mut tx)).await {
+            Ok(res) => res?,
+            Err(_) => return Err(Error::new(ErrorKind::TimedOut, "serial port synchronization timeout")),
+        }
+    }
+
+    let guard = guard.map(Arc::new);
+    Ok((TimeoutReader::new(rx, read_timeout, guard.clone()), GuardedWriter { inner: tx, _guard: guard }))
+}
+
+/// Serial port transport for outgoing connections.
+///
+/// One persistent link is established for each serial port.
+/// The remote side must use a [`SerialAcceptor`].
+///
+/// A link is only attempted while the device node of the serial port exists.
+/// When the device node disappears, the link fails and is reestablished once the
+/// device node reappears.
+#[derive(Debug, Clone)]
+pub struct SerialConnector {
+    devices: Vec<(String, u32)>,
+    read_timeout: Duration,
+}
+
+impl fmt::Display for SerialConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let devices: Vec<_> = self.devices.iter().map(|(device, _)| device.as_str()).collect();
+        if devices.len() > 1 {
+            write!(f, "[{}]", devices.join(", "))
+        } else {
+            write!(f, "{}", devices[0])
+        }
+    }
+}
+
+impl SerialConnector {
+    /// Create a new serial port transport for outgoing connections over the serial port `device`
+    /// using the specified baud rate.
+    pub fn new(device: impl AsRef<str>, baud: u32) -> Self {
+        Self { devices: vec![(device.as_ref().to_string(), baud)], read_timeout: DEFAULT_READ_TIMEOUT }
+    }
+
+    /// Adds a serial port to connect over.
+    ///
+    /// A separate link is established over each serial port.
+    pub fn add_device(&mut self, device: impl AsRef<str>, baud: u32) {
+        let device = device.as_ref().to_string();
+        if !self.devices.iter().any(|(d, _)| *d == device) {
+            self.devices.push((device, baud));
+        }
+    }
+
+    /// Sets the read timeout.
+    ///
+    /// If no data is received within this time, the link fails.
+    /// This also limits the time for synchronizing with the remote side.
+    ///
+    /// The default is 60 seconds.
+    ///
+    /// # Panics
+    /// Panics when `read_timeout` is zero.
+    pub fn set_read_timeout(&mut self, read_timeout: Duration) {
+        assert!(!read_timeout.is_zero(), "read timeout must not be zero");
+        self.read_timeout = read_timeout;
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for SerialConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        loop {
+            let tags: HashSet<LinkTagBox> = self
+                .devices
+                .iter()
+                .map(|(device, baud)| SerialLinkTag::new(device, *baud, Direction::Outgoing))
+                .filter(|tag| tag.device_exists())
+                .map(|tag| Box::new(tag) as LinkTagBox)
+                .collect();
+
+            tx.send_if_modified(|v| {
+                if *v != tags {
+                    *v = tags;
+                    true
+                } else {
+                    false
+                }
+            });
+
+            sleep(DEVICE_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
+        let tag: &SerialLinkTag = tag.as_any().downcast_ref().unwrap();
+
+        let (rh, wh) = open_link(tag, self.read_timeout, None, false).await?;
+        Ok(IoBox::new(rh, wh))
+    }
+}
+
+/// Serial port transport for incoming connections.
+///
+/// The serial port is opened and waits for synchronization with a
+/// [`SerialConnector`] on the remote side.
+/// Once the link has terminated, the serial port is reopened for
+/// the next connection.
+/// If the device node does not exist, for example because the USB-serial
+/// adapter has been unplugged, the acceptor waits for it to appear.
+/// If the serial port cannot be opened, opening is retried with exponential backoff.
+#[derive(Debug)]
+pub struct SerialAcceptor {
+    tag: SerialLinkTag,
+    read_timeout: Duration,
+}
+
+impl fmt::Display for SerialAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.tag.device)
+    }
+}
+
+impl SerialAcceptor {
+    /// Create a new serial port transport listening for incoming connections on the serial port `device`
+    /// using the specified baud rate.
+    pub fn new(device: impl AsRef<str>, baud: u32) -> Self {
+        Self {
+            tag: SerialLinkTag::new(device.as_ref(), baud, Direction::Incoming),
+            read_timeout: DEFAULT_READ_TIMEOUT,
+        }
+    }
+
+    /// Sets the read timeout.
+    ///
+    /// If no data is received within this time, the link fails.
+    ///
+    /// The default is 60 seconds.
+    ///
+    /// # Panics
+    /// Panics when `read_timeout` is zero.
+    pub fn set_read_timeout(&mut self, read_timeout: Duration) {
+        assert!(!read_timeout.is_zero(), "read timeout must not be zero");
+        self.read_timeout = read_timeout;
+    }
+}
+
+#[async_trait]
+impl AcceptingTransport for SerialAcceptor {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
+        let mut reopen_delay = REOPEN_DELAY_MIN;
+
+        loop {
+            self.tag.wait_for_device().await;
+
+            let (guard_tx, guard_rx) = oneshot::channel();
+            let (rh, wh) = match open_link(&self.tag, self.read_timeout, Some(guard_tx), true).await {
+                Ok(io) => io,
+                Err(err) => {
+                    tracing::warn!(
+                        "cannot open serial port {}, retrying in {} ms: {err}",
+                        &self.tag.device,
+                        reopen_delay.as_millis()
+                    );
+                    sleep(reopen_delay).await;
+                    reopen_delay = (reopen_delay * 2).min(REOPEN_DELAY_MAX);
+                    continue;
+                }
+            };
+            reopen_delay = REOPEN_DELAY_MIN;
+            tracing::debug!("Accepted serial connection {}", &self.tag);
+
+            let _ = tx.send(AcceptedIoBox::new(rh, wh, self.tag.clone())).await;
+
+            // Wait until the link has released the serial port.
+            let _ = guard_rx.await;
+            tracing::debug!("serial port {} released", &self.tag.device);
+        }
+    }
+}
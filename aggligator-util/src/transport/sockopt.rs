@@ -0,0 +1,447 @@
+//! TCP socket options.
+//!
+//! The type of service and traffic class marking is also used by the UDP transport.
+
+#![cfg_attr(not(feature = "tcp"), allow(dead_code))]
+
+use socket2::{SockRef, TcpKeepalive};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{Error, ErrorKind, Result},
+    net::{IpAddr, SocketAddr},
+    ops::RangeInclusive,
+    sync::atomic::{AtomicU16, Ordering},
+    time::Duration,
+};
+use tokio::net::TcpSocket;
+
+/// TCP keepalive configuration.
+///
+/// The [default](Self::default) sends the first probe after the connection has been idle
+/// for 60 seconds and considers it dead after 5 further probes sent 10 seconds apart,
+/// i.e. a dead connection is detected after less than two minutes.
+/// This is much shorter than the usual operating system default of two hours before the
+/// first probe and keeps NAT mappings of idle links alive.
+///
+/// Keepalive is not enabled automatically; the configuration must be passed to
+/// [`TcpConnector::set_keepalive`](super::tcp::TcpConnector::set_keepalive) or
+/// [`TcpAcceptor::set_keepalive`](super::tcp::TcpAcceptor::set_keepalive).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepaliveConfig {
+    /// Time the connection must be idle before the first keepalive probe is sent.
+    pub time: Duration,
+    /// Time between keepalive probes.
+    ///
+    /// Not supported on all platforms.
+    pub interval: Duration,
+    /// Number of unacknowledged keepalive probes after which the connection is considered dead.
+    ///
+    /// Not supported on all platforms, for example Windows.
+    pub retries: u32,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self { time: Duration::from_secs(60), interval: Duration::from_secs(10), retries: 5 }
+    }
+}
+
+impl KeepaliveConfig {
+    /// Creates a new keepalive configuration.
+    pub fn new(time: Duration, interval: Duration, retries: u32) -> Self {
+        Self { time, interval, retries }
+    }
+
+    /// Time after which an unresponsive connection is considered dead.
+    fn dead_after(&self) -> Duration {
+        self.time.saturating_add(self.interval.saturating_mul(self.retries))
+    }
+
+    /// Checks the configuration for validity.
+    fn check(&self) -> Result<()> {
+        // Many platforms specify these values in seconds.
+        if self.time < Duration::from_secs(1) || self.interval < Duration::from_secs(1) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "keepalive time and interval must be at least one second",
+            ));
+        }
+        if self.retries == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "keepalive retries must not be zero"));
+        }
+        Ok(())
+    }
+}
+
+/// Effective options of the TCP socket of a link, as reported by the operating system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SocketInfo {
+    /// Local address the socket is bound to, including the source port of outgoing links.
+    pub local: Option<SocketAddr>,
+    /// Whether Nagle's algorithm is disabled.
+    pub nodelay: bool,
+    /// Size of the send buffer in bytes.
+    pub send_buffer_size: usize,
+    /// Size of the receive buffer in bytes.
+    pub recv_buffer_size: usize,
+    /// Whether the socket uses Multipath TCP.
+    pub mptcp: bool,
+}
+
+impl fmt::Display for SocketInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(local) = self.local {
+            write!(f, "local: {local}, ")?;
+        }
+        write!(
+            f,
+            "nodelay: {}, send buffer: {} bytes, receive buffer: {} bytes",
+            self.nodelay, self.send_buffer_size, self.recv_buffer_size
+        )?;
+        if self.mptcp {
+            write!(f, ", MPTCP")?;
+        }
+        Ok(())
+    }
+}
+
+impl SocketInfo {
+    /// Queries the effective options of a socket.
+    pub(crate) fn query(socket: SockRef) -> Result<Self> {
+        Ok(Self {
+            local: socket.local_addr()?.as_socket(),
+            nodelay: socket.nodelay()?,
+            send_buffer_size: socket.send_buffer_size()?,
+            recv_buffer_size: socket.recv_buffer_size()?,
+            mptcp: is_mptcp(&socket)?,
+        })
+    }
+}
+
+/// Protocol number of Multipath TCP.
+#[cfg(target_os = "linux")]
+const IPPROTO_MPTCP: i32 = 262;
+
+/// Creates a TCP socket for the address family of `addr`.
+///
+/// If `mptcp` is true, a Multipath TCP socket is created.
+/// If the kernel refuses to create it, a plain TCP socket is created and a warning is logged.
+pub(crate) fn tcp_socket(addr: SocketAddr, mptcp: bool) -> Result<TcpSocket> {
+    #[cfg(target_os = "linux")]
+    if mptcp {
+        use socket2::{Domain, Protocol, Socket, Type};
+
+        match Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::from(IPPROTO_MPTCP))) {
+            Ok(socket) => {
+                socket.set_nonblocking(true)?;
+                return Ok(TcpSocket::from_std_stream(socket.into()));
+            }
+            Err(err) => tracing::warn!("cannot create MPTCP socket, falling back to TCP: {err}"),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    if mptcp {
+        tracing::warn!("MPTCP is not supported on this platform, falling back to TCP");
+    }
+
+    match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4(),
+        SocketAddr::V6(_) => TcpSocket::new_v6(),
+    }
+}
+
+/// Returns whether a socket uses Multipath TCP.
+#[cfg(target_os = "linux")]
+fn is_mptcp(socket: &SockRef) -> Result<bool> {
+    Ok(socket.protocol()? == Some(socket2::Protocol::from(IPPROTO_MPTCP)))
+}
+
+/// Returns whether a socket uses Multipath TCP.
+#[cfg(not(target_os = "linux"))]
+fn is_mptcp(_socket: &SockRef) -> Result<bool> {
+    Ok(false)
+}
+
+/// Maximum number of ports tried when binding a socket to a source port.
+const SOURCE_PORT_ATTEMPTS: usize = 64;
+
+/// Range of local ports outgoing TCP links are bound to.
+#[derive(Debug)]
+pub(crate) struct SourcePorts {
+    range: RangeInclusive<u16>,
+    next: AtomicU16,
+}
+
+impl SourcePorts {
+    /// Creates a new source port range.
+    pub fn new(range: RangeInclusive<u16>) -> Result<Self> {
+        if range.is_empty() || *range.start() == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "source port range must be non-empty and exclude port 0",
+            ));
+        }
+        Ok(Self { next: AtomicU16::new(*range.start()), range })
+    }
+
+    /// Binds the socket to the specified local IP address and a free port within the range.
+    ///
+    /// Ports are tried in turn, continuing after the port last tried.
+    /// Fails with [`ErrorKind::AddrInUse`] if no free port has been found after a bounded number of attempts.
+    pub fn bind(&self, socket: &TcpSocket, ip: IpAddr) -> Result<()> {
+        let (start, end) = (*self.range.start(), *self.range.end());
+        let attempts = SOURCE_PORT_ATTEMPTS.min(usize::from(end - start) + 1);
+
+        for _ in 0..attempts {
+            let port = self
+                .next
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |port| {
+                    Some(if port >= end || port < start { start } else { port + 1 })
+                })
+                .unwrap();
+
+            match socket.bind(SocketAddr::new(ip, port)) {
+                Ok(()) => return Ok(()),
+                Err(err) if err.kind() == ErrorKind::AddrInUse => {
+                    tracing::trace!("source port {port} is in use");
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(Error::new(
+            ErrorKind::AddrInUse,
+            format!("no free source port in range {start}-{end} after {attempts} attempts"),
+        ))
+    }
+}
+
+/// Options applied to TCP sockets of links.
+#[derive(Debug, Clone)]
+pub(crate) struct SocketOptions {
+    nodelay: bool,
+    send_buffer_size: Option<usize>,
+    recv_buffer_size: Option<usize>,
+    keepalive: Option<KeepaliveConfig>,
+    user_timeout: Option<Duration>,
+    tos: Option<u8>,
+    traffic_class: Option<u8>,
+    interface_tos: HashMap<Vec<u8>, u8>,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            keepalive: None,
+            user_timeout: None,
+            tos: None,
+            traffic_class: None,
+            interface_tos: HashMap::new(),
+        }
+    }
+}
+
+impl SocketOptions {
+    /// Sets whether Nagle's algorithm is disabled.
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.nodelay = nodelay;
+    }
+
+    /// Sets the size of the send buffer.
+    pub fn set_send_buffer_size(&mut self, send_buffer_size: usize) {
+        self.send_buffer_size = Some(send_buffer_size);
+    }
+
+    /// Sets the size of the receive buffer.
+    pub fn set_recv_buffer_size(&mut self, recv_buffer_size: usize) {
+        self.recv_buffer_size = Some(recv_buffer_size);
+    }
+
+    /// Sets the keepalive configuration, checking it for validity.
+    pub fn set_keepalive(&mut self, keepalive: Option<KeepaliveConfig>) -> Result<()> {
+        Self { keepalive, ..self.clone() }.check()?;
+        self.keepalive = keepalive;
+        Ok(())
+    }
+
+    /// Sets the user timeout, checking it for validity.
+    pub fn set_user_timeout(&mut self, user_timeout: Duration) -> Result<()> {
+        let user_timeout = Some(user_timeout);
+        Self { user_timeout, ..self.clone() }.check()?;
+        self.user_timeout = user_timeout;
+        Ok(())
+    }
+
+    /// Sets the type of service of IPv4 sockets.
+    pub fn set_tos(&mut self, tos: u8) {
+        self.tos = Some(tos);
+    }
+
+    /// Sets the traffic class of IPv6 sockets.
+    pub fn set_traffic_class(&mut self, traffic_class: u8) {
+        self.traffic_class = Some(traffic_class);
+    }
+
+    /// Sets the type of service and traffic class of sockets over the specified interface.
+    pub fn set_interface_tos(&mut self, interface: &[u8], tos: u8) {
+        self.interface_tos.insert(interface.to_vec(), tos);
+    }
+
+    /// Checks the combination of options for validity.
+    fn check(&self) -> Result<()> {
+        if let Some(keepalive) = &self.keepalive {
+            keepalive.check()?;
+        }
+
+        match (&self.keepalive, self.user_timeout) {
+            (_, Some(user_timeout)) if user_timeout.is_zero() => {
+                Err(Error::new(ErrorKind::InvalidInput, "user timeout must not be zero"))
+            }
+            (Some(keepalive), Some(user_timeout)) if user_timeout < keepalive.dead_after() => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "user timeout must not be shorter than the time keepalive takes to detect a dead connection",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Applies the options to a socket.
+    ///
+    /// Options that are not supported by the platform or cannot be set are skipped.
+    pub fn apply(&self, socket: SockRef) {
+        if let Err(err) = socket.set_nodelay(self.nodelay) {
+            tracing::warn!("cannot set TCP nodelay: {err}");
+        }
+
+        if let Some(size) = self.send_buffer_size {
+            if let Err(err) = socket.set_send_buffer_size(size) {
+                tracing::warn!("cannot set send buffer size: {err}");
+            }
+        }
+
+        if let Some(size) = self.recv_buffer_size {
+            if let Err(err) = socket.set_recv_buffer_size(size) {
+                tracing::warn!("cannot set receive buffer size: {err}");
+            }
+        }
+
+        if let Some(keepalive) = &self.keepalive {
+            let params = TcpKeepalive::new().with_time(keepalive.time);
+
+            #[cfg(any(
+                target_os = "android",
+                target_os = "dragonfly",
+                target_os = "freebsd",
+                target_os = "fuchsia",
+                target_os = "illumos",
+                target_os = "linux",
+                target_os = "netbsd",
+                target_vendor = "apple",
+                windows,
+            ))]
+            let params = params.with_interval(keepalive.interval);
+
+            #[cfg(any(
+                target_os = "android",
+                target_os = "dragonfly",
+                target_os = "freebsd",
+                target_os = "fuchsia",
+                target_os = "illumos",
+                target_os = "linux",
+                target_os = "netbsd",
+                target_vendor = "apple",
+            ))]
+            let params = params.with_retries(keepalive.retries);
+
+            if let Err(err) = socket.set_tcp_keepalive(&params) {
+                tracing::warn!("cannot enable TCP keepalive: {err}");
+            }
+        }
+
+        if let Some(user_timeout) = self.user_timeout {
+            #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+            if let Err(err) = socket.set_tcp_user_timeout(Some(user_timeout)) {
+                tracing::warn!("cannot set TCP user timeout: {err}");
+            }
+
+            #[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+            tracing::debug!("TCP user timeout of {user_timeout:?} is not supported on this platform");
+        }
+    }
+
+    /// Applies the type of service (IPv4) or traffic class (IPv6) to the socket of a link
+    /// over the specified interface.
+    ///
+    /// In contrast to [`apply`](Self::apply) this fails if the marking cannot be set
+    /// or is not taken over by the operating system.
+    pub fn apply_marking(&self, socket: SockRef, interface: &[u8], ipv6: bool) -> Result<()> {
+        let tos = match self.interface_tos.get(interface) {
+            Some(tos) => Some(*tos),
+            None if ipv6 => self.traffic_class,
+            None => self.tos,
+        };
+        let Some(tos) = tos else { return Ok(()) };
+        apply_tos(socket, tos, ipv6)
+    }
+}
+
+/// Sets the type of service (IPv4) or traffic class (IPv6) of a socket.
+///
+/// Fails if the marking cannot be set or is not taken over by the operating system.
+pub(crate) fn apply_tos(socket: SockRef, tos: u8, ipv6: bool) -> Result<()> {
+    let (name, effective) = if ipv6 {
+        ("traffic class", set_traffic_class(&socket, tos)?)
+    } else {
+        ("TOS", set_tos(&socket, tos)?)
+    };
+
+    // The ECN bits are managed by the operating system.
+    if effective & !ECN_MASK != tos & !ECN_MASK {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{name} {tos:#04x} is not supported, socket uses {effective:#04x}"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Explicit congestion notification bits of the type of service and traffic class fields.
+const ECN_MASK: u8 = 0b11;
+
+/// Sets the type of service of an IPv4 socket and returns the effective value.
+#[cfg(not(any(target_os = "fuchsia", target_os = "redox", target_os = "solaris", target_os = "illumos")))]
+fn set_tos(socket: &SockRef, tos: u8) -> Result<u8> {
+    socket.set_tos(tos.into()).map_err(|err| Error::new(err.kind(), format!("cannot set TOS: {err}")))?;
+    Ok(socket.tos()? as u8)
+}
+
+/// Sets the type of service of an IPv4 socket and returns the effective value.
+#[cfg(any(target_os = "fuchsia", target_os = "redox", target_os = "solaris", target_os = "illumos"))]
+fn set_tos(_socket: &SockRef, _tos: u8) -> Result<u8> {
+    Err(Error::new(ErrorKind::Unsupported, "TOS is not supported on this platform"))
+}
+
+/// Sets the traffic class of an IPv6 socket and returns the effective value.
+#[cfg(target_os = "linux")]
+fn set_traffic_class(socket: &SockRef, traffic_class: u8) -> Result<u8> {
+    use nix::sys::socket::{getsockopt, setsockopt, sockopt::Ipv6TClass};
+    use std::os::unix::io::AsRawFd;
+
+    let fd = socket.as_raw_fd();
+    setsockopt(fd, Ipv6TClass, &traffic_class.into())
+        .map_err(Error::from)
+        .map_err(|err| Error::new(err.kind(), format!("cannot set traffic class: {err}")))?;
+    Ok(getsockopt(fd, Ipv6TClass)? as u8)
+}
+
+/// Sets the traffic class of an IPv6 socket and returns the effective value.
+#[cfg(not(target_os = "linux"))]
+fn set_traffic_class(_socket: &SockRef, _traffic_class: u8) -> Result<u8> {
+    Err(Error::new(ErrorKind::Unsupported, "traffic class is not supported on this platform"))
+}
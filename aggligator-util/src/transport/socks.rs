@@ -0,0 +1,203 @@
+//! SOCKS5 proxy client.
+
+use std::{
+    fmt,
+    io::{Error, ErrorKind, Result},
+    net::{IpAddr, SocketAddr},
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const VERSION: u8 = 0x05;
+const AUTH_VERSION: u8 = 0x01;
+
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_USERNAME_PASSWORD: u8 = 0x02;
+const METHOD_NOT_ACCEPTABLE: u8 = 0xff;
+
+const CMD_CONNECT: u8 = 0x01;
+
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// SOCKS5 proxy.
+///
+/// By default the target host names are resolved locally and the proxy is asked
+/// to connect to the resulting IP addresses.
+/// Use [`set_remote_dns`](Self::set_remote_dns) to let the proxy resolve them instead.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Socks5Proxy {
+    addr: SocketAddr,
+    credentials: Option<(String, String)>,
+    remote_dns: bool,
+}
+
+impl fmt::Debug for Socks5Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Socks5Proxy")
+            .field("addr", &self.addr)
+            .field("username", &self.credentials.as_ref().map(|(username, _)| username))
+            .field("remote_dns", &self.remote_dns)
+            .finish()
+    }
+}
+
+impl fmt::Display for Socks5Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "socks5://{}", self.addr)
+    }
+}
+
+impl Socks5Proxy {
+    /// Creates a new SOCKS5 proxy configuration for the proxy at the specified address.
+    pub fn new(addr: SocketAddr) -> Self {
+        Self { addr, credentials: None, remote_dns: false }
+    }
+
+    /// Address of the proxy.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Sets the username and password for authenticating with the proxy.
+    ///
+    /// By default no authentication is performed.
+    pub fn set_credentials(&mut self, username: impl Into<String>, password: impl Into<String>) {
+        self.credentials = Some((username.into(), password.into()));
+    }
+
+    /// Sets whether target host names are resolved by the proxy.
+    ///
+    /// If enabled (SOCKS5h semantics), target host names are never resolved locally.
+    /// This is required for connecting to Tor onion services.
+    pub fn set_remote_dns(&mut self, remote_dns: bool) {
+        self.remote_dns = remote_dns;
+    }
+
+    /// Whether target host names are resolved by the proxy.
+    pub fn remote_dns(&self) -> bool {
+        self.remote_dns
+    }
+
+    /// Performs the SOCKS5 handshake over `stream` asking the proxy to connect to `target`.
+    ///
+    /// `target` is either a socket address or a host name with port.
+    /// Errors contain the address of the proxy.
+    pub(crate) async fn handshake<S>(&self, stream: &mut S, target: &str) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.handshake_inner(stream, target)
+            .await
+            .map_err(|err| Error::new(err.kind(), format!("SOCKS5 proxy {}: {err}", self.addr)))
+    }
+
+    async fn handshake_inner<S>(&self, stream: &mut S, target: &str) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        // Negotiate authentication method.
+        let method = match &self.credentials {
+            Some(_) => METHOD_USERNAME_PASSWORD,
+            None => METHOD_NO_AUTH,
+        };
+        stream.write_all(&[VERSION, 1, method]).await?;
+
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, "invalid protocol version"));
+        }
+        match reply[1] {
+            m if m == method => (),
+            METHOD_NOT_ACCEPTABLE => {
+                return Err(Error::new(ErrorKind::PermissionDenied, "no acceptable authentication method"))
+            }
+            m => {
+                return Err(Error::new(ErrorKind::InvalidData, format!("unrequested authentication method {m}")))
+            }
+        }
+
+        // Authenticate using username and password (RFC 1929).
+        if let Some((username, password)) = &self.credentials {
+            let (username, password) = (username.as_bytes(), password.as_bytes());
+            let (Ok(username_len), Ok(password_len)) =
+                (u8::try_from(username.len()), u8::try_from(password.len()))
+            else {
+                return Err(Error::new(ErrorKind::InvalidInput, "username or password too long"));
+            };
+
+            let mut req = vec![AUTH_VERSION, username_len];
+            req.extend_from_slice(username);
+            req.push(password_len);
+            req.extend_from_slice(password);
+            stream.write_all(&req).await?;
+
+            let mut reply = [0; 2];
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0 {
+                return Err(Error::new(ErrorKind::PermissionDenied, "authentication failed"));
+            }
+        }
+
+        // Request connection to target.
+        let mut req = vec![VERSION, CMD_CONNECT, 0];
+        match target.parse::<SocketAddr>() {
+            Ok(addr) => {
+                match addr.ip() {
+                    IpAddr::V4(ip) => {
+                        req.push(ATYP_IPV4);
+                        req.extend_from_slice(&ip.octets());
+                    }
+                    IpAddr::V6(ip) => {
+                        req.push(ATYP_IPV6);
+                        req.extend_from_slice(&ip.octets());
+                    }
+                }
+                req.extend_from_slice(&addr.port().to_be_bytes());
+            }
+            Err(_) => {
+                let invalid = || Error::new(ErrorKind::InvalidInput, format!("invalid target {target}"));
+                let (host, port) = target.rsplit_once(':').ok_or_else(invalid)?;
+                let port: u16 = port.parse().map_err(|_| invalid())?;
+                let host_len = u8::try_from(host.len()).map_err(|_| invalid())?;
+                req.push(ATYP_DOMAIN);
+                req.push(host_len);
+                req.extend_from_slice(host.as_bytes());
+                req.extend_from_slice(&port.to_be_bytes());
+            }
+        }
+        stream.write_all(&req).await?;
+
+        let mut reply = [0; 4];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, "invalid protocol version"));
+        }
+        if reply[1] != 0x00 {
+            let (kind, msg) = match reply[1] {
+                0x02 => (ErrorKind::PermissionDenied, "connection not allowed by ruleset"),
+                0x03 => (ErrorKind::Other, "network unreachable"),
+                0x04 => (ErrorKind::Other, "host unreachable"),
+                0x05 => (ErrorKind::ConnectionRefused, "connection refused"),
+                0x06 => (ErrorKind::TimedOut, "TTL expired"),
+                0x07 => (ErrorKind::Unsupported, "command not supported"),
+                0x08 => (ErrorKind::Unsupported, "address type not supported"),
+                _ => (ErrorKind::Other, "general failure"),
+            };
+            return Err(Error::new(kind, format!("connecting to {target} failed: {msg}")));
+        }
+
+        // Skip bound address.
+        let addr_len = match reply[3] {
+            ATYP_IPV4 => 4,
+            ATYP_IPV6 => 16,
+            ATYP_DOMAIN => stream.read_u8().await?.into(),
+            other => return Err(Error::new(ErrorKind::InvalidData, format!("invalid address type {other}"))),
+        };
+        let mut bound = vec![0; addr_len + 2];
+        stream.read_exact(&mut bound).await?;
+
+        Ok(())
+    }
+}
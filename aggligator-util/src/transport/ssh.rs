@@ -0,0 +1,387 @@
+//! SSH transport.
+//!
+//! Links are tunneled through an SSH connection to a server, so that no
+//! additional ports need to be opened on it.
+//! Each link is a direct TCP/IP channel (as used for local port forwarding by `ssh -L`)
+//! from the SSH server to the target, which is usually a [`TcpAcceptor`](super::tcp::TcpAcceptor)
+//! listening on the loopback interface of the server.
+//!
+//! All links to one server share one SSH session.
+//! If the session fails, all its links fail and are reestablished by the
+//! [connector](super::Connector) over a new session.
+
+use async_trait::async_trait;
+use futures::future;
+use russh::client::{self, Handle};
+use russh_keys::key::PublicKey;
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::split,
+    sync::{watch, Mutex},
+};
+
+use super::{ConnectingTransport, IoBox, LinkTag, LinkTagBox};
+use aggligator::control::Direction;
+
+static NAME: &str = "ssh";
+
+/// Default SSH port.
+const DEFAULT_PORT: u16 = 22;
+
+/// Converts an SSH error into an IO error.
+fn ssh_err(err: impl fmt::Display) -> Error {
+    Error::new(ErrorKind::Other, err.to_string())
+}
+
+/// Link tag for SSH link.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SshLinkTag {
+    /// User name on SSH server.
+    pub user: String,
+    /// SSH server host name.
+    pub host: String,
+    /// SSH server port.
+    pub port: u16,
+    /// Channel number.
+    ///
+    /// This distinguishes multiple links through the same SSH server.
+    pub channel: u32,
+    /// Link direction.
+    pub direction: Direction,
+}
+
+impl fmt::Display for SshLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        write!(f, "{dir} {}@{}:{} channel {}", self.user, self.host, self.port, self.channel)
+    }
+}
+
+impl SshLinkTag {
+    /// Creates a new link tag for an SSH link.
+    pub fn new(user: &str, host: &str, port: u16, channel: u32, direction: Direction) -> Self {
+        Self { user: user.to_string(), host: host.to_string(), port, channel, direction }
+    }
+}
+
+impl LinkTag for SshLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// SSH authentication method.
+#[derive(Clone)]
+pub enum SshAuth {
+    /// Authenticate using the keys provided by the SSH agent.
+    ///
+    /// The agent is contacted using the socket specified by the `SSH_AUTH_SOCK`
+    /// environment variable.
+    /// This is only supported on Unix platforms.
+    Agent,
+    /// Authenticate using a private key file.
+    KeyFile {
+        /// Path to private key file.
+        path: PathBuf,
+        /// Passphrase for decrypting the private key.
+        passphrase: Option<String>,
+    },
+}
+
+impl fmt::Debug for SshAuth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Agent => write!(f, "Agent"),
+            Self::KeyFile { path, .. } => f.debug_struct("KeyFile").field("path", path).finish_non_exhaustive(),
+        }
+    }
+}
+
+/// Handles events of an SSH client session.
+struct ClientHandler {
+    host: String,
+    port: u16,
+    accept_unknown_host_keys: bool,
+}
+
+#[async_trait]
+impl client::Handler for ClientHandler {
+    type Error = russh::Error;
+
+    async fn check_server_key(
+        self, server_public_key: &PublicKey,
+    ) -> std::result::Result<(Self, bool), Self::Error> {
+        let accept = match russh_keys::check_known_hosts(&self.host, self.port, server_public_key) {
+            Ok(true) => true,
+            Ok(false) if self.accept_unknown_host_keys => {
+                tracing::warn!("accepting unknown host key of SSH server {}:{}", self.host, self.port);
+                true
+            }
+            Ok(false) => {
+                tracing::warn!("host key of SSH server {}:{} is unknown", self.host, self.port);
+                false
+            }
+            Err(err) => {
+                tracing::warn!("checking host key of SSH server {}:{} failed: {err}", self.host, self.port);
+                false
+            }
+        };
+        Ok((self, accept))
+    }
+}
+
+/// SSH transport for outgoing connections.
+///
+/// The host key of the SSH server is verified using the `known_hosts` file
+/// of the current user.
+pub struct SshConnector {
+    user: String,
+    host: String,
+    port: u16,
+    auth: SshAuth,
+    target_host: String,
+    target_port: u16,
+    channels: u32,
+    accept_unknown_host_keys: bool,
+    keepalive_interval: Duration,
+    session: Mutex<Option<Arc<Handle<ClientHandler>>>>,
+}
+
+impl fmt::Debug for SshConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SshConnector")
+            .field("user", &self.user)
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("auth", &self.auth)
+            .field("target_host", &self.target_host)
+            .field("target_port", &self.target_port)
+            .field("channels", &self.channels)
+            .finish()
+    }
+}
+
+impl fmt::Display for SshConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}@{}:{} -> {}:{}", self.user, self.host, self.port, self.target_host, self.target_port)
+    }
+}
+
+impl SshConnector {
+    /// Creates a new SSH transport for outgoing connections.
+    ///
+    /// `destination` specifies the SSH server in the form `user@host` or `user@host:port`.
+    /// Links are tunneled to `target_port` on the loopback interface of the SSH server,
+    /// see [`set_target_host`](Self::set_target_host) for connecting to another host.
+    pub fn new(destination: &str, target_port: u16, auth: SshAuth) -> Result<Self> {
+        let invalid = || Error::new(ErrorKind::InvalidInput, format!("invalid SSH destination: {destination}"));
+
+        let (user, host) = destination.split_once('@').ok_or_else(invalid)?;
+        let (host, port) = match host.parse::<SocketAddr>() {
+            Ok(addr) => (addr.ip().to_string(), addr.port()),
+            Err(_) => match host.rsplit_once(':') {
+                Some((host, port)) if !host.contains(':') => {
+                    (host.to_string(), port.parse().map_err(|_| invalid())?)
+                }
+                _ => (host.trim_start_matches('[').trim_end_matches(']').to_string(), DEFAULT_PORT),
+            },
+        };
+        if user.is_empty() || host.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            user: user.to_string(),
+            host,
+            port,
+            auth,
+            target_host: "127.0.0.1".to_string(),
+            target_port,
+            channels: 1,
+            accept_unknown_host_keys: false,
+            keepalive_interval: Duration::from_secs(15),
+            session: Mutex::new(None),
+        })
+    }
+
+    /// Sets the host, as seen from the SSH server, links are tunneled to.
+    ///
+    /// The default is `127.0.0.1`.
+    pub fn set_target_host(&mut self, target_host: impl Into<String>) {
+        self.target_host = target_host.into();
+    }
+
+    /// Sets the number of links established through the SSH server.
+    ///
+    /// Each link uses its own channel within the shared SSH session.
+    /// The default is one.
+    pub fn set_channels(&mut self, channels: u32) {
+        self.channels = channels.max(1);
+    }
+
+    /// Sets whether host keys of SSH servers that are not present in the `known_hosts`
+    /// file are accepted.
+    ///
+    /// This makes the connection vulnerable to man-in-the-middle attacks.
+    /// Changed host keys are never accepted.
+    /// The default is `false`.
+    pub fn set_accept_unknown_host_keys(&mut self, accept_unknown_host_keys: bool) {
+        self.accept_unknown_host_keys = accept_unknown_host_keys;
+    }
+
+    /// Sets the interval for sending keepalive messages over the SSH session.
+    ///
+    /// This is used to detect failed sessions.
+    /// The default is 15 seconds.
+    pub fn set_keepalive_interval(&mut self, keepalive_interval: Duration) {
+        self.keepalive_interval = keepalive_interval;
+    }
+
+    /// Returns the SSH session, establishing it if necessary.
+    async fn session(&self) -> Result<Arc<Handle<ClientHandler>>> {
+        let mut session = self.session.lock().await;
+
+        if let Some(handle) = &*session {
+            if !handle.is_closed() {
+                return Ok(handle.clone());
+            }
+            tracing::debug!("SSH session to {}:{} was closed", self.host, self.port);
+        }
+
+        let handle = Arc::new(self.establish_session().await?);
+        *session = Some(handle.clone());
+        Ok(handle)
+    }
+
+    /// Establishes and authenticates a new SSH session.
+    async fn establish_session(&self) -> Result<Handle<ClientHandler>> {
+        tracing::debug!("establishing SSH session to {}@{}:{}", self.user, self.host, self.port);
+
+        let config = client::Config { keepalive_interval: Some(self.keepalive_interval), ..Default::default() };
+        let handler = ClientHandler {
+            host: self.host.clone(),
+            port: self.port,
+            accept_unknown_host_keys: self.accept_unknown_host_keys,
+        };
+        let mut handle =
+            client::connect(Arc::new(config), (self.host.as_str(), self.port), handler).await.map_err(ssh_err)?;
+
+        let authenticated = match &self.auth {
+            SshAuth::Agent => self.authenticate_with_agent(&mut handle).await?,
+            SshAuth::KeyFile { path, passphrase } => {
+                let key = russh_keys::load_secret_key(path, passphrase.as_deref()).map_err(|err| {
+                    Error::new(ErrorKind::InvalidInput, format!("cannot load SSH key {}: {err}", path.display()))
+                })?;
+                handle.authenticate_publickey(&self.user, Arc::new(key)).await.map_err(ssh_err)?
+            }
+        };
+        if !authenticated {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!("SSH authentication as {}@{}:{} failed", self.user, self.host, self.port),
+            ));
+        }
+
+        tracing::debug!("SSH session to {}@{}:{} established", self.user, self.host, self.port);
+        Ok(handle)
+    }
+
+    /// Authenticates using the keys provided by the SSH agent.
+    #[cfg(unix)]
+    async fn authenticate_with_agent(&self, handle: &mut Handle<ClientHandler>) -> Result<bool> {
+        let mut agent = russh_keys::agent::client::AgentClient::connect_env().await.map_err(ssh_err)?;
+        let keys = agent.request_identities().await.map_err(ssh_err)?;
+
+        for key in keys {
+            let (returned_agent, res) = handle.authenticate_future(&self.user, key, agent).await;
+            agent = returned_agent;
+            if res.map_err(ssh_err)? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Authenticates using the keys provided by the SSH agent.
+    #[cfg(not(unix))]
+    async fn authenticate_with_agent(&self, _handle: &mut Handle<ClientHandler>) -> Result<bool> {
+        Err(Error::new(ErrorKind::Unsupported, "SSH agent is not supported on this platform"))
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for SshConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        let tags = (0..self.channels)
+            .map(|channel| {
+                Box::new(SshLinkTag::new(&self.user, &self.host, self.port, channel, Direction::Outgoing))
+                    as Box<dyn LinkTag>
+            })
+            .collect();
+        tx.send_replace(tags);
+        future::pending().await
+    }
+
+    async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
+        let tag: &SshLinkTag = tag.as_any().downcast_ref().unwrap();
+
+        let session = self.session().await?;
+        let channel = session
+            .channel_open_direct_tcpip(self.target_host.as_str(), self.target_port.into(), "127.0.0.1", 0)
+            .await
+            .map_err(|err| {
+                Error::new(
+                    ErrorKind::ConnectionRefused,
+                    format!("opening SSH channel to {}:{} failed: {err}", self.target_host, self.target_port),
+                )
+            })?;
+        tracing::debug!("SSH channel for {tag} opened");
+
+        let (rh, wh) = split(channel.into_stream());
+        Ok(IoBox::new(rh, wh))
+    }
+}
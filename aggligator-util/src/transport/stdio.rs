@@ -0,0 +1,204 @@
+//! Stdio transport.
+//!
+//! This transport uses the standard input and output of the process as a single link.
+//! It allows running Aggligator over a channel established by other means,
+//! for example `ssh host program` or the standard streams of a container.
+//! It can be used together with other transports, so that the pipe acts as one
+//! of several aggregated links.
+//!
+//! Standard input and output can be claimed by only one transport per process and
+//! thus only one link is established.
+//! The link is closed when standard input reaches its end.
+//! Since the link data is written to standard output, nothing else, such as log
+//! output, must be written to it while the transport is in use.
+//! Use standard error for logging instead.
+
+use async_trait::async_trait;
+use futures::future;
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    sync::{
+        atomic::{AtomicBool, Ordering as AtomicOrdering},
+        Mutex,
+    },
+};
+use tokio::{
+    io::{stdin, stdout},
+    sync::{mpsc, watch},
+};
+
+use super::{AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox};
+use aggligator::control::Direction;
+
+static NAME: &str = "stdio";
+
+/// Whether standard input and output have been claimed by a transport.
+static CLAIMED: AtomicBool = AtomicBool::new(false);
+
+/// Claims standard input and output for exclusive use by a transport.
+fn claim() -> Result<IoBox> {
+    if CLAIMED.swap(true, AtomicOrdering::SeqCst) {
+        return Err(Error::new(ErrorKind::AddrInUse, "standard input and output are already in use"));
+    }
+    Ok(IoBox::new(stdin(), stdout()))
+}
+
+fn used_error() -> Error {
+    Error::new(ErrorKind::NotConnected, "standard input and output have already been used for a link")
+}
+
+/// Link tag for stdio link.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StdioLinkTag {
+    /// Link direction.
+    pub direction: Direction,
+}
+
+impl fmt::Display for StdioLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        write!(f, "{dir} stdio")
+    }
+}
+
+impl StdioLinkTag {
+    /// Creates a new link tag for the stdio link.
+    pub fn new(direction: Direction) -> Self {
+        Self { direction }
+    }
+}
+
+impl LinkTag for StdioLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// Stdio transport for outgoing connections.
+///
+/// The link tag is available until the link has been established.
+/// Afterwards it is withdrawn, since standard input and output cannot be reused.
+pub struct StdioConnector {
+    io: Mutex<Option<IoBox>>,
+    used_tx: watch::Sender<bool>,
+}
+
+impl fmt::Debug for StdioConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StdioConnector").field("used", &*self.used_tx.borrow()).finish()
+    }
+}
+
+impl StdioConnector {
+    /// Creates a new stdio transport for outgoing connections.
+    ///
+    /// Fails if standard input and output have already been claimed by another transport.
+    pub fn new() -> Result<Self> {
+        let io = claim()?;
+        Ok(Self { io: Mutex::new(Some(io)), used_tx: watch::channel(false).0 })
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for StdioConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        let mut used_rx = self.used_tx.subscribe();
+
+        let tag: LinkTagBox = Box::new(StdioLinkTag::new(Direction::Outgoing));
+        tx.send_replace([tag].into_iter().collect());
+
+        while !*used_rx.borrow_and_update() {
+            // The transport keeps the sender alive.
+            used_rx.changed().await.unwrap();
+        }
+        tx.send_replace(HashSet::new());
+
+        // Returning would remove the transport and thus disconnect the link.
+        future::pending().await
+    }
+
+    async fn connect(&self, _tag: &dyn LinkTag) -> Result<IoBox> {
+        let io = self.io.lock().unwrap().take().ok_or_else(used_error)?;
+        self.used_tx.send_replace(true);
+        Ok(io)
+    }
+}
+
+/// Stdio transport for incoming connections.
+///
+/// The link is accepted immediately when the transport is added to an acceptor.
+pub struct StdioAcceptor {
+    io: Mutex<Option<IoBox>>,
+}
+
+impl fmt::Debug for StdioAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StdioAcceptor").field("used", &self.io.lock().unwrap().is_none()).finish()
+    }
+}
+
+impl StdioAcceptor {
+    /// Creates a new stdio transport for incoming connections.
+    ///
+    /// Fails if standard input and output have already been claimed by another transport.
+    pub fn new() -> Result<Self> {
+        let io = claim()?;
+        Ok(Self { io: Mutex::new(Some(io)) })
+    }
+}
+
+#[async_trait]
+impl AcceptingTransport for StdioAcceptor {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
+        let io = self.io.lock().unwrap().take().ok_or_else(used_error)?;
+
+        let tag = StdioLinkTag::new(Direction::Incoming);
+        tracing::debug!("Accepted stdio link {tag}");
+
+        let _ = tx.send(AcceptedIoBox { io, tag: Box::new(tag) }).await;
+
+        // Returning would remove the transport and thus disconnect the link.
+        future::pending().await
+    }
+}
@@ -2,73 +2,101 @@
 
 use async_trait::async_trait;
 use futures::{future, FutureExt};
-use network_interface::{NetworkInterface, NetworkInterfaceConfig};
+use network_interface::NetworkInterface;
 use std::{
     any::Any,
     cmp::Ordering,
-    collections::HashSet,
+    collections::{BTreeMap, HashMap, HashSet},
     fmt,
     hash::{Hash, Hasher},
     io::{Error, ErrorKind, Result},
-    net::{IpAddr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    num::NonZeroUsize,
+    ops::RangeInclusive,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 use tokio::{
-    net::{lookup_host, TcpListener, TcpSocket},
-    sync::{mpsc, watch},
-    time::sleep,
+    net::{TcpListener, TcpSocket, TcpStream},
+    sync::{mpsc, watch, Notify},
+    time::{sleep, sleep_until, timeout, Instant},
 };
 
-use super::{AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox};
+use super::{
+    ip::{
+        addr_in_network, host_with_default_ports, host_with_port, hosts_with_default_ports, interface_index,
+        interface_name_for_local_addr, is_ipv6_link_local, local_interfaces, resolve_hosts_with, use_proper_ipv4,
+        without_scope,
+    },
+    sockopt::{tcp_socket, SocketOptions, SourcePorts},
+    AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox,
+};
 use aggligator::{control::Direction, Link};
 
 static NAME: &str = "tcp";
 
-/// IP protocol version.
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum IpVersion {
-    /// IP version 4.
-    IPv4,
-    /// IP version 6.
-    IPv6,
-    /// Both IP versions.
-    #[default]
-    Both,
-}
-
-impl IpVersion {
-    /// Create from "only" arguments.
-    pub fn from_only(only_ipv4: bool, only_ipv6: bool) -> Result<Self> {
-        match (only_ipv4, only_ipv6) {
-            (false, false) => Ok(Self::Both),
-            (true, false) => Ok(Self::IPv4),
-            (false, true) => Ok(Self::IPv6),
-            (true, true) => {
-                Err(Error::new(ErrorKind::InvalidInput, "IPv4 and IPv6 options are mutally exclusive"))
-            }
-        }
-    }
-
-    /// Whether only IPv4 should be supported.
-    pub fn is_only_ipv4(&self) -> bool {
-        matches!(self, Self::IPv4)
-    }
-
-    /// Whether only IPv6 should be supported.
-    pub fn is_only_ipv6(&self) -> bool {
-        matches!(self, Self::IPv6)
-    }
-}
+pub use super::{
+    http_proxy::HttpProxy,
+    ip::{IpVersion, Resolve, SystemResolver},
+    sockopt::{KeepaliveConfig, SocketInfo},
+    socks::Socks5Proxy,
+};
 
 /// Link tag for TCP link.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone)]
 pub struct TcpLinkTag {
     /// Local interface name.
     pub interface: Vec<u8>,
+    /// Local address the socket is bound to.
+    ///
+    /// For incoming links this is the address of the listener that accepted the connection.
+    /// For outgoing links this is only set when [bind addresses](TcpConnector::set_bind_addrs)
+    /// have been specified.
+    pub local: Option<SocketAddr>,
     /// Remote address.
+    ///
+    /// For links established through a proxy this is the address of the proxy.
+    /// It is unspecified if the target host could not be resolved.
+    ///
+    /// IPv6 link-local addresses carry the index of the local interface as scope id.
     pub remote: SocketAddr,
+    /// Target the proxy is asked to connect to or the host that could not be resolved.
+    ///
+    /// This is only set for outgoing links.
+    pub target: Option<String>,
     /// Link direction.
     pub direction: Direction,
+    /// Effective options of the socket.
+    ///
+    /// This is only set on the link tags of established links and
+    /// not taken into account when comparing link tags.
+    pub socket: Option<SocketInfo>,
+}
+
+impl PartialEq for TcpLinkTag {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for TcpLinkTag {}
+
+impl PartialOrd for TcpLinkTag {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TcpLinkTag {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Hash for TcpLinkTag {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
 }
 
 impl fmt::Display for TcpLinkTag {
@@ -77,14 +105,92 @@ impl fmt::Display for TcpLinkTag {
             Direction::Incoming => "<-",
             Direction::Outgoing => "->",
         };
-        write!(f, "{:16} {dir} {}", String::from_utf8_lossy(&self.interface), self.remote)
+        let interface = String::from_utf8_lossy(&self.interface);
+        match self.local {
+            Some(local) if self.direction == Direction::Incoming => {
+                write!(f, "{:16} {dir} ", format!("{interface} {}", self.scoped(local)))?
+            }
+            Some(local) => write!(f, "{:16} {dir} ", format!("{interface} {}", local.ip()))?,
+            None => write!(f, "{interface:16} {dir} ")?,
+        }
+        match &self.target {
+            Some(target) if self.is_unresolved() => write!(f, "{target} (unresolved)")?,
+            Some(target) => write!(f, "{target} via {}", self.scoped(self.remote))?,
+            None => write!(f, "{}", self.scoped(self.remote))?,
+        }
+        if self.socket.map(|socket| socket.mptcp).unwrap_or_default() {
+            write!(f, " (MPTCP)")?;
+        }
+        Ok(())
     }
 }
 
 impl TcpLinkTag {
     /// Creates a new link tag for a TCP link.
     pub fn new(interface: &[u8], remote: SocketAddr, direction: Direction) -> Self {
-        Self { interface: interface.to_vec(), remote, direction }
+        Self { interface: interface.to_vec(), local: None, remote, target: None, direction, socket: None }
+    }
+
+    /// Creates a new link tag for an outgoing TCP link bound to the specified local address.
+    pub fn bound(interface: &[u8], local: SocketAddr, remote: SocketAddr) -> Self {
+        Self {
+            interface: interface.to_vec(),
+            local: Some(local),
+            remote,
+            target: None,
+            direction: Direction::Outgoing,
+            socket: None,
+        }
+    }
+
+    /// Creates a new link tag for an incoming TCP link accepted by the listener at the specified local address.
+    pub fn accepted(interface: &[u8], local: SocketAddr, remote: SocketAddr) -> Self {
+        Self {
+            interface: interface.to_vec(),
+            local: Some(local),
+            remote,
+            target: None,
+            direction: Direction::Incoming,
+            socket: None,
+        }
+    }
+
+    /// Creates a new link tag for an outgoing TCP link to a host that could not be resolved.
+    ///
+    /// Connecting this tag always fails with the resolution error.
+    pub fn unresolved(host: &str) -> Self {
+        Self {
+            interface: Vec::new(),
+            local: None,
+            remote: SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+            target: Some(host.to_string()),
+            direction: Direction::Outgoing,
+            socket: None,
+        }
+    }
+
+    /// Whether the target host of this link tag could not be resolved.
+    pub fn is_unresolved(&self) -> bool {
+        self.direction == Direction::Outgoing && self.remote.ip().is_unspecified()
+    }
+
+    /// Formats the address, naming the interface of the link as scope of IPv6 link-local addresses.
+    fn scoped(&self, addr: SocketAddr) -> String {
+        match addr {
+            SocketAddr::V6(addr)
+                if addr.scope_id() != 0
+                    && !self.interface.is_empty()
+                    && is_ipv6_link_local(IpAddr::V6(*addr.ip())) =>
+            {
+                format!("[{}%{}]:{}", addr.ip(), String::from_utf8_lossy(&self.interface), addr.port())
+            }
+            addr => addr.to_string(),
+        }
+    }
+
+    /// Fields identifying the link.
+    fn key(&self) -> (&[u8], Option<SocketAddr>, SocketAddr, Option<&str>, Direction) {
+        (&self.interface, self.local, self.remote, self.target.as_deref(), self.direction)
     }
 }
 
@@ -98,7 +204,10 @@ impl LinkTag for TcpLinkTag {
     }
 
     fn user_data(&self) -> Vec<u8> {
-        self.interface.clone()
+        match self.local {
+            Some(local) => format!("{} {}", String::from_utf8_lossy(&self.interface), local.ip()).into_bytes(),
+            None => self.interface.clone(),
+        }
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -114,106 +223,1196 @@ impl LinkTag for TcpLinkTag {
         Ord::cmp(self, other)
     }
 
-    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
-        Hash::hash(self, &mut state)
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// Proxy for establishing outgoing TCP links.
+#[derive(Debug, Clone)]
+enum Proxy {
+    Socks5(Socks5Proxy),
+    Http(HttpProxy),
+}
+
+impl fmt::Display for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Socks5(proxy) => proxy.fmt(f),
+            Self::Http(proxy) => proxy.fmt(f),
+        }
+    }
+}
+
+impl Proxy {
+    fn addr(&self) -> SocketAddr {
+        match self {
+            Self::Socks5(proxy) => proxy.addr(),
+            Self::Http(proxy) => proxy.addr(),
+        }
+    }
+
+    fn remote_dns(&self) -> bool {
+        match self {
+            Self::Socks5(proxy) => proxy.remote_dns(),
+            Self::Http(_) => true,
+        }
+    }
+
+    async fn handshake(&self, stream: &mut TcpStream, target: &str) -> Result<()> {
+        match self {
+            Self::Socks5(proxy) => proxy.handshake(stream, target).await,
+            Self::Http(proxy) => proxy.handshake(stream, target).await,
+        }
+    }
+}
+
+/// Information about a local network interface that is passed to an
+/// [interface filter](TcpConnector::set_interface_filter).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct InterfaceInfo {
+    /// Interface name.
+    pub name: String,
+    /// IP address of the interface.
+    ///
+    /// When bind addresses are used, this is the bind address.
+    /// It is `None` for specified interfaces that are not present or have no IP address.
+    pub addr: Option<IpAddr>,
+}
+
+impl InterfaceInfo {
+    fn from_interface(iface: &NetworkInterface) -> Self {
+        Self { name: iface.name.clone(), addr: iface.addr.map(|addr| addr.ip()) }
+    }
+}
+
+/// Custom filter for local network interfaces.
+type InterfaceFilterFn = dyn Fn(&InterfaceInfo) -> bool + Send + Sync;
+
+/// Local interface name and bound address of an outgoing link.
+type LocalEndpoint = (Vec<u8>, Option<SocketAddr>);
+
+/// Filters local network interfaces used for outgoing TCP links.
+#[derive(Clone, Default)]
+struct InterfaceFilter {
+    denied_names: HashSet<String>,
+    allowed_networks: Vec<(IpAddr, u8)>,
+    denied_networks: Vec<(IpAddr, u8)>,
+    filter: Option<Arc<InterfaceFilterFn>>,
+}
+
+impl fmt::Debug for InterfaceFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InterfaceFilter")
+            .field("denied_names", &self.denied_names)
+            .field("allowed_networks", &self.allowed_networks)
+            .field("denied_networks", &self.denied_networks)
+            .field("filter", &self.filter.is_some())
+            .finish()
+    }
+}
+
+impl InterfaceFilter {
+    /// Whether the interface may be used for outgoing links.
+    fn permits(&self, info: &InterfaceInfo) -> bool {
+        if self.denied_names.contains(&info.name) {
+            return false;
+        }
+
+        let in_networks = |networks: &[(IpAddr, u8)]| {
+            info.addr
+                .map(|addr| networks.iter().any(|&(net, len)| addr_in_network(addr, net, len)))
+                .unwrap_or_default()
+        };
+        if !self.allowed_networks.is_empty() && !in_networks(&self.allowed_networks) {
+            return false;
+        }
+        if in_networks(&self.denied_networks) {
+            return false;
+        }
+
+        self.filter.as_ref().map(|filter| filter(info)).unwrap_or(true)
+    }
+}
+
+/// Information about a socket that is passed to a socket preparation callback,
+/// see [`TcpConnector::set_socket_prepare`] and [`TcpAcceptor::set_socket_prepare`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct TargetInfo {
+    /// Local interface name the socket is bound to.
+    ///
+    /// It is empty if the socket is not bound to a specific interface.
+    pub interface: Vec<u8>,
+    /// Local address the socket is bound to, if it has been bound to a specific address.
+    pub local: Option<SocketAddr>,
+    /// Remote address the socket will be connected to.
+    ///
+    /// For links established through a proxy this is the address of the proxy.
+    /// It is `None` for listener sockets.
+    pub remote: Option<SocketAddr>,
+    /// Target the proxy is asked to connect to.
+    pub target: Option<String>,
+}
+
+/// Socket preparation callback.
+type SocketPrepareFn = dyn Fn(&socket2::Socket, &TargetInfo) -> Result<()> + Send + Sync;
+
+/// Optional socket preparation callback.
+#[derive(Clone, Default)]
+struct SocketPrepare(Option<Arc<SocketPrepareFn>>);
+
+impl fmt::Debug for SocketPrepare {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("SocketPrepare").field(&self.0.is_some()).finish()
+    }
+}
+
+impl SocketPrepare {
+    /// Invokes the callback, if any, on the socket.
+    fn prepare(&self, socket: socket2::SockRef, info: &TargetInfo) -> Result<()> {
+        match &self.0 {
+            Some(prepare) => prepare(&socket, info)
+                .map_err(|err| Error::new(err.kind(), format!("preparing socket failed: {err}"))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Handle for changing the targets of a [`TcpConnector`] while it is in use.
+///
+/// Obtain it using [`TcpConnector::targets`] before adding the transport to a
+/// [connector](super::Connector).
+///
+/// Links to added targets are established without affecting existing links.
+/// Links to removed targets are [drained](aggligator::Link::drain) and then disconnected,
+/// while links to the remaining targets stay up.
+/// Added targets are not checked for resolvability; resolution failures are reported as
+/// [link errors](super::Connector::link_errors).
+#[derive(Debug, Clone)]
+pub struct TcpTargets {
+    hosts: Arc<watch::Sender<Vec<String>>>,
+    default_ports: Vec<u16>,
+}
+
+impl TcpTargets {
+    /// Returns the current targets.
+    pub fn get(&self) -> Vec<String> {
+        self.hosts.borrow().clone()
+    }
+
+    /// Replaces the targets.
+    ///
+    /// `hosts` can contain IP addresses and hostnames, including port numbers.
+    /// If an entry does not specify a port number, the default ports of the transport are used.
+    /// Entries without port number are ignored if the transport has no default ports,
+    /// because it was created using [`TcpConnector::with_targets`].
+    pub fn set(&self, hosts: impl IntoIterator<Item = String>) {
+        let hosts: Vec<_> =
+            hosts.into_iter().flat_map(|host| host_with_default_ports(host, &self.default_ports)).collect();
+        self.hosts.send_if_modified(|current| {
+            if *current != hosts {
+                *current = hosts;
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    /// Adds a target.
+    ///
+    /// If the target does not specify a port number, it is added for each default port
+    /// of the transport.
+    ///
+    /// Returns `false` if the target is already present or does not specify a port number
+    /// while the transport has no default ports.
+    pub fn add(&self, host: impl Into<String>) -> bool {
+        let added = host_with_default_ports(host.into(), &self.default_ports);
+        self.hosts.send_if_modified(|hosts| {
+            let len = hosts.len();
+            for host in added {
+                if !hosts.contains(&host) {
+                    hosts.push(host);
+                }
+            }
+            hosts.len() != len
+        })
+    }
+
+    /// Removes a target.
+    ///
+    /// If the target does not specify a port number, it is removed for each default port
+    /// of the transport.
+    ///
+    /// Returns `false` if the target is not present.
+    pub fn remove(&self, host: &str) -> bool {
+        let removed = host_with_default_ports(host.to_string(), &self.default_ports);
+        self.hosts.send_if_modified(|hosts| {
+            let len = hosts.len();
+            hosts.retain(|h| !removed.contains(h));
+            hosts.len() != len
+        })
+    }
+}
+
+/// Limits on the number of links of a [`TcpConnector`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct LinkLimits {
+    per_interface: Option<NonZeroUsize>,
+    per_remote: Option<NonZeroUsize>,
+    ports_per_interface: Option<NonZeroUsize>,
+}
+
+/// Number of consecutive failed connection attempts after which a link tag is
+/// considered failing persistently.
+const PERSISTENT_FAILURES: u32 = 3;
+
+/// Initial delay for retrying name resolution after it failed.
+const RESOLVE_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Handle for configuring the link limits of a [`TcpConnector`], also while it is in use.
+///
+/// Obtain it using [`TcpConnector::link_limits`].
+/// By default no limits apply.
+///
+/// When the limits are raised, additional links are established.
+/// When they are lowered, links exceeding them are [drained](aggligator::Link::drain)
+/// and then disconnected, while the remaining links stay up.
+#[derive(Debug, Clone)]
+pub struct TcpLinkLimits {
+    limits: Arc<watch::Sender<LinkLimits>>,
+}
+
+impl TcpLinkLimits {
+    /// The maximum number of links established over each local interface.
+    pub fn links_per_interface(&self) -> Option<NonZeroUsize> {
+        self.limits.borrow().per_interface
+    }
+
+    /// Sets the maximum number of links established over each local interface.
+    ///
+    /// When links are limited, combinations of interface and remote address are chosen
+    /// deterministically, preferring distinct pairs: every interface and every remote address
+    /// is used once before any of them is used again.
+    /// Combinations with connected links are kept, while combinations that fail persistently
+    /// are replaced by previously unused combinations, so that the number of links is maintained.
+    ///
+    /// This has no effect on links established through a proxy.
+    /// `None` removes the limit.
+    pub fn set_links_per_interface(&self, links: Option<NonZeroUsize>) {
+        self.limits.send_if_modified(|limits| {
+            let modified = limits.per_interface != links;
+            limits.per_interface = links;
+            modified
+        });
+    }
+
+    /// The maximum number of links established to each remote address.
+    pub fn links_per_remote(&self) -> Option<NonZeroUsize> {
+        self.limits.borrow().per_remote
+    }
+
+    /// Sets the maximum number of links established to each remote address.
+    ///
+    /// Links to different ports of a target count as links to different remote addresses.
+    /// See [`set_links_per_interface`](Self::set_links_per_interface) for how combinations of
+    /// interface and remote address are chosen.
+    ///
+    /// This has no effect on links established through a proxy.
+    /// `None` removes the limit.
+    pub fn set_links_per_remote(&self, links: Option<NonZeroUsize>) {
+        self.limits.send_if_modified(|limits| {
+            let modified = limits.per_remote != links;
+            limits.per_remote = links;
+            modified
+        });
+    }
+
+    /// The maximum number of ports of a target links are established to over each interface.
+    pub fn ports_per_interface(&self) -> Option<NonZeroUsize> {
+        self.limits.borrow().ports_per_interface
+    }
+
+    /// Sets the maximum number of ports of a target links are established to over each interface.
+    ///
+    /// Ports are counted separately for each remote address and chosen in the order they were
+    /// specified when [creating](TcpConnector::with_ports) the transport, or in ascending order for
+    /// transports created using [`with_targets`](TcpConnector::with_targets).
+    /// This has no effect on links established through a proxy.
+    /// `None` removes the limit.
+    pub fn set_ports_per_interface(&self, ports: Option<NonZeroUsize>) {
+        self.limits.send_if_modified(|limits| {
+            let modified = limits.ports_per_interface != ports;
+            limits.ports_per_interface = ports;
+            modified
+        });
+    }
+}
+
+/// TCP transport for outgoing connections.
+///
+/// The resolved addresses of a target are raced for each link, as described in RFC 8305.
+/// Connection attempts are started in order of preference, interleaving IPv6 and IPv4, and staggered
+/// by the [happy eyeballs attempt delay](Self::set_happy_eyeballs).
+/// The first attempt to succeed is used and its address is recorded in the
+/// [link tag](TcpLinkTag::remote), while all other attempts are aborted.
+///
+/// Links can be established through a SOCKS5 proxy or an HTTP proxy by creating the transport
+/// using [`via_socks5`](Self::via_socks5) or [`via_http_proxy`](Self::via_http_proxy) respectively.
+/// Using [`via_socks5_proxies`](Self::via_socks5_proxies) links are spread over multiple SOCKS5 proxies.
+/// Connection wrappers, such as TLS, are applied to the tunnel established through the proxy.
+///
+/// Hosts that cannot be resolved are reported as [link errors](super::Connector::link_errors)
+/// with an [unresolved link tag](TcpLinkTag::unresolved).
+/// Their resolution is retried with exponential backoff, up to the
+/// [resolve interval](Self::set_resolve_interval), and links are established once it succeeds.
+/// Thus the transport can be created while name resolution is unavailable; use
+/// [`check_resolution`](Self::check_resolution) to fail fast instead.
+///
+/// The targets can be changed while the transport is in use through the [`targets`](Self::targets) handle.
+///
+/// Using [`with_ports`](Self::with_ports) links are spread over multiple ports of each target.
+/// Using [`with_targets`](Self::with_targets) each target is connected to on its own port.
+///
+/// The number of links over each interface, to each remote address and the number of ports
+/// used over each interface can be limited, also at runtime, through the
+/// [`link_limits`](Self::link_limits) handle.
+///
+/// Network interfaces are checked for changes periodically, as specified by the
+/// [resolve interval](Self::set_resolve_interval).
+/// On Linux with the `netlink` crate feature enabled, the kernel additionally notifies the transport
+/// when interfaces or their addresses change, so that links over new interfaces are established
+/// immediately.
+/// Links over interfaces that have vanished are disconnected.
+#[derive(Debug, Clone)]
+pub struct TcpConnector {
+    hosts: Arc<watch::Sender<Vec<String>>>,
+    default_ports: Vec<u16>,
+    link_limits: Arc<watch::Sender<LinkLimits>>,
+    tag_failures: Arc<Mutex<HashMap<TcpLinkTag, u32>>>,
+    connected_tags: Arc<Mutex<HashSet<TcpLinkTag>>>,
+    suppressed_tags: Arc<Mutex<HashSet<TcpLinkTag>>>,
+    reselect: Arc<Notify>,
+    resolver: Arc<dyn Resolve>,
+    ip_version: IpVersion,
+    link_local: bool,
+    resolve_interval: Duration,
+    bind_addrs: Vec<SocketAddr>,
+    source_ports: Option<Arc<SourcePorts>>,
+    interfaces: Option<HashSet<Vec<u8>>>,
+    interface_filter: InterfaceFilter,
+    denied_tags: Arc<Mutex<HashSet<TcpLinkTag>>>,
+    removed_tags: Arc<Mutex<HashSet<TcpLinkTag>>>,
+    present_interfaces: Arc<Mutex<Option<HashSet<Vec<u8>>>>>,
+    ipv4_fallback_timeout: Duration,
+    connect_timeout: Duration,
+    ipv6_links: Arc<Mutex<HashSet<LocalEndpoint>>>,
+    happy_eyeballs: Option<Duration>,
+    socket_options: SocketOptions,
+    mptcp: bool,
+    connectivity_check: Option<Duration>,
+    no_connectivity_tags: Arc<Mutex<HashSet<TcpLinkTag>>>,
+    socket_prepare: SocketPrepare,
+    proxies: Vec<Proxy>,
+}
+
+impl fmt::Display for TcpConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hosts = self.hosts.borrow();
+        if hosts.len() == 1 {
+            write!(f, "{}", &hosts[0])?;
+        } else {
+            write!(f, "[{}]", hosts.join(", "))?;
+        }
+        match self.proxies.as_slice() {
+            [] => (),
+            [proxy] => write!(f, " via {proxy}")?,
+            proxies => {
+                let proxies: Vec<_> = proxies.iter().map(|proxy| proxy.to_string()).collect();
+                write!(f, " via [{}]", proxies.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TcpConnector {
+    /// Create a new TCP transport for outgoing connections.
+    ///
+    /// `hosts` can contain IP addresses and hostnames, including port numbers.
+    /// If an entry does not specify a port number, the `default_port` is used.
+    ///
+    /// Host name resolution is retried periodically, thus DNS updates will be taken
+    /// into account without the need to recreate this transport.
+    pub async fn new(hosts: impl IntoIterator<Item = String>, default_port: u16) -> Result<Self> {
+        Self::with_resolver(hosts, default_port, Arc::new(SystemResolver)).await
+    }
+
+    /// Create a new TCP transport for outgoing connections to multiple ports of each host.
+    ///
+    /// `hosts` can contain IP addresses and hostnames, including port numbers.
+    /// If an entry does not specify a port number, a link is established to each of the `ports`
+    /// over each local interface.
+    /// This helps when network providers throttle traffic depending on the port.
+    /// Use [`TcpLinkLimits::set_ports_per_interface`] to limit the number of ports
+    /// used simultaneously.
+    pub async fn with_ports(
+        hosts: impl IntoIterator<Item = String>, ports: impl IntoIterator<Item = u16>,
+    ) -> Result<Self> {
+        Self::with_proxies(hosts, ports.into_iter().collect(), Vec::new(), Arc::new(SystemResolver)).await
+    }
+
+    /// Create a new TCP transport for outgoing connections to targets listening on different ports.
+    ///
+    /// Each target consists of a host, which can be an IP address or hostname, and the port
+    /// to connect to.
+    /// The host must not specify a port number itself.
+    /// Each pair of host and port is a distinct target, thus the same host can be specified
+    /// multiple times with different ports.
+    ///
+    /// The transport has no default port, thus targets [added](TcpTargets::add) later must
+    /// specify a port number.
+    pub async fn with_targets(targets: impl IntoIterator<Item = (String, u16)>) -> Result<Self> {
+        let hosts =
+            targets.into_iter().map(|(host, port)| host_with_port(host, port)).collect::<Result<Vec<_>>>()?;
+        Self::with_proxies(hosts, Vec::new(), Vec::new(), Arc::new(SystemResolver)).await
+    }
+
+    /// Create a new TCP transport for outgoing connections using the specified resolver
+    /// for resolving `hosts`.
+    ///
+    /// `hosts` can contain IP addresses and hostnames, including port numbers.
+    /// If an entry does not specify a port number, the `default_port` is used.
+    pub async fn with_resolver(
+        hosts: impl IntoIterator<Item = String>, default_port: u16, resolver: Arc<dyn Resolve>,
+    ) -> Result<Self> {
+        Self::with_proxies(hosts, vec![default_port], Vec::new(), resolver).await
+    }
+
+    /// Create a new TCP transport for outgoing connections through a SOCKS5 proxy.
+    ///
+    /// `hosts` can contain IP addresses and hostnames, including port numbers.
+    /// If an entry does not specify a port number, the `default_port` is used.
+    ///
+    /// With [remote DNS](Socks5Proxy::set_remote_dns) enabled on the proxy, the hosts are passed
+    /// to the proxy as they are and the [IP version](Self::set_ip_version) setting has no effect.
+    ///
+    /// Failures of the proxy handshake are reported as [link errors](super::Connector::link_errors).
+    pub async fn via_socks5(
+        hosts: impl IntoIterator<Item = String>, default_port: u16, proxy: Socks5Proxy,
+    ) -> Result<Self> {
+        Self::with_proxies(hosts, vec![default_port], vec![Proxy::Socks5(proxy)], Arc::new(SystemResolver)).await
+    }
+
+    /// Create a new TCP transport for outgoing connections through multiple SOCKS5 proxies.
+    ///
+    /// A separate link to each target is established through each proxy, thus
+    /// the links of the connection take diverse network paths.
+    /// The proxy a link goes through is recorded as the [remote address](TcpLinkTag::remote)
+    /// of its link tag, therefore the proxies must have distinct addresses.
+    ///
+    /// See [`via_socks5`](Self::via_socks5) for details.
+    pub async fn via_socks5_proxies(
+        hosts: impl IntoIterator<Item = String>, default_port: u16,
+        proxies: impl IntoIterator<Item = Socks5Proxy>,
+    ) -> Result<Self> {
+        let proxies: Vec<_> = proxies.into_iter().map(Proxy::Socks5).collect();
+
+        if proxies.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "at least one proxy is required"));
+        }
+        let addrs: HashSet<_> = proxies.iter().map(|proxy| proxy.addr()).collect();
+        if addrs.len() != proxies.len() {
+            return Err(Error::new(ErrorKind::InvalidInput, "proxies must have distinct addresses"));
+        }
+
+        Self::with_proxies(hosts, vec![default_port], proxies, Arc::new(SystemResolver)).await
+    }
+
+    /// Create a new TCP transport for outgoing connections through an HTTP proxy.
+    ///
+    /// `hosts` can contain IP addresses and hostnames, including port numbers.
+    /// If an entry does not specify a port number, the `default_port` is used.
+    ///
+    /// Each link is established by sending a `CONNECT` request for the target host to the proxy.
+    /// The hosts are resolved by the proxy and thus the [IP version](Self::set_ip_version)
+    /// setting has no effect.
+    ///
+    /// Failures of the proxy handshake, including responses with a non-2xx status code,
+    /// are reported as [link errors](super::Connector::link_errors).
+    pub async fn via_http_proxy(
+        hosts: impl IntoIterator<Item = String>, default_port: u16, proxy: HttpProxy,
+    ) -> Result<Self> {
+        Self::with_proxies(hosts, vec![default_port], vec![Proxy::Http(proxy)], Arc::new(SystemResolver)).await
+    }
+
+    async fn with_proxies(
+        hosts: impl IntoIterator<Item = String>, default_ports: Vec<u16>, proxies: Vec<Proxy>,
+        resolver: Arc<dyn Resolve>,
+    ) -> Result<Self> {
+        let hosts = hosts_with_default_ports(hosts, &default_ports)?;
+        let this = Self {
+            hosts: Arc::new(watch::channel(hosts).0),
+            default_ports,
+            link_limits: Arc::new(watch::channel(LinkLimits::default()).0),
+            tag_failures: Arc::new(Mutex::new(HashMap::new())),
+            connected_tags: Arc::new(Mutex::new(HashSet::new())),
+            suppressed_tags: Arc::new(Mutex::new(HashSet::new())),
+            reselect: Arc::new(Notify::new()),
+            resolver,
+            ip_version: IpVersion::Both,
+            link_local: true,
+            resolve_interval: Duration::from_secs(10),
+            bind_addrs: Vec::new(),
+            source_ports: None,
+            interfaces: None,
+            interface_filter: InterfaceFilter::default(),
+            denied_tags: Arc::new(Mutex::new(HashSet::new())),
+            removed_tags: Arc::new(Mutex::new(HashSet::new())),
+            present_interfaces: Arc::new(Mutex::new(None)),
+            ipv4_fallback_timeout: Duration::from_secs(5),
+            connect_timeout: Duration::from_secs(10),
+            ipv6_links: Arc::new(Mutex::new(HashSet::new())),
+            happy_eyeballs: Some(Duration::from_millis(250)),
+            socket_options: SocketOptions::default(),
+            mptcp: false,
+            connectivity_check: None,
+            no_connectivity_tags: Arc::new(Mutex::new(HashSet::new())),
+            socket_prepare: SocketPrepare::default(),
+            proxies,
+        };
+
+        Ok(this)
+    }
+
+    /// Checks that the targets resolve to at least one IP address.
+    ///
+    /// Since the transport is created even if its targets cannot be resolved, call this
+    /// after creation to fail fast when name resolution is unavailable.
+    /// When all links use a proxy with [remote DNS](Socks5Proxy::set_remote_dns),
+    /// no resolution is performed and this always succeeds.
+    pub async fn check_resolution(&self) -> Result<()> {
+        if !self.resolves_locally() {
+            return Ok(());
+        }
+
+        let hosts = self.hosts.borrow().clone();
+        let (addrs, failed) = self.resolve(&hosts).await;
+        if addrs.is_empty() {
+            return Err(match failed.into_iter().next() {
+                Some((host, err)) => Error::new(err.kind(), format!("cannot resolve {host}: {err}")),
+                None => Error::new(ErrorKind::NotFound, "cannot resolve IP address of host"),
+            });
+        }
+        tracing::info!("{} resolves to: {:?}", self, addrs);
+
+        Ok(())
+    }
+
+    /// Restricts the number of ports links over each interface are established to.
+    fn limit_ports(&self, tags: &mut HashSet<LinkTagBox>) {
+        let Some(max_ports) = self.link_limits.borrow().ports_per_interface else { return };
+        let is_direct = |tag: &TcpLinkTag| !tag.is_unresolved() && tag.target.is_none();
+
+        let mut ports: HashMap<(Vec<u8>, Option<SocketAddr>, IpAddr), Vec<u16>> = HashMap::new();
+        for tag in tags.iter().filter_map(|tag| tag.as_any().downcast_ref::<TcpLinkTag>()) {
+            if !is_direct(tag) {
+                continue;
+            }
+            let ports = ports.entry((tag.interface.clone(), tag.local, tag.remote.ip())).or_default();
+            if !ports.contains(&tag.remote.port()) {
+                ports.push(tag.remote.port());
+            }
+        }
+        for ports in ports.values_mut() {
+            ports.sort_by_key(|port| {
+                (self.default_ports.iter().position(|p| p == port).unwrap_or(usize::MAX), *port)
+            });
+            ports.truncate(max_ports.get());
+        }
+
+        tags.retain(|tag| match tag.as_any().downcast_ref::<TcpLinkTag>() {
+            Some(tag) if is_direct(tag) => {
+                ports[&(tag.interface.clone(), tag.local, tag.remote.ip())].contains(&tag.remote.port())
+            }
+            _ => true,
+        });
+    }
+
+    /// Returns a handle for configuring the link limits, also while the transport is in use.
+    pub fn link_limits(&self) -> TcpLinkLimits {
+        TcpLinkLimits { limits: self.link_limits.clone() }
+    }
+
+    /// Restricts the links to the configured number per interface and per remote address.
+    fn limit_links(&self, tags: &mut HashSet<LinkTagBox>) {
+        let is_direct = |tag: &TcpLinkTag| !tag.is_unresolved() && tag.target.is_none();
+        let mut candidates: Vec<TcpLinkTag> = tags
+            .iter()
+            .filter_map(|tag| tag.as_any().downcast_ref::<TcpLinkTag>())
+            .filter(|tag| is_direct(tag))
+            .cloned()
+            .collect();
+
+        let mut failures = self.tag_failures.lock().unwrap();
+        failures.retain(|tag, _| candidates.contains(tag));
+
+        let LinkLimits { per_interface, per_remote, .. } = *self.link_limits.borrow();
+        let mut suppressed_tags = self.suppressed_tags.lock().unwrap();
+        if per_interface.is_none() && per_remote.is_none() {
+            suppressed_tags.clear();
+            return;
+        }
+        let per_interface = per_interface.map(|n| n.get()).unwrap_or(usize::MAX);
+        let per_remote = per_remote.map(|n| n.get()).unwrap_or(usize::MAX);
+
+        // Prefer combinations with connected links and avoid persistently failing ones.
+        let connected_tags = self.connected_tags.lock().unwrap();
+        candidates.sort_by_cached_key(|tag| {
+            let failing = failures.get(tag).copied().unwrap_or_default() >= PERSISTENT_FAILURES;
+            (!connected_tags.contains(tag), failing, tag.clone())
+        });
+
+        // Select in rounds, using each interface and remote address at most once per round,
+        // so that distinct pairs are preferred.
+        let mut selected = HashSet::new();
+        let mut interface_links: HashMap<LocalEndpoint, usize> = HashMap::new();
+        let mut remote_links: HashMap<SocketAddr, usize> = HashMap::new();
+        for round in 1..=candidates.len() {
+            for tag in &candidates {
+                if selected.contains(tag) {
+                    continue;
+                }
+                let interface_links = interface_links.entry((tag.interface.clone(), tag.local)).or_default();
+                let remote_links = remote_links.entry(tag.remote).or_default();
+                if *interface_links < round.min(per_interface) && *remote_links < round.min(per_remote) {
+                    *interface_links += 1;
+                    *remote_links += 1;
+                    selected.insert(tag.clone());
+                }
+            }
+        }
+
+        *suppressed_tags = candidates.into_iter().filter(|tag| !selected.contains(tag)).collect();
+        if !suppressed_tags.is_empty() {
+            tracing::debug!(
+                "suppressed tags exceeding link limits: {}",
+                suppressed_tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>().join(", ")
+            );
+        }
+        tags.retain(|tag| match tag.as_any().downcast_ref::<TcpLinkTag>() {
+            Some(tag) => !suppressed_tags.contains(tag),
+            None => true,
+        });
+    }
+
+    /// Establishes a TCP connection for a resolved link tag.
+    async fn connect_resolved(&self, tag: &TcpLinkTag) -> Result<(IoBox, LinkTagBox)> {
+        let mut stream = self.connect_socket(tag).await?;
+        let socket = socket_info(&stream, tag);
+
+        if let Some(target) = &tag.target {
+            let proxy = self
+                .proxies
+                .iter()
+                .find(|proxy| without_scope(proxy.addr()) == without_scope(tag.remote))
+                .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("no proxy at {}", tag.remote)))?;
+            proxy.handshake(&mut stream, target).await?;
+        }
+
+        let (rh, wh) = stream.into_split();
+        Ok((IoBox::new(rh, wh), Box::new(TcpLinkTag { socket, ..tag.clone() })))
+    }
+
+    /// Connects a TCP socket to the remote address of a resolved link tag.
+    async fn connect_socket(&self, tag: &TcpLinkTag) -> Result<TcpStream> {
+        let socket = tcp_socket(tag.remote, self.mptcp)?;
+        self.socket_options.apply(socket2::SockRef::from(&socket));
+        self.socket_options.apply_marking(
+            socket2::SockRef::from(&socket),
+            &tag.interface,
+            tag.remote.is_ipv6(),
+        )?;
+
+        match tag.local {
+            Some(local) => {
+                match &self.source_ports {
+                    Some(source_ports) if local.port() == 0 => source_ports.bind(&socket, local.ip())?,
+                    _ => socket.bind(local)?,
+                }
+
+                #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+                if !tag.interface.is_empty() {
+                    check_carrier(&tag.interface)?;
+                    socket.bind_device(Some(&tag.interface))?;
+                }
+            }
+            None => Self::bind_socket_to_interface(
+                &socket,
+                &tag.interface,
+                tag.remote.ip(),
+                self.source_ports.as_deref(),
+            )?,
+        }
+
+        let info = TargetInfo {
+            interface: tag.interface.clone(),
+            local: tag.local,
+            remote: Some(tag.remote),
+            target: tag.target.clone(),
+        };
+        self.socket_prepare.prepare(socket2::SockRef::from(&socket), &info)?;
+
+        timeout(self.connect_timeout, socket.connect(tag.remote))
+            .await
+            .map_err(|_| Error::new(ErrorKind::TimedOut, format!("connecting to {} timed out", tag.remote)))?
+    }
+
+    /// Removes tags over which the remote address cannot be reached from the candidates
+    /// and records them as having no connectivity.
+    ///
+    /// Tags of connected links are not probed.
+    async fn check_connectivity(&self, tags: &mut HashSet<LinkTagBox>, probe_timeout: Duration) {
+        let connected_tags = self.connected_tags.lock().unwrap().clone();
+        let probes = tags
+            .iter()
+            .filter_map(|tag| tag.as_any().downcast_ref::<TcpLinkTag>())
+            .filter(|tag| !tag.is_unresolved() && !connected_tags.contains(tag))
+            .map(|tag| async move {
+                match timeout(probe_timeout, self.connect_socket(tag)).await {
+                    Ok(Ok(_)) => None,
+                    Ok(Err(err)) => {
+                        tracing::debug!("no connectivity for {tag}: {err}");
+                        Some(tag.clone())
+                    }
+                    Err(_) => {
+                        tracing::debug!("no connectivity for {tag}: probe timed out");
+                        Some(tag.clone())
+                    }
+                }
+            });
+        let no_connectivity: HashSet<_> = future::join_all(probes).await.into_iter().flatten().collect();
+
+        tags.retain(|tag| match tag.as_any().downcast_ref::<TcpLinkTag>() {
+            Some(tag) => !no_connectivity.contains(tag),
+            None => true,
+        });
+        *self.no_connectivity_tags.lock().unwrap() = no_connectivity;
+    }
+
+    /// Records the outcome of a connection attempt for the specified tag.
+    fn record_attempt(&self, tag: &TcpLinkTag, success: bool) {
+        let mut failures = self.tag_failures.lock().unwrap();
+        if success {
+            failures.remove(tag);
+            return;
+        }
+
+        let failures = failures.entry(tag.clone()).or_default();
+        *failures += 1;
+        let limits = *self.link_limits.borrow();
+        if *failures == PERSISTENT_FAILURES && (limits.per_interface.is_some() || limits.per_remote.is_some()) {
+            tracing::debug!("tag {tag} is failing persistently, reselecting links");
+            self.reselect.notify_one();
+        }
+    }
+
+    /// Sets the IP version used for connecting.
+    ///
+    /// With [`IpVersion::PreferIPv6`] only IPv6 links are attempted over an interface, unless no
+    /// IPv6 link over it can be established within the [IPv4 fallback timeout](Self::set_ipv4_fallback_timeout).
+    /// The IP version a link uses is shown by the [remote address](TcpLinkTag::remote) of its tag.
+    pub fn set_ip_version(&mut self, ip_version: IpVersion) {
+        self.ip_version = ip_version;
+    }
+
+    /// Sets the time after which IPv4 links are attempted over an interface, if no IPv6 link
+    /// over it could be established.
+    ///
+    /// This only has an effect when [`IpVersion::PreferIPv6`] is used.
+    /// The default is 5 seconds.
+    pub fn set_ipv4_fallback_timeout(&mut self, ipv4_fallback_timeout: Duration) {
+        self.ipv4_fallback_timeout = ipv4_fallback_timeout;
+    }
+
+    /// Sets whether targets with IPv6 link-local addresses (`fe80::/10`) are connected to.
+    ///
+    /// A link-local address is only valid on a particular network link, thus each link to
+    /// it is established over a single local interface, using the index of that interface
+    /// as scope id of the remote address.
+    /// This allows aggregating over direct cable connections without routable addresses.
+    /// If a target already specifies a scope, for example `fe80::1%eth0`, only the
+    /// interface matching that scope is used.
+    ///
+    /// By default link-local addresses are used.
+    pub fn set_link_local(&mut self, link_local: bool) {
+        self.link_local = link_local;
+    }
+
+    /// Pairs an IPv6 link-local remote address with the scope of the local interface.
+    ///
+    /// Returns `None` if link-local addresses are disabled or the remote address cannot be
+    /// reached over the interface.
+    /// Other addresses are returned unchanged.
+    fn scope_remote(&self, remote: SocketAddr, interface: &[u8]) -> Option<SocketAddr> {
+        let SocketAddr::V6(mut remote) = remote else { return Some(remote) };
+        if !is_ipv6_link_local(IpAddr::V6(*remote.ip())) {
+            return Some(remote.into());
+        }
+        if !self.link_local {
+            return None;
+        }
+
+        let index = interface_index(interface)?;
+        match remote.scope_id() {
+            0 => remote.set_scope_id(index),
+            scope_id if scope_id != index => return None,
+            _ => (),
+        }
+        Some(remote.into())
+    }
+
+    /// Sets the time after which an attempt to establish the TCP connection of a link is abandoned.
+    ///
+    /// This applies to each connection attempt individually and avoids waiting for the
+    /// operating system timeout, which can take minutes, when a target does not respond.
+    /// An expired attempt is reported as a [link error](super::Connector::link_errors) of kind
+    /// [`ErrorKind::TimedOut`] and retried after the reconnect delay.
+    /// The handshake with a proxy is not covered by this timeout.
+    ///
+    /// The default is 10 seconds.
+    pub fn set_connect_timeout(&mut self, connect_timeout: Duration) {
+        self.connect_timeout = connect_timeout;
+    }
+
+    /// Sets the delay between staggered connection attempts to the resolved addresses of a target.
+    ///
+    /// The next address is attempted when the previous attempt fails or after `attempt_delay`
+    /// has elapsed, whichever comes first.
+    /// If `None`, all addresses are connected independently, unless
+    /// [happy eyeballs](super::ConnectorBuilder::set_happy_eyeballs) is enabled on the connector.
+    ///
+    /// The default is 250 ms, as recommended by RFC 8305.
+    pub fn set_happy_eyeballs(&mut self, attempt_delay: Option<Duration>) {
+        self.happy_eyeballs = attempt_delay;
+    }
+
+    /// Sets whether Nagle's algorithm is disabled on link sockets.
+    ///
+    /// The default is `true`, i.e. data is sent without delay.
+    /// The effective setting is available from the [socket information](TcpLinkTag::socket) of the link tag.
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.socket_options.set_nodelay(nodelay);
+    }
+
+    /// Sets the size of the send buffer of link sockets.
+    ///
+    /// By default the system default is used.
+    /// The operating system may adjust the specified size; the effective size is available
+    /// from the [socket information](TcpLinkTag::socket) of the link tag.
+    pub fn set_send_buffer_size(&mut self, send_buffer_size: usize) {
+        self.socket_options.set_send_buffer_size(send_buffer_size);
+    }
+
+    /// Sets the size of the receive buffer of link sockets.
+    ///
+    /// By default the system default is used.
+    /// The operating system may adjust the specified size; the effective size is available
+    /// from the [socket information](TcpLinkTag::socket) of the link tag.
+    pub fn set_recv_buffer_size(&mut self, recv_buffer_size: usize) {
+        self.socket_options.set_recv_buffer_size(recv_buffer_size);
+    }
+
+    /// Sets the TCP keepalive configuration of link sockets.
+    ///
+    /// Keepalive lets the operating system detect dead links independently of the link ping.
+    /// It stays disabled until a configuration is set; pass `Some(KeepaliveConfig::default())`
+    /// to enable it using the [recommended parameters](KeepaliveConfig::default).
+    ///
+    /// Fails if the configuration is invalid or the [user timeout](Self::set_user_timeout)
+    /// is shorter than the time keepalive takes to detect a dead link.
+    pub fn set_keepalive(&mut self, keepalive: Option<KeepaliveConfig>) -> Result<()> {
+        self.socket_options.set_keepalive(keepalive)
+    }
+
+    /// Sets the TCP user timeout of link sockets.
+    ///
+    /// This is the maximum time sent data may remain unacknowledged before the link is
+    /// closed by the operating system.
+    /// It is only supported on Linux, Android and Fuchsia and ignored on other platforms.
+    ///
+    /// Fails if the timeout is zero or shorter than the time [keepalive](Self::set_keepalive)
+    /// takes to detect a dead link.
+    pub fn set_user_timeout(&mut self, user_timeout: Duration) -> Result<()> {
+        self.socket_options.set_user_timeout(user_timeout)
+    }
+
+    /// Sets the type of service (`IP_TOS`) of IPv4 link sockets.
+    ///
+    /// This is used for DSCP marking of outgoing packets.
+    /// If the value cannot be set or is not taken over by the operating system,
+    /// establishing the link fails with a corresponding [link error](super::LinkError).
+    /// The two least significant bits are used for explicit congestion notification and
+    /// may be managed by the operating system.
+    pub fn set_tos(&mut self, tos: u8) {
+        self.socket_options.set_tos(tos);
+    }
+
+    /// Sets the traffic class (`IPV6_TCLASS`) of IPv6 link sockets.
+    ///
+    /// This is only supported on Linux; on other platforms links over IPv6 fail to be established.
+    /// See [`set_tos`](Self::set_tos) for details.
+    pub fn set_traffic_class(&mut self, traffic_class: u8) {
+        self.socket_options.set_traffic_class(traffic_class);
+    }
+
+    /// Sets the type of service and traffic class of link sockets over the specified local interface.
+    ///
+    /// This overrides the values specified by [`set_tos`](Self::set_tos) and
+    /// [`set_traffic_class`](Self::set_traffic_class) for links over that interface,
+    /// so that different interfaces can use different markings.
+    pub fn set_interface_tos(&mut self, interface: impl AsRef<[u8]>, tos: u8) {
+        self.socket_options.set_interface_tos(interface.as_ref(), tos);
+    }
+
+    /// Sets whether links are established using Multipath TCP (MPTCP).
+    ///
+    /// The kernel then manages the subflows of each link and fails over between them.
+    /// If the kernel refuses to create MPTCP sockets, plain TCP is used and a warning is logged.
+    /// Whether a link uses MPTCP is shown by the [socket information](TcpLinkTag::socket) of its tag.
+    ///
+    /// MPTCP is only supported on Linux 5.6 and later. By default it is disabled.
+    pub fn set_mptcp(&mut self, mptcp: bool) {
+        self.mptcp = mptcp;
+    }
+
+    /// Sets whether connectivity to the remote address is checked before a link over an
+    /// interface is attempted and the timeout of each check.
+    ///
+    /// If enabled, a TCP connection to the remote address is established over the interface
+    /// of each link tag as a probe and closed immediately.
+    /// Tags whose probe fails or times out, for example because the interface is connected to a
+    /// network with a captive portal, are withheld from the connector and thus produce no
+    /// [link errors](super::Connector::link_errors).
+    /// They are listed by [`no_connectivity_tags`](Self::no_connectivity_tags) and probed again
+    /// each [resolve interval](Self::set_resolve_interval), so that links are established once
+    /// the interface gains connectivity.
+    /// Tags of connected links are not probed.
+    ///
+    /// By default no check is performed.
+    pub fn set_connectivity_check(&mut self, probe_timeout: Option<Duration>) {
+        self.connectivity_check = probe_timeout;
+    }
+
+    /// Sets a callback that prepares each link socket before it is connected.
+    ///
+    /// It is invoked after the socket has been created, configured and bound to its local
+    /// interface and address, but before it is connected to the remote address.
+    /// This allows, for example, to exclude the socket from an Android VPN using
+    /// `VpnService.protect` or to set a firewall mark (`SO_MARK`) for policy routing on Linux.
+    ///
+    /// If the callback fails, establishing the link fails with a corresponding
+    /// [link error](super::LinkError) and is retried later.
+    pub fn set_socket_prepare(
+        &mut self, prepare: impl Fn(&socket2::Socket, &TargetInfo) -> Result<()> + Send + Sync + 'static,
+    ) {
+        self.socket_prepare = SocketPrepare(Some(Arc::new(prepare)));
+    }
+
+    /// Link tags currently withheld because the [connectivity check](Self::set_connectivity_check)
+    /// found no connectivity to their remote address.
+    ///
+    /// Clones of this transport share this information, thus a clone can be kept for
+    /// querying after the transport has been added to a connector.
+    pub fn no_connectivity_tags(&self) -> HashSet<TcpLinkTag> {
+        self.no_connectivity_tags.lock().unwrap().clone()
+    }
+
+    /// Sets the interval for re-resolving the hostname and checking for changed network interfaces.
+    ///
+    /// The default is 10 seconds.
+    /// On Linux with the `netlink` crate feature enabled, changes of network interfaces are
+    /// additionally detected immediately.
+    /// When a host resolves to a changed set of addresses, links to the new addresses are
+    /// established and links to addresses that have vanished are [drained](aggligator::Link::drain)
+    /// and then disconnected.
+    /// If resolution fails temporarily, existing links are kept and resolution is retried
+    /// with exponential backoff, starting at 500 milliseconds and capped at this interval.
+    pub fn set_resolve_interval(&mut self, resolve_interval: Duration) {
+        self.resolve_interval = resolve_interval;
+    }
+
+    /// Sets the local addresses outgoing links are bound to.
+    ///
+    /// If bind addresses are specified, one link is established from each bind address
+    /// to each resolved target address of the same IP version.
+    /// This overrides the default behavior of establishing one link per local interface.
+    /// On Linux the socket is additionally bound to the network interface that has the
+    /// bind address assigned, so that the link egresses from that interface.
+    ///
+    /// The port of a bind address should usually be zero to let the operating system
+    /// choose a free port.
+    pub fn set_bind_addrs(&mut self, bind_addrs: impl IntoIterator<Item = SocketAddr>) {
+        self.bind_addrs = bind_addrs.into_iter().collect();
+    }
+
+    /// Sets the range of local ports outgoing links are bound to.
+    ///
+    /// Each link binds its socket to a free port within the range before connecting,
+    /// which is useful when a firewall only permits outgoing connections from specific ports.
+    /// If no free port is found after a bounded number of attempts, the connection attempt fails
+    /// with a link error of kind [`ErrorKind::AddrInUse`] and is retried after the reconnect delay.
+    /// The port a link uses is available from the [socket information](TcpLinkTag::socket) of its tag.
+    ///
+    /// Bind addresses with a non-zero port take precedence.
+    /// Fails if the range is empty or includes port 0.
+    pub fn set_source_port_range(&mut self, range: RangeInclusive<u16>) -> Result<()> {
+        self.source_ports = Some(Arc::new(SourcePorts::new(range)?));
+        Ok(())
     }
-}
 
-/// Gets the list of local network interfaces from the operating system.
-///
-/// Filters out interfaces that are most likely useless.
-fn local_interfaces() -> Result<Vec<NetworkInterface>> {
-    Ok(NetworkInterface::show()
-        .map_err(|err| Error::new(ErrorKind::Other, err.to_string()))?
-        .into_iter()
-        .filter(|iface| !iface.name.starts_with("ifb"))
-        .collect())
-}
+    /// Sets the names of the local network interfaces that are used for outgoing links.
+    ///
+    /// By default all local interfaces are used.
+    /// On Linux the socket is bound to the interface using `SO_BINDTODEVICE` and on
+    /// macOS using `IP_BOUND_IF` for IPv4, so that the link is pinned to the device
+    /// regardless of routing.
+    /// Otherwise it is bound to an IP address of the interface.
+    ///
+    /// A link is attempted over each specified interface even while it is not
+    /// present, has no carrier or no IP address.
+    /// Connection attempts are retried and failures are reported as
+    /// [link errors](super::Connector::link_errors).
+    pub fn set_interfaces<I>(&mut self, interfaces: impl IntoIterator<Item = I>)
+    where
+        I: AsRef<str>,
+    {
+        self.interfaces = Some(interfaces.into_iter().map(|iface| iface.as_ref().as_bytes().to_vec()).collect());
+    }
 
-/// TCP transport for outgoing connections.
-#[derive(Debug, Clone)]
-pub struct TcpConnector {
-    hosts: Vec<String>,
-    ip_version: IpVersion,
-    resolve_interval: Duration,
-}
+    /// Sets the names of the local network interfaces that are not used for outgoing links.
+    ///
+    /// Existing links over interfaces that become denied are disconnected.
+    pub fn set_denied_interfaces<I>(&mut self, interfaces: impl IntoIterator<Item = I>)
+    where
+        I: AsRef<str>,
+    {
+        self.interface_filter.denied_names =
+            interfaces.into_iter().map(|iface| iface.as_ref().to_string()).collect();
+    }
 
-impl fmt::Display for TcpConnector {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.hosts.len() > 1 {
-            write!(f, "[{}]", self.hosts.join(", "))
-        } else {
-            write!(f, "{}", &self.hosts[0])
-        }
+    /// Sets the networks, given as address and prefix length, local interfaces must have
+    /// an IP address in to be used for outgoing links.
+    ///
+    /// By default interfaces with any IP address are used.
+    /// Interfaces without an IP address are not used when allowed networks are specified.
+    pub fn set_allowed_networks(&mut self, networks: impl IntoIterator<Item = (IpAddr, u8)>) {
+        self.interface_filter.allowed_networks = networks.into_iter().collect();
     }
-}
 
-impl TcpConnector {
-    /// Create a new TCP transport for outgoing connections.
+    /// Sets the networks, given as address and prefix length, local interfaces must not have
+    /// an IP address in to be used for outgoing links.
     ///
-    /// `hosts` can contain IP addresses and hostnames, including port numbers.
-    /// If an entry does not specify a port number, the `default_port` is used.
+    /// Existing links over interfaces that become denied are disconnected.
+    pub fn set_denied_networks(&mut self, networks: impl IntoIterator<Item = (IpAddr, u8)>) {
+        self.interface_filter.denied_networks = networks.into_iter().collect();
+    }
+
+    /// Sets a filter deciding whether a local network interface is used for outgoing links.
     ///
-    /// It is checked at creation that `hosts` resolves to at least one IP address.
+    /// The filter is called for each IP address of an interface and an interface is used
+    /// if the filter accepts at least one of them.
+    /// It is applied in addition to the [allowed](Self::set_interfaces) and
+    /// [denied interfaces](Self::set_denied_interfaces) and networks.
     ///
-    /// Host name resolution is retried periodically, thus DNS updates will be taken
-    /// into account without the need to recreate this transport.
-    pub async fn new(hosts: impl IntoIterator<Item = String>, default_port: u16) -> Result<Self> {
-        let mut hosts: Vec<_> = hosts.into_iter().collect();
+    /// The filter is re-applied each time the local interfaces are
+    /// [checked for changes](Self::set_resolve_interval), thus it may change its decision over time.
+    /// Existing links over interfaces that are no longer accepted are disconnected.
+    pub fn set_interface_filter(&mut self, filter: impl Fn(&InterfaceInfo) -> bool + Send + Sync + 'static) {
+        self.interface_filter.filter = Some(Arc::new(filter));
+    }
 
-        if hosts.is_empty() {
-            return Err(Error::new(ErrorKind::InvalidInput, "at least one host is required"));
+    /// Whether the interface with the specified name passes the interface filter.
+    fn interface_permitted(&self, interfaces: &[NetworkInterface], iface: &[u8]) -> bool {
+        let mut infos = interfaces
+            .iter()
+            .filter(|i| i.name.as_bytes() == iface)
+            .map(InterfaceInfo::from_interface)
+            .peekable();
+
+        match infos.peek() {
+            Some(_) => infos.any(|info| self.interface_filter.permits(&info)),
+            None => self
+                .interface_filter
+                .permits(&InterfaceInfo { name: String::from_utf8_lossy(iface).to_string(), addr: None }),
         }
+    }
 
-        for host in &mut hosts {
-            if !host.contains(':') {
-                host.push_str(&format!(":{default_port}"));
+    /// Withholds IPv4 tags of interfaces that have IPv6 tags, unless no IPv6 link over the
+    /// interface has been established within the IPv4 fallback timeout.
+    ///
+    /// `fallbacks` contains when IPv4 tags are offered for each interface and local address.
+    /// Returns when IPv4 tags of an interface will be offered next.
+    fn prefer_ipv6(
+        &self, tags: &mut HashSet<LinkTagBox>, fallbacks: &mut HashMap<LocalEndpoint, Instant>,
+    ) -> Option<Instant> {
+        let now = Instant::now();
+        let ipv6_links = self.ipv6_links.lock().unwrap();
+
+        let ipv6_groups: HashSet<_> = tags
+            .iter()
+            .filter_map(|tag| tag.as_any().downcast_ref::<TcpLinkTag>())
+            .filter(|tag| tag.remote.is_ipv6())
+            .map(|tag| (tag.interface.clone(), tag.local))
+            .collect();
+        fallbacks.retain(|group, _| ipv6_groups.contains(group) && !ipv6_links.contains(group));
+
+        let mut withheld = HashSet::new();
+        for group in ipv6_groups {
+            let fallback = if ipv6_links.contains(&group) {
+                None
+            } else {
+                Some(*fallbacks.entry(group.clone()).or_insert(now + self.ipv4_fallback_timeout))
+            };
+            if fallback.map(|fallback| fallback > now).unwrap_or(true) {
+                withheld.insert(group);
             }
         }
 
-        let this = Self { hosts, ip_version: IpVersion::Both, resolve_interval: Duration::from_secs(10) };
-
-        let addrs = this.resolve().await;
-        if addrs.is_empty() {
-            return Err(Error::new(ErrorKind::NotFound, "cannot resolve IP address of host"));
-        }
-        tracing::info!("{} resolves to: {:?}", &this, addrs);
+        tags.retain(|tag| match tag.as_any().downcast_ref::<TcpLinkTag>() {
+            Some(tag) if tag.remote.is_ipv4() && !tag.is_unresolved() => {
+                !withheld.contains(&(tag.interface.clone(), tag.local))
+            }
+            _ => true,
+        });
 
-        Ok(this)
+        fallbacks.values().filter(|&&fallback| fallback > now).min().copied()
     }
 
-    /// Sets the IP version used for connecting.
-    pub fn set_ip_version(&mut self, ip_version: IpVersion) {
-        self.ip_version = ip_version;
+    /// Whether hosts are resolved locally, i.e. not all links use a proxy with remote DNS.
+    fn resolves_locally(&self) -> bool {
+        self.proxies.is_empty() || self.proxies.iter().any(|proxy| !proxy.remote_dns())
     }
 
-    /// Sets the interval for re-resolving the hostname and checking for changed network interfaces.
-    pub fn set_resolve_interval(&mut self, resolve_interval: Duration) {
-        self.resolve_interval = resolve_interval;
+    /// Returns a handle for changing the targets while the transport is in use.
+    pub fn targets(&self) -> TcpTargets {
+        TcpTargets { hosts: self.hosts.clone(), default_ports: self.default_ports.clone() }
     }
 
-    /// Resolve target to socket addresses.
-    async fn resolve(&self) -> Vec<SocketAddr> {
-        let mut all_addrs = HashSet::new();
-
-        for host in &self.hosts {
-            let Ok(addrs) = lookup_host(host).await else { continue };
-            all_addrs.extend(addrs.filter(|addr| {
-                !((addr.is_ipv4() && self.ip_version.is_only_ipv6())
-                    || (addr.is_ipv6() && self.ip_version.is_only_ipv4()))
-            }));
-        }
-
-        let mut all_addrs: Vec<_> = all_addrs.into_iter().collect();
-        all_addrs.sort();
-        all_addrs
+    /// Resolve hosts to socket addresses.
+    ///
+    /// Also returns the hosts that could not be resolved.
+    async fn resolve(&self, hosts: &[String]) -> (Vec<SocketAddr>, Vec<(String, Error)>) {
+        resolve_hosts_with(&*self.resolver, hosts, self.ip_version).await
     }
 
     /// Returns the interface usable for connecting to target.
     ///
     /// Filters interfaces out that either have no IP address or only support
     /// an IP protocol version that does not match the target address.
-    fn interface_names_for_target(interfaces: &[NetworkInterface], target: SocketAddr) -> HashSet<Vec<u8>> {
+    pub(crate) fn interface_names_for_target(interfaces: &[NetworkInterface], target: SocketAddr) -> HashSet<Vec<u8>> {
         interfaces
             .iter()
             .cloned()
@@ -228,15 +1427,41 @@ impl TcpConnector {
     }
 
     /// Binds the socket the the specifed network interface.
-    fn bind_socket_to_interface(socket: &TcpSocket, interface: &[u8], remote: IpAddr) -> Result<()> {
+    pub(crate) fn bind_socket_to_interface(
+        socket: &TcpSocket, interface: &[u8], remote: IpAddr, source_ports: Option<&SourcePorts>,
+    ) -> Result<()> {
+        check_carrier(interface)?;
+
+        let unspecified = match remote {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+
         #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
         {
-            let _ = remote;
-            socket.bind_device(Some(interface))
+            socket.bind_device(Some(interface))?;
+            match source_ports {
+                Some(source_ports) => source_ports.bind(socket, unspecified),
+                None => Ok(()),
+            }
+        }
+
+        #[cfg(target_vendor = "apple")]
+        if remote.is_ipv4() {
+            let name = String::from_utf8_lossy(interface);
+            let index = nix::net::if_::if_nametoindex(&*name)
+                .map_err(|err| Error::new(ErrorKind::NotFound, format!("interface {name} not found: {err}")))?;
+            tracing::debug!("binding to interface {name} with index {index}");
+            socket2::SockRef::from(socket).bind_device_by_index(std::num::NonZeroU32::new(index))?;
+            return match source_ports {
+                Some(source_ports) => source_ports.bind(socket, unspecified),
+                None => Ok(()),
+            };
         }
 
         #[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
         {
+            let _ = unspecified;
             for ifn in local_interfaces()? {
                 if ifn.name.as_bytes() == interface {
                     let Some(addr) = ifn.addr else { continue };
@@ -250,8 +1475,21 @@ impl TcpConnector {
                         continue;
                     }
 
+                    // Link-local remote addresses are only reachable from link-local local addresses
+                    // and vice versa.
+                    if is_ipv6_link_local(addr.ip()) != is_ipv6_link_local(remote) {
+                        continue;
+                    }
+
                     tracing::debug!("binding to {addr:?} on interface {}", &ifn.name);
-                    socket.bind(SocketAddr::new(addr.ip(), 0))?;
+                    match (source_ports, addr.ip()) {
+                        (Some(source_ports), ip) => source_ports.bind(socket, ip)?,
+                        (None, IpAddr::V6(ip)) if is_ipv6_link_local(ip.into()) => {
+                            let scope_id = interface_index(interface).unwrap_or_default();
+                            socket.bind(std::net::SocketAddrV6::new(ip, 0, 0, scope_id).into())?
+                        }
+                        (None, ip) => socket.bind(SocketAddr::new(ip, 0))?,
+                    }
                     return Ok(());
                 }
             }
@@ -261,6 +1499,41 @@ impl TcpConnector {
     }
 }
 
+/// Checks that the network interface is present and has a carrier.
+#[cfg(target_os = "linux")]
+fn check_carrier(interface: &[u8]) -> Result<()> {
+    let name = String::from_utf8_lossy(interface);
+    match std::fs::read_to_string(format!("/sys/class/net/{name}/operstate")) {
+        Ok(state) if matches!(state.trim(), "down" | "lowerlayerdown" | "notpresent") => {
+            Err(Error::new(ErrorKind::NotConnected, format!("interface {name} has no carrier")))
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            Err(Error::new(ErrorKind::NotFound, format!("interface {name} not found")))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the network interface is present and has a carrier.
+#[cfg(not(target_os = "linux"))]
+fn check_carrier(_interface: &[u8]) -> Result<()> {
+    Ok(())
+}
+
+/// Queries the effective socket options of the link with the specified tag.
+fn socket_info(stream: &TcpStream, tag: &TcpLinkTag) -> Option<SocketInfo> {
+    match SocketInfo::query(socket2::SockRef::from(stream)) {
+        Ok(info) => {
+            tracing::debug!("socket of TCP link {tag}: {info}");
+            Some(info)
+        }
+        Err(err) => {
+            tracing::debug!("cannot query socket of TCP link {tag}: {err}");
+            None
+        }
+    }
+}
+
 #[async_trait]
 impl ConnectingTransport for TcpConnector {
     fn name(&self) -> &str {
@@ -268,16 +1541,179 @@ impl ConnectingTransport for TcpConnector {
     }
 
     async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        let mut hosts_rx = self.hosts.subscribe();
+        let mut link_limits_rx = self.link_limits.subscribe();
+        let mut prev_tags = HashSet::new();
+        let mut ipv4_fallbacks = HashMap::new();
+        let mut resolve_failures = 0;
+
+        #[cfg(all(feature = "netlink", target_os = "linux"))]
+        let mut interface_monitor = match super::netlink::InterfaceMonitor::new() {
+            Ok(monitor) => Some(monitor),
+            Err(err) => {
+                tracing::warn!("cannot monitor network interfaces, falling back to polling: {err}");
+                None
+            }
+        };
+
         loop {
+            let hosts = hosts_rx.borrow_and_update().clone();
             let interfaces = local_interfaces()?;
+            *self.present_interfaces.lock().unwrap() =
+                Some(interfaces.iter().map(|iface| iface.name.as_bytes().to_vec()).collect());
 
             let mut tags: HashSet<LinkTagBox> = HashSet::new();
-            for addr in self.resolve().await {
-                for iface in Self::interface_names_for_target(&interfaces, addr) {
-                    let tag = TcpLinkTag::new(&iface, addr, Direction::Outgoing);
-                    tags.insert(Box::new(tag.clone()));
+            let mut all_resolved = true;
+
+            // Determine addresses to connect to and, if using a proxy, the targets.
+            let addrs = if self.resolves_locally() {
+                let (addrs, failed) = self.resolve(&hosts).await;
+                for (host, err) in failed {
+                    tracing::debug!("cannot resolve {host}: {err}");
+                    tags.insert(Box::new(TcpLinkTag::unresolved(&host)));
+                    all_resolved = false;
+                }
+                addrs
+            } else {
+                Vec::new()
+            };
+            let remotes: Vec<(SocketAddr, Option<String>)> = if self.proxies.is_empty() {
+                addrs.into_iter().map(|addr| (addr, None)).collect()
+            } else {
+                self.proxies
+                    .iter()
+                    .flat_map(|proxy| -> Vec<_> {
+                        if proxy.remote_dns() {
+                            hosts.iter().map(|host| (proxy.addr(), Some(host.clone()))).collect()
+                        } else {
+                            addrs.iter().map(|addr| (proxy.addr(), Some(addr.to_string()))).collect()
+                        }
+                    })
+                    .collect()
+            };
+
+            let current_remotes: HashSet<_> =
+                remotes.iter().map(|(addr, target)| (without_scope(*addr), target.clone())).collect();
+            let mut denied_tags = HashSet::new();
+            for (addr, target) in remotes {
+                if self.bind_addrs.is_empty() {
+                    let mut ifaces = Self::interface_names_for_target(&interfaces, addr);
+
+                    // Keep specified interfaces without IP address, for example due to lacking carrier,
+                    // so that connecting over them is retried.
+                    if let Some(specified) = &self.interfaces {
+                        ifaces.extend(
+                            specified
+                                .iter()
+                                .filter(|iface| !interfaces.iter().any(|i| i.name.as_bytes() == iface.as_slice()))
+                                .cloned(),
+                        );
+                    }
+
+                    for iface in ifaces {
+                        let Some(remote) = self.scope_remote(addr, &iface) else { continue };
+                        if self.interfaces.as_ref().map(|ifaces| ifaces.contains(&iface)).unwrap_or(true) {
+                            let mut tag = TcpLinkTag::new(&iface, remote, Direction::Outgoing);
+                            tag.target = target.clone();
+                            if self.interface_permitted(&interfaces, &iface) {
+                                tags.insert(Box::new(tag));
+                            } else {
+                                denied_tags.insert(tag);
+                            }
+                        }
+                    }
+                } else {
+                    for bind_addr in &self.bind_addrs {
+                        if bind_addr.is_ipv4() != addr.is_ipv4() {
+                            continue;
+                        }
+
+                        let iface = interfaces
+                            .iter()
+                            .find(|iface| iface.addr.map(|a| a.ip() == bind_addr.ip()).unwrap_or_default())
+                            .map(|iface| iface.name.as_bytes().to_vec())
+                            .unwrap_or_default();
+                        let Some(remote) = self.scope_remote(addr, &iface) else { continue };
+                        if self.interfaces.as_ref().map(|ifaces| ifaces.contains(&iface)).unwrap_or(true) {
+                            let mut tag = TcpLinkTag::bound(&iface, *bind_addr, remote);
+                            tag.target = target.clone();
+                            let info = InterfaceInfo {
+                                name: String::from_utf8_lossy(&iface).to_string(),
+                                addr: Some(bind_addr.ip()),
+                            };
+                            if self.interface_filter.permits(&info) {
+                                tags.insert(Box::new(tag));
+                            } else {
+                                denied_tags.insert(tag);
+                            }
+                        }
+                    }
+                }
+            }
+
+            if !denied_tags.is_empty() {
+                tracing::debug!(
+                    "denied tags: {}",
+                    denied_tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>().join(", ")
+                );
+            }
+            *self.denied_tags.lock().unwrap() = denied_tags;
+
+            if let Some(probe_timeout) = self.connectivity_check {
+                self.check_connectivity(&mut tags, probe_timeout).await;
+            }
+
+            // Retry failed resolution with exponential backoff.
+            let resolve_delay = if all_resolved {
+                resolve_failures = 0;
+                self.resolve_interval
+            } else {
+                resolve_failures += 1;
+                RESOLVE_RETRY_DELAY
+                    .saturating_mul(2u32.saturating_pow(resolve_failures - 1))
+                    .min(self.resolve_interval)
+            };
+
+            link_limits_rx.borrow_and_update();
+            self.limit_ports(&mut tags);
+
+            let next_ipv4_fallback = match self.ip_version {
+                IpVersion::PreferIPv6 => self.prefer_ipv6(&mut tags, &mut ipv4_fallbacks),
+                _ => None,
+            };
+
+            self.limit_links(&mut tags);
+
+            // Retire tags to addresses that vanished due to removal of a target or because
+            // a target now resolves to different addresses.
+            // During temporary resolution failures previous tags are remembered instead,
+            // so that they are retired once all targets can be resolved again.
+            let tcp_tags: HashSet<TcpLinkTag> = tags
+                .iter()
+                .filter_map(|tag| tag.as_any().downcast_ref::<TcpLinkTag>())
+                .filter(|tag| !tag.is_unresolved())
+                .cloned()
+                .collect();
+            {
+                let mut removed_tags = self.removed_tags.lock().unwrap();
+                if all_resolved {
+                    let vanished: Vec<_> = prev_tags
+                        .difference(&tcp_tags)
+                        .filter(|tag| !current_remotes.contains(&(without_scope(tag.remote), tag.target.clone())))
+                        .cloned()
+                        .collect();
+                    if !vanished.is_empty() {
+                        tracing::debug!(
+                            "retiring tags to vanished addresses: {}",
+                            vanished.iter().map(|tag| tag.to_string()).collect::<Vec<_>>().join(", ")
+                        );
+                    }
+                    removed_tags.extend(vanished);
+                    prev_tags.clear();
                 }
+                removed_tags.retain(|tag| !tcp_tags.contains(tag));
             }
+            prev_tags.extend(tcp_tags);
 
             tx.send_if_modified(|v| {
                 if *v != tags {
@@ -288,25 +1724,54 @@ impl ConnectingTransport for TcpConnector {
                 }
             });
 
-            sleep(self.resolve_interval).await;
+            let ipv4_fallback = async {
+                match next_ipv4_fallback {
+                    Some(next_ipv4_fallback) => sleep_until(next_ipv4_fallback).await,
+                    None => future::pending().await,
+                }
+            };
+
+            let interfaces_changed = async {
+                #[cfg(all(feature = "netlink", target_os = "linux"))]
+                if let Some(monitor) = &mut interface_monitor {
+                    return monitor.changed().await;
+                }
+                future::pending().await
+            };
+
+            tokio::select! {
+                () = sleep(resolve_delay) => (),
+                () = ipv4_fallback => tracing::debug!("IPv4 fallback timeout elapsed"),
+                () = interfaces_changed => tracing::debug!("network interfaces changed"),
+                () = self.reselect.notified() => (),
+                _ = link_limits_rx.changed() => tracing::debug!("link limits changed"),
+                _ = hosts_rx.changed() => tracing::debug!("targets changed: {}", hosts_rx.borrow().join(", ")),
+            }
         }
     }
 
     async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
-        let tag: &TcpLinkTag = tag.as_any().downcast_ref().unwrap();
-
-        let socket = match tag.remote.ip() {
-            IpAddr::V4(_) => TcpSocket::new_v4(),
-            IpAddr::V6(_) => TcpSocket::new_v6(),
-        }?;
+        let (io, _tag) = self.connect_tagged(tag).await?;
+        Ok(io)
+    }
 
-        Self::bind_socket_to_interface(&socket, &tag.interface, tag.remote.ip())?;
+    async fn connect_tagged(&self, tag: &dyn LinkTag) -> Result<(IoBox, LinkTagBox)> {
+        let tag: &TcpLinkTag = tag.as_any().downcast_ref().unwrap();
 
-        let stream = socket.connect(tag.remote).await?;
-        let _ = stream.set_nodelay(true);
+        if tag.is_unresolved() {
+            let host = tag.target.as_deref().unwrap_or_default();
+            return Err(match self.resolver.resolve(host).await {
+                Ok(_) => {
+                    self.reselect.notify_one();
+                    Error::new(ErrorKind::NotFound, format!("{host} was resolved, awaiting link tag update"))
+                }
+                Err(err) => Error::new(err.kind(), format!("cannot resolve {host}: {err}")),
+            });
+        }
 
-        let (rh, wh) = stream.into_split();
-        Ok(IoBox::new(rh, wh))
+        let res = self.connect_resolved(tag).await;
+        self.record_attempt(tag, res.is_ok());
+        res
     }
 
     async fn link_filter(&self, new: &Link<LinkTagBox>, existing: &[Link<LinkTagBox>]) -> bool {
@@ -324,9 +1789,18 @@ impl ConnectingTransport for TcpConnector {
             String::from_utf8_lossy(&new_tag.interface)
         );
 
+        // Links being drained, for example due to removal of their target, are about to go away
+        // and thus not considered.
+        // Links through different proxies take different paths and are thus not redundant.
+        // Links to different ports may be treated differently by the network and are thus not redundant.
         match existing.iter().find(|link| {
             let Some(tag) = link.tag().as_any().downcast_ref::<TcpLinkTag>() else { return false };
-            tag.interface == new_tag.interface && link.remote_user_data() == new.remote_user_data()
+            !link.is_draining()
+                && (new_tag.target.is_none() || tag.remote == new_tag.remote)
+                && (new_tag.target.is_some() || tag.remote.port() == new_tag.remote.port())
+                && tag.interface == new_tag.interface
+                && tag.local == new_tag.local
+                && link.remote_user_data() == new.remote_user_data()
         }) {
             Some(other) => {
                 let other_tag = other.tag().as_any().downcast_ref::<TcpLinkTag>().unwrap();
@@ -339,25 +1813,121 @@ impl ConnectingTransport for TcpConnector {
             }
         }
     }
+
+    async fn connected_links(&self, links: &[Link<LinkTagBox>]) {
+        // Track interfaces with IPv6 links for preferring IPv6.
+        *self.ipv6_links.lock().unwrap() = links
+            .iter()
+            .filter_map(|link| link.tag().as_any().downcast_ref::<TcpLinkTag>())
+            .filter(|tag| tag.direction == Direction::Outgoing && tag.remote.is_ipv6())
+            .map(|tag| (tag.interface.clone(), tag.local))
+            .collect();
+
+        // Track tags with connected links for keeping them when limiting links.
+        *self.connected_tags.lock().unwrap() = links
+            .iter()
+            .filter(|link| !link.is_disconnected() && !link.is_draining())
+            .filter_map(|link| link.tag().as_any().downcast_ref::<TcpLinkTag>())
+            .filter(|tag| tag.direction == Direction::Outgoing)
+            .cloned()
+            .collect();
+
+        // Disconnect links over interfaces that have been denied by the interface filter
+        // or have vanished and drain links to removed targets and links exceeding the link limits.
+        let denied_tags = self.denied_tags.lock().unwrap();
+        let removed_tags = self.removed_tags.lock().unwrap();
+        let suppressed_tags = self.suppressed_tags.lock().unwrap();
+        let present_interfaces = self.present_interfaces.lock().unwrap();
+        for link in links {
+            let Some(tag) = link.tag().as_any().downcast_ref::<TcpLinkTag>() else { continue };
+            let vanished = match &*present_interfaces {
+                Some(present) => {
+                    tag.direction == Direction::Outgoing
+                        && !tag.interface.is_empty()
+                        && !present.contains(&tag.interface)
+                }
+                None => false,
+            };
+            if denied_tags.contains(tag) {
+                tracing::info!("disconnecting link {tag} over denied interface");
+                link.start_disconnect();
+            } else if vanished && !link.is_disconnected() {
+                tracing::info!("disconnecting link {tag} over vanished interface");
+                link.start_disconnect();
+            } else if removed_tags.contains(tag) && !link.is_draining() {
+                tracing::info!("draining link {tag} to removed target");
+                link.start_drain();
+            } else if suppressed_tags.contains(tag) && !link.is_draining() {
+                tracing::info!("draining link {tag} exceeding link limits");
+                link.start_drain();
+            }
+        }
+    }
+
+    fn race_groups(&self, tags: HashSet<LinkTagBox>) -> Vec<Vec<LinkTagBox>> {
+        // All remote addresses reachable from the same local interface and address are
+        // alternatives, since the link filter keeps only one of them per server.
+        // Links to different ports or through different proxies are established independently.
+        // Address families are interleaved, starting with IPv6, as recommended by RFC 8305.
+        let mut groups: BTreeMap<_, (Vec<TcpLinkTag>, Vec<TcpLinkTag>)> = BTreeMap::new();
+        for tag in tags {
+            let tag: &TcpLinkTag = tag.as_any().downcast_ref().unwrap();
+            let proxy = tag.target.is_some().then_some(tag.remote);
+            let key = (tag.interface.clone(), tag.local, proxy, tag.remote.port());
+            let (v6, v4) = groups.entry(key).or_default();
+            match tag.remote {
+                SocketAddr::V6(_) => v6.push(tag.clone()),
+                SocketAddr::V4(_) => v4.push(tag.clone()),
+            }
+        }
+
+        groups
+            .into_values()
+            .map(|(mut v6, mut v4)| {
+                v6.sort();
+                v4.sort();
+
+                let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+                let mut group: Vec<LinkTagBox> = Vec::new();
+                loop {
+                    match (v6.next(), v4.next()) {
+                        (None, None) => break,
+                        (a, b) => group.extend(a.into_iter().chain(b).map(|tag| Box::new(tag) as LinkTagBox)),
+                    }
+                }
+                group
+            })
+            .collect()
+    }
+
+    fn race_attempt_delay(&self) -> Option<Duration> {
+        self.happy_eyeballs
+    }
 }
 
 /// TCP transport for incoming connections.
-#[derive(Debug)]
+///
+/// Listen addresses can be added and removed while the transport is in use.
+/// For this purpose keep a clone of the transport before adding it to an
+/// [acceptor](super::Acceptor); all clones share the same listeners.
+#[derive(Debug, Clone)]
 pub struct TcpAcceptor {
-    listeners: Vec<TcpListener>,
+    listeners: Arc<watch::Sender<Vec<Arc<TcpListener>>>>,
+    ip_version: IpVersion,
+    link_local: bool,
+    socket_options: SocketOptions,
+    mptcp: bool,
+    v6_only: Option<bool>,
+    socket_prepare: SocketPrepare,
 }
 
 impl fmt::Display for TcpAcceptor {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let addrs: Vec<_> = self
-            .listeners
-            .iter()
-            .filter_map(|listener| listener.local_addr().ok().map(|addr| addr.to_string()))
-            .collect();
-        if addrs.len() > 1 {
-            write!(f, "[{}]", addrs.join(", "))
-        } else {
+        let addrs: Vec<_> = self.local_addrs().into_iter().map(|addr| addr.to_string()).collect();
+        if addrs.len() == 1 {
             write!(f, "{}", addrs[0])
+        } else {
+            write!(f, "[{}]", addrs.join(", "))
         }
     }
 }
@@ -366,6 +1936,8 @@ impl TcpAcceptor {
     /// Create a new TCP transport listening for incoming connections.
     ///
     /// It listens on the local addresses specified in `addrs`.
+    /// Addresses with port 0 are bound to a port chosen by the operating system,
+    /// which can be obtained using [`local_addrs`](Self::local_addrs).
     pub async fn new(addrs: impl IntoIterator<Item = SocketAddr>) -> Result<Self> {
         let mut listeners = Vec::new();
 
@@ -384,7 +1956,272 @@ impl TcpAcceptor {
             return Err(Error::new(ErrorKind::InvalidInput, "at least one listener is required"));
         }
 
-        Ok(Self { listeners: listeners.into_iter().collect() })
+        Ok(Self::with_listeners(listeners))
+    }
+
+    /// Create a new TCP transport for incoming connections, listening on all local IPv4 and
+    /// IPv6 addresses.
+    ///
+    /// Where the operating system supports it, a single dual-stack listener on `[::]` with
+    /// [IPv6 only](Self::set_v6_only) disabled is used.
+    /// Otherwise separate listeners on `[::]` and `0.0.0.0` are created, or only the latter
+    /// if IPv6 is unavailable.
+    /// In both cases IPv4 connections are reported with their IPv4 addresses in the link tags.
+    /// If `port` is 0, the port chosen by the operating system for IPv6 is also used for IPv4.
+    pub async fn dual_stack(port: u16) -> Result<Self> {
+        let addr_v6 = SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), port);
+        let mut this = Self::with_listeners(Vec::new());
+
+        this.v6_only = Some(false);
+        let listeners = match this.bind(addr_v6, None) {
+            Ok(listener) => vec![listener],
+            Err(err) => {
+                tracing::debug!("dual-stack listening on {addr_v6} is unavailable: {err}");
+                this.v6_only = Some(true);
+                let listener_v6 = this.bind(addr_v6, None);
+                let port = match &listener_v6 {
+                    Ok(listener) => listener.local_addr()?.port(),
+                    Err(_) => port,
+                };
+                let listener_v4 = this.bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port), None)?;
+                match listener_v6 {
+                    Ok(listener_v6) => vec![listener_v6, listener_v4],
+                    Err(err) => {
+                        tracing::debug!("listening on {addr_v6} failed, using IPv4 only: {err}");
+                        vec![listener_v4]
+                    }
+                }
+            }
+        };
+
+        this.listeners.send_replace(listeners.into_iter().map(Arc::new).collect());
+        Ok(this)
+    }
+
+    /// Creates the transport using the specified listeners.
+    fn with_listeners(listeners: Vec<TcpListener>) -> Self {
+        Self {
+            listeners: Arc::new(watch::channel(listeners.into_iter().map(Arc::new).collect()).0),
+            ip_version: IpVersion::Both,
+            link_local: true,
+            socket_options: SocketOptions::default(),
+            mptcp: false,
+            v6_only: None,
+            socket_prepare: SocketPrepare::default(),
+        }
+    }
+
+    /// Sets the IP version of accepted connections.
+    ///
+    /// Incoming connections using another IP version, for example IPv4 connections to
+    /// a dual-stack listener, are rejected.
+    pub fn set_ip_version(&mut self, ip_version: IpVersion) {
+        self.ip_version = ip_version;
+    }
+
+    /// Sets whether connections from IPv6 link-local addresses (`fe80::/10`) are accepted.
+    ///
+    /// The link tags of accepted connections contain the scope id of such remote addresses
+    /// and name the interface the connection was received on.
+    ///
+    /// By default link-local connections are accepted.
+    pub fn set_link_local(&mut self, link_local: bool) {
+        self.link_local = link_local;
+    }
+
+    /// Sets whether listeners on IPv6 addresses only accept IPv6 connections (`IPV6_V6ONLY`).
+    ///
+    /// If disabled, a listener on `[::]` also accepts IPv4 connections using IPv4-mapped IPv6
+    /// addresses; these are reported with their IPv4 addresses in the link tags.
+    /// Not all operating systems support this; then recreating the listeners fails.
+    /// By default the operating system setting is used, which for example is disabled on Linux
+    /// and enabled on Windows.
+    /// See [`dual_stack`](Self::dual_stack) for a portable way of accepting both IP versions.
+    ///
+    /// Since the option must be set before binding, all listeners are recreated on their
+    /// local addresses. Thus this must be called before the transport is added to an acceptor.
+    /// Listeners added afterwards using [`add_addr`](Self::add_addr) use the same setting.
+    pub fn set_v6_only(&mut self, v6_only: bool) -> Result<()> {
+        self.v6_only = Some(v6_only);
+        self.rebind()
+    }
+
+    /// Sets whether Nagle's algorithm is disabled on accepted sockets.
+    ///
+    /// See [`TcpConnector::set_nodelay`] for details.
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.socket_options.set_nodelay(nodelay);
+    }
+
+    /// Sets the size of the send buffer of accepted sockets.
+    ///
+    /// Since it is set after a connection has been accepted, it does not influence the
+    /// window scaling negotiated during connection establishment.
+    /// See [`TcpConnector::set_send_buffer_size`] for details.
+    pub fn set_send_buffer_size(&mut self, send_buffer_size: usize) {
+        self.socket_options.set_send_buffer_size(send_buffer_size);
+    }
+
+    /// Sets the size of the receive buffer of accepted sockets.
+    ///
+    /// Since it is set after a connection has been accepted, it does not influence the
+    /// window scaling negotiated during connection establishment.
+    /// See [`TcpConnector::set_recv_buffer_size`] for details.
+    pub fn set_recv_buffer_size(&mut self, recv_buffer_size: usize) {
+        self.socket_options.set_recv_buffer_size(recv_buffer_size);
+    }
+
+    /// Sets the TCP keepalive configuration of accepted sockets.
+    ///
+    /// Keepalive of accepted sockets stays disabled until a configuration is set;
+    /// pass `Some(KeepaliveConfig::default())` to enable it using the recommended parameters.
+    /// See [`TcpConnector::set_keepalive`] for details.
+    pub fn set_keepalive(&mut self, keepalive: Option<KeepaliveConfig>) -> Result<()> {
+        self.socket_options.set_keepalive(keepalive)
+    }
+
+    /// Sets the TCP user timeout of accepted sockets.
+    ///
+    /// See [`TcpConnector::set_user_timeout`] for details.
+    pub fn set_user_timeout(&mut self, user_timeout: Duration) -> Result<()> {
+        self.socket_options.set_user_timeout(user_timeout)
+    }
+
+    /// Sets the type of service (`IP_TOS`) of accepted IPv4 sockets.
+    ///
+    /// Connections whose socket cannot be marked are rejected and a warning is logged.
+    /// See [`TcpConnector::set_tos`] for details.
+    pub fn set_tos(&mut self, tos: u8) {
+        self.socket_options.set_tos(tos);
+    }
+
+    /// Sets the traffic class (`IPV6_TCLASS`) of accepted IPv6 sockets.
+    ///
+    /// See [`TcpConnector::set_traffic_class`] for details.
+    pub fn set_traffic_class(&mut self, traffic_class: u8) {
+        self.socket_options.set_traffic_class(traffic_class);
+    }
+
+    /// Sets the type of service and traffic class of sockets accepted over the specified local interface.
+    ///
+    /// See [`TcpConnector::set_interface_tos`] for details.
+    pub fn set_interface_tos(&mut self, interface: impl AsRef<[u8]>, tos: u8) {
+        self.socket_options.set_interface_tos(interface.as_ref(), tos);
+    }
+
+    /// Sets whether incoming links use Multipath TCP (MPTCP).
+    ///
+    /// Since the protocol of a socket cannot be changed, all listeners are recreated on their
+    /// local addresses. Thus this must be called before the transport is added to an acceptor.
+    /// Listeners added afterwards using [`add_addr`](Self::add_addr) use the same setting.
+    ///
+    /// See [`TcpConnector::set_mptcp`] for details.
+    pub fn set_mptcp(&mut self, mptcp: bool) -> Result<()> {
+        self.mptcp = mptcp;
+        self.rebind()
+    }
+
+    /// Sets a callback that prepares each listener socket before it starts listening.
+    ///
+    /// It is invoked after the socket has been created and bound to its local address and
+    /// interface, but before it starts listening.
+    /// This allows, for example, to exclude the listener from an Android VPN using
+    /// `VpnService.protect` or to set a firewall mark (`SO_MARK`) for policy routing on Linux.
+    ///
+    /// Since the callback must run before listening, all listeners are recreated on their
+    /// local addresses and the callback is invoked for each of them.
+    /// Thus this must be called before the transport is added to an acceptor.
+    /// Listeners added afterwards using [`add_addr`](Self::add_addr) are prepared likewise.
+    /// Fails if the callback fails for any listener.
+    ///
+    /// See [`TcpConnector::set_socket_prepare`] for details.
+    pub fn set_socket_prepare(
+        &mut self, prepare: impl Fn(&socket2::Socket, &TargetInfo) -> Result<()> + Send + Sync + 'static,
+    ) -> Result<()> {
+        self.socket_prepare = SocketPrepare(Some(Arc::new(prepare)));
+        self.rebind()
+    }
+
+    /// Recreates all listeners on their local addresses and interfaces.
+    fn rebind(&self) -> Result<()> {
+        let mut listeners = Vec::new();
+        for listener in self.listeners.send_replace(Vec::new()) {
+            let addr = listener.local_addr()?;
+
+            #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+            let device = socket2::SockRef::from(&*listener).device()?;
+            #[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+            let device: Option<Vec<u8>> = None;
+
+            drop(listener);
+            listeners.push(Arc::new(self.bind(addr, device.as_deref())?));
+        }
+        self.listeners.send_replace(listeners);
+
+        Ok(())
+    }
+
+    /// Creates a listener on the specified local address and optionally interface.
+    fn bind(&self, addr: SocketAddr, interface: Option<&[u8]>) -> Result<TcpListener> {
+        let socket = tcp_socket(addr, self.mptcp)?;
+        #[cfg(not(windows))]
+        socket.set_reuseaddr(true)?;
+        if let (true, Some(v6_only)) = (addr.is_ipv6(), self.v6_only) {
+            socket2::SockRef::from(&socket).set_only_v6(v6_only)?;
+        }
+        socket.bind(addr)?;
+
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        if let Some(interface) = interface {
+            socket.bind_device(Some(interface))?;
+        }
+
+        let info = TargetInfo {
+            interface: interface.map(|interface| interface.to_vec()).unwrap_or_default(),
+            local: Some(socket.local_addr()?),
+            remote: None,
+            target: None,
+        };
+        self.socket_prepare.prepare(socket2::SockRef::from(&socket), &info)?;
+
+        socket.listen(1024)
+    }
+
+    /// Local addresses the transport is listening on.
+    ///
+    /// These are the actual bound addresses, i.e. listeners that were requested with port 0
+    /// report the port assigned by the operating system.
+    /// They are returned in the order the listeners were added.
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.listeners.borrow().iter().filter_map(|listener| listener.local_addr().ok()).collect()
+    }
+
+    /// Starts listening on the specified local address.
+    ///
+    /// Fails if the address cannot be bound or the transport is already listening on it.
+    /// Existing listeners are not affected.
+    /// If the port of `addr` is 0, the bound address can be obtained from [`local_addrs`](Self::local_addrs).
+    pub async fn add_addr(&self, addr: SocketAddr) -> Result<()> {
+        if self.local_addrs().contains(&addr) {
+            return Err(Error::new(ErrorKind::AlreadyExists, format!("already listening on {addr}")));
+        }
+
+        let listener = self.bind(addr, None)?;
+        tracing::debug!("listening on {}", listener.local_addr()?);
+        self.listeners.send_modify(|listeners| listeners.push(Arc::new(listener)));
+        Ok(())
+    }
+
+    /// Stops listening on the specified local address.
+    ///
+    /// Links that have been accepted through the listener stay connected.
+    /// Returns `false` if the transport is not listening on the address.
+    pub fn remove_addr(&self, addr: SocketAddr) -> bool {
+        self.listeners.send_if_modified(|listeners| {
+            let len = listeners.len();
+            listeners.retain(|listener| listener.local_addr().ok() != Some(addr));
+            listeners.len() != len
+        })
     }
 
     /// Create a new TCP transport for incoming connections, listening individually on all interfaces.
@@ -411,7 +2248,14 @@ impl TcpAcceptor {
     }
 
     fn listen(interface: &NetworkInterface, port: u16) -> Result<TcpListener> {
-        let addr = SocketAddr::new(interface.addr.ok_or(ErrorKind::NotFound)?.ip(), port);
+        let mut addr = SocketAddr::new(interface.addr.ok_or(ErrorKind::NotFound)?.ip(), port);
+
+        // Link-local addresses can only be bound to together with the scope of their interface.
+        if let SocketAddr::V6(addr) = &mut addr {
+            if is_ipv6_link_local(IpAddr::V6(*addr.ip())) {
+                addr.set_scope_id(interface_index(interface.name.as_bytes()).ok_or(ErrorKind::NotFound)?);
+            }
+        }
 
         let socket = match addr.ip() {
             IpAddr::V4(_) => TcpSocket::new_v4()?,
@@ -436,47 +2280,66 @@ impl AcceptingTransport for TcpAcceptor {
     }
 
     async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
+        let mut listeners_rx = self.listeners.subscribe();
+
         loop {
-            // Accept incoming connection.
-            let (res, _, _) =
-                future::select_all(self.listeners.iter().map(|listener| listener.accept().boxed())).await;
-            let (socket, mut remote) = res?;
+            // Accept incoming connection, restarting when listeners have been added or removed.
+            let listeners = listeners_rx.borrow_and_update().clone();
+            let accept = async {
+                if listeners.is_empty() {
+                    future::pending().await
+                } else {
+                    let (res, _, _) =
+                        future::select_all(listeners.iter().map(|listener| listener.accept().boxed())).await;
+                    res
+                }
+            };
+            let (socket, mut remote) = tokio::select! {
+                res = accept => res?,
+                _ = listeners_rx.changed() => continue,
+            };
             let mut local = socket.local_addr()?;
 
             // Use proper IPv4 addresses.
-            if let IpAddr::V6(addr) = remote.ip() {
-                if let Some(addr) = addr.to_ipv4_mapped() {
-                    remote.set_ip(addr.into());
-                }
+            use_proper_ipv4(&mut remote);
+            use_proper_ipv4(&mut local);
+
+            // Check IP version.
+            if (remote.is_ipv4() && self.ip_version.is_only_ipv6())
+                || (remote.is_ipv6() && self.ip_version.is_only_ipv4())
+            {
+                tracing::debug!("Incoming connection from {remote} uses disallowed IP version, rejecting.");
+                continue;
             }
-            if let IpAddr::V6(addr) = local.ip() {
-                if let Some(addr) = addr.to_ipv4_mapped() {
-                    local.set_ip(addr.into());
-                }
+
+            // Check for link-local address.
+            if !self.link_local && is_ipv6_link_local(remote.ip()) {
+                tracing::debug!("Incoming connection from link-local address {remote}, rejecting.");
+                continue;
             }
 
             // Find local interface.
-            let interfaces = local_interfaces()?;
-            let Some(interface) = interfaces
-                .into_iter()
-                .find_map(|interface| {
-                    interface
-                        .addr
-                        .map(|addr| addr.ip() == local.ip())
-                        .unwrap_or_default()
-                        .then_some(interface.name.into_bytes())
-                })
-            else {
-                tracing::warn!("Interface for incoming connection from {remote} to {local} not found, rejecting.");
+            let Some(interface) = interface_name_for_local_addr(local)? else {
+                tracing::warn!(
+                    "Interface for incoming connection from {remote} to {local} not found, rejecting."
+                );
                 continue;
             };
 
+            // Configure socket.
+            self.socket_options.apply(socket2::SockRef::from(&socket));
+            if let Err(err) =
+                self.socket_options.apply_marking(socket2::SockRef::from(&socket), &interface, remote.is_ipv6())
+            {
+                tracing::warn!("Cannot mark incoming connection from {remote}: {err}, rejecting.");
+                continue;
+            }
+
             // Build tag.
             tracing::debug!("Accepted TCP connection from {remote} on {}", String::from_utf8_lossy(&interface));
-            let tag = TcpLinkTag { interface, remote, direction: Direction::Incoming };
+            let mut tag = TcpLinkTag::accepted(&interface, local, remote);
+            tag.socket = socket_info(&socket, &tag);
 
-            // Configure socket.
-            let _ = socket.set_nodelay(true);
             let (rh, wh) = socket.into_split();
 
             let _ = tx.send(AcceptedIoBox::new(rh, wh, tag)).await;
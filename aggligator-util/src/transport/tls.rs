@@ -1,34 +1,550 @@
 //! TLS wrapper.
+//!
+//! Client certificate authentication is supported using
+//! [`TlsClient::with_client_auth`] and [`TlsServer::with_client_auth`].
+//! For full control, for example over certificate verification or cipher suites,
+//! pass a rustls configuration to [`TlsClient::new`] and [`TlsServer::new`].
+//! The negotiated TLS parameters and the certificates presented by the remote endpoint
+//! of a link are available from its link tag via [`tls_info`](TlsInfo#accessing-tls-information).
+//! This applies to every link of a connection, including links added after the connection
+//! has been accepted, so that the [certificate chain](TlsInfo::peer_certificate_chain) of each
+//! client can be recorded, for example for audit logging.
+//! Links rejected because of a missing or invalid client certificate are identified
+//! by the [TLS error](LinkError::tls_error) of the reported link error.
+//!
+//! Instead of verifying the certificate chain of the server, [`TlsClient`] can accept servers
+//! by the hash of their public key using [certificate pinning](TlsClient::set_pinned_spki).
+//!
+//! The server name used for SNI and certificate verification is independent of the host
+//! a link is dialed to, so that a server can be reached through relays whose names are not
+//! covered by its certificate.
+//! It can be [chosen per link](TlsClient::set_server_name_selector) based on the link tag.
+//!
+//! [`TlsServer`] can use a separate configuration, for example with its own certificate,
+//! for each [server name](TlsServer::add_server_name) requested by clients using SNI.
+//! The requested name is available from the TLS information of the link tag and can be
+//! used by a [link authorizer](super::AcceptorBuilder::set_link_authorizer) to route
+//! links to separate connections.
+//!
+//! The certificate presented by [`TlsServer`] can be [reloaded](TlsServer::reload) while
+//! it is in use, for example when it has been renewed, without affecting established links.
+//!
+//! Links that are reestablished, for example after a network change, resume the TLS session
+//! of a previous link, which saves the signature operations of a full handshake.
+//! The client session cache is part of the client configuration and thus shared by all links
+//! of a [`TlsClient`] and its clones.
+//! Resumption can be [disabled](TlsClient::set_resumption) on either side and whether
+//! a link resumed a session is available from its [TLS information](TlsInfo::resumed).
+//!
+//! Links are always established without early data (0-RTT),
+//! since the TLS information of a link and certificate pinning require the completed handshake.
+//! The [QUIC transport](super::quic) supports 0-RTT.
 
 use async_trait::async_trait;
-use rustls::{ClientConfig, ServerConfig, ServerName};
-use std::{io::Result, sync::Arc};
-use tokio::io::split;
-use tokio_rustls::{TlsAcceptor, TlsConnector};
+use rustls::{
+    client::{ClientSessionMemoryCache, NoClientSessionStorage, ServerCertVerified, ServerCertVerifier},
+    server::{
+        AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, ClientHello, NoServerSessionStorage,
+        ProducesTickets, ResolvesServerCert, ServerSessionMemoryCache,
+    },
+    sign::{self, CertifiedKey},
+    Certificate, CipherSuite, ClientConfig, CommonState, PrivateKey, ProtocolVersion, RootCertStore,
+    ServerConfig, ServerName,
+};
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    hash::Hasher,
+    io::{Error, ErrorKind, Result},
+    iter,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::SystemTime,
+};
+use tokio::io::{split, AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::{client, server, LazyConfigAcceptor, TlsAcceptor, TlsConnector};
 
-use super::{AcceptingWrapper, ConnectingWrapper, IoBox};
+use super::{x509, AcceptingWrapper, ConnectingWrapper, IoBox, LinkError, LinkMetadata, LinkTag, LinkTagBox};
+use aggligator::control::Direction;
 
 static NAME: &str = "tls";
 
+/// Number of sessions kept for resumption when it is enabled.
+const SESSION_CACHE_SIZE: usize = 256;
+
+/// Information about the TLS session of a link.
+///
+/// # Accessing TLS information
+/// TLS information is attached to the link tag when a link is wrapped
+/// by [`TlsClient`] or [`TlsServer`].
+/// Use the `tls_info` method of a [`LinkTag`] to obtain it.
+///
+/// If the TLS handshake fails, the `tls_info` method of the reported [`LinkError`]
+/// provides the parameters that were negotiated before the failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TlsInfo {
+    /// Negotiated protocol version.
+    pub version: Option<ProtocolVersion>,
+    /// Negotiated cipher suite.
+    pub cipher_suite: Option<CipherSuite>,
+    /// Server name indication (SNI) sent by the client.
+    pub sni: Option<String>,
+    /// Certificate chain presented by the remote endpoint, starting with its own certificate.
+    ///
+    /// This is empty if the remote endpoint did not authenticate itself.
+    pub peer_certificates: Vec<Certificate>,
+    /// Subject of the certificate of the remote endpoint (RFC 4514).
+    pub peer_subject: Option<String>,
+    /// Subject alternative names of the certificate of the remote endpoint.
+    ///
+    /// Email addresses, DNS names, URIs and IP addresses are included with the prefixes
+    /// `email:`, `DNS:`, `URI:` and `IP:` respectively, for example `DNS:example.com`.
+    pub peer_subject_alt_names: Vec<String>,
+    /// Application protocol negotiated using ALPN.
+    pub alpn: Option<Vec<u8>>,
+    /// Whether the handshake resumed a previous TLS session instead of performing
+    /// a full handshake.
+    ///
+    /// The certificates of the remote endpoint are then those presented when the session
+    /// was initially established.
+    pub resumed: bool,
+}
+
+impl TlsInfo {
+    /// Certificate chain presented by the remote endpoint, starting with its own certificate.
+    ///
+    /// Each certificate is DER-encoded.
+    /// Returns `None` if the remote endpoint did not authenticate itself, for example
+    /// a client connecting to a server with [optional](ClientAuth::Optional) client authentication
+    /// without a certificate.
+    pub fn peer_certificate_chain(&self) -> Option<&[Certificate]> {
+        match self.peer_certificates.as_slice() {
+            [] => None,
+            chain => Some(chain),
+        }
+    }
+
+    /// Information about an established TLS session.
+    fn established(conn: &CommonState, sni: Option<&str>, resumed: bool) -> Self {
+        let peer_certificates = conn.peer_certificates().unwrap_or_default().to_vec();
+        let peer_subject = peer_certificates.first().and_then(|cert| x509::subject(&cert.0));
+        let peer_subject_alt_names =
+            peer_certificates.first().map(|cert| x509::subject_alt_names(&cert.0)).unwrap_or_default();
+        Self {
+            version: conn.protocol_version(),
+            cipher_suite: conn.negotiated_cipher_suite().map(|suite| suite.suite()),
+            sni: sni.map(|sni| sni.to_string()),
+            peer_certificates,
+            peer_subject,
+            peer_subject_alt_names,
+            alpn: conn.alpn_protocol().map(|alpn| alpn.to_vec()),
+            resumed,
+        }
+    }
+}
+
+impl fmt::Display for TlsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.version {
+            Some(version) => write!(f, "{version:?}")?,
+            None => write!(f, "TLS")?,
+        }
+        if let Some(cipher_suite) = self.cipher_suite {
+            write!(f, " {cipher_suite:?}")?;
+        }
+        if let Some(subject) = &self.peer_subject {
+            write!(f, " {subject}")?;
+        }
+        if let Some(sni) = &self.sni {
+            write!(f, " SNI {sni}")?;
+        }
+        if self.resumed {
+            write!(f, " (resumed)")?;
+        }
+        Ok(())
+    }
+}
+
+impl dyn LinkTag {
+    /// TLS information of the link, if it has been wrapped by [`TlsClient`] or [`TlsServer`].
+    pub fn tls_info(&self) -> Option<&TlsInfo> {
+        self.wrapper_info(NAME)?.downcast_ref()
+    }
+}
+
+impl LinkError<LinkTagBox> {
+    /// TLS information of the link.
+    ///
+    /// If the TLS handshake failed, this contains the parameters negotiated before the failure.
+    pub fn tls_info(&self) -> Option<&TlsInfo> {
+        self.tag.tls_info().or_else(|| {
+            let err = self.error.get_ref()?.downcast_ref::<HandshakeError>()?;
+            Some(&err.info)
+        })
+    }
+
+    /// TLS protocol error that caused the TLS handshake of the link to fail.
+    ///
+    /// On the accepting side, a client that presented no certificate although
+    /// [required](ClientAuth::Mandatory) is reported as [`NoCertificatesPresented`](rustls::Error::NoCertificatesPresented)
+    /// and a client certificate that failed verification as one of the `InvalidCertificate` variants.
+    pub fn tls_error(&self) -> Option<&rustls::Error> {
+        let err = self.error.get_ref()?.downcast_ref::<HandshakeError>()?;
+        err.error.get_ref()?.downcast_ref()
+    }
+}
+
+/// Link tag of a link wrapped in TLS.
+///
+/// It compares equal to and behaves like the link tag of the underlying transport.
+#[derive(Debug, Clone)]
+struct TlsLinkTag {
+    inner: LinkTagBox,
+    info: TlsInfo,
+}
+
+impl fmt::Display for TlsLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} [{}]", &self.inner, &self.info)
+    }
+}
+
+impl LinkTag for TlsLinkTag {
+    fn transport_name(&self) -> &str {
+        self.inner.transport_name()
+    }
+
+    fn direction(&self) -> Direction {
+        self.inner.direction()
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        self.inner.user_data()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self.inner.as_any()
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        self.inner.dyn_cmp(other)
+    }
+
+    fn dyn_hash(&self, state: &mut dyn Hasher) {
+        self.inner.dyn_hash(state)
+    }
+
+    fn wrapper_info(&self, wrapper: &str) -> Option<&dyn Any> {
+        if wrapper == NAME {
+            Some(&self.info)
+        } else {
+            self.inner.wrapper_info(wrapper)
+        }
+    }
+
+    fn metadata(&self) -> Option<&LinkMetadata> {
+        self.inner.metadata()
+    }
+}
+
+/// Converts a TLS configuration error into an IO error.
+fn config_error(err: rustls::Error) -> Error {
+    Error::new(ErrorKind::InvalidInput, err)
+}
+
+/// TLS handshake error with the parameters negotiated before the failure.
+#[derive(Debug)]
+struct HandshakeError {
+    info: TlsInfo,
+    error: Error,
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TLS handshake failed: {}", &self.error)
+    }
+}
+
+impl std::error::Error for HandshakeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Converts a failed TLS handshake into an IO error.
+fn handshake_error(error: Error, tap: &HelloTap, client: bool) -> Error {
+    Error::new(error.kind(), HandshakeError { info: tap.info(client), error })
+}
+
+/// The public key of the certificate presented by the server does not match
+/// any [pinned hash](TlsClient::set_pinned_spki).
+///
+/// This is returned as the inner error of an [`std::io::Error`] of kind
+/// [`PermissionDenied`](ErrorKind::PermissionDenied).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinMismatchError {
+    /// SHA-256 hash of the DER-encoded SubjectPublicKeyInfo of the presented certificate.
+    pub spki_sha256: [u8; 32],
+}
+
+impl fmt::Display for PinMismatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "server public key with SHA-256 ")?;
+        for b in &self.spki_sha256 {
+            write!(f, "{b:02x}")?;
+        }
+        write!(f, " does not match any pin")
+    }
+}
+
+impl std::error::Error for PinMismatchError {}
+
+impl From<PinMismatchError> for Error {
+    fn from(err: PinMismatchError) -> Self {
+        Error::new(ErrorKind::PermissionDenied, err)
+    }
+}
+
+/// Accepts every server certificate, leaving verification to the certificate pins.
+struct PinnedServerCert;
+
+impl ServerCertVerifier for PinnedServerCert {
+    fn verify_server_cert(
+        &self, _end_entity: &Certificate, _intermediates: &[Certificate], _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>, _ocsp_response: &[u8], _now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Checks the certificate presented by the server against the pinned public key hashes.
+fn check_pins(pins: &[[u8; 32]], info: &TlsInfo) -> Result<()> {
+    let spki = info
+        .peer_certificates
+        .first()
+        .and_then(|cert| x509::subject_public_key_info(&cert.0))
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "cannot parse public key of server certificate"))?;
+    let spki_sha256: [u8; 32] = ring::digest::digest(&ring::digest::SHA256, spki).as_ref().try_into().unwrap();
+
+    if pins.contains(&spki_sha256) {
+        Ok(())
+    } else {
+        Err(PinMismatchError { spki_sha256 }.into())
+    }
+}
+
+/// Function choosing the server name of a link based on its tag.
+type ServerNameSelectorFn = Arc<dyn Fn(&dyn LinkTag) -> Option<ServerName> + Send + Sync>;
+
 /// TLS outgoing connection wrapper.
 ///
 /// Pass this to [`Connector::wrapped`](super::Connector::wrapped) to apply TLS
 /// encryption to each outgoing link.
-#[derive(Debug)]
+#[derive(Clone)]
 #[must_use = "you must pass this wrapper to the connector"]
 pub struct TlsClient {
     server_name: ServerName,
+    server_name_selector: Option<ServerNameSelectorFn>,
     client_cfg: Arc<ClientConfig>,
+    pinned_spki: Arc<Mutex<Vec<[u8; 32]>>>,
+    pin_verify_chain: bool,
+}
+
+impl fmt::Debug for TlsClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TlsClient")
+            .field("server_name", &self.server_name)
+            .field("server_name_selector", &self.server_name_selector.is_some())
+            .field("client_cfg", &self.client_cfg)
+            .field("pinned_spki", &self.pinned_spki)
+            .field("pin_verify_chain", &self.pin_verify_chain)
+            .finish()
+    }
 }
 
 impl TlsClient {
     /// Creates a new TLS outgoing connection wrapper.
     ///
-    /// The identity of the server is verified using TLS against `server_name`.
+    /// The identity of the server is verified using TLS against `server_name`,
+    /// which is also sent as server name indication (SNI).
+    /// It does not need to match the host the underlying links are established to.
     /// The outgoing link is encrypted using TLS with the configuration specified
     /// in `client_cfg`.
+    ///
+    /// The configuration is used as provided, including its certificate verifier,
+    /// cipher suites, session storage and key log.
+    /// This wrapper only overrides the ALPN protocols, if [`set_alpn`](Self::set_alpn) is called,
+    /// the session storage, if [`set_resumption`](Self::set_resumption) is called,
+    /// and the certificate verifier, if [certificate pins](Self::set_pinned_spki) are set
+    /// without [verifying the chain](Self::set_pin_verify_chain).
+    ///
+    /// Use [`with_client_auth`](Self::with_client_auth) for a configuration with
+    /// safe defaults.
     pub fn new(client_cfg: Arc<ClientConfig>, server_name: ServerName) -> Self {
-        Self { server_name, client_cfg }
+        Self {
+            server_name,
+            server_name_selector: None,
+            client_cfg,
+            pinned_spki: Default::default(),
+            pin_verify_chain: false,
+        }
+    }
+
+    /// Creates a new TLS outgoing connection wrapper that authenticates using a client certificate.
+    ///
+    /// The identity of the server is verified against `server_name` using the
+    /// root certificates in `server_roots`.
+    /// The client authenticates itself using the certificate chain `cert_chain`,
+    /// starting with its own certificate, and the corresponding private key `key`.
+    pub fn with_client_auth(
+        server_roots: RootCertStore, server_name: ServerName, cert_chain: Vec<Certificate>, key: PrivateKey,
+    ) -> Result<Self> {
+        let client_cfg = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(server_roots)
+            .with_single_cert(cert_chain, key)
+            .map_err(config_error)?;
+        Ok(Self::new(Arc::new(client_cfg), server_name))
+    }
+
+    /// Sets the name the identity of the server is verified against and that is sent as
+    /// server name indication (SNI).
+    ///
+    /// This is used for all links for which the [server name selector](Self::set_server_name_selector)
+    /// does not choose a name.
+    pub fn set_server_name(&mut self, server_name: ServerName) {
+        self.server_name = server_name;
+    }
+
+    /// The default name the identity of the server is verified against.
+    pub fn server_name(&self) -> &ServerName {
+        &self.server_name
+    }
+
+    /// Sets a function choosing the server name of each link based on its tag.
+    ///
+    /// This allows reaching the server through several relays, each requiring a
+    /// different name for SNI and certificate verification, while the underlying link is
+    /// still established to the relay address.
+    /// For example, the tag can be downcast to the link tag of the TCP transport
+    /// to choose the name based on the remote address.
+    /// If the function returns `None`, the [default server name](Self::set_server_name) is used.
+    ///
+    /// The chosen name is available from the [TLS information](TlsInfo::sni) of the link tag
+    /// and displayed alongside the remote address of the link.
+    pub fn set_server_name_selector(
+        &mut self, server_name_selector: impl Fn(&dyn LinkTag) -> Option<ServerName> + Send + Sync + 'static,
+    ) {
+        self.server_name_selector = Some(Arc::new(server_name_selector));
+    }
+
+    /// The server name used for the link with the specified tag.
+    fn server_name_for(&self, tag: Option<&dyn LinkTag>) -> ServerName {
+        tag.zip(self.server_name_selector.as_ref())
+            .and_then(|(tag, selector)| selector(tag))
+            .unwrap_or_else(|| self.server_name.clone())
+    }
+
+    /// Sets the application protocols offered to the server using ALPN, in order of preference.
+    ///
+    /// The protocol selected by the server is available from the [TLS information](TlsInfo::alpn)
+    /// of the link tag.
+    /// By default the protocols of the client configuration are offered.
+    pub fn set_alpn(&mut self, protocols: Vec<Vec<u8>>) {
+        Arc::make_mut(&mut self.client_cfg).alpn_protocols = protocols;
+    }
+
+    /// Sets whether links resume TLS sessions of previously established links.
+    ///
+    /// When enabled, sessions are stored in a cache shared by all links established
+    /// using this wrapper and its clones, so that a reestablished link can resume
+    /// a session instead of performing a full handshake.
+    /// When disabled, sessions are neither stored nor resumed, which may be required
+    /// by compliance policies.
+    ///
+    /// This replaces the session storage of the client configuration.
+    /// By default the session storage of the client configuration is used, which
+    /// enables resumption for configurations with default settings.
+    pub fn set_resumption(&mut self, resumption: bool) {
+        let client_cfg = Arc::make_mut(&mut self.client_cfg);
+        client_cfg.session_storage = match resumption {
+            true => ClientSessionMemoryCache::new(SESSION_CACHE_SIZE),
+            false => Arc::new(NoClientSessionStorage {}),
+        };
+        client_cfg.enable_tickets = resumption;
+    }
+
+    /// Pins the server certificate to the specified SHA-256 hashes of its
+    /// DER-encoded SubjectPublicKeyInfo.
+    ///
+    /// When pins are set, a link is only established if the public key of the certificate
+    /// presented by the server matches one of them; otherwise it fails with a
+    /// [`PinMismatchError`] containing the hash of the presented public key.
+    /// The certificate chain and server name are then not verified, unless enabled using
+    /// [`set_pin_verify_chain`](Self::set_pin_verify_chain).
+    /// An empty list disables pinning.
+    ///
+    /// The pins are shared with all clones of this wrapper.
+    /// Thus they can be changed using a clone while the wrapper is in use by a connector,
+    /// for example to rotate certificates, and apply to all links established afterwards.
+    pub fn set_pinned_spki(&self, pins: Vec<[u8; 32]>) {
+        *self.pinned_spki.lock().unwrap() = pins;
+    }
+
+    /// The pinned SHA-256 hashes of the SubjectPublicKeyInfo of the server certificate.
+    pub fn pinned_spki(&self) -> Vec<[u8; 32]> {
+        self.pinned_spki.lock().unwrap().clone()
+    }
+
+    /// Sets whether the certificate chain and server name are verified in addition
+    /// to the [certificate pins](Self::set_pinned_spki).
+    ///
+    /// If enabled, the certificate must pass the verification configured in the client
+    /// configuration and match a pin.
+    /// The default is disabled.
+    pub fn set_pin_verify_chain(&mut self, pin_verify_chain: bool) {
+        self.pin_verify_chain = pin_verify_chain;
+    }
+
+    /// Performs the TLS handshake.
+    async fn connect(
+        &self, io: IoBox, tag: Option<&dyn LinkTag>,
+    ) -> Result<(client::TlsStream<HelloTap>, TlsInfo)> {
+        let pins = self.pinned_spki();
+        let client_cfg = if pins.is_empty() || self.pin_verify_chain {
+            self.client_cfg.clone()
+        } else {
+            let mut client_cfg = (*self.client_cfg).clone();
+            client_cfg.dangerous().set_certificate_verifier(Arc::new(PinnedServerCert));
+            Arc::new(client_cfg)
+        };
+
+        let server_name = self.server_name_for(tag);
+
+        let connector = TlsConnector::from(client_cfg);
+        match connector.connect(server_name.clone(), HelloTap::new(io)).into_fallible().await {
+            Ok(tls) => {
+                let sni = match &server_name {
+                    ServerName::DnsName(name) => Some(name.as_ref()),
+                    _ => None,
+                };
+                let (tap, conn) = tls.get_ref();
+                let info = TlsInfo::established(conn, sni, tap.resumed(true));
+                if !pins.is_empty() {
+                    check_pins(&pins, &info)?;
+                }
+                Ok((tls, info))
+            }
+            Err((err, tap)) => Err(handshake_error(err, &tap, true)),
+        }
     }
 }
 
@@ -39,18 +555,98 @@ impl ConnectingWrapper for TlsClient {
     }
 
     async fn wrap(&self, io: IoBox) -> Result<IoBox> {
-        let connector = TlsConnector::from(self.client_cfg.clone());
-        let tls = connector.connect(self.server_name.clone(), io).await?;
+        let (tls, _info) = self.connect(io, None).await?;
         let (rh, wh) = split(tls);
         Ok(IoBox::new(rh, wh))
     }
+
+    async fn wrap_tagged(&self, io: IoBox, tag: LinkTagBox) -> Result<(IoBox, LinkTagBox)> {
+        let (tls, info) = self.connect(io, Some(&*tag)).await?;
+        let (rh, wh) = split(tls);
+        Ok((IoBox::new(rh, wh), Box::new(TlsLinkTag { inner: tag, info })))
+    }
 }
 
-/// TLS incoming connection wrapper.
+/// Client certificate authentication mode of a [`TlsServer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClientAuth {
+    /// Clients may authenticate using a certificate.
+    ///
+    /// Presented certificates are verified and links from clients presenting
+    /// an invalid certificate are rejected.
+    Optional,
+    /// Clients must authenticate using a valid certificate.
+    ///
+    /// Links from unauthenticated clients are rejected.
+    Mandatory,
+}
+
+/// Server certificate resolver that can be overridden by a reloaded certificate.
+struct ReloadableCert {
+    configured: Arc<dyn ResolvesServerCert>,
+    reloaded: Mutex<Option<Arc<CertifiedKey>>>,
+}
+
+impl fmt::Debug for ReloadableCert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReloadableCert").field("reloaded", &self.reloaded.lock().unwrap().is_some()).finish()
+    }
+}
+
+impl ResolvesServerCert for ReloadableCert {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        match &*self.reloaded.lock().unwrap() {
+            Some(cert) => Some(cert.clone()),
+            None => self.configured.resolve(client_hello),
+        }
+    }
+}
+
+/// Ticket producer that never issues tickets.
 #[derive(Debug)]
+struct NoTickets;
+
+impl ProducesTickets for NoTickets {
+    fn enabled(&self) -> bool {
+        false
+    }
+
+    fn lifetime(&self) -> u32 {
+        0
+    }
+
+    fn encrypt(&self, _plain: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn decrypt(&self, _cipher: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+/// Enables or disables session resumption in a server configuration.
+fn set_server_resumption(server_cfg: &mut Arc<ServerConfig>, resumption: bool) {
+    let server_cfg = Arc::make_mut(server_cfg);
+    match resumption {
+        true => server_cfg.session_storage = ServerSessionMemoryCache::new(SESSION_CACHE_SIZE),
+        false => {
+            server_cfg.session_storage = Arc::new(NoServerSessionStorage {});
+            server_cfg.ticketer = Arc::new(NoTickets);
+        }
+    }
+}
+
+/// TLS incoming connection wrapper.
+#[derive(Debug, Clone)]
 #[must_use = "you must pass this wrapper to the acceptor"]
 pub struct TlsServer {
     server_cfg: Arc<ServerConfig>,
+    cert: Arc<ReloadableCert>,
+    server_names: HashMap<String, Arc<ServerConfig>>,
+    reject_unknown_server_name: bool,
+    alpn: Option<Vec<Vec<u8>>>,
+    resumption: Option<bool>,
+    alpn_required: bool,
 }
 
 impl TlsServer {
@@ -58,8 +654,217 @@ impl TlsServer {
     ///
     /// Incoming links are encrypted using TLS with the configuration specified
     /// in `server_cfg`.
-    pub fn new(server_cfg: Arc<ServerConfig>) -> Self {
-        Self { server_cfg }
+    ///
+    /// The configuration is used as provided, including its client certificate verifier,
+    /// cipher suites, session storage and key log.
+    /// This wrapper only overrides the ALPN protocols, if [`set_alpn`](Self::set_alpn) is called,
+    /// the session storage, if [`set_resumption`](Self::set_resumption) is called,
+    /// and the certificate, if it is [reloaded](Self::reload).
+    ///
+    /// Use [`with_client_auth`](Self::with_client_auth) for a configuration with
+    /// safe defaults.
+    pub fn new(mut server_cfg: Arc<ServerConfig>) -> Self {
+        let cert =
+            Arc::new(ReloadableCert { configured: server_cfg.cert_resolver.clone(), reloaded: Mutex::new(None) });
+        Arc::make_mut(&mut server_cfg).cert_resolver = cert.clone();
+        Self {
+            server_cfg,
+            cert,
+            server_names: HashMap::new(),
+            reject_unknown_server_name: false,
+            alpn: None,
+            resumption: None,
+            alpn_required: false,
+        }
+    }
+
+    /// Creates a new TLS incoming connection wrapper that verifies client certificates.
+    ///
+    /// The server authenticates itself using the certificate chain `cert_chain`,
+    /// starting with its own certificate, and the corresponding private key `key`.
+    /// Client certificates are verified using the root certificates in `client_roots`
+    /// and `client_auth` specifies whether clients must present a certificate.
+    ///
+    /// Rejected links are reported as link errors by the acceptor.
+    pub fn with_client_auth(
+        cert_chain: Vec<Certificate>, key: PrivateKey, client_roots: RootCertStore, client_auth: ClientAuth,
+    ) -> Result<Self> {
+        let verifier = match client_auth {
+            ClientAuth::Optional => AllowAnyAnonymousOrAuthenticatedClient::new(client_roots),
+            ClientAuth::Mandatory => AllowAnyAuthenticatedClient::new(client_roots),
+        };
+        let server_cfg = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(cert_chain, key)
+            .map_err(config_error)?;
+        Ok(Self::new(Arc::new(server_cfg)))
+    }
+
+    /// Sets the application protocols accepted from clients using ALPN, in order of preference.
+    ///
+    /// Links from clients offering none of these protocols are rejected with the
+    /// [TLS error](LinkError::tls_error) [`NoApplicationProtocol`](rustls::Error::NoApplicationProtocol).
+    /// Clients not using ALPN are accepted, unless [required](Self::set_alpn_required).
+    /// The negotiated protocol is available from the [TLS information](TlsInfo::alpn)
+    /// of the link tag.
+    /// By default the protocols of the server configuration are accepted.
+    ///
+    /// This applies to the configurations of all [server names](Self::add_server_name).
+    pub fn set_alpn(&mut self, protocols: Vec<Vec<u8>>) {
+        for server_cfg in iter::once(&mut self.server_cfg).chain(self.server_names.values_mut()) {
+            Arc::make_mut(server_cfg).alpn_protocols = protocols.clone();
+        }
+        self.alpn = Some(protocols);
+    }
+
+    /// Sets whether links from clients that do not negotiate an application protocol
+    /// using ALPN are rejected.
+    ///
+    /// This refuses clients that are not configured for the [accepted protocols](Self::set_alpn)
+    /// early.
+    /// The default is `false`.
+    pub fn set_alpn_required(&mut self, alpn_required: bool) {
+        self.alpn_required = alpn_required;
+    }
+
+    /// Sets whether clients may resume TLS sessions of previously established links.
+    ///
+    /// When enabled, sessions are kept in a cache, so that a reestablished link can resume
+    /// a session instead of performing a full handshake.
+    /// When disabled, sessions are neither stored nor resumed and no session tickets
+    /// are issued, which may be required by compliance policies.
+    ///
+    /// This replaces the session storage of the server configuration.
+    /// By default the session storage and ticket producer of the server configuration are used,
+    /// which enables resumption for configurations with default settings.
+    ///
+    /// This applies to the configurations of all [server names](Self::add_server_name).
+    pub fn set_resumption(&mut self, resumption: bool) {
+        for server_cfg in iter::once(&mut self.server_cfg).chain(self.server_names.values_mut()) {
+            set_server_resumption(server_cfg, resumption);
+        }
+        self.resumption = Some(resumption);
+    }
+
+    /// Uses the specified configuration for links from clients requesting the specified
+    /// server name using server name indication (SNI).
+    ///
+    /// This allows terminating multiple services, each with its own certificate,
+    /// using one acceptor.
+    /// Server names are compared case-insensitively.
+    /// Links from clients requesting another server name or not using SNI use the
+    /// configuration passed to [`new`](Self::new), unless
+    /// [rejected](Self::set_reject_unknown_server_name).
+    ///
+    /// The requested server name is available from the [TLS information](TlsInfo::sni)
+    /// of the link tag.
+    /// To aggregate links for different server names into separate connections, return
+    /// a group key derived from it in the [link authorizer](super::AcceptorBuilder::set_link_authorizer).
+    pub fn add_server_name(&mut self, server_name: &str, mut server_cfg: Arc<ServerConfig>) {
+        if let Some(alpn) = &self.alpn {
+            Arc::make_mut(&mut server_cfg).alpn_protocols = alpn.clone();
+        }
+        if let Some(resumption) = self.resumption {
+            set_server_resumption(&mut server_cfg, resumption);
+        }
+        self.server_names.insert(server_name.to_ascii_lowercase(), server_cfg);
+    }
+
+    /// Sets whether links from clients requesting a server name that has not been
+    /// [added](Self::add_server_name) or not using SNI are rejected.
+    ///
+    /// Rejected links fail before the handshake is performed with an error of kind
+    /// [`PermissionDenied`](ErrorKind::PermissionDenied).
+    /// The requested server name is available from the [TLS information](LinkError::tls_info)
+    /// of the reported link error.
+    /// The default is `false`.
+    pub fn set_reject_unknown_server_name(&mut self, reject_unknown_server_name: bool) {
+        self.reject_unknown_server_name = reject_unknown_server_name;
+    }
+
+    /// Replaces the certificate presented to clients.
+    ///
+    /// The server authenticates itself using the certificate chain `cert_chain`,
+    /// starting with its own certificate, and the corresponding private key `key`.
+    /// This overrides the certificate of the configuration passed to [`new`](Self::new),
+    /// but not of the configurations of [server names](Self::add_server_name).
+    ///
+    /// Only the TLS handshakes of links established afterwards use the new certificate;
+    /// established links and thus connections are not affected.
+    /// The certificate is shared with all clones of this wrapper.
+    /// Thus it can be reloaded using a clone while the wrapper is in use by an acceptor,
+    /// for example when it has been renewed.
+    ///
+    /// If the certificate chain is empty or the private key is invalid or of an unsupported type,
+    /// an error is returned and the previous certificate stays in use.
+    pub fn reload(&self, cert_chain: Vec<Certificate>, key: PrivateKey) -> Result<()> {
+        let Some(cert) = cert_chain.first() else {
+            return Err(Error::new(ErrorKind::InvalidInput, "certificate chain is empty"));
+        };
+        if x509::subject_public_key_info(&cert.0).is_none() {
+            return Err(Error::new(ErrorKind::InvalidInput, "cannot parse server certificate"));
+        }
+        let key = sign::any_supported_type(&key)
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "invalid or unsupported private key"))?;
+
+        *self.cert.reloaded.lock().unwrap() = Some(Arc::new(CertifiedKey::new(cert_chain, key)));
+        Ok(())
+    }
+
+    /// Returns the configuration for the server name requested by the client.
+    fn server_cfg_for(&self, server_name: Option<&str>) -> Result<Arc<ServerConfig>> {
+        match server_name.and_then(|name| self.server_names.get(&name.to_ascii_lowercase())) {
+            Some(server_cfg) => Ok(server_cfg.clone()),
+            None if self.reject_unknown_server_name => {
+                let error = Error::new(
+                    ErrorKind::PermissionDenied,
+                    format!("unknown server name {}", server_name.unwrap_or("(none)")),
+                );
+                let info = TlsInfo {
+                    version: None,
+                    cipher_suite: None,
+                    sni: server_name.map(|name| name.to_string()),
+                    peer_certificates: Vec::new(),
+                    peer_subject: None,
+                    peer_subject_alt_names: Vec::new(),
+                    alpn: None,
+                    resumed: false,
+                };
+                Err(Error::new(ErrorKind::PermissionDenied, HandshakeError { info, error }))
+            }
+            None => Ok(self.server_cfg.clone()),
+        }
+    }
+
+    /// Performs the TLS handshake, choosing the configuration by the requested server name.
+    async fn handshake(
+        &self, tap: HelloTap,
+    ) -> Result<std::result::Result<server::TlsStream<HelloTap>, (Error, HelloTap)>> {
+        if self.server_names.is_empty() && !self.reject_unknown_server_name {
+            let acceptor = TlsAcceptor::from(self.server_cfg.clone());
+            return Ok(acceptor.accept(tap).into_fallible().await);
+        }
+
+        let acceptor = rustls::server::Acceptor::default();
+        let start = LazyConfigAcceptor::new(acceptor, tap).await?;
+        let server_cfg = self.server_cfg_for(start.client_hello().server_name())?;
+        Ok(start.into_stream(server_cfg).into_fallible().await)
+    }
+
+    /// Performs the TLS handshake.
+    async fn accept(&self, io: IoBox) -> Result<(server::TlsStream<HelloTap>, TlsInfo)> {
+        match self.handshake(HelloTap::new(io)).await? {
+            Ok(tls) => {
+                let (tap, conn) = tls.get_ref();
+                let info = TlsInfo::established(conn, conn.sni_hostname(), tap.resumed(false));
+                if self.alpn_required && info.alpn.is_none() {
+                    return Err(Error::new(ErrorKind::PermissionDenied, "no application protocol negotiated"));
+                }
+                Ok((tls, info))
+            }
+            Err((err, tap)) => Err(handshake_error(err, &tap, false)),
+        }
     }
 }
 
@@ -70,9 +875,252 @@ impl AcceptingWrapper for TlsServer {
     }
 
     async fn wrap(&self, io: IoBox) -> Result<IoBox> {
-        let acceptor = TlsAcceptor::from(self.server_cfg.clone());
-        let tls = acceptor.accept(io).await?;
+        let (tls, _info) = self.accept(io).await?;
         let (rh, wh) = split(tls);
         Ok(IoBox::new(rh, wh))
     }
+
+    async fn wrap_tagged(&self, io: IoBox, tag: LinkTagBox) -> Result<(IoBox, LinkTagBox)> {
+        let (tls, info) = self.accept(io).await?;
+        let (rh, wh) = split(tls);
+        Ok((IoBox::new(rh, wh), Box::new(TlsLinkTag { inner: tag, info })))
+    }
+}
+
+/// Content type of TLS handshake records.
+const RECORD_HANDSHAKE: u8 = 0x16;
+/// Length of a TLS record header.
+const RECORD_HEADER_LEN: usize = 5;
+
+const HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+const HANDSHAKE_SERVER_HELLO: u8 = 0x02;
+
+const EXTENSION_SERVER_NAME: u16 = 0x0000;
+const EXTENSION_SUPPORTED_VERSIONS: u16 = 0x002b;
+const EXTENSION_PRE_SHARED_KEY: u16 = 0x0029;
+
+/// IO stream recording the first TLS record sent and received.
+///
+/// These contain the hello messages of the handshake, which are parsed
+/// to obtain the negotiated parameters if the handshake fails.
+struct HelloTap {
+    io: IoBox,
+    sent: Vec<u8>,
+    recved: Vec<u8>,
+}
+
+impl HelloTap {
+    fn new(io: IoBox) -> Self {
+        Self { io, sent: Vec::new(), recved: Vec::new() }
+    }
+
+    /// Appends data to a record buffer until it contains a complete record.
+    fn record(buf: &mut Vec<u8>, data: &[u8]) {
+        let needed = match buf.get(3..RECORD_HEADER_LEN) {
+            Some(len) => RECORD_HEADER_LEN + usize::from(u16::from_be_bytes([len[0], len[1]])),
+            None => RECORD_HEADER_LEN,
+        };
+        if buf.len() >= needed {
+            return;
+        }
+
+        let n = data.len().min(needed - buf.len());
+        buf.extend_from_slice(&data[..n]);
+        if n < data.len() {
+            Self::record(buf, &data[n..]);
+        }
+    }
+
+    /// The recorded records containing the ClientHello and ServerHello messages.
+    fn hellos(&self, client: bool) -> (&[u8], &[u8]) {
+        match client {
+            true => (&self.sent, &self.recved),
+            false => (&self.recved, &self.sent),
+        }
+    }
+
+    /// Whether the recorded hello messages indicate that a session was resumed.
+    fn resumed(&self, client: bool) -> bool {
+        let (client_hello, server_hello) = self.hellos(client);
+        handshake_message(client_hello, HANDSHAKE_CLIENT_HELLO)
+            .zip(handshake_message(server_hello, HANDSHAKE_SERVER_HELLO))
+            .and_then(|(client_hello, server_hello)| parse_resumed(client_hello, server_hello))
+            .unwrap_or_default()
+    }
+
+    /// Information about the TLS handshake obtained from the recorded hello messages.
+    fn info(&self, client: bool) -> TlsInfo {
+        let (client_hello, server_hello) = self.hellos(client);
+
+        let sni = handshake_message(client_hello, HANDSHAKE_CLIENT_HELLO).and_then(parse_client_hello);
+        let (version, cipher_suite) = handshake_message(server_hello, HANDSHAKE_SERVER_HELLO)
+            .and_then(parse_server_hello)
+            .map(|(version, cipher_suite)| (Some(version), Some(cipher_suite)))
+            .unwrap_or_default();
+
+        TlsInfo {
+            version,
+            cipher_suite,
+            sni,
+            peer_certificates: Vec::new(),
+            peer_subject: None,
+            peer_subject_alt_names: Vec::new(),
+            alpn: None,
+            resumed: self.resumed(client),
+        }
+    }
+}
+
+impl AsyncRead for HelloTap {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let res = Pin::new(&mut this.io).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = &res {
+            Self::record(&mut this.recved, &buf.filled()[before..]);
+        }
+        res
+    }
+}
+
+impl AsyncWrite for HelloTap {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        let res = Pin::new(&mut this.io).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = &res {
+            Self::record(&mut this.sent, &buf[..*n]);
+        }
+        res
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+}
+
+/// Reader for TLS handshake messages.
+#[derive(Clone)]
+struct MsgReader<'a>(&'a [u8]);
+
+impl<'a> MsgReader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (data, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(data)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let data = self.take(2)?;
+        Some(u16::from_be_bytes([data[0], data[1]]))
+    }
+
+    fn vec8(&mut self) -> Option<&'a [u8]> {
+        let len = self.u8()?;
+        self.take(len.into())
+    }
+
+    fn vec16(&mut self) -> Option<&'a [u8]> {
+        let len = self.u16()?;
+        self.take(len.into())
+    }
+
+    /// Reads extensions and returns the data of the extension with the specified type.
+    fn extension(&mut self, ext_type: u16) -> Option<&'a [u8]> {
+        let mut exts = MsgReader(self.vec16()?);
+        while !exts.0.is_empty() {
+            let typ = exts.u16()?;
+            let data = exts.vec16()?;
+            if typ == ext_type {
+                return Some(data);
+            }
+        }
+        None
+    }
+}
+
+/// Extracts the handshake message of the specified type from a recorded TLS record.
+fn handshake_message(record: &[u8], msg_type: u8) -> Option<&[u8]> {
+    let mut record = MsgReader(record);
+    if record.u8()? != RECORD_HANDSHAKE {
+        return None;
+    }
+    record.take(2)?;
+    let mut msg = MsgReader(record.vec16()?);
+
+    if msg.u8()? != msg_type {
+        return None;
+    }
+    let len = msg.take(3)?;
+    msg.take(usize::from(len[0]) << 16 | usize::from(len[1]) << 8 | usize::from(len[2]))
+}
+
+/// Parses the server name indication from a ClientHello message.
+fn parse_client_hello(msg: &[u8]) -> Option<String> {
+    let mut msg = MsgReader(msg);
+    msg.take(2 + 32)?;
+    msg.vec8()?;
+    msg.vec16()?;
+    msg.vec8()?;
+
+    let mut names = MsgReader(msg.extension(EXTENSION_SERVER_NAME)?);
+    let mut names = MsgReader(names.vec16()?);
+    while !names.0.is_empty() {
+        let name_type = names.u8()?;
+        let name = names.vec16()?;
+        if name_type == 0 {
+            return String::from_utf8(name.to_vec()).ok();
+        }
+    }
+    None
+}
+
+/// Parses the protocol version and cipher suite from a ServerHello message.
+fn parse_server_hello(msg: &[u8]) -> Option<(ProtocolVersion, CipherSuite)> {
+    let mut msg = MsgReader(msg);
+    let legacy_version = msg.u16()?;
+    msg.take(32)?;
+    msg.vec8()?;
+    let cipher_suite = msg.u16()?;
+    msg.u8()?;
+
+    let version = match msg.extension(EXTENSION_SUPPORTED_VERSIONS) {
+        Some(data) => MsgReader(data).u16()?,
+        None => legacy_version,
+    };
+
+    Some((version.into(), cipher_suite.into()))
+}
+
+/// Parses whether the server resumed a session from the ClientHello and ServerHello messages.
+///
+/// In TLS 1.3 the server accepts a pre-shared key for resumption, while in TLS 1.2
+/// it echoes the session id sent by the client.
+fn parse_resumed(client_hello: &[u8], server_hello: &[u8]) -> Option<bool> {
+    let mut client_hello = MsgReader(client_hello);
+    client_hello.take(2 + 32)?;
+    let client_session_id = client_hello.vec8()?;
+
+    let mut msg = MsgReader(server_hello);
+    msg.take(2 + 32)?;
+    let session_id = msg.vec8()?;
+    msg.take(2 + 1)?;
+
+    if msg.clone().extension(EXTENSION_PRE_SHARED_KEY).is_some() {
+        return Some(true);
+    }
+    if msg.extension(EXTENSION_SUPPORTED_VERSIONS).is_some() {
+        return Some(false);
+    }
+    Some(!session_id.is_empty() && session_id == client_session_id)
 }
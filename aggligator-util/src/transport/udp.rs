@@ -0,0 +1,1127 @@
+//! UDP transport.
+//!
+//! Since UDP provides neither reliable nor ordered delivery, each link runs a
+//! lightweight framing protocol on top of the datagram flow.
+//! Data is split into segments that fit into one datagram, sequenced
+//! by byte offset and acknowledged cumulatively by the receiver.
+//! Unacknowledged segments are retransmitted after a timeout that is derived
+//! from the measured round-trip time or when duplicate acknowledgements
+//! indicate a loss. The amount of data in flight is limited by a congestion
+//! window that is managed similar to TCP.
+//!
+//! This provides just enough reliability for an Aggligator link; aggregation,
+//! flow control and failover are handled by Aggligator itself.
+//! Aggligator expects each link to deliver its messages completely and in order
+//! and treats a gap as failure of the link, thus lost datagrams must be recovered
+//! by the transport.
+//!
+//! A link is dropped when sent data remains unacknowledged for longer than the
+//! configured acknowledgement timeout or when nothing has been received from the
+//! remote endpoint for the configured idle timeout.
+//!
+//! Each datagram carries at most the configured maximum datagram size, thus
+//! data is always fragmented at segment boundaries determined by the sender.
+//! Received datagrams exceeding the configured maximum datagram size are discarded.
+//! If datagrams of the configured size cannot be sent or are repeatedly lost, the
+//! segment size is reduced to the minimum datagram size that every IPv4 host
+//! must be able to receive.
+
+use async_trait::async_trait;
+use futures::{future, FutureExt};
+use socket2::SockRef;
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::{hash_map::RandomState, HashMap, HashSet, VecDeque},
+    fmt,
+    hash::{BuildHasher, Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::{duplex, split, AsyncReadExt, AsyncWriteExt, DuplexStream},
+    net::UdpSocket,
+    sync::{mpsc, watch},
+    time::{sleep, sleep_until, timeout, Instant},
+};
+
+use super::{
+    ip::{
+        hosts_with_default_ports, interface_name_for_addr, local_addrs_for_target, resolve_hosts, use_proper_ipv4,
+    },
+    sockopt::apply_tos,
+    AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox,
+};
+use aggligator::{control::Direction, Link};
+
+pub use super::ip::IpVersion;
+
+static NAME: &str = "udp";
+
+/// Minimum datagram size.
+///
+/// This is the maximum UDP payload size that is guaranteed to be deliverable over IPv4.
+pub const MIN_DATAGRAM_SIZE: usize = 508;
+
+/// Maximum datagram size.
+pub const MAX_DATAGRAM_SIZE: usize = 65507;
+
+/// Size of the framing header.
+const HEADER_SIZE: usize = 21;
+
+/// Initial retransmission timeout.
+const INITIAL_RTO: Duration = Duration::from_millis(250);
+
+/// Minimum retransmission timeout.
+const MIN_RTO: Duration = Duration::from_millis(50);
+
+/// Initial congestion window in segments.
+const INITIAL_CWND_SEGMENTS: usize = 10;
+
+/// Number of duplicate acknowledgements that trigger a retransmission.
+const DUP_ACKS_FAST_RETRANSMIT: u32 = 3;
+
+/// Number of consecutive retransmission timeouts after which the segment size
+/// is reduced to the minimum.
+const MTU_FALLBACK_TIMEOUTS: u32 = 3;
+
+/// Number of received datagrams that are queued for a link.
+const QUEUE_LEN: usize = 1024;
+
+/// Default maximum number of incoming links of an acceptor.
+const DEFAULT_MAX_LINKS: usize = 256;
+
+/// Link tag for UDP link.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UdpLinkTag {
+    /// Local interface name.
+    pub interface: Vec<u8>,
+    /// Local address.
+    ///
+    /// For outgoing links the port is zero, since an ephemeral port
+    /// is chosen when connecting.
+    pub local: SocketAddr,
+    /// Remote address.
+    pub remote: SocketAddr,
+    /// Link direction.
+    pub direction: Direction,
+}
+
+impl fmt::Display for UdpLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        write!(f, "{:16} {dir} {}", String::from_utf8_lossy(&self.interface), self.remote)
+    }
+}
+
+impl UdpLinkTag {
+    /// Creates a new link tag for a UDP link.
+    pub fn new(interface: &[u8], local: SocketAddr, remote: SocketAddr, direction: Direction) -> Self {
+        Self { interface: interface.to_vec(), local, remote, direction }
+    }
+}
+
+impl LinkTag for UdpLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        self.interface.clone()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// Configuration of the framing protocol.
+#[derive(Debug, Clone, Copy)]
+struct LinkCfg {
+    /// Maximum size of a datagram.
+    max_datagram_size: usize,
+    /// Time after which a link is dropped when sent data is not acknowledged.
+    ack_timeout: Duration,
+    /// Time after which a link is dropped when nothing is received from the remote endpoint.
+    idle_timeout: Duration,
+    /// Maximum number of buffered bytes per direction.
+    window: usize,
+}
+
+impl Default for LinkCfg {
+    fn default() -> Self {
+        Self {
+            max_datagram_size: 1200,
+            ack_timeout: Duration::from_secs(15),
+            idle_timeout: Duration::from_secs(60),
+            window: 1_048_576,
+        }
+    }
+}
+
+impl LinkCfg {
+    fn set_max_datagram_size(&mut self, max_datagram_size: usize) {
+        assert!(
+            (MIN_DATAGRAM_SIZE..=MAX_DATAGRAM_SIZE).contains(&max_datagram_size),
+            "maximum datagram size must be between {MIN_DATAGRAM_SIZE} and {MAX_DATAGRAM_SIZE}"
+        );
+        self.max_datagram_size = max_datagram_size;
+    }
+
+    fn set_ack_timeout(&mut self, ack_timeout: Duration) {
+        assert!(!ack_timeout.is_zero(), "acknowledgement timeout must not be zero");
+        self.ack_timeout = ack_timeout;
+    }
+
+    fn set_idle_timeout(&mut self, idle_timeout: Duration) {
+        assert!(!idle_timeout.is_zero(), "idle timeout must not be zero");
+        self.idle_timeout = idle_timeout;
+    }
+}
+
+/// Kind of framing packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// Opens a link.
+    Open,
+    /// Data segment.
+    Data,
+    /// Acknowledgement.
+    Ack,
+    /// Ends the sending direction of a link.
+    Fin,
+    /// Aborts a link.
+    Close,
+}
+
+impl Kind {
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            1 => Some(Self::Open),
+            2 => Some(Self::Data),
+            3 => Some(Self::Ack),
+            4 => Some(Self::Fin),
+            5 => Some(Self::Close),
+            _ => None,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::Open => 1,
+            Self::Data => 2,
+            Self::Ack => 3,
+            Self::Fin => 4,
+            Self::Close => 5,
+        }
+    }
+}
+
+/// Header of framing packet.
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    /// Packet kind.
+    kind: Kind,
+    /// Session id of link.
+    session: u32,
+    /// Offset of first data byte in packet.
+    seq: u64,
+    /// Offset of next data byte expected from remote endpoint.
+    ack: u64,
+}
+
+impl Header {
+    fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_SIZE + payload.len());
+        buf.push(self.kind.to_u8());
+        buf.extend_from_slice(&self.session.to_be_bytes());
+        buf.extend_from_slice(&self.seq.to_be_bytes());
+        buf.extend_from_slice(&self.ack.to_be_bytes());
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    fn decode(buf: &[u8]) -> Option<(Self, &[u8])> {
+        if buf.len() < HEADER_SIZE {
+            return None;
+        }
+
+        let kind = Kind::from_u8(buf[0])?;
+        let session = u32::from_be_bytes(buf[1..5].try_into().unwrap());
+        let seq = u64::from_be_bytes(buf[5..13].try_into().unwrap());
+        let ack = u64::from_be_bytes(buf[13..21].try_into().unwrap());
+
+        Some((Self { kind, session, seq, ack }, &buf[HEADER_SIZE..]))
+    }
+}
+
+/// Generates a random session id.
+fn random_session() -> u32 {
+    RandomState::new().build_hasher().finish() as u32
+}
+
+/// Source of datagrams received from the remote endpoint.
+enum Datagrams {
+    /// Socket used exclusively by this link.
+    Socket(Arc<UdpSocket>, Vec<u8>),
+    /// Datagrams demultiplexed by the acceptor.
+    Channel(mpsc::Receiver<Vec<u8>>),
+}
+
+impl Datagrams {
+    fn socket(socket: Arc<UdpSocket>) -> Self {
+        Self::Socket(socket, vec![0; MAX_DATAGRAM_SIZE])
+    }
+
+    async fn recv(&mut self, remote: SocketAddr) -> Result<Vec<u8>> {
+        match self {
+            Self::Socket(socket, buf) => loop {
+                match socket.recv_from(buf).await {
+                    Ok((n, from)) if from == remote => return Ok(buf[..n].to_vec()),
+                    Ok(_) => (),
+                    Err(err) if err.kind() == ErrorKind::ConnectionReset => (),
+                    Err(err) => return Err(err),
+                }
+            },
+            Self::Channel(rx) => {
+                rx.recv().await.ok_or_else(|| Error::new(ErrorKind::BrokenPipe, "UDP listener terminated"))
+            }
+        }
+    }
+}
+
+/// Framing protocol state of a link.
+struct Framing {
+    socket: Arc<UdpSocket>,
+    remote: SocketAddr,
+    session: u32,
+    direction: Direction,
+    cfg: LinkCfg,
+
+    /// Unacknowledged and unsent data.
+    send_buf: VecDeque<u8>,
+    /// Offset of first byte in send buffer.
+    send_base: u64,
+    /// Offset of next byte to send.
+    send_next: u64,
+    /// Highest offset that has been sent.
+    send_max: u64,
+    /// End offset and send time of each transmitted segment.
+    in_flight: VecDeque<(u64, Instant)>,
+    /// Data up to this offset has been retransmitted.
+    retransmitted: u64,
+    /// Current segment size.
+    segment_size: usize,
+    /// Congestion window in bytes.
+    cwnd: usize,
+    /// Slow start threshold in bytes.
+    ssthresh: usize,
+    /// Number of received duplicate acknowledgements.
+    dup_acks: u32,
+    /// Smoothed round-trip time.
+    srtt: Option<Duration>,
+    /// Retransmission timeout.
+    rto: Duration,
+    /// Consecutive retransmission timeouts.
+    timeouts: u32,
+    /// Time of last acknowledgement progress.
+    last_progress: Instant,
+    /// Time a datagram was last received from the remote endpoint.
+    last_recv: Instant,
+
+    /// Received data not yet passed to the reader.
+    recv_buf: VecDeque<u8>,
+    /// Offset of next byte expected from remote endpoint.
+    recv_next: u64,
+    /// Whether an acknowledgement must be sent.
+    ack_pending: bool,
+    /// Offset of the end of the sent stream, once it has been closed.
+    fin: Option<u64>,
+    /// Whether the remote endpoint has closed its sending direction.
+    remote_fin: bool,
+    /// Whether the reader has been dropped.
+    reader_gone: bool,
+}
+
+impl Framing {
+    fn new(socket: Arc<UdpSocket>, remote: SocketAddr, session: u32, direction: Direction, cfg: LinkCfg) -> Self {
+        Self {
+            socket,
+            remote,
+            session,
+            direction,
+            cfg,
+            send_buf: VecDeque::new(),
+            send_base: 0,
+            send_next: 0,
+            send_max: 0,
+            in_flight: VecDeque::new(),
+            retransmitted: 0,
+            segment_size: cfg.max_datagram_size,
+            cwnd: INITIAL_CWND_SEGMENTS * cfg.max_datagram_size,
+            ssthresh: usize::MAX,
+            dup_acks: 0,
+            srtt: None,
+            rto: INITIAL_RTO,
+            timeouts: 0,
+            last_progress: Instant::now(),
+            last_recv: Instant::now(),
+            recv_buf: VecDeque::new(),
+            recv_next: 0,
+            ack_pending: false,
+            fin: None,
+            remote_fin: false,
+            reader_gone: false,
+        }
+    }
+
+    fn header(&self, kind: Kind, seq: u64) -> Header {
+        Header { kind, session: self.session, seq, ack: self.recv_next }
+    }
+
+    /// Sends a packet without payload.
+    async fn send_control(&self, kind: Kind) {
+        let packet = self.header(kind, self.send_next).encode(&[]);
+        if let Err(err) = self.socket.send_to(&packet, self.remote).await {
+            tracing::debug!("sending UDP packet to {} failed: {err}", self.remote);
+        }
+    }
+
+    /// Transmits unsent data as far as the congestion window allows.
+    async fn transmit(&mut self) {
+        let data_end = self.send_base + self.send_buf.len() as u64;
+        let end = data_end + u64::from(self.fin.is_some());
+
+        while self.send_next < end && ((self.send_next - self.send_base) as usize) < self.cwnd {
+            if self.send_max == self.send_base {
+                self.last_progress = Instant::now();
+            }
+
+            // The end of stream occupies one offset after the data.
+            if self.send_next == data_end {
+                self.send_control(Kind::Fin).await;
+                self.send_next += 1;
+                self.send_max = self.send_max.max(self.send_next);
+                self.in_flight.push_back((self.send_next, Instant::now()));
+                continue;
+            }
+
+            let offset = (self.send_next - self.send_base) as usize;
+            let len = (self.segment_size - HEADER_SIZE).min((data_end - self.send_next) as usize);
+            let payload: Vec<u8> = self.send_buf.range(offset..offset + len).copied().collect();
+            let packet = self.header(Kind::Data, self.send_next).encode(&payload);
+
+            if let Err(err) = self.socket.send_to(&packet, self.remote).await {
+                if self.segment_size > MIN_DATAGRAM_SIZE {
+                    self.segment_size = (self.segment_size / 2).max(MIN_DATAGRAM_SIZE);
+                    tracing::warn!(
+                        "sending UDP datagram of {} bytes to {} failed: {err}, reducing datagram size to {}",
+                        packet.len(),
+                        self.remote,
+                        self.segment_size
+                    );
+                    continue;
+                }
+
+                // Segment is considered lost and will be retransmitted.
+                tracing::debug!("sending UDP datagram to {} failed: {err}", self.remote);
+            }
+
+            self.send_next += len as u64;
+            self.send_max = self.send_max.max(self.send_next);
+            self.in_flight.push_back((self.send_next, Instant::now()));
+            self.ack_pending = false;
+        }
+    }
+
+    /// Restarts transmission from the first unacknowledged byte.
+    fn go_back(&mut self) {
+        let flight = (self.send_max - self.send_base) as usize;
+        self.ssthresh = (flight / 2).max(2 * self.segment_size);
+
+        self.retransmitted = self.send_max;
+        self.send_next = self.send_base;
+        self.in_flight.clear();
+        self.dup_acks = 0;
+    }
+
+    /// Processes an acknowledgement from the remote endpoint.
+    fn process_ack(&mut self, ack: u64, duplicate_possible: bool) {
+        if ack > self.send_max {
+            return;
+        }
+
+        if ack <= self.send_base {
+            if duplicate_possible && ack == self.send_base && self.send_max > self.send_base {
+                self.dup_acks += 1;
+                if self.dup_acks == DUP_ACKS_FAST_RETRANSMIT {
+                    self.go_back();
+                    self.cwnd = self.ssthresh;
+                }
+            }
+            return;
+        }
+
+        let acked = (ack - self.send_base) as usize;
+        self.send_buf.drain(..acked.min(self.send_buf.len()));
+        self.send_base = ack;
+        self.send_next = self.send_next.max(ack);
+
+        let now = Instant::now();
+        let mut sample = None;
+        while let Some(&(end, sent)) = self.in_flight.front() {
+            if end > ack {
+                break;
+            }
+            if end > self.retransmitted {
+                sample = Some(now - sent);
+            }
+            self.in_flight.pop_front();
+        }
+
+        if let Some(sample) = sample {
+            let srtt = match self.srtt {
+                Some(srtt) => (srtt * 7 + sample) / 8,
+                None => sample,
+            };
+            self.srtt = Some(srtt);
+            self.rto = (srtt * 2).max(MIN_RTO);
+        }
+
+        if self.cwnd < self.ssthresh {
+            self.cwnd += acked;
+        } else {
+            self.cwnd += (self.segment_size * acked / self.cwnd).max(1);
+        }
+        self.cwnd = self.cwnd.min(self.cfg.window);
+
+        self.dup_acks = 0;
+        self.timeouts = 0;
+        self.last_progress = now;
+    }
+
+    /// Processes a received datagram.
+    ///
+    /// Returns `false` if the link has been aborted by the remote endpoint.
+    async fn process_datagram(&mut self, datagram: &[u8]) -> bool {
+        if datagram.len() > self.cfg.max_datagram_size {
+            tracing::trace!(
+                "discarding UDP datagram of {} bytes from {} exceeding maximum datagram size",
+                datagram.len(),
+                self.remote
+            );
+            return true;
+        }
+
+        let Some((hdr, payload)) = Header::decode(datagram) else { return true };
+        if hdr.session != self.session {
+            return true;
+        }
+        self.last_recv = Instant::now();
+
+        match hdr.kind {
+            Kind::Open => {
+                if self.direction == Direction::Incoming {
+                    self.send_control(Kind::Open).await;
+                }
+            }
+            Kind::Data => {
+                self.process_ack(hdr.ack, false);
+                if hdr.seq == self.recv_next
+                    && !self.remote_fin
+                    && !payload.is_empty()
+                    && self.recv_buf.len() + payload.len() <= self.cfg.window
+                {
+                    if !self.reader_gone {
+                        self.recv_buf.extend(payload);
+                    }
+                    self.recv_next += payload.len() as u64;
+                }
+                self.ack_pending = true;
+            }
+            Kind::Fin => {
+                self.process_ack(hdr.ack, false);
+                if hdr.seq == self.recv_next && !self.remote_fin {
+                    self.recv_next += 1;
+                    self.remote_fin = true;
+                }
+                self.ack_pending = true;
+            }
+            Kind::Ack => self.process_ack(hdr.ack, true),
+            Kind::Close => return false,
+        }
+
+        true
+    }
+
+    /// Handles expiry of the retransmission timer.
+    fn retransmit_timeout(&mut self) -> Result<()> {
+        if self.last_progress.elapsed() >= self.cfg.ack_timeout {
+            return Err(Error::new(ErrorKind::TimedOut, "no acknowledgement received from remote endpoint"));
+        }
+
+        self.timeouts += 1;
+        if self.timeouts >= MTU_FALLBACK_TIMEOUTS && self.segment_size > MIN_DATAGRAM_SIZE {
+            tracing::warn!(
+                "UDP datagrams to {} are not acknowledged, reducing datagram size to {MIN_DATAGRAM_SIZE}",
+                self.remote
+            );
+            self.segment_size = MIN_DATAGRAM_SIZE;
+        }
+
+        self.go_back();
+        self.cwnd = 2 * self.segment_size;
+        self.rto = (self.rto * 2).min(self.cfg.ack_timeout);
+
+        Ok(())
+    }
+
+    /// Runs the framing protocol, forwarding data between the stream and the remote endpoint.
+    async fn run(mut self, mut datagrams: Datagrams, stream: DuplexStream) -> Result<()> {
+        let (mut stream_rx, mut stream_tx) = split(stream);
+        let mut buf = vec![0; self.cfg.max_datagram_size];
+        let mut stream_tx_shutdown = false;
+
+        loop {
+            self.transmit().await;
+            if self.ack_pending {
+                self.send_control(Kind::Ack).await;
+                self.ack_pending = false;
+            }
+
+            // Signal end of stream to reader.
+            if self.remote_fin && self.recv_buf.is_empty() && !stream_tx_shutdown {
+                let _ = stream_tx.shutdown().await;
+                stream_tx_shutdown = true;
+            }
+
+            // Terminate when both directions have been closed.
+            let fin_acked = self.fin.map(|fin| self.send_base > fin).unwrap_or_default();
+            if fin_acked && ((self.remote_fin && self.recv_buf.is_empty()) || self.reader_gone) {
+                if !self.remote_fin {
+                    self.send_control(Kind::Close).await;
+                }
+                return Ok(());
+            }
+
+            let rto_timer = match self.in_flight.front() {
+                Some((_, sent)) => *sent + self.rto,
+                None => Instant::now() + self.cfg.ack_timeout,
+            };
+            let recv_slice = self.recv_buf.as_slices().0;
+
+            tokio::select! {
+                res = stream_rx.read(&mut buf), if self.fin.is_none() && self.send_buf.len() < self.cfg.window => {
+                    match res? {
+                        0 => self.fin = Some(self.send_base + self.send_buf.len() as u64),
+                        n => self.send_buf.extend(&buf[..n]),
+                    }
+                }
+                res = stream_tx.write(recv_slice), if !recv_slice.is_empty() => {
+                    match res {
+                        Ok(n) => {
+                            self.recv_buf.drain(..n);
+                        }
+                        Err(_) => {
+                            self.reader_gone = true;
+                            self.recv_buf.clear();
+                        }
+                    }
+                }
+                res = datagrams.recv(self.remote) => {
+                    if !self.process_datagram(&res?).await {
+                        let (a, b) = self.recv_buf.as_slices();
+                        let _ = stream_tx.write_all(a).await;
+                        let _ = stream_tx.write_all(b).await;
+                        return Err(Error::new(ErrorKind::ConnectionReset, "link closed by remote endpoint"));
+                    }
+                }
+                () = sleep_until(self.last_recv + self.cfg.idle_timeout) => {
+                    self.send_control(Kind::Close).await;
+                    return Err(Error::new(ErrorKind::TimedOut, "nothing received from remote endpoint"));
+                }
+                () = sleep_until(rto_timer) => {
+                    if !self.in_flight.is_empty() {
+                        if let Err(err) = self.retransmit_timeout() {
+                            self.send_control(Kind::Close).await;
+                            return Err(err);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Spawns the framing protocol task and returns the stream for the link.
+    fn spawn(self, datagrams: Datagrams) -> IoBox {
+        let (a, b) = duplex(self.cfg.window);
+        let remote = self.remote;
+
+        tokio::spawn(async move {
+            if let Err(err) = self.run(datagrams, b).await {
+                tracing::debug!("UDP link to {remote} failed: {err}");
+            }
+        });
+
+        let (rh, wh) = split(a);
+        IoBox::new(rh, wh)
+    }
+}
+
+/// UDP transport for outgoing connections.
+#[derive(Debug, Clone)]
+pub struct UdpConnector {
+    hosts: Vec<String>,
+    ip_version: IpVersion,
+    resolve_interval: Duration,
+    cfg: LinkCfg,
+    tos: Option<u8>,
+    traffic_class: Option<u8>,
+}
+
+impl fmt::Display for UdpConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.hosts.len() > 1 {
+            write!(f, "[{}]", self.hosts.join(", "))
+        } else {
+            write!(f, "{}", &self.hosts[0])
+        }
+    }
+}
+
+impl UdpConnector {
+    /// Create a new UDP transport for outgoing connections.
+    ///
+    /// `hosts` can contain IP addresses and hostnames, including port numbers.
+    /// If an entry does not specify a port number, the `default_port` is used.
+    ///
+    /// It is checked at creation that `hosts` resolves to at least one IP address.
+    ///
+    /// Host name resolution is retried periodically, thus DNS updates will be taken
+    /// into account without the need to recreate this transport.
+    pub async fn new(hosts: impl IntoIterator<Item = String>, default_port: u16) -> Result<Self> {
+        let hosts = hosts_with_default_ports(hosts, &[default_port])?;
+        let this = Self {
+            hosts,
+            ip_version: IpVersion::Both,
+            resolve_interval: Duration::from_secs(10),
+            cfg: LinkCfg::default(),
+            tos: None,
+            traffic_class: None,
+        };
+
+        let addrs = resolve_hosts(&this.hosts, this.ip_version).await;
+        if addrs.is_empty() {
+            return Err(Error::new(ErrorKind::NotFound, "cannot resolve IP address of host"));
+        }
+        tracing::info!("{} resolves to: {:?}", &this, addrs);
+
+        Ok(this)
+    }
+
+    /// Sets the IP version used for connecting.
+    pub fn set_ip_version(&mut self, ip_version: IpVersion) {
+        self.ip_version = ip_version;
+    }
+
+    /// Sets the interval for re-resolving the hostname and checking for changed network interfaces.
+    pub fn set_resolve_interval(&mut self, resolve_interval: Duration) {
+        self.resolve_interval = resolve_interval;
+    }
+
+    /// Sets the maximum size of a datagram, including the framing header.
+    ///
+    /// Larger received datagrams are discarded, thus both endpoints should use the same setting.
+    /// The default is 1200 bytes, which fits into the path MTU of almost all networks.
+    ///
+    /// # Panics
+    /// Panics if the size is not between [`MIN_DATAGRAM_SIZE`] and [`MAX_DATAGRAM_SIZE`].
+    pub fn set_max_datagram_size(&mut self, max_datagram_size: usize) {
+        self.cfg.set_max_datagram_size(max_datagram_size);
+    }
+
+    /// Sets the time after which a link is dropped when sent data is not acknowledged.
+    ///
+    /// This also limits the duration of the handshake when establishing a link.
+    /// The default is 15 seconds.
+    ///
+    /// # Panics
+    /// Panics if the timeout is zero.
+    pub fn set_ack_timeout(&mut self, ack_timeout: Duration) {
+        self.cfg.set_ack_timeout(ack_timeout);
+    }
+
+    /// Sets the time after which a link is dropped when no datagram is received from the remote endpoint.
+    ///
+    /// Aggligator sends pings over idle links, thus this only triggers when the
+    /// link ping is disabled or the remote endpoint has become unreachable.
+    /// The default is 60 seconds.
+    ///
+    /// # Panics
+    /// Panics if the timeout is zero.
+    pub fn set_idle_timeout(&mut self, idle_timeout: Duration) {
+        self.cfg.set_idle_timeout(idle_timeout);
+    }
+
+    /// Sets the type of service (`IP_TOS`) of IPv4 link sockets.
+    ///
+    /// This is used for DSCP marking of outgoing datagrams, for example `0xb8` for
+    /// expedited forwarding of interactive traffic.
+    /// If the value cannot be set or is not taken over by the operating system,
+    /// establishing the link fails with a corresponding [link error](super::LinkError).
+    /// The two least significant bits are used for explicit congestion notification and
+    /// may be managed by the operating system.
+    pub fn set_tos(&mut self, tos: u8) {
+        self.tos = Some(tos);
+    }
+
+    /// Sets the traffic class (`IPV6_TCLASS`) of IPv6 link sockets.
+    ///
+    /// This is only supported on Linux; on other platforms links over IPv6 fail to be established.
+    /// See [`set_tos`](Self::set_tos) for details.
+    pub fn set_traffic_class(&mut self, traffic_class: u8) {
+        self.traffic_class = Some(traffic_class);
+    }
+
+    /// Performs the handshake with the remote endpoint.
+    async fn open(socket: &UdpSocket, remote: SocketAddr, session: u32, ack_timeout: Duration) -> Result<()> {
+        let hdr = Header { kind: Kind::Open, session, seq: 0, ack: 0 };
+        let packet = hdr.encode(&[]);
+        let deadline = Instant::now() + ack_timeout;
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+        let mut retry = INITIAL_RTO;
+
+        while Instant::now() < deadline {
+            socket.send_to(&packet, remote).await?;
+
+            let reply = async {
+                loop {
+                    match socket.recv_from(&mut buf).await {
+                        Ok((n, from)) if from == remote => match Header::decode(&buf[..n]) {
+                            Some((hdr, _)) if hdr.kind == Kind::Open && hdr.session == session => break,
+                            _ => (),
+                        },
+                        Ok(_) => (),
+                        Err(err) => tracing::debug!("receiving from {remote} failed: {err}"),
+                    }
+                }
+            };
+
+            if timeout(retry.min(deadline - Instant::now()), reply).await.is_ok() {
+                return Ok(());
+            }
+            retry *= 2;
+        }
+
+        Err(Error::new(ErrorKind::TimedOut, "UDP handshake timed out"))
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for UdpConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        loop {
+            let mut tags: HashSet<LinkTagBox> = HashSet::new();
+            for addr in resolve_hosts(&self.hosts, self.ip_version).await {
+                for (iface, ip) in local_addrs_for_target(addr)? {
+                    let tag = UdpLinkTag::new(&iface, SocketAddr::new(ip, 0), addr, Direction::Outgoing);
+                    tags.insert(Box::new(tag));
+                }
+            }
+
+            tx.send_if_modified(|v| {
+                if *v != tags {
+                    *v = tags;
+                    true
+                } else {
+                    false
+                }
+            });
+
+            sleep(self.resolve_interval).await;
+        }
+    }
+
+    async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
+        let tag: &UdpLinkTag = tag.as_any().downcast_ref().unwrap();
+
+        let socket = UdpSocket::bind(tag.local).await?;
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        socket.bind_device(Some(&tag.interface))?;
+
+        let ipv6 = tag.local.is_ipv6();
+        if let Some(tos) = if ipv6 { self.traffic_class } else { self.tos } {
+            apply_tos(SockRef::from(&socket), tos, ipv6)?;
+        }
+
+        let session = random_session();
+        Self::open(&socket, tag.remote, session, self.cfg.ack_timeout).await?;
+
+        let socket = Arc::new(socket);
+        let framing = Framing::new(socket.clone(), tag.remote, session, Direction::Outgoing, self.cfg);
+        Ok(framing.spawn(Datagrams::socket(socket)))
+    }
+
+    async fn link_filter(&self, new: &Link<LinkTagBox>, existing: &[Link<LinkTagBox>]) -> bool {
+        let Some(new_tag) = new.tag().as_any().downcast_ref::<UdpLinkTag>() else { return true };
+
+        let intro = format!(
+            "Judging {} UDP link {} {} ({}) on {}",
+            new.direction(),
+            match new.direction() {
+                Direction::Incoming => "from",
+                Direction::Outgoing => "to",
+            },
+            new_tag.remote,
+            String::from_utf8_lossy(new.remote_user_data()),
+            String::from_utf8_lossy(&new_tag.interface)
+        );
+
+        match existing.iter().find(|link| {
+            let Some(tag) = link.tag().as_any().downcast_ref::<UdpLinkTag>() else { return false };
+            tag.interface == new_tag.interface && link.remote_user_data() == new.remote_user_data()
+        }) {
+            Some(other) => {
+                let other_tag = other.tag().as_any().downcast_ref::<UdpLinkTag>().unwrap();
+                tracing::debug!("{intro} => link {} is redundant, rejecting.", other_tag.remote);
+                false
+            }
+            None => {
+                tracing::debug!("{intro} => accepted.");
+                true
+            }
+        }
+    }
+}
+
+/// Incoming link registered with the UDP acceptor.
+struct IncomingLink {
+    session: u32,
+    tx: mpsc::Sender<Vec<u8>>,
+}
+
+/// UDP transport for incoming connections.
+#[derive(Debug)]
+pub struct UdpAcceptor {
+    sockets: Vec<Arc<UdpSocket>>,
+    cfg: LinkCfg,
+    max_links: usize,
+}
+
+impl fmt::Display for UdpAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let addrs: Vec<_> = self
+            .sockets
+            .iter()
+            .filter_map(|socket| socket.local_addr().ok().map(|addr| addr.to_string()))
+            .collect();
+        match addrs.as_slice() {
+            [addr] => write!(f, "{addr}"),
+            addrs => write!(f, "[{}]", addrs.join(", ")),
+        }
+    }
+}
+
+impl UdpAcceptor {
+    /// Create a new UDP transport listening for incoming connections.
+    ///
+    /// It listens on the local addresses specified in `addrs`.
+    pub async fn new(addrs: impl IntoIterator<Item = SocketAddr>) -> Result<Self> {
+        let mut sockets = Vec::new();
+
+        for addr in addrs {
+            sockets.push(UdpSocket::bind(addr).await?);
+        }
+
+        Self::from_sockets(sockets)
+    }
+
+    /// Create a new UDP transport for incoming connections using the specified UDP sockets.
+    pub fn from_sockets(sockets: impl IntoIterator<Item = UdpSocket>) -> Result<Self> {
+        let sockets: Vec<_> = sockets.into_iter().map(Arc::new).collect();
+
+        if sockets.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "at least one socket is required"));
+        }
+
+        Ok(Self { sockets, cfg: LinkCfg::default(), max_links: DEFAULT_MAX_LINKS })
+    }
+
+    /// Sets the maximum size of a datagram, including the framing header.
+    ///
+    /// Larger received datagrams are discarded, thus both endpoints should use the same setting.
+    /// The default is 1200 bytes, which fits into the path MTU of almost all networks.
+    ///
+    /// # Panics
+    /// Panics if the size is not between [`MIN_DATAGRAM_SIZE`] and [`MAX_DATAGRAM_SIZE`].
+    pub fn set_max_datagram_size(&mut self, max_datagram_size: usize) {
+        self.cfg.set_max_datagram_size(max_datagram_size);
+    }
+
+    /// Sets the time after which a link is dropped when sent data is not acknowledged.
+    ///
+    /// The default is 15 seconds.
+    ///
+    /// # Panics
+    /// Panics if the timeout is zero.
+    pub fn set_ack_timeout(&mut self, ack_timeout: Duration) {
+        self.cfg.set_ack_timeout(ack_timeout);
+    }
+
+    /// Sets the time after which a link is dropped when no datagram is received from the remote endpoint.
+    ///
+    /// Aggligator sends pings over idle links, thus this only triggers when the
+    /// link ping is disabled or the remote endpoint has become unreachable.
+    /// The default is 60 seconds.
+    ///
+    /// # Panics
+    /// Panics if the timeout is zero.
+    pub fn set_idle_timeout(&mut self, idle_timeout: Duration) {
+        self.cfg.set_idle_timeout(idle_timeout);
+    }
+
+    /// Sets the maximum number of incoming links.
+    ///
+    /// Since a link is opened by a single unauthenticated datagram, this limits the
+    /// resources that can be occupied by datagrams with spoofed source addresses.
+    /// Further link requests are discarded while the limit is reached.
+    /// The default is 256 links.
+    ///
+    /// # Panics
+    /// Panics if `max_links` is zero.
+    pub fn set_max_links(&mut self, max_links: usize) {
+        assert!(max_links > 0, "maximum number of links must not be zero");
+        self.max_links = max_links;
+    }
+
+    /// Sets the type of service (`IP_TOS`) of the IPv4 sockets used for incoming links.
+    ///
+    /// This is used for DSCP marking of outgoing datagrams.
+    /// Fails if the value cannot be set or is not taken over by the operating system.
+    /// See [`UdpConnector::set_tos`] for details.
+    pub fn set_tos(&mut self, tos: u8) -> Result<()> {
+        self.set_marking(tos, false)
+    }
+
+    /// Sets the traffic class (`IPV6_TCLASS`) of the IPv6 sockets used for incoming links.
+    ///
+    /// This is only supported on Linux and fails on other platforms, if IPv6 sockets are used.
+    /// See [`UdpConnector::set_tos`] for details.
+    pub fn set_traffic_class(&mut self, traffic_class: u8) -> Result<()> {
+        self.set_marking(traffic_class, true)
+    }
+
+    /// Applies the type of service or traffic class to all sockets of the specified IP version.
+    fn set_marking(&self, tos: u8, ipv6: bool) -> Result<()> {
+        for socket in &self.sockets {
+            if socket.local_addr()?.is_ipv6() == ipv6 {
+                apply_tos(SockRef::from(&**socket), tos, ipv6)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AcceptingTransport for UdpAcceptor {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
+        let mut links: HashMap<(usize, SocketAddr), IncomingLink> = HashMap::new();
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+
+        loop {
+            // Receive datagram.
+            let (res, idx, _) =
+                future::select_all(self.sockets.iter().map(|socket| socket.readable().boxed())).await;
+            res?;
+            let socket = &self.sockets[idx];
+            let (n, remote) = match socket.try_recv_from(&mut buf) {
+                Ok(v) => v,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => continue,
+                Err(err) => {
+                    tracing::debug!("receiving UDP datagram failed: {err}");
+                    continue;
+                }
+            };
+            let datagram = &buf[..n];
+            let Some((hdr, _)) = Header::decode(datagram) else { continue };
+
+            // Forward to existing link.
+            // The framing task processes datagrams as they arrive, thus waiting for
+            // queue space only applies back pressure while it is sending.
+            if let Some(link) = links.get(&(idx, remote)) {
+                if link.session == hdr.session {
+                    match link.tx.send(datagram.to_vec()).await {
+                        Ok(()) => continue,
+                        Err(_) => {
+                            links.remove(&(idx, remote));
+                        }
+                    }
+                }
+            }
+
+            if hdr.kind != Kind::Open {
+                continue;
+            }
+
+            links.retain(|_, link| !link.tx.is_closed());
+            if links.len() >= self.max_links && !links.contains_key(&(idx, remote)) {
+                tracing::debug!(
+                    "discarding UDP link request from {remote} since maximum number of links is reached"
+                );
+                continue;
+            }
+
+            // Build tag.
+            let mut local = socket.local_addr()?;
+            use_proper_ipv4(&mut local);
+            let mut tag_remote = remote;
+            use_proper_ipv4(&mut tag_remote);
+            let interface = interface_name_for_addr(local.ip())?.unwrap_or_default();
+            tracing::debug!(
+                "Accepted UDP link from {tag_remote} on {local} ({})",
+                String::from_utf8_lossy(&interface)
+            );
+            let tag = UdpLinkTag::new(&interface, local, tag_remote, Direction::Incoming);
+
+            // Start framing.
+            let (link_tx, link_rx) = mpsc::channel(QUEUE_LEN);
+            let _ = link_tx.try_send(datagram.to_vec());
+            links.insert((idx, remote), IncomingLink { session: hdr.session, tx: link_tx });
+
+            let framing = Framing::new(socket.clone(), remote, hdr.session, Direction::Incoming, self.cfg);
+            let io = framing.spawn(Datagrams::Channel(link_rx));
+
+            let _ = tx.send(AcceptedIoBox { io, tag: Box::new(tag) }).await;
+        }
+    }
+}
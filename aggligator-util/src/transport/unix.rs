@@ -0,0 +1,258 @@
+//! Unix domain socket transport.
+//!
+//! This is useful for local inter-process communication and for
+//! testing without a network.
+//! Since all links share the same host, redundancy is achieved by
+//! connecting over multiple socket paths.
+
+use async_trait::async_trait;
+use futures::future;
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+};
+use tokio::{
+    net::{UnixListener, UnixStream},
+    sync::{mpsc, watch},
+};
+
+use super::{AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox};
+use aggligator::control::Direction;
+
+static NAME: &str = "unix";
+
+/// Credentials of the process at the remote end of a Unix domain socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PeerCred {
+    /// User id.
+    pub uid: u32,
+    /// Group id.
+    pub gid: u32,
+    /// Process id, if available.
+    pub pid: Option<i32>,
+}
+
+impl fmt::Display for PeerCred {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "uid={} gid={}", self.uid, self.gid)?;
+        if let Some(pid) = self.pid {
+            write!(f, " pid={pid}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Link tag for Unix domain socket link.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnixLinkTag {
+    /// Socket path.
+    pub path: PathBuf,
+    /// Credentials of the remote process.
+    ///
+    /// This is only available for incoming links.
+    pub peer_cred: Option<PeerCred>,
+    /// Link direction.
+    pub direction: Direction,
+}
+
+impl fmt::Display for UnixLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        write!(f, "{dir} {}", self.path.display())?;
+        if let Some(peer_cred) = &self.peer_cred {
+            write!(f, " ({peer_cred})")?;
+        }
+        Ok(())
+    }
+}
+
+impl UnixLinkTag {
+    /// Creates a new link tag for a Unix domain socket link.
+    pub fn new(path: &Path, peer_cred: Option<PeerCred>, direction: Direction) -> Self {
+        Self { path: path.to_path_buf(), peer_cred, direction }
+    }
+}
+
+impl LinkTag for UnixLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// Unix domain socket transport for outgoing connections.
+///
+/// One link is established for each socket path.
+#[derive(Debug, Clone)]
+pub struct UnixConnector {
+    paths: Vec<PathBuf>,
+}
+
+impl fmt::Display for UnixConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let paths: Vec<_> = self.paths.iter().map(|path| path.display().to_string()).collect();
+        if paths.len() > 1 {
+            write!(f, "[{}]", paths.join(", "))
+        } else {
+            write!(f, "{}", paths[0])
+        }
+    }
+}
+
+impl UnixConnector {
+    /// Create a new Unix domain socket transport for outgoing connections to the socket at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { paths: vec![path.into()] }
+    }
+
+    /// Adds a socket path to connect to.
+    ///
+    /// A separate link is established to each socket path.
+    pub fn add_path(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        if !self.paths.contains(&path) {
+            self.paths.push(path);
+        }
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for UnixConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        let tags = self
+            .paths
+            .iter()
+            .map(|path| Box::new(UnixLinkTag::new(path, None, Direction::Outgoing)) as LinkTagBox);
+        tx.send_replace(tags.collect());
+        future::pending().await
+    }
+
+    async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
+        let tag: &UnixLinkTag = tag.as_any().downcast_ref().unwrap();
+
+        let stream = UnixStream::connect(&tag.path).await?;
+        let (rh, wh) = stream.into_split();
+        Ok(IoBox::new(rh, wh))
+    }
+}
+
+/// Unix domain socket transport for incoming connections.
+///
+/// The socket file is removed when the transport is dropped.
+#[derive(Debug)]
+pub struct UnixAcceptor {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl fmt::Display for UnixAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path.display())
+    }
+}
+
+impl UnixAcceptor {
+    /// Create a new Unix domain socket transport listening for incoming connections on the socket at `path`.
+    ///
+    /// A stale socket file left behind at `path` is removed.
+    /// If another process is listening on `path`, an error of kind
+    /// [`AddrInUse`](ErrorKind::AddrInUse) is returned.
+    pub async fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+
+        if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+            if !metadata.file_type().is_socket() {
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path.display()),
+                ));
+            }
+
+            match UnixStream::connect(&path).await {
+                Ok(_) => {
+                    return Err(Error::new(
+                        ErrorKind::AddrInUse,
+                        format!("socket {} is already in use", path.display()),
+                    ))
+                }
+                Err(_) => {
+                    tracing::debug!("removing stale socket {}", path.display());
+                    std::fs::remove_file(&path)?;
+                }
+            }
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        Ok(Self { listener, path })
+    }
+}
+
+impl Drop for UnixAcceptor {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[async_trait]
+impl AcceptingTransport for UnixAcceptor {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
+        loop {
+            let (stream, _) = self.listener.accept().await?;
+
+            let peer_cred = match stream.peer_cred() {
+                Ok(cred) => Some(PeerCred { uid: cred.uid(), gid: cred.gid(), pid: cred.pid() }),
+                Err(err) => {
+                    tracing::debug!("cannot obtain peer credentials: {err}");
+                    None
+                }
+            };
+
+            let tag = UnixLinkTag::new(&self.path, peer_cred, Direction::Incoming);
+            tracing::debug!("Accepted Unix connection {tag}");
+
+            let (rh, wh) = stream.into_split();
+            let _ = tx.send(AcceptedIoBox::new(rh, wh, tag)).await;
+        }
+    }
+}
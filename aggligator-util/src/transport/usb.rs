@@ -0,0 +1,489 @@
+//! USB transport.
+//!
+//! The [host side](UsbConnector) opens a pair of bulk endpoints on a matching USB
+//! device, for example an Android device in accessory mode or a Linux device using
+//! a FunctionFS gadget.
+//!
+//! On Linux the [device side](UsbGadgetAcceptor) can be provided by a
+//! FunctionFS USB gadget function.
+//! The gadget must be configured through configfs and the FunctionFS instance
+//! must be mounted before the acceptor is created.
+//!
+//! Unplugging the USB cable fails the link.
+//! The device is reconnected automatically when it is plugged in again.
+
+use async_trait::async_trait;
+use nusb::{
+    transfer::{RequestBuffer, TransferError},
+    DeviceInfo, Interface,
+};
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    time::Duration,
+};
+use tokio::{
+    io::{duplex, split, AsyncReadExt, AsyncWriteExt, DuplexStream},
+    sync::{mpsc, watch},
+    time::sleep,
+};
+
+use super::{AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox};
+use aggligator::control::Direction;
+
+static NAME: &str = "usb";
+
+/// Interval for checking which USB devices are present.
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Size of a bulk transfer.
+const TRANSFER_SIZE: usize = 16_384;
+
+/// Number of concurrently submitted bulk IN transfers.
+const IN_TRANSFERS: usize = 4;
+
+/// Link tag for USB link from the host side.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UsbLinkTag {
+    /// Bus number.
+    pub bus: u8,
+    /// Device address on the bus.
+    pub address: u8,
+    /// Vendor id.
+    pub vendor_id: u16,
+    /// Product id.
+    pub product_id: u16,
+    /// Serial number.
+    pub serial: Option<String>,
+    /// Link direction.
+    pub direction: Direction,
+}
+
+impl fmt::Display for UsbLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        write!(
+            f,
+            "{dir} usb {:03}:{:03} {:04x}:{:04x}",
+            self.bus, self.address, self.vendor_id, self.product_id
+        )?;
+        if let Some(serial) = &self.serial {
+            write!(f, " {serial}")?;
+        }
+        Ok(())
+    }
+}
+
+impl UsbLinkTag {
+    /// Creates a new link tag for the USB device.
+    pub fn new(info: &DeviceInfo, direction: Direction) -> Self {
+        Self {
+            bus: info.bus_number(),
+            address: info.device_address(),
+            vendor_id: info.vendor_id(),
+            product_id: info.product_id(),
+            serial: info.serial_number().map(|s| s.to_string()),
+            direction,
+        }
+    }
+
+    /// Whether this tag refers to the specified USB device.
+    fn matches(&self, info: &DeviceInfo) -> bool {
+        self.bus == info.bus_number()
+            && self.address == info.device_address()
+            && self.vendor_id == info.vendor_id()
+            && self.product_id == info.product_id()
+    }
+}
+
+impl LinkTag for UsbLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// Converts a USB transfer error into an IO error.
+fn transfer_err(err: TransferError) -> Error {
+    match err {
+        TransferError::Disconnected => Error::new(ErrorKind::NotConnected, "USB device disconnected"),
+        other => Error::new(ErrorKind::Other, other),
+    }
+}
+
+/// Forwards data between the stream and the bulk endpoints of the USB interface.
+async fn forward(interface: Interface, ep_in: u8, ep_out: u8, stream: DuplexStream) -> Result<()> {
+    let mut in_queue = interface.bulk_in_queue(ep_in);
+    let mut out_queue = interface.bulk_out_queue(ep_out);
+    while in_queue.pending() < IN_TRANSFERS {
+        in_queue.submit(RequestBuffer::new(TRANSFER_SIZE));
+    }
+
+    let (mut stream_rx, mut stream_tx) = split(stream);
+    let mut buf = vec![0; TRANSFER_SIZE];
+
+    loop {
+        tokio::select! {
+            res = stream_rx.read(&mut buf), if out_queue.pending() == 0 => {
+                match res? {
+                    0 => return Ok(()),
+                    n => out_queue.submit(buf[..n].to_vec()),
+                }
+            }
+            completion = out_queue.next_complete(), if out_queue.pending() > 0 => {
+                completion.status.map_err(transfer_err)?;
+            }
+            completion = in_queue.next_complete() => {
+                completion.status.map_err(transfer_err)?;
+                stream_tx.write_all(&completion.data).await?;
+                in_queue.submit(RequestBuffer::reuse(completion.data, TRANSFER_SIZE));
+            }
+        }
+    }
+}
+
+/// USB transport for outgoing connections from the host side.
+///
+/// One link is established to each present USB device with matching
+/// vendor and product id.
+#[derive(Debug, Clone)]
+pub struct UsbConnector {
+    vendor_id: u16,
+    product_id: u16,
+    interface: u8,
+    ep_in: u8,
+    ep_out: u8,
+}
+
+impl fmt::Display for UsbConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vendor_id, self.product_id)
+    }
+}
+
+impl UsbConnector {
+    /// Creates a new USB transport for USB devices with the specified vendor and product id.
+    ///
+    /// By default interface 0 with bulk endpoints `0x81` (IN) and `0x01` (OUT) is used.
+    pub fn new(vendor_id: u16, product_id: u16) -> Self {
+        Self { vendor_id, product_id, interface: 0, ep_in: 0x81, ep_out: 0x01 }
+    }
+
+    /// Sets the number of the USB interface to claim.
+    pub fn set_interface(&mut self, interface: u8) {
+        self.interface = interface;
+    }
+
+    /// Sets the addresses of the bulk IN and OUT endpoints.
+    pub fn set_endpoints(&mut self, ep_in: u8, ep_out: u8) {
+        self.ep_in = ep_in;
+        self.ep_out = ep_out;
+    }
+
+    /// Lists present USB devices with matching vendor and product id.
+    fn devices(&self) -> Result<Vec<DeviceInfo>> {
+        Ok(nusb::list_devices()?
+            .filter(|info| info.vendor_id() == self.vendor_id && info.product_id() == self.product_id)
+            .collect())
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for UsbConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        loop {
+            let tags: HashSet<LinkTagBox> = self
+                .devices()?
+                .iter()
+                .map(|info| Box::new(UsbLinkTag::new(info, Direction::Outgoing)) as LinkTagBox)
+                .collect();
+
+            tx.send_if_modified(|v| {
+                if *v != tags {
+                    *v = tags;
+                    true
+                } else {
+                    false
+                }
+            });
+
+            sleep(DEVICE_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
+        let tag: &UsbLinkTag = tag.as_any().downcast_ref().unwrap();
+
+        let info = self
+            .devices()?
+            .into_iter()
+            .find(|info| tag.matches(info))
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "USB device not present"))?;
+        let device = info.open()?;
+        let interface = device.claim_interface(self.interface)?;
+
+        let (a, b) = duplex(IN_TRANSFERS * TRANSFER_SIZE);
+        let (ep_in, ep_out) = (self.ep_in, self.ep_out);
+        let name = tag.to_string();
+        tokio::spawn(async move {
+            if let Err(err) = forward(interface, ep_in, ep_out, b).await {
+                tracing::debug!("USB link {name} failed: {err}");
+            }
+        });
+
+        let (rh, wh) = split(a);
+        Ok(IoBox::new(rh, wh))
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use gadget::*;
+
+#[cfg(target_os = "linux")]
+mod gadget {
+    use std::{
+        fs::{File, OpenOptions},
+        io::{Read, Write},
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicU64, Ordering as AtomicOrdering},
+    };
+
+    use super::*;
+
+    /// FunctionFS descriptors header magic (version 2).
+    const DESCRIPTORS_MAGIC_V2: u32 = 3;
+    /// FunctionFS strings header magic.
+    const STRINGS_MAGIC: u32 = 2;
+    /// Full-speed, high-speed and super-speed descriptors are present.
+    const HAS_FS_HS_SS_DESC: u32 = 1 | 2 | 4;
+    /// FunctionFS event type for enabling the function.
+    const EVENT_ENABLE: u8 = 2;
+    /// FunctionFS event type for disabling the function.
+    const EVENT_DISABLE: u8 = 3;
+    /// FunctionFS event type for a control request.
+    const EVENT_SETUP: u8 = 4;
+    /// Size of a FunctionFS event.
+    const EVENT_SIZE: usize = 12;
+
+    /// Interface descriptor of a vendor-specific interface with two endpoints.
+    const INTERFACE_DESC: [u8; 9] = [9, 4, 0, 0, 2, 0xff, 0, 0, 1];
+
+    /// Builds a bulk endpoint descriptor.
+    fn endpoint_desc(address: u8, max_packet_size: u16) -> Vec<u8> {
+        let [lo, hi] = max_packet_size.to_le_bytes();
+        vec![7, 5, address, 2, lo, hi, 0]
+    }
+
+    /// Super-speed endpoint companion descriptor.
+    const SS_COMPANION_DESC: [u8; 6] = [6, 0x30, 0, 0, 0, 0];
+
+    /// Builds the FunctionFS descriptors.
+    ///
+    /// The first endpoint (file `ep1`) is the bulk IN endpoint, the second endpoint (file `ep2`)
+    /// is the bulk OUT endpoint.
+    fn descriptors() -> Vec<u8> {
+        let mut descs = Vec::new();
+        for max_packet_size in [64, 512] {
+            descs.extend_from_slice(&INTERFACE_DESC);
+            descs.extend(endpoint_desc(0x81, max_packet_size));
+            descs.extend(endpoint_desc(0x01, max_packet_size));
+        }
+        descs.extend_from_slice(&INTERFACE_DESC);
+        descs.extend(endpoint_desc(0x81, 1024));
+        descs.extend_from_slice(&SS_COMPANION_DESC);
+        descs.extend(endpoint_desc(0x01, 1024));
+        descs.extend_from_slice(&SS_COMPANION_DESC);
+
+        let mut data = Vec::new();
+        data.extend(DESCRIPTORS_MAGIC_V2.to_le_bytes());
+        data.extend(((24 + descs.len()) as u32).to_le_bytes());
+        data.extend(HAS_FS_HS_SS_DESC.to_le_bytes());
+        data.extend(3u32.to_le_bytes());
+        data.extend(3u32.to_le_bytes());
+        data.extend(5u32.to_le_bytes());
+        data.extend(descs);
+        data
+    }
+
+    /// Builds the FunctionFS strings.
+    fn strings(interface_name: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(STRINGS_MAGIC.to_le_bytes());
+        data.extend(((16 + 2 + interface_name.len() + 1) as u32).to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        data.extend(0x0409u16.to_le_bytes());
+        data.extend(interface_name.as_bytes());
+        data.push(0);
+        data
+    }
+
+    /// Link tag for USB link on the gadget side.
+    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct UsbGadgetLinkTag {
+        /// Mount point of the FunctionFS instance.
+        pub ffs: PathBuf,
+        /// Number of times the function had been enabled by the USB host before this link.
+        pub session: u64,
+    }
+
+    impl fmt::Display for UsbGadgetLinkTag {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "<- usb gadget {} #{}", self.ffs.display(), self.session)
+        }
+    }
+
+    impl UsbGadgetLinkTag {
+        /// Creates a new link tag for a USB gadget link.
+        pub fn new(ffs: &Path, session: u64) -> Self {
+            Self { ffs: ffs.to_path_buf(), session }
+        }
+    }
+
+    impl LinkTag for UsbGadgetLinkTag {
+        fn transport_name(&self) -> &str {
+            NAME
+        }
+
+        fn direction(&self) -> Direction {
+            Direction::Incoming
+        }
+
+        fn user_data(&self) -> Vec<u8> {
+            Vec::new()
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn box_clone(&self) -> LinkTagBox {
+            Box::new(self.clone())
+        }
+
+        fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+            let other = other.as_any().downcast_ref::<Self>().unwrap();
+            Ord::cmp(self, other)
+        }
+
+        fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+            Hash::hash(self, &mut state)
+        }
+    }
+
+    /// USB transport for incoming connections on the device side using a FunctionFS gadget function.
+    ///
+    /// The function provides a vendor-specific interface with one bulk IN and one bulk OUT endpoint.
+    /// Their addresses are assigned by the USB device controller and are usually `0x81` (IN)
+    /// and `0x01` (OUT), matching the defaults of [`UsbConnector`].
+    ///
+    /// A link is accepted each time the USB host enables the function.
+    #[derive(Debug)]
+    pub struct UsbGadgetAcceptor {
+        ffs: PathBuf,
+        ep0: File,
+        session: AtomicU64,
+    }
+
+    impl fmt::Display for UsbGadgetAcceptor {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}", self.ffs.display())
+        }
+    }
+
+    impl UsbGadgetAcceptor {
+        /// Creates a new USB transport for the FunctionFS instance mounted at `ffs`.
+        ///
+        /// This writes the USB descriptors of the function.
+        /// Afterwards the USB gadget can be bound to a USB device controller.
+        pub fn new(ffs: impl AsRef<Path>) -> Result<Self> {
+            let ffs = ffs.as_ref().to_path_buf();
+            let mut ep0 = OpenOptions::new().read(true).write(true).open(ffs.join("ep0"))?;
+            ep0.write_all(&descriptors())?;
+            ep0.write_all(&strings("aggligator"))?;
+            Ok(Self { ffs, ep0, session: AtomicU64::new(0) })
+        }
+
+        /// Waits for the next FunctionFS event and returns its type.
+        async fn event(&self) -> Result<u8> {
+            let mut ep0 = self.ep0.try_clone()?;
+            tokio::task::spawn_blocking(move || {
+                let mut event = [0; EVENT_SIZE];
+                ep0.read_exact(&mut event)?;
+
+                // Stall control requests, since the function does not handle any.
+                if event[8] == EVENT_SETUP {
+                    if event[0] & 0x80 != 0 {
+                        let _ = ep0.read(&mut []);
+                    } else {
+                        let _ = ep0.write(&[]);
+                    }
+                }
+
+                Ok(event[8])
+            })
+            .await?
+        }
+    }
+
+    #[async_trait]
+    impl AcceptingTransport for UsbGadgetAcceptor {
+        fn name(&self) -> &str {
+            NAME
+        }
+
+        async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
+            loop {
+                match self.event().await? {
+                    EVENT_ENABLE => {
+                        let ep_in = tokio::fs::OpenOptions::new().write(true).open(self.ffs.join("ep1")).await?;
+                        let ep_out = tokio::fs::File::open(self.ffs.join("ep2")).await?;
+
+                        let session = self.session.fetch_add(1, AtomicOrdering::SeqCst);
+                        let tag = UsbGadgetLinkTag::new(&self.ffs, session);
+                        tracing::debug!("USB host enabled function {tag}");
+
+                        let _ = tx.send(AcceptedIoBox::new(ep_out, ep_in, tag)).await;
+                    }
+                    EVENT_DISABLE => tracing::debug!("USB host disabled function {}", self.ffs.display()),
+                    _ => (),
+                }
+            }
+        }
+    }
+}
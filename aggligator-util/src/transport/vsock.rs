@@ -0,0 +1,189 @@
+//! Virtio socket (vsock) transport.
+//!
+//! This is useful for links between a virtual machine and its host
+//! that do not depend on the virtual network interface.
+//!
+//! Failed connection attempts, for example because the remote side is not
+//! listening yet, are retried by the [connector](super::Connector) using
+//! its reconnect delay.
+
+use async_trait::async_trait;
+use futures::future;
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    io::Result,
+};
+use tokio::{
+    io::split,
+    sync::{mpsc, watch, Mutex},
+};
+use tokio_vsock::{VsockListener, VsockStream};
+
+use super::{AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox};
+use aggligator::control::Direction;
+
+static NAME: &str = "vsock";
+
+/// Context id for listening on all context ids.
+const VMADDR_CID_ANY: u32 = u32::MAX;
+
+/// Context id of the host.
+pub const VMADDR_CID_HOST: u32 = 2;
+
+/// Context id for local communication (loopback).
+pub const VMADDR_CID_LOCAL: u32 = 1;
+
+/// Link tag for vsock link.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VsockLinkTag {
+    /// Context id of the remote endpoint.
+    pub cid: u32,
+    /// Port of the remote endpoint.
+    pub port: u32,
+    /// Link direction.
+    pub direction: Direction,
+}
+
+impl fmt::Display for VsockLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        write!(f, "{dir} vsock {}:{}", self.cid, self.port)
+    }
+}
+
+impl VsockLinkTag {
+    /// Creates a new link tag for a vsock link.
+    pub fn new(cid: u32, port: u32, direction: Direction) -> Self {
+        Self { cid, port, direction }
+    }
+}
+
+impl LinkTag for VsockLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// Vsock transport for outgoing connections.
+#[derive(Debug, Clone)]
+pub struct VsockConnector {
+    cid: u32,
+    port: u32,
+}
+
+impl fmt::Display for VsockConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.cid, self.port)
+    }
+}
+
+impl VsockConnector {
+    /// Create a new vsock transport for outgoing connections to the specified context id and port.
+    ///
+    /// Use [`VMADDR_CID_HOST`] to connect from a virtual machine to its host.
+    pub fn new(cid: u32, port: u32) -> Self {
+        Self { cid, port }
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for VsockConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        let tag: LinkTagBox = Box::new(VsockLinkTag::new(self.cid, self.port, Direction::Outgoing));
+        tx.send_replace([tag].into_iter().collect());
+        future::pending().await
+    }
+
+    async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
+        let tag: &VsockLinkTag = tag.as_any().downcast_ref().unwrap();
+
+        let stream = VsockStream::connect(tag.cid, tag.port).await?;
+        let (rh, wh) = split(stream);
+        Ok(IoBox::new(rh, wh))
+    }
+}
+
+/// Vsock transport for incoming connections.
+pub struct VsockAcceptor {
+    port: u32,
+    listener: Mutex<VsockListener>,
+}
+
+impl fmt::Debug for VsockAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VsockAcceptor").field("port", &self.port).finish()
+    }
+}
+
+impl fmt::Display for VsockAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "vsock port {}", self.port)
+    }
+}
+
+impl VsockAcceptor {
+    /// Create a new vsock transport listening for incoming connections on the specified port.
+    ///
+    /// Connections from all context ids are accepted.
+    pub fn new(port: u32) -> Result<Self> {
+        let listener = VsockListener::bind(VMADDR_CID_ANY, port)?;
+        Ok(Self { port, listener: Mutex::new(listener) })
+    }
+}
+
+#[async_trait]
+impl AcceptingTransport for VsockAcceptor {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
+        let mut listener = self.listener.lock().await;
+
+        loop {
+            let (stream, remote) = listener.accept().await?;
+
+            let tag = VsockLinkTag::new(remote.cid(), remote.port(), Direction::Incoming);
+            tracing::debug!("Accepted vsock connection {tag}");
+
+            let (rh, wh) = split(stream);
+            let _ = tx.send(AcceptedIoBox::new(rh, wh, tag)).await;
+        }
+    }
+}
@@ -0,0 +1,608 @@
+//! WebSocket transport.
+//!
+//! This allows links to pass through networks where only HTTP(S) traffic is allowed.
+//! Data is exchanged using binary WebSocket messages.
+
+use async_trait::async_trait;
+use futures::{future, stream::FuturesUnordered, FutureExt, SinkExt, StreamExt};
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+use tokio::{
+    io::{duplex, split, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream},
+    net::{TcpListener, TcpSocket, TcpStream},
+    sync::{mpsc, watch},
+    time::{interval_at, sleep, timeout, Instant},
+};
+use tokio_tungstenite::{
+    accept_hdr_async, client_async,
+    tungstenite::{
+        client::IntoClientRequest,
+        handshake::server::{ErrorResponse, Request, Response},
+        http::{HeaderName, HeaderValue, StatusCode, Uri},
+        protocol::Role,
+        Error as WsError, Message,
+    },
+    WebSocketStream,
+};
+
+use super::{
+    ip::{interface_name_for_addr, local_interfaces, resolve_hosts, use_proper_ipv4},
+    tcp::TcpConnector,
+    AcceptedIoBox, AcceptingTransport, ConnectingTransport, IoBox, LinkTag, LinkTagBox,
+};
+use aggligator::{control::Direction, Link};
+
+pub use super::ip::IpVersion;
+
+static NAME: &str = "websocket";
+
+/// Timeout for the WebSocket handshake of an incoming connection.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default interval for sending pings.
+const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Maximum size of a sent message and buffer size of the link stream.
+const BUFFER_SIZE: usize = 16384;
+
+/// Response sent for HTTP requests that are not WebSocket upgrade requests.
+const BAD_REQUEST_RESPONSE: &[u8] = b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\nContent-Length: 0\r\n\r\n";
+
+/// Link tag for WebSocket link.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WebSocketLinkTag {
+    /// Local interface name.
+    pub interface: Vec<u8>,
+    /// URL of the WebSocket endpoint.
+    ///
+    /// For incoming links this is the requested URI.
+    pub url: String,
+    /// Remote address.
+    pub remote: SocketAddr,
+    /// Link direction.
+    pub direction: Direction,
+}
+
+impl fmt::Display for WebSocketLinkTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+        };
+        write!(f, "{:16} {dir} {} ({})", String::from_utf8_lossy(&self.interface), self.url, self.remote)
+    }
+}
+
+impl WebSocketLinkTag {
+    /// Creates a new link tag for a WebSocket link.
+    pub fn new(interface: &[u8], url: &str, remote: SocketAddr, direction: Direction) -> Self {
+        Self { interface: interface.to_vec(), url: url.to_string(), remote, direction }
+    }
+}
+
+impl LinkTag for WebSocketLinkTag {
+    fn transport_name(&self) -> &str {
+        NAME
+    }
+
+    fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    fn user_data(&self) -> Vec<u8> {
+        self.interface.clone()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_clone(&self) -> LinkTagBox {
+        Box::new(self.clone())
+    }
+
+    fn dyn_cmp(&self, other: &dyn LinkTag) -> Ordering {
+        let other = other.as_any().downcast_ref::<Self>().unwrap();
+        Ord::cmp(self, other)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        Hash::hash(self, &mut state)
+    }
+}
+
+/// Converts a WebSocket error into an IO error.
+fn io_error(err: WsError) -> Error {
+    match err {
+        WsError::Io(err) => err,
+        WsError::ConnectionClosed | WsError::AlreadyClosed => Error::new(ErrorKind::BrokenPipe, err),
+        err => Error::new(ErrorKind::Other, err),
+    }
+}
+
+/// Forwards data between the stream of a link and its WebSocket.
+///
+/// Each write to the stream is sent as one binary message.
+/// Pings are sent periodically to keep intermediaries from dropping an idle connection.
+/// Pings from the remote endpoint are answered by the WebSocket implementation.
+async fn forward<S>(ws: WebSocketStream<S>, stream: DuplexStream, ping_interval: Duration) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut ws_tx, mut ws_rx) = ws.split();
+    let (mut stream_rx, mut stream_tx) = split(stream);
+    let mut buf = vec![0; BUFFER_SIZE];
+    let mut ping = interval_at(Instant::now() + ping_interval, ping_interval);
+
+    loop {
+        tokio::select! {
+            res = stream_rx.read(&mut buf) => {
+                match res? {
+                    0 => {
+                        ws_tx.close().await.map_err(io_error)?;
+                        return Ok(());
+                    }
+                    n => ws_tx.send(Message::Binary(buf[..n].to_vec())).await.map_err(io_error)?,
+                }
+                ping.reset();
+            }
+            msg = ws_rx.next() => {
+                match msg {
+                    Some(Ok(Message::Binary(data))) => stream_tx.write_all(&data).await?,
+                    Some(Ok(Message::Close(_))) | None => {
+                        stream_tx.shutdown().await?;
+                        return Ok(());
+                    }
+                    Some(Ok(_)) => (),
+                    Some(Err(err)) => return Err(io_error(err)),
+                }
+            }
+            _ = ping.tick() => ws_tx.send(Message::Ping(Vec::new())).await.map_err(io_error)?,
+        }
+    }
+}
+
+/// Spawns a task forwarding data for the WebSocket and returns the stream for the link.
+fn spawn_forward<S>(ws: WebSocketStream<S>, ping_interval: Duration) -> IoBox
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (a, b) = duplex(BUFFER_SIZE);
+
+    tokio::spawn(async move {
+        if let Err(err) = forward(ws, b, ping_interval).await {
+            tracing::debug!("WebSocket link failed: {err}");
+        }
+    });
+
+    let (rh, wh) = split(a);
+    IoBox::new(rh, wh)
+}
+
+/// WebSocket URL to connect to.
+#[derive(Debug, Clone)]
+struct Target {
+    url: String,
+    uri: Uri,
+    host: String,
+    secure: bool,
+}
+
+impl Target {
+    fn parse(url: String) -> Result<Self> {
+        let uri: Uri = url.parse().map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+
+        let secure = match uri.scheme_str() {
+            Some("ws") => false,
+            Some("wss") => true,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, "URL scheme must be ws or wss")),
+        };
+
+        let Some(hostname) = uri.host() else {
+            return Err(Error::new(ErrorKind::InvalidInput, "URL must contain a host"));
+        };
+        let port = uri.port_u16().unwrap_or(if secure { 443 } else { 80 });
+        let host = format!("{hostname}:{port}");
+
+        Ok(Self { url, uri, host, secure })
+    }
+}
+
+/// WebSocket transport for outgoing connections.
+///
+/// Connects to `ws://` and `wss://` URLs.
+/// One link is established for each resolved IP address and local interface.
+#[derive(Debug, Clone)]
+pub struct WebSocketConnector {
+    targets: Vec<Target>,
+    ip_version: IpVersion,
+    resolve_interval: Duration,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    ping_interval: Duration,
+    #[cfg(feature = "tls")]
+    tls_client_cfg: Option<std::sync::Arc<rustls::ClientConfig>>,
+}
+
+impl fmt::Display for WebSocketConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let urls: Vec<_> = self.targets.iter().map(|target| target.url.as_str()).collect();
+        if urls.len() > 1 {
+            write!(f, "[{}]", urls.join(", "))
+        } else {
+            write!(f, "{}", urls[0])
+        }
+    }
+}
+
+impl WebSocketConnector {
+    /// Create a new WebSocket transport for outgoing connections.
+    ///
+    /// `urls` must contain `ws://` or `wss://` URLs.
+    /// Connecting to a `wss://` URL requires a TLS client configuration, which
+    /// must be set using [`set_tls_client_cfg`](Self::set_tls_client_cfg).
+    ///
+    /// It is checked at creation that the hosts of `urls` resolve to at least one IP address.
+    ///
+    /// Host name resolution is retried periodically, thus DNS updates will be taken
+    /// into account without the need to recreate this transport.
+    pub async fn new(urls: impl IntoIterator<Item = String>) -> Result<Self> {
+        let targets = urls.into_iter().map(Target::parse).collect::<Result<Vec<_>>>()?;
+        if targets.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "at least one URL is required"));
+        }
+
+        let this = Self {
+            targets,
+            ip_version: IpVersion::Both,
+            resolve_interval: Duration::from_secs(10),
+            headers: Vec::new(),
+            ping_interval: DEFAULT_PING_INTERVAL,
+            #[cfg(feature = "tls")]
+            tls_client_cfg: None,
+        };
+
+        let hosts: Vec<_> = this.targets.iter().map(|target| target.host.clone()).collect();
+        let addrs = resolve_hosts(&hosts, this.ip_version).await;
+        if addrs.is_empty() {
+            return Err(Error::new(ErrorKind::NotFound, "cannot resolve IP address of host"));
+        }
+        tracing::info!("{} resolves to: {:?}", &this, addrs);
+
+        Ok(this)
+    }
+
+    /// Sets the IP version used for connecting.
+    pub fn set_ip_version(&mut self, ip_version: IpVersion) {
+        self.ip_version = ip_version;
+    }
+
+    /// Sets the interval for re-resolving the hostname and checking for changed network interfaces.
+    pub fn set_resolve_interval(&mut self, resolve_interval: Duration) {
+        self.resolve_interval = resolve_interval;
+    }
+
+    /// Adds a header to the HTTP upgrade request.
+    ///
+    /// This can be used to pass authentication tokens or routing information
+    /// to a reverse proxy.
+    pub fn add_header(&mut self, name: &str, value: &str) -> Result<()> {
+        let name = HeaderName::try_from(name).map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+        let value = HeaderValue::try_from(value).map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+        self.headers.push((name, value));
+        Ok(())
+    }
+
+    /// Sets the interval for sending WebSocket pings.
+    ///
+    /// Pings keep intermediaries, such as proxies, from dropping idle connections.
+    /// The default is 30 seconds.
+    ///
+    /// # Panics
+    /// Panics if the interval is zero.
+    pub fn set_ping_interval(&mut self, ping_interval: Duration) {
+        assert!(!ping_interval.is_zero(), "ping interval must not be zero");
+        self.ping_interval = ping_interval;
+    }
+
+    /// Sets the TLS client configuration used for connecting to `wss://` URLs.
+    ///
+    /// The identity of the server is verified against the host name of the URL.
+    #[cfg(feature = "tls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+    pub fn set_tls_client_cfg(&mut self, tls_client_cfg: std::sync::Arc<rustls::ClientConfig>) {
+        self.tls_client_cfg = Some(tls_client_cfg);
+    }
+
+    /// Performs the WebSocket handshake over the specified stream.
+    async fn handshake<S>(&self, target: &Target, stream: S) -> Result<IoBox>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let mut request = target.uri.clone().into_client_request().map_err(io_error)?;
+        for (name, value) in &self.headers {
+            request.headers_mut().append(name, value.clone());
+        }
+
+        let (ws, _) = client_async(request, stream).await.map_err(io_error)?;
+        Ok(spawn_forward(ws, self.ping_interval))
+    }
+
+    /// Establishes TLS over the specified stream and performs the WebSocket handshake.
+    #[cfg(feature = "tls")]
+    async fn secure_handshake(&self, target: &Target, stream: TcpStream) -> Result<IoBox> {
+        let Some(tls_client_cfg) = self.tls_client_cfg.clone() else {
+            return Err(Error::new(ErrorKind::InvalidInput, "TLS client configuration is required for wss"));
+        };
+
+        let server_name = rustls::ServerName::try_from(target.uri.host().unwrap_or_default())
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+        let tls = tokio_rustls::TlsConnector::from(tls_client_cfg).connect(server_name, stream).await?;
+
+        self.handshake(target, tls).await
+    }
+
+    #[cfg(not(feature = "tls"))]
+    async fn secure_handshake(&self, _target: &Target, _stream: TcpStream) -> Result<IoBox> {
+        Err(Error::new(ErrorKind::Unsupported, "wss requires the tls crate feature"))
+    }
+}
+
+#[async_trait]
+impl ConnectingTransport for WebSocketConnector {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn link_tags(&self, tx: watch::Sender<HashSet<LinkTagBox>>) -> Result<()> {
+        loop {
+            let interfaces = local_interfaces()?;
+
+            let mut tags: HashSet<LinkTagBox> = HashSet::new();
+            for target in &self.targets {
+                for addr in resolve_hosts(std::slice::from_ref(&target.host), self.ip_version).await {
+                    for iface in TcpConnector::interface_names_for_target(&interfaces, addr) {
+                        let tag = WebSocketLinkTag::new(&iface, &target.url, addr, Direction::Outgoing);
+                        tags.insert(Box::new(tag));
+                    }
+                }
+            }
+
+            tx.send_if_modified(|v| {
+                if *v != tags {
+                    *v = tags;
+                    true
+                } else {
+                    false
+                }
+            });
+
+            sleep(self.resolve_interval).await;
+        }
+    }
+
+    async fn connect(&self, tag: &dyn LinkTag) -> Result<IoBox> {
+        let tag: &WebSocketLinkTag = tag.as_any().downcast_ref().unwrap();
+        let Some(target) = self.targets.iter().find(|target| target.url == tag.url) else {
+            return Err(Error::new(ErrorKind::NotFound, "URL was removed"));
+        };
+
+        let socket = match tag.remote.ip() {
+            IpAddr::V4(_) => TcpSocket::new_v4(),
+            IpAddr::V6(_) => TcpSocket::new_v6(),
+        }?;
+
+        TcpConnector::bind_socket_to_interface(&socket, &tag.interface, tag.remote.ip(), None)?;
+
+        let stream = socket.connect(tag.remote).await?;
+        let _ = stream.set_nodelay(true);
+
+        if target.secure {
+            self.secure_handshake(target, stream).await
+        } else {
+            self.handshake(target, stream).await
+        }
+    }
+
+    async fn link_filter(&self, new: &Link<LinkTagBox>, existing: &[Link<LinkTagBox>]) -> bool {
+        let Some(new_tag) = new.tag().as_any().downcast_ref::<WebSocketLinkTag>() else { return true };
+
+        let intro = format!(
+            "Judging {} WebSocket link {} {} ({}) on {}",
+            new.direction(),
+            match new.direction() {
+                Direction::Incoming => "from",
+                Direction::Outgoing => "to",
+            },
+            new_tag.remote,
+            String::from_utf8_lossy(new.remote_user_data()),
+            String::from_utf8_lossy(&new_tag.interface)
+        );
+
+        // Links to different URLs may pass through different proxies and are thus not redundant.
+        match existing.iter().find(|link| {
+            let Some(tag) = link.tag().as_any().downcast_ref::<WebSocketLinkTag>() else { return false };
+            tag.url == new_tag.url
+                && tag.interface == new_tag.interface
+                && link.remote_user_data() == new.remote_user_data()
+        }) {
+            Some(other) => {
+                let other_tag = other.tag().as_any().downcast_ref::<WebSocketLinkTag>().unwrap();
+                tracing::debug!("{intro} => link {} is redundant, rejecting.", other_tag.remote);
+                false
+            }
+            None => {
+                tracing::debug!("{intro} => accepted.");
+                true
+            }
+        }
+    }
+}
+
+/// WebSocket transport for incoming connections.
+///
+/// Listens for HTTP connections and upgrades requests below the configured path
+/// to WebSocket connections.
+#[derive(Debug)]
+pub struct WebSocketAcceptor {
+    listeners: Vec<TcpListener>,
+    path: String,
+    ping_interval: Duration,
+}
+
+impl fmt::Display for WebSocketAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let addrs: Vec<_> = self
+            .listeners
+            .iter()
+            .filter_map(|listener| listener.local_addr().ok().map(|addr| format!("{addr}{}", &self.path)))
+            .collect();
+        match addrs.as_slice() {
+            [addr] => write!(f, "{addr}"),
+            addrs => write!(f, "[{}]", addrs.join(", ")),
+        }
+    }
+}
+
+impl WebSocketAcceptor {
+    /// Create a new WebSocket transport listening for incoming connections.
+    ///
+    /// It listens on the local addresses specified in `addrs` and accepts
+    /// WebSocket connections for HTTP requests whose path is `path` or below it.
+    /// Thus the path prefix of a reverse proxy can be used as `path`.
+    ///
+    /// Requests for other paths are rejected with status 404 and requests that
+    /// are not WebSocket upgrade requests are rejected with status 400.
+    pub async fn new(addrs: impl IntoIterator<Item = SocketAddr>, path: impl Into<String>) -> Result<Self> {
+        let mut listeners = Vec::new();
+
+        for addr in addrs {
+            listeners.push(TcpListener::bind(addr).await?);
+        }
+
+        Self::from_listeners(listeners, path)
+    }
+
+    /// Create a new WebSocket transport for incoming connections using the specified TCP listeners.
+    pub fn from_listeners(
+        listeners: impl IntoIterator<Item = TcpListener>, path: impl Into<String>,
+    ) -> Result<Self> {
+        let listeners: Vec<_> = listeners.into_iter().collect();
+
+        if listeners.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "at least one listener is required"));
+        }
+
+        Ok(Self { listeners, path: path.into(), ping_interval: DEFAULT_PING_INTERVAL })
+    }
+
+    /// Sets the interval for sending WebSocket pings.
+    ///
+    /// Pings keep intermediaries, such as proxies, from dropping idle connections.
+    /// The default is 30 seconds.
+    ///
+    /// # Panics
+    /// Panics if the interval is zero.
+    pub fn set_ping_interval(&mut self, ping_interval: Duration) {
+        assert!(!ping_interval.is_zero(), "ping interval must not be zero");
+        self.ping_interval = ping_interval;
+    }
+
+    /// Checks whether the request path is equal to or below the configured path.
+    fn path_matches(path: &str, request_path: &str) -> bool {
+        let path = path.trim_end_matches('/');
+        match request_path.strip_prefix(path) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+
+    /// Performs the WebSocket handshake for an incoming connection.
+    #[allow(clippy::result_large_err)]
+    async fn accept(
+        path: String, mut socket: TcpStream, remote: SocketAddr, interface: Vec<u8>, ping_interval: Duration,
+    ) -> Result<AcceptedIoBox> {
+        let mut uri = String::new();
+        let callback = |req: &Request, resp: Response| {
+            if !Self::path_matches(&path, req.uri().path()) {
+                let mut resp = ErrorResponse::new(None);
+                *resp.status_mut() = StatusCode::NOT_FOUND;
+                return Err(resp);
+            }
+            uri = req.uri().to_string();
+            Ok(resp)
+        };
+
+        // The handshake is performed over the borrowed socket, so that an error
+        // response can be sent if the request is not a WebSocket upgrade request.
+        let res = timeout(HANDSHAKE_TIMEOUT, accept_hdr_async(&mut socket, callback))
+            .await
+            .map_err(|_| Error::new(ErrorKind::TimedOut, "WebSocket handshake timed out"))?;
+        match res {
+            Ok(_) => (),
+            Err(err @ (WsError::Protocol(_) | WsError::Capacity(_) | WsError::HttpFormat(_))) => {
+                let _ = socket.write_all(BAD_REQUEST_RESPONSE).await;
+                return Err(io_error(err));
+            }
+            Err(err) => return Err(io_error(err)),
+        }
+        let ws = WebSocketStream::from_raw_socket(socket, Role::Server, None).await;
+
+        let tag = WebSocketLinkTag::new(&interface, &uri, remote, Direction::Incoming);
+        Ok(AcceptedIoBox { io: spawn_forward(ws, ping_interval), tag: Box::new(tag) })
+    }
+}
+
+#[async_trait]
+impl AcceptingTransport for WebSocketAcceptor {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn listen(&self, tx: mpsc::Sender<AcceptedIoBox>) -> Result<()> {
+        let mut handshakes = FuturesUnordered::new();
+
+        loop {
+            tokio::select! {
+                (res, _, _) = future::select_all(self.listeners.iter().map(|listener| listener.accept().boxed())) => {
+                    let (socket, mut remote) = res?;
+                    let mut local = socket.local_addr()?;
+
+                    // Use proper IPv4 addresses.
+                    use_proper_ipv4(&mut remote);
+                    use_proper_ipv4(&mut local);
+
+                    // Find local interface.
+                    let Some(interface) = interface_name_for_addr(local.ip())? else {
+                        tracing::warn!(
+                            "Interface for incoming connection from {remote} to {local} not found, rejecting."
+                        );
+                        continue;
+                    };
+
+                    let _ = socket.set_nodelay(true);
+                    handshakes.push(
+                        Self::accept(self.path.clone(), socket, remote, interface, self.ping_interval)
+                            .map(move |res| (remote, res))
+                    );
+                }
+                Some((remote, res)) = handshakes.next() => {
+                    match res {
+                        Ok(accepted) => {
+                            tracing::debug!("Accepted WebSocket connection {}", &accepted.tag);
+                            let _ = tx.send(accepted).await;
+                        }
+                        Err(err) => tracing::debug!("WebSocket handshake with {remote} failed: {err}"),
+                    }
+                }
+            }
+        }
+    }
+}
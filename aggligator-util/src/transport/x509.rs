@@ -0,0 +1,269 @@
+//! Minimal X.509 certificate parsing.
+
+use std::{
+    fmt::Write,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+const TAG_OID: u8 = 0x06;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_VERSION: u8 = 0xa0;
+const TAG_EXTENSIONS: u8 = 0xa3;
+
+const TAG_RFC822_NAME: u8 = 0x81;
+const TAG_DNS_NAME: u8 = 0x82;
+const TAG_URI: u8 = 0x86;
+const TAG_IP_ADDRESS: u8 = 0x87;
+
+/// Object identifier of the subject alternative name extension (2.5.29.17).
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+const TAG_UTF8_STRING: u8 = 0x0c;
+const TAG_PRINTABLE_STRING: u8 = 0x13;
+const TAG_T61_STRING: u8 = 0x14;
+const TAG_IA5_STRING: u8 = 0x16;
+const TAG_BMP_STRING: u8 = 0x1e;
+
+/// Short names of well-known attribute types (RFC 4514).
+const ATTRIBUTE_NAMES: &[(&str, &str)] = &[
+    ("2.5.4.3", "CN"),
+    ("2.5.4.6", "C"),
+    ("2.5.4.7", "L"),
+    ("2.5.4.8", "ST"),
+    ("2.5.4.9", "STREET"),
+    ("2.5.4.10", "O"),
+    ("2.5.4.11", "OU"),
+    ("0.9.2342.19200300.100.1.1", "UID"),
+    ("0.9.2342.19200300.100.1.25", "DC"),
+];
+
+/// Reads a DER element and returns its tag, its contents and the remaining data.
+fn read_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&len, mut data) = data.split_first()?;
+
+    let len = if len & 0x80 == 0 {
+        usize::from(len)
+    } else {
+        let n = usize::from(len & 0x7f);
+        if n == 0 || n > std::mem::size_of::<usize>() || data.len() < n {
+            return None;
+        }
+        let (len_bytes, rest) = data.split_at(n);
+        data = rest;
+        len_bytes.iter().fold(0, |len, &b| len << 8 | usize::from(b))
+    };
+
+    if data.len() < len {
+        return None;
+    }
+    let (contents, rest) = data.split_at(len);
+    Some((tag, contents, rest))
+}
+
+/// Reads a DER element with the specified tag.
+fn expect_element(data: &[u8], expected: u8) -> Option<(&[u8], &[u8])> {
+    match read_element(data)? {
+        (tag, contents, rest) if tag == expected => Some((contents, rest)),
+        _ => None,
+    }
+}
+
+/// Formats an object identifier in dotted-decimal notation.
+fn format_oid(oid: &[u8]) -> Option<String> {
+    let (&first, rest) = oid.split_first()?;
+    let mut out = format!("{}.{}", (first / 40).min(2), first - 40 * (first / 40).min(2));
+
+    let mut value: u64 = 0;
+    for &b in rest {
+        value = value.checked_mul(128)? | u64::from(b & 0x7f);
+        if b & 0x80 == 0 {
+            write!(out, ".{value}").unwrap();
+            value = 0;
+        }
+    }
+
+    Some(out)
+}
+
+/// Formats an attribute value as escaped string (RFC 4514).
+///
+/// `raw` is the complete DER element, which is hex-encoded if the value is not a string.
+fn format_value(tag: u8, value: &[u8], raw: &[u8]) -> String {
+    let text = match tag {
+        TAG_UTF8_STRING | TAG_PRINTABLE_STRING | TAG_IA5_STRING => String::from_utf8(value.to_vec()).ok(),
+        TAG_T61_STRING => Some(value.iter().map(|&b| char::from(b)).collect()),
+        TAG_BMP_STRING if value.len() % 2 == 0 => {
+            let units: Vec<u16> = value.chunks(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+            String::from_utf16(&units).ok()
+        }
+        _ => None,
+    };
+
+    let Some(text) = text else {
+        let mut out = "#".to_string();
+        for b in raw {
+            write!(out, "{b:02x}").unwrap();
+        }
+        return out;
+    };
+
+    let mut out = String::with_capacity(text.len());
+    let last = text.chars().count().saturating_sub(1);
+    for (i, c) in text.chars().enumerate() {
+        match c {
+            ',' | '+' | '"' | '\\' | '<' | '>' | ';' => out.push('\\'),
+            '#' if i == 0 => out.push('\\'),
+            ' ' if i == 0 || i == last => out.push('\\'),
+            _ => (),
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Formats a distinguished name as string (RFC 4514).
+fn format_name(mut name: &[u8]) -> Option<String> {
+    let mut rdns = Vec::new();
+
+    while !name.is_empty() {
+        let (mut rdn, rest) = expect_element(name, TAG_SET)?;
+        name = rest;
+
+        let mut attrs = Vec::new();
+        while !rdn.is_empty() {
+            let (attr, rest) = expect_element(rdn, TAG_SEQUENCE)?;
+            rdn = rest;
+
+            let (oid, attr) = expect_element(attr, TAG_OID)?;
+            let (value_tag, value, rest) = read_element(attr)?;
+            let raw = &attr[..attr.len() - rest.len()];
+
+            let oid = format_oid(oid)?;
+            let attr_type = match ATTRIBUTE_NAMES.iter().find(|(known, _)| *known == oid) {
+                Some((_, short)) => short.to_string(),
+                None => oid,
+            };
+            attrs.push(format!("{attr_type}={}", format_value(value_tag, value, raw)));
+        }
+        rdns.push(attrs.join("+"));
+    }
+
+    rdns.reverse();
+    Some(rdns.join(","))
+}
+
+/// Returns the fields of a DER-encoded X.509 certificate starting with the subject.
+fn subject_and_following(cert: &[u8]) -> Option<&[u8]> {
+    let (cert, _) = expect_element(cert, TAG_SEQUENCE)?;
+    let (tbs, _) = expect_element(cert, TAG_SEQUENCE)?;
+
+    let mut tbs = tbs;
+    if let Some((TAG_VERSION, _, rest)) = read_element(tbs) {
+        tbs = rest;
+    }
+
+    // Skip serial number, signature algorithm, issuer and validity.
+    for _ in 0..4 {
+        let (_, _, rest) = read_element(tbs)?;
+        tbs = rest;
+    }
+
+    Some(tbs)
+}
+
+/// Returns the subject of a DER-encoded X.509 certificate as string (RFC 4514).
+///
+/// Returns `None` if the certificate cannot be parsed.
+pub(crate) fn subject(cert: &[u8]) -> Option<String> {
+    let (subject, _) = expect_element(subject_and_following(cert)?, TAG_SEQUENCE)?;
+    format_name(subject)
+}
+
+/// Returns the DER-encoded subject public key info of a DER-encoded X.509 certificate.
+///
+/// Returns `None` if the certificate cannot be parsed.
+pub(crate) fn subject_public_key_info(cert: &[u8]) -> Option<&[u8]> {
+    let (_, _, tbs) = read_element(subject_and_following(cert)?)?;
+    let (_, _, rest) = read_element(tbs)?;
+    Some(&tbs[..tbs.len() - rest.len()])
+}
+
+/// Formats a general name of a subject alternative name extension.
+///
+/// Returns `None` for name types that are not supported.
+fn format_general_name(tag: u8, value: &[u8]) -> Option<String> {
+    let text = || String::from_utf8(value.to_vec()).ok();
+    match tag {
+        TAG_RFC822_NAME => Some(format!("email:{}", text()?)),
+        TAG_DNS_NAME => Some(format!("DNS:{}", text()?)),
+        TAG_URI => Some(format!("URI:{}", text()?)),
+        TAG_IP_ADDRESS => {
+            let addr: IpAddr = match value.len() {
+                4 => Ipv4Addr::from(<[u8; 4]>::try_from(value).ok()?).into(),
+                16 => Ipv6Addr::from(<[u8; 16]>::try_from(value).ok()?).into(),
+                _ => return None,
+            };
+            Some(format!("IP:{addr}"))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the subject alternative names of a DER-encoded X.509 certificate.
+///
+/// Email addresses, DNS names, URIs and IP addresses are returned with the prefixes
+/// `email:`, `DNS:`, `URI:` and `IP:` respectively; other name types are omitted.
+/// Returns an empty list if the certificate has no such names or cannot be parsed.
+pub(crate) fn subject_alt_names(cert: &[u8]) -> Vec<String> {
+    let names = || {
+        // Skip subject and subject public key info.
+        let mut tbs = subject_and_following(cert)?;
+        for _ in 0..2 {
+            let (_, _, rest) = read_element(tbs)?;
+            tbs = rest;
+        }
+
+        // Find extensions, skipping unique identifiers.
+        let extensions = loop {
+            match read_element(tbs)? {
+                (TAG_EXTENSIONS, extensions, _) => break extensions,
+                (_, _, rest) => tbs = rest,
+            }
+        };
+        let (mut extensions, _) = expect_element(extensions, TAG_SEQUENCE)?;
+
+        while !extensions.is_empty() {
+            let (extension, rest) = expect_element(extensions, TAG_SEQUENCE)?;
+            extensions = rest;
+
+            let (oid, mut extension) = expect_element(extension, TAG_OID)?;
+            if oid != OID_SUBJECT_ALT_NAME {
+                continue;
+            }
+
+            // Skip the critical flag and parse the contained sequence of general names.
+            loop {
+                match read_element(extension)? {
+                    (TAG_OCTET_STRING, value, _) => {
+                        let (mut general_names, _) = expect_element(value, TAG_SEQUENCE)?;
+                        let mut names = Vec::new();
+                        while !general_names.is_empty() {
+                            let (tag, value, rest) = read_element(general_names)?;
+                            general_names = rest;
+                            names.extend(format_general_name(tag, value));
+                        }
+                        return Some(names);
+                    }
+                    (_, _, rest) => extension = rest,
+                }
+            }
+        }
+
+        None
+    };
+
+    names().unwrap_or_default()
+}
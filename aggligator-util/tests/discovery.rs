@@ -0,0 +1,50 @@
+//! mDNS service discovery transport tests.
+
+use std::{process, time::Duration};
+use tokio::time::timeout;
+
+use aggligator_util::transport::{
+    discovery::{DiscoveryAcceptor, DiscoveryConnector, DiscoveryLinkTag},
+    Acceptor, Connector,
+};
+
+const SERVICE_TYPE: &str = "_aggligator-test._tcp";
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn discover_and_connect() {
+    let instance = format!("test-{}", process::id());
+
+    let acceptor = Acceptor::new();
+    let _discovery_acceptor = acceptor.add(DiscoveryAcceptor::new(SERVICE_TYPE, &instance, 0).await.unwrap());
+
+    let mut connector = Connector::new();
+    let mut discovery = DiscoveryConnector::new(SERVICE_TYPE).unwrap();
+    discovery.set_query_interval(Duration::from_secs(1));
+    let filter_instance = instance.clone();
+    discovery.set_instance_filter(move |name| name == filter_instance);
+    let _discovery_connector = connector.add(discovery);
+    let client_control = connector.control();
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let ((_server_ch, server_control), _client_ch) =
+        timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+            .await
+            .expect("connection was not established");
+
+    for link in client_control.links() {
+        let tag = link.tag().as_any().downcast_ref::<DiscoveryLinkTag>().unwrap();
+        assert_eq!(tag.instance, instance);
+        assert!(tag.interface.is_some());
+    }
+    for link in server_control.links() {
+        let tag = link.tag().as_any().downcast_ref::<DiscoveryLinkTag>().unwrap();
+        assert_eq!(tag.instance, instance);
+    }
+}
+
+#[test]
+fn invalid_service_type() {
+    assert!(DiscoveryConnector::new("aggligator").is_err());
+    assert!(DiscoveryConnector::new("_aggligator._tcp.local").is_err());
+}
@@ -0,0 +1,99 @@
+//! Connection-level encryption tests.
+
+use futures::join;
+use std::{io::ErrorKind, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::timeout,
+};
+
+use aggligator::Cfg;
+use aggligator_util::transport::{
+    encryption::Encryption, memory::MemoryHub, Acceptor, AcceptorBuilder, Connector, ConnectorBuilder,
+};
+
+const KEY: &[u8] = b"aggligator test pre-shared key";
+
+fn acceptor(key: &[u8]) -> Acceptor {
+    let mut builder = AcceptorBuilder::new(Cfg::default());
+    builder.set_encryption(Encryption::new(key).unwrap());
+    builder.build()
+}
+
+fn connector(key: &[u8]) -> Connector {
+    let mut builder = ConnectorBuilder::new(Cfg::default());
+    builder.set_encryption(Encryption::new(key).unwrap());
+    builder.build()
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn encryption_echo() {
+    const COUNT: usize = 1_000_000;
+
+    let hub = MemoryHub::with_links(2);
+
+    let acceptor = acceptor(KEY);
+    let _acceptor = acceptor.add(hub.acceptor());
+    assert!(acceptor.accept().await.is_err());
+
+    let mut connector = connector(KEY);
+    let _connector = connector.add(hub.connector());
+    assert!(connector.channel().is_none());
+
+    let server = async {
+        let (mut stream, _control) = acceptor.accept_stream().await.unwrap();
+        let mut buf = vec![0; 8192];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            stream.write_all(&buf[..n]).await.unwrap();
+        }
+        stream.shutdown().await.unwrap();
+    };
+
+    let client = async {
+        let stream = connector.stream().unwrap().await.unwrap();
+        let (mut rx, mut tx) = tokio::io::split(stream);
+
+        let data: Vec<u8> = (0..COUNT).map(|i| i as u8).collect();
+        let writer = async {
+            tx.write_all(&data).await.unwrap();
+            tx.shutdown().await.unwrap();
+        };
+        let reader = async {
+            let mut received = Vec::new();
+            rx.read_to_end(&mut received).await.unwrap();
+            received
+        };
+        let ((), received) = join!(writer, reader);
+        assert_eq!(received, data);
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server, client) }).await.expect("echo timed out");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn encryption_key_mismatch() {
+    let hub = MemoryHub::with_links(1);
+
+    let acceptor = acceptor(KEY);
+    let _acceptor = acceptor.add(hub.acceptor());
+
+    let mut connector = connector(b"another pre-shared key");
+    let _connector = connector.add(hub.connector());
+
+    let (server_res, client_res) =
+        timeout(Duration::from_secs(30), async { join!(acceptor.accept_stream(), connector.stream().unwrap()) })
+            .await
+            .expect("handshake timed out");
+
+    assert_eq!(server_res.err().unwrap().kind(), ErrorKind::PermissionDenied);
+    assert_eq!(client_res.err().unwrap().kind(), ErrorKind::PermissionDenied);
+}
+
+#[test]
+fn encryption_short_key() {
+    assert_eq!(Encryption::new(b"short").unwrap_err().kind(), ErrorKind::InvalidInput);
+}
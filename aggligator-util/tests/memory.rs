@@ -0,0 +1,301 @@
+//! In-memory transport tests.
+
+use futures::join;
+use std::{collections::HashSet, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::{sleep, timeout},
+};
+
+use aggligator::{control::Direction, Cfg, Control};
+use aggligator_util::transport::{
+    memory::{MemoryHub, MemoryLinkTag},
+    Acceptor, AcceptorBuilder, Connector, ConnectorBuilder, LinkAuthorization, LinkTagBox, MetadataLinkTag,
+};
+
+async fn wait_for_links<TX, RX, TAG>(control: &Control<TX, RX, TAG>, count: usize) {
+    timeout(Duration::from_secs(30), async {
+        while control.links().len() != count {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("{count} links were not established"));
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn memory_failover() {
+    const COUNT: usize = 1_000_000;
+
+    let hub = MemoryHub::with_links(2);
+
+    let acceptor = Acceptor::new();
+    let _acceptor = acceptor.add(hub.acceptor());
+
+    let mut connector = Connector::new();
+    let _connector = connector.add(hub.connector());
+    let control = connector.control();
+
+    let server = async {
+        let (ch, _control) = acceptor.accept().await.unwrap();
+        let mut stream = ch.into_stream();
+        let mut buf = vec![0; 8192];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            stream.write_all(&buf[..n]).await.unwrap();
+        }
+        stream.shutdown().await.unwrap();
+    };
+
+    let client = async {
+        let ch = connector.channel().unwrap().await.unwrap();
+        wait_for_links(&control, 2).await;
+
+        let (mut rx, mut tx) = tokio::io::split(ch.into_stream());
+        let data: Vec<u8> = (0..COUNT).map(|i| i as u8).collect();
+        let writer = async {
+            for chunk in data.chunks(COUNT / 10) {
+                tx.write_all(chunk).await.unwrap();
+                sleep(Duration::from_millis(10)).await;
+            }
+            tx.shutdown().await.unwrap();
+        };
+        let reader = async {
+            let mut received = Vec::new();
+            rx.read_to_end(&mut received).await.unwrap();
+            received
+        };
+        let killer = async {
+            sleep(Duration::from_millis(30)).await;
+            assert_eq!(hub.kill_link("link0"), 1);
+        };
+        let ((), received, ()) = join!(writer, reader, killer);
+        assert_eq!(received.len(), data.len());
+        assert!(received == data, "received data mismatch");
+    };
+
+    join!(server, client);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn memory_add_remove_links() {
+    let hub = MemoryHub::new();
+    assert!(hub.add_link("a"));
+    assert!(!hub.add_link("a"));
+
+    let acceptor = Acceptor::new();
+    let _acceptor = acceptor.add(hub.acceptor());
+
+    let mut connector = Connector::new();
+    let _connector = connector.add(hub.connector());
+    let control = connector.control();
+
+    let server = async {
+        let (_ch, _control) = acceptor.accept().await.unwrap();
+        sleep(Duration::from_secs(5)).await;
+    };
+
+    let client = async {
+        let _ch = connector.channel().unwrap().await.unwrap();
+        wait_for_links(&control, 1).await;
+
+        hub.add_link("b");
+        hub.add_link("c");
+        wait_for_links(&control, 3).await;
+
+        assert!(hub.remove_link("b"));
+        assert!(!hub.remove_link("b"));
+        wait_for_links(&control, 2).await;
+    };
+
+    join!(server, client);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn memory_maintain_links() {
+    let hub = MemoryHub::with_links(4);
+
+    let acceptor = Acceptor::new();
+    let _acceptor = acceptor.add(hub.acceptor());
+
+    let mut builder = ConnectorBuilder::new(Cfg::default());
+    builder.set_reconnect_delay(Duration::from_millis(100));
+    let mut connector = builder.build();
+    let maintain = connector.maintain(2);
+    assert_eq!(maintain.target_links(), 2);
+    let _connector = connector.add(hub.connector());
+    let control = connector.control();
+
+    let server = async {
+        let (_ch, _control) = acceptor.accept().await.unwrap();
+        sleep(Duration::from_secs(5)).await;
+    };
+
+    let client = async {
+        let _ch = connector.channel().unwrap().await.unwrap();
+        wait_for_links(&control, 2).await;
+        sleep(Duration::from_millis(500)).await;
+        assert_eq!(control.links().len(), 2);
+
+        let link = control.links().pop().unwrap();
+        let tag: &MemoryLinkTag = link.tag().as_any().downcast_ref().unwrap();
+        assert!(hub.remove_link(&tag.name));
+        timeout(Duration::from_secs(30), async {
+            while control.links().len() != 2 || control.links().iter().any(|other| other.tag() == link.tag()) {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("removed link was not replaced");
+
+        maintain.set_target_links(3);
+        wait_for_links(&control, 3).await;
+
+        drop(maintain);
+        wait_for_links(&control, 3).await;
+        sleep(Duration::from_millis(500)).await;
+        assert_eq!(control.links().len(), 3);
+    };
+
+    join!(server, client);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn memory_link_authorizer() {
+    let hub = MemoryHub::new();
+    hub.add_link("a0");
+
+    let mut builder = AcceptorBuilder::new(Cfg::default());
+    builder.set_link_authorizer(|tag, _user_data| {
+        let tag: &MemoryLinkTag = tag.as_any().downcast_ref().unwrap();
+        match tag.name.as_bytes() {
+            [b'a' | b'b', ..] => LinkAuthorization::AcceptGrouped(tag.name.as_bytes()[..1].to_vec()),
+            _ => LinkAuthorization::Reject,
+        }
+    });
+    let acceptor = builder.build();
+    let _acceptor = acceptor.add(hub.acceptor());
+
+    let mut connector = Connector::new();
+    let _connector = connector.add(hub.connector());
+    let control = connector.control();
+
+    let server = async {
+        let (_ch, _control) = acceptor.accept().await.unwrap();
+        sleep(Duration::from_secs(5)).await;
+    };
+
+    let client = async {
+        let _ch = connector.channel().unwrap().await.unwrap();
+        wait_for_links(&control, 1).await;
+
+        hub.add_link("a1");
+        hub.add_link("b0");
+        hub.add_link("denied");
+        wait_for_links(&control, 2).await;
+        sleep(Duration::from_millis(500)).await;
+
+        let mut names: Vec<_> = control
+            .links()
+            .iter()
+            .map(|link| link.tag().as_any().downcast_ref::<MemoryLinkTag>().unwrap().name.clone())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["a0", "a1"]);
+    };
+
+    join!(server, client);
+}
+
+#[test]
+fn memory_link_tag_metadata() {
+    let tag = MemoryLinkTag::new("a0", Direction::Outgoing);
+    let metadata = |carrier: &str| [("carrier".to_string(), carrier.to_string())];
+
+    let plain: LinkTagBox = Box::new(tag.clone());
+    let a: LinkTagBox = Box::new(MetadataLinkTag::new(tag.clone(), metadata("a")));
+    let b: LinkTagBox = Box::new(MetadataLinkTag::new(tag.clone(), metadata("b")));
+
+    assert_eq!(a.metadata().unwrap()["carrier"], "a");
+    assert!(plain.metadata().is_none());
+    assert_eq!(a.downcast_ref::<MemoryLinkTag>(), Some(&tag));
+    assert_eq!(a.to_string(), "-> a0 {carrier=a}");
+    assert!(*a == *plain && *a == *b);
+    assert_eq!(HashSet::from([a.clone(), b.clone()]).len(), 1);
+
+    let compared = |carrier| -> LinkTagBox {
+        let mut tag = MetadataLinkTag::new(tag.clone(), metadata(carrier));
+        tag.set_compare_metadata(true);
+        Box::new(tag)
+    };
+    assert!(compared("a") != compared("b"));
+    assert!(compared("a") == compared("a"));
+    assert_eq!(HashSet::from([compared("a"), compared("b")]).len(), 2);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn memory_link_groups() {
+    let hub_a = MemoryHub::new();
+    hub_a.add_link("a0");
+    hub_a.add_link("a1");
+    let hub_b = MemoryHub::new();
+    hub_b.add_link("b0");
+    hub_b.add_link("x0");
+
+    let mut builder = AcceptorBuilder::new(Cfg::default());
+    builder.set_link_authorizer(|tag, _user_data| {
+        let tag: &MemoryLinkTag = tag.as_any().downcast_ref().unwrap();
+        LinkAuthorization::AcceptGrouped(tag.name.as_bytes()[..1].to_vec())
+    });
+    let acceptor = builder.build();
+    let _acceptor_a = acceptor.add(hub_a.acceptor());
+    let _acceptor_b = acceptor.add(hub_b.acceptor());
+
+    let mut connector_a = Connector::new();
+    let _connector_a = connector_a.add(hub_a.connector());
+    let mut connector_b = Connector::new();
+    let _connector_b = connector_b.add(hub_b.connector());
+
+    let link_groups =
+        |names: Vec<String>| -> HashSet<u8> { names.iter().map(|name| name.as_bytes()[0]).collect() };
+    let link_names = |links: Vec<aggligator::Link<LinkTagBox>>| -> Vec<String> {
+        let mut names: Vec<_> = links
+            .iter()
+            .map(|link| link.tag().as_any().downcast_ref::<MemoryLinkTag>().unwrap().name.clone())
+            .collect();
+        names.sort();
+        names
+    };
+
+    let server = async {
+        let (_ch1, control1) = acceptor.accept().await.unwrap();
+        let (_ch2, control2) = acceptor.accept().await.unwrap();
+        sleep(Duration::from_secs(1)).await;
+
+        // Each client forms its own connection.
+        assert_ne!(control1.id(), control2.id());
+        let groups1 = link_groups(link_names(control1.links()));
+        let groups2 = link_groups(link_names(control2.links()));
+        assert_eq!(groups1.len(), 1);
+        assert_eq!(groups2.len(), 1);
+        assert_ne!(groups1, groups2);
+        sleep(Duration::from_secs(1)).await;
+    };
+
+    let client = async {
+        let _ch_a = connector_a.channel().unwrap().await.unwrap();
+        let _ch_b = connector_b.channel().unwrap().await.unwrap();
+        wait_for_links(&connector_a.control(), 2).await;
+        sleep(Duration::from_millis(500)).await;
+
+        // The link of the second client with another group key is refused.
+        assert_eq!(link_names(connector_a.control().links()), ["a0", "a1"]);
+        assert_eq!(link_groups(link_names(connector_b.control().links())).len(), 1);
+        sleep(Duration::from_secs(1)).await;
+    };
+
+    timeout(Duration::from_secs(30), async { join!(server, client) }).await.expect("test timed out");
+}
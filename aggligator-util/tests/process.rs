@@ -0,0 +1,36 @@
+//! Child process transport tests.
+
+#![cfg(unix)]
+
+use std::time::Duration;
+use tokio::time::timeout;
+
+use aggligator_util::transport::{process::ProcessConnector, Connector};
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn process_exit_error() {
+    let connector = Connector::new();
+    let mut link_errors = connector.link_errors();
+    let _process = connector.add(ProcessConnector::new("sh", ["-c", "echo something went wrong >&2; exit 3"]));
+
+    let error = timeout(Duration::from_secs(30), link_errors.recv())
+        .await
+        .expect("process exit was not reported")
+        .unwrap();
+    let msg = error.error.to_string();
+    assert!(msg.contains("exit status: 3"), "unexpected error: {msg}");
+    assert!(msg.contains("something went wrong"), "unexpected error: {msg}");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn process_not_found() {
+    let connector = Connector::new();
+    let mut link_errors = connector.link_errors();
+    let _process = connector.add(ProcessConnector::new("/nonexistent/aggligator-command", [] as [&str; 0]));
+
+    let error = timeout(Duration::from_secs(30), link_errors.recv())
+        .await
+        .expect("spawn failure was not reported")
+        .unwrap();
+    assert!(error.error.to_string().starts_with("cannot run"), "unexpected error: {}", error.error);
+}
@@ -0,0 +1,188 @@
+//! QUIC transport tests.
+
+use futures::join;
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, PrivateKey, ServerName,
+};
+use rustls_pemfile::{certs, pkcs8_private_keys};
+use std::{
+    collections::HashSet,
+    io::BufReader,
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::{sleep, timeout},
+};
+
+use aggligator_util::transport::{
+    quic::{QuicAcceptor, QuicConnector, QuicLinkTag},
+    tcp::{TcpAcceptor, TcpConnector},
+    Acceptor, Connector,
+};
+
+static TLS_CERT_PEM: &[u8] = include_bytes!("../src/bin/agg-speed-cert.pem");
+static TLS_KEY_PEM: &[u8] = include_bytes!("../src/bin/agg-speed-key.pem");
+static TLS_SERVER_NAME: &str = "aggligator.rs";
+
+fn tls_cert() -> Certificate {
+    let mut reader = BufReader::new(TLS_CERT_PEM);
+    Certificate(certs(&mut reader).unwrap().pop().unwrap())
+}
+
+fn tls_key() -> PrivateKey {
+    let mut reader = BufReader::new(TLS_KEY_PEM);
+    PrivateKey(pkcs8_private_keys(&mut reader).unwrap().pop().unwrap())
+}
+
+/// Accepts every TLS server certificate.
+struct TlsNullVerifier;
+
+impl ServerCertVerifier for TlsNullVerifier {
+    fn verify_server_cert(
+        &self, _end_entity: &Certificate, _intermediates: &[Certificate], _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>, _ocsp_response: &[u8], _now: std::time::SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+fn tls_client_config() -> Arc<ClientConfig> {
+    Arc::new(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(TlsNullVerifier))
+            .with_no_client_auth(),
+    )
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn quic_and_tcp() {
+    const QUIC_PORT: u16 = 5811;
+    const TCP_PORT: u16 = 5812;
+    const COUNT: usize = 1_000_000;
+
+    let acceptor = Acceptor::new();
+    let _quic_acceptor = acceptor.add(
+        QuicAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), QUIC_PORT)], vec![tls_cert()], tls_key())
+            .unwrap(),
+    );
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), TCP_PORT)]).await.unwrap());
+
+    let mut connector = Connector::new();
+    let _quic_connector = connector.add(
+        QuicConnector::new(["127.0.0.1".to_string()], QUIC_PORT, TLS_SERVER_NAME, tls_client_config())
+            .await
+            .unwrap(),
+    );
+    let _tcp_connector = connector.add(TcpConnector::new(["127.0.0.1".to_string()], TCP_PORT).await.unwrap());
+    let control = connector.control();
+
+    let server = async {
+        let (ch, _control) = acceptor.accept().await.unwrap();
+        let mut stream = ch.into_stream();
+        let mut buf = vec![0; 8192];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            stream.write_all(&buf[..n]).await.unwrap();
+        }
+        stream.shutdown().await.unwrap();
+    };
+
+    let client = async {
+        let ch = connector.channel().unwrap().await.unwrap();
+
+        timeout(Duration::from_secs(30), async {
+            loop {
+                let transports: HashSet<_> =
+                    control.links().iter().map(|link| link.tag().transport_name().to_string()).collect();
+                if transports.contains("quic") && transports.contains("tcp") {
+                    break;
+                }
+                sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .expect("links of both transports were not established");
+
+        let (mut rx, mut tx) = tokio::io::split(ch.into_stream());
+        let data: Vec<u8> = (0..COUNT).map(|i| i as u8).collect();
+        let writer = async {
+            tx.write_all(&data).await.unwrap();
+            tx.shutdown().await.unwrap();
+        };
+        let reader = async {
+            let mut received = Vec::new();
+            rx.read_to_end(&mut received).await.unwrap();
+            received
+        };
+        let ((), received) = join!(writer, reader);
+        assert_eq!(received, data);
+    };
+
+    join!(server, client);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn quic_zero_rtt() {
+    const PORT: u16 = 5879;
+
+    let acceptor = Acceptor::new();
+    let _quic_acceptor = acceptor.add(
+        QuicAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)], vec![tls_cert()], tls_key())
+            .unwrap(),
+    );
+
+    let mut tls_client_cfg = (*tls_client_config()).clone();
+    tls_client_cfg.enable_early_data = true;
+    let mut quic_connector =
+        QuicConnector::new(["127.0.0.1".to_string()], PORT, TLS_SERVER_NAME, Arc::new(tls_client_cfg))
+            .await
+            .unwrap();
+    quic_connector.set_zero_rtt(true);
+    let mut connector = Connector::new();
+    let _quic_connector = connector.add(quic_connector);
+    let control = connector.control();
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let ((_server_ch, _server_control), _client_ch) =
+        timeout(Duration::from_secs(30), async { join!(server, client) })
+            .await
+            .expect("connection was not established");
+
+    // The first link performs a full handshake.
+    let link = timeout(Duration::from_secs(30), async {
+        loop {
+            if let Some(link) = control.links().pop() {
+                break link;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("link was not established");
+    assert!(!link.tag().downcast_ref::<QuicLinkTag>().unwrap().zero_rtt);
+
+    // The reestablished link resumes the session using 0-RTT.
+    link.disconnect().await;
+    let tag = timeout(Duration::from_secs(30), async {
+        loop {
+            if let Some(new_link) = control.links().into_iter().find(|new_link| new_link.id() != link.id()) {
+                break new_link.tag().downcast_ref::<QuicLinkTag>().unwrap().clone();
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("link was not reestablished");
+    tracing::info!("reestablished link: {tag}");
+    assert!(tag.zero_rtt);
+}
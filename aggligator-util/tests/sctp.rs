@@ -0,0 +1,72 @@
+//! SCTP transport tests.
+
+use futures::join;
+use std::{
+    io::ErrorKind,
+    net::{Ipv4Addr, SocketAddr},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use aggligator_util::transport::{
+    sctp::{SctpAcceptor, SctpConnector, SctpLinkTag},
+    Acceptor, Connector,
+};
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn sctp_loopback() {
+    const PORT: u16 = 5841;
+    const COUNT: usize = 1_000_000;
+
+    let sctp_acceptor = match SctpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await {
+        Ok(sctp_acceptor) => sctp_acceptor,
+        Err(err) if err.kind() == ErrorKind::Unsupported => {
+            println!("SCTP is not supported on this host: {err}");
+            return;
+        }
+        Err(err) => panic!("cannot listen on SCTP port {PORT}: {err}"),
+    };
+
+    let acceptor = Acceptor::new();
+    let _sctp_acceptor = acceptor.add(sctp_acceptor);
+
+    let mut connector = Connector::new();
+    let _sctp_connector = connector.add(SctpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap());
+
+    let server = async {
+        let (ch, control) = acceptor.accept().await.unwrap();
+        let link = &control.links()[0];
+        let tag = link.tag().as_any().downcast_ref::<SctpLinkTag>().unwrap();
+        assert_eq!(tag.remote.ip(), Ipv4Addr::LOCALHOST);
+
+        let mut stream = ch.into_stream();
+        let mut buf = vec![0; 8192];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            stream.write_all(&buf[..n]).await.unwrap();
+        }
+        stream.shutdown().await.unwrap();
+    };
+
+    let client = async {
+        let ch = connector.channel().unwrap().await.unwrap();
+
+        let (mut rx, mut tx) = tokio::io::split(ch.into_stream());
+        let data: Vec<u8> = (0..COUNT).map(|i| i as u8).collect();
+        let writer = async {
+            tx.write_all(&data).await.unwrap();
+            tx.shutdown().await.unwrap();
+        };
+        let reader = async {
+            let mut received = Vec::new();
+            rx.read_to_end(&mut received).await.unwrap();
+            received
+        };
+        let ((), received) = join!(writer, reader);
+        assert_eq!(received, data);
+    };
+
+    join!(server, client);
+}
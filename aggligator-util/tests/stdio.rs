@@ -0,0 +1,136 @@
+//! Stdio transport tests.
+//!
+//! The test binary runs itself as a child process, which accepts a link
+//! over its standard input and output.
+
+use futures::join;
+use std::{process::Stdio, time::Duration};
+use tokio::{
+    io::{duplex, stdout, AsyncReadExt, AsyncWriteExt},
+    process::Command,
+    time::timeout,
+};
+
+use aggligator::control::Direction;
+use aggligator_util::transport::{
+    stdio::{StdioAcceptor, StdioLinkTag},
+    Acceptor, Connector, IoBox,
+};
+
+/// Environment variable that selects child mode.
+const CHILD_ENV: &str = "AGGLIGATOR_STDIO_TEST_CHILD";
+
+/// Written by the child before its standard output is used for the link.
+///
+/// The test harness may print to standard output before the test runs.
+const READY: &[u8] = b"aggligator stdio child ready\n";
+
+/// Runs in the child process: echoes all data received over the stdio link.
+///
+/// No log output is enabled, since log messages from runtime threads would not be
+/// captured by the test harness and would thus corrupt the link.
+#[tokio::test(flavor = "multi_thread")]
+async fn stdio_child() {
+    if std::env::var_os(CHILD_ENV).is_none() {
+        return;
+    }
+
+    let mut out = stdout();
+    out.write_all(READY).await.unwrap();
+    out.flush().await.unwrap();
+
+    let acceptor = Acceptor::new();
+    let _stdio_acceptor = acceptor.add(StdioAcceptor::new().unwrap());
+
+    let (ch, control) = acceptor.accept().await.unwrap();
+    let links = control.links();
+    assert_eq!(links.len(), 1);
+    let tag = links[0].tag().as_any().downcast_ref::<StdioLinkTag>().unwrap();
+    assert_eq!(tag.direction, Direction::Incoming);
+
+    let mut stream = ch.into_stream();
+    let mut buf = vec![0; 8192];
+    loop {
+        let n = stream.read(&mut buf).await.unwrap();
+        if n == 0 {
+            break;
+        }
+        stream.write_all(&buf[..n]).await.unwrap();
+    }
+    stream.shutdown().await.unwrap();
+
+    // The test harness writes to standard output after the test has finished.
+    control.terminated().await.unwrap();
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn stdio_child_process() {
+    const COUNT: usize = 1_000_000;
+
+    if std::env::var_os(CHILD_ENV).is_some() {
+        return;
+    }
+
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .args(["stdio_child", "--exact", "--test-threads=1", "--quiet"])
+        .env(CHILD_ENV, "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let child_stdin = child.stdin.take().unwrap();
+    let mut child_stdout = child.stdout.take().unwrap();
+
+    timeout(Duration::from_secs(30), async {
+        let mut output = Vec::new();
+        while !output.ends_with(READY) {
+            output.push(child_stdout.read_u8().await.unwrap());
+        }
+    })
+    .await
+    .expect("child process did not start");
+
+    // The test harness of the child writes to its standard output after the link
+    // has been closed, thus the output is drained until the child exits.
+    let (link_rx, mut output_tx) = duplex(65_536);
+    tokio::spawn(async move {
+        let mut buf = vec![0; 8192];
+        loop {
+            let n = child_stdout.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            let _ = output_tx.write_all(&buf[..n]).await;
+        }
+    });
+
+    let mut connector = Connector::new();
+    connector.add_io(IoBox::new(link_rx, child_stdin), StdioLinkTag::new(Direction::Outgoing)).await.unwrap();
+
+    let ch = timeout(Duration::from_secs(30), connector.channel().unwrap())
+        .await
+        .expect("connection over child process was not established")
+        .unwrap();
+
+    let (mut rx, mut tx) = tokio::io::split(ch.into_stream());
+    let data: Vec<u8> = (0..COUNT).map(|i| i as u8).collect();
+    let writer = async {
+        tx.write_all(&data).await.unwrap();
+        tx.shutdown().await.unwrap();
+    };
+    let reader = async {
+        let mut received = Vec::new();
+        rx.read_to_end(&mut received).await.unwrap();
+        received
+    };
+    let ((), received) =
+        timeout(Duration::from_secs(60), async { join!(writer, reader) }).await.expect("transfer timed out");
+    assert_eq!(received.len(), data.len());
+    assert!(received == data, "received data mismatch");
+    drop((rx, tx));
+
+    let status =
+        timeout(Duration::from_secs(30), child.wait()).await.expect("child process did not exit").unwrap();
+    assert!(status.success(), "child process failed: {status}");
+}
@@ -0,0 +1,1465 @@
+//! TCP transport tests.
+
+use async_trait::async_trait;
+use std::{
+    collections::HashSet,
+    io::{Error, ErrorKind, IoSlice, Result},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6},
+    num::NonZeroUsize,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::{sleep, timeout, Instant},
+};
+
+use aggligator::{cfg::Cfg, control::Direction};
+use aggligator_util::transport::{
+    tcp::{
+        HttpProxy, IpVersion, KeepaliveConfig, Resolve, Socks5Proxy, TargetInfo, TcpAcceptor, TcpConnector,
+        TcpLinkTag,
+    },
+    Acceptor, ConnectTimeoutError, ConnectingTransportHandle, Connector, ConnectorBuilder, IoBox, LinkTagBox,
+};
+
+/// Resolves `server.test` to localhost and fails for all other hosts.
+#[derive(Debug)]
+struct TestResolver;
+
+#[async_trait]
+impl Resolve for TestResolver {
+    async fn resolve(&self, host: &str) -> Result<Vec<SocketAddr>> {
+        let (name, port) = host.rsplit_once(':').unwrap();
+        match name {
+            "server.test" => Ok(vec![SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port.parse().unwrap())]),
+            _ => Err(Error::new(ErrorKind::NotFound, "unknown host")),
+        }
+    }
+}
+
+/// Resolves all hosts to the port on localhost that is currently set or fails if none is set.
+#[derive(Debug)]
+struct RotatingResolver(Mutex<Option<u16>>);
+
+#[async_trait]
+impl Resolve for RotatingResolver {
+    async fn resolve(&self, _host: &str) -> Result<Vec<SocketAddr>> {
+        match *self.0.lock().unwrap() {
+            Some(port) => Ok(vec![SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port)]),
+            None => Err(Error::new(ErrorKind::Other, "temporary failure")),
+        }
+    }
+}
+
+/// Runs a minimal SOCKS5 proxy that requires username and password authentication
+/// and supports connecting to IPv4 addresses only.
+async fn socks5_proxy(listener: TcpListener, username: &'static str, password: &'static str) {
+    async fn handle(mut client: TcpStream, username: &str, password: &str) -> Result<()> {
+        let mut buf = [0; 2];
+        client.read_exact(&mut buf).await?;
+        let mut methods = vec![0; buf[1].into()];
+        client.read_exact(&mut methods).await?;
+        if !methods.contains(&0x02) {
+            client.write_all(&[0x05, 0xff]).await?;
+            return Ok(());
+        }
+        client.write_all(&[0x05, 0x02]).await?;
+
+        client.read_exact(&mut buf).await?;
+        let mut user = vec![0; buf[1].into()];
+        client.read_exact(&mut user).await?;
+        let mut pass = vec![0; client.read_u8().await?.into()];
+        client.read_exact(&mut pass).await?;
+        if user != username.as_bytes() || pass != password.as_bytes() {
+            client.write_all(&[0x01, 0x01]).await?;
+            return Ok(());
+        }
+        client.write_all(&[0x01, 0x00]).await?;
+
+        let mut req = [0; 10];
+        client.read_exact(&mut req).await?;
+        assert_eq!(req[..4], [0x05, 0x01, 0x00, 0x01]);
+        let target = SocketAddr::new(
+            Ipv4Addr::new(req[4], req[5], req[6], req[7]).into(),
+            u16::from_be_bytes([req[8], req[9]]),
+        );
+        let mut server = TcpStream::connect(target).await?;
+        client.write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0]).await?;
+
+        tokio::io::copy_bidirectional(&mut client, &mut server).await?;
+        Ok(())
+    }
+
+    loop {
+        let (client, _) = listener.accept().await.unwrap();
+        tokio::spawn(async move {
+            let _ = handle(client, username, password).await;
+        });
+    }
+}
+
+/// Runs a minimal HTTP CONNECT proxy that requires the specified `Proxy-Authorization` header.
+async fn http_proxy(listener: TcpListener, authorization: &'static str) {
+    async fn handle(mut client: TcpStream, authorization: &str) -> Result<()> {
+        let mut req = Vec::new();
+        while !req.ends_with(b"\r\n\r\n") {
+            req.push(client.read_u8().await?);
+        }
+        let req = String::from_utf8(req).unwrap();
+        let target = req.strip_prefix("CONNECT ").unwrap().split(' ').next().unwrap();
+
+        if !req.lines().any(|line| line == format!("Proxy-Authorization: {authorization}")) {
+            client.write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").await?;
+            return Ok(());
+        }
+        let mut server = TcpStream::connect(target).await?;
+        client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await?;
+
+        tokio::io::copy_bidirectional(&mut client, &mut server).await?;
+        Ok(())
+    }
+
+    loop {
+        let (client, _) = listener.accept().await.unwrap();
+        tokio::spawn(async move {
+            let _ = handle(client, authorization).await;
+        });
+    }
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn custom_resolver() {
+    const PORT: u16 = 5821;
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    let missing =
+        TcpConnector::with_resolver(["missing.test".to_string()], PORT, Arc::new(TestResolver)).await.unwrap();
+    assert_eq!(missing.check_resolution().await.unwrap_err().kind(), ErrorKind::NotFound);
+
+    let mut connector = Connector::new();
+    let mut link_errors = connector.link_errors();
+    let _tcp_connector = connector.add(
+        TcpConnector::with_resolver(
+            ["server.test".to_string(), "missing.test".to_string()],
+            PORT,
+            Arc::new(TestResolver),
+        )
+        .await
+        .unwrap(),
+    );
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established");
+
+    let error = timeout(Duration::from_secs(30), link_errors.recv())
+        .await
+        .expect("no link error for unresolved host")
+        .unwrap();
+    let tag = error.tag.as_any().downcast_ref::<TcpLinkTag>().unwrap();
+    assert!(tag.is_unresolved());
+    assert_eq!(tag.target.as_deref(), Some("missing.test:5821"));
+    assert_eq!(error.error.kind(), ErrorKind::NotFound);
+}
+
+#[cfg(target_os = "linux")]
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn missing_interface() {
+    const PORT: u16 = 5822;
+    const MISSING: &str = "aggmissing0";
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    let mut connector = Connector::new();
+    let mut link_errors = connector.link_errors();
+    let mut tcp_connector = TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap();
+    tcp_connector.set_interfaces(["lo", MISSING]);
+    let _tcp_connector = connector.add(tcp_connector);
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established");
+
+    let error = timeout(Duration::from_secs(30), link_errors.recv())
+        .await
+        .expect("no link error for missing interface")
+        .unwrap();
+    let tag = error.tag.as_any().downcast_ref::<TcpLinkTag>().unwrap();
+    assert_eq!(tag.interface, MISSING.as_bytes());
+    assert_eq!(error.error.kind(), ErrorKind::NotFound);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn interface_filter() {
+    const PORT: u16 = 5823;
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    let allowed = Arc::new(AtomicBool::new(true));
+    let mut connector = Connector::new();
+    let mut tcp_connector = TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap();
+    tcp_connector.set_resolve_interval(Duration::from_millis(100));
+    tcp_connector.set_denied_networks([("10.254.0.0".parse().unwrap(), 16)]);
+    let filter_allowed = allowed.clone();
+    tcp_connector.set_interface_filter(move |info| {
+        !info.addr.map(|addr| addr.is_loopback()).unwrap_or_default() || filter_allowed.load(Ordering::SeqCst)
+    });
+    let _tcp_connector = connector.add(tcp_connector);
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established");
+
+    let mut control = connector.control();
+    assert!(!control.links().is_empty());
+
+    tracing::info!("denying loopback interface");
+    allowed.store(false, Ordering::SeqCst);
+    timeout(Duration::from_secs(30), async {
+        while !control.links().is_empty() {
+            control.links_changed().await;
+        }
+    })
+    .await
+    .expect("link over denied interface was not disconnected");
+
+    assert!(connector.available_tags().is_empty());
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn runtime_targets() {
+    const OLD_PORT: u16 = 5824;
+    const NEW_PORT: u16 = 5825;
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(
+        TcpAcceptor::new([
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), OLD_PORT),
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), NEW_PORT),
+        ])
+        .await
+        .unwrap(),
+    );
+
+    let mut connector = Connector::new();
+    let tcp_connector = TcpConnector::new(["127.0.0.1".to_string()], OLD_PORT).await.unwrap();
+    let targets = tcp_connector.targets();
+    let _tcp_connector = connector.add(tcp_connector);
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established");
+
+    let mut control = connector.control();
+    fn link_ports<TX, RX>(control: &aggligator::Control<TX, RX, LinkTagBox>) -> Vec<u16> {
+        control
+            .links()
+            .iter()
+            .map(|link| link.tag().as_any().downcast_ref::<TcpLinkTag>().unwrap().remote.port())
+            .collect()
+    }
+    assert_eq!(link_ports(&control), [OLD_PORT]);
+
+    tracing::info!("moving target to new port");
+    assert!(targets.add(format!("127.0.0.1:{NEW_PORT}")));
+    assert!(!targets.add(format!("127.0.0.1:{NEW_PORT}")));
+    assert!(targets.remove("127.0.0.1"));
+    assert!(!targets.remove("127.0.0.1"));
+    assert_eq!(targets.get(), [format!("127.0.0.1:{NEW_PORT}")]);
+
+    timeout(Duration::from_secs(30), async {
+        while link_ports(&control) != [NEW_PORT] {
+            control.links_changed().await;
+        }
+    })
+    .await
+    .expect("link was not moved to new target");
+    assert!(!control.is_terminated());
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn runtime_listen_addrs() {
+    const OLD_PORT: u16 = 5826;
+    const NEW_PORT: u16 = 5827;
+    let old_addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), OLD_PORT);
+    let new_addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), NEW_PORT);
+
+    let acceptor = Acceptor::new();
+    let tcp_acceptor = TcpAcceptor::new([old_addr]).await.unwrap();
+    let _tcp_acceptor = acceptor.add(tcp_acceptor.clone());
+
+    let mut old_connector = Connector::new();
+    let _old_tcp_connector =
+        old_connector.add(TcpConnector::new(["127.0.0.1".to_string()], OLD_PORT).await.unwrap());
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { old_connector.channel().unwrap().await.unwrap() };
+    let (_old_server, _old_client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection over old address was not established");
+
+    tracing::info!("adding new listen address");
+    tcp_acceptor.add_addr(new_addr).await.unwrap();
+    assert_eq!(tcp_acceptor.add_addr(new_addr).await.unwrap_err().kind(), ErrorKind::AlreadyExists);
+    assert_eq!(tcp_acceptor.local_addrs(), [old_addr, new_addr]);
+
+    let mut new_connector = Connector::new();
+    let _new_tcp_connector =
+        new_connector.add(TcpConnector::new(["127.0.0.1".to_string()], NEW_PORT).await.unwrap());
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { new_connector.channel().unwrap().await.unwrap() };
+    let (_new_server, _new_client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection over new address was not established");
+
+    tracing::info!("removing old listen address");
+    assert!(tcp_acceptor.remove_addr(old_addr));
+    assert!(!tcp_acceptor.remove_addr(old_addr));
+    assert_eq!(tcp_acceptor.local_addrs(), [new_addr]);
+
+    timeout(Duration::from_secs(30), async {
+        while tokio::net::TcpStream::connect(old_addr).await.is_ok() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("old address is still accepting connections");
+
+    let control = old_connector.control();
+    assert!(!control.is_terminated());
+    assert_eq!(control.links().len(), 1);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn re_resolution() {
+    const OLD_PORT: u16 = 5828;
+    const NEW_PORT: u16 = 5829;
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(
+        TcpAcceptor::new([
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), OLD_PORT),
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), NEW_PORT),
+        ])
+        .await
+        .unwrap(),
+    );
+
+    let resolver = Arc::new(RotatingResolver(Mutex::new(Some(OLD_PORT))));
+    let mut connector = Connector::new();
+    let mut tcp_connector =
+        TcpConnector::with_resolver(["server.test".to_string()], OLD_PORT, resolver.clone()).await.unwrap();
+    tcp_connector.set_resolve_interval(Duration::from_millis(100));
+    let _tcp_connector = connector.add(tcp_connector);
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established");
+
+    let mut control = connector.control();
+    fn link_ports<TX, RX>(control: &aggligator::Control<TX, RX, LinkTagBox>) -> Vec<u16> {
+        control
+            .links()
+            .iter()
+            .map(|link| link.tag().as_any().downcast_ref::<TcpLinkTag>().unwrap().remote.port())
+            .collect()
+    }
+    assert_eq!(link_ports(&control), [OLD_PORT]);
+
+    tracing::info!("failing resolution temporarily");
+    *resolver.0.lock().unwrap() = None;
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(link_ports(&control), [OLD_PORT]);
+
+    tracing::info!("resolving to new address");
+    *resolver.0.lock().unwrap() = Some(NEW_PORT);
+    timeout(Duration::from_secs(30), async {
+        while link_ports(&control) != [NEW_PORT] {
+            control.links_changed().await;
+        }
+    })
+    .await
+    .expect("link was not moved to new address");
+    assert!(!control.is_terminated());
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn socks5_proxies() {
+    const PORT: u16 = 5830;
+    const PROXY_PORTS: [u16; 3] = [5831, 5832, 5833];
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    let mut proxies = Vec::new();
+    for port in PROXY_PORTS {
+        let addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port);
+        tokio::spawn(socks5_proxy(TcpListener::bind(addr).await.unwrap(), "user", "secret"));
+
+        let mut proxy = Socks5Proxy::new(addr);
+        let password = if port == PROXY_PORTS[2] { "wrong" } else { "secret" };
+        proxy.set_credentials("user", password);
+        proxies.push(proxy);
+    }
+
+    let mut connector = Connector::new();
+    let mut link_errors = connector.link_errors();
+    let _tcp_connector =
+        connector.add(TcpConnector::via_socks5_proxies(["127.0.0.1".to_string()], PORT, proxies).await.unwrap());
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established");
+
+    let error = timeout(Duration::from_secs(30), link_errors.recv())
+        .await
+        .expect("no link error for failed proxy authentication")
+        .unwrap();
+    let tag = error.tag.as_any().downcast_ref::<TcpLinkTag>().unwrap();
+    assert_eq!(tag.remote.port(), PROXY_PORTS[2]);
+    assert_eq!(error.error.kind(), ErrorKind::PermissionDenied);
+
+    let mut control = connector.control();
+    timeout(Duration::from_secs(30), async {
+        loop {
+            let mut proxy_ports: Vec<_> = control
+                .links()
+                .iter()
+                .map(|link| link.tag().as_any().downcast_ref::<TcpLinkTag>().unwrap().remote.port())
+                .collect();
+            proxy_ports.sort();
+            if proxy_ports == PROXY_PORTS[..2] {
+                break;
+            }
+            control.links_changed().await;
+        }
+    })
+    .await
+    .expect("links through both proxies were not established");
+
+    for link in control.links() {
+        let tag = link.tag().as_any().downcast_ref::<TcpLinkTag>().unwrap();
+        assert_eq!(tag.target, Some(format!("127.0.0.1:{PORT}")));
+    }
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn http_connect_proxy() {
+    const PORT: u16 = 5834;
+    const PROXY_PORT: u16 = 5835;
+    let proxy_addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PROXY_PORT);
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+    tokio::spawn(http_proxy(TcpListener::bind(proxy_addr).await.unwrap(), "Basic dXNlcjpzZWNyZXQ="));
+
+    tracing::info!("connecting with wrong credentials");
+    let mut proxy = HttpProxy::new(proxy_addr);
+    proxy.set_basic_auth("user", "wrong");
+    let connector = Connector::new();
+    let mut link_errors = connector.link_errors();
+    let _tcp_connector =
+        connector.add(TcpConnector::via_http_proxy(["127.0.0.1".to_string()], PORT, proxy).await.unwrap());
+
+    let error = timeout(Duration::from_secs(30), link_errors.recv())
+        .await
+        .expect("no link error for failed proxy authentication")
+        .unwrap();
+    let tag = error.tag.as_any().downcast_ref::<TcpLinkTag>().unwrap();
+    assert_eq!(tag.remote, proxy_addr);
+    assert_eq!(error.error.kind(), ErrorKind::PermissionDenied);
+    assert!(error.error.to_string().contains("407 Proxy Authentication Required"), "{}", error.error);
+    drop(connector);
+
+    tracing::info!("connecting with correct credentials");
+    let mut proxy = HttpProxy::new(proxy_addr);
+    proxy.set_basic_auth("user", "secret");
+    let mut connector = Connector::new();
+    let _tcp_connector =
+        connector.add(TcpConnector::via_http_proxy(["127.0.0.1".to_string()], PORT, proxy).await.unwrap());
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection through proxy was not established");
+
+    let links = connector.control().links();
+    let tag = links[0].tag().as_any().downcast_ref::<TcpLinkTag>().unwrap();
+    assert_eq!(tag.remote, proxy_addr);
+    assert_eq!(tag.target, Some(format!("127.0.0.1:{PORT}")));
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn prefer_ipv6() {
+    const PORT: u16 = 5836;
+    const FALLBACK_PORT: u16 = 5837;
+
+    fn link_addrs<TX, RX>(control: &aggligator::Control<TX, RX, LinkTagBox>) -> Vec<SocketAddr> {
+        control
+            .links()
+            .iter()
+            .map(|link| link.tag().as_any().downcast_ref::<TcpLinkTag>().unwrap().remote)
+            .collect()
+    }
+
+    async fn connect(port: u16) -> (Connector, ConnectingTransportHandle) {
+        let connector = Connector::new();
+        let mut tcp_connector =
+            TcpConnector::new(["127.0.0.1".to_string(), "[::1]".to_string()], port).await.unwrap();
+        tcp_connector.set_ip_version(IpVersion::PreferIPv6);
+        tcp_connector.set_ipv4_fallback_timeout(Duration::from_millis(500));
+        let handle = connector.add(tcp_connector);
+        (connector, handle)
+    }
+
+    tracing::info!("connecting to dual-stack server");
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(
+        TcpAcceptor::new([
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT),
+            SocketAddr::new(Ipv6Addr::LOCALHOST.into(), PORT),
+        ])
+        .await
+        .unwrap(),
+    );
+
+    let (mut connector, _tcp_connector) = connect(PORT).await;
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established");
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(link_addrs(&connector.control()), [SocketAddr::new(Ipv6Addr::LOCALHOST.into(), PORT)]);
+
+    tracing::info!("connecting to IPv4-only server");
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor
+        .add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), FALLBACK_PORT)]).await.unwrap());
+
+    let (mut connector, _tcp_connector) = connect(FALLBACK_PORT).await;
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established using IPv4 fallback");
+    assert_eq!(link_addrs(&connector.control()), [SocketAddr::new(Ipv4Addr::LOCALHOST.into(), FALLBACK_PORT)]);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn keepalive() {
+    const PORT: u16 = 5838;
+
+    let keepalive = KeepaliveConfig::new(Duration::from_secs(10), Duration::from_secs(2), 5);
+
+    let mut tcp_acceptor = TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap();
+    tcp_acceptor.set_keepalive(Some(keepalive)).unwrap();
+    tcp_acceptor.set_user_timeout(Duration::from_secs(30)).unwrap();
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(tcp_acceptor);
+
+    let mut tcp_connector = TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap();
+    let invalid = KeepaliveConfig::new(Duration::from_millis(100), Duration::from_secs(1), 3);
+    assert_eq!(tcp_connector.set_keepalive(Some(invalid)).unwrap_err().kind(), ErrorKind::InvalidInput);
+    let invalid = KeepaliveConfig { retries: 0, ..keepalive };
+    assert_eq!(tcp_connector.set_keepalive(Some(invalid)).unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(tcp_connector.set_user_timeout(Duration::ZERO).unwrap_err().kind(), ErrorKind::InvalidInput);
+    tcp_connector.set_keepalive(Some(KeepaliveConfig::default())).unwrap();
+    tcp_connector.set_keepalive(None).unwrap();
+    tcp_connector.set_user_timeout(Duration::from_secs(15)).unwrap();
+    assert_eq!(tcp_connector.set_keepalive(Some(keepalive)).unwrap_err().kind(), ErrorKind::InvalidInput);
+    tcp_connector.set_user_timeout(Duration::from_secs(20)).unwrap();
+    tcp_connector.set_keepalive(Some(keepalive)).unwrap();
+    assert_eq!(
+        tcp_connector.set_user_timeout(Duration::from_secs(19)).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+
+    let mut connector = Connector::new();
+    let _tcp_connector = connector.add(tcp_connector);
+
+    let server = async {
+        let (ch, _control) = acceptor.accept().await.unwrap();
+        let mut stream = ch.into_stream();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        stream.write_all(&buf).await.unwrap();
+        stream.flush().await.unwrap();
+        stream
+    };
+    let client = async {
+        let mut stream = connector.channel().unwrap().await.unwrap().into_stream();
+        stream.write_all(b"ping").await.unwrap();
+        stream.flush().await.unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        stream
+    };
+    timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn socket_options() {
+    const PORT: u16 = 5839;
+    const BUFFER_SIZE: usize = 65536;
+
+    let mut tcp_acceptor = TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap();
+    tcp_acceptor.set_nodelay(false);
+    tcp_acceptor.set_send_buffer_size(BUFFER_SIZE);
+    tcp_acceptor.set_recv_buffer_size(BUFFER_SIZE);
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(tcp_acceptor);
+
+    let mut tcp_connector = TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap();
+    tcp_connector.set_send_buffer_size(BUFFER_SIZE);
+    tcp_connector.set_recv_buffer_size(BUFFER_SIZE);
+    let mut connector = Connector::new();
+    let _tcp_connector = connector.add(tcp_connector);
+    let control = connector.control();
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let ((_server_ch, server_control), _client) =
+        timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+            .await
+            .expect("connection was not established");
+
+    let client_link = control.links().pop().unwrap();
+    let client_tag = client_link.tag().as_any().downcast_ref::<TcpLinkTag>().unwrap();
+    let client_socket = client_tag.socket.expect("no socket information on outgoing link tag");
+    tracing::info!("outgoing link socket: {client_socket}");
+    assert!(client_socket.nodelay);
+    assert!(client_socket.send_buffer_size >= BUFFER_SIZE);
+    assert!(client_socket.recv_buffer_size >= BUFFER_SIZE);
+
+    let server_link = server_control.links().pop().unwrap();
+    let server_tag = server_link.tag().as_any().downcast_ref::<TcpLinkTag>().unwrap();
+    let server_socket = server_tag.socket.expect("no socket information on incoming link tag");
+    tracing::info!("incoming link socket: {server_socket}");
+    assert!(!server_socket.nodelay);
+    assert!(server_socket.send_buffer_size >= BUFFER_SIZE);
+    assert!(server_socket.recv_buffer_size >= BUFFER_SIZE);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn tos_marking() {
+    const PORT: u16 = 5840;
+
+    let mut tcp_acceptor = TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap();
+    tcp_acceptor.set_tos(0x28);
+    tcp_acceptor.set_interface_tos("lo", 0x48);
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(tcp_acceptor);
+
+    let mut tcp_connector = TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap();
+    tcp_connector.set_tos(0xb8);
+    tcp_connector.set_traffic_class(0xb8);
+    let mut connector = Connector::new();
+    let _tcp_connector = connector.add(tcp_connector);
+
+    let server = async {
+        let (ch, _control) = acceptor.accept().await.unwrap();
+        let mut stream = ch.into_stream();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        stream.write_all(&buf).await.unwrap();
+        stream.flush().await.unwrap();
+        stream
+    };
+    let client = async {
+        let mut stream = connector.channel().unwrap().await.unwrap().into_stream();
+        stream.write_all(b"ping").await.unwrap();
+        stream.flush().await.unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        stream
+    };
+    timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("marked connection was not established");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn multiple_ports() {
+    const PORTS: [u16; 3] = [5841, 5842, 5843];
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(
+        TcpAcceptor::new(PORTS.map(|port| SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port))).await.unwrap(),
+    );
+
+    fn link_ports<TX, RX>(control: &aggligator::Control<TX, RX, LinkTagBox>) -> Vec<u16> {
+        let mut ports: Vec<_> = control
+            .links()
+            .iter()
+            .map(|link| link.tag().as_any().downcast_ref::<TcpLinkTag>().unwrap().remote.port())
+            .collect();
+        ports.sort_unstable();
+        ports
+    }
+
+    let mut connector = Connector::new();
+    let tcp_connector = TcpConnector::with_ports(["127.0.0.1".to_string()], PORTS).await.unwrap();
+    assert_eq!(tcp_connector.targets().get(), PORTS.map(|port| format!("127.0.0.1:{port}")));
+    let _tcp_connector = connector.add(tcp_connector);
+    let mut control = connector.control();
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established");
+
+    timeout(Duration::from_secs(30), async {
+        while link_ports(&control) != PORTS {
+            control.links_changed().await;
+        }
+    })
+    .await
+    .expect("links to all ports were not established");
+
+    let mut capped_connector = Connector::new();
+    let tcp_connector = TcpConnector::with_ports(["127.0.0.1".to_string()], PORTS).await.unwrap();
+    tcp_connector.link_limits().set_ports_per_interface(NonZeroUsize::new(2));
+    let _tcp_connector = capped_connector.add(tcp_connector);
+    let mut capped_control = capped_connector.control();
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { capped_connector.channel().unwrap().await.unwrap() };
+    let (_capped_server, _capped_client) =
+        timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+            .await
+            .expect("capped connection was not established");
+
+    timeout(Duration::from_secs(30), async {
+        while link_ports(&capped_control) != PORTS[..2] {
+            capped_control.links_changed().await;
+        }
+    })
+    .await
+    .expect("links to first two ports were not established");
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(link_ports(&capped_control), PORTS[..2]);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn happy_eyeballs() {
+    const PORT: u16 = 5844;
+    let refused = SocketAddr::new(Ipv6Addr::LOCALHOST.into(), PORT);
+    let listening = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT);
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(TcpAcceptor::new([listening]).await.unwrap());
+
+    // Reconnect quickly, so that addresses connected independently would fail repeatedly.
+    let mut builder = ConnectorBuilder::new(Cfg::default());
+    builder.set_reconnect_delay(Duration::from_millis(100));
+    let mut connector = builder.build();
+    let mut link_errors = connector.link_errors();
+    let mut tcp_connector = TcpConnector::new([refused.to_string(), listening.to_string()], PORT).await.unwrap();
+    tcp_connector.set_happy_eyeballs(Some(Duration::from_secs(60)));
+    let _tcp_connector = connector.add(tcp_connector);
+    let control = connector.control();
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established");
+
+    let error = timeout(Duration::from_secs(30), link_errors.recv())
+        .await
+        .expect("no link error for refused address")
+        .unwrap();
+    let tag = error.tag.as_any().downcast_ref::<TcpLinkTag>().unwrap();
+    assert_eq!(tag.remote, refused);
+    assert_eq!(error.error.kind(), ErrorKind::ConnectionRefused);
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let links = control.links();
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].tag().as_any().downcast_ref::<TcpLinkTag>().unwrap().remote, listening);
+    assert!(link_errors.try_recv().is_err(), "refused address was attempted again");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn vectored_write() {
+    const PORT: u16 = 5845;
+    const BUFS: [&[u8]; 3] = [b"header", b"payload", b"trailer"];
+
+    let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)).await.unwrap();
+    let (client, server) = tokio::join!(TcpStream::connect(listener.local_addr().unwrap()), listener.accept());
+    let (rh, wh) = client.unwrap().into_split();
+    let (mut server, _) = server.unwrap();
+
+    let mut io = IoBox::new(rh, wh);
+    assert!(io.is_write_vectored());
+
+    // All buffers must be written by a single call, instead of only the first one.
+    let bufs = BUFS.map(IoSlice::new);
+    let len = BUFS.iter().map(|buf| buf.len()).sum();
+    assert_eq!(io.write_vectored(&bufs).await.unwrap(), len);
+
+    let mut received = vec![0; len];
+    server.read_exact(&mut received).await.unwrap();
+    assert_eq!(received, BUFS.concat());
+}
+
+/// Writer counting the write calls passed to the underlying writer.
+///
+/// If `vectored` is false, vectored writes are not passed through and each
+/// buffer is written by a separate call.
+struct CountingWriter<W> {
+    inner: W,
+    vectored: bool,
+    calls: Arc<AtomicUsize>,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for CountingWriter<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        this.calls.fetch_add(1, Ordering::Relaxed);
+        Pin::new(&mut this.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context, bufs: &[IoSlice]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        this.calls.fetch_add(1, Ordering::Relaxed);
+        if this.vectored {
+            Pin::new(&mut this.inner).poll_write_vectored(cx, bufs)
+        } else {
+            let buf = bufs.iter().find(|buf| !buf.is_empty()).map_or(&[][..], |buf| &**buf);
+            Pin::new(&mut this.inner).poll_write(cx, buf)
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.vectored && self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Compares throughput and write calls of vectored and per-buffer writes through [`IoBox`].
+///
+/// Run with `cargo test --release --features tcp --test tcp -- --ignored --nocapture vectored_write_throughput`.
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+#[ignore = "benchmark"]
+async fn vectored_write_throughput() {
+    const PORT: u16 = 5869;
+    const ROUNDS: usize = 100_000;
+    const HEADER: [u8; 16] = [1; 16];
+    const PAYLOAD: [u8; 1024] = [2; 1024];
+    const TRAILER: [u8; 16] = [3; 16];
+    const ROUND_LEN: usize = HEADER.len() + PAYLOAD.len() + TRAILER.len();
+
+    let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)).await.unwrap();
+
+    let mut results = Vec::new();
+    for vectored in [true, false] {
+        let (client, server) =
+            tokio::join!(TcpStream::connect(listener.local_addr().unwrap()), listener.accept());
+        let (rh, wh) = client.unwrap().into_split();
+        let (mut server, _) = server.unwrap();
+        let reader = tokio::spawn(async move {
+            let mut buf = vec![0; 65536];
+            let mut total = 0;
+            loop {
+                match server.read(&mut buf).await.unwrap() {
+                    0 => break total,
+                    n => total += n,
+                }
+            }
+        });
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut io = IoBox::new(rh, CountingWriter { inner: wh, vectored, calls: calls.clone() });
+        assert_eq!(io.is_write_vectored(), vectored);
+
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            let bufs: [&[u8]; 3] = [&HEADER, &PAYLOAD, &TRAILER];
+            let (mut idx, mut offset) = (0, 0);
+            while idx < bufs.len() {
+                let slices: Vec<_> = bufs[idx..]
+                    .iter()
+                    .enumerate()
+                    .map(|(i, buf)| IoSlice::new(if i == 0 { &buf[offset..] } else { buf }))
+                    .collect();
+                let mut n = io.write_vectored(&slices).await.unwrap();
+                while idx < bufs.len() && n >= bufs[idx].len() - offset {
+                    n -= bufs[idx].len() - offset;
+                    idx += 1;
+                    offset = 0;
+                }
+                offset += n;
+            }
+        }
+        io.shutdown().await.unwrap();
+        assert_eq!(reader.await.unwrap(), ROUNDS * ROUND_LEN);
+        let elapsed = start.elapsed();
+
+        let calls = calls.load(Ordering::Relaxed);
+        let throughput = (ROUNDS * ROUND_LEN) as f64 / elapsed.as_secs_f64() / 1_000_000.0;
+        println!("vectored={vectored}: {calls} write calls, {elapsed:?}, {throughput:.1} MB/s");
+        results.push(calls);
+    }
+
+    assert!(results[0] < results[1], "vectored writes did not reduce write calls: {results:?}");
+}
+
+#[cfg(target_os = "linux")]
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn mptcp() {
+    const PORT: u16 = 5846;
+    let addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT);
+
+    let mut tcp_acceptor = TcpAcceptor::new([addr]).await.unwrap();
+    tcp_acceptor.set_mptcp(true).unwrap();
+    assert_eq!(tcp_acceptor.local_addrs(), [addr]);
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(tcp_acceptor);
+
+    let mut tcp_connector = TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap();
+    tcp_connector.set_mptcp(true);
+    let mut connector = Connector::new();
+    let _tcp_connector = connector.add(tcp_connector);
+    let control = connector.control();
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let ((_server_ch, server_control), _client) =
+        timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+            .await
+            .expect("connection was not established");
+
+    // Without kernel support both sides fall back to plain TCP.
+    let client_link = control.links().pop().unwrap();
+    let client_tag = client_link.tag().as_any().downcast_ref::<TcpLinkTag>().unwrap();
+    let client_mptcp = client_tag.socket.unwrap().mptcp;
+    tracing::info!("outgoing link: {client_tag}");
+    assert_eq!(client_tag.to_string().ends_with("(MPTCP)"), client_mptcp);
+
+    let server_link = server_control.links().pop().unwrap();
+    let server_tag = server_link.tag().as_any().downcast_ref::<TcpLinkTag>().unwrap();
+    tracing::info!("incoming link: {server_tag}");
+    assert_eq!(server_tag.socket.unwrap().mptcp, client_mptcp);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn connect_timeout() {
+    const PORT: u16 = 5847;
+    let addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT);
+
+    // Accept connections, but never perform the link handshake.
+    let listener = TcpListener::bind(addr).await.unwrap();
+    let _stalling = tokio::spawn(async move {
+        let mut streams = Vec::new();
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            streams.push(stream);
+        }
+    });
+
+    let mut builder = ConnectorBuilder::new(Cfg::default());
+    builder.set_reconnect_delay(Duration::from_millis(100));
+    builder.set_connect_timeout(Some(Duration::from_secs(3)));
+    builder.set_link_attempt_timeout(Some(Duration::from_millis(500)));
+    let mut connector = builder.build();
+    let _tcp_connector = connector.add(TcpConnector::new([addr.to_string()], PORT).await.unwrap());
+
+    let start = Instant::now();
+    let err = timeout(Duration::from_secs(30), connector.channel_with_timeout().unwrap())
+        .await
+        .expect("connect timeout did not fire")
+        .unwrap_err();
+    assert!(start.elapsed() >= Duration::from_secs(3));
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+
+    let err = err.get_ref().unwrap().downcast_ref::<ConnectTimeoutError>().unwrap();
+    println!("{err}");
+    assert_eq!(err.link_errors.len(), 1);
+    let tag = err.link_errors[0].tag.as_any().downcast_ref::<TcpLinkTag>().unwrap();
+    assert_eq!(tag.remote, addr);
+    assert_eq!(err.link_errors[0].error.kind(), ErrorKind::TimedOut);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn links_per_interface() {
+    const PORT: u16 = 5848;
+    let refused = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT);
+    let listening = SocketAddr::new(Ipv6Addr::LOCALHOST.into(), PORT);
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(TcpAcceptor::new([listening]).await.unwrap());
+
+    let mut builder = ConnectorBuilder::new(Cfg::default());
+    builder.set_reconnect_delay(Duration::from_millis(100));
+    let mut connector = builder.build();
+    let tcp_connector = TcpConnector::new([refused.to_string(), listening.to_string()], PORT).await.unwrap();
+    let link_limits = tcp_connector.link_limits();
+    link_limits.set_links_per_interface(NonZeroUsize::new(1));
+    let _tcp_connector = connector.add(tcp_connector);
+    let control = connector.control();
+
+    // The persistently failing combination must be replaced by the suppressed one.
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established");
+
+    let links = control.links();
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].tag().as_any().downcast_ref::<TcpLinkTag>().unwrap().remote, listening);
+    let remotes = |tags: HashSet<LinkTagBox>| -> Vec<SocketAddr> {
+        tags.iter().map(|tag| tag.as_any().downcast_ref::<TcpLinkTag>().unwrap().remote).collect()
+    };
+    assert_eq!(remotes(connector.available_tags()), [listening]);
+
+    // Removing the limit makes all combinations available without affecting the link.
+    let mut tags_rx = connector.available_tags_watch();
+    link_limits.set_links_per_interface(None);
+    timeout(Duration::from_secs(30), tags_rx.wait_for(|tags| tags.len() == 2))
+        .await
+        .expect("limit was not removed")
+        .unwrap();
+    assert_eq!(control.links(), links);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn incoming_link_addrs() {
+    const PORT: u16 = 5849;
+    let listen = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT);
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(TcpAcceptor::new([listen]).await.unwrap());
+
+    let mut connector = Connector::new();
+    let _tcp_connector = connector.add(TcpConnector::new([listen.to_string()], PORT).await.unwrap());
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let ((_server_ch, server_control), _client) =
+        timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+            .await
+            .expect("connection was not established");
+
+    let server_link = server_control.links().pop().unwrap();
+    tracing::info!("incoming link: {}", server_link.tag());
+    let tag = server_link.tag().downcast_ref::<TcpLinkTag>().unwrap();
+    assert_eq!(tag.local, Some(listen));
+    assert_eq!(tag.remote.ip(), listen.ip());
+    assert_ne!(tag.remote.port(), PORT);
+    assert!(server_link.tag().to_string().contains(&listen.to_string()));
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn ephemeral_listen_ports() {
+    let tcp_acceptor = TcpAcceptor::new([
+        SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0),
+        SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 0),
+    ])
+    .await
+    .unwrap();
+    let addrs = tcp_acceptor.local_addrs();
+    tracing::info!("listening on {addrs:?}");
+    assert_eq!(addrs.len(), 2);
+    assert_eq!(addrs[0].ip(), Ipv4Addr::LOCALHOST);
+    assert_eq!(addrs[1].ip(), Ipv6Addr::LOCALHOST);
+    assert!(addrs.iter().all(|addr| addr.port() != 0));
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(tcp_acceptor.clone());
+
+    for addr in addrs {
+        let mut connector = Connector::new();
+        let _tcp_connector = connector.add(TcpConnector::new([addr.to_string()], 1).await.unwrap());
+        let server = async { acceptor.accept().await.unwrap() };
+        let client = async { connector.channel().unwrap().await.unwrap() };
+        let ((_server_ch, server_control), _client) =
+            timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+                .await
+                .expect("connection was not established");
+
+        let server_link = server_control.links().pop().unwrap();
+        assert_eq!(server_link.tag().downcast_ref::<TcpLinkTag>().unwrap().local, Some(addr));
+    }
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn source_port_range() {
+    const PORT: u16 = 5850;
+    const SOURCE_PORTS: std::ops::RangeInclusive<u16> = 5851..=5858;
+    const OCCUPIED_PORT: u16 = 5859;
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    let mut tcp_connector = TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap();
+    tcp_connector.set_source_port_range(SOURCE_PORTS).unwrap();
+    let mut connector = Connector::new();
+    let _tcp_connector = connector.add(tcp_connector);
+    let control = connector.control();
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established");
+
+    let link = control.links().pop().unwrap();
+    let socket = link.tag().downcast_ref::<TcpLinkTag>().unwrap().socket.unwrap();
+    tracing::info!("outgoing link socket: {socket}");
+    assert!(SOURCE_PORTS.contains(&socket.local.unwrap().port()));
+
+    tracing::info!("exhausting source port range");
+    let _occupied =
+        TcpListener::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), OCCUPIED_PORT)).await.unwrap();
+    let mut tcp_connector = TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap();
+    tcp_connector.set_source_port_range(OCCUPIED_PORT..=OCCUPIED_PORT).unwrap();
+    let connector = Connector::new();
+    let mut link_errors = connector.link_errors();
+    let _tcp_connector = connector.add(tcp_connector);
+
+    let err = timeout(Duration::from_secs(30), link_errors.recv())
+        .await
+        .expect("no link error for exhausted source port range")
+        .unwrap();
+    tracing::info!("link error: {err}");
+    assert_eq!(err.error.kind(), ErrorKind::AddrInUse);
+
+    let mut tcp_connector = TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap();
+    assert_eq!(tcp_connector.set_source_port_range(0..=10).unwrap_err().kind(), ErrorKind::InvalidInput);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn tcp_connect_timeout() {
+    const PORT: u16 = 5860;
+    let addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT);
+
+    // Fill the accept queue of a listener that never accepts, so that further
+    // connection attempts are not answered.
+    let socket = tokio::net::TcpSocket::new_v4().unwrap();
+    socket.bind(addr).unwrap();
+    let _listener = socket.listen(1).unwrap();
+    let mut queued = Vec::new();
+    while let Ok(Ok(stream)) = timeout(Duration::from_millis(200), TcpStream::connect(addr)).await {
+        queued.push(stream);
+    }
+    tracing::info!("{} connections queued", queued.len());
+
+    let mut tcp_connector = TcpConnector::new([addr.to_string()], PORT).await.unwrap();
+    tcp_connector.set_connect_timeout(Duration::from_millis(500));
+    let connector = Connector::new();
+    let mut link_errors = connector.link_errors();
+    let _tcp_connector = connector.add(tcp_connector);
+
+    let start = Instant::now();
+    let err = timeout(Duration::from_secs(10), link_errors.recv())
+        .await
+        .expect("connect attempt did not time out")
+        .unwrap();
+    tracing::info!("link error after {:?}: {err}", start.elapsed());
+    assert_eq!(err.error.kind(), ErrorKind::TimedOut);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn targets_with_own_ports() {
+    let primary = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 5861);
+    let relay = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 5862);
+
+    let err = TcpConnector::with_targets([("127.0.0.1:5861".to_string(), 5861)]).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(TcpAcceptor::new([primary, relay]).await.unwrap());
+
+    fn link_remotes<TX, RX>(control: &aggligator::Control<TX, RX, LinkTagBox>) -> Vec<SocketAddr> {
+        let mut remotes: Vec<_> = control
+            .links()
+            .iter()
+            .map(|link| link.tag().as_any().downcast_ref::<TcpLinkTag>().unwrap().remote)
+            .collect();
+        remotes.sort_unstable();
+        remotes
+    }
+
+    let mut connector = Connector::new();
+    let tcp_connector = TcpConnector::with_targets([
+        (primary.ip().to_string(), primary.port()),
+        (relay.ip().to_string(), relay.port()),
+    ])
+    .await
+    .unwrap();
+    assert_eq!(tcp_connector.targets().get(), [primary.to_string(), relay.to_string()]);
+    assert!(!tcp_connector.targets().add("127.0.0.1"), "target without port was added");
+    let _tcp_connector = connector.add(tcp_connector);
+    let mut control = connector.control();
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established");
+
+    timeout(Duration::from_secs(30), async {
+        while link_remotes(&control) != [primary, relay] {
+            control.links_changed().await;
+        }
+    })
+    .await
+    .expect("links to both targets were not established");
+}
+
+#[test]
+fn link_local_tag() {
+    let link_local: Ipv6Addr = "fe80::1".parse().unwrap();
+
+    let tag = TcpLinkTag::new(b"eth0", SocketAddrV6::new(link_local, 5000, 0, 2).into(), Direction::Outgoing);
+    assert!(tag.to_string().ends_with("-> [fe80::1%eth0]:5000"), "{tag}");
+
+    let local = SocketAddrV6::new("fe80::2".parse().unwrap(), 5000, 0, 2).into();
+    let tag = TcpLinkTag::accepted(b"eth0", local, SocketAddrV6::new(link_local, 40000, 0, 2).into());
+    assert!(tag.to_string().starts_with("eth0 [fe80::2%eth0]:5000 <- "), "{tag}");
+    assert!(tag.to_string().ends_with("<- [fe80::1%eth0]:40000"), "{tag}");
+
+    let global = SocketAddr::new("2001:db8::1".parse().unwrap(), 5000);
+    let tag = TcpLinkTag::new(b"eth0", global, Direction::Outgoing);
+    assert!(tag.to_string().ends_with("-> [2001:db8::1]:5000"), "{tag}");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn initially_unresolvable() {
+    const PORT: u16 = 5863;
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    let resolver = Arc::new(RotatingResolver(Mutex::new(None)));
+    let tcp_connector = TcpConnector::with_resolver(["server.test".to_string()], PORT, resolver.clone())
+        .await
+        .expect("creation failed while name resolution is unavailable");
+    assert!(tcp_connector.check_resolution().await.is_err());
+
+    let mut connector = Connector::new();
+    let mut link_errors = connector.link_errors();
+    let _tcp_connector = connector.add(tcp_connector);
+    let client = connector.channel().unwrap();
+
+    let error = timeout(Duration::from_secs(30), link_errors.recv())
+        .await
+        .expect("no link error for unresolved host")
+        .unwrap();
+    let tag = error.tag.as_any().downcast_ref::<TcpLinkTag>().unwrap();
+    assert!(tag.is_unresolved());
+    assert!(error.error.to_string().contains("temporary failure"));
+
+    tracing::info!("name resolution becomes available");
+    *resolver.0.lock().unwrap() = Some(PORT);
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { client.await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(5), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established after name resolution succeeded");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn connectivity_check() {
+    const PORT: u16 = 5864;
+
+    let mut tcp_connector = TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap();
+    tcp_connector.set_resolve_interval(Duration::from_millis(500));
+    tcp_connector.set_connectivity_check(Some(Duration::from_secs(1)));
+    let tcp_connector_info = tcp_connector.clone();
+
+    let mut connector = Connector::new();
+    let mut link_errors = connector.link_errors();
+    let _tcp_connector = connector.add(tcp_connector);
+    let client = connector.channel().unwrap();
+
+    sleep(Duration::from_secs(2)).await;
+    assert!(link_errors.try_recv().is_err(), "link error for interface without connectivity");
+    let no_connectivity = tcp_connector_info.no_connectivity_tags();
+    tracing::info!("no connectivity: {no_connectivity:?}");
+    assert!(!no_connectivity.is_empty());
+
+    tracing::info!("server becomes reachable");
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { client.await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(5), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established after connectivity became available");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn socket_prepare() {
+    const PORT: u16 = 5865;
+    let server_addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT);
+
+    let listeners = Arc::new(Mutex::new(Vec::new()));
+    let mut tcp_acceptor = TcpAcceptor::new([server_addr]).await.unwrap();
+    let listeners_prepare = listeners.clone();
+    tcp_acceptor
+        .set_socket_prepare(move |_socket, info: &TargetInfo| {
+            listeners_prepare.lock().unwrap().push(info.clone());
+            Ok(())
+        })
+        .unwrap();
+    let listeners = listeners.lock().unwrap().clone();
+    assert_eq!(listeners.len(), 1);
+    assert_eq!(listeners[0].local, Some(server_addr));
+    assert_eq!(listeners[0].remote, None);
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(tcp_acceptor);
+
+    // The first preparation fails, so that the link is established on retry.
+    let prepared = Arc::new(AtomicUsize::new(0));
+    let mut tcp_connector = TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap();
+    let prepared_prepare = prepared.clone();
+    tcp_connector.set_socket_prepare(move |_socket, info: &TargetInfo| {
+        assert_eq!(info.remote, Some(server_addr));
+        match prepared_prepare.fetch_add(1, Ordering::SeqCst) {
+            0 => Err(Error::new(ErrorKind::PermissionDenied, "not protected")),
+            _ => Ok(()),
+        }
+    });
+
+    let mut connector = Connector::new();
+    let mut link_errors = connector.link_errors();
+    let _tcp_connector = connector.add(tcp_connector);
+    let client = connector.channel().unwrap();
+
+    let error = timeout(Duration::from_secs(10), link_errors.recv()).await.unwrap().unwrap();
+    tracing::info!("link error: {error}");
+    assert_eq!(error.error.kind(), ErrorKind::PermissionDenied);
+    assert!(error.error.to_string().contains("preparing socket failed"));
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { client.await.unwrap() };
+    let (_server, _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established after socket preparation succeeded");
+    assert!(prepared.load(Ordering::SeqCst) >= 2);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn dual_stack_acceptor() {
+    const PORT: u16 = 5866;
+    const V6_ONLY_PORT: u16 = 5867;
+
+    let mut tcp_acceptor =
+        TcpAcceptor::new([SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), V6_ONLY_PORT)]).await.unwrap();
+    tcp_acceptor.set_v6_only(true).unwrap();
+    assert!(TcpStream::connect((Ipv4Addr::LOCALHOST, V6_ONLY_PORT)).await.is_err());
+    tcp_acceptor.set_v6_only(false).unwrap();
+    TcpStream::connect((Ipv4Addr::LOCALHOST, V6_ONLY_PORT)).await.unwrap();
+    drop(tcp_acceptor);
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(TcpAcceptor::dual_stack(PORT).await.unwrap());
+
+    let tcp_connector = TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap();
+    let mut connector = Connector::new();
+    let _tcp_connector = connector.add(tcp_connector);
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let ((_server, server_control), _client) =
+        timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+            .await
+            .expect("connection to dual-stack listener was not established");
+
+    // IPv4 connections accepted by the dual-stack listener must use proper IPv4 addresses.
+    let links = server_control.links();
+    let tag = links[0].tag().as_any().downcast_ref::<TcpLinkTag>().unwrap();
+    tracing::info!("accepted link: {tag}");
+    assert_eq!(tag.remote.ip(), Ipv4Addr::LOCALHOST);
+    assert!(tag.local.unwrap().is_ipv4());
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn pre_connected_link() {
+    const PORT: u16 = 5868;
+    let server_addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT);
+
+    let acceptor = Acceptor::new();
+    let _tcp_acceptor = acceptor.add(TcpAcceptor::new([server_addr]).await.unwrap());
+
+    let mut connector = Connector::new();
+    let (rx, tx) = TcpStream::connect(server_addr).await.unwrap().into_split();
+
+    let server = async {
+        let (mut stream, _control) = acceptor.accept_stream().await.unwrap();
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+    };
+    let client = async {
+        let link = connector
+            .add_io(IoBox::new(rx, tx), TcpLinkTag::new(b"pre-connected", server_addr, Direction::Outgoing))
+            .await
+            .unwrap();
+        tracing::info!("added link: {}", link.tag());
+
+        let mut stream = connector.stream().unwrap().await.unwrap();
+        stream.write_all(b"hello").await.unwrap();
+        stream.flush().await.unwrap();
+        stream
+    };
+    let ((), _stream) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection over pre-connected link was not established");
+
+    let links = connector.control().links();
+    assert_eq!(links.len(), 1);
+    let tag = links[0].tag().as_any().downcast_ref::<TcpLinkTag>().unwrap();
+    assert_eq!(tag.interface, b"pre-connected");
+}
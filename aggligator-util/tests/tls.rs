@@ -0,0 +1,664 @@
+//! TLS wrapper tests.
+
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, PrivateKey, ProtocolVersion, RootCertStore, ServerConfig, ServerName,
+};
+use rustls_pemfile::{certs, pkcs8_private_keys};
+use std::{
+    io::{BufReader, ErrorKind},
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    net::TcpStream,
+    time::{sleep, timeout},
+};
+
+use aggligator_util::transport::{
+    tcp::{TcpAcceptor, TcpConnector, TcpLinkTag},
+    tls::{ClientAuth, PinMismatchError, TlsClient, TlsServer},
+    Acceptor, AcceptorBuilder, Connector,
+};
+
+static TLS_CERT_PEM: &[u8] = include_bytes!("../src/bin/agg-speed-cert.pem");
+static TLS_KEY_PEM: &[u8] = include_bytes!("../src/bin/agg-speed-key.pem");
+static TLS_SERVER_NAME: &str = "aggligator.rs";
+static TLS_SERVER_SUBJECT: &str = "CN=Aggligator Speed Test,OU=Aggligator-Util,O=Aggligator";
+static TLS_SERVER_SPKI_SHA256: &str = "e12d40e8365f1910aadfdcf64c2cf8877665f12aa8d6742f2bdf8a009f1d478d";
+
+static TLS_CA_CERT_PEM: &[u8] = include_bytes!("tls-ca-cert.pem");
+static TLS_CLIENT_CERT_PEM: &[u8] = include_bytes!("tls-client-cert.pem");
+static TLS_CLIENT_KEY_PEM: &[u8] = include_bytes!("tls-client-key.pem");
+static TLS_CLIENT_SUBJECT: &str = "CN=Aggligator Test Client,OU=Aggligator-Util,O=Aggligator";
+static TLS_CLIENT_ALT_NAMES: &[&str] = &["DNS:client.aggligator.rs", "email:client@aggligator.rs"];
+
+fn load_cert(pem: &[u8]) -> Certificate {
+    let mut reader = BufReader::new(pem);
+    Certificate(certs(&mut reader).unwrap().pop().unwrap())
+}
+
+fn load_key(pem: &[u8]) -> PrivateKey {
+    let mut reader = BufReader::new(pem);
+    PrivateKey(pkcs8_private_keys(&mut reader).unwrap().pop().unwrap())
+}
+
+fn tls_server() -> TlsServer {
+    let mut client_roots = RootCertStore::empty();
+    client_roots.add(&load_cert(TLS_CA_CERT_PEM)).unwrap();
+    TlsServer::with_client_auth(
+        vec![load_cert(TLS_CERT_PEM)],
+        load_key(TLS_KEY_PEM),
+        client_roots,
+        ClientAuth::Mandatory,
+    )
+    .unwrap()
+}
+
+/// Accepts every TLS server certificate.
+struct TlsNullVerifier;
+
+impl ServerCertVerifier for TlsNullVerifier {
+    fn verify_server_cert(
+        &self, _end_entity: &Certificate, _intermediates: &[Certificate], _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>, _ocsp_response: &[u8], _now: std::time::SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+fn tls_client(client_auth: bool) -> TlsClient {
+    let builder =
+        ClientConfig::builder().with_safe_defaults().with_custom_certificate_verifier(Arc::new(TlsNullVerifier));
+    let client_cfg = match client_auth {
+        true => {
+            builder.with_single_cert(vec![load_cert(TLS_CLIENT_CERT_PEM)], load_key(TLS_CLIENT_KEY_PEM)).unwrap()
+        }
+        false => builder.with_no_client_auth(),
+    };
+    TlsClient::new(Arc::new(client_cfg), ServerName::try_from(TLS_SERVER_NAME).unwrap())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn tls_client_auth() {
+    const PORT: u16 = 5831;
+
+    let acceptor = Acceptor::wrapped(tls_server());
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    let mut connector = Connector::wrapped(tls_client(true));
+    let _tcp_connector = connector.add(TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap());
+    let client_control = connector.control();
+
+    let (_ch, server_control) = timeout(Duration::from_secs(30), acceptor.accept())
+        .await
+        .expect("connection was not established")
+        .unwrap();
+    let _client_ch = connector.channel().unwrap().await.unwrap();
+
+    for (control, peer_cert, peer_subject, peer_alt_names) in [
+        (&server_control, TLS_CLIENT_CERT_PEM, TLS_CLIENT_SUBJECT, TLS_CLIENT_ALT_NAMES),
+        (&client_control, TLS_CERT_PEM, TLS_SERVER_SUBJECT, &[][..]),
+    ] {
+        timeout(Duration::from_secs(30), async {
+            while control.links().is_empty() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("link was not established");
+
+        let link = control.links().pop().unwrap();
+        let info = link.tag().tls_info().expect("no TLS information on link tag");
+        assert_eq!(info.version, Some(ProtocolVersion::TLSv1_3));
+        assert!(info.cipher_suite.is_some());
+        assert_eq!(info.sni.as_deref(), Some(TLS_SERVER_NAME));
+        assert_eq!(info.peer_certificates, vec![load_cert(peer_cert)]);
+        assert_eq!(info.peer_subject.as_deref(), Some(peer_subject));
+        assert_eq!(info.peer_subject_alt_names, peer_alt_names);
+    }
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn tls_client_auth_rejected() {
+    const PORT: u16 = 5832;
+
+    let acceptor = Acceptor::wrapped(tls_server());
+    let mut link_errors = acceptor.link_errors();
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    let connector = Connector::wrapped(tls_client(false));
+    let _tcp_connector = connector.add(TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap());
+
+    let error = timeout(Duration::from_secs(30), link_errors.recv())
+        .await
+        .expect("unauthenticated link was not rejected")
+        .unwrap();
+    assert!(error.tag.tls_info().is_none());
+
+    let info = error.tls_info().expect("no TLS information on link error");
+    assert_eq!(info.version, Some(ProtocolVersion::TLSv1_3));
+    assert!(info.cipher_suite.is_some());
+    assert_eq!(info.sni.as_deref(), Some(TLS_SERVER_NAME));
+    assert!(info.peer_subject.is_none());
+    assert!(error.error.to_string().starts_with("TLS handshake failed"), "unexpected error: {}", error.error);
+    assert_eq!(error.tls_error(), Some(&rustls::Error::NoCertificatesPresented));
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn tls_client_cert_invalid() {
+    const PORT: u16 = 5869;
+
+    let acceptor = Acceptor::wrapped(tls_server());
+    let mut link_errors = acceptor.link_errors();
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    // Present a certificate that is not issued by the client CA.
+    let client_cfg = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(TlsNullVerifier))
+        .with_single_cert(vec![load_cert(TLS_CERT_PEM)], load_key(TLS_KEY_PEM))
+        .unwrap();
+    let tls_client = TlsClient::new(Arc::new(client_cfg), ServerName::try_from(TLS_SERVER_NAME).unwrap());
+    let connector = Connector::wrapped(tls_client);
+    let _tcp_connector = connector.add(TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap());
+
+    let error = timeout(Duration::from_secs(30), link_errors.recv())
+        .await
+        .expect("link with invalid client certificate was not rejected")
+        .unwrap();
+    tracing::info!("link error: {}", error.error);
+    let tls_error = error.tls_error().expect("no TLS error on link error");
+    assert!(
+        matches!(
+            tls_error,
+            rustls::Error::InvalidCertificateData(_) | rustls::Error::InvalidCertificateSignature
+        ),
+        "unexpected TLS error: {tls_error:?}"
+    );
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn tls_pinned_spki() {
+    const PORT: u16 = 5870;
+
+    let acceptor = Acceptor::wrapped(tls_server());
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    // The self-issued server certificate cannot be verified without pinning.
+    let client_cfg = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(RootCertStore::empty())
+        .with_single_cert(vec![load_cert(TLS_CLIENT_CERT_PEM)], load_key(TLS_CLIENT_KEY_PEM))
+        .unwrap();
+    let tls_client = TlsClient::new(Arc::new(client_cfg), ServerName::try_from(TLS_SERVER_NAME).unwrap());
+    tls_client.set_pinned_spki(vec![[0; 32]]);
+    let pins = tls_client.clone();
+
+    let mut connector = Connector::wrapped(tls_client);
+    let mut link_errors = connector.link_errors();
+    let _tcp_connector = connector.add(TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap());
+
+    let error = timeout(Duration::from_secs(30), link_errors.recv())
+        .await
+        .expect("link with mismatching pin was not rejected")
+        .unwrap();
+    tracing::info!("link error: {}", error.error);
+    assert_eq!(error.error.kind(), ErrorKind::PermissionDenied);
+    let mismatch = error.error.get_ref().unwrap().downcast_ref::<PinMismatchError>().unwrap();
+    let presented: String = mismatch.spki_sha256.iter().map(|b| format!("{b:02x}")).collect();
+    assert_eq!(presented, TLS_SERVER_SPKI_SHA256);
+    assert!(error.error.to_string().contains(TLS_SERVER_SPKI_SHA256));
+
+    tracing::info!("updating pins");
+    pins.set_pinned_spki(vec![[0; 32], mismatch.spki_sha256]);
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let ((_server, _), _client) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+        .await
+        .expect("connection was not established after updating pins");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn tls_alpn() {
+    const PORTS: [u16; 3] = [5871, 5872, 5873];
+
+    let mut acceptors = Vec::new();
+    for port in PORTS {
+        let mut tls_server = tls_server();
+        tls_server.set_alpn(vec![b"h2".to_vec(), b"http/1.1".to_vec()]);
+        tls_server.set_alpn_required(true);
+        let acceptor = Acceptor::wrapped(tls_server);
+        let tcp_acceptor =
+            acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port)]).await.unwrap());
+        acceptors.push((acceptor, tcp_acceptor));
+    }
+
+    let connect = |port, alpn: Option<&'static [u8]>| async move {
+        let mut tls_client = tls_client(true);
+        if let Some(alpn) = alpn {
+            tls_client.set_alpn(vec![alpn.to_vec()]);
+        }
+        let connector = Connector::wrapped(tls_client);
+        let tcp_connector = connector.add(TcpConnector::new(["127.0.0.1".to_string()], port).await.unwrap());
+        (connector, tcp_connector)
+    };
+
+    // Client without ALPN.
+    let mut link_errors = acceptors[0].0.link_errors();
+    let _client = connect(PORTS[0], None).await;
+    let error = timeout(Duration::from_secs(30), link_errors.recv())
+        .await
+        .expect("link without ALPN was not rejected")
+        .unwrap();
+    tracing::info!("link error without ALPN: {}", error.error);
+    assert_eq!(error.error.kind(), ErrorKind::PermissionDenied);
+
+    // Client with mismatching ALPN.
+    let mut link_errors = acceptors[1].0.link_errors();
+    let _client = connect(PORTS[1], Some(b"spdy/3")).await;
+    let error = timeout(Duration::from_secs(30), link_errors.recv())
+        .await
+        .expect("link with mismatching ALPN was not rejected")
+        .unwrap();
+    tracing::info!("link error with mismatching ALPN: {}", error.error);
+    assert_eq!(error.tls_error(), Some(&rustls::Error::NoApplicationProtocol));
+
+    // Client with matching ALPN.
+    let (mut connector, _tcp_connector) = connect(PORTS[2], Some(b"http/1.1")).await;
+    let client_control = connector.control();
+    let server = async { acceptors[2].0.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let ((_server_ch, server_control), _client_ch) =
+        timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+            .await
+            .expect("link with matching ALPN was not established");
+
+    for control in [&server_control, &client_control] {
+        let link = control.links().pop().expect("no link");
+        let info = link.tag().tls_info().expect("no TLS information on link tag");
+        assert_eq!(info.alpn.as_deref(), Some(&b"http/1.1"[..]));
+    }
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn tls_server_name_selector() {
+    const PORT: u16 = 5874;
+
+    let acceptor = Acceptor::wrapped(tls_server());
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    // The link is dialed to a relay, but verified against the canonical server name.
+    let mut tls_client = tls_client(true);
+    tls_client.set_server_name(ServerName::try_from("relay.aggligator.rs").unwrap());
+    tls_client.set_server_name_selector(|tag| {
+        let tag = tag.downcast_ref::<TcpLinkTag>()?;
+        (tag.remote.port() == PORT).then(|| ServerName::try_from(TLS_SERVER_NAME).unwrap())
+    });
+    let mut connector = Connector::wrapped(tls_client);
+    let _tcp_connector = connector.add(TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap());
+    let client_control = connector.control();
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let ((_server_ch, server_control), _client_ch) =
+        timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+            .await
+            .expect("connection was not established");
+
+    for control in [&server_control, &client_control] {
+        let link = control.links().pop().expect("no link");
+        let info = link.tag().tls_info().expect("no TLS information on link tag");
+        assert_eq!(info.sni.as_deref(), Some(TLS_SERVER_NAME));
+    }
+
+    let link = client_control.links().pop().unwrap();
+    let tag = link.tag().to_string();
+    tracing::info!("client link tag: {tag}");
+    assert!(tag.contains(&format!("127.0.0.1:{PORT}")) && tag.contains(&format!("SNI {TLS_SERVER_NAME}")));
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn tls_server_reload() {
+    const PORT: u16 = 5875;
+
+    let tls_server = tls_server();
+    let acceptor = Acceptor::wrapped(tls_server.clone());
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    let connect = || async {
+        let mut connector = Connector::wrapped(tls_client(true));
+        let tcp_connector = connector.add(TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap());
+        let server = async { acceptor.accept().await.unwrap() };
+        let client = async { connector.channel().unwrap().await.unwrap() };
+        let (server, client_ch) = timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+            .await
+            .expect("connection was not established");
+        (connector, tcp_connector, server, client_ch)
+    };
+    let peer_subject = |connector: &Connector| {
+        let link = connector.control().links().pop().expect("no link");
+        let info = link.tag().tls_info().expect("no TLS information on link tag");
+        info.peer_subject.clone().unwrap()
+    };
+
+    let first = connect().await;
+    assert_eq!(peer_subject(&first.0), TLS_SERVER_SUBJECT);
+
+    let err = tls_server.reload(vec![load_cert(TLS_CLIENT_CERT_PEM)], PrivateKey(vec![1, 2, 3])).unwrap_err();
+    tracing::info!("reload with invalid key failed: {err}");
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let second = connect().await;
+    assert_eq!(peer_subject(&second.0), TLS_SERVER_SUBJECT);
+
+    tls_server.reload(vec![load_cert(TLS_CLIENT_CERT_PEM)], load_key(TLS_CLIENT_KEY_PEM)).unwrap();
+
+    let third = connect().await;
+    assert_eq!(peer_subject(&third.0), TLS_CLIENT_SUBJECT);
+    assert_eq!(peer_subject(&first.0), TLS_SERVER_SUBJECT);
+    assert!(!first.0.control().is_terminated());
+}
+
+/// Accepts every TLS server certificate and records the server names it was verified against.
+#[derive(Default)]
+struct TlsRecordingVerifier(Mutex<Vec<ServerName>>);
+
+impl ServerCertVerifier for TlsRecordingVerifier {
+    fn verify_server_cert(
+        &self, _end_entity: &Certificate, _intermediates: &[Certificate], server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>, _ocsp_response: &[u8], _now: std::time::SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.0.lock().unwrap().push(server_name.clone());
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn tls_custom_config() {
+    const PORT: u16 = 5876;
+
+    let mut server_cfg = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec![load_cert(TLS_CERT_PEM)], load_key(TLS_KEY_PEM))
+        .unwrap();
+    server_cfg.alpn_protocols = vec![b"h2".to_vec()];
+    let acceptor = Acceptor::wrapped(TlsServer::new(Arc::new(server_cfg)));
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    let verifier = Arc::new(TlsRecordingVerifier::default());
+    let mut client_cfg = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+    client_cfg.alpn_protocols = vec![b"h2".to_vec()];
+    let tls_client = TlsClient::new(Arc::new(client_cfg), ServerName::try_from(TLS_SERVER_NAME).unwrap());
+    let mut connector = Connector::wrapped(tls_client);
+    let _tcp_connector = connector.add(TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap());
+    let client_control = connector.control();
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let ((_server_ch, server_control), _client_ch) =
+        timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+            .await
+            .expect("connection was not established");
+
+    let verified = verifier.0.lock().unwrap().clone();
+    assert_eq!(verified, vec![ServerName::try_from(TLS_SERVER_NAME).unwrap()]);
+
+    for control in [&server_control, &client_control] {
+        let link = control.links().pop().expect("no link");
+        let info = link.tag().tls_info().expect("no TLS information on link tag");
+        assert_eq!(info.alpn.as_deref(), Some(&b"h2"[..]));
+    }
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn tls_server_name_routing() {
+    const PORTS: [u16; 2] = [5877, 5878];
+    const CLIENT_NAME: &str = "client.aggligator.rs";
+
+    let mut acceptors = Vec::new();
+    for (port, reject_unknown) in PORTS.into_iter().zip([false, true]) {
+        let client_name_cfg = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(vec![load_cert(TLS_CLIENT_CERT_PEM)], load_key(TLS_CLIENT_KEY_PEM))
+            .unwrap();
+        let mut tls_server = tls_server();
+        tls_server.add_server_name(CLIENT_NAME, Arc::new(client_name_cfg));
+        tls_server.set_reject_unknown_server_name(reject_unknown);
+        let acceptor = Acceptor::wrapped(tls_server);
+        let tcp_acceptor =
+            acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port)]).await.unwrap());
+        acceptors.push((acceptor, tcp_acceptor));
+    }
+
+    let connect = |port, server_name: &'static str| async move {
+        let mut tls_client = tls_client(true);
+        tls_client.set_server_name(ServerName::try_from(server_name).unwrap());
+        let connector = Connector::wrapped(tls_client);
+        let tcp_connector = connector.add(TcpConnector::new(["127.0.0.1".to_string()], port).await.unwrap());
+        (connector, tcp_connector)
+    };
+    async fn establish(acceptor: &Acceptor, mut connector: Connector) -> (Option<String>, Option<String>) {
+        let server = async { acceptor.accept().await.unwrap() };
+        let client = async { connector.channel().unwrap().await.unwrap() };
+        let ((_server_ch, server_control), _client_ch) =
+            timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+                .await
+                .expect("connection was not established");
+        let server_link = server_control.links().pop().expect("no server link");
+        let client_link = connector.control().links().pop().expect("no client link");
+        let sni = server_link.tag().tls_info().unwrap().sni.clone();
+        let subject = client_link.tag().tls_info().unwrap().peer_subject.clone();
+        (sni, subject)
+    }
+
+    // Known server name is routed to its configuration.
+    let (connector, _tcp_connector) = connect(PORTS[0], "CLIENT.aggligator.rs").await;
+    let (sni, subject) = establish(&acceptors[0].0, connector).await;
+    assert!(sni.unwrap().eq_ignore_ascii_case(CLIENT_NAME));
+    assert_eq!(subject.as_deref(), Some(TLS_CLIENT_SUBJECT));
+
+    // Unknown server name uses the default configuration.
+    let (connector, _tcp_connector) = connect(PORTS[0], TLS_SERVER_NAME).await;
+    let (sni, subject) = establish(&acceptors[0].0, connector).await;
+    assert_eq!(sni.as_deref(), Some(TLS_SERVER_NAME));
+    assert_eq!(subject.as_deref(), Some(TLS_SERVER_SUBJECT));
+
+    // Unknown server name is rejected.
+    let mut link_errors = acceptors[1].0.link_errors();
+    let _client = connect(PORTS[1], TLS_SERVER_NAME).await;
+    let error = timeout(Duration::from_secs(30), link_errors.recv())
+        .await
+        .expect("link with unknown server name was not rejected")
+        .unwrap();
+    tracing::info!("link error with unknown server name: {}", error.error);
+    assert_eq!(error.error.kind(), ErrorKind::PermissionDenied);
+    assert_eq!(error.tls_info().unwrap().sni.as_deref(), Some(TLS_SERVER_NAME));
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn tls_resumption() {
+    const PORT: u16 = 5880;
+
+    let acceptor = Acceptor::wrapped(tls_server());
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    let acceptor = &acceptor;
+    let connect = |tls_client: TlsClient| async move {
+        let mut connector = Connector::wrapped(tls_client);
+        let tcp_connector = connector.add(TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap());
+        let server = async { acceptor.accept().await.unwrap() };
+        let client = async { connector.channel().unwrap().await.unwrap() };
+        let ((server_ch, server_control), client_ch) =
+            timeout(Duration::from_secs(30), async { tokio::join!(server, client) })
+                .await
+                .expect("connection was not established");
+
+        let client_link = connector.control().links().pop().expect("no client link");
+        let server_link = server_control.links().pop().expect("no server link");
+        let client_info = client_link.tag().tls_info().expect("no TLS information on client link tag").clone();
+        let server_info = server_link.tag().tls_info().expect("no TLS information on server link tag").clone();
+        tracing::info!("client: {client_info}, server: {server_info}");
+        assert_eq!(client_info.resumed, server_info.resumed);
+        assert_eq!(client_info.peer_subject.as_deref(), Some(TLS_SERVER_SUBJECT));
+        assert_eq!(server_info.peer_subject.as_deref(), Some(TLS_CLIENT_SUBJECT));
+
+        (client_info.resumed, (connector, tcp_connector, server_ch, client_ch))
+    };
+
+    let mut tls_client = tls_client(true);
+
+    let (resumed, _first) = connect(tls_client.clone()).await;
+    assert!(!resumed, "first link resumed a session");
+
+    let (resumed, _second) = connect(tls_client.clone()).await;
+    assert!(resumed, "second link did not resume the session of the first link");
+
+    tls_client.set_resumption(false);
+    let (resumed, _third) = connect(tls_client).await;
+    assert!(!resumed, "link resumed a session although resumption is disabled");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn tls_peer_certificate_chain() {
+    const PORTS: [u16; 2] = [5881, 5882];
+
+    let mut client_roots = RootCertStore::empty();
+    client_roots.add(&load_cert(TLS_CA_CERT_PEM)).unwrap();
+    let tls_server = TlsServer::with_client_auth(
+        vec![load_cert(TLS_CERT_PEM)],
+        load_key(TLS_KEY_PEM),
+        client_roots,
+        ClientAuth::Optional,
+    )
+    .unwrap();
+    let acceptor = Acceptor::wrapped(tls_server);
+    let _tcp_acceptor = acceptor.add(
+        TcpAcceptor::new(PORTS.map(|port| SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port))).await.unwrap(),
+    );
+
+    // Client without certificate.
+    let mut connector = Connector::wrapped(tls_client(false));
+    let _tcp_connector = connector.add(TcpConnector::new(["127.0.0.1".to_string()], PORTS[0]).await.unwrap());
+    let (_ch, server_control) = timeout(Duration::from_secs(30), acceptor.accept())
+        .await
+        .expect("connection was not established")
+        .unwrap();
+    let _client_ch = connector.channel().unwrap().await.unwrap();
+    timeout(Duration::from_secs(30), async {
+        while server_control.links().is_empty() {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("link was not established");
+    let link = server_control.links().pop().unwrap();
+    let info = link.tag().tls_info().expect("no TLS information on link tag");
+    assert!(info.peer_certificate_chain().is_none());
+    assert!(info.peer_subject.is_none());
+
+    // Client with certificate adding a link after the connection has been accepted.
+    let mut connector = Connector::wrapped(tls_client(true));
+    let _tcp_connector = connector.add(TcpConnector::new(["127.0.0.1".to_string()], PORTS[0]).await.unwrap());
+    let (_ch, server_control) = timeout(Duration::from_secs(30), acceptor.accept())
+        .await
+        .expect("connection was not established")
+        .unwrap();
+    let _client_ch = connector.channel().unwrap().await.unwrap();
+    let _second_tcp_connector =
+        connector.add(TcpConnector::new(["127.0.0.1".to_string()], PORTS[1]).await.unwrap());
+    timeout(Duration::from_secs(30), async {
+        while server_control.links().len() < 2 {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("link added after accepting the connection was not established");
+    for link in server_control.links() {
+        let info = link.tag().tls_info().expect("no TLS information on link tag");
+        tracing::info!(
+            "link {} has peer certificate chain of length {:?}",
+            link.tag(),
+            info.peer_certificate_chain().map(|chain| chain.len())
+        );
+        assert_eq!(info.peer_certificate_chain(), Some(&[load_cert(TLS_CLIENT_CERT_PEM)][..]));
+    }
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn tls_stalled_handshakes() {
+    const PORT: u16 = 5883;
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+
+    let mut builder = AcceptorBuilder::new(Default::default());
+    builder.wrap(tls_server());
+    builder.set_handshake_timeout(HANDSHAKE_TIMEOUT);
+    builder.set_max_pending_handshakes(2);
+    let acceptor = builder.build();
+    let mut link_errors = acceptor.link_errors();
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    let wait_for_pending = |pending: usize| {
+        let acceptor = &acceptor;
+        async move {
+            timeout(Duration::from_secs(30), async {
+                while acceptor.handshake_stats().pending != pending {
+                    sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("handshake did not start")
+        }
+    };
+
+    // A client that never starts its handshake does not delay other links.
+    let _stalled = TcpStream::connect(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)).await.unwrap();
+    wait_for_pending(1).await;
+
+    let mut connector = Connector::wrapped(tls_client(true));
+    let _tcp_connector = connector.add(TcpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap());
+    let (_ch, _server_control) = timeout(HANDSHAKE_TIMEOUT / 2, acceptor.accept())
+        .await
+        .expect("connection was delayed by stalled handshake")
+        .unwrap();
+    let _client_ch = connector.channel().unwrap().await.unwrap();
+    assert_eq!(acceptor.handshake_stats().completed, 1);
+
+    // Links exceeding the maximum number of pending handshakes are dropped.
+    let _stalled_second = TcpStream::connect(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)).await.unwrap();
+    wait_for_pending(2).await;
+    let _rejected = TcpStream::connect(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)).await.unwrap();
+
+    let mut kinds = Vec::new();
+    while kinds.len() < 3 {
+        let error = timeout(Duration::from_secs(30), link_errors.recv())
+            .await
+            .expect("stalled handshakes were not reported")
+            .unwrap();
+        tracing::info!("link error: {}", error.error);
+        kinds.push(error.error.kind());
+    }
+    assert_eq!(kinds, [ErrorKind::ConnectionRefused, ErrorKind::TimedOut, ErrorKind::TimedOut]);
+
+    let stats = acceptor.handshake_stats();
+    assert_eq!(stats.pending, 0);
+    assert_eq!(stats.completed, 1);
+    assert_eq!(stats.timed_out, 2);
+    assert_eq!(stats.rejected, 1);
+}
@@ -0,0 +1,321 @@
+//! UDP transport tests.
+
+use futures::join;
+use std::{
+    collections::HashSet,
+    net::{Ipv4Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UdpSocket,
+    time::{sleep, timeout},
+};
+
+use aggligator::{alc::Channel, cfg::LinkPing, Cfg};
+use aggligator_util::transport::{
+    tcp::{TcpAcceptor, TcpConnector},
+    udp::{UdpAcceptor, UdpConnector, UdpLinkTag, MIN_DATAGRAM_SIZE},
+    Acceptor, AcceptorBuilder, Connector, ConnectorBuilder,
+};
+
+/// UDP proxy between one client and a server that can drop datagrams.
+#[derive(Default)]
+struct Proxy {
+    /// Every datagram with an index divisible by this is dropped; zero drops none.
+    drop_every: usize,
+    /// Whether all datagrams are dropped.
+    blackhole: AtomicBool,
+    /// Number of dropped datagrams.
+    dropped: AtomicUsize,
+    /// Size of the largest forwarded datagram.
+    max_len: AtomicUsize,
+}
+
+impl Proxy {
+    /// Starts a proxy listening on the specified port and forwarding to `server`.
+    async fn start(port: u16, server: SocketAddr, drop_every: usize) -> Arc<Self> {
+        let this = Arc::new(Self { drop_every, ..Default::default() });
+        let client_side = UdpSocket::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port)).await.unwrap();
+        let server_side = UdpSocket::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).await.unwrap();
+        tokio::spawn(this.clone().run(client_side, server_side, server));
+        this
+    }
+
+    async fn run(self: Arc<Self>, client_side: UdpSocket, server_side: UdpSocket, server: SocketAddr) {
+        let mut client = None;
+        let mut client_buf = vec![0; 65_536];
+        let mut server_buf = vec![0; 65_536];
+        let mut index = 0;
+
+        loop {
+            let (datagram, socket, target) = tokio::select! {
+                res = client_side.recv_from(&mut client_buf) => {
+                    let Ok((n, from)) = res else { continue };
+                    client = Some(from);
+                    (client_buf[..n].to_vec(), &server_side, server)
+                }
+                res = server_side.recv_from(&mut server_buf) => {
+                    let Ok((n, _)) = res else { continue };
+                    let Some(client) = client else { continue };
+                    (server_buf[..n].to_vec(), &client_side, client)
+                }
+            };
+
+            index += 1;
+            if self.blackhole.load(Ordering::SeqCst) || (self.drop_every != 0 && index % self.drop_every == 0) {
+                self.dropped.fetch_add(1, Ordering::SeqCst);
+                continue;
+            }
+
+            self.max_len.fetch_max(datagram.len(), Ordering::SeqCst);
+            let _ = socket.send_to(&datagram, target).await;
+        }
+    }
+}
+
+/// Echoes all data received over the channel.
+async fn echo(ch: Channel) {
+    let mut stream = ch.into_stream();
+    let mut buf = vec![0; 8192];
+    loop {
+        let n = stream.read(&mut buf).await.unwrap();
+        if n == 0 {
+            break;
+        }
+        stream.write_all(&buf[..n]).await.unwrap();
+    }
+    stream.shutdown().await.unwrap();
+}
+
+/// Sends `count` bytes over the channel and verifies that they are echoed back.
+async fn send_and_verify(ch: Channel, count: usize) {
+    let (mut rx, mut tx) = tokio::io::split(ch.into_stream());
+    let data: Vec<u8> = (0..count).map(|i| i as u8).collect();
+    let writer = async {
+        tx.write_all(&data).await.unwrap();
+        tx.shutdown().await.unwrap();
+    };
+    let reader = async {
+        let mut received = Vec::new();
+        rx.read_to_end(&mut received).await.unwrap();
+        received
+    };
+    let ((), received) = join!(writer, reader);
+    assert_eq!(received.len(), data.len());
+    assert!(received == data, "received data mismatch");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn udp_round_trip() {
+    const PORT: u16 = 5885;
+    const COUNT: usize = 1_000_000;
+
+    let acceptor = Acceptor::new();
+    let _udp_acceptor =
+        acceptor.add(UdpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    let mut connector = Connector::new();
+    let _udp_connector = connector.add(UdpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap());
+    let control = connector.control();
+
+    let server = async {
+        let (ch, _control) = acceptor.accept().await.unwrap();
+        echo(ch).await;
+    };
+
+    let client = async {
+        let ch = connector.channel().unwrap().await.unwrap();
+
+        let links = control.links();
+        assert_eq!(links.len(), 1);
+        let tag = links[0].tag().as_any().downcast_ref::<UdpLinkTag>().unwrap();
+        assert_eq!(tag.remote, SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT));
+
+        send_and_verify(ch, COUNT).await;
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server, client) }).await.expect("round trip timed out");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn udp_and_tcp() {
+    const UDP_PORT: u16 = 5886;
+    const TCP_PORT: u16 = 5887;
+    const COUNT: usize = 1_000_000;
+
+    let acceptor = Acceptor::new();
+    let _udp_acceptor =
+        acceptor.add(UdpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), UDP_PORT)]).await.unwrap());
+    let _tcp_acceptor =
+        acceptor.add(TcpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), TCP_PORT)]).await.unwrap());
+
+    let mut connector = Connector::new();
+    let _udp_connector = connector.add(UdpConnector::new(["127.0.0.1".to_string()], UDP_PORT).await.unwrap());
+    let _tcp_connector = connector.add(TcpConnector::new(["127.0.0.1".to_string()], TCP_PORT).await.unwrap());
+    let control = connector.control();
+
+    let server = async {
+        let (ch, _control) = acceptor.accept().await.unwrap();
+        echo(ch).await;
+    };
+
+    let client = async {
+        let ch = connector.channel().unwrap().await.unwrap();
+
+        timeout(Duration::from_secs(30), async {
+            loop {
+                let transports: HashSet<_> =
+                    control.links().iter().map(|link| link.tag().transport_name().to_string()).collect();
+                if transports.contains("udp") && transports.contains("tcp") {
+                    break;
+                }
+                sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .expect("links of both transports were not established");
+
+        send_and_verify(ch, COUNT).await;
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server, client) }).await.expect("transfer timed out");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn udp_idle_timeout() {
+    const PORT: u16 = 5888;
+    const PROXY_PORT: u16 = 5889;
+
+    let proxy = Proxy::start(PROXY_PORT, SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT), 0).await;
+
+    // Keep the links busy with pings, but leave detection of a silent link to the transport.
+    let cfg = Cfg {
+        link_ping: LinkPing::Periodic(Duration::from_millis(100)),
+        link_ping_timeout: Duration::from_secs(600),
+        ..Default::default()
+    };
+
+    let acceptor = AcceptorBuilder::new(cfg.clone()).build();
+    let mut udp_acceptor = UdpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap();
+    udp_acceptor.set_idle_timeout(Duration::from_secs(1));
+    let _udp_acceptor = acceptor.add(udp_acceptor);
+
+    let mut connector = ConnectorBuilder::new(cfg).build();
+    let mut udp_connector = UdpConnector::new(["127.0.0.1".to_string()], PROXY_PORT).await.unwrap();
+    udp_connector.set_idle_timeout(Duration::from_secs(1));
+    let _udp_connector = connector.add(udp_connector);
+
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    let ((_server_ch, server_control), _client_ch) =
+        timeout(Duration::from_secs(30), async { join!(server, client) })
+            .await
+            .expect("connection was not established");
+
+    // Pings keep the link alive beyond the idle timeout.
+    sleep(Duration::from_secs(3)).await;
+    assert_eq!(server_control.links().len(), 1, "link was closed despite pings");
+
+    proxy.blackhole.store(true, Ordering::SeqCst);
+    timeout(Duration::from_secs(10), async {
+        while !server_control.links().is_empty() {
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("silent link was not closed");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn udp_lossy_link() {
+    const PORT: u16 = 5851;
+    const PROXY_PORT: u16 = 5852;
+    const COUNT: usize = 1_000_000;
+
+    let proxy = Proxy::start(PROXY_PORT, SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT), 10).await;
+
+    let acceptor = Acceptor::new();
+    let _udp_acceptor =
+        acceptor.add(UdpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap());
+
+    let mut connector = Connector::new();
+    let _udp_connector = connector.add(UdpConnector::new(["127.0.0.1".to_string()], PROXY_PORT).await.unwrap());
+
+    let server = async {
+        let (ch, _control) = acceptor.accept().await.unwrap();
+        echo(ch).await;
+    };
+
+    let client = async {
+        let ch = connector.channel().unwrap().await.unwrap();
+        send_and_verify(ch, COUNT).await;
+    };
+
+    timeout(Duration::from_secs(120), async { join!(server, client) }).await.expect("transfer timed out");
+    assert!(proxy.dropped.load(Ordering::SeqCst) > 0, "no datagrams were dropped");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn udp_max_datagram_size() {
+    const PORT: u16 = 5853;
+    const PROXY_PORT: u16 = 5854;
+    const COUNT: usize = 100_000;
+
+    let proxy = Proxy::start(PROXY_PORT, SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT), 0).await;
+
+    let acceptor = Acceptor::new();
+    let mut udp_acceptor = UdpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap();
+    udp_acceptor.set_max_datagram_size(MIN_DATAGRAM_SIZE);
+    let _udp_acceptor = acceptor.add(udp_acceptor);
+
+    let mut connector = Connector::new();
+    let mut udp_connector = UdpConnector::new(["127.0.0.1".to_string()], PROXY_PORT).await.unwrap();
+    udp_connector.set_max_datagram_size(MIN_DATAGRAM_SIZE);
+    let _udp_connector = connector.add(udp_connector);
+
+    let server = async {
+        let (ch, _control) = acceptor.accept().await.unwrap();
+        echo(ch).await;
+    };
+
+    let client = async {
+        let ch = connector.channel().unwrap().await.unwrap();
+        send_and_verify(ch, COUNT).await;
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server, client) }).await.expect("transfer timed out");
+    assert_eq!(proxy.max_len.load(Ordering::SeqCst), MIN_DATAGRAM_SIZE);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn udp_datagram_size_mismatch() {
+    const PORT: u16 = 5855;
+    const COUNT: usize = 100_000;
+
+    // The acceptor discards the larger datagrams of the connector, which then
+    // has to fall back to the minimum datagram size.
+    let acceptor = Acceptor::new();
+    let mut udp_acceptor = UdpAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)]).await.unwrap();
+    udp_acceptor.set_max_datagram_size(MIN_DATAGRAM_SIZE);
+    let _udp_acceptor = acceptor.add(udp_acceptor);
+
+    let mut connector = Connector::new();
+    let _udp_connector = connector.add(UdpConnector::new(["127.0.0.1".to_string()], PORT).await.unwrap());
+
+    let server = async {
+        let (ch, _control) = acceptor.accept().await.unwrap();
+        echo(ch).await;
+    };
+
+    let client = async {
+        let ch = connector.channel().unwrap().await.unwrap();
+        send_and_verify(ch, COUNT).await;
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server, client) }).await.expect("transfer timed out");
+}
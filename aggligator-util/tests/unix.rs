@@ -0,0 +1,121 @@
+//! Unix domain socket transport tests.
+
+use futures::join;
+use std::{
+    io::ErrorKind,
+    os::unix::{fs::MetadataExt, net::UnixListener},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::{sleep, timeout},
+};
+
+use aggligator::control::Direction;
+use aggligator_util::transport::{
+    unix::{UnixAcceptor, UnixConnector, UnixLinkTag},
+    Acceptor, Connector,
+};
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn unix_round_trip() {
+    const COUNT: usize = 1_000_000;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path1 = dir.path().join("link1.sock");
+    let path2 = dir.path().join("link2.sock");
+    let uid = std::fs::metadata(dir.path()).unwrap().uid();
+
+    let acceptor = Acceptor::new();
+    let _unix_acceptor1 = acceptor.add(UnixAcceptor::new(&path1).await.unwrap());
+    let _unix_acceptor2 = acceptor.add(UnixAcceptor::new(&path2).await.unwrap());
+
+    let mut unix_connector = UnixConnector::new(&path1);
+    unix_connector.add_path(&path2);
+    let mut connector = Connector::new();
+    let _unix_connector = connector.add(unix_connector);
+    let control = connector.control();
+
+    let server = async {
+        let (ch, control) = acceptor.accept().await.unwrap();
+
+        timeout(Duration::from_secs(30), async {
+            while control.links().len() != 2 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("two links were not accepted");
+        for link in control.links() {
+            let tag = link.tag().as_any().downcast_ref::<UnixLinkTag>().unwrap();
+            assert_eq!(tag.direction, Direction::Incoming);
+            assert!(tag.path == path1 || tag.path == path2);
+            let peer_cred = tag.peer_cred.expect("no peer credentials");
+            assert_eq!(peer_cred.uid, uid);
+            assert_eq!(peer_cred.pid, Some(std::process::id() as i32));
+        }
+
+        let mut stream = ch.into_stream();
+        let mut buf = vec![0; 8192];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            stream.write_all(&buf[..n]).await.unwrap();
+        }
+        stream.shutdown().await.unwrap();
+    };
+
+    let client = async {
+        let ch = connector.channel().unwrap().await.unwrap();
+
+        timeout(Duration::from_secs(30), async {
+            while control.links().len() != 2 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("two links were not established");
+
+        let (mut rx, mut tx) = tokio::io::split(ch.into_stream());
+        let data: Vec<u8> = (0..COUNT).map(|i| i as u8).collect();
+        let writer = async {
+            tx.write_all(&data).await.unwrap();
+            tx.shutdown().await.unwrap();
+        };
+        let reader = async {
+            let mut received = Vec::new();
+            rx.read_to_end(&mut received).await.unwrap();
+            received
+        };
+        let ((), received) = join!(writer, reader);
+        assert_eq!(received.len(), data.len());
+        assert!(received == data, "received data mismatch");
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server, client) }).await.expect("transfer timed out");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn unix_socket_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("agg.sock");
+
+    // A stale socket file is removed.
+    drop(UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+    let unix_acceptor = UnixAcceptor::new(&path).await.unwrap();
+
+    // A socket in use is not taken over.
+    assert_eq!(UnixAcceptor::new(&path).await.unwrap_err().kind(), ErrorKind::AddrInUse);
+
+    // The socket file is removed together with the transport.
+    drop(unix_acceptor);
+    assert!(!path.exists());
+
+    // Other files are not removed.
+    std::fs::write(&path, b"data").unwrap();
+    assert_eq!(UnixAcceptor::new(&path).await.unwrap_err().kind(), ErrorKind::AlreadyExists);
+    assert!(path.exists());
+}
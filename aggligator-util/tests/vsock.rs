@@ -0,0 +1,70 @@
+//! Vsock transport tests.
+
+use futures::join;
+use std::io::ErrorKind;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use aggligator_util::transport::{
+    vsock::{VsockAcceptor, VsockConnector, VMADDR_CID_LOCAL},
+    Acceptor, Connector,
+};
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn vsock_loopback() {
+    const PORT: u32 = 5821;
+    const COUNT: usize = 1_000_000;
+
+    let vsock_acceptor = match VsockAcceptor::new(PORT) {
+        Ok(vsock_acceptor) => vsock_acceptor,
+        Err(err)
+            if matches!(err.kind(), ErrorKind::Unsupported | ErrorKind::AddrNotAvailable)
+                || err.raw_os_error() == Some(97) =>
+        {
+            println!("vsock is not supported on this host: {err}");
+            return;
+        }
+        Err(err) => panic!("cannot listen on vsock port {PORT}: {err}"),
+    };
+
+    let acceptor = Acceptor::new();
+    let _vsock_acceptor = acceptor.add(vsock_acceptor);
+
+    let mut connector = Connector::new();
+    let _vsock_connector = connector.add(VsockConnector::new(VMADDR_CID_LOCAL, PORT));
+
+    let server = async {
+        let (ch, control) = acceptor.accept().await.unwrap();
+        assert_eq!(control.links()[0].tag().transport_name(), "vsock");
+
+        let mut stream = ch.into_stream();
+        let mut buf = vec![0; 8192];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            stream.write_all(&buf[..n]).await.unwrap();
+        }
+        stream.shutdown().await.unwrap();
+    };
+
+    let client = async {
+        let ch = connector.channel().unwrap().await.unwrap();
+
+        let (mut rx, mut tx) = tokio::io::split(ch.into_stream());
+        let data: Vec<u8> = (0..COUNT).map(|i| i as u8).collect();
+        let writer = async {
+            tx.write_all(&data).await.unwrap();
+            tx.shutdown().await.unwrap();
+        };
+        let reader = async {
+            let mut received = Vec::new();
+            rx.read_to_end(&mut received).await.unwrap();
+            received
+        };
+        let ((), received) = join!(writer, reader);
+        assert_eq!(received, data);
+    };
+
+    join!(server, client);
+}
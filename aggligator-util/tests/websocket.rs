@@ -0,0 +1,263 @@
+//! WebSocket transport tests.
+
+use futures::{join, StreamExt};
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::{sleep, timeout},
+};
+use tokio_tungstenite::{client_async, tungstenite::Message};
+
+use aggligator::alc::Channel;
+use aggligator_util::transport::{
+    websocket::{WebSocketAcceptor, WebSocketConnector, WebSocketLinkTag},
+    Acceptor, Connector,
+};
+
+/// Sends a raw HTTP request to the specified port and returns the status line of the response.
+async fn http_status(port: u16, request: &str) -> String {
+    let mut socket = TcpStream::connect(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port)).await.unwrap();
+    socket.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    socket.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).lines().next().unwrap_or_default().to_string()
+}
+
+/// Echoes all data received over the channel.
+async fn echo(ch: Channel) {
+    let mut stream = ch.into_stream();
+    let mut buf = vec![0; 8192];
+    loop {
+        let n = stream.read(&mut buf).await.unwrap();
+        if n == 0 {
+            break;
+        }
+        stream.write_all(&buf[..n]).await.unwrap();
+    }
+    stream.shutdown().await.unwrap();
+}
+
+/// Sends `count` bytes over the channel and verifies that they are echoed back.
+async fn send_and_verify(ch: Channel, count: usize) {
+    let (mut rx, mut tx) = tokio::io::split(ch.into_stream());
+    let data: Vec<u8> = (0..count).map(|i| i as u8).collect();
+    let writer = async {
+        tx.write_all(&data).await.unwrap();
+        tx.shutdown().await.unwrap();
+    };
+    let reader = async {
+        let mut received = Vec::new();
+        rx.read_to_end(&mut received).await.unwrap();
+        received
+    };
+    let ((), received) = join!(writer, reader);
+    assert_eq!(received.len(), data.len());
+    assert!(received == data, "received data mismatch");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn websocket_connect() {
+    const PORT: u16 = 5890;
+    const COUNT: usize = 1_000_000;
+
+    let acceptor = Acceptor::new();
+    let _ws_acceptor = acceptor
+        .add(WebSocketAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)], "/agg").await.unwrap());
+
+    let url = format!("ws://127.0.0.1:{PORT}/agg");
+    let mut connector = Connector::new();
+    let _ws_connector = connector.add(WebSocketConnector::new([url.clone()]).await.unwrap());
+    let control = connector.control();
+
+    let server = async {
+        let (ch, control) = acceptor.accept().await.unwrap();
+
+        let links = control.links();
+        assert_eq!(links.len(), 1);
+        let tag = links[0].tag().as_any().downcast_ref::<WebSocketLinkTag>().unwrap();
+        assert_eq!(tag.url, "/agg");
+
+        echo(ch).await;
+    };
+
+    let client = async {
+        let ch = connector.channel().unwrap().await.unwrap();
+
+        let links = control.links();
+        assert_eq!(links.len(), 1);
+        let tag = links[0].tag().as_any().downcast_ref::<WebSocketLinkTag>().unwrap();
+        assert_eq!(tag.url, url);
+        assert_eq!(tag.remote, SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT));
+
+        send_and_verify(ch, COUNT).await;
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server, client) }).await.expect("transfer timed out");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn websocket_multiple_links() {
+    const PORT1: u16 = 5891;
+    const PORT2: u16 = 5892;
+    const COUNT: usize = 1_000_000;
+
+    let acceptor = Acceptor::new();
+    let _ws_acceptor = acceptor.add(
+        WebSocketAcceptor::new(
+            [
+                SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT1),
+                SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT2),
+            ],
+            "/",
+        )
+        .await
+        .unwrap(),
+    );
+
+    let mut connector = Connector::new();
+    let _ws_connector = connector.add(
+        WebSocketConnector::new([format!("ws://127.0.0.1:{PORT1}/"), format!("ws://127.0.0.1:{PORT2}/")])
+            .await
+            .unwrap(),
+    );
+    let control = connector.control();
+
+    let server = async {
+        let (ch, _control) = acceptor.accept().await.unwrap();
+        echo(ch).await;
+    };
+
+    let client = async {
+        let ch = connector.channel().unwrap().await.unwrap();
+
+        timeout(Duration::from_secs(30), async {
+            while control.links().len() != 2 {
+                sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .expect("two links were not established");
+
+        send_and_verify(ch, COUNT).await;
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server, client) }).await.expect("transfer timed out");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn websocket_path_prefix() {
+    const PORT: u16 = 5893;
+
+    let acceptor = Acceptor::new();
+    let _ws_acceptor = acceptor.add(
+        WebSocketAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)], "/proxy/agg/").await.unwrap(),
+    );
+
+    let mut connector = Connector::new();
+    let _ws_connector =
+        connector.add(WebSocketConnector::new([format!("ws://127.0.0.1:{PORT}/proxy/agg/link")]).await.unwrap());
+
+    let server = async {
+        let (_ch, control) = acceptor.accept().await.unwrap();
+        let links = control.links();
+        let tag = links[0].tag().as_any().downcast_ref::<WebSocketLinkTag>().unwrap();
+        assert_eq!(tag.url, "/proxy/agg/link");
+    };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    timeout(Duration::from_secs(30), async { join!(server, client) })
+        .await
+        .expect("connection below path prefix was not established");
+
+    let upgrade = "Connection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Version: 13\r\n\
+                   Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n";
+    for path in ["/proxy", "/proxy/aggregate", "/other/agg"] {
+        let status = http_status(PORT, &format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n{upgrade}")).await;
+        assert!(status.starts_with("HTTP/1.1 404"), "unexpected response for {path}: {status}");
+    }
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn websocket_custom_headers() {
+    const PORT: u16 = 5894;
+
+    let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)).await.unwrap();
+
+    let mut ws_connector = WebSocketConnector::new([format!("ws://127.0.0.1:{PORT}/agg")]).await.unwrap();
+    ws_connector.add_header("Authorization", "Bearer secret").unwrap();
+    ws_connector.add_header("X-Route", "backend-1").unwrap();
+    assert!(ws_connector.add_header("Invalid Name", "value").is_err());
+
+    let connector = Connector::new();
+    let _ws_connector = connector.add(ws_connector);
+
+    let (mut socket, _) =
+        timeout(Duration::from_secs(30), listener.accept()).await.expect("no connection attempt").unwrap();
+    let mut request = Vec::new();
+    while !request.ends_with(b"\r\n\r\n") {
+        request.push(socket.read_u8().await.unwrap());
+    }
+    let request = String::from_utf8(request).unwrap();
+
+    assert!(request.starts_with("GET /agg HTTP/1.1\r\n"), "unexpected request: {request}");
+    let lines: Vec<_> = request.lines().map(|line| line.to_ascii_lowercase()).collect();
+    assert!(lines.contains(&"authorization: bearer secret".to_string()), "missing header: {request}");
+    assert!(lines.contains(&"x-route: backend-1".to_string()), "missing header: {request}");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn websocket_bad_request() {
+    const PORT: u16 = 5895;
+
+    let acceptor = Acceptor::new();
+    let _ws_acceptor = acceptor
+        .add(WebSocketAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)], "/agg").await.unwrap());
+
+    for request in
+        ["GET /agg HTTP/1.1\r\nHost: localhost\r\n\r\n", "POST /agg HTTP/1.1\r\nHost: localhost\r\n\r\n"]
+    {
+        let status = timeout(Duration::from_secs(30), http_status(PORT, request)).await.expect("no response");
+        assert!(status.starts_with("HTTP/1.1 400"), "unexpected response for {request:?}: {status}");
+    }
+
+    // The acceptor keeps accepting WebSocket connections.
+    let mut connector = Connector::new();
+    let _ws_connector =
+        connector.add(WebSocketConnector::new([format!("ws://127.0.0.1:{PORT}/agg")]).await.unwrap());
+    let server = async { acceptor.accept().await.unwrap() };
+    let client = async { connector.channel().unwrap().await.unwrap() };
+    timeout(Duration::from_secs(30), async { join!(server, client) })
+        .await
+        .expect("connection was not established after bad requests");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn websocket_ping() {
+    const PORT: u16 = 5896;
+
+    let acceptor = Acceptor::new();
+    let mut ws_acceptor =
+        WebSocketAcceptor::new([SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)], "/agg").await.unwrap();
+    ws_acceptor.set_ping_interval(Duration::from_millis(100));
+    let _ws_acceptor = acceptor.add(ws_acceptor);
+
+    let socket = TcpStream::connect(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), PORT)).await.unwrap();
+    let (mut ws, _) = client_async(format!("ws://127.0.0.1:{PORT}/agg"), socket).await.unwrap();
+
+    // Pings are sent while the link is idle and the answering pongs keep it open.
+    let mut pings = 0;
+    timeout(Duration::from_secs(30), async {
+        while pings < 5 {
+            match ws.next().await {
+                Some(Ok(Message::Ping(_))) => pings += 1,
+                Some(Ok(_)) => (),
+                other => panic!("WebSocket closed: {other:?}"),
+            }
+        }
+    })
+    .await
+    .expect("no pings received");
+}
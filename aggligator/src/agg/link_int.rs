@@ -5,8 +5,9 @@ use futures::{future, future::poll_fn, FutureExt, Sink, SinkExt, Stream, StreamE
 use std::{
     collections::VecDeque,
     fmt, io, mem,
+    num::NonZeroUsize,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
     task::Poll,
@@ -20,7 +21,10 @@ use tokio::{
 
 use crate::{
     cfg::{Cfg, ExchangedCfg},
-    control::{Direction, DisconnectReason, Link, LinkIntervalStats, LinkStats, NotWorkingReason},
+    control::{
+        ConnLinkStats, Direction, DisconnectReason, Link, LinkEvent, LinkEventKind, LinkHealth,
+        LinkIntervalStats, LinkStats, NotWorkingReason, PathStats,
+    },
     id::{ConnId, LinkId},
     msg::LinkMsg,
     seq::Seq,
@@ -48,10 +52,17 @@ pub(crate) enum LinkIntEvent {
     FlushDelayPassed,
     /// Local disconnection request.
     Disconnect,
+    /// Local draining request.
+    Drain,
     /// Link blocked status has changed.
     BlockedChanged,
+    /// Link rate limit has changed.
+    RateLimitChanged,
 }
 
+/// Value of the link rate limit denoting that no limit is set.
+pub(crate) const NO_RATE_LIMIT: u64 = u64::MAX;
+
 /// Link test status.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum LinkTest {
@@ -82,6 +93,8 @@ pub(crate) struct LinkInt<TX, RX, TAG> {
     link_id: LinkId,
     /// Direction of link.
     direction: Direction,
+    /// Tracing span of link.
+    pub(crate) span: tracing::Span,
     /// Configuration.
     cfg: Arc<Cfg>,
     /// Configuration of remote endpoint.
@@ -100,6 +113,8 @@ pub(crate) struct LinkInt<TX, RX, TAG> {
     pub(crate) tx_pending: bool,
     /// When last message has been sent.
     pub(crate) tx_last_msg: Option<Instant>,
+    /// When last message has been received.
+    pub(crate) rx_last_msg: Option<Instant>,
     /// Sequence number of sent and not yet acknowledged packet.
     txed_unacked: Option<Seq>,
     /// Since when the transmit part of the link is idle.
@@ -130,6 +145,12 @@ pub(crate) struct LinkInt<TX, RX, TAG> {
     disconnect_tx: mpsc::Sender<()>,
     /// Graceful disconnect request receiver.
     disconnect_rx: mpsc::Receiver<()>,
+    /// Link is being drained.
+    pub(crate) draining: Arc<AtomicBool>,
+    /// Drain request sender.
+    drain_tx: mpsc::Sender<()>,
+    /// Drain request receiver.
+    drain_rx: mpsc::Receiver<()>,
     /// Link blocked by user.
     pub(crate) blocked: Arc<AtomicBool>,
     /// Blocked status last sent to remote endpoint.
@@ -144,6 +165,20 @@ pub(crate) struct LinkInt<TX, RX, TAG> {
     blocked_changed_out_rx: watch::Receiver<()>,
     /// Link blocked by remote endpoint.
     pub(crate) remotely_blocked: Arc<AtomicBool>,
+    /// Link weight set by user.
+    pub(crate) weight: Arc<AtomicU32>,
+    /// Limit of bytes sent per second set by user, [`NO_RATE_LIMIT`] if unlimited.
+    pub(crate) rate_limit: Arc<AtomicU64>,
+    /// Link rate limit changed.
+    pub(crate) rate_limit_changed_tx: mpsc::Sender<()>,
+    /// Link rate limit changed receiver.
+    rate_limit_changed_rx: mpsc::Receiver<()>,
+    /// Preferred size of data segments set by user, zero if unset.
+    pub(crate) segment_size: Arc<AtomicUsize>,
+    /// Token bucket for rate limiting: bytes that may be sent and when this was last updated.
+    rate_tokens: (f64, Instant),
+    /// Path characteristics estimator.
+    path: PathEstimator,
     /// Since when the link is unconfirmed, i.e. it has not been tested or message
     /// acknowledgement timed out.
     pub(crate) unconfirmed: Option<(Instant, NotWorkingReason)>,
@@ -159,6 +194,8 @@ pub(crate) struct LinkInt<TX, RX, TAG> {
     pub(crate) last_ping: Option<Instant>,
     /// When current (not yet answered) ping has been sent.
     pub(crate) current_ping_sent: Option<Instant>,
+    /// Number of consecutive pings that have not been answered in time.
+    pub(crate) missed_pings: u32,
     /// Send ping when link becomes ready for sending.
     pub(crate) send_ping: bool,
     /// Send ping reply when link becomes ready for sending.
@@ -213,16 +250,20 @@ where
     ) -> Self {
         let (disconnected_tx, _) = watch::channel(DisconnectReason::TaskTerminated);
         let (disconnect_tx, disconnect_rx) = mpsc::channel(1);
+        let (drain_tx, drain_rx) = mpsc::channel(1);
         let (blocked_changed_tx, blocked_changed_rx) = mpsc::channel(2);
+        let (rate_limit_changed_tx, rate_limit_changed_rx) = mpsc::channel(2);
         let stats = LinkStatistican::new(&cfg.stats_intervals, roundtrip);
         let (unconfirmed_tx, unconfirmed_rx) = watch::channel(None);
         let (blocked_changed_out_tx, blocked_changed_out_rx) = watch::channel(());
+        let link_id = LinkId::generate();
 
         Self {
             tag: Arc::new(tag),
             conn_id,
-            link_id: LinkId::generate(),
+            link_id,
             direction,
+            span: tracing::debug_span!("link", conn = %conn_id, link = %link_id, %direction),
             tx,
             tx_data: None,
             tx_error: None,
@@ -232,6 +273,9 @@ where
             disconnected_tx,
             disconnect_tx,
             disconnect_rx,
+            draining: Arc::new(AtomicBool::new(false)),
+            drain_tx,
+            drain_rx,
             stats,
             goodbye_sent: false,
             tx_polling: None,
@@ -242,6 +286,13 @@ where
             blocked_changed_out_tx,
             blocked_changed_out_rx,
             remotely_blocked: Arc::new(AtomicBool::new(false)),
+            weight: Arc::new(AtomicU32::new(Link::<TAG>::DEFAULT_WEIGHT)),
+            rate_limit: Arc::new(AtomicU64::new(NO_RATE_LIMIT)),
+            rate_limit_changed_tx,
+            rate_limit_changed_rx,
+            segment_size: Arc::new(AtomicUsize::new(0)),
+            rate_tokens: (0., Instant::now()),
+            path: PathEstimator::new(),
             unconfirmed: None,
             unconfirmed_tx,
             unconfirmed_rx,
@@ -250,9 +301,11 @@ where
             tx_flushed: true,
             rxed_data_msg: None,
             tx_last_msg: None,
+            rx_last_msg: None,
             txed_unacked: None,
             last_ping: None,
             current_ping_sent: None,
+            missed_pings: 0,
             send_ping: false,
             send_pong: false,
             roundtrip,
@@ -292,6 +345,7 @@ where
         });
 
         let flushable = !(self.tx_flushing || self.tx_flushed);
+        let span = &self.span;
 
         let tx_task = async {
             loop {
@@ -331,7 +385,7 @@ where
                             }
                         },
                         Err(err) => {
-                            tracing::debug!("link {id} poll ready failure: {}", err);
+                            tracing::debug!(parent: span, "link {id} poll ready failure: {}", err);
                             break LinkIntEvent::TxError(err);
                         }
                     }
@@ -372,11 +426,11 @@ where
                         }
                     }
                     Some(Err(err)) => {
-                        tracing::debug!("link {id} receive failure: {}", err);
+                        tracing::debug!(parent: span, "link {id} receive failure: {}", err);
                         break LinkIntEvent::RxError(err);
                     }
                     None => {
-                        tracing::debug!("link {id} receive end");
+                        tracing::debug!(parent: span, "link {id} receive end");
                         break LinkIntEvent::RxError(io::ErrorKind::BrokenPipe.into());
                     }
                 }
@@ -395,7 +449,9 @@ where
             rx_event = rx_task => rx_event,
             () = flush_req_task => LinkIntEvent::FlushDelayPassed,
             Some(()) = self.disconnect_rx.recv() => LinkIntEvent::Disconnect,
+            Some(()) = self.drain_rx.recv() => LinkIntEvent::Drain,
             Some(()) = self.blocked_changed_rx.recv() => LinkIntEvent::BlockedChanged,
+            Some(()) = self.rate_limit_changed_rx.recv() => LinkIntEvent::RateLimitChanged,
         }
     }
 
@@ -423,12 +479,13 @@ where
         let data_len = data.as_ref().map(|data| data.len()).unwrap_or_default();
 
         if let Err(err) = self.tx.start_send_unpin(encoded) {
-            tracing::debug!("link send failure: {}", err);
+            tracing::debug!(parent: &self.span, "link send failure: {}", err);
             self.tx_error = Some(err);
             return;
         }
 
         self.stats.record(msg_len + data_len, 0);
+        self.consume_rate_tokens(msg_len + data_len);
 
         self.tx_data = data;
         self.tx_last_msg = Some(Instant::now());
@@ -510,6 +567,11 @@ where
 
     /// Notifies of link disconnection.
     pub(crate) fn notify_disconnected(mut self, reason: DisconnectReason) {
+        tracing::debug!(parent: &self.span, "link disconnected: {reason}");
+
+        self.stats.current.health = LinkHealth::Dead;
+        self.stats.tx.send_replace(self.stats.current.clone());
+
         self.disconnected_tx.send_replace(reason);
         self.disconnect_rx.close();
     }
@@ -520,9 +582,64 @@ where
         self.stats.mark_idle();
     }
 
-    /// Returns whether unacknowledged sent data is under the limit.
+    /// Link weight for scheduling.
+    pub(crate) fn weight(&self) -> u32 {
+        self.weight.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether unacknowledged sent data is under the limit and
+    /// the rate limit permits sending.
     pub(crate) fn is_sendable(&self) -> bool {
-        self.txed_unacked_data < self.txed_unacked_data_limit
+        self.txed_unacked_data < self.txed_unacked_data_limit && !self.is_rate_limited()
+    }
+
+    /// Limit of bytes sent per second.
+    pub(crate) fn rate_limit(&self) -> Option<u64> {
+        match self.rate_limit.load(Ordering::SeqCst) {
+            NO_RATE_LIMIT => None,
+            limit => Some(limit),
+        }
+    }
+
+    /// Preferred size of data segments sent over the link.
+    pub(crate) fn segment_size(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(self.segment_size.load(Ordering::SeqCst))
+    }
+
+    /// Bytes that may be sent at the specified time according to the rate limit.
+    ///
+    /// At most one second worth of bytes is accumulated.
+    fn rate_tokens(&self, limit: u64, now: Instant) -> f64 {
+        let (tokens, since) = self.rate_tokens;
+        let limit = limit as f64;
+        (tokens + now.saturating_duration_since(since).as_secs_f64() * limit).min(limit)
+    }
+
+    /// Takes bytes that have been sent from the rate limit token bucket.
+    fn consume_rate_tokens(&mut self, size: usize) {
+        if let Some(limit) = self.rate_limit() {
+            let now = Instant::now();
+            self.rate_tokens = (self.rate_tokens(limit, now) - size as f64, now);
+        }
+    }
+
+    /// Whether sending is currently prevented by the rate limit.
+    pub(crate) fn is_rate_limited(&self) -> bool {
+        match self.rate_limit() {
+            Some(limit) => self.rate_tokens(limit, Instant::now()) <= 0.,
+            None => false,
+        }
+    }
+
+    /// When sending will be permitted again by the rate limit, if it is currently prevented.
+    pub(crate) fn rate_limited_until(&self) -> Option<Instant> {
+        let limit = self.rate_limit().filter(|&limit| limit > 0)?;
+        if !self.is_rate_limited() {
+            return None;
+        }
+
+        let (tokens, since) = self.rate_tokens;
+        Some(since + Duration::from_secs_f64((1. - tokens) / limit as f64))
     }
 
     /// Since when transmitter is being polled for readyness.
@@ -541,9 +658,16 @@ where
         self.txed_unacked_data_limit_increased_consecutively = 0;
     }
 
-    /// Whether link is blocked locally or remotely.
+    /// Whether link is blocked locally or remotely or is being drained.
     pub(crate) fn is_blocked(&self) -> bool {
-        self.blocked.load(Ordering::SeqCst) || self.remotely_blocked.load(Ordering::SeqCst)
+        self.blocked.load(Ordering::SeqCst)
+            || self.remotely_blocked.load(Ordering::SeqCst)
+            || self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Whether link is being drained.
+    pub(crate) fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
     }
 
     /// Publishes link statistics.
@@ -552,8 +676,74 @@ where
         self.stats.current.unacked_limit = self.txed_unacked_data_limit as _;
         self.stats.current.roundtrip = self.roundtrip;
 
+        if self.stats.current.draining != self.is_draining() {
+            self.stats.current.draining = self.is_draining();
+            self.stats.tx.send_replace(self.stats.current.clone());
+        }
+
+        let blocked = self.blocked.load(Ordering::SeqCst);
+        let remotely_blocked = self.remotely_blocked.load(Ordering::SeqCst);
+        if self.stats.current.blocked != blocked || self.stats.current.remotely_blocked != remotely_blocked {
+            self.stats.current.blocked = blocked;
+            self.stats.current.remotely_blocked = remotely_blocked;
+            self.stats.tx.send_replace(self.stats.current.clone());
+        }
+
+        if self.stats.current.rate_limit != self.rate_limit() {
+            self.stats.current.rate_limit = self.rate_limit();
+            self.stats.tx.send_replace(self.stats.current.clone());
+        }
+
+        if self.stats.current.missed_pings != self.missed_pings {
+            self.stats.current.missed_pings = self.missed_pings;
+            self.stats.current.health = self.health();
+            self.stats.tx.send_replace(self.stats.current.clone());
+        }
+
         self.stats.publish();
     }
+
+    /// Health of the link determined by pinging.
+    pub(crate) fn health(&self) -> LinkHealth {
+        match self.missed_pings {
+            0 => LinkHealth::Alive,
+            _ => LinkHealth::Suspect,
+        }
+    }
+
+    /// Records that a packet has been resent over the link.
+    pub(crate) fn record_resent(&mut self) {
+        self.stats.current.total_resent += 1;
+    }
+
+    /// Records that a packet sent over the link has been acknowledged after the specified round trip time.
+    pub(crate) fn record_acked(&mut self, roundtrip: Duration) {
+        self.path.record(Some(roundtrip));
+    }
+
+    /// Records that a packet sent over the link has not been acknowledged in time.
+    pub(crate) fn record_lost(&mut self) {
+        self.path.record(None);
+    }
+
+    /// Event of the specified kind concerning this link.
+    pub(crate) fn link_event(&self, kind: LinkEventKind) -> LinkEvent<TAG> {
+        LinkEvent { conn_id: self.conn_id, link_id: self.link_id, tag: self.tag.clone(), kind }
+    }
+
+    /// Link statistics for inclusion in connection statistics.
+    pub(crate) fn conn_link_stats(&self) -> ConnLinkStats<TAG> {
+        ConnLinkStats {
+            id: self.link_id,
+            tag: self.tag.clone(),
+            total_sent: self.stats.current.total_sent,
+            total_recved: self.stats.current.total_recved,
+            total_resent: self.stats.current.total_resent,
+            roundtrip: self.roundtrip,
+            health: self.health(),
+            rate_limit: self.rate_limit(),
+        }
+    }
 }
 
 impl<TX, RX, TAG> From<&LinkInt<TX, RX, TAG>> for Link<TAG> {
@@ -566,6 +756,8 @@ impl<TX, RX, TAG> From<&LinkInt<TX, RX, TAG>> for Link<TAG> {
             cfg: link_int.cfg.clone(),
             disconnected_rx: link_int.disconnected_tx.subscribe(),
             disconnect_tx: link_int.disconnect_tx.clone(),
+            draining: link_int.draining.clone(),
+            drain_tx: link_int.drain_tx.clone(),
             stats_rx: link_int.stats.subscribe(),
             remote_user_data: link_int.remote_user_data.clone(),
             blocked: link_int.blocked.clone(),
@@ -573,10 +765,76 @@ impl<TX, RX, TAG> From<&LinkInt<TX, RX, TAG>> for Link<TAG> {
             blocked_changed_rx: link_int.blocked_changed_out_rx.clone(),
             not_working_rx: link_int.unconfirmed_rx.clone(),
             remotely_blocked: link_int.remotely_blocked.clone(),
+            weight: link_int.weight.clone(),
+            rate_limit: link_int.rate_limit.clone(),
+            rate_limit_changed_tx: link_int.rate_limit_changed_tx.clone(),
+            segment_size: link_int.segment_size.clone(),
+            path_rx: link_int.path.subscribe(),
         }
     }
 }
 
+/// Minimum number of samples before a path characteristic is reported.
+const PATH_MIN_SAMPLES: u32 = 8;
+
+/// Number of samples over which the round trip time is smoothed.
+const PATH_ROUNDTRIP_SMOOTHING: u32 = 8;
+
+/// Number of samples over which the loss rate is smoothed.
+const PATH_LOSS_SMOOTHING: u32 = 64;
+
+/// Estimator of path characteristics from acknowledgements.
+///
+/// Until the smoothing window is filled, the plain average of all samples is used.
+struct PathEstimator {
+    /// Smoothed round trip time.
+    roundtrip: Duration,
+    /// Number of round trip time samples.
+    roundtrip_samples: u32,
+    /// Smoothed loss rate.
+    loss: f64,
+    /// Number of loss samples, i.e. acknowledged or lost packets.
+    loss_samples: u32,
+    /// Channel for publishing path statistics.
+    tx: watch::Sender<PathStats>,
+}
+
+impl PathEstimator {
+    fn new() -> Self {
+        Self {
+            roundtrip: Duration::ZERO,
+            roundtrip_samples: 0,
+            loss: 0.,
+            loss_samples: 0,
+            tx: watch::channel(PathStats::default()).0,
+        }
+    }
+
+    fn subscribe(&self) -> watch::Receiver<PathStats> {
+        self.tx.subscribe()
+    }
+
+    /// Records a packet that has been acknowledged after the specified round trip time
+    /// or, if `None`, has been lost.
+    fn record(&mut self, roundtrip: Option<Duration>) {
+        if let Some(roundtrip) = roundtrip {
+            self.roundtrip_samples = self.roundtrip_samples.saturating_add(1);
+            let weight = self.roundtrip_samples.min(PATH_ROUNDTRIP_SMOOTHING);
+            self.roundtrip = (self.roundtrip * (weight - 1) + roundtrip) / weight;
+        }
+
+        self.loss_samples = self.loss_samples.saturating_add(1);
+        let weight = self.loss_samples.min(PATH_LOSS_SMOOTHING) as f64;
+        let lost = if roundtrip.is_none() { 1. } else { 0. };
+        self.loss += (lost - self.loss) / weight;
+
+        self.tx.send_replace(PathStats {
+            roundtrip: (self.roundtrip_samples >= PATH_MIN_SAMPLES).then_some(self.roundtrip),
+            loss: (self.loss_samples >= PATH_MIN_SAMPLES).then_some(self.loss),
+        });
+    }
+}
+
 /// Link statistics keeper.
 struct LinkStatistican {
     /// Channel for publishing statistics.
@@ -596,10 +854,17 @@ impl LinkStatistican {
             established: Instant::now(),
             total_sent: 0,
             total_recved: 0,
+            total_resent: 0,
             sent_unacked: 0,
             unacked_limit: 0,
             roundtrip,
             hangs: 0,
+            draining: false,
+            blocked: false,
+            remotely_blocked: false,
+            health: LinkHealth::Alive,
+            missed_pings: 0,
+            rate_limit: None,
             time_stats: running_stats.clone(),
         };
 
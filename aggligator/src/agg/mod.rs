@@ -4,16 +4,20 @@ use bytes::Bytes;
 use futures::{Sink, Stream};
 use std::{
     io,
-    sync::{atomic::AtomicBool, Arc},
+    sync::{
+        atomic::{AtomicBool, AtomicU8},
+        Arc,
+    },
 };
-use tokio::sync::{mpsc, oneshot, watch, Mutex};
+use tokio::sync::{broadcast, mpsc, oneshot, watch, Mutex};
 
 use crate::{
     agg::{link_int::LinkInt, task::Task},
     alc::{Channel, RecvError, SendError},
     cfg::{Cfg, ExchangedCfg},
-    control::{Control, Direction, Link},
+    control::{ConnState, Control, Direction, Link, RetransmitPolicy, SendMode},
     id::{OwnedConnId, ServerId},
+    queue_limit::queue_limit,
     TaskError,
 };
 
@@ -46,6 +50,14 @@ where
     ) -> Self {
         let (read_tx, read_rx) = mpsc::channel(cfg.recv_queue.get());
         let (write_tx, write_rx) = mpsc::channel(cfg.send_queue.get());
+        let (read_space, read_limit) = match cfg.recv_queue_size.map(queue_limit) {
+            Some((space, limit)) => (Some(space), Some(limit)),
+            None => (None, None),
+        };
+        let (write_space, write_limit) = match cfg.send_queue_size.map(queue_limit) {
+            Some((space, limit)) => (Some(space), Some(limit)),
+            None => (None, None),
+        };
         let (read_error_tx, read_error_rx) = watch::channel(Some(RecvError::TaskTerminated));
         let (write_error_tx, write_error_rx) = watch::channel(SendError::TaskTerminated);
         let (read_closed_tx, read_closed_rx) = mpsc::channel(1);
@@ -53,8 +65,17 @@ where
         let (link_tx, link_rx) = link_tx_rx.unwrap_or_else(|| mpsc::channel(cfg.connect_queue.get()));
         let (connected_tx, connected_rx) = oneshot::channel();
         let (stats_tx, stats_rx) = watch::channel(Default::default());
+        let (conn_stats_tx, conn_stats_rx) = watch::channel(Default::default());
+        let (state_tx, state_rx) = watch::channel(ConnState::Connecting);
+        let (link_count_tx, link_count_rx) = watch::channel(0);
         let (server_changed_tx, server_changed_rx) = mpsc::channel(1);
         let (result_tx, result_rx) = watch::channel(Err(TaskError::Terminated));
+        let send_mode = Arc::new(AtomicU8::new(SendMode::default().to_u8()));
+        let (send_mode_changed_tx, send_mode_changed_rx) = mpsc::channel(1);
+        let retransmit_policy = Arc::new(AtomicU8::new(RetransmitPolicy::default().to_u8()));
+        let (retransmit_policy_changed_tx, retransmit_policy_changed_rx) = mpsc::channel(1);
+        let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
+        let link_event_tx = Arc::new(broadcast::channel(cfg.link_event_queue.get()).0);
         let remote_cfg = links.first().as_ref().map(|link| link.remote_cfg());
         let connected = Arc::new(AtomicBool::new(!links.is_empty()));
 
@@ -68,13 +89,24 @@ where
                 link_rx,
                 connected_tx,
                 read_tx,
+                read_space,
                 read_closed_rx,
                 write_rx,
+                write_limit,
                 read_error_tx,
                 write_error_tx,
                 stats_tx,
+                conn_stats_tx,
+                state_tx,
+                link_count_tx,
                 server_changed_rx,
                 result_tx,
+                send_mode.clone(),
+                send_mode_changed_rx,
+                retransmit_policy.clone(),
+                retransmit_policy_changed_rx,
+                shutdown_rx,
+                link_event_tx.clone(),
                 links,
             ),
             channel: Channel::new(
@@ -82,8 +114,10 @@ where
                 remote_cfg,
                 conn_id.get(),
                 write_tx,
+                write_space,
                 write_error_rx,
                 read_rx,
+                read_limit,
                 read_closed_tx,
                 read_error_rx,
             ),
@@ -97,8 +131,18 @@ where
                 links_rx,
                 connected,
                 stats_rx,
+                conn_stats_rx,
+                state_rx,
+                link_count_rx,
+                redundant_seen: false,
                 server_changed_tx,
                 result_rx,
+                send_mode,
+                send_mode_changed_tx,
+                retransmit_policy,
+                retransmit_policy_changed_tx,
+                shutdown_tx,
+                link_event_tx: Arc::downgrade(&link_event_tx),
             },
             connected_rx,
         }
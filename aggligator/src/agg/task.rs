@@ -14,14 +14,18 @@ use std::{
     future::IntoFuture,
     io,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU8, Ordering},
         Arc,
     },
     time::Duration,
 };
 use tokio::{
     select,
-    sync::{mpsc, oneshot, watch},
+    sync::{
+        broadcast,
+        mpsc::{self, error::TryRecvError},
+        oneshot, watch, OwnedSemaphorePermit,
+    },
     time::{interval, sleep_until, timeout, Instant},
 };
 use tokio_stream::wrappers::IntervalStream;
@@ -29,17 +33,23 @@ use tokio_stream::wrappers::IntervalStream;
 use crate::{
     agg::link_int::{DisconnectInitiator, LinkInt, LinkIntEvent, LinkTest},
     alc::{RecvError, SendError},
-    cfg::{Cfg, ExchangedCfg, LinkPing},
-    control::{Direction, DisconnectReason, Link, NotWorkingReason, Stats},
+    cfg::{Cfg, ExchangedCfg, LinkPing, ReorderPolicy},
+    control::{
+        ConnState, ConnStats, Direction, DisconnectReason, Link, LinkEvent, LinkEventKind, NotWorkingReason,
+        RetransmitPolicy, SendMode, Stats,
+    },
     id::{ConnId, LinkId, OwnedConnId},
     msg::{LinkMsg, RefusedReason, ReliableMsg},
     peekable_mpsc::{PeekableReceiver, RecvIfError},
     protocol_err,
+    queue_limit::{QueueLimit, QueueSpace},
+    selector::{LinkCandidate, LinkSelector, LowLatencyLinkSelector, WeightedLinkSelector},
     seq::Seq,
 };
 
 /// Error indicating why a connection of aggregated links failed.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum TaskError {
     /// All links were unconfirmed for too long at the same time.
     AllUnconfirmedTimeout,
@@ -58,6 +68,12 @@ pub enum TaskError {
     ServerIdMismatch,
     /// The task was terminated, possibly due to the runtime shutting down.
     Terminated,
+    /// A [shutdown](crate::control::Control::shutdown) did not complete, because not all
+    /// sent data was acknowledged by the remote endpoint before the timeout elapsed or
+    /// all links failed.
+    ShutdownIncomplete,
+    /// No data was sent or received within the [idle timeout](crate::cfg::Cfg::idle_timeout).
+    IdleTimeout,
 }
 
 impl fmt::Display for TaskError {
@@ -68,6 +84,8 @@ impl fmt::Display for TaskError {
             Self::ProtocolError { link_id, error } => write!(f, "protocol error on link {link_id}: {error}"),
             Self::ServerIdMismatch => write!(f, "a new link connected to another server"),
             Self::Terminated => write!(f, "task terminated"),
+            Self::ShutdownIncomplete => write!(f, "shutdown incomplete"),
+            Self::IdleTimeout => write!(f, "idle timeout"),
         }
     }
 }
@@ -85,10 +103,22 @@ impl From<TaskError> for std::io::Error {
 pub(crate) enum SendReq {
     /// Send data.
     Send(Bytes),
+    /// Send data written using stream-based IO, which may be split into segments.
+    Write(Bytes),
     /// Flush.
     Flush(oneshot::Sender<()>),
 }
 
+impl SendReq {
+    /// Length of the data to send.
+    fn data_len(&self) -> Option<usize> {
+        match self {
+            Self::Send(data) | Self::Write(data) => Some(data.len()),
+            Self::Flush(_) => None,
+        }
+    }
+}
+
 /// Send overrun handling.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum SendOverrun {
@@ -118,6 +148,18 @@ impl fmt::Debug for SentReliable {
     }
 }
 
+impl SentReliable {
+    /// Size of data.
+    fn size(&self) -> usize {
+        match &*self.status.borrow() {
+            SentReliableStatus::Sent { msg: ReliableMsg::Data(data), .. }
+            | SentReliableStatus::ResendQueued { msg: ReliableMsg::Data(data) } => data.len(),
+            SentReliableStatus::Received { size } => *size,
+            _ => 0,
+        }
+    }
+}
+
 /// Status of a sent reliable packet.
 #[derive(Debug, Clone)]
 enum SentReliableStatus {
@@ -131,6 +173,9 @@ enum SentReliableStatus {
         msg: ReliableMsg,
         /// Whether packet has been resent.
         resent: bool,
+        /// Index of link a copy of the packet has been sent over, because its
+        /// acknowledgement was overdue.
+        reissued: Option<usize>,
     },
     /// Message was received by remote endpoint.
     Received {
@@ -162,7 +207,7 @@ enum TaskEvent<TX, RX, TAG> {
     /// A link event occurred.
     LinkEvent { id: usize, event: LinkIntEvent },
     /// Data to send over an idle link has been received.
-    WriteRx { id: usize, data: Bytes },
+    WriteRx { id: usize, req: SendReq },
     /// No more data to send will be received.
     WriteEnd,
     /// Flush.
@@ -171,12 +216,20 @@ enum TaskEvent<TX, RX, TAG> {
     ConfirmTimedOut(usize),
     /// Resend packet over an idle link.
     Resend(Arc<SentReliable>),
+    /// Send copy of packet with overdue acknowledgement over specified idle link.
+    Reissue { id: usize, packet: Arc<SentReliable> },
+    /// Data to send has been queued while none was queued before.
+    WriteQueued,
     /// Data consumer was dropped.
     ReadDropped,
     /// Data consumer was closed.
     ReadClosed,
     /// Received data has been consumed.
-    ConsumeReceived { received: ReceivedReliableMsg, permit: Option<mpsc::OwnedPermit<Bytes>> },
+    ConsumeReceived {
+        received: ReceivedReliableMsg,
+        permit: Option<mpsc::OwnedPermit<Bytes>>,
+        space: Option<OwnedSemaphorePermit>,
+    },
     /// Space for sending a queued ack has become available.
     SendConsumed,
     /// Ping a link.
@@ -189,6 +242,8 @@ enum TaskEvent<TX, RX, TAG> {
     LinkPingTimeout(usize),
     /// A link requires testing.
     LinkTesting,
+    /// A rate limited link may be able to send again.
+    RateLimitPassed,
     /// No working links within timeout.
     NoLinksTimeout,
     /// Publish link statistics.
@@ -197,6 +252,16 @@ enum TaskEvent<TX, RX, TAG> {
     RefusedLinkTask,
     /// The server id changed.
     ServerChanged,
+    /// The send mode changed.
+    SendModeChanged,
+    /// The retransmit policy changed.
+    RetransmitPolicyChanged,
+    /// Graceful shutdown with the specified timeout requested.
+    Shutdown(Duration),
+    /// Graceful shutdown did not complete within timeout.
+    ShutdownTimeout,
+    /// No data was sent or received within the idle timeout.
+    IdleTimeout,
 }
 
 /// Link filter function type.
@@ -232,6 +297,8 @@ pub struct Task<TX, RX, TAG> {
     connected_tx: Option<oneshot::Sender<Arc<ExchangedCfg>>>,
     /// Channel for sending received message to user.
     read_tx: Option<mpsc::Sender<Bytes>>,
+    /// Space in the queue of received messages, if its size is limited.
+    read_space: Option<QueueSpace>,
     /// Channel to receive message from user that receive channel should be closed.
     read_closed_rx: Option<mpsc::Receiver<()>>,
     /// ReceiveClose message has been sent.
@@ -240,6 +307,8 @@ pub struct Task<TX, RX, TAG> {
     receive_finish_sent: bool,
     /// Channel for receiving messages to send from user.
     write_rx: Option<PeekableReceiver<SendReq>>,
+    /// Size limit of the queue of messages to send.
+    write_limit: Option<QueueLimit>,
     /// Whether remote endpoint closed its receiver.
     write_closed: Arc<AtomicBool>,
     /// SendFinish message has been sent.
@@ -266,6 +335,10 @@ pub struct Task<TX, RX, TAG> {
     txed_last_consumed: Seq,
     /// Queue of packets that have been declared lost and must be send again.
     resend_queue: VecDeque<Arc<SentReliable>>,
+    /// Number of packets a copy has been sent of over a second link.
+    reissued: u64,
+    /// Number of packets whose copy has been acknowledged first.
+    reissued_won: u64,
     /// Ids of links that are ready to send data.
     idle_links: Vec<usize>,
     /// Next data sequence number for handing out.
@@ -276,6 +349,8 @@ pub struct Task<TX, RX, TAG> {
     rxed_reliable_consumable: VecDeque<ReceivedReliableMsg>,
     /// Sum of size of all buffers in `rxed_reliable` and `rxed_reliable_consumable`.
     rxed_reliable_size: usize,
+    /// Sum of size of all buffers in `rxed_reliable`, i.e. data that cannot yet be consumed.
+    rxed_reliable_unconsumable_size: usize,
     /// Size of that that has been consumed since last acknowledgement.
     rxed_reliable_consumed_since_last_ack: usize,
     /// Forces acking consumed data.
@@ -294,14 +369,42 @@ pub struct Task<TX, RX, TAG> {
     stats_tx: watch::Sender<Stats>,
     /// Time when connection statistics were last sent.
     stats_last_sent: Instant,
+    /// Channel for publishing statistics of all links.
+    conn_stats_tx: watch::Sender<ConnStats<TAG>>,
+    /// When statistics of all links were last published.
+    conn_stats_last_sent: Instant,
+    /// Channel for publishing the connection state.
+    state_tx: watch::Sender<ConnState>,
+    /// Channel for publishing the number of working links.
+    link_count_tx: watch::Sender<usize>,
     /// Filter function for new links.
     link_filter: LinkFilterFn<TAG>,
+    /// Selector of link for sending data.
+    link_selector: Box<dyn LinkSelector>,
+    /// Selector of link for sending data in single path mode.
+    low_latency_selector: LowLatencyLinkSelector,
+    /// Mode of sending data.
+    send_mode: Arc<AtomicU8>,
+    /// Send mode changed notification.
+    send_mode_changed_rx: mpsc::Receiver<()>,
+    /// Policy for retransmitting data.
+    retransmit_policy: Arc<AtomicU8>,
+    /// Retransmit policy changed notification.
+    retransmit_policy_changed_rx: mpsc::Receiver<()>,
     /// Links provided at creation of this task.
     init_links: VecDeque<LinkInt<TX, RX, TAG>>,
     /// Tasks handling refused links.
     refused_links_tasks: FuturesUnordered<BoxFuture<'static, ()>>,
     /// Server changed notification.
     server_changed_rx: mpsc::Receiver<()>,
+    /// Shutdown request receiver.
+    shutdown_rx: mpsc::Receiver<Duration>,
+    /// Deadline for completing a requested shutdown.
+    shutdown_deadline: Option<Instant>,
+    /// When data was last sent or received by the application.
+    last_data: Instant,
+    /// Link events sender.
+    link_event_tx: Arc<broadcast::Sender<LinkEvent<TAG>>>,
     /// Result of task sender.
     result_tx: watch::Sender<Result<(), TaskError>>,
     /// Channel for sending analysis data.
@@ -326,10 +429,15 @@ where
         cfg: Arc<Cfg>, remote_cfg: Option<Arc<ExchangedCfg>>, conn_id: OwnedConnId, direction: Direction,
         links_tx: watch::Sender<Vec<Link<TAG>>>, link_rx: mpsc::Receiver<LinkInt<TX, RX, TAG>>,
         connected_tx: oneshot::Sender<Arc<ExchangedCfg>>, read_tx: mpsc::Sender<Bytes>,
-        read_closed_rx: mpsc::Receiver<()>, write_rx: mpsc::Receiver<SendReq>,
-        read_error_tx: watch::Sender<Option<RecvError>>, write_error_tx: watch::Sender<SendError>,
-        stats_tx: watch::Sender<Stats>, server_changed_rx: mpsc::Receiver<()>,
-        result_tx: watch::Sender<Result<(), TaskError>>, links: Vec<LinkInt<TX, RX, TAG>>,
+        read_space: Option<QueueSpace>, read_closed_rx: mpsc::Receiver<()>, write_rx: mpsc::Receiver<SendReq>,
+        write_limit: Option<QueueLimit>, read_error_tx: watch::Sender<Option<RecvError>>,
+        write_error_tx: watch::Sender<SendError>, stats_tx: watch::Sender<Stats>,
+        conn_stats_tx: watch::Sender<ConnStats<TAG>>, state_tx: watch::Sender<ConnState>,
+        link_count_tx: watch::Sender<usize>, server_changed_rx: mpsc::Receiver<()>,
+        result_tx: watch::Sender<Result<(), TaskError>>, send_mode: Arc<AtomicU8>,
+        send_mode_changed_rx: mpsc::Receiver<()>, retransmit_policy: Arc<AtomicU8>,
+        retransmit_policy_changed_rx: mpsc::Receiver<()>, shutdown_rx: mpsc::Receiver<Duration>,
+        link_event_tx: Arc<broadcast::Sender<LinkEvent<TAG>>>, links: Vec<LinkInt<TX, RX, TAG>>,
     ) -> Self {
         Self {
             cfg,
@@ -342,10 +450,12 @@ where
             links_not_working_since: None,
             connected_tx: Some(connected_tx),
             read_tx: Some(read_tx),
+            read_space,
             read_closed_rx: Some(read_closed_rx),
             receive_close_sent: false,
             receive_finish_sent: false,
             write_rx: Some(write_rx.into()),
+            write_limit,
             write_closed: Arc::new(AtomicBool::new(false)),
             send_finish_sent: false,
             read_error_tx,
@@ -356,6 +466,8 @@ where
             txed_packets: VecDeque::new(),
             txed_unacked: 0,
             resend_queue: VecDeque::new(),
+            reissued: 0,
+            reissued_won: 0,
             idle_links: Vec::new(),
             rx_seq: Seq::ZERO,
             rxed_reliable: VecDeque::new(),
@@ -365,6 +477,7 @@ where
             txed_unconsumable: 0,
             txed_last_consumed: Seq::MINUS_ONE,
             rxed_reliable_size: 0,
+            rxed_reliable_unconsumable_size: 0,
             rxed_reliable_consumed_force_ack: false,
             unflushed_links: HashSet::new(),
             flushed_tx: None,
@@ -373,10 +486,24 @@ where
             established: None,
             stats_tx,
             stats_last_sent: Instant::now(),
+            conn_stats_tx,
+            conn_stats_last_sent: Instant::now(),
+            state_tx,
+            link_count_tx,
             link_filter: Box::new(|_, _| async { true }.boxed()),
+            link_selector: Box::new(WeightedLinkSelector),
+            low_latency_selector: LowLatencyLinkSelector::default(),
+            send_mode,
+            send_mode_changed_rx,
+            retransmit_policy,
+            retransmit_policy_changed_rx,
             init_links: links.into(),
             refused_links_tasks: FuturesUnordered::new(),
             server_changed_rx,
+            shutdown_rx,
+            shutdown_deadline: None,
+            last_data: Instant::now(),
+            link_event_tx,
             result_tx,
             #[cfg(feature = "dump")]
             dump_tx: None,
@@ -387,13 +514,20 @@ where
     ///
     /// This returns when the connection has been terminated.
     /// Cancelling the returned future leads to immediate termination of the connection.
-    #[tracing::instrument(level = "debug")]
+    #[tracing::instrument(
+        level = "debug", name = "connection", skip(self), fields(conn = %self.conn_id, direction = %self.direction)
+    )]
     pub async fn run(mut self) -> Result<(), TaskError> {
         tracing::debug!("link aggregator task starting");
         self.start_time = Instant::now();
 
-        let mut stat_timers =
-            stream::select_all(self.cfg.stats_intervals.iter().map(|t| IntervalStream::new(interval(*t))));
+        let mut stat_timers = stream::select_all(
+            self.cfg
+                .stats_intervals
+                .iter()
+                .chain(Some(&self.cfg.conn_stats_interval))
+                .map(|t| IntervalStream::new(interval(*t))),
+        );
 
         let mut fast_rng = SplitMix64::seed_from_u64(1);
 
@@ -414,6 +548,7 @@ where
 
             // Send statistics and dump.
             self.send_stats();
+            self.send_conn_stats();
             #[cfg(feature = "dump")]
             self.send_dump();
 
@@ -422,17 +557,20 @@ where
             if self.read_tx.is_none() && self.write_rx.is_none() {
                 let since = self.read_write_closed.get_or_insert_with(Instant::now);
 
-                if (self.txed_packets.is_empty()
+                let drained = self.txed_packets.is_empty()
                     && self.txed_unconsumed == 0
                     && self.rxed_reliable_size == 0
                     && self.rxed_reliable_consumed_since_last_ack == 0
                     && self.send_finish_sent
-                    && self.receive_finish_sent)
-                    || !links_available
-                    || since.elapsed() >= self.cfg.termination_timeout
-                {
+                    && self.receive_finish_sent;
+
+                if drained || !links_available || since.elapsed() >= self.cfg.termination_timeout {
                     tracing::info!("disconnecting because sender and receiver were dropped");
-                    result = Ok(());
+                    result = if drained || self.shutdown_deadline.is_none() {
+                        Ok(())
+                    } else {
+                        Err(TaskError::ShutdownIncomplete)
+                    };
                     read_term = None;
                     write_term = SendError::Closed;
                     link_term = DisconnectReason::ConnectionClosed;
@@ -473,6 +611,8 @@ where
 
             // Timeout for no working links.
             let no_link_since = self.links_not_working_since();
+            self.publish_state(no_link_since);
+            self.publish_link_count();
             let no_link_timeout = self.cfg.no_link_timeout;
             let links_timeout = async move {
                 match no_link_since {
@@ -481,6 +621,24 @@ where
                 }
             };
 
+            // Timeout for completing shutdown.
+            let shutdown_deadline = self.shutdown_deadline;
+            let shutdown_timeout = async move {
+                match shutdown_deadline {
+                    Some(deadline) => sleep_until(deadline).await,
+                    None => future::pending().await,
+                }
+            };
+
+            // Timeout for connection without data.
+            let idle_deadline = self.cfg.idle_timeout.map(|idle_timeout| self.last_data + idle_timeout);
+            let idle_timeout = async move {
+                match idle_deadline {
+                    Some(deadline) => sleep_until(deadline).await,
+                    None => future::pending().await,
+                }
+            };
+
             // Timeout for sending next ping.
             let next_link_ping = self.next_link_ping();
             let next_ping_timeout = async move {
@@ -528,6 +686,44 @@ where
                 }
             };
 
+            // Timeout for sending a copy of a packet with overdue acknowledgement.
+            let next_reissue = self.next_reissue();
+            let reissue_timeout = async move {
+                match next_reissue {
+                    Some((timeout, id, packet)) => {
+                        sleep_until(timeout).await;
+                        (id, packet)
+                    }
+                    None => future::pending().await,
+                }
+            };
+
+            // Timeout for rate limited idle links becoming sendable again.
+            let next_rate_limited = self
+                .idle_links
+                .iter()
+                .filter_map(|id| self.links[*id].as_ref().unwrap().rate_limited_until())
+                .min();
+            let rate_limit_timeout = async move {
+                match next_rate_limited {
+                    Some(timeout) => sleep_until(timeout).await,
+                    None => future::pending().await,
+                }
+            };
+
+            // Size of data queued for sending.
+            let (queued_len, write_queued) = match self.write_rx.as_mut().map(|write_rx| write_rx.try_peek()) {
+                Some(Ok(SendReq::Send(data) | SendReq::Write(data))) => (Some(data.len()), true),
+                Some(Err(TryRecvError::Empty)) => (None, false),
+                _ => (None, true),
+            };
+
+            // Idle link for sending data, as chosen by the link selector.
+            // Resending takes priority over sending new data.
+            let segment_len =
+                if resending { self.resend_queue.front().map(|packet| packet.size()) } else { queued_len };
+            let sendable_idle_link_id = segment_len.and_then(|len| self.select_link(None, len));
+
             // Task for receiving a new link.
             let new_link_task = async {
                 match &mut self.link_rx {
@@ -541,25 +737,30 @@ where
             };
 
             // Task for receiving requests from sender.
-            let sendable_idle_link_id =
-                self.idle_links.iter().rev().cloned().find(|id| self.links[*id].as_ref().unwrap().is_sendable());
             let write_rx_task = async {
                 if links_idling && is_consume_ack_required {
                     TaskEvent::SendConsumed
                 } else {
                     match &mut self.write_rx {
+                        Some(write_rx) if tx_seq_avail && !resending && !write_queued => {
+                            // Wait for data, so that a link can be selected for it.
+                            match write_rx.peek().await {
+                                Some(_) => TaskEvent::WriteQueued,
+                                None => TaskEvent::WriteEnd,
+                            }
+                        }
                         Some(write_rx) if tx_seq_avail && !resending => {
                             match write_rx
                                 .recv_if(|msg| match msg {
-                                    SendReq::Send(data) => {
+                                    SendReq::Send(data) | SendReq::Write(data) => {
                                         data.len() <= tx_space && sendable_idle_link_id.is_some()
                                     }
                                     SendReq::Flush(_) => true,
                                 })
                                 .await
                             {
-                                Ok(SendReq::Send(data)) => {
-                                    TaskEvent::WriteRx { id: sendable_idle_link_id.unwrap(), data }
+                                Ok(req @ (SendReq::Send(_) | SendReq::Write(_))) => {
+                                    TaskEvent::WriteRx { id: sendable_idle_link_id.unwrap(), req }
                                 }
                                 Ok(SendReq::Flush(flushed_tx)) => TaskEvent::Flush(flushed_tx),
                                 Err(RecvIfError::NoMatch) => future::pending().await,
@@ -613,16 +814,31 @@ where
             let consume_task = async {
                 if !self.rxed_reliable_consumable.is_empty() {
                     match self.read_tx.as_ref() {
-                        Some(read_tx) => match read_tx.clone().reserve_owned().await {
-                            Ok(permit) => TaskEvent::ConsumeReceived {
-                                received: self.rxed_reliable_consumable.pop_front().unwrap(),
-                                permit: Some(permit),
-                            },
-                            Err(_) => TaskEvent::ReadDropped,
-                        },
+                        Some(read_tx) => {
+                            // Wait for space in the queue of received data, if its size is limited.
+                            let space = match (&self.read_space, &self.rxed_reliable_consumable[0].msg) {
+                                (Some(read_space), ReliableMsg::Data(data)) => {
+                                    match read_space.acquire(data.len()).await {
+                                        Some(space) => Some(space),
+                                        None => return TaskEvent::ReadDropped,
+                                    }
+                                }
+                                _ => None,
+                            };
+
+                            match read_tx.clone().reserve_owned().await {
+                                Ok(permit) => TaskEvent::ConsumeReceived {
+                                    received: self.rxed_reliable_consumable.pop_front().unwrap(),
+                                    permit: Some(permit),
+                                    space,
+                                },
+                                Err(_) => TaskEvent::ReadDropped,
+                            }
+                        }
                         None => TaskEvent::ConsumeReceived {
                             received: self.rxed_reliable_consumable.pop_front().unwrap(),
                             permit: None,
+                            space: None,
                         },
                     }
                 } else {
@@ -641,14 +857,21 @@ where
                 link_id = next_unconfirmed_timeout => TaskEvent::LinkUnconfirmedTimeout(link_id),
                 link_id = next_send_timeout => TaskEvent::LinkSendTimeout(link_id),
                 packet = resend_task => TaskEvent::Resend (packet),
+                (id, packet) = reissue_timeout => TaskEvent::Reissue { id, packet },
                 consume_event = consume_task => consume_event,
                 event = read_closed_task => event,
                 () = link_testing_timeout => TaskEvent::LinkTesting,
+                () = rate_limit_timeout => TaskEvent::RateLimitPassed,
                 () = links_timeout => TaskEvent::NoLinksTimeout,
+                () = shutdown_timeout => TaskEvent::ShutdownTimeout,
+                () = idle_timeout => TaskEvent::IdleTimeout,
                 Some(_) = stat_timers.next() => TaskEvent::PublishLinkStats,
                 Some(()) = self.refused_links_tasks.next(), if !self.refused_links_tasks.is_empty()
                     => TaskEvent::RefusedLinkTask,
                 Some(()) = self.server_changed_rx.recv() => TaskEvent::ServerChanged,
+                Some(()) = self.send_mode_changed_rx.recv() => TaskEvent::SendModeChanged,
+                Some(()) = self.retransmit_policy_changed_rx.recv() => TaskEvent::RetransmitPolicyChanged,
+                Some(timeout) = self.shutdown_rx.recv() => TaskEvent::Shutdown(timeout),
             };
 
             // Handle event.
@@ -663,6 +886,7 @@ where
                         self.links.iter().filter_map(|link_opt| link_opt.as_ref().map(Link::from)).collect();
                     if (self.link_filter)(Link::from(&link), others).await {
                         let id = self.add_link(link);
+                        let _span = self.enter_link_span(id);
                         tracing::info!("added new link with id {id}");
                     } else {
                         tracing::debug!("link was refused by link filter");
@@ -691,9 +915,11 @@ where
                     self.link_rx = None;
                 }
                 TaskEvent::LinkEvent { id, event } => {
+                    let _span = self.enter_link_span(id);
                     match event {
                         LinkIntEvent::TxReady => {
                             // Link is ready to send more data.
+                            let link_selected = self.is_selected_when_ready(id, tx_seq_avail, tx_space);
                             let link = self.links[id].as_mut().unwrap();
                             let link_blocked = link.blocked.load(Ordering::SeqCst);
                             if link.needs_tx_accepted {
@@ -763,23 +989,23 @@ where
                                     self.idle_links.retain(|&idle_id| idle_id != id);
                                     self.send_reliable_over_link(id, ReliableMsg::SendFinish);
                                     self.send_finish_sent = true;
-                                } else if let Some(SendReq::Send(data)) = self
-                                    .write_rx
-                                    .as_mut()
-                                    .filter(|_| tx_seq_avail && link.is_sendable())
-                                    .and_then(|rx| {
+                                } else if let Some(req) =
+                                    self.write_rx.as_mut().filter(|_| link_selected).and_then(|rx| {
                                         rx.try_recv_if(
-                                            |msg| matches!(msg, SendReq::Send(data) if data.len() <= tx_space),
+                                            |msg| matches!(msg.data_len(), Some(len) if len <= tx_space),
                                         )
                                         .ok()
                                     })
                                 {
+                                    let data = self.segment_for_link(id, req);
                                     tracing::trace!(
                                         "sending data of size {} over non-idle link {id}",
                                         data.len()
                                     );
                                     self.idle_links.retain(|idle_id| *idle_id != id);
                                     self.send_reliable_over_link(id, ReliableMsg::Data(data));
+                                    self.send_redundant_copy(id);
+                                    self.last_data = Instant::now();
                                 } else if link.need_ack_flush() {
                                     tracing::trace!("flushing link {id} due to sent acks");
                                     self.idle_links.retain(|&idle_id| idle_id != id);
@@ -838,12 +1064,19 @@ where
                             };
                             self.remove_link(id, reason);
                         }
+                        LinkIntEvent::RateLimitChanged => {
+                            // Local link rate limit has changed.
+                            let link = self.links[id].as_mut().unwrap();
+                            tracing::debug!("rate limit of link {id} has become {:?}", link.rate_limit());
+                            link.publish_stats();
+                        }
                         LinkIntEvent::BlockedChanged => {
                             // Local link blocking has changed.
                             let link = self.links[id].as_mut().unwrap();
                             self.idle_links.retain(|&idle_id| idle_id != id);
                             link.report_ready();
                             link.blocked_changed_out_tx.send_replace(());
+                            link.publish_stats();
                         }
                         LinkIntEvent::Disconnect => {
                             // Local request to disconnect link.
@@ -855,12 +1088,24 @@ where
                                 link.start_flush();
                             }
                         }
+                        LinkIntEvent::Drain => {
+                            // Local request to drain link.
+                            let link = self.links[id].as_mut().unwrap();
+                            tracing::info!("starting draining of link {id} by local request");
+                            self.idle_links.retain(|&idle_id| idle_id != id);
+                            link.report_ready();
+                            link.publish_stats();
+                            self.disconnect_if_drained(id);
+                        }
                     }
                 }
-                TaskEvent::WriteRx { id, data } => {
+                TaskEvent::WriteRx { id, req } => {
+                    let data = self.segment_for_link(id, req);
                     tracing::trace!("sending data of size {} over idle link {id}", data.len());
                     self.idle_links.retain(|&idle_id| idle_id != id);
                     self.send_reliable_over_link(id, ReliableMsg::Data(data));
+                    self.send_redundant_copy(id);
+                    self.last_data = Instant::now();
                 }
                 TaskEvent::SendConsumed => {
                     let id = self.idle_links.pop().unwrap();
@@ -902,15 +1147,24 @@ where
                     self.flushed_tx = Some(tx);
                 }
                 TaskEvent::ConfirmTimedOut(id) => {
+                    let _span = self.enter_link_span(id);
                     tracing::warn!("acknowledgement timeout on link {id}");
                     self.unconfirm_link(id, NotWorkingReason::AckTimeout);
                 }
+                TaskEvent::WriteQueued => (),
                 TaskEvent::Resend(packet) => {
                     let id = sendable_idle_link_id.unwrap();
+                    let _span = self.enter_link_span(id);
                     self.idle_links.retain(|&idle_id| idle_id != id);
                     tracing::trace!("resending message {} over idle link {id}", packet.seq);
                     self.resend_reliable_over_link(id, packet);
                 }
+                TaskEvent::Reissue { id, packet } => {
+                    let _span = self.enter_link_span(id);
+                    self.idle_links.retain(|&idle_id| idle_id != id);
+                    tracing::trace!("reissuing message {} over idle link {id}", packet.seq);
+                    self.reissue_reliable_over_link(id, packet);
+                }
                 TaskEvent::ReadDropped => {
                     tracing::debug!("receiver was dropped");
                     self.read_tx = None;
@@ -931,14 +1185,18 @@ where
                         self.receive_close_sent = true;
                     }
                 }
-                TaskEvent::ConsumeReceived { received, permit } => {
+                TaskEvent::ConsumeReceived { received, permit, space } => {
                     tracing::trace!("consuming received data message {:?}", &received.msg);
                     match received.msg {
                         ReliableMsg::Data(data) => {
                             self.rxed_reliable_size -= data.len();
                             self.rxed_reliable_consumed_since_last_ack += data.len();
+                            self.last_data = Instant::now();
                             if let Some(permit) = permit {
                                 permit.send(data);
+                                if let Some(space) = space {
+                                    space.forget();
+                                }
                             }
                         }
                         ReliableMsg::SendFinish => {
@@ -954,24 +1212,39 @@ where
                     }
                 }
                 TaskEvent::PingLink(id) => {
+                    let _span = self.enter_link_span(id);
                     tracing::trace!("requesting ping of link {id}");
                     let link = self.links[id].as_mut().unwrap();
                     link.send_ping = true;
                     self.flush_link(id);
                 }
                 TaskEvent::LinkPingTimeout(id) => {
-                    tracing::warn!("removing link {id} due to ping timeout");
-                    self.remove_link(id, DisconnectReason::PingTimeout);
+                    let _span = self.enter_link_span(id);
+                    let link = self.links[id].as_mut().unwrap();
+                    link.missed_pings = link.missed_pings.saturating_add(1);
+                    if link.missed_pings >= self.cfg.link_ping_max_missed.get() {
+                        tracing::warn!("removing link {id} due to ping timeout");
+                        self.remove_link(id, DisconnectReason::PingTimeout);
+                    } else {
+                        tracing::debug!("link {id} missed {} ping(s), pinging again", link.missed_pings);
+                        link.current_ping_sent = None;
+                        link.send_ping = true;
+                        link.publish_stats();
+                        self.flush_link(id);
+                    }
                 }
                 TaskEvent::LinkUnconfirmedTimeout(id) => {
+                    let _span = self.enter_link_span(id);
                     tracing::warn!("removing link {id} due to unconfirmed timeout");
                     self.remove_link(id, DisconnectReason::UnconfirmedTimeout);
                 }
                 TaskEvent::LinkSendTimeout(id) => {
+                    let _span = self.enter_link_span(id);
                     tracing::warn!("removing link {id} due to send timeout");
                     self.remove_link(id, DisconnectReason::SendTimeout);
                 }
                 TaskEvent::LinkTesting => (),
+                TaskEvent::RateLimitPassed => (),
                 TaskEvent::NoLinksTimeout => {
                     tracing::warn!("disconnecting because no links are available for too long");
                     result = Err(TaskError::NoLinksTimeout);
@@ -988,6 +1261,15 @@ where
                     }
                 }
                 TaskEvent::RefusedLinkTask => (),
+                TaskEvent::SendModeChanged => {
+                    let send_mode = SendMode::from_u8(self.send_mode.load(Ordering::SeqCst));
+                    tracing::debug!("send mode changed to {send_mode}");
+                }
+                TaskEvent::RetransmitPolicyChanged => {
+                    let retransmit_policy =
+                        RetransmitPolicy::from_u8(self.retransmit_policy.load(Ordering::SeqCst));
+                    tracing::debug!("retransmit policy changed to {retransmit_policy}");
+                }
                 TaskEvent::ServerChanged => {
                     tracing::warn!("disconnecting because server id changed");
                     result = Err(TaskError::ServerIdMismatch);
@@ -996,6 +1278,48 @@ where
                     link_term = DisconnectReason::ServerIdMismatch;
                     break;
                 }
+                TaskEvent::Shutdown(timeout) => {
+                    if self.shutdown_deadline.is_none() {
+                        tracing::info!("starting shutdown with timeout {} ms", timeout.as_millis());
+                        self.shutdown_deadline = Some(Instant::now() + timeout);
+
+                        // Send queued data, but accept no new data.
+                        if let Some(write_rx) = &mut self.write_rx {
+                            write_rx.close();
+                            self.write_error_tx.send_replace(SendError::Shutdown);
+                        }
+
+                        // Stop receiving data.
+                        if self.read_tx.is_some() {
+                            self.read_error_tx.send_replace(None);
+                            self.read_tx = None;
+                            self.read_closed_rx = None;
+                            if let Some(id) = self.idle_links.pop() {
+                                tracing::debug!("sending ReceiveFinish over idle link {id}");
+                                self.send_reliable_over_link(id, ReliableMsg::ReceiveFinish);
+                                self.receive_finish_sent = true;
+                            } else {
+                                tracing::debug!("queueing sending of ReceiveFinish");
+                            }
+                        }
+                    }
+                }
+                TaskEvent::ShutdownTimeout => {
+                    tracing::warn!("disconnecting because shutdown timed out");
+                    result = Err(TaskError::ShutdownIncomplete);
+                    read_term = None;
+                    write_term = SendError::Shutdown;
+                    link_term = DisconnectReason::ConnectionClosed;
+                    break;
+                }
+                TaskEvent::IdleTimeout => {
+                    tracing::info!("disconnecting because no data was sent or received for too long");
+                    result = Err(TaskError::IdleTimeout);
+                    read_term = Some(RecvError::IdleTimeout);
+                    write_term = SendError::IdleTimeout;
+                    link_term = DisconnectReason::ConnectionClosed;
+                    break;
+                }
             }
 
             // Check for link ping exceeding configured limit.
@@ -1033,6 +1357,8 @@ where
 
         // Publish termination reasons.
         let _ = self.result_tx.send_replace(result.clone());
+        self.state_tx.send_replace(ConnState::Terminated);
+        self.link_count_tx.send_replace(0);
         if *self.read_error_tx.borrow() == Some(RecvError::TaskTerminated) {
             self.read_error_tx.send_replace(read_term);
         }
@@ -1041,6 +1367,7 @@ where
         }
         for link in &mut self.links {
             if let Some(link) = link.take() {
+                let _ = self.link_event_tx.send(link.link_event(LinkEventKind::Disconnected(link_term.clone())));
                 link.notify_disconnected(link_term.clone());
             }
         }
@@ -1056,6 +1383,7 @@ where
     fn add_link(&mut self, mut link: LinkInt<TX, RX, TAG>) -> usize {
         link.report_ready();
         link.unconfirmed = Some((Instant::now(), NotWorkingReason::New));
+        let _ = self.link_event_tx.send(link.link_event(LinkEventKind::Connected));
 
         for (id, link_opt) in self.links.iter_mut().enumerate() {
             if link_opt.is_none() {
@@ -1071,8 +1399,18 @@ where
         self.links.len() - 1
     }
 
+    /// Enters the tracing span of the link with the specified index.
+    fn enter_link_span(&self, id: usize) -> tracing::span::EnteredSpan {
+        let span = match &self.links[id] {
+            Some(link) => link.span.clone(),
+            None => tracing::Span::none(),
+        };
+        span.entered()
+    }
+
     /// Removes the link with the specified index.
     fn remove_link(&mut self, id: usize, reason: DisconnectReason) {
+        let _span = self.enter_link_span(id);
         tracing::debug!("removing link {id} for reason {reason:?}");
 
         // Queue unconfirmed packets for resending.
@@ -1080,6 +1418,7 @@ where
 
         // Send disconnect reason.
         let link = self.links[id].take().unwrap();
+        let _ = self.link_event_tx.send(link.link_event(LinkEventKind::Disconnected(reason.clone())));
         link.notify_disconnected(reason);
 
         // Cleanup and publish links.
@@ -1095,6 +1434,59 @@ where
         self.links_tx.send_replace(links);
     }
 
+    /// Publishes the connection state, if it has changed.
+    fn publish_state(&self, no_link_since: Option<Instant>) {
+        let state = match (self.established, no_link_since) {
+            (None, _) => ConnState::Connecting,
+            (Some(_), Some(since)) => ConnState::Stalled { since },
+            (Some(_), None) => ConnState::Active,
+        };
+
+        self.state_tx.send_if_modified(|current| {
+            if *current == state {
+                return false;
+            }
+
+            match (&*current, &state) {
+                (ConnState::Active, ConnState::Stalled { .. }) => {
+                    tracing::info!("connection stalled because no links are working")
+                }
+                (ConnState::Stalled { since }, ConnState::Active) => {
+                    tracing::info!("connection resumed after being stalled for {:?}", since.elapsed())
+                }
+                _ => (),
+            }
+
+            *current = state;
+            true
+        });
+    }
+
+    /// Publishes the number of working links, if it has changed.
+    ///
+    /// A link is working if it is confirmed and not blocked, i.e. usable for sending data.
+    fn publish_link_count(&self) {
+        let count = self
+            .links
+            .iter()
+            .flatten()
+            .filter(|link| link.unconfirmed.is_none() && link.disconnecting.is_none() && !link.is_blocked())
+            .count();
+
+        self.link_count_tx.send_if_modified(|current| {
+            if *current == count {
+                return false;
+            }
+
+            if *current > 1 && count == 1 {
+                tracing::info!("connection has lost redundancy because only one link is working");
+            }
+
+            *current = count;
+            true
+        });
+    }
+
     /// Returns since when no link is working.
     fn links_not_working_since(&mut self) -> Option<Instant> {
         let links_working = self
@@ -1120,7 +1512,19 @@ where
     fn tx_space(&self) -> usize {
         let tx_local_space = (self.cfg.send_buffer.get() as usize).saturating_sub(self.txed_unacked);
         let tx_remote_space = self.remote_recv_buffer().unwrap_or_default().saturating_sub(self.txed_unconsumed);
-        tx_local_space.min(tx_remote_space)
+        match (self.cfg.reorder_policy, self.reorder_buffer_limit()) {
+            (ReorderPolicy::Backpressure, Some(limit)) if self.txed_unconsumable >= limit => 0,
+            _ => tx_local_space.min(tx_remote_space),
+        }
+    }
+
+    /// Limit for data received by remote endpoint that cannot yet be consumed.
+    fn reorder_buffer_limit(&self) -> Option<usize> {
+        let limit = (self.cfg.send_buffer.get() as usize).min(self.remote_recv_buffer()?);
+        match self.cfg.reorder_buffer {
+            Some(reorder_buffer) => Some(limit.min(reorder_buffer.get() as usize)),
+            None => Some(limit),
+        }
     }
 
     /// Returns whether a sequence number is available for sending.
@@ -1130,14 +1534,13 @@ where
 
     /// Adjusts the link transmission buffer limits to ensure that no link stalls the channel.
     fn adjust_link_tx_limits(&mut self) {
-        let Some(remote_recv_buffer) = self.remote_recv_buffer() else { return };
+        let Some(unconsumable_limit) = self.reorder_buffer_limit() else { return };
         let coming_seq = match self.resend_queue.front() {
             Some(packet) => packet.seq,
             None => self.tx_seq,
         };
 
         // Check for unconsumable data approaching its limits.
-        let unconsumable_limit = (self.cfg.send_buffer.get() as usize).min(remote_recv_buffer);
         let low_level = self.txed_unconsumable < unconsumable_limit / 4;
         let soft_overrun = self.txed_unconsumable > unconsumable_limit / 3;
         let hard_overrun = self.txed_unconsumable > unconsumable_limit * 3 / 4;
@@ -1355,19 +1758,29 @@ where
                 Some(link)
                     if link.current_ping_sent.is_none() && !link.send_ping && link.unconfirmed.is_none() =>
                 {
-                    match self.cfg.link_ping {
+                    let ping = match self.cfg.link_ping {
                         LinkPing::Periodic(interval) => {
-                            Some((id, link.last_ping.map(|last| last + interval).unwrap_or_else(Instant::now)))
+                            Some(link.last_ping.map(|last| last + interval).unwrap_or_else(Instant::now))
                         }
                         LinkPing::WhenIdle(timeout) => {
                             let msg_timeout =
                                 link.tx_last_msg.map(|last| last + timeout).unwrap_or_else(Instant::now);
                             let ping_timeout =
                                 link.last_ping.map(|last| last + timeout).unwrap_or_else(Instant::now);
-                            Some((id, msg_timeout.max(ping_timeout)))
+                            Some(msg_timeout.max(ping_timeout))
                         }
                         LinkPing::WhenTimedOut => None,
-                    }
+                    };
+
+                    // Probe link when no message has been sent or received.
+                    let probe = self.cfg.link_idle_probe.map(|idle| {
+                        link.tx_last_msg
+                            .max(link.rx_last_msg)
+                            .map(|last| last + idle)
+                            .unwrap_or_else(Instant::now)
+                    });
+
+                    ping.into_iter().chain(probe).min().map(|next_ping| (id, next_ping))
                 }
                 _ => None,
             })
@@ -1376,6 +1789,7 @@ where
 
     /// Sends a sequenced reliable message over the specified link.
     fn send_reliable_over_link(&mut self, id: usize, reliable_msg: ReliableMsg) -> Seq {
+        let _span = self.enter_link_span(id);
         let seq = self.next_tx_seq();
         let link = self.links[id].as_mut().unwrap();
 
@@ -1399,6 +1813,7 @@ where
                 link_id: id,
                 msg: reliable_msg,
                 resent: false,
+                reissued: None,
             }),
         };
         self.txed_packets.push_back(Arc::new(packet));
@@ -1406,8 +1821,132 @@ where
         seq
     }
 
+    /// Selects the link for sending a segment of `len` bytes using the link selector.
+    ///
+    /// Sendable idle links and the link `ready_id` are considered ready.
+    /// Returns the id of the selected link, if it is ready.
+    fn select_link(&mut self, ready_id: Option<usize>, len: usize) -> Option<usize> {
+        let is_working = |link: &LinkInt<TX, RX, TAG>| {
+            link.unconfirmed.is_none() && link.disconnecting.is_none() && !link.is_blocked()
+        };
+
+        let ready: Vec<_> = self
+            .idle_links
+            .iter()
+            .cloned()
+            .filter(|&id| Some(id) != ready_id)
+            .chain(ready_id)
+            .filter(|&id| {
+                let link = self.links[id].as_ref().unwrap();
+                is_working(link) && link.is_sendable()
+            })
+            .collect();
+        let mut ids: Vec<_> = self
+            .links
+            .iter()
+            .enumerate()
+            .filter_map(|(id, link)| {
+                link.as_ref().filter(|link| is_working(link) && !ready.contains(&id)).map(|_| id)
+            })
+            .collect();
+        let busy = ids.len();
+        ids.extend(ready);
+
+        let candidates: Vec<_> = ids
+            .iter()
+            .enumerate()
+            .map(|(n, &id)| {
+                let link = self.links[id].as_ref().unwrap();
+                LinkCandidate {
+                    id: link.link_id(),
+                    weight: link.weight(),
+                    roundtrip: link.roundtrip,
+                    ready: n >= busy,
+                    sendable: link.is_sendable(),
+                }
+            })
+            .collect();
+
+        let n = match SendMode::from_u8(self.send_mode.load(Ordering::SeqCst)) {
+            SendMode::Aggregate | SendMode::Redundant => self.link_selector.select(&candidates, len)?,
+            SendMode::SinglePathLowLatency => self.low_latency_selector.select(&candidates, len)?,
+        };
+        candidates.get(n).filter(|candidate| candidate.ready).map(|_| ids[n])
+    }
+
+    /// Whether the link selector selects the specified link, which has become ready,
+    /// for sending the queued data.
+    fn is_selected_when_ready(&mut self, id: usize, tx_seq_avail: bool, tx_space: usize) -> bool {
+        let link = self.links[id].as_ref().unwrap();
+        if !tx_seq_avail || link.unconfirmed.is_some() || link.is_blocked() || !link.is_sendable() {
+            return false;
+        }
+
+        let Some(Ok(SendReq::Send(data) | SendReq::Write(data))) =
+            self.write_rx.as_mut().map(|write_rx| write_rx.try_peek())
+        else {
+            return false;
+        };
+        let len = data.len();
+        len <= tx_space && self.select_link(Some(id), len) == Some(id)
+    }
+
+    /// Returns the data of a send request to be sent over the specified link.
+    ///
+    /// Data written using stream-based IO that exceeds the preferred segment size of the link
+    /// is split and the remainder is returned to the front of the send queue.
+    fn segment_for_link(&mut self, id: usize, req: SendReq) -> Bytes {
+        match req {
+            SendReq::Send(data) => {
+                if let Some(write_limit) = &self.write_limit {
+                    write_limit.release(data.len(), 0);
+                }
+                data
+            }
+            SendReq::Write(mut data) => {
+                let len = data.len();
+                let segment_size = self.links[id].as_ref().unwrap().segment_size();
+                let rest = match segment_size.filter(|size| data.len() > size.get()) {
+                    Some(segment_size) => data.split_off(segment_size.get()),
+                    None => Bytes::new(),
+                };
+                if let Some(write_limit) = &self.write_limit {
+                    write_limit.release(len, rest.len());
+                }
+                if !rest.is_empty() {
+                    self.write_rx.as_mut().unwrap().unrecv(SendReq::Write(rest));
+                }
+                data
+            }
+            SendReq::Flush(_) => unreachable!("flush request cannot be sent over a link"),
+        }
+    }
+
+    /// Starts disconnection of the specified link if it is being drained and all
+    /// reliable messages sent over it have been acknowledged.
+    fn disconnect_if_drained(&mut self, id: usize) {
+        let link = self.links[id].as_ref().unwrap();
+        if !link.is_draining() || link.disconnecting.is_some() {
+            return;
+        }
+
+        let in_flight = self
+            .txed_packets
+            .iter()
+            .any(|p| matches!(&*p.status.borrow(), SentReliableStatus::Sent { link_id, .. } if *link_id == id));
+        if in_flight {
+            return;
+        }
+
+        tracing::info!("link {id} has been drained, starting disconnection");
+        let link = self.links[id].as_mut().unwrap();
+        link.disconnecting = Some(DisconnectInitiator::Local);
+        link.start_flush();
+    }
+
     /// Resends a packet over the specified link.
     fn resend_reliable_over_link(&mut self, id: usize, packet: Arc<SentReliable>) {
+        let _span = self.enter_link_span(id);
         let link = self.links[id].as_mut().unwrap();
 
         // Extract message and link used for sending.
@@ -1420,6 +1959,7 @@ where
         tracing::trace!("resending reliable message {} over link {id}: {:?}", packet.seq, reliable_msg);
         let (msg, data) = reliable_msg.to_link_msg(packet.seq);
         link.start_send_msg(msg, data);
+        link.record_resent();
 
         // Update link statistics.
         if let ReliableMsg::Data(data) = reliable_msg {
@@ -1440,13 +1980,102 @@ where
             link_id: id,
             msg: reliable_msg.clone(),
             resent: true,
+            reissued: None,
         };
     }
 
+    /// Packet with overdue acknowledgement that should be reissued next.
+    ///
+    /// Returns the time when it becomes overdue, the index of the idle link to send a copy over
+    /// and the packet.
+    fn next_reissue(&self) -> Option<(Instant, usize, Arc<SentReliable>)> {
+        if RetransmitPolicy::from_u8(self.retransmit_policy.load(Ordering::SeqCst)) != RetransmitPolicy::Fastest {
+            return None;
+        }
+
+        let idle = self.idle_working_links();
+        if idle.is_empty() {
+            return None;
+        }
+
+        let mut next: Option<(Instant, usize, Arc<SentReliable>)> = None;
+        for p in &self.txed_packets {
+            if let SentReliableStatus::Sent { sent, link_id, resent: false, reissued: None, .. } =
+                &*p.status.borrow()
+            {
+                let Some(&id) = idle.iter().find(|&&id| id != *link_id) else { continue };
+                let link = self.links[*link_id].as_ref().unwrap();
+                let overdue = *sent + link.roundtrip * 2;
+                if next.as_ref().map(|(t, _, _)| overdue < *t).unwrap_or(true) {
+                    next = Some((overdue, id, p.clone()));
+                }
+            }
+        }
+
+        next
+    }
+
+    /// Idle working links that can send data, ordered by round trip time.
+    fn idle_working_links(&self) -> Vec<usize> {
+        let mut idle: Vec<_> = self
+            .idle_links
+            .iter()
+            .cloned()
+            .filter(|&id| {
+                let link = self.links[id].as_ref().unwrap();
+                link.unconfirmed.is_none()
+                    && link.disconnecting.is_none()
+                    && !link.is_blocked()
+                    && link.is_sendable()
+            })
+            .collect();
+        idle.sort_by_key(|&id| self.links[id].as_ref().unwrap().roundtrip);
+        idle
+    }
+
+    /// Sends a copy of the data packet just sent over the specified link over the fastest
+    /// other idle link, if the connection is in [redundant mode](SendMode::Redundant).
+    fn send_redundant_copy(&mut self, id: usize) {
+        if SendMode::from_u8(self.send_mode.load(Ordering::SeqCst)) != SendMode::Redundant {
+            return;
+        }
+        let Some(copy_id) = self.idle_working_links().into_iter().find(|&idle_id| idle_id != id) else {
+            return;
+        };
+
+        let _span = self.enter_link_span(copy_id);
+        let packet = self.txed_packets.back().unwrap().clone();
+        tracing::trace!("sending copy of message {} over idle link {copy_id}", packet.seq);
+        self.idle_links.retain(|&idle_id| idle_id != copy_id);
+        self.reissue_reliable_over_link(copy_id, packet);
+    }
+
+    /// Sends a copy of a packet with overdue acknowledgement over the specified link.
+    fn reissue_reliable_over_link(&mut self, id: usize, packet: Arc<SentReliable>) {
+        let link = self.links[id].as_mut().unwrap();
+
+        let mut status = packet.status.borrow_mut();
+        let SentReliableStatus::Sent { msg: reliable_msg, reissued, .. } = &mut *status else {
+            unreachable!("message is not awaiting acknowledgement")
+        };
+
+        tracing::trace!("reissuing reliable message {} over link {id}: {:?}", packet.seq, reliable_msg);
+        let (msg, data) = reliable_msg.to_link_msg(packet.seq);
+        link.start_send_msg(msg, data);
+        link.record_resent();
+
+        *reissued = Some(id);
+        self.reissued += 1;
+    }
+
     /// Unconfirms a link.
     fn unconfirm_link(&mut self, id: usize, reason: NotWorkingReason) {
+        let _span = self.enter_link_span(id);
         // Mark link as unconfirmed.
         let link = self.links[id].as_mut().unwrap();
+        if link.unconfirmed.is_none() && reason != NotWorkingReason::Disconnecting {
+            let _ = self.link_event_tx.send(link.link_event(LinkEventKind::Degraded(reason.clone())));
+        }
         link.unconfirmed = Some((Instant::now(), reason));
         self.idle_links.retain(|&idle_id| idle_id != id);
         self.unflushed_links.remove(&id);
@@ -1463,10 +2092,11 @@ where
             match &*status {
                 SentReliableStatus::Sent { link_id, msg, .. } if *link_id == id => {
                     // Update link statistics.
+                    let old_link = self.links[*link_id].as_mut().unwrap();
                     if let ReliableMsg::Data(data) = &msg {
-                        let old_link = self.links[*link_id].as_mut().unwrap();
                         old_link.txed_unacked_data -= data.len();
                     }
+                    old_link.record_lost();
 
                     *status = SentReliableStatus::ResendQueued { msg: msg.clone() };
                     self.resend_queue.push_back(p.clone());
@@ -1475,6 +2105,15 @@ where
             };
         }
 
+        // Packets whose copy has been sent over the link may be reissued again.
+        for p in &self.txed_packets {
+            if let SentReliableStatus::Sent { reissued, .. } = &mut *p.status.borrow_mut() {
+                if *reissued == Some(id) {
+                    *reissued = None;
+                }
+            }
+        }
+
         // Sort resend queue, so that oldest packets are resend first.
         self.resend_queue.make_contiguous().sort_by_key(|packet| packet.seq);
 
@@ -1484,6 +2123,9 @@ where
                 link.test = LinkTest::Inactive;
             }
         }
+
+        // Unacknowledged messages will be resent over other links.
+        self.disconnect_if_drained(id);
     }
 
     /// Considers activating a link that has been disabled due to confirmation timeout.
@@ -1552,6 +2194,7 @@ where
                             );
                             link.unconfirmed = None;
                             link.test = LinkTest::Inactive;
+                            let _ = self.link_event_tx.send(link.link_event(LinkEventKind::Working));
 
                             self.idle_links.retain(|&idle_id| idle_id != id);
                             link.report_ready();
@@ -1568,7 +2211,12 @@ where
                             link.test = LinkTest::Failed(when);
                             match &mut link.unconfirmed {
                                 Some((_since, reason)) => *reason = NotWorkingReason::TestFailed,
-                                None => link.unconfirmed = Some((Instant::now(), NotWorkingReason::TestFailed)),
+                                None => {
+                                    let _ = self.link_event_tx.send(
+                                        link.link_event(LinkEventKind::Degraded(NotWorkingReason::TestFailed)),
+                                    );
+                                    link.unconfirmed = Some((Instant::now(), NotWorkingReason::TestFailed));
+                                }
                             }
                             Some(when + self.cfg.link_retest_interval)
                         }
@@ -1600,6 +2248,7 @@ where
     /// Handle a received message.
     fn handle_received_msg(&mut self, id: usize, msg: LinkMsg, data: Option<Bytes>) -> Result<(), io::Error> {
         let link = self.links[id].as_mut().unwrap();
+        link.rx_last_msg = Some(Instant::now());
 
         match msg {
             LinkMsg::Ping => {
@@ -1614,6 +2263,11 @@ where
                     tracing::trace!("ping round-trip time is {} ms", elapsed.as_millis());
                     link.roundtrip = elapsed;
                     link.last_ping = Some(Instant::now());
+                    if link.missed_pings > 0 {
+                        tracing::debug!("link {id} answered ping again after {} missed", link.missed_pings);
+                        link.missed_pings = 0;
+                        link.publish_stats();
+                    }
                     self.link_testing_step(id);
                 }
             }
@@ -1629,6 +2283,7 @@ where
             LinkMsg::Ack { received } => {
                 tracing::trace!("link {id} acked reception up to {received}");
                 self.handle_ack(id, received);
+                self.disconnect_if_drained(id);
             }
             LinkMsg::TestData { size } => {
                 tracing::trace!("link {id} received {size} bytes of test data");
@@ -1639,6 +2294,7 @@ where
                 self.idle_links.retain(|&idle_id| idle_id != id);
                 link.report_ready();
                 link.blocked_changed_out_tx.send_replace(());
+                link.publish_stats();
             }
             LinkMsg::Goodbye => {
                 match link.disconnecting {
@@ -1657,6 +2313,7 @@ where
                         // Remote endpoint is initiating disconnection.
                         tracing::debug!("remote requests disconnection of link {id}");
                         link.disconnecting = Some(DisconnectInitiator::Remote);
+                        self.flush_link(id);
                     }
                 }
             }
@@ -1693,6 +2350,7 @@ where
                 match &msg {
                     ReliableMsg::Data(data) => {
                         self.rxed_reliable_size += data.len();
+                        self.rxed_reliable_unconsumable_size += data.len();
                         if self.rxed_reliable_size > self.cfg.recv_buffer.get() as usize {
                             return Err(protocol_err!("receive buffer overflow"));
                         }
@@ -1715,6 +2373,7 @@ where
                     ReliableMsg::ReceiveFinish => {
                         self.write_error_tx.send_replace(SendError::Dropped);
                         self.write_rx = None;
+                        self.write_limit = None;
                         self.send_finish_sent = true;
                         self.rxed_reliable_consumed_force_ack = true;
                     }
@@ -1735,6 +2394,10 @@ where
             assert_eq!(msg.seq, self.rx_seq);
             self.rx_seq += 1;
 
+            if let ReliableMsg::Data(data) = &msg.msg {
+                self.rxed_reliable_unconsumable_size -= data.len();
+            }
+
             if matches!(&msg.msg, ReliableMsg::Data(_) | ReliableMsg::SendFinish) {
                 self.rxed_reliable_consumable.push_back(msg);
             }
@@ -1773,14 +2436,25 @@ where
 
             let mut status = packet.status.borrow_mut();
             match &*status {
-                SentReliableStatus::Sent { sent, link_id, msg, .. } if *link_id == id => {
+                SentReliableStatus::Sent { sent, link_id, msg, reissued, .. }
+                    if *link_id == id || *reissued == Some(id) =>
+                {
                     let size = if let ReliableMsg::Data(data) = &msg { data.len() } else { 0 };
 
+                    let link = self.links[*link_id].as_mut().unwrap();
                     link.txed_unacked_data -= size;
                     self.txed_unacked -= size;
                     self.txed_unconsumable += size;
 
-                    link.roundtrip = (99 * link.roundtrip + sent.elapsed()) / 100;
+                    // A copy received over the reissuing link says nothing about the round trip
+                    // time of the link the packet has originally been sent over.
+                    if *link_id != id {
+                        self.reissued_won += 1;
+                    } else {
+                        let roundtrip = sent.elapsed();
+                        link.roundtrip = (99 * link.roundtrip + roundtrip) / 100;
+                        link.record_acked(roundtrip);
+                    }
 
                     *status = SentReliableStatus::Received { size };
                 }
@@ -1828,8 +2502,23 @@ where
                 sent_unconsumed_count: self.txed_packets.len(),
                 sent_unconsumable: self.txed_unconsumable,
                 resend_queue_len: self.resend_queue.len(),
+                reissued: self.reissued,
+                reissued_won: self.reissued_won,
                 recved_unconsumed: self.rxed_reliable_size,
                 recved_unconsumed_count: self.rxed_reliable.len(),
+                recved_unconsumable: self.rxed_reliable_unconsumable_size,
+            });
+        }
+    }
+
+    /// Sends statistics of all links.
+    fn send_conn_stats(&mut self) {
+        if self.conn_stats_last_sent.elapsed() >= self.cfg.conn_stats_interval {
+            self.conn_stats_last_sent = Instant::now();
+
+            self.conn_stats_tx.send_replace(ConnStats {
+                time: self.conn_stats_last_sent,
+                links: self.links.iter().flatten().map(|link| link.conn_link_stats()).collect(),
             });
         }
     }
@@ -1862,6 +2551,17 @@ where
         self.link_filter = Box::new(move |link, others| link_filter(link, others).boxed());
     }
 
+    /// Sets the link selector deciding over which link data is sent.
+    ///
+    /// By default the [`WeightedLinkSelector`] is used.
+    /// While the connection is in [single path mode](SendMode::SinglePathLowLatency)
+    /// the [`LowLatencyLinkSelector`] is used instead.
+    /// Messages that are resent after a link failure may be sent over any ready link
+    /// without consulting the link selector.
+    pub fn set_link_selector(&mut self, link_selector: impl LinkSelector) {
+        self.link_selector = Box::new(link_selector);
+    }
+
     /// Enables dumping of analysis data over the provided channel while the aggregator task is running.
     ///
     /// The purpose of the dumped data is to debug connection performance issues
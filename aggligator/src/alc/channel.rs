@@ -17,6 +17,7 @@ use crate::{
     agg::task::SendReq,
     cfg::{Cfg, ExchangedCfg},
     id::ConnId,
+    queue_limit::{QueueLimit, QueueSpace},
 };
 
 /// A bi-directional channel backed by a connection of aggregated links.
@@ -31,8 +32,10 @@ pub struct Channel {
     remote_cfg: Option<Arc<ExchangedCfg>>,
     conn_id: ConnId,
     tx: mpsc::Sender<SendReq>,
+    tx_space: Option<QueueSpace>,
     tx_error: watch::Receiver<SendError>,
     rx: mpsc::Receiver<Bytes>,
+    rx_limit: Option<QueueLimit>,
     rx_closed: mpsc::Sender<()>,
     rx_error: watch::Receiver<Option<RecvError>>,
 }
@@ -41,10 +44,10 @@ impl Channel {
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         cfg: Arc<Cfg>, remote_cfg: Option<Arc<ExchangedCfg>>, conn_id: ConnId, tx: mpsc::Sender<SendReq>,
-        tx_error: watch::Receiver<SendError>, rx: mpsc::Receiver<Bytes>, rx_closed: mpsc::Sender<()>,
-        rx_error: watch::Receiver<Option<RecvError>>,
+        tx_space: Option<QueueSpace>, tx_error: watch::Receiver<SendError>, rx: mpsc::Receiver<Bytes>,
+        rx_limit: Option<QueueLimit>, rx_closed: mpsc::Sender<()>, rx_error: watch::Receiver<Option<RecvError>>,
     ) -> Self {
-        Self { cfg, remote_cfg, conn_id, tx, tx_error, rx, rx_closed, rx_error }
+        Self { cfg, remote_cfg, conn_id, tx, tx_space, tx_error, rx, rx_limit, rx_closed, rx_error }
     }
 
     /// Connection id.
@@ -62,10 +65,10 @@ impl Channel {
     ///
     /// Note that the local sender is connected to the receiver *of the remote endpoint* and vice versa.
     pub fn into_tx_rx(self) -> (Sender, Receiver) {
-        let Self { cfg, remote_cfg, conn_id, tx, tx_error, rx, rx_closed, rx_error } = self;
+        let Self { cfg, remote_cfg, conn_id, tx, tx_space, tx_error, rx, rx_limit, rx_closed, rx_error } = self;
 
-        let tx = Sender::new(cfg, remote_cfg.unwrap(), conn_id, tx, tx_error);
-        let rx = Receiver::new(conn_id, rx, rx_closed, rx_error);
+        let tx = Sender::new(cfg, remote_cfg.unwrap(), conn_id, tx, tx_space, tx_error);
+        let rx = Receiver::new(conn_id, rx, rx_limit, rx_closed, rx_error);
 
         (tx, rx)
     }
@@ -102,6 +105,22 @@ impl Stream {
     pub fn close(&mut self) {
         self.rx.close()
     }
+
+    /// Attempts to write data from a reference-counted buffer without copying it.
+    ///
+    /// See [`SenderSink::poll_write_bytes`] for details.
+    pub fn poll_write_bytes(
+        self: Pin<&mut Self>, cx: &mut Context, buf: &mut Bytes,
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.get_mut().tx).poll_write_bytes(cx, buf)
+    }
+
+    /// Writes all data from a reference-counted buffer without copying it.
+    ///
+    /// See [`SenderSink::poll_write_bytes`] for details.
+    pub async fn write_bytes(&mut self, buf: Bytes) -> Result<(), io::Error> {
+        self.tx.write_bytes(buf).await
+    }
 }
 
 impl AsyncRead for Stream {
@@ -12,10 +12,11 @@ use tokio::{
     sync::{mpsc, watch},
 };
 
-use crate::id::ConnId;
+use crate::{id::ConnId, queue_limit::QueueLimit};
 
 /// Error receiving from an aggregated link channel.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum RecvError {
     /// All links have failed.
     AllLinksFailed,
@@ -25,6 +26,8 @@ pub enum RecvError {
     ServerIdMismatch,
     /// The connection task was terminated.
     TaskTerminated,
+    /// No data was sent or received within the [idle timeout](crate::cfg::Cfg::idle_timeout).
+    IdleTimeout,
 }
 
 impl fmt::Display for RecvError {
@@ -34,6 +37,7 @@ impl fmt::Display for RecvError {
             Self::ProtocolError => write!(f, "protocol error"),
             Self::ServerIdMismatch => write!(f, "a new link connected to another server"),
             Self::TaskTerminated => write!(f, "task terminated"),
+            Self::IdleTimeout => write!(f, "idle timeout"),
         }
     }
 }
@@ -50,6 +54,7 @@ impl From<RecvError> for io::Error {
 pub struct Receiver {
     conn_id: ConnId,
     rx: mpsc::Receiver<Bytes>,
+    limit: Option<QueueLimit>,
     closed_tx: mpsc::Sender<()>,
     error_rx: watch::Receiver<Option<RecvError>>,
 }
@@ -62,10 +67,17 @@ impl fmt::Debug for Receiver {
 
 impl Receiver {
     pub(crate) fn new(
-        conn_id: ConnId, rx: mpsc::Receiver<Bytes>, closed_tx: mpsc::Sender<()>,
+        conn_id: ConnId, rx: mpsc::Receiver<Bytes>, limit: Option<QueueLimit>, closed_tx: mpsc::Sender<()>,
         error_rx: watch::Receiver<Option<RecvError>>,
     ) -> Self {
-        Self { conn_id, rx, closed_tx, error_rx }
+        Self { conn_id, rx, limit, closed_tx, error_rx }
+    }
+
+    /// Releases the queue space occupied by received data.
+    fn received(&self, data: &Bytes) {
+        if let Some(limit) = &self.limit {
+            limit.release(data.len(), 0);
+        }
     }
 
     /// Connection id.
@@ -77,7 +89,10 @@ impl Receiver {
     #[inline]
     pub async fn recv(&mut self) -> Result<Option<Bytes>, RecvError> {
         match self.rx.recv().await {
-            Some(data) => Ok(Some(data)),
+            Some(data) => {
+                self.received(&data);
+                Ok(Some(data))
+            }
             None => match self.error_rx.borrow().clone() {
                 None => Ok(None),
                 Some(err) => Err(err),
@@ -89,7 +104,10 @@ impl Receiver {
     #[inline]
     pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<Result<Option<Bytes>, RecvError>> {
         match ready!(self.rx.poll_recv(cx)) {
-            Some(data) => Poll::Ready(Ok(Some(data))),
+            Some(data) => {
+                self.received(&data);
+                Poll::Ready(Ok(Some(data)))
+            }
             None => match self.error_rx.borrow().clone() {
                 None => Poll::Ready(Ok(None)),
                 Some(err) => Poll::Ready(Err(err)),
@@ -1,16 +1,18 @@
 //! Sender front-end of aggregated stream.
 
-use bytes::Bytes;
-use futures::{ready, FutureExt, Sink, SinkExt};
+use bytes::{Buf, Bytes};
+use futures::{future::poll_fn, ready, FutureExt, Sink, SinkExt};
 use std::{
-    fmt, io,
+    fmt,
+    future::Future,
+    io,
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
 };
 use tokio::{
     io::AsyncWrite,
-    sync::{mpsc, oneshot, watch},
+    sync::{mpsc, oneshot, watch, OwnedSemaphorePermit},
 };
 use tokio_util::sync;
 
@@ -18,10 +20,12 @@ use crate::{
     agg::task::SendReq,
     cfg::{Cfg, ExchangedCfg},
     id::ConnId,
+    queue_limit::QueueSpace,
 };
 
 /// Error sending to an aggregated link channel.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum SendError {
     /// The remote endpoint closed the connection.
     Closed,
@@ -39,6 +43,8 @@ pub enum SendError {
     ServerIdMismatch,
     /// The connection task was terminated.
     TaskTerminated,
+    /// No data was sent or received within the [idle timeout](crate::cfg::Cfg::idle_timeout).
+    IdleTimeout,
 }
 
 impl fmt::Display for SendError {
@@ -52,6 +58,7 @@ impl fmt::Display for SendError {
             Self::ProtocolError => write!(f, "protocol error"),
             Self::ServerIdMismatch => write!(f, "a new link connected to another server"),
             Self::TaskTerminated => write!(f, "task terminated"),
+            Self::IdleTimeout => write!(f, "idle timeout"),
         }
     }
 }
@@ -64,6 +71,7 @@ impl From<SendError> for io::Error {
             SendError::Closed | SendError::Dropped => io::ErrorKind::ConnectionReset,
             SendError::Shutdown => io::ErrorKind::BrokenPipe,
             SendError::DataTooBig => io::ErrorKind::InvalidData,
+            SendError::IdleTimeout => io::ErrorKind::TimedOut,
             SendError::AllLinksFailed
             | SendError::TaskTerminated
             | SendError::ProtocolError
@@ -83,6 +91,7 @@ pub struct Sender {
     remote_cfg: Arc<ExchangedCfg>,
     conn_id: ConnId,
     tx: mpsc::Sender<SendReq>,
+    space: Option<QueueSpace>,
     error_rx: watch::Receiver<SendError>,
 }
 
@@ -95,9 +104,9 @@ impl fmt::Debug for Sender {
 impl Sender {
     pub(crate) fn new(
         cfg: Arc<Cfg>, remote_cfg: Arc<ExchangedCfg>, conn_id: ConnId, tx: mpsc::Sender<SendReq>,
-        error_rx: watch::Receiver<SendError>,
+        space: Option<QueueSpace>, error_rx: watch::Receiver<SendError>,
     ) -> Self {
-        Self { cfg, remote_cfg, conn_id, tx, error_rx }
+        Self { cfg, remote_cfg, conn_id, tx, space, error_rx }
     }
 
     /// Connection id.
@@ -106,13 +115,26 @@ impl Sender {
     }
 
     /// Enqueues data for sending.
+    ///
+    /// Waits for space in the send queue, if its [size is limited](Cfg::send_queue_size).
     #[inline]
     pub async fn send(&self, data: Bytes) -> Result<(), SendError> {
         if data.len() > self.max_size() {
             return Err(SendError::DataTooBig);
         }
 
-        self.tx.send(SendReq::Send(data)).await.map_err(|_| self.error_rx.borrow().clone())
+        let permit = match &self.space {
+            Some(space) => Some(space.acquire(data.len()).await.ok_or_else(|| self.error_rx.borrow().clone())?),
+            None => None,
+        };
+
+        self.tx.send(SendReq::Send(data)).await.map_err(|_| self.error_rx.borrow().clone())?;
+
+        if let Some(permit) = permit {
+            permit.forget();
+        }
+
+        Ok(())
     }
 
     /// Flushes data queued for sending.
@@ -131,12 +153,15 @@ impl Sender {
 
     /// Converts this sender into a [SenderSink], that implements the [Sink] and [AsyncWrite] traits.
     pub fn into_sink(self) -> SenderSink {
-        let Self { cfg, remote_cfg, conn_id, tx, error_rx } = self;
+        let Self { cfg, remote_cfg, conn_id, tx, space, error_rx } = self;
         SenderSink {
             cfg,
             remote_cfg,
             conn_id,
             tx: sync::PollSender::new(tx),
+            space,
+            space_owed: 0,
+            space_acquire: None,
             flushed_rx: None,
             error_rx,
             closed: false,
@@ -152,6 +177,10 @@ pub struct SenderSink {
     remote_cfg: Arc<ExchangedCfg>,
     conn_id: ConnId,
     tx: sync::PollSender<SendReq>,
+    space: Option<QueueSpace>,
+    /// Size of enqueued data that has not yet acquired space in the send queue.
+    space_owed: usize,
+    space_acquire: Option<Pin<Box<dyn Future<Output = Option<OwnedSemaphorePermit>> + Send + Sync>>>,
     flushed_rx: Option<oneshot::Receiver<()>>,
     error_rx: watch::Receiver<SendError>,
     closed: bool,
@@ -179,6 +208,79 @@ impl SenderSink {
     pub fn max_size(&self) -> usize {
         max_send_size(&self.remote_cfg)
     }
+
+    /// Maximum size of a packet written using [`AsyncWrite`].
+    fn max_write_size(&self) -> usize {
+        self.cfg.io_write_size.get().min(self.remote_cfg.recv_buffer.get() as usize)
+    }
+
+    /// Enqueues data written using [`AsyncWrite`], which may be split into segments for sending.
+    ///
+    /// The sink must be ready.
+    fn start_write(&mut self, data: Bytes) -> Result<(), SendError> {
+        if self.closed {
+            return Err(SendError::Shutdown);
+        }
+
+        let len = data.len();
+        self.tx.start_send_unpin(SendReq::Write(data)).map_err(|_| self.error_rx.borrow().clone())?;
+        self.space_owed += len;
+
+        Ok(())
+    }
+
+    /// Waits until the data enqueued since the last call has acquired space in the send queue,
+    /// if its [size is limited](Cfg::send_queue_size).
+    ///
+    /// Thus the queue is overfilled by at most one data packet.
+    fn poll_space(&mut self, cx: &mut Context) -> Poll<Result<(), SendError>> {
+        let Some(space) = &self.space else { return Poll::Ready(Ok(())) };
+        if self.space_owed == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        let acquire = self.space_acquire.get_or_insert_with(|| Box::pin(space.acquire(self.space_owed)));
+        let permit = ready!(acquire.poll_unpin(cx));
+        self.space_acquire = None;
+        self.space_owed = 0;
+
+        match permit {
+            Some(permit) => {
+                permit.forget();
+                Poll::Ready(Ok(()))
+            }
+            None => Poll::Ready(Err(self.error_rx.borrow().clone())),
+        }
+    }
+
+    /// Attempts to write data from a reference-counted buffer.
+    ///
+    /// This works like [`AsyncWrite::poll_write`], but the written part is split off the front
+    /// of `buf` and passed on without copying it.
+    /// On success, the number of bytes written is returned and `buf` contains the remaining data.
+    pub fn poll_write_bytes(
+        self: Pin<&mut Self>, cx: &mut Context, buf: &mut Bytes,
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = Pin::into_inner(self);
+
+        ready!(this.poll_ready_unpin(cx))?;
+
+        let len = buf.len().min(this.max_write_size());
+        this.start_write(buf.slice(..len))?;
+        buf.advance(len);
+
+        Poll::Ready(Ok(len))
+    }
+
+    /// Writes all data from a reference-counted buffer without copying it.
+    ///
+    /// See [`poll_write_bytes`](Self::poll_write_bytes) for details.
+    pub async fn write_bytes(&mut self, mut buf: Bytes) -> Result<(), io::Error> {
+        while !buf.is_empty() {
+            poll_fn(|cx| Pin::new(&mut *self).poll_write_bytes(cx, &mut buf)).await?;
+        }
+        Ok(())
+    }
 }
 
 impl Sink<Bytes> for SenderSink {
@@ -192,6 +294,7 @@ impl Sink<Bytes> for SenderSink {
             return Poll::Ready(Err(SendError::Shutdown));
         }
 
+        ready!(this.poll_space(cx))?;
         this.tx.poll_ready_unpin(cx).map_err(|_| this.error_rx.borrow().clone())
     }
 
@@ -207,7 +310,11 @@ impl Sink<Bytes> for SenderSink {
             return Err(SendError::DataTooBig);
         }
 
-        this.tx.start_send_unpin(SendReq::Send(item)).map_err(|_| this.error_rx.borrow().clone())
+        let len = item.len();
+        this.tx.start_send_unpin(SendReq::Send(item)).map_err(|_| this.error_rx.borrow().clone())?;
+        this.space_owed += len;
+
+        Ok(())
     }
 
     #[inline]
@@ -255,10 +362,9 @@ impl AsyncWrite for SenderSink {
 
         ready!(this.poll_ready_unpin(cx))?;
 
-        let max_packet_size = this.cfg.io_write_size.get().min(this.remote_cfg.recv_buffer.get() as usize);
-        let len = buf.len().min(max_packet_size);
+        let len = buf.len().min(this.max_write_size());
         let data = Bytes::copy_from_slice(&buf[..len]);
-        this.start_send_unpin(data)?;
+        this.start_write(data)?;
 
         Poll::Ready(Ok(len))
     }
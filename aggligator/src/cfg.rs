@@ -22,6 +22,25 @@ pub enum LinkPing {
     WhenTimedOut,
 }
 
+/// Behavior when the data buffered by the remote endpoint for reordering reaches
+/// its [limit](Cfg::reorder_buffer).
+#[cfg_attr(feature = "dump", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum ReorderPolicy {
+    /// Decrease the amount of data sent over the link that delays reordering,
+    /// so that more data is sent over the faster links.
+    ///
+    /// The limit may be exceeded temporarily.
+    #[default]
+    Throttle,
+    /// Additionally stop sending new data over all links until the missing data has
+    /// been received, applying backpressure to the faster links.
+    ///
+    /// The limit may only be exceeded by data that is already in flight when it is reached.
+    Backpressure,
+}
+
 /// Configuration of a connection consisting of aggregated links.
 ///
 /// For most use cases the default configuration, i.e. [`Cfg::default()`](Self::default),
@@ -38,15 +57,46 @@ pub enum LinkPing {
 #[allow(clippy::manual_non_exhaustive)]
 pub struct Cfg {
     /// The size of a data packet when sending using [stream-based IO](crate::alc::Stream).
+    ///
+    /// Packets are split further when sent over a link with a smaller
+    /// [segment size](crate::Link::set_segment_size).
     pub io_write_size: NonZeroUsize,
     /// Maximum number of unacknowledged sent bytes.
     pub send_buffer: NonZeroU32,
     /// Length of queue for sending data packets.
     pub send_queue: NonZeroUsize,
+    /// Maximum total size in bytes of data packets in the queue for sending.
+    ///
+    /// When it is reached, writing to the connection is paused until queued data has been sent.
+    /// Together with [`send_buffer`](Self::send_buffer) this bounds the memory used by a
+    /// connection for buffering sent data.
+    /// A data packet larger than this occupies the whole queue.
+    /// If `None`, only the [length of the queue](Self::send_queue) is limited.
+    pub send_queue_size: Option<NonZeroU32>,
     /// Maximum number of unacknowledged received bytes.
     pub recv_buffer: NonZeroU32,
     /// Length of queue for received data packets.
     pub recv_queue: NonZeroUsize,
+    /// Maximum total size in bytes of data packets in the queue of received data.
+    ///
+    /// When it is reached, no further data is passed to the receiver until queued data has been
+    /// read, causing the remote endpoint to pause sending once the [receive buffer](Self::recv_buffer)
+    /// is full.
+    /// Together with [`recv_buffer`](Self::recv_buffer) this bounds the memory used by a
+    /// connection for buffering received data.
+    /// A data packet larger than this occupies the whole queue.
+    /// If `None`, only the [length of the queue](Self::recv_queue) is limited.
+    pub recv_queue_size: Option<NonZeroU32>,
+    /// Maximum size of sent data the remote endpoint buffers for reordering,
+    /// because intermediate data sent over slower links has not yet been received.
+    ///
+    /// This limits the data sent by this endpoint and thus must be configured on the sending side.
+    /// The occupancy of the reorder buffers is reported in the [connection statistics](crate::control::Stats).
+    /// If `None`, the smaller of [`send_buffer`](Self::send_buffer) and the receive buffer of the
+    /// remote endpoint is used.
+    pub reorder_buffer: Option<NonZeroU32>,
+    /// Behavior when the [reorder buffer](Self::reorder_buffer) limit is reached.
+    pub reorder_policy: ReorderPolicy,
     /// Minimum timeout waiting for a packet to be acknowledged.
     pub link_ack_timeout_min: Duration,
     /// Factor to calculate acknowledgement timeout from roundtrip time.
@@ -61,8 +111,33 @@ pub struct Cfg {
     pub link_unacked_limit: NonZeroUsize,
     /// Link pinging mode.
     pub link_ping: LinkPing,
-    /// Timeout for waiting for ping response, which when exceeded leads to removal of the link.
+    /// Timeout for waiting for ping response.
+    ///
+    /// When exceeded, the ping is considered missed.
     pub link_ping_timeout: Duration,
+    /// Number of consecutive missed pings after which the link is removed.
+    ///
+    /// After a missed ping, the link is reported as [suspect](crate::control::LinkHealth::Suspect)
+    /// and pinged again immediately.
+    /// Thus a silent link is removed after approximately this number times
+    /// [`link_ping_timeout`](Self::link_ping_timeout).
+    ///
+    /// Together with [`link_ping`](Self::link_ping) this controls how fast a link that went silent,
+    /// for example due to a NAT timeout, is detected.
+    pub link_ping_max_missed: NonZeroU32,
+    /// Time after which an idle link is probed by sending a ping.
+    ///
+    /// When no message has been sent or received over a link for the specified time,
+    /// a ping is sent over it, regardless of the [`link_ping`](Self::link_ping) mode.
+    /// This keeps the mappings of NAT gateways and stateful firewalls, that drop idle
+    /// connections, open.
+    /// The probe does not count as data, its reply updates the round trip time estimate
+    /// of the link and a probe that is not answered in time counts as a missed ping.
+    ///
+    /// Set this below the idle timeout of the network, for example to 30 seconds for a NAT
+    /// gateway that drops mappings after 60 seconds of inactivity.
+    /// By default idle links are not probed.
+    pub link_idle_probe: Option<Duration>,
     /// Maximum ping for a link to be usable.
     ///
     /// A link is used anyways if all links have a ping higher than the specified value.
@@ -75,16 +150,40 @@ pub struct Cfg {
     pub link_non_working_timeout: Duration,
     /// Delay before flushing a link when it has become idle.
     pub link_flush_delay: Duration,
+    /// Timeout for draining a link before it is disconnected regardless of unacknowledged data.
+    ///
+    /// See [`Link::drain`](crate::control::Link::drain).
+    pub link_drain_timeout: Duration,
     /// Timeout after which connection is closed when no working links are present.
+    ///
+    /// During this time the connection is [stalled](crate::control::ConnState::Stalled)
+    /// and resumes transparently when a link starts working again or a new link is added.
     pub no_link_timeout: Duration,
     /// Timeout after which connection is forcefully closed when sender and receiver are closed.
     pub termination_timeout: Duration,
+    /// Timeout after which the connection is closed when no data has been sent or received.
+    ///
+    /// Only data sent and received by the application resets the timer; pings, probes
+    /// of idle links and acknowledgements do not.
+    /// When it elapses, sending and receiving fail with an idle timeout error and the task
+    /// returns [`TaskError::IdleTimeout`](crate::TaskError::IdleTimeout).
+    /// Configure it on both endpoints, so that the remote endpoint also releases the connection.
+    /// By default idle connections are kept open.
+    pub idle_timeout: Option<Duration>,
     /// Queue length for establishing connections.
     pub connect_queue: NonZeroUsize,
     /// Disconnect the aggregated connection when a server id mismatch occurs while connecting a link.
     pub disconnect_on_server_id_mismatch: bool,
     /// Link speed statistics interval durations.
     pub stats_intervals: Vec<Duration>,
+    /// Interval for publishing the statistics of all links of a connection.
+    ///
+    /// See [`Control::conn_stats`](crate::control::Control::conn_stats).
+    pub conn_stats_interval: Duration,
+    /// Number of link events buffered for each subscriber.
+    ///
+    /// See [`Control::link_events`](crate::control::Control::link_events).
+    pub link_event_queue: NonZeroUsize,
     #[doc(hidden)]
     pub _non_exhaustive: (),
 }
@@ -96,8 +195,12 @@ impl Default for Cfg {
             io_write_size: NonZeroUsize::new(8_192).unwrap(),
             send_buffer: NonZeroU32::new(67_108_864).unwrap(),
             send_queue: NonZeroUsize::new(1024).unwrap(),
+            send_queue_size: None,
             recv_buffer: NonZeroU32::new(67_108_864).unwrap(),
             recv_queue: NonZeroUsize::new(1024).unwrap(),
+            recv_queue_size: None,
+            reorder_buffer: None,
+            reorder_policy: ReorderPolicy::Throttle,
             link_ack_timeout_min: Duration::from_secs(1),
             link_ack_timeout_roundtrip_factor: NonZeroU32::new(5).unwrap(),
             link_ack_timeout_max: Duration::from_secs(30),
@@ -105,13 +208,17 @@ impl Default for Cfg {
             link_unacked_limit: NonZeroUsize::new(33_554_432).unwrap(),
             link_ping: LinkPing::WhenIdle(Duration::from_secs(15)),
             link_ping_timeout: Duration::from_secs(40),
+            link_ping_max_missed: NonZeroU32::new(1).unwrap(),
+            link_idle_probe: None,
             link_max_ping: None,
             link_test_data_limit: usize::MAX,
             link_retest_interval: Duration::from_secs(15),
             link_non_working_timeout: Duration::from_secs(600),
             link_flush_delay: Duration::from_millis(500),
+            link_drain_timeout: Duration::from_secs(30),
             no_link_timeout: Duration::from_secs(90),
             termination_timeout: Duration::from_secs(300),
+            idle_timeout: None,
             connect_queue: NonZeroUsize::new(32).unwrap(),
             disconnect_on_server_id_mismatch: true,
             stats_intervals: vec![
@@ -120,6 +227,8 @@ impl Default for Cfg {
                 Duration::from_secs(5),
                 Duration::from_secs(10),
             ],
+            conn_stats_interval: Duration::from_secs(1),
+            link_event_queue: NonZeroUsize::new(256).unwrap(),
             _non_exhaustive: (),
         }
     }
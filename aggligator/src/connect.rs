@@ -23,6 +23,7 @@ use tokio::{
     sync::{mpsc, oneshot},
     time::{error::Elapsed, timeout, Instant},
 };
+use tracing::Instrument;
 use x25519_dalek::{EphemeralSecret, PublicKey};
 
 use crate::{
@@ -30,7 +31,7 @@ use crate::{
     alc::Channel,
     cfg::{Cfg, ExchangedCfg},
     control::{Control, Direction, Link},
-    id::{ConnId, OwnedConnId, ServerId},
+    id::{ConnId, ConnIdGenerator, OwnedConnId, RandomConnIdGenerator, ServerId},
     io::{IoRx, IoTx},
     msg::{LinkMsg, RefusedReason},
     protocol_err,
@@ -61,6 +62,7 @@ impl From<ListenError> for io::Error {
 
 /// Incoming link error.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum IncomingError {
     /// Sending or receiving over the link failed.
     Io(io::Error),
@@ -70,6 +72,10 @@ pub enum IncomingError {
     NotListening,
     /// The incoming link belonged to an already closed connection.
     Closed,
+    /// The connection id of the incoming link collides with the id of an unrelated connection.
+    ///
+    /// See the [id module](crate::id#connection-id-collisions) for details.
+    ConnIdCollision,
     /// The link aggregator server was dropped.
     ServerDropped,
 }
@@ -81,6 +87,7 @@ impl fmt::Display for IncomingError {
             Self::Refused => write!(f, "connection refused"),
             Self::NotListening => write!(f, "not listening"),
             Self::Closed => write!(f, "connection was closed"),
+            Self::ConnIdCollision => write!(f, "connection id collides with unrelated connection"),
             Self::ServerDropped => write!(f, "server dropped"),
         }
     }
@@ -107,6 +114,7 @@ impl From<IncomingError> for io::Error {
             IncomingError::Refused => io::Error::new(io::ErrorKind::ConnectionRefused, err),
             IncomingError::NotListening => io::Error::new(io::ErrorKind::ConnectionRefused, err),
             IncomingError::Closed => io::Error::new(io::ErrorKind::ConnectionAborted, err),
+            IncomingError::ConnIdCollision => io::Error::new(io::ErrorKind::ConnectionRefused, err),
             IncomingError::ServerDropped => io::Error::new(io::ErrorKind::ConnectionRefused, err),
         }
     }
@@ -252,21 +260,57 @@ where
     }
 }
 
+/// Maximum number of attempts to generate a connection id that does not collide
+/// with a known connection.
+const CONN_ID_ATTEMPTS: usize = 16;
+
+/// Connection known to a server.
+struct ServerConn<TX, RX, TAG> {
+    /// Queue for adding links to the connection.
+    link_tx: mpsc::Sender<LinkInt<TX, RX, TAG>>,
+    /// Server id of the remote endpoint that established the connection,
+    /// or `None` if the connection was established by this server.
+    origin: Option<Option<ServerId>>,
+}
+
+impl<TX, RX, TAG> ServerConn<TX, RX, TAG> {
+    /// Whether a link from the specified remote server may join this connection.
+    ///
+    /// A link establishing a new connection only joins a connection established by the same
+    /// remote server, since otherwise the connection ids collide.
+    /// Connections established by this server only accept links to existing connections.
+    fn accepts(&self, remote_server_id: Option<ServerId>, existing: bool) -> bool {
+        match self.origin {
+            Some(origin) => origin == remote_server_id,
+            None => existing,
+        }
+    }
+}
+
 /// Server implementation.
 struct ServerInner<TX, RX, TAG> {
     cfg: Arc<Cfg>,
     server_id: ServerId,
-    conns: HashMap<ConnId, mpsc::Sender<LinkInt<TX, RX, TAG>>>,
+    conn_id_generator: Arc<dyn ConnIdGenerator>,
+    conns: HashMap<ConnId, ServerConn<TX, RX, TAG>>,
     closed_conns_tx: mpsc::UnboundedSender<ConnId>,
     closed_conns_rx: mpsc::UnboundedReceiver<ConnId>,
     listen_tx: mpsc::Sender<Incoming<TX, RX, TAG>>,
 }
 
 impl<TX, RX, TAG> ServerInner<TX, RX, TAG> {
-    fn new(cfg: Arc<Cfg>, server_id: ServerId) -> Self {
+    fn new(cfg: Arc<Cfg>, server_id: ServerId, conn_id_generator: Arc<dyn ConnIdGenerator>) -> Self {
         let (closed_conns_tx, closed_conns_rx) = mpsc::unbounded_channel();
         let listen_tx = mpsc::channel(cfg.connect_queue.get()).0;
-        Self { cfg, server_id, conns: HashMap::new(), closed_conns_tx, closed_conns_rx, listen_tx }
+        Self {
+            cfg,
+            server_id,
+            conn_id_generator,
+            conns: HashMap::new(),
+            closed_conns_tx,
+            closed_conns_rx,
+            listen_tx,
+        }
     }
 
     /// Clean up closed connections.
@@ -307,9 +351,22 @@ where
     TX: Sink<Bytes, Error = io::Error> + Unpin + Send + 'static,
 {
     /// Creates a new link aggregator server.
+    ///
+    /// Connection ids of outgoing connections are generated randomly.
     pub fn new(cfg: Cfg) -> Self {
+        Self::with_conn_id_generator(cfg, RandomConnIdGenerator)
+    }
+
+    /// Creates a new link aggregator server using the specified generator for the
+    /// connection ids of outgoing connections.
+    ///
+    /// This is useful for obtaining deterministic connection ids in tests.
+    pub fn with_conn_id_generator(cfg: Cfg, conn_id_generator: impl ConnIdGenerator) -> Self {
         let server_id = ServerId::generate();
-        Self { server_id, inner: Arc::new(Mutex::new(ServerInner::new(Arc::new(cfg), server_id))) }
+        Self {
+            server_id,
+            inner: Arc::new(Mutex::new(ServerInner::new(Arc::new(cfg), server_id, Arc::new(conn_id_generator)))),
+        }
     }
 
     /// The server id.
@@ -325,10 +382,27 @@ where
     /// (for example using [`tokio::spawn`]) for the connection to work.
     /// Add links to the connection using [`Control::add`] or [`Control::add_io`]
     /// and then call [`Outgoing::connect`] to establish the connection.
+    ///
+    /// If the generated connection id collides with a connection known to this server,
+    /// a new id is generated.
+    ///
+    /// # Panics
+    /// Panics if the [connection id generator](Self::with_conn_id_generator) repeatedly
+    /// generates ids of known connections.
     pub fn connect(&self) -> (Task<TX, RX, TAG>, Outgoing, Control<TX, RX, TAG>) {
         let mut inner = self.inner.lock().unwrap();
-
-        let conn_id = ConnId::generate();
+        inner.cleanup_links();
+
+        let conn_id = (0..CONN_ID_ATTEMPTS)
+            .map(|_| inner.conn_id_generator.generate())
+            .find(|conn_id| {
+                let collides = inner.conns.contains_key(conn_id);
+                if collides {
+                    tracing::warn!(%conn_id, "generated connection id collides with known connection");
+                }
+                !collides
+            })
+            .expect("connection id generator repeatedly generated ids of known connections");
         let (link_tx, link_rx) = mpsc::channel(inner.cfg.connect_queue.get());
 
         let AggParts { task, channel, control, connected_rx } = AggParts::new(
@@ -341,7 +415,7 @@ where
             Some((link_tx.clone(), link_rx)),
         );
 
-        inner.conns.insert(conn_id, link_tx);
+        inner.conns.insert(conn_id, ServerConn { link_tx, origin: None });
 
         (task, Outgoing { channel, connected_rx }, control)
     }
@@ -401,38 +475,50 @@ where
         }
 
         // Perform protocol handshake.
+        let span = tracing::debug_span!("handshake", %server_id, direction = %Direction::Incoming);
+        let res = timeout(cfg.link_ping_timeout, async {
+            let server_secret = EphemeralSecret::random_from_rng(rand_core::OsRng);
+            let server_public_key = PublicKey::from(&server_secret);
+
+            let start = Instant::now();
+            LinkMsg::Welcome {
+                extensions: 0,
+                public_key: server_public_key,
+                server_id,
+                user_data: user_data.to_vec(),
+                cfg: (&*cfg).into(),
+            }
+            .send(&mut tx)
+            .await?;
+
+            let LinkMsg::Connect {
+                extensions: _,
+                public_key: client_public_key,
+                server_id,
+                connection_id: encrypted_conn_id,
+                existing_connection,
+                user_data: remote_user_data, cfg
+            } = LinkMsg::recv(&mut rx).await?
+                else { return Err::<_, IncomingError>(protocol_err!("expected Connect message").into()) };
+
+            let shared_secret = server_secret.diffie_hellman(&client_public_key);
+            let conn_id = encrypted_conn_id.decrypt(&shared_secret);
+            tracing::trace!(%conn_id, remote_server_id = ?server_id, "received Connect");
+
+            Ok((server_id, conn_id, existing_connection, cfg, start.elapsed(), remote_user_data))
+        })
+        .instrument(span.clone())
+        .await
+        .map_err(IncomingError::from)
+        .and_then(|res| res);
         let (remote_server_id, conn_id, existing, remote_cfg, roundtrip, remote_user_data) =
-            timeout(cfg.link_ping_timeout, async {
-                let server_secret = EphemeralSecret::new(rand_core::OsRng);
-                let server_public_key = PublicKey::from(&server_secret);
-
-                let start = Instant::now();
-                LinkMsg::Welcome {
-                    extensions: 0,
-                    public_key: server_public_key,
-                    server_id,
-                    user_data: user_data.to_vec(),
-                    cfg: (&*cfg).into(),
+            match res {
+                Ok(handshake) => handshake,
+                Err(err) => {
+                    tracing::debug!(parent: &span, "handshake failed: {err}");
+                    return Err(err);
                 }
-                .send(&mut tx)
-                .await?;
-
-                let LinkMsg::Connect {
-                    extensions: _,
-                    public_key: client_public_key,
-                    server_id,
-                    connection_id: encrypted_conn_id,
-                    existing_connection,
-                    user_data: remote_user_data, cfg
-                } = LinkMsg::recv(&mut rx).await?
-                    else { return Err::<_, IncomingError>(protocol_err!("expected Connect message").into()) };
-
-                let shared_secret = server_secret.diffie_hellman(&client_public_key);
-                let conn_id = encrypted_conn_id.decrypt(&shared_secret);
-
-                Ok((server_id, conn_id, existing_connection, cfg, start.elapsed(), remote_user_data))
-            })
-            .await??;
+            };
 
         tracing::debug!(%server_id, %conn_id, %existing, "handling incoming link");
 
@@ -464,14 +550,23 @@ where
             // Check if link belongs to existing connection.
             let mut inner = self.inner.lock().unwrap();
             match inner.conns.entry(conn_id) {
+                // Link belongs to connection with colliding id.
+                Entry::Occupied(ocu) if !ocu.get().accepts(remote_server_id, existing) => {
+                    tracing::warn!(%conn_id, ?remote_server_id, "connection id collides with unrelated connection");
+                    break Connection::Refuse {
+                        reason: RefusedReason::ConnectionRefused,
+                        err: IncomingError::ConnIdCollision,
+                    };
+                }
+
                 // Link joins existing connection.
-                Entry::Occupied(ocu) => break Connection::Existing { link_tx: ocu.get().clone() },
+                Entry::Occupied(ocu) => break Connection::Existing { link_tx: ocu.get().link_tx.clone() },
 
                 // Link belongs to new, incoming connection.
                 Entry::Vacant(vac) if !existing => match listen_tx_permit {
                     Some(Ok(listen_tx_permit)) => {
                         let (link_tx, link_rx) = mpsc::channel(cfg.connect_queue.get());
-                        vac.insert(link_tx.clone());
+                        vac.insert(ServerConn { link_tx: link_tx.clone(), origin: Some(remote_server_id) });
                         break Connection::New { link_tx, link_rx, listen_tx_permit };
                     }
                     Some(Err(_)) => {
@@ -505,6 +600,7 @@ where
                         roundtrip,
                         remote_user_data,
                     );
+                    tracing::debug!(parent: &link_int.span, "link established with roundtrip time {roundtrip:?}");
                     let link = Link::from(&link_int);
                     link_tx_permit.send(link_int);
 
@@ -535,6 +631,7 @@ where
                     roundtrip,
                     remote_user_data,
                 );
+                tracing::debug!(parent: &link_int.span, "link established with roundtrip time {roundtrip:?}");
                 let link = Link::from(&link_int);
                 link_tx.try_send(link_int).unwrap();
 
@@ -3,25 +3,28 @@
 use bytes::Bytes;
 use futures::{Sink, Stream};
 use std::{
+    collections::HashMap,
     error::Error,
     fmt,
     hash::Hash,
     io,
+    num::NonZeroUsize,
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Arc, Weak,
     },
     time::Duration,
 };
 use tokio::{
     io::{AsyncRead, AsyncWrite},
-    sync::{mpsc, watch, Mutex},
+    sync::{broadcast, mpsc, watch, Mutex},
     time::{error::Elapsed, timeout, Instant},
 };
+use tracing::Instrument;
 use x25519_dalek::{EphemeralSecret, PublicKey};
 
 use crate::{
-    agg::link_int::LinkInt,
+    agg::link_int::{LinkInt, NO_RATE_LIMIT},
     cfg::Cfg,
     id::{ConnId, EncryptedConnId, LinkId, ServerId},
     io::{IoRx, IoTx},
@@ -128,6 +131,149 @@ impl fmt::Display for Direction {
     }
 }
 
+/// Mode of sending data over the links of a connection.
+///
+/// The mode only affects sending of data from this endpoint.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SendMode {
+    /// Data is spread over all links according to the [link selector](crate::Task::set_link_selector).
+    #[default]
+    Aggregate,
+    /// All data is sent over the working link with the lowest round trip time.
+    ///
+    /// This avoids reordering delays caused by links of different latencies, but limits
+    /// throughput to that of a single link.
+    /// Data is switched to another link only when the pinned link stops working or another
+    /// link has a considerably lower round trip time.
+    /// See [`LowLatencyLinkSelector`](crate::selector::LowLatencyLinkSelector) for details.
+    SinglePathLowLatency,
+    /// Data is spread over the links like in [aggregate mode](Self::Aggregate) and
+    /// additionally a copy of each data packet is sent simultaneously over the idle
+    /// working link with the lowest round trip time.
+    ///
+    /// The remote endpoint delivers whichever copy arrives first and discards the other.
+    /// This trades bandwidth for latency, hiding delays and losses of individual links,
+    /// and is intended for latency-critical traffic of low volume.
+    /// If no other link is idle, a packet is sent only once.
+    /// How often the copy arrived first is reported in the [statistics](Stats::reissued_won).
+    Redundant,
+}
+
+impl SendMode {
+    pub(crate) fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::SinglePathLowLatency,
+            2 => Self::Redundant,
+            _ => Self::Aggregate,
+        }
+    }
+
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
+            Self::Aggregate => 0,
+            Self::SinglePathLowLatency => 1,
+            Self::Redundant => 2,
+        }
+    }
+}
+
+impl fmt::Display for SendMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Aggregate => write!(f, "aggregate"),
+            Self::SinglePathLowLatency => write!(f, "single path low latency"),
+            Self::Redundant => write!(f, "redundant"),
+        }
+    }
+}
+
+/// Policy for retransmitting data whose acknowledgement is overdue.
+///
+/// The policy only affects data sent from this endpoint.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RetransmitPolicy {
+    /// Data is left to the link it has been sent over.
+    ///
+    /// It is only sent again over another link when the link fails, i.e. when its
+    /// acknowledgement times out or the link is disconnected.
+    #[default]
+    SameLink,
+    /// Data whose acknowledgement is overdue is additionally sent over the fastest
+    /// other working link.
+    ///
+    /// Data is considered overdue when it has not been acknowledged within twice the
+    /// round trip time of the link it has been sent over.
+    /// A copy is then sent over the idle working link with the lowest round trip time
+    /// and the data is delivered by whichever link is faster.
+    /// The link the data has originally been sent over is not considered failed.
+    ///
+    /// This trades bandwidth for latency and is intended for interactive traffic
+    /// over links that occasionally stall or drop data.
+    Fastest,
+}
+
+impl RetransmitPolicy {
+    pub(crate) fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Fastest,
+            _ => Self::SameLink,
+        }
+    }
+
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
+            Self::SameLink => 0,
+            Self::Fastest => 1,
+        }
+    }
+}
+
+impl fmt::Display for RetransmitPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::SameLink => write!(f, "same link"),
+            Self::Fastest => write!(f, "fastest"),
+        }
+    }
+}
+
+/// State of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConnState {
+    /// The connection is being established.
+    Connecting,
+    /// At least one link of the connection is working.
+    Active,
+    /// No link of the connection is working.
+    ///
+    /// The connection is kept alive while it waits for a link to start working again
+    /// or a new link to be added.
+    /// Data sent in the meantime is buffered, up to the [send queue](Cfg::send_queue) and
+    /// [send buffer](Cfg::send_buffer), and transmitted once a link is available.
+    /// The connection fails if no link is working after the
+    /// [no link timeout](Cfg::no_link_timeout) has elapsed.
+    Stalled {
+        /// Time since when no link is working.
+        since: Instant,
+    },
+    /// The connection has been terminated.
+    Terminated,
+}
+
+impl fmt::Display for ConnState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Connecting => write!(f, "connecting"),
+            Self::Active => write!(f, "active"),
+            Self::Stalled { since } => write!(f, "stalled for {:.1} s", since.elapsed().as_secs_f32()),
+            Self::Terminated => write!(f, "terminated"),
+        }
+    }
+}
+
 /// A handle for controlling and monitoring a connection consisting of aggregated links.
 ///
 /// Clones of this handle refer to the same underlying connection.
@@ -142,8 +288,18 @@ pub struct Control<TX, RX, TAG> {
     pub(crate) link_tx: mpsc::Sender<LinkInt<TX, RX, TAG>>,
     pub(crate) links_rx: watch::Receiver<Vec<Link<TAG>>>,
     pub(crate) stats_rx: watch::Receiver<Stats>,
+    pub(crate) conn_stats_rx: watch::Receiver<ConnStats<TAG>>,
+    pub(crate) state_rx: watch::Receiver<ConnState>,
+    pub(crate) link_count_rx: watch::Receiver<usize>,
+    pub(crate) redundant_seen: bool,
     pub(crate) server_changed_tx: mpsc::Sender<()>,
     pub(crate) result_rx: watch::Receiver<Result<(), TaskError>>,
+    pub(crate) send_mode: Arc<AtomicU8>,
+    pub(crate) send_mode_changed_tx: mpsc::Sender<()>,
+    pub(crate) retransmit_policy: Arc<AtomicU8>,
+    pub(crate) retransmit_policy_changed_tx: mpsc::Sender<()>,
+    pub(crate) shutdown_tx: mpsc::Sender<Duration>,
+    pub(crate) link_event_tx: Weak<broadcast::Sender<LinkEvent<TAG>>>,
 }
 
 impl<TX, RX, TAG> Clone for Control<TX, RX, TAG> {
@@ -158,8 +314,18 @@ impl<TX, RX, TAG> Clone for Control<TX, RX, TAG> {
             link_tx: self.link_tx.clone(),
             links_rx: self.links_rx.clone(),
             stats_rx: self.stats_rx.clone(),
+            conn_stats_rx: self.conn_stats_rx.clone(),
+            state_rx: self.state_rx.clone(),
+            link_count_rx: self.link_count_rx.clone(),
+            redundant_seen: self.redundant_seen,
             server_changed_tx: self.server_changed_tx.clone(),
             result_rx: self.result_rx.clone(),
+            send_mode: self.send_mode.clone(),
+            send_mode_changed_tx: self.send_mode_changed_tx.clone(),
+            retransmit_policy: self.retransmit_policy.clone(),
+            retransmit_policy_changed_tx: self.retransmit_policy_changed_tx.clone(),
+            shutdown_tx: self.shutdown_tx.clone(),
+            link_event_tx: self.link_event_tx.clone(),
         }
     }
 }
@@ -237,6 +403,109 @@ impl<TX, RX, TAG> Control<TX, RX, TAG> {
         self.result_rx.borrow().clone()
     }
 
+    /// Gracefully shuts down the connection.
+    ///
+    /// All data that has been accepted by the sender of the connection before this is
+    /// called is still transmitted; afterwards sending fails with
+    /// [`SendError::Shutdown`](crate::alc::SendError::Shutdown).
+    /// Receiving stops immediately: the receiver reaches the end of the stream after
+    /// the data already received has been read and data arriving later is discarded.
+    ///
+    /// The connection is terminated and its links are closed once all sent data has been
+    /// acknowledged and consumed by the remote endpoint.
+    /// If this does not happen before the `timeout` elapses or all links fail,
+    /// the connection is terminated anyway and [`TaskError::ShutdownIncomplete`] is returned.
+    /// If the connection is shut down multiple times, the first timeout applies.
+    ///
+    /// In contrast, dropping the sender and receiver closes the connection in the background
+    /// and [`poll_shutdown`](AsyncWrite::poll_shutdown) on the [stream](crate::alc::Stream)
+    /// or the [sender](crate::alc::SenderSink), also when boxed, completes once the data has
+    /// been flushed to the links, but does not wait for the remote endpoint to acknowledge it.
+    /// Data written before a completed `poll_shutdown` is always transmitted before the
+    /// connection is shut down by this.
+    /// Calling `poll_shutdown` after this has been called fails, since the connection
+    /// does not accept further data.
+    ///
+    /// The underlying transports of the links are closed only after this has completed,
+    /// thus this must be awaited before the process exits to be sure that no data is lost.
+    pub async fn shutdown(&self, timeout: Duration) -> Result<(), TaskError> {
+        let _ = self.shutdown_tx.send(timeout).await;
+        self.terminated().await
+    }
+
+    /// The current state of the connection.
+    pub fn state(&self) -> ConnState {
+        *self.state_rx.borrow()
+    }
+
+    /// Gets the current state of the connection and marks it as seen.
+    ///
+    /// This will cause [`state_changed`](Self::state_changed) to wait until a change occurs.
+    pub fn state_update(&mut self) -> ConnState {
+        *self.state_rx.borrow_and_update()
+    }
+
+    /// Waits until the state of the connection has changed.
+    pub async fn state_changed(&mut self) {
+        let _ = self.state_rx.changed().await;
+    }
+
+    /// The number of working links of the connection.
+    ///
+    /// A link is working if it is currently usable for sending data, i.e. it is
+    /// [working](Link::is_working), not [blocked](Link::is_blocked) and not being disconnected.
+    /// This is zero once the connection has been terminated.
+    pub fn link_count(&self) -> usize {
+        *self.link_count_rx.borrow()
+    }
+
+    /// Returns whether the connection is redundant, i.e. more than one link is working.
+    ///
+    /// If this is `false` while the connection is [active](ConnState::Active), the connection
+    /// depends on a single link and fails if that link fails.
+    pub fn is_redundant(&self) -> bool {
+        self.link_count() > 1
+    }
+
+    /// Subscribes to the number of [working links](Self::link_count) of the connection.
+    ///
+    /// The receiver is notified whenever the number changes.
+    pub fn link_count_watch(&self) -> watch::Receiver<usize> {
+        self.link_count_rx.clone()
+    }
+
+    /// Returns whether the connection is redundant and marks this as seen.
+    ///
+    /// This will cause [`redundancy_changed`](Self::redundancy_changed) to wait until
+    /// the redundancy differs from the returned value.
+    pub fn redundancy_update(&mut self) -> bool {
+        self.redundant_seen = *self.link_count_rx.borrow_and_update() > 1;
+        self.redundant_seen
+    }
+
+    /// Waits until the connection has become redundant or lost its redundancy,
+    /// compared to when it was last seen by this method or [`redundancy_update`](Self::redundancy_update).
+    ///
+    /// Initially the connection is seen as not redundant.
+    /// Changes that occurred before this method is called are thus not missed and
+    /// make it return immediately.
+    ///
+    /// Returns whether the connection is now [redundant](Self::is_redundant).
+    /// This can be used to warn the user when only one link is left.
+    /// Also returns when the connection has been terminated.
+    pub async fn redundancy_changed(&mut self) -> bool {
+        loop {
+            let redundant = *self.link_count_rx.borrow_and_update() > 1;
+            if redundant != self.redundant_seen {
+                self.redundant_seen = redundant;
+                return redundant;
+            }
+            if self.link_count_rx.changed().await.is_err() {
+                return self.is_redundant();
+            }
+        }
+    }
+
     /// Gets handles to all links of the connection.
     pub fn links(&self) -> Vec<Link<TAG>> {
         self.links_rx.borrow().clone()
@@ -254,6 +523,136 @@ impl<TX, RX, TAG> Control<TX, RX, TAG> {
         let _ = self.links_rx.changed().await;
     }
 
+    /// Sets the weight of the link with the specified tag.
+    ///
+    /// Returns `false` if no link with the specified tag is part of the connection.
+    /// See [`Link::set_weight`] for details.
+    pub fn set_link_weight(&self, tag: &TAG, weight: u32) -> bool
+    where
+        TAG: PartialEq,
+    {
+        let links = self.links_rx.borrow();
+        let mut found = false;
+        for link in links.iter().filter(|link| link.tag() == tag) {
+            link.set_weight(weight);
+            found = true;
+        }
+        found
+    }
+
+    /// Sets the limit of bytes sent per second over the link with the specified tag.
+    ///
+    /// Returns `false` if no link with the specified tag is part of the connection.
+    /// See [`Link::set_rate_limit`] for details.
+    pub fn set_link_rate_limit(&self, tag: &TAG, bytes_per_sec: Option<u64>) -> bool
+    where
+        TAG: PartialEq,
+    {
+        let links = self.links_rx.borrow();
+        let mut found = false;
+        for link in links.iter().filter(|link| link.tag() == tag) {
+            link.set_rate_limit(bytes_per_sec);
+            found = true;
+        }
+        found
+    }
+
+    /// Pauses the link with the specified tag by [blocking](Link::set_blocked) it.
+    ///
+    /// A paused link stays connected and keeps being pinged, but no data is exchanged over it
+    /// until it is [resumed](Self::resume_link).
+    /// Since the link does not need to be reestablished, resuming takes effect immediately.
+    ///
+    /// Returns `false` if no link with the specified tag is part of the connection.
+    pub fn pause_link(&self, tag: &TAG) -> bool
+    where
+        TAG: PartialEq,
+    {
+        let links = self.links_rx.borrow();
+        let mut found = false;
+        for link in links.iter().filter(|link| link.tag() == tag) {
+            link.set_blocked(true);
+            found = true;
+        }
+        found
+    }
+
+    /// Resumes the link with the specified tag that has been [paused](Self::pause_link).
+    ///
+    /// Returns `false` if no link with the specified tag is part of the connection.
+    pub fn resume_link(&self, tag: &TAG) -> bool
+    where
+        TAG: PartialEq,
+    {
+        let links = self.links_rx.borrow();
+        let mut found = false;
+        for link in links.iter().filter(|link| link.tag() == tag) {
+            link.set_blocked(false);
+            found = true;
+        }
+        found
+    }
+
+    /// Sets the preferred size of data segments sent over the link with the specified tag.
+    ///
+    /// Returns `false` if no link with the specified tag is part of the connection.
+    /// See [`Link::set_segment_size`] for details.
+    pub fn set_link_segment_size(&self, tag: &TAG, segment_size: Option<NonZeroUsize>) -> bool
+    where
+        TAG: PartialEq,
+    {
+        let links = self.links_rx.borrow();
+        let mut found = false;
+        for link in links.iter().filter(|link| link.tag() == tag) {
+            link.set_segment_size(segment_size);
+            found = true;
+        }
+        found
+    }
+
+    /// Returns the mode of sending data over the links of the connection.
+    pub fn send_mode(&self) -> SendMode {
+        SendMode::from_u8(self.send_mode.load(Ordering::SeqCst))
+    }
+
+    /// Sets the mode of sending data over the links of the connection.
+    ///
+    /// The mode can be switched at any time without losing data.
+    /// Data that has already been sent over a link is not affected.
+    pub fn set_send_mode(&self, send_mode: SendMode) {
+        self.send_mode.store(send_mode.to_u8(), Ordering::SeqCst);
+        let _ = self.send_mode_changed_tx.try_send(());
+    }
+
+    /// Returns the policy for retransmitting data whose acknowledgement is overdue.
+    pub fn retransmit_policy(&self) -> RetransmitPolicy {
+        RetransmitPolicy::from_u8(self.retransmit_policy.load(Ordering::SeqCst))
+    }
+
+    /// Sets the policy for retransmitting data whose acknowledgement is overdue.
+    ///
+    /// The policy can be switched at any time and applies to data that is awaiting
+    /// acknowledgement.
+    pub fn set_retransmit_policy(&self, retransmit_policy: RetransmitPolicy) {
+        self.retransmit_policy.store(retransmit_policy.to_u8(), Ordering::SeqCst);
+        let _ = self.retransmit_policy_changed_tx.try_send(());
+    }
+
+    /// Gracefully drains and then disconnects the link with the specified tag.
+    ///
+    /// Returns `None` if no link with the specified tag is part of the connection.
+    /// Otherwise returns whether the link was fully drained before the
+    /// [drain timeout](Cfg::link_drain_timeout) elapsed.
+    /// See [`Link::drain`] for details.
+    pub async fn drain_link(&self, tag: &TAG) -> Option<bool>
+    where
+        TAG: PartialEq,
+    {
+        let link =
+            self.links_rx.borrow().iter().find(|link| link.tag() == tag && !link.is_disconnected()).cloned()?;
+        Some(link.drain().await)
+    }
+
     /// The current connection statistics.
     pub fn stats(&self) -> Stats {
         self.stats_rx.borrow().clone()
@@ -270,6 +669,50 @@ impl<TX, RX, TAG> Control<TX, RX, TAG> {
     pub async fn stats_changed(&mut self) {
         let _ = self.stats_rx.changed().await;
     }
+
+    /// Returns the [path characteristics](PathStats) of all links of the connection.
+    ///
+    /// The map is keyed by the link tag. Disconnected links are not included.
+    /// See [`Link::path_stats`] for details.
+    pub fn path_stats(&self) -> HashMap<TAG, PathStats>
+    where
+        TAG: Clone + Eq + Hash,
+    {
+        let links = self.links_rx.borrow();
+        links
+            .iter()
+            .filter(|link| !link.is_disconnected())
+            .map(|link| (link.tag().clone(), link.path_stats()))
+            .collect()
+    }
+
+    /// Subscribes to the statistics of all links of the connection.
+    ///
+    /// The statistics are updated at the interval specified in the
+    /// [configuration](crate::cfg::Cfg::conn_stats_interval).
+    pub fn conn_stats(&self) -> watch::Receiver<ConnStats<TAG>> {
+        self.conn_stats_rx.clone()
+    }
+
+    /// Subscribes to events concerning the links of the connection.
+    ///
+    /// This is the event-driven counterpart to [`links`](Self::links) and
+    /// [`conn_stats`](Self::conn_stats).
+    /// Events are delivered in the order they occurred, starting with the first event
+    /// after subscribing.
+    ///
+    /// Up to [`Cfg::link_event_queue`] events are buffered for each subscriber.
+    /// If a subscriber falls further behind, the oldest events are dropped and
+    /// [`RecvError::Lagged`](broadcast::error::RecvError::Lagged) indicates the number
+    /// of missed events; use [`links`](Self::links) to resynchronize.
+    /// The channel is closed after the connection has been terminated and the final
+    /// disconnection events have been received.
+    pub fn link_events(&self) -> broadcast::Receiver<LinkEvent<TAG>> {
+        match self.link_event_tx.upgrade() {
+            Some(tx) => tx.subscribe(),
+            None => broadcast::channel(1).1,
+        }
+    }
 }
 
 impl<TX, RX, TAG> Control<TX, RX, TAG>
@@ -299,8 +742,9 @@ where
         assert!(user_data.len() <= u16::MAX as usize, "user_data is too big");
 
         // Perform protocol handshake.
-        let (remote_cfg, roundtrip, remote_user_data) = timeout(self.cfg.link_ping_timeout, async {
-            let client_secret = EphemeralSecret::new(rand_core::OsRng);
+        let span = tracing::debug_span!("handshake", conn = %self.conn_id, direction = %Direction::Outgoing);
+        let res = timeout(self.cfg.link_ping_timeout, async {
+            let client_secret = EphemeralSecret::random_from_rng(rand_core::OsRng);
             let client_public_key = PublicKey::from(&client_secret);
 
             let LinkMsg::Welcome {
@@ -311,6 +755,7 @@ where
                 user_data: remote_user_data
             } = LinkMsg::recv(&mut rx).await?
                 else { return Err::<_, AddLinkError>(protocol_err!("expected Welcome message").into()) };
+            tracing::trace!(%server_id, "received Welcome");
 
             let shared_secret = client_secret.diffie_hellman(&server_public_key);
 
@@ -345,6 +790,7 @@ where
             }
             .send(&mut tx)
             .await?;
+            tracing::trace!("sent Connect");
 
             match LinkMsg::recv(&mut rx).await? {
                 LinkMsg::Accepted => {
@@ -355,7 +801,17 @@ where
                 _ => Err(protocol_err!("expected Accepted or Refused message").into()),
             }
         })
-        .await??;
+        .instrument(span.clone())
+        .await
+        .map_err(AddLinkError::from)
+        .and_then(|res| res);
+        let (remote_cfg, roundtrip, remote_user_data) = match res {
+            Ok(handshake) => handshake,
+            Err(err) => {
+                tracing::debug!(parent: &span, "handshake failed: {err}");
+                return Err(err);
+            }
+        };
 
         // Create link.
         let link_int = LinkInt::new(
@@ -369,6 +825,7 @@ where
             roundtrip,
             remote_user_data,
         );
+        tracing::debug!(parent: &link_int.span, "link established with roundtrip time {roundtrip:?}");
         let link = Link::from(&link_int);
         self.link_tx.send(link_int).await.map_err(|_| AddLinkError::ConnectionClosed)?;
 
@@ -423,13 +880,154 @@ pub struct Stats {
     pub sent_unconsumed_count: usize,
     /// Size of data received by remote endpoint that cannot yet be consumed,
     /// because intermediate data has not yet been received.
+    ///
+    /// This is the occupancy of the reorder buffer of the remote endpoint,
+    /// which is limited by [`Cfg::reorder_buffer`](crate::cfg::Cfg::reorder_buffer).
     pub sent_unconsumable: usize,
     /// Length of the queue for resending lost packets.
     pub resend_queue_len: usize,
+    /// Number of packets of which a copy has been sent over a second link.
+    ///
+    /// Copies are sent in [redundant mode](SendMode::Redundant) and by the
+    /// [fastest retransmit policy](RetransmitPolicy::Fastest).
+    pub reissued: u64,
+    /// Number of packets whose copy sent over a second link has been acknowledged
+    /// before the original, i.e. the copy most probably arrived first.
+    pub reissued_won: u64,
     /// Size of data that has been received and not yet consumed.
     pub recved_unconsumed: usize,
     /// Number of packets received and not yet consumed.
     pub recved_unconsumed_count: usize,
+    /// Size of data that has been received and cannot yet be consumed,
+    /// because intermediate data has not yet been received.
+    ///
+    /// This is the occupancy of the reorder buffer.
+    pub recved_unconsumable: usize,
+}
+
+/// Statistics of all links of a connection.
+///
+/// Only links that are currently part of the connection are included.
+/// When a link is removed its statistics are dropped, and a link that is
+/// later reconnected with the same tag starts with fresh counters.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ConnStats<TAG> {
+    /// Time when the statistics were gathered.
+    pub time: Instant,
+    /// Statistics of each link.
+    pub links: Vec<ConnLinkStats<TAG>>,
+}
+
+impl<TAG> Clone for ConnStats<TAG> {
+    fn clone(&self) -> Self {
+        Self { time: self.time, links: self.links.clone() }
+    }
+}
+
+impl<TAG> Default for ConnStats<TAG> {
+    fn default() -> Self {
+        Self { time: Instant::now(), links: Vec::new() }
+    }
+}
+
+impl<TAG> ConnStats<TAG>
+where
+    TAG: PartialEq,
+{
+    /// Gets the statistics of the link with the specified tag.
+    pub fn link(&self, tag: &TAG) -> Option<&ConnLinkStats<TAG>> {
+        self.links.iter().find(|link| *link.tag == *tag)
+    }
+}
+
+/// Statistics of a link as part of [connection statistics](ConnStats).
+///
+/// All counters are monotonically increasing over the lifetime of the link.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ConnLinkStats<TAG> {
+    /// Link id.
+    pub id: LinkId,
+    /// Link tag.
+    pub tag: Arc<TAG>,
+    /// Total data sent in bytes.
+    pub total_sent: u64,
+    /// Total data received in bytes.
+    pub total_recved: u64,
+    /// Number of packets resent over the link because they were lost on another or this link.
+    pub total_resent: u64,
+    /// Current round trip duration estimate.
+    pub roundtrip: Duration,
+    /// Health of the link determined by pinging.
+    pub health: LinkHealth,
+    /// Limit of bytes sent per second over the link.
+    pub rate_limit: Option<u64>,
+}
+
+impl<TAG> Clone for ConnLinkStats<TAG> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            tag: self.tag.clone(),
+            total_sent: self.total_sent,
+            total_recved: self.total_recved,
+            total_resent: self.total_resent,
+            roundtrip: self.roundtrip,
+            health: self.health,
+            rate_limit: self.rate_limit,
+        }
+    }
+}
+
+/// Event concerning a link of a connection.
+///
+/// See [`Control::link_events`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct LinkEvent<TAG> {
+    /// Connection id.
+    pub conn_id: ConnId,
+    /// Link id.
+    pub link_id: LinkId,
+    /// Link tag.
+    pub tag: Arc<TAG>,
+    /// Kind of event.
+    pub kind: LinkEventKind,
+}
+
+impl<TAG> Clone for LinkEvent<TAG> {
+    fn clone(&self) -> Self {
+        Self { conn_id: self.conn_id, link_id: self.link_id, tag: self.tag.clone(), kind: self.kind.clone() }
+    }
+}
+
+/// Kind of [link event](LinkEvent).
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum LinkEventKind {
+    /// The link has been added to the connection and is yet to be tested.
+    Connected,
+    /// The link has started working, either after it has been tested for the first time
+    /// or after it was degraded.
+    Working,
+    /// The link has stopped working, but is still part of the connection.
+    ///
+    /// Data is no longer sent over the link until it is working again.
+    Degraded(NotWorkingReason),
+    /// The link has been disconnected.
+    Disconnected(DisconnectReason),
+}
+
+impl fmt::Display for LinkEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Connected => write!(f, "connected"),
+            Self::Working => write!(f, "working"),
+            Self::Degraded(reason) => write!(f, "degraded: {reason}"),
+            Self::Disconnected(reason) => write!(f, "disconnected: {reason}"),
+        }
+    }
 }
 
 /// A handle for controlling and monitoring a link.
@@ -444,12 +1042,19 @@ pub struct Link<TAG> {
     pub(crate) cfg: Arc<Cfg>,
     pub(crate) disconnected_rx: watch::Receiver<DisconnectReason>,
     pub(crate) disconnect_tx: mpsc::Sender<()>,
+    pub(crate) draining: Arc<AtomicBool>,
+    pub(crate) drain_tx: mpsc::Sender<()>,
     pub(crate) stats_rx: watch::Receiver<LinkStats>,
     pub(crate) remote_user_data: Arc<Vec<u8>>,
     pub(crate) blocked: Arc<AtomicBool>,
     pub(crate) blocked_changed_tx: mpsc::Sender<()>,
     pub(crate) blocked_changed_rx: watch::Receiver<()>,
     pub(crate) remotely_blocked: Arc<AtomicBool>,
+    pub(crate) weight: Arc<AtomicU32>,
+    pub(crate) rate_limit: Arc<AtomicU64>,
+    pub(crate) rate_limit_changed_tx: mpsc::Sender<()>,
+    pub(crate) segment_size: Arc<AtomicUsize>,
+    pub(crate) path_rx: watch::Receiver<PathStats>,
     pub(crate) not_working_rx: watch::Receiver<Option<(Instant, NotWorkingReason)>>,
 }
 
@@ -463,12 +1068,19 @@ impl<TAG> Clone for Link<TAG> {
             cfg: self.cfg.clone(),
             disconnected_rx: self.disconnected_rx.clone(),
             disconnect_tx: self.disconnect_tx.clone(),
+            draining: self.draining.clone(),
+            drain_tx: self.drain_tx.clone(),
             stats_rx: self.stats_rx.clone(),
             remote_user_data: self.remote_user_data.clone(),
             blocked: self.blocked.clone(),
             blocked_changed_tx: self.blocked_changed_tx.clone(),
             blocked_changed_rx: self.blocked_changed_rx.clone(),
             remotely_blocked: self.remotely_blocked.clone(),
+            weight: self.weight.clone(),
+            rate_limit: self.rate_limit.clone(),
+            rate_limit_changed_tx: self.rate_limit_changed_tx.clone(),
+            segment_size: self.segment_size.clone(),
+            path_rx: self.path_rx.clone(),
             not_working_rx: self.not_working_rx.clone(),
         }
     }
@@ -515,6 +1127,9 @@ where
 }
 
 impl<TAG> Link<TAG> {
+    /// The default link weight.
+    pub const DEFAULT_WEIGHT: u32 = 1;
+
     /// The link id.
     pub fn id(&self) -> LinkId {
         self.link_id
@@ -584,6 +1199,43 @@ impl<TAG> Link<TAG> {
         let _ = self.disconnect_tx.try_send(());
     }
 
+    /// Gracefully drains and then disconnects this link.
+    ///
+    /// No new data is sent over the link, while the other links of the connection
+    /// continue to carry traffic.
+    /// Once all data sent over the link has been acknowledged by the remote endpoint,
+    /// the link is disconnected.
+    ///
+    /// If the [drain timeout](Cfg::link_drain_timeout) elapses before that, the link is
+    /// disconnected anyway and its unacknowledged data is resent over the other links.
+    ///
+    /// Returns `true` if the link was fully drained before it was disconnected
+    /// and `false` if the drain timeout elapsed.
+    /// Draining continues if the returned future is dropped.
+    pub async fn drain(&self) -> bool {
+        self.start_drain();
+        let drained = timeout(self.cfg.link_drain_timeout, self.disconnected()).await.is_ok();
+        if !drained {
+            tracing::debug!("drain timeout of link {} elapsed", self.link_id);
+            self.disconnect().await;
+        }
+        drained
+    }
+
+    /// Starts draining of this link.
+    ///
+    /// Returns immediately.
+    /// The link is disconnected once it has been drained, see [`drain`](Self::drain) for details.
+    pub fn start_drain(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+        let _ = self.drain_tx.try_send(());
+    }
+
+    /// Returns whether the link is being drained.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
     /// Returns whether the link is blocked locally.
     pub fn is_blocked(&self) -> bool {
         self.blocked.load(Ordering::SeqCst)
@@ -597,6 +1249,77 @@ impl<TAG> Link<TAG> {
         let _ = self.blocked_changed_tx.try_send(());
     }
 
+    /// Returns the weight of the link.
+    pub fn weight(&self) -> u32 {
+        self.weight.load(Ordering::SeqCst)
+    }
+
+    /// Sets the weight of the link.
+    ///
+    /// Data is preferably sent over working links with the highest weight.
+    /// A link with a lower weight is only used for sending data while all working links with a
+    /// higher weight have reached their limit of unacknowledged data, i.e. when they are
+    /// fully loaded.
+    /// If all links with a higher weight fail, data is sent over the links with the next lower weight.
+    ///
+    /// By default all links have the weight [`DEFAULT_WEIGHT`](Self::DEFAULT_WEIGHT) and are
+    /// thus used equally.
+    /// The weight only affects sending of data from this endpoint.
+    pub fn set_weight(&self, weight: u32) {
+        self.weight.store(weight, Ordering::SeqCst);
+    }
+
+    /// Returns the limit of bytes sent per second over the link.
+    pub fn rate_limit(&self) -> Option<u64> {
+        match self.rate_limit.load(Ordering::SeqCst) {
+            NO_RATE_LIMIT => None,
+            limit => Some(limit),
+        }
+    }
+
+    /// Sets the limit of bytes sent per second over the link or removes it if `None`.
+    ///
+    /// Sending over the link is throttled using a token bucket that accumulates at most one
+    /// second worth of bytes.
+    /// While the link is throttled, data is sent over the other links of the connection.
+    /// A limit of zero prevents sending data over the link, but it stays connected.
+    ///
+    /// The limit includes protocol overhead and only affects sending from this endpoint.
+    /// It is reported in the [link statistics](LinkStats::rate_limit).
+    pub fn set_rate_limit(&self, bytes_per_sec: Option<u64>) {
+        self.rate_limit.store(bytes_per_sec.unwrap_or(NO_RATE_LIMIT), Ordering::SeqCst);
+        let _ = self.rate_limit_changed_tx.try_send(());
+    }
+
+    /// Returns the preferred size of data segments sent over the link.
+    pub fn segment_size(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(self.segment_size.load(Ordering::SeqCst))
+    }
+
+    /// Sets the preferred size of data segments sent over the link or removes it if `None`.
+    ///
+    /// Data written using [stream-based IO](crate::alc::Stream) is split into segments
+    /// of at most this size when it is sent over the link.
+    /// This allows to use small segments on links with small frames, such as Bluetooth RFCOMM,
+    /// while other links use larger segments.
+    /// Since data is written in packets of at most [`Cfg::io_write_size`] bytes,
+    /// a larger segment size has no effect.
+    ///
+    /// Messages sent using a [`Sender`](crate::alc::Sender) are never split.
+    /// Only sending from this endpoint is affected.
+    pub fn set_segment_size(&self, segment_size: Option<NonZeroUsize>) {
+        self.segment_size.store(segment_size.map(|size| size.get()).unwrap_or_default(), Ordering::SeqCst);
+    }
+
+    /// Returns the path characteristics of the link.
+    ///
+    /// In contrast to the [link statistics](Self::stats) these are updated immediately
+    /// as acknowledgements are received and are thus suitable for adapting application
+    /// behavior to the current condition of each link.
+    pub fn path_stats(&self) -> PathStats {
+        *self.path_rx.borrow()
+    }
+
     /// Returns whether the link is blocked by the remote endpoint.
     pub fn is_remotely_blocked(&self) -> bool {
         self.remotely_blocked.load(Ordering::SeqCst)
@@ -709,14 +1432,80 @@ pub struct LinkStats {
     pub sent_unacked: u64,
     /// Current limit of [`sent_unacked`](Self::sent_unacked).
     pub unacked_limit: u64,
+    /// Total number of packets resent over the link.
+    pub total_resent: u64,
     /// Round trip duration, i.e. ping.
     pub roundtrip: Duration,
     /// Number of times link exceeded timeout.
     pub hangs: usize,
+    /// Whether the link is being drained.
+    ///
+    /// See [`Link::drain`].
+    pub draining: bool,
+    /// Whether the link is blocked locally, i.e. paused.
+    ///
+    /// See [`Link::set_blocked`] and [`Control::pause_link`].
+    pub blocked: bool,
+    /// Whether the link is blocked by the remote endpoint.
+    pub remotely_blocked: bool,
+    /// Health of the link determined by pinging.
+    pub health: LinkHealth,
+    /// Number of consecutive pings that have not been answered in time.
+    pub missed_pings: u32,
+    /// Limit of bytes sent per second over the link.
+    ///
+    /// See [`Link::set_rate_limit`].
+    pub rate_limit: Option<u64>,
     /// Statistics over time intervals specified in the [configuration](crate::cfg::Cfg::stats_intervals).
     pub time_stats: Vec<LinkIntervalStats>,
 }
 
+/// Path characteristics of a link measured from acknowledgements of sent packets.
+///
+/// Each characteristic is `None` until at least 8 samples have been measured on the link.
+///
+/// See [`Link::path_stats`] and [`Control::path_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[non_exhaustive]
+pub struct PathStats {
+    /// Smoothed round trip time.
+    ///
+    /// This is the time from sending a packet over the link until its acknowledgement
+    /// is received, including the processing delay of the remote endpoint.
+    /// It is exponentially smoothed with a weight of 1/8 for each new sample.
+    pub roundtrip: Option<Duration>,
+    /// Observed loss rate as a fraction between 0.0 (no loss) and 1.0 (all packets lost).
+    ///
+    /// A packet is considered lost when it has not been acknowledged in time and is
+    /// thus resent over another link.
+    /// It is exponentially smoothed with a weight of 1/64 for each sent packet.
+    pub loss: Option<f64>,
+}
+
+/// Health of a link determined by pinging.
+///
+/// See [`Cfg::link_ping_max_missed`](crate::cfg::Cfg::link_ping_max_missed) for configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum LinkHealth {
+    /// Link answers pings in time.
+    Alive,
+    /// At least one ping has not been answered in time, but the link has not been removed yet.
+    Suspect,
+    /// Link has been disconnected.
+    Dead,
+}
+
+impl fmt::Display for LinkHealth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Alive => write!(f, "alive"),
+            Self::Suspect => write!(f, "suspect"),
+            Self::Dead => write!(f, "dead"),
+        }
+    }
+}
+
 /// Reason why a link is not working.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum NotWorkingReason {
@@ -3,6 +3,24 @@
 //! All identifier are generated automatically from random numbers
 //! and managed internally.
 //!
+//! # Connection id collisions
+//! Connection ids are 128-bit numbers generated by the connecting side using the
+//! random number generator of the operating system.
+//! Thus the probability that any two of `n` connections share an id is below `n² / 2¹²⁹`,
+//! i.e. less than 10⁻²¹ for a billion connections.
+//!
+//! Nevertheless a [`Server`](crate::Server) does not merge unrelated connections
+//! if a collision occurs.
+//! It regenerates the id of an outgoing connection that collides with a connection
+//! already known to it, and refuses incoming links whose id belongs to a connection
+//! established by another remote server, reporting
+//! [`IncomingError::ConnIdCollision`](crate::connect::IncomingError::ConnIdCollision).
+//! Collisions between connections established using the [`connect`](crate::connect())
+//! function cannot be detected, since these carry no server id.
+//!
+//! A custom [generator](ConnIdGenerator) can be used, for example to obtain deterministic
+//! connection ids in tests.
+//!
 
 use byteorder::{ByteOrder, BE};
 use rand::{random, rngs::OsRng, Rng};
@@ -33,6 +51,38 @@ impl ConnId {
     }
 }
 
+/// Generator of connection ids.
+///
+/// Each generated id should be unique; see the [module documentation](self#connection-id-collisions)
+/// on how collisions are handled.
+///
+/// This is implemented for functions returning a connection id.
+pub trait ConnIdGenerator: Send + Sync + 'static {
+    /// Generates a new connection id.
+    fn generate(&self) -> ConnId;
+}
+
+impl<F> ConnIdGenerator for F
+where
+    F: Fn() -> ConnId + Send + Sync + 'static,
+{
+    fn generate(&self) -> ConnId {
+        self()
+    }
+}
+
+/// Generates connection ids using the random number generator of the operating system.
+///
+/// This is the default generator.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomConnIdGenerator;
+
+impl ConnIdGenerator for RandomConnIdGenerator {
+    fn generate(&self) -> ConnId {
+        ConnId::generate()
+    }
+}
+
 /// Encrypted connection identifier.
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub(crate) struct EncryptedConnId(pub u128);
@@ -79,6 +79,24 @@ impl IntegrityCodec {
         self.max_frame_len = max_packet_size;
     }
 
+    /// Encodes the header of a packet containing `data`, without the data itself.
+    pub(crate) fn encode_header(&mut self, data: &[u8], dst: &mut BytesMut) -> io::Result<()> {
+        if data.len() > self.max_frame_len as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, IntegrityError::PacketTooBig));
+        }
+
+        dst.reserve(Self::HEADER_LEN);
+
+        dst.put_u32(data.len() as u32);
+
+        dst.put_u16(self.encode_seq);
+        self.encode_seq = self.encode_seq.wrapping_add(1);
+
+        dst.put_u32(hash(data));
+
+        Ok(())
+    }
+
     fn decode_header(&mut self, src: &mut BytesMut) -> io::Result<Option<Header>> {
         if src.len() < Self::HEADER_LEN {
             return Ok(None);
@@ -148,19 +166,8 @@ impl Encoder<Bytes> for IntegrityCodec {
     type Error = io::Error;
 
     fn encode(&mut self, data: Bytes, dst: &mut BytesMut) -> io::Result<()> {
-        if data.len() > self.max_frame_len as usize {
-            return Err(io::Error::new(io::ErrorKind::InvalidInput, IntegrityError::PacketTooBig));
-        }
-
         dst.reserve(Self::HEADER_LEN + data.len());
-
-        dst.put_u32(data.len() as u32);
-
-        dst.put_u16(self.encode_seq);
-        self.encode_seq = self.encode_seq.wrapping_add(1);
-
-        dst.put_u32(hash(&data));
-
+        self.encode_header(&data, dst)?;
         dst.extend_from_slice(&data[..]);
 
         Ok(())
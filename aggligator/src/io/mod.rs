@@ -2,6 +2,8 @@
 //!
 //! These wrapper types turn stream-based links into packet-based links
 //! by applying the [integrity codec](IntegrityCodec).
+//! When the underlying writer supports vectored writes, the data of large packets
+//! is passed to it without copying.
 //!
 //! They are applied by the [`Server::add_incoming_io`](crate::connect::Server::add_incoming_io)
 //! and [`Control::add_io`](crate::control::Control::add_io) methods to stream-based links,
@@ -10,21 +12,44 @@
 
 mod codec;
 
-use bytes::Bytes;
-use futures::{Sink, SinkExt, Stream, StreamExt};
+use bytes::{Buf, Bytes, BytesMut};
+use futures::{ready, Sink, Stream, StreamExt};
 use std::{
-    io,
+    collections::VecDeque,
+    io::{self, IoSlice},
     pin::Pin,
     task::{Context, Poll},
 };
 use tokio::io::{AsyncRead, AsyncWrite};
-use tokio_util::codec::{FramedRead, FramedWrite};
+use tokio_util::codec::FramedRead;
 
 pub use codec::*;
 
 /// Transmit wrapper for using an IO-stream-based link.
+///
+/// Headers and small packets are coalesced into a buffer.
+/// The data of large packets is written without copying, if the writer supports
+/// [vectored writes](AsyncWrite::is_write_vectored).
 #[derive(Debug)]
-pub struct IoTx<W>(pub FramedWrite<W, IntegrityCodec>);
+pub struct IoTx<W> {
+    write: W,
+    codec: IntegrityCodec,
+    /// Headers and copied data of small packets.
+    buffer: BytesMut,
+    /// Buffers queued for writing.
+    queue: VecDeque<Bytes>,
+    /// Total length of queued buffers.
+    queued_len: usize,
+}
+
+/// Amount of unwritten data at which writing is started before accepting more packets.
+const BACKPRESSURE_BOUNDARY: usize = 8 * 1_024;
+
+/// Minimum packet size for passing data to the writer without copying.
+const ZERO_COPY_MIN_LEN: usize = 1_024;
+
+/// Maximum number of buffers passed to a vectored write.
+const MAX_IO_SLICES: usize = 64;
 
 impl<W> IoTx<W>
 where
@@ -32,12 +57,95 @@ where
 {
     /// Wraps an IO writer using the default configuration of the integrity codec.
     pub fn new(write: W) -> Self {
-        Self(FramedWrite::new(write, IntegrityCodec::new()))
+        Self::with_codec(write, IntegrityCodec::new())
     }
 
     /// Wraps an IO writer using a customized integrity codec.
     pub fn with_codec(write: W, codec: IntegrityCodec) -> Self {
-        Self(FramedWrite::new(write, codec))
+        Self { write, codec, buffer: BytesMut::new(), queue: VecDeque::new(), queued_len: 0 }
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.write
+    }
+
+    /// Returns a mutable reference to the underlying writer.
+    ///
+    /// Writing to it directly corrupts the packet stream.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.write
+    }
+
+    /// Returns a reference to the integrity codec.
+    pub fn codec(&self) -> &IntegrityCodec {
+        &self.codec
+    }
+
+    /// Returns a mutable reference to the integrity codec.
+    pub fn codec_mut(&mut self) -> &mut IntegrityCodec {
+        &mut self.codec
+    }
+
+    /// Consumes this, returning the underlying writer.
+    ///
+    /// Packets that have not been written yet are lost.
+    pub fn into_inner(self) -> W {
+        self.write
+    }
+
+    /// Length of data that has not been written yet.
+    fn unwritten_len(&self) -> usize {
+        self.buffer.len() + self.queued_len
+    }
+}
+
+impl<W> IoTx<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    /// Writes all buffered and queued data to the writer.
+    fn poll_write_all(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        if !self.buffer.is_empty() {
+            let buffer = self.buffer.split().freeze();
+            self.queued_len += buffer.len();
+            self.queue.push_back(buffer);
+        }
+
+        while let Some(front) = self.queue.front() {
+            let n = if self.write.is_write_vectored() {
+                let mut slices = [IoSlice::new(&[]); MAX_IO_SLICES];
+                let mut count = 0;
+                for (slice, buf) in slices.iter_mut().zip(&self.queue) {
+                    *slice = IoSlice::new(buf);
+                    count += 1;
+                }
+                ready!(Pin::new(&mut self.write).poll_write_vectored(cx, &slices[..count]))?
+            } else {
+                ready!(Pin::new(&mut self.write).poll_write(cx, front))?
+            };
+
+            if n == 0 {
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write to link")));
+            }
+            self.advance(n);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Removes `n` written bytes from the queue.
+    fn advance(&mut self, mut n: usize) {
+        self.queued_len -= n;
+        while n > 0 {
+            let front = self.queue.front_mut().unwrap();
+            if n < front.len() {
+                front.advance(n);
+                break;
+            }
+            n -= front.len();
+            self.queue.pop_front();
+        }
     }
 }
 
@@ -49,21 +157,45 @@ where
 
     #[inline]
     fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
-        Pin::into_inner(self).0.poll_ready_unpin(cx)
+        let this = Pin::into_inner(self);
+
+        if this.unwritten_len() >= BACKPRESSURE_BOUNDARY {
+            ready!(this.poll_write_all(cx))?;
+        }
+
+        Poll::Ready(Ok(()))
     }
 
     #[inline]
     fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
-        Pin::into_inner(self).0.start_send_unpin(item)
+        let this = Pin::into_inner(self);
+
+        this.codec.encode_header(&item, &mut this.buffer)?;
+
+        if item.len() >= ZERO_COPY_MIN_LEN && this.write.is_write_vectored() {
+            let header = this.buffer.split().freeze();
+            this.queued_len += header.len() + item.len();
+            this.queue.push_back(header);
+            this.queue.push_back(item);
+        } else {
+            this.buffer.extend_from_slice(&item);
+        }
+
+        Ok(())
     }
 
     #[inline]
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
-        Pin::into_inner(self).0.poll_flush_unpin(cx)
+        let this = Pin::into_inner(self);
+        ready!(this.poll_write_all(cx))?;
+        Pin::new(&mut this.write).poll_flush(cx)
     }
 
     fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
-        Pin::into_inner(self).0.poll_close_unpin(cx)
+        let this = Pin::into_inner(self);
+        ready!(this.poll_write_all(cx))?;
+        ready!(Pin::new(&mut this.write).poll_flush(cx))?;
+        Pin::new(&mut this.write).poll_shutdown(cx)
     }
 }
 
@@ -95,6 +95,21 @@
 //!
 //! [aggligator-util]: https://docs.rs/aggligator-util/latest/aggligator_util/
 //!
+//! # Diagnostics
+//!
+//! Aggligator emits [tracing] events; they are discarded unless a subscriber is installed.
+//! The task of a connection runs in a `connection` span and each link has its own `link` span,
+//! which carries the connection id, link id and direction as fields.
+//! All events concerning a link, such as retransmissions, timeouts and its disconnection,
+//! are recorded within the span of that link.
+//!
+//! The span of a link is created as a child of the span that is current when the link
+//! is added to the connection.
+//! Thus, to attach the link tag to the events of a link, add the link from within a span
+//! containing the tag.
+//!
+//! [tracing]: https://docs.rs/tracing/
+//!
 
 #[cfg(any(target_pointer_width = "8", target_pointer_width = "16"))]
 compile_error!("target pointer width must be at least 32 bits");
@@ -108,6 +123,8 @@ pub mod id;
 pub mod io;
 mod msg;
 mod peekable_mpsc;
+mod queue_limit;
+pub mod selector;
 mod seq;
 
 #[cfg(feature = "dump")]
@@ -38,6 +38,21 @@ impl<T> PeekableReceiver<T> {
         }
     }
 
+    /// Returns a received message to the front of the queue.
+    ///
+    /// Panics if a message has been peeked at.
+    pub fn unrecv(&mut self, msg: T) {
+        assert!(self.peeked.is_none(), "cannot return message while another message is peeked");
+        self.peeked = Some(msg);
+    }
+
+    /// Closes the receiving half without dropping it.
+    ///
+    /// Messages that have already been sent can still be received.
+    pub fn close(&mut self) {
+        self.rx.close();
+    }
+
     /// Peeks at the next message.
     pub async fn peek(&mut self) -> Option<&T> {
         if self.peeked.is_none() {
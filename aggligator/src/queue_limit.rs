@@ -0,0 +1,64 @@
+//! Limit for the total size of data in a queue.
+
+use std::{future::Future, num::NonZeroU32, sync::Arc};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Creates a queue size limit of the specified number of bytes.
+///
+/// The producer acquires space using [`QueueSpace`] before enqueuing data and
+/// the consumer releases it using [`QueueLimit`] after dequeuing.
+/// Data larger than the limit occupies the whole queue.
+pub fn queue_limit(limit: NonZeroU32) -> (QueueSpace, QueueLimit) {
+    let sem = Arc::new(Semaphore::new(limit.get() as usize));
+    (QueueSpace { limit, sem: sem.clone() }, QueueLimit { limit, sem })
+}
+
+/// Number of permits occupied by data of the specified length.
+fn permits(limit: NonZeroU32, len: usize) -> u32 {
+    len.min(limit.get() as usize) as u32
+}
+
+/// Producer side of a queue size limit.
+#[derive(Debug, Clone)]
+pub struct QueueSpace {
+    limit: NonZeroU32,
+    sem: Arc<Semaphore>,
+}
+
+impl QueueSpace {
+    /// Waits until space for data of the specified length is available.
+    ///
+    /// The space is occupied until the returned permit is dropped.
+    /// Forget the permit once the data has been enqueued.
+    /// Returns `None` if the consumer has been dropped.
+    pub fn acquire(
+        &self, len: usize,
+    ) -> impl Future<Output = Option<OwnedSemaphorePermit>> + Send + Sync + 'static {
+        let sem = self.sem.clone();
+        let permits = permits(self.limit, len);
+        async move { sem.acquire_many_owned(permits).await.ok() }
+    }
+}
+
+/// Consumer side of a queue size limit.
+///
+/// Dropping it releases all producers waiting for space.
+#[derive(Debug)]
+pub struct QueueLimit {
+    limit: NonZeroU32,
+    sem: Arc<Semaphore>,
+}
+
+impl QueueLimit {
+    /// Releases the space occupied by dequeued data of length `len`,
+    /// of which `requeued` bytes have been returned to the front of the queue.
+    pub fn release(&self, len: usize, requeued: usize) {
+        self.sem.add_permits((permits(self.limit, len) - permits(self.limit, requeued)) as usize);
+    }
+}
+
+impl Drop for QueueLimit {
+    fn drop(&mut self) {
+        self.sem.close();
+    }
+}
@@ -0,0 +1,109 @@
+//! Link selection for sending data.
+//!
+//! When data is to be sent, the [connection task](crate::Task) asks its [`LinkSelector`]
+//! which of the links of the connection to use.
+//! By default the [`WeightedLinkSelector`] is used, unless the connection is switched to
+//! [single path mode](crate::control::SendMode::SinglePathLowLatency), which uses the
+//! [`LowLatencyLinkSelector`].
+//! A custom selector can be installed using [`Task::set_link_selector`](crate::Task::set_link_selector),
+//! for example to make link choice deterministic in tests or to implement policies
+//! based on latency.
+
+use std::time::Duration;
+
+use crate::id::LinkId;
+
+/// Information about a link provided to a [`LinkSelector`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct LinkCandidate {
+    /// Link id, as returned by [`Link::id`](crate::Link::id).
+    pub id: LinkId,
+    /// Link weight, as set by [`Link::set_weight`](crate::Link::set_weight).
+    pub weight: u32,
+    /// Current round trip time of the link.
+    pub roundtrip: Duration,
+    /// Whether the link is ready to send data immediately.
+    pub ready: bool,
+    /// Whether the link may send more data.
+    ///
+    /// This is `false` if the amount of unacknowledged data sent over the link or its rate limit
+    /// has been reached.
+    /// A link that is sendable but not ready is busy sending and will become ready soon.
+    pub sendable: bool,
+}
+
+/// Strategy for selecting the link used for sending data.
+pub trait LinkSelector: Send + 'static {
+    /// Selects the link for sending a segment of `len` bytes.
+    ///
+    /// `links` contains all working links of the connection.
+    /// Ready links are listed after all other links in the order they became ready,
+    /// i.e. the link that became ready most recently is listed last.
+    ///
+    /// Returns the index of the selected link within `links`.
+    /// If `None` or a link that is not ready is returned, sending is delayed until
+    /// another link becomes ready, at which point the selector is called again.
+    ///
+    /// The selector may be called multiple times for the same segment.
+    /// It is called from the connection task and should thus execute quickly.
+    fn select(&mut self, links: &[LinkCandidate], len: usize) -> Option<usize>;
+}
+
+/// The default link selector.
+///
+/// It selects the link that became ready most recently, but never a link while a sendable
+/// link with a higher [weight](crate::Link::set_weight) is present.
+/// Thus, links of the same weight are used as fast as they can send, while lower-weight links
+/// only carry data when all higher-weight links are exhausted.
+#[derive(Debug, Clone, Default)]
+pub struct WeightedLinkSelector;
+
+impl LinkSelector for WeightedLinkSelector {
+    fn select(&mut self, links: &[LinkCandidate], _len: usize) -> Option<usize> {
+        let max_weight = links.iter().filter(|link| link.sendable).map(|link| link.weight).max()?;
+        links.iter().rposition(|link| link.ready && link.weight >= max_weight)
+    }
+}
+
+/// A link selector that sends all data over the link with the lowest round trip time.
+///
+/// Only links with the highest [weight](crate::Link::set_weight) are considered.
+/// Once a link has been chosen, it is kept until it stops working or another link
+/// has a considerably lower round trip time.
+/// This avoids reordering delays caused by spreading data over paths of different latencies
+/// at the expense of throughput.
+#[derive(Debug, Clone, Default)]
+pub struct LowLatencyLinkSelector {
+    pinned: Option<LinkId>,
+}
+
+impl LowLatencyLinkSelector {
+    /// Fraction of the round trip time of the pinned link another link must undercut
+    /// to be switched to.
+    const SWITCH_RATIO: f64 = 0.75;
+
+    /// The link all data is currently sent over.
+    pub fn pinned(&self) -> Option<LinkId> {
+        self.pinned
+    }
+}
+
+impl LinkSelector for LowLatencyLinkSelector {
+    fn select(&mut self, links: &[LinkCandidate], _len: usize) -> Option<usize> {
+        let max_weight = links.iter().map(|link| link.weight).max()?;
+        let best = links.iter().filter(|link| link.weight >= max_weight).min_by_key(|link| link.roundtrip)?;
+
+        match self.pinned.and_then(|id| links.iter().find(|link| link.id == id)) {
+            Some(pinned)
+                if pinned.weight >= max_weight
+                    && best.roundtrip.as_secs_f64() >= pinned.roundtrip.as_secs_f64() * Self::SWITCH_RATIO => {}
+            _ => {
+                tracing::debug!("pinning link {} with round trip time {:?}", best.id, best.roundtrip);
+                self.pinned = Some(best.id);
+            }
+        }
+
+        links.iter().position(|link| Some(link.id) == self.pinned)
+    }
+}
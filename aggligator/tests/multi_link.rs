@@ -1,20 +1,31 @@
 //! Multi-link tests.
 
-use aggligator::control::DisconnectReason;
-use futures::{future, join};
+use aggligator::control::{
+    ConnState, Control, DisconnectReason, LinkEvent, LinkEventKind, LinkHealth, NotWorkingReason,
+    RetransmitPolicy, SendMode,
+};
+use futures::{future, join, SinkExt};
 use std::{
     future::IntoFuture,
     iter,
     num::{NonZeroU32, NonZeroUsize},
+    sync::{Arc, Mutex},
     time::Duration,
 };
-use tokio::time::{sleep, timeout};
+use tokio::{
+    io::AsyncWriteExt,
+    sync::broadcast,
+    time::{sleep, timeout, Instant},
+};
 
 use crate::test_data::send_and_verify;
 use aggligator::{
     alc::{RecvError, SendError},
-    cfg::{Cfg, LinkPing},
+    TaskError,
+    cfg::{Cfg, LinkPing, ReorderPolicy},
     connect::{connect, Server},
+    id::{ConnId, LinkId},
+    selector::{LinkCandidate, LinkSelector},
 };
 
 mod test_channel;
@@ -523,3 +534,1525 @@ async fn ten_x_link_timeout() {
         .await
         .unwrap();
 }
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn weighted_links() {
+    const PACKET_SIZE: usize = 1000;
+    const COUNT: usize = 100;
+
+    let cfg = Cfg::default();
+    let (a0_tx, a0_rx, a0_control) = test_channel::channel(Default::default());
+    let (b0_tx, b0_rx, _b0_control) = test_channel::channel(Default::default());
+    let (a1_tx, a1_rx, _a1_control) = test_channel::channel(Default::default());
+    let (b1_tx, b1_rx, _b1_control) = test_channel::channel(Default::default());
+
+    let server_cfg = cfg.clone();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+        server.add_incoming(b1_tx, a1_rx, "1".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let (tx, mut rx) = ch.into_tx_rx();
+        drop(tx);
+        let mut received = 0;
+        while let Some(data) = rx.recv().await.unwrap() {
+            received += data.len();
+        }
+        println!("server: received {received} bytes");
+        assert_eq!(received, 2 * COUNT * PACKET_SIZE);
+
+        task.await.unwrap().unwrap();
+    };
+
+    let client_task = async move {
+        let (task, outgoing, mut control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+
+        let (link0, link1) = future::try_join(
+            control.add(a0_tx, b0_rx, "0".to_string(), &[]),
+            control.add(a1_tx, b1_rx, "1".to_string(), &[]),
+        )
+        .await
+        .unwrap();
+        timeout(Duration::from_secs(1), async {
+            while control.links().len() < 2 {
+                control.links_changed().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(control.set_link_weight(&"0".to_string(), 10));
+        assert_eq!(link0.weight(), 10);
+        assert_eq!(link1.weight(), aggligator::Link::<String>::DEFAULT_WEIGHT);
+
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, rx) = ch.into_tx_rx();
+        drop(rx);
+
+        println!("client: sending over preferred link");
+        for _ in 0..COUNT {
+            tx.send(vec![1; PACKET_SIZE].into()).await.unwrap();
+            sleep(Duration::from_millis(10)).await;
+        }
+        tx.flush().await.unwrap();
+        sleep(Duration::from_millis(500)).await;
+
+        let (stats0, stats1) = (link0.stats(), link1.stats());
+        println!("client: link 0 sent {} bytes, link 1 sent {} bytes", stats0.total_sent, stats1.total_sent);
+        assert!(stats0.total_sent >= (COUNT * PACKET_SIZE) as u64 * 9 / 10);
+        assert!(stats1.total_sent < stats0.total_sent / 10, "low weight link was used");
+
+        println!("client: failing preferred link");
+        a0_control.disconnect().await.unwrap();
+
+        println!("client: sending over remaining link");
+        for _ in 0..COUNT {
+            tx.send(vec![2; PACKET_SIZE].into()).await.unwrap();
+        }
+        drop(tx);
+
+        task.await.unwrap().unwrap();
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn rate_limited_link() {
+    const PACKET_SIZE: usize = 1000;
+    const COUNT: usize = 300;
+    const RATE_LIMIT: u64 = 20_000;
+
+    let cfg = Cfg::default();
+    let (a0_tx, a0_rx, _a0_control) = test_channel::channel(Default::default());
+    let (b0_tx, b0_rx, _b0_control) = test_channel::channel(Default::default());
+    let (a1_tx, a1_rx, _a1_control) = test_channel::channel(Default::default());
+    let (b1_tx, b1_rx, _b1_control) = test_channel::channel(Default::default());
+
+    let server_cfg = cfg.clone();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+        server.add_incoming(b1_tx, a1_rx, "1".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let (tx, mut rx) = ch.into_tx_rx();
+        drop(tx);
+        let mut received = 0;
+        while let Some(data) = rx.recv().await.unwrap() {
+            received += data.len();
+        }
+        println!("server: received {received} bytes");
+        assert_eq!(received, COUNT * PACKET_SIZE);
+
+        task.await.unwrap().unwrap();
+    };
+
+    let client_task = async move {
+        let (task, outgoing, mut control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+
+        let (link0, link1) = future::try_join(
+            control.add(a0_tx, b0_rx, "0".to_string(), &[]),
+            control.add(a1_tx, b1_rx, "1".to_string(), &[]),
+        )
+        .await
+        .unwrap();
+        timeout(Duration::from_secs(1), async {
+            while control.links().len() < 2 {
+                control.links_changed().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(control.set_link_rate_limit(&"0".to_string(), Some(RATE_LIMIT)));
+        assert_eq!(link0.rate_limit(), Some(RATE_LIMIT));
+        assert_eq!(link1.rate_limit(), None);
+
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, rx) = ch.into_tx_rx();
+        drop(rx);
+
+        println!("client: sending over both links");
+        let start = Instant::now();
+        let sent0 = link0.stats().total_sent;
+        for _ in 0..COUNT {
+            tx.send(vec![1; PACKET_SIZE].into()).await.unwrap();
+        }
+        tx.flush().await.unwrap();
+        sleep(Duration::from_millis(500)).await;
+        let elapsed = start.elapsed();
+
+        let (stats0, stats1) = (link0.stats(), link1.stats());
+        println!(
+            "client: link 0 sent {} bytes, link 1 sent {} bytes within {elapsed:?}",
+            stats0.total_sent, stats1.total_sent
+        );
+        assert_eq!(stats0.rate_limit, Some(RATE_LIMIT));
+        assert_eq!(stats1.rate_limit, None);
+        let allowed = RATE_LIMIT as f64 * (elapsed.as_secs_f64() + 1.) + 2. * PACKET_SIZE as f64;
+        assert!(((stats0.total_sent - sent0) as f64) < allowed, "rate limit was exceeded");
+        assert!(stats1.total_sent >= (COUNT * PACKET_SIZE) as u64 / 2, "unlimited link did not pick up slack");
+
+        println!("client: removing rate limit");
+        link0.set_rate_limit(None);
+        timeout(Duration::from_secs(1), async {
+            while link0.stats().rate_limit.is_some() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        drop(tx);
+        task.await.unwrap().unwrap();
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn link_path_stats() {
+    const PACKET_SIZE: usize = 1000;
+    const COUNT: usize = 100;
+    const LATENCY: Duration = Duration::from_millis(20);
+
+    let cfg = Cfg::default();
+    let channel_cfg = test_channel::Cfg { latency: Some(LATENCY), ..Default::default() };
+    let (a_tx, a_rx, _a_control) = test_channel::channel(channel_cfg.clone());
+    let (b_tx, b_rx, _b_control) = test_channel::channel(channel_cfg);
+
+    let server_cfg = cfg.clone();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b_tx, a_rx, "0".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let (tx, mut rx) = ch.into_tx_rx();
+        drop(tx);
+        let mut received = 0;
+        while let Some(data) = rx.recv().await.unwrap() {
+            received += data.len();
+        }
+        assert_eq!(received, COUNT * PACKET_SIZE);
+
+        task.await.unwrap().unwrap();
+    };
+
+    let client_task = async move {
+        let (task, outgoing, control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+
+        let link = control.add(a_tx, b_rx, "0".to_string(), &[]).await.unwrap();
+        let path = link.path_stats();
+        println!("client: path stats of new link: {path:?}");
+        assert_eq!(path.roundtrip, None);
+        assert_eq!(path.loss, None);
+
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, rx) = ch.into_tx_rx();
+        drop(rx);
+        for _ in 0..COUNT {
+            tx.send(vec![1; PACKET_SIZE].into()).await.unwrap();
+        }
+        tx.flush().await.unwrap();
+
+        let path = link.path_stats();
+        println!("client: path stats after sending: {path:?}");
+        assert_eq!(control.path_stats().get("0"), Some(&path));
+        let roundtrip = path.roundtrip.expect("no round trip time measured");
+        assert!(roundtrip >= 2 * LATENCY, "round trip time {roundtrip:?} too short");
+        assert_eq!(path.loss, Some(0.));
+
+        drop(tx);
+        task.await.unwrap().unwrap();
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn drain_link() {
+    const PACKET_SIZE: usize = 1000;
+    const COUNT: usize = 100;
+
+    let cfg = Cfg::default();
+    let (a0_tx, a0_rx, _a0_control) = test_channel::channel(Default::default());
+    let (b0_tx, b0_rx, _b0_control) = test_channel::channel(Default::default());
+    let (a1_tx, a1_rx, _a1_control) = test_channel::channel(Default::default());
+    let (b1_tx, b1_rx, _b1_control) = test_channel::channel(Default::default());
+
+    let server_cfg = cfg.clone();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+        server.add_incoming(b1_tx, a1_rx, "1".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let (tx, mut rx) = ch.into_tx_rx();
+        drop(tx);
+        let mut received = 0;
+        while let Some(data) = rx.recv().await.unwrap() {
+            received += data.len();
+        }
+        println!("server: received {received} bytes");
+        assert_eq!(received, 2 * COUNT * PACKET_SIZE);
+
+        task.await.unwrap().unwrap();
+    };
+
+    let client_task = async move {
+        let (task, outgoing, mut control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+
+        let (link0, link1) = future::try_join(
+            control.add(a0_tx, b0_rx, "0".to_string(), &[]),
+            control.add(a1_tx, b1_rx, "1".to_string(), &[]),
+        )
+        .await
+        .unwrap();
+        timeout(Duration::from_secs(1), async {
+            while control.links().len() < 2 {
+                control.links_changed().await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, rx) = ch.into_tx_rx();
+        drop(rx);
+
+        println!("client: sending over both links");
+        for _ in 0..COUNT {
+            tx.send(vec![1; PACKET_SIZE].into()).await.unwrap();
+        }
+
+        println!("client: draining link 0");
+        assert!(!link0.is_draining());
+        assert_eq!(control.drain_link(&"0".to_string()).await, Some(true));
+        assert!(link0.is_draining());
+        assert!(matches!(link0.disconnect_reason(), Some(DisconnectReason::LocallyRequested)));
+        assert!(!link1.is_disconnected());
+        assert_eq!(control.drain_link(&"0".to_string()).await, None);
+
+        println!("client: sending over remaining link");
+        let sent_before = link1.stats().total_sent;
+        for _ in 0..COUNT {
+            tx.send(vec![2; PACKET_SIZE].into()).await.unwrap();
+        }
+        tx.flush().await.unwrap();
+        sleep(Duration::from_millis(500)).await;
+        assert!(link1.stats().total_sent >= sent_before + (COUNT * PACKET_SIZE) as u64);
+        drop(tx);
+
+        task.await.unwrap().unwrap();
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn link_health() {
+    let cfg = Cfg {
+        link_ping: LinkPing::Periodic(Duration::from_millis(100)),
+        link_ping_timeout: Duration::from_millis(300),
+        link_ping_max_missed: NonZeroU32::new(3).unwrap(),
+        ..Default::default()
+    };
+    let (a0_tx, a0_rx, a0_control) = test_channel::channel(Default::default());
+    let (b0_tx, b0_rx, _b0_control) = test_channel::channel(Default::default());
+    let (a1_tx, a1_rx, _a1_control) = test_channel::channel(Default::default());
+    let (b1_tx, b1_rx, _b1_control) = test_channel::channel(Default::default());
+
+    let server_cfg = cfg.clone();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+        server.add_incoming(b1_tx, a1_rx, "1".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let (tx, mut rx) = ch.into_tx_rx();
+        drop(tx);
+        while rx.recv().await.unwrap().is_some() {}
+
+        task.await.unwrap().unwrap();
+    };
+
+    let client_task = async move {
+        let (task, outgoing, control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+
+        let (link0, link1) = future::try_join(
+            control.add(a0_tx, b0_rx, "0".to_string(), &[]),
+            control.add(a1_tx, b1_rx, "1".to_string(), &[]),
+        )
+        .await
+        .unwrap();
+        let ch = outgoing.connect().await.unwrap();
+
+        sleep(Duration::from_millis(500)).await;
+        assert_eq!(link0.stats().health, LinkHealth::Alive);
+
+        println!("client: pausing link 0 shortly");
+        let pause_control = a0_control.clone();
+        let pause = tokio::spawn(async move { pause_control.pause_for(Duration::from_millis(500)).await });
+        sleep(Duration::from_millis(400)).await;
+        let stats = link0.stats();
+        assert_eq!(stats.health, LinkHealth::Suspect);
+        assert!(stats.missed_pings >= 1);
+        assert!(!link0.is_disconnected());
+
+        pause.await.unwrap().unwrap();
+        sleep(Duration::from_millis(300)).await;
+        assert_eq!(link0.stats().health, LinkHealth::Alive);
+        assert_eq!(link0.stats().missed_pings, 0);
+
+        println!("client: pausing link 0 permanently");
+        tokio::spawn(async move { a0_control.pause_for(Duration::from_secs(10000)).await });
+        let reason = timeout(Duration::from_secs(5), link0.disconnected()).await.unwrap();
+        println!("client: link 0 disconnected: {reason}");
+        assert_eq!(link0.stats().health, LinkHealth::Dead);
+        assert_eq!(link1.stats().health, LinkHealth::Alive);
+
+        drop(ch);
+        task.await.unwrap().unwrap();
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+/// Sends all data over the pinned link.
+struct PinnedLinkSelector(Arc<Mutex<Option<LinkId>>>);
+
+impl LinkSelector for PinnedLinkSelector {
+    fn select(&mut self, links: &[LinkCandidate], _len: usize) -> Option<usize> {
+        let pinned = (*self.0.lock().unwrap())?;
+        links.iter().position(|link| link.id == pinned)
+    }
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn custom_link_selector() {
+    const PACKET_SIZE: usize = 1000;
+    const COUNT: usize = 100;
+
+    let cfg = Cfg::default();
+    let (a0_tx, a0_rx, _a0_control) = test_channel::channel(Default::default());
+    let (b0_tx, b0_rx, _b0_control) = test_channel::channel(Default::default());
+    let (a1_tx, a1_rx, _a1_control) = test_channel::channel(Default::default());
+    let (b1_tx, b1_rx, _b1_control) = test_channel::channel(Default::default());
+
+    let server_cfg = cfg.clone();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+        server.add_incoming(b1_tx, a1_rx, "1".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let (tx, mut rx) = ch.into_tx_rx();
+        drop(tx);
+        let mut received = 0;
+        while let Some(data) = rx.recv().await.unwrap() {
+            received += data.len();
+        }
+        assert_eq!(received, COUNT * PACKET_SIZE);
+
+        task.await.unwrap().unwrap();
+    };
+
+    let client_task = async move {
+        let pinned = Arc::new(Mutex::new(None));
+        let (mut task, outgoing, control) = connect(cfg);
+        task.set_link_selector(PinnedLinkSelector(pinned.clone()));
+        let task = tokio::spawn(task.into_future());
+
+        let (link0, link1) = future::try_join(
+            control.add(a0_tx, b0_rx, "0".to_string(), &[]),
+            control.add(a1_tx, b1_rx, "1".to_string(), &[]),
+        )
+        .await
+        .unwrap();
+        *pinned.lock().unwrap() = Some(link1.id());
+
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, rx) = ch.into_tx_rx();
+        drop(rx);
+
+        println!("client: sending over pinned link");
+        for _ in 0..COUNT {
+            tx.send(vec![1; PACKET_SIZE].into()).await.unwrap();
+        }
+        tx.flush().await.unwrap();
+        sleep(Duration::from_millis(500)).await;
+
+        let (stats0, stats1) = (link0.stats(), link1.stats());
+        println!("client: link 0 sent {} bytes, link 1 sent {} bytes", stats0.total_sent, stats1.total_sent);
+        assert!(stats1.total_sent >= (COUNT * PACKET_SIZE) as u64);
+        assert!(stats0.total_sent < (PACKET_SIZE as u64), "data was sent over unpinned link");
+
+        drop(tx);
+        task.await.unwrap().unwrap();
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn single_path_low_latency() {
+    const PACKET_SIZE: usize = 1000;
+    const COUNT: usize = 100;
+
+    let cfg = Cfg::default();
+    let slow = test_channel::Cfg { latency: Some(Duration::from_millis(50)), ..Default::default() };
+    let fast = test_channel::Cfg { latency: Some(Duration::from_millis(5)), ..Default::default() };
+    let (a0_tx, a0_rx, _a0_control) = test_channel::channel(slow.clone());
+    let (b0_tx, b0_rx, _b0_control) = test_channel::channel(slow);
+    let (a1_tx, a1_rx, _a1_control) = test_channel::channel(fast.clone());
+    let (b1_tx, b1_rx, _b1_control) = test_channel::channel(fast);
+
+    let server_cfg = cfg.clone();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+        server.add_incoming(b1_tx, a1_rx, "1".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let (tx, mut rx) = ch.into_tx_rx();
+        drop(tx);
+        let mut received = 0;
+        while let Some(data) = rx.recv().await.unwrap() {
+            assert!(data.iter().all(|&b| b == 1));
+            received += data.len();
+        }
+        assert_eq!(received, 2 * COUNT * PACKET_SIZE);
+
+        task.await.unwrap().unwrap();
+    };
+
+    let client_task = async move {
+        let (task, outgoing, control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+        assert_eq!(control.send_mode(), SendMode::Aggregate);
+        control.set_send_mode(SendMode::SinglePathLowLatency);
+        assert_eq!(control.send_mode(), SendMode::SinglePathLowLatency);
+
+        let (link0, link1) = future::try_join(
+            control.add(a0_tx, b0_rx, "0".to_string(), &[]),
+            control.add(a1_tx, b1_rx, "1".to_string(), &[]),
+        )
+        .await
+        .unwrap();
+
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, rx) = ch.into_tx_rx();
+        drop(rx);
+
+        println!("client: sending over lowest latency link");
+        for _ in 0..COUNT {
+            tx.send(vec![1; PACKET_SIZE].into()).await.unwrap();
+        }
+        tx.flush().await.unwrap();
+        sleep(Duration::from_millis(500)).await;
+
+        let (stats0, stats1) = (link0.stats(), link1.stats());
+        println!("client: link 0 sent {} bytes, link 1 sent {} bytes", stats0.total_sent, stats1.total_sent);
+        assert!(stats1.total_sent >= (COUNT * PACKET_SIZE) as u64);
+        assert!(stats0.total_sent < (PACKET_SIZE as u64), "data was sent over high latency link");
+
+        println!("client: switching to aggregate mode while sending");
+        for n in 0..COUNT {
+            if n == COUNT / 2 {
+                control.set_send_mode(SendMode::Aggregate);
+            }
+            tx.send(vec![1; PACKET_SIZE].into()).await.unwrap();
+        }
+        tx.flush().await.unwrap();
+
+        drop(tx);
+        task.await.unwrap().unwrap();
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn reorder_buffer_backpressure() {
+    const PACKET_SIZE: usize = 1000;
+    const COUNT: usize = 3000;
+    const REORDER_BUFFER: u32 = 50_000;
+
+    let cfg = Cfg {
+        reorder_buffer: Some(NonZeroU32::new(REORDER_BUFFER).unwrap()),
+        reorder_policy: ReorderPolicy::Backpressure,
+        stats_intervals: vec![Duration::from_millis(10)],
+        ..Default::default()
+    };
+    let slow =
+        test_channel::Cfg { speed: 1_000_000, latency: Some(Duration::from_millis(100)), ..Default::default() };
+    let fast =
+        test_channel::Cfg { speed: 10_000_000, latency: Some(Duration::from_millis(1)), ..Default::default() };
+    let (a0_tx, a0_rx, _a0_control) = test_channel::channel(slow.clone());
+    let (b0_tx, b0_rx, _b0_control) = test_channel::channel(slow);
+    let (a1_tx, a1_rx, _a1_control) = test_channel::channel(fast.clone());
+    let (b1_tx, b1_rx, _b1_control) = test_channel::channel(fast);
+
+    let server_cfg = cfg.clone();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+        server.add_incoming(b1_tx, a1_rx, "1".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, mut control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let (tx, mut rx) = ch.into_tx_rx();
+        drop(tx);
+        let mut received = 0;
+        let mut max_unconsumable = 0;
+        loop {
+            tokio::select! {
+                data = rx.recv() => match data.unwrap() {
+                    Some(data) => received += data.len(),
+                    None => break,
+                },
+                () = control.stats_changed() => {
+                    max_unconsumable = max_unconsumable.max(control.stats_update().recved_unconsumable);
+                }
+            }
+        }
+        assert_eq!(received, COUNT * PACKET_SIZE);
+        println!("server: maximum reorder buffer occupancy was {max_unconsumable} bytes");
+
+        task.await.unwrap().unwrap();
+        max_unconsumable
+    };
+
+    let client_task = async move {
+        let (task, outgoing, mut control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+
+        future::try_join(
+            control.add(a0_tx, b0_rx, "0".to_string(), &[]),
+            control.add(a1_tx, b1_rx, "1".to_string(), &[]),
+        )
+        .await
+        .unwrap();
+
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, rx) = ch.into_tx_rx();
+        drop(rx);
+
+        let send = async {
+            for _ in 0..COUNT {
+                tx.send(vec![1; PACKET_SIZE].into()).await.unwrap();
+            }
+            tx.flush().await.unwrap();
+        };
+        let mut max_unconsumable = 0;
+        let monitor = async {
+            loop {
+                control.stats_changed().await;
+                max_unconsumable = max_unconsumable.max(control.stats_update().sent_unconsumable);
+            }
+        };
+        tokio::select! {
+            () = send => (),
+            () = monitor => unreachable!(),
+        }
+        println!("client: maximum remote reorder buffer occupancy was {max_unconsumable} bytes");
+
+        drop(tx);
+        task.await.unwrap().unwrap();
+        max_unconsumable
+    };
+
+    let (server_max, client_max) =
+        timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+    assert!(server_max > 0, "no reordering occurred");
+    assert!(client_max <= 4 * REORDER_BUFFER as usize, "reorder buffer limit exceeded");
+    assert!(server_max <= 4 * REORDER_BUFFER as usize, "reorder buffer limit exceeded");
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn resume_after_link_loss() {
+    const PACKET_SIZE: usize = 1000;
+    const COUNT: usize = 100;
+
+    let cfg = Cfg { no_link_timeout: Duration::from_secs(30), ..Default::default() };
+    let (a0_tx, a0_rx, a0_control) = test_channel::channel(Default::default());
+    let (b0_tx, b0_rx, b0_control) = test_channel::channel(Default::default());
+    let (a1_tx, a1_rx, _a1_control) = test_channel::channel(Default::default());
+    let (b1_tx, b1_rx, _b1_control) = test_channel::channel(Default::default());
+
+    let server = Server::new(cfg.clone());
+    let mut listener = server.listen().unwrap();
+    let (client_task, outgoing, client_control) = connect(cfg);
+    let client_task = tokio::spawn(client_task.into_future());
+    let (server_res, client_res, incoming) = join!(
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]),
+        client_control.add(a0_tx, b0_rx, "0".to_string(), &[]),
+        async {
+            let (task, ch, control) = listener.next().await.unwrap().accept();
+            (tokio::spawn(task.into_future()), ch, control)
+        }
+    );
+    server_res.unwrap();
+    client_res.unwrap();
+    let (server_task, server_ch, server_control) = incoming;
+    let (server_tx, mut server_rx) = server_ch.into_tx_rx();
+    let (client_tx, _client_rx) = outgoing.connect().await.unwrap().into_tx_rx();
+
+    let wait_for_state = |control: &Control<_, _, String>, pred: fn(ConnState) -> bool| {
+        let mut control = control.clone();
+        async move {
+            timeout(Duration::from_secs(10), async {
+                while !pred(control.state_update()) {
+                    control.state_changed().await;
+                }
+            })
+            .await
+            .expect("state not reached")
+        }
+    };
+
+    wait_for_state(&client_control, |state| state == ConnState::Active).await;
+
+    let receive = tokio::spawn(async move {
+        let mut received = 0;
+        while received < 2 * COUNT * PACKET_SIZE {
+            received += server_rx.recv().await.unwrap().unwrap().len();
+        }
+        received
+    });
+
+    for _ in 0..COUNT {
+        client_tx.send(vec![1; PACKET_SIZE].into()).await.unwrap();
+    }
+    client_tx.flush().await.unwrap();
+
+    tracing::info!("disconnecting all links");
+    a0_control.disconnect().await.unwrap();
+    b0_control.disconnect().await.unwrap();
+    wait_for_state(&client_control, |state| matches!(state, ConnState::Stalled { .. })).await;
+    wait_for_state(&server_control, |state| matches!(state, ConnState::Stalled { .. })).await;
+
+    tracing::info!("sending while stalled");
+    for _ in 0..COUNT {
+        client_tx.send(vec![2; PACKET_SIZE].into()).await.unwrap();
+    }
+    assert!(matches!(client_control.state(), ConnState::Stalled { .. }));
+    assert!(!client_control.is_terminated());
+
+    tracing::info!("adding new link");
+    let (server_res, client_res) = join!(
+        server.add_incoming(b1_tx, a1_rx, "1".to_string(), &[]),
+        client_control.add(a1_tx, b1_rx, "1".to_string(), &[])
+    );
+    server_res.unwrap();
+    client_res.unwrap();
+    wait_for_state(&client_control, |state| state == ConnState::Active).await;
+    wait_for_state(&server_control, |state| state == ConnState::Active).await;
+
+    client_tx.flush().await.unwrap();
+    let received = timeout(Duration::from_secs(10), receive).await.unwrap().unwrap();
+    assert_eq!(received, 2 * COUNT * PACKET_SIZE);
+
+    drop(client_tx);
+    drop(server_tx);
+    client_task.await.unwrap().unwrap();
+    server_task.await.unwrap().unwrap();
+    assert_eq!(client_control.state(), ConnState::Terminated);
+    assert_eq!(server_control.state(), ConnState::Terminated);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn pause_resume_link() {
+    const PACKET_SIZE: usize = 1000;
+    const COUNT: usize = 100;
+
+    let cfg = Cfg::default();
+    let (a0_tx, a0_rx, _a0_control) = test_channel::channel(Default::default());
+    let (b0_tx, b0_rx, _b0_control) = test_channel::channel(Default::default());
+    let (a1_tx, a1_rx, _a1_control) = test_channel::channel(Default::default());
+    let (b1_tx, b1_rx, _b1_control) = test_channel::channel(Default::default());
+
+    let server_cfg = cfg.clone();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+        server.add_incoming(b1_tx, a1_rx, "1".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let (tx, mut rx) = ch.into_tx_rx();
+        drop(tx);
+        let mut received = 0;
+        while let Some(data) = rx.recv().await.unwrap() {
+            received += data.len();
+        }
+        println!("server: received {received} bytes");
+        assert_eq!(received, 2 * COUNT * PACKET_SIZE);
+
+        task.await.unwrap().unwrap();
+    };
+
+    let client_task = async move {
+        let (task, outgoing, mut control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+
+        let (mut link0, link1) = future::try_join(
+            control.add(a0_tx, b0_rx, "0".to_string(), &[]),
+            control.add(a1_tx, b1_rx, "1".to_string(), &[]),
+        )
+        .await
+        .unwrap();
+        timeout(Duration::from_secs(1), async {
+            while control.links().len() < 2 {
+                control.links_changed().await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, rx) = ch.into_tx_rx();
+        drop(rx);
+
+        println!("client: pausing link 0");
+        assert!(control.pause_link(&"0".to_string()));
+        assert!(!control.pause_link(&"2".to_string()));
+        timeout(Duration::from_secs(1), async {
+            while !link0.stats_update().blocked {
+                link0.stats_changed().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(!link1.stats().blocked);
+
+        let sent_before = link0.stats().total_sent;
+        for _ in 0..COUNT {
+            tx.send(vec![1; PACKET_SIZE].into()).await.unwrap();
+        }
+        tx.flush().await.unwrap();
+        sleep(Duration::from_millis(500)).await;
+        let paused_sent = link0.stats().total_sent - sent_before;
+        println!("client: paused link 0 sent {paused_sent} bytes");
+        assert!(paused_sent < PACKET_SIZE as u64, "paused link was used");
+        assert!(!link0.is_disconnected());
+
+        println!("client: resuming link 0 and pausing link 1");
+        assert!(control.resume_link(&"0".to_string()));
+        assert!(control.pause_link(&"1".to_string()));
+        let sent_before = link0.stats().total_sent;
+        for _ in 0..COUNT {
+            tx.send(vec![2; PACKET_SIZE].into()).await.unwrap();
+        }
+        tx.flush().await.unwrap();
+        sleep(Duration::from_millis(500)).await;
+        assert!(!link0.stats().blocked);
+        assert!(link0.stats().total_sent - sent_before >= (COUNT * PACKET_SIZE) as u64 * 9 / 10);
+        drop(tx);
+
+        task.await.unwrap().unwrap();
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn link_segment_size() {
+    const SEGMENT_SIZE: usize = 100;
+    const WRITE_SIZE: usize = 4_000;
+    const MSG_SIZE: usize = 1_000;
+
+    let cfg = Cfg::default();
+    let (a0_tx, a0_rx, _a0_control) = test_channel::channel(Default::default());
+    let (b0_tx, b0_rx, _b0_control) = test_channel::channel(Default::default());
+    let (a1_tx, a1_rx, _a1_control) = test_channel::channel(Default::default());
+    let (b1_tx, b1_rx, _b1_control) = test_channel::channel(Default::default());
+
+    let server_cfg = cfg.clone();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+        server.add_incoming(b1_tx, a1_rx, "1".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let (tx, mut rx) = ch.into_tx_rx();
+        drop(tx);
+        let mut written = 0;
+        while written < WRITE_SIZE {
+            let data = rx.recv().await.unwrap().unwrap();
+            assert!(data.len() <= SEGMENT_SIZE, "received segment of {} bytes", data.len());
+            written += data.len();
+        }
+        assert_eq!(written, WRITE_SIZE);
+        println!("server: received written data in segments");
+
+        let msg = rx.recv().await.unwrap().unwrap();
+        assert_eq!(msg.len(), MSG_SIZE, "message was split");
+        assert!(rx.recv().await.unwrap().is_none());
+
+        task.await.unwrap().unwrap();
+    };
+
+    let client_task = async move {
+        let (task, outgoing, mut control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+
+        let (link0, _link1) = future::try_join(
+            control.add(a0_tx, b0_rx, "0".to_string(), &[]),
+            control.add(a1_tx, b1_rx, "1".to_string(), &[]),
+        )
+        .await
+        .unwrap();
+        timeout(Duration::from_secs(1), async {
+            while control.links().len() < 2 {
+                control.links_changed().await;
+            }
+        })
+        .await
+        .unwrap();
+
+        assert!(control.pause_link(&"1".to_string()));
+        assert!(control.set_link_segment_size(&"0".to_string(), NonZeroUsize::new(SEGMENT_SIZE)));
+        assert_eq!(link0.segment_size(), NonZeroUsize::new(SEGMENT_SIZE));
+
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, rx) = ch.into_tx_rx();
+        drop(rx);
+        let mut sink = tx.into_sink();
+
+        println!("client: writing {WRITE_SIZE} bytes");
+        sink.write_all(&[1; WRITE_SIZE]).await.unwrap();
+        println!("client: sending message of {MSG_SIZE} bytes");
+        sink.send(vec![2; MSG_SIZE].into()).await.unwrap();
+        sink.close().await.unwrap();
+
+        task.await.unwrap().unwrap();
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[tokio::test]
+async fn graceful_shutdown() {
+    const WRITE_SIZE: usize = 100_000;
+
+    let cfg = Cfg::default();
+    let (a0_tx, a0_rx, _a0_control) = test_channel::channel(Default::default());
+    let (b0_tx, b0_rx, _b0_control) = test_channel::channel(Default::default());
+    let (a1_tx, a1_rx, _a1_control) = test_channel::channel(Default::default());
+    let (b1_tx, b1_rx, _b1_control) = test_channel::channel(Default::default());
+
+    let server_cfg = cfg.clone();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+        server.add_incoming(b1_tx, a1_rx, "1".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let (_tx, mut rx) = ch.into_tx_rx();
+        let mut received = 0;
+        while let Some(data) = rx.recv().await.unwrap() {
+            assert!(data.iter().all(|&b| b == 1));
+            received += data.len();
+        }
+        assert_eq!(received, WRITE_SIZE);
+        println!("server: received all data");
+
+        task.await.unwrap().unwrap();
+    };
+
+    let client_task = async move {
+        let (task, outgoing, mut control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+
+        future::try_join(
+            control.add(a0_tx, b0_rx, "0".to_string(), &[]),
+            control.add(a1_tx, b1_rx, "1".to_string(), &[]),
+        )
+        .await
+        .unwrap();
+        timeout(Duration::from_secs(1), async {
+            while control.links().len() < 2 {
+                control.links_changed().await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, mut rx) = ch.into_tx_rx();
+        let mut sink = tx.into_sink();
+
+        println!("client: writing {WRITE_SIZE} bytes without flushing");
+        sink.write_all(&vec![1; WRITE_SIZE]).await.unwrap();
+
+        println!("client: shutting down");
+        control.shutdown(Duration::from_secs(10)).await.unwrap();
+        assert!(control.is_terminated());
+
+        assert!(rx.recv().await.unwrap().is_none());
+        assert_eq!(sink.write_all(&[1]).await.unwrap_err().kind(), std::io::ErrorKind::BrokenPipe);
+
+        task.await.unwrap().unwrap();
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[tokio::test]
+async fn graceful_shutdown_timeout() {
+    let cfg = Cfg::default();
+    let (a0_tx, a0_rx, _a0_control) = test_channel::channel(Default::default());
+    let (b0_tx, b0_rx, b0_control) = test_channel::channel(Default::default());
+
+    let server_cfg = cfg.clone();
+    let (done_tx, done_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, _ch, _control) = incoming.accept();
+        let _task = tokio::spawn(task.into_future());
+
+        let _ = done_rx.await;
+    };
+
+    let client_task = async move {
+        let (task, outgoing, control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+        control.add(a0_tx, b0_rx, "0".to_string(), &[]).await.unwrap();
+
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, _rx) = ch.into_tx_rx();
+
+        println!("client: sending data while acknowledgements are not delivered");
+        tokio::spawn(async move { b0_control.pause_for(Duration::from_secs(30)).await });
+        sleep(Duration::from_millis(100)).await;
+        tx.send(vec![1; 1_000].into()).await.unwrap();
+
+        println!("client: shutting down");
+        let start = Instant::now();
+        let res = control.shutdown(Duration::from_millis(500)).await;
+        assert_eq!(res, Err(TaskError::ShutdownIncomplete));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(task.await.unwrap(), Err(TaskError::ShutdownIncomplete));
+
+        done_tx.send(()).unwrap();
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[tokio::test]
+async fn link_events() {
+    let cfg = Cfg::default();
+    let (a0_tx, a0_rx, a0_control) = test_channel::channel(Default::default());
+    let (b0_tx, b0_rx, _b0_control) = test_channel::channel(Default::default());
+    let (a1_tx, a1_rx, _a1_control) = test_channel::channel(Default::default());
+    let (b1_tx, b1_rx, _b1_control) = test_channel::channel(Default::default());
+
+    let server_cfg = cfg.clone();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+        server.add_incoming(b1_tx, a1_rx, "1".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let (tx, mut rx) = ch.into_tx_rx();
+        drop(tx);
+        while rx.recv().await.unwrap().is_some() {}
+
+        task.await.unwrap().unwrap();
+    };
+
+    let client_task = async move {
+        let (task, outgoing, control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+        let mut events = control.link_events();
+
+        let (link0, _link1) = future::try_join(
+            control.add(a0_tx, b0_rx, "0".to_string(), &[]),
+            control.add(a1_tx, b1_rx, "1".to_string(), &[]),
+        )
+        .await
+        .unwrap();
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, rx) = ch.into_tx_rx();
+        drop(rx);
+
+        let conn_id = control.id();
+        async fn next_event(
+            events: &mut broadcast::Receiver<LinkEvent<String>>, conn_id: ConnId,
+        ) -> LinkEvent<String> {
+            let event = timeout(Duration::from_secs(30), events.recv()).await.unwrap().unwrap();
+            println!("client: link {} event: {}", event.tag, event.kind);
+            assert_eq!(event.conn_id, conn_id);
+            event
+        }
+
+        let mut connected = Vec::new();
+        let mut working = Vec::new();
+        while working.len() < 2 {
+            let event = next_event(&mut events, conn_id).await;
+            match event.kind {
+                LinkEventKind::Connected => connected.push(event.tag),
+                LinkEventKind::Working => {
+                    assert!(connected.contains(&event.tag), "link working before connected");
+                    working.push(event.tag);
+                }
+                other => panic!("unexpected event {other}"),
+            }
+        }
+
+        println!("client: pausing link 0 while sending");
+        tokio::spawn(async move { a0_control.pause_for(Duration::from_secs(5)).await });
+        let sender = tokio::spawn(async move {
+            for _ in 0..100 {
+                if tx.send(vec![1; 1_000].into()).await.is_err() {
+                    break;
+                }
+                sleep(Duration::from_millis(50)).await;
+            }
+            tx
+        });
+
+        let event = next_event(&mut events, conn_id).await;
+        assert_eq!(*event.tag, "0");
+        assert!(matches!(event.kind, LinkEventKind::Degraded(NotWorkingReason::AckTimeout)));
+        let event = next_event(&mut events, conn_id).await;
+        assert_eq!(*event.tag, "0");
+        assert!(matches!(event.kind, LinkEventKind::Working));
+        let tx = sender.await.unwrap();
+
+        println!("client: disconnecting link 0");
+        link0.disconnect().await;
+        let event = next_event(&mut events, conn_id).await;
+        assert_eq!(*event.tag, "0");
+        assert!(matches!(event.kind, LinkEventKind::Disconnected(DisconnectReason::LocallyRequested)));
+
+        drop(tx);
+        task.await.unwrap().unwrap();
+        let event = next_event(&mut events, conn_id).await;
+        assert_eq!(*event.tag, "1");
+        assert!(matches!(event.kind, LinkEventKind::Disconnected(DisconnectReason::ConnectionClosed)));
+        assert!(events.recv().await.is_err());
+    };
+
+    timeout(Duration::from_secs(90), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[tokio::test]
+async fn redundancy() {
+    let cfg = Cfg::default();
+    let (a0_tx, a0_rx, _a0_control) = test_channel::channel(Default::default());
+    let (b0_tx, b0_rx, _b0_control) = test_channel::channel(Default::default());
+    let (a1_tx, a1_rx, _a1_control) = test_channel::channel(Default::default());
+    let (b1_tx, b1_rx, _b1_control) = test_channel::channel(Default::default());
+
+    let server_cfg = cfg.clone();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+        server.add_incoming(b1_tx, a1_rx, "1".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let (tx, mut rx) = ch.into_tx_rx();
+        drop(tx);
+        while rx.recv().await.unwrap().is_some() {}
+
+        task.await.unwrap().unwrap();
+    };
+
+    let client_task = async move {
+        let (task, outgoing, mut control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+        let mut link_count = control.link_count_watch();
+        assert_eq!(control.link_count(), 0);
+        assert!(!control.is_redundant());
+
+        let (link0, _link1) = future::try_join(
+            control.add(a0_tx, b0_rx, "0".to_string(), &[]),
+            control.add(a1_tx, b1_rx, "1".to_string(), &[]),
+        )
+        .await
+        .unwrap();
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, rx) = ch.into_tx_rx();
+        drop(rx);
+
+        println!("client: waiting for redundancy");
+        while !timeout(Duration::from_secs(30), control.redundancy_changed()).await.unwrap() {}
+        assert!(control.is_redundant());
+        assert_eq!(control.link_count(), 2);
+        assert_eq!(*link_count.borrow_and_update(), 2);
+
+        println!("client: disconnecting link 0");
+        link0.disconnect().await;
+        let redundant = timeout(Duration::from_secs(30), control.redundancy_changed()).await.unwrap();
+        assert!(!redundant);
+        assert_eq!(control.link_count(), 1);
+        timeout(Duration::from_secs(30), link_count.changed()).await.unwrap().unwrap();
+        assert_eq!(*link_count.borrow(), 1);
+
+        drop(tx);
+        task.await.unwrap().unwrap();
+        assert_eq!(control.link_count(), 0);
+    };
+
+    timeout(Duration::from_secs(90), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[tokio::test]
+async fn idle_link_probe() {
+    const LATENCY: Duration = Duration::from_millis(100);
+
+    let cfg = Cfg {
+        link_ping: LinkPing::WhenTimedOut,
+        link_idle_probe: Some(Duration::from_millis(500)),
+        stats_intervals: vec![Duration::from_millis(100)],
+        ..Default::default()
+    };
+    let (a0_tx, a0_rx, a0_control) = test_channel::channel(Default::default());
+    let (b0_tx, b0_rx, b0_control) = test_channel::channel(Default::default());
+
+    // Only the client probes, so that pings from the server do not keep the link busy.
+    let server_cfg = Cfg { link_idle_probe: None, ..cfg.clone() };
+    let (done_tx, done_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, _ch, _control) = incoming.accept();
+        let _task = tokio::spawn(task.into_future());
+
+        let _ = done_rx.await;
+    };
+
+    let client_task = async move {
+        let (task, outgoing, control) = connect(cfg);
+        let _task = tokio::spawn(task.into_future());
+        let mut link = control.add(a0_tx, b0_rx, "0".to_string(), &[]).await.unwrap();
+        let _ch = outgoing.connect().await.unwrap();
+
+        timeout(Duration::from_secs(10), async {
+            while !link.is_working() {
+                link.working_changed().await;
+            }
+        })
+        .await
+        .unwrap();
+        let roundtrip = link.stats().roundtrip;
+        println!("client: link working with round trip time {} ms", roundtrip.as_millis());
+
+        println!("client: adding latency to idle link");
+        a0_control.set_latency(Some(LATENCY)).await.unwrap();
+        b0_control.set_latency(Some(LATENCY)).await.unwrap();
+
+        timeout(Duration::from_secs(10), async {
+            while link.stats().roundtrip < LATENCY {
+                link.stats_changed().await;
+            }
+        })
+        .await
+        .expect("idle link was not probed");
+        println!("client: probe measured round trip time {} ms", link.stats().roundtrip.as_millis());
+        assert_eq!(link.stats().missed_pings, 0);
+
+        done_tx.send(()).unwrap();
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[tokio::test]
+async fn queue_size_limits() {
+    const QUEUE_SIZE: u32 = 65_536;
+    const CHUNK: usize = 8_192;
+    const TOTAL: usize = 4_194_304;
+
+    let cfg = Cfg {
+        send_queue_size: NonZeroU32::new(QUEUE_SIZE),
+        recv_queue_size: NonZeroU32::new(QUEUE_SIZE),
+        recv_buffer: NonZeroU32::new(262_144).unwrap(),
+        ..Default::default()
+    };
+    let (a0_tx, a0_rx, _a0_control) = test_channel::channel(Default::default());
+    let (b0_tx, b0_rx, _b0_control) = test_channel::channel(Default::default());
+
+    let server_cfg = cfg.clone();
+    let (start_tx, start_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let _task = tokio::spawn(task.into_future());
+
+        let (_tx, mut rx) = ch.into_tx_rx();
+        start_rx.await.unwrap();
+
+        println!("server: receiving");
+        let mut received = 0;
+        while let Some(data) = rx.recv().await.unwrap() {
+            assert!(data.iter().enumerate().all(|(i, &b)| b == ((received + i) % 251) as u8));
+            received += data.len();
+        }
+        assert_eq!(received, TOTAL);
+        println!("server: received all data");
+    };
+
+    let client_task = async move {
+        let (task, outgoing, control) = connect(cfg);
+        let _task = tokio::spawn(task.into_future());
+        control.add(a0_tx, b0_rx, "0".to_string(), &[]).await.unwrap();
+
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, _rx) = ch.into_tx_rx();
+        let mut sink = tx.into_sink();
+        let data: Vec<u8> = (0..TOTAL).map(|i| (i % 251) as u8).collect();
+
+        println!("client: writing until blocked");
+        let mut written = 0;
+        while timeout(Duration::from_secs(1), sink.write_all(&data[written..written + CHUNK])).await.is_ok() {
+            written += CHUNK;
+            assert!(written <= 1_048_576, "writing was not blocked");
+        }
+        println!("client: writing blocked after {written} bytes");
+
+        start_tx.send(()).unwrap();
+        sink.write_all(&data[written..]).await.unwrap();
+        sink.close().await.unwrap();
+        println!("client: wrote all data");
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn retransmit_policy_fastest() {
+    const PACKET_SIZE: usize = 100;
+    const COUNT: usize = 20;
+    const PAUSE: Duration = Duration::from_secs(3);
+
+    // Data is only resent because of a failed link after a long timeout.
+    let cfg = Cfg {
+        link_ack_timeout_min: Duration::from_secs(10),
+        link_ack_timeout_max: Duration::from_secs(10),
+        link_ping: LinkPing::WhenTimedOut,
+        ..Default::default()
+    };
+    let (a0_tx, a0_rx, a0_control) = test_channel::channel(Default::default());
+    let (b0_tx, b0_rx, _b0_control) = test_channel::channel(Default::default());
+    let (a1_tx, a1_rx, _a1_control) = test_channel::channel(Default::default());
+    let (b1_tx, b1_rx, _b1_control) = test_channel::channel(Default::default());
+
+    let server_cfg = cfg.clone();
+    let (received_tx, received_rx) = tokio::sync::oneshot::channel();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+        server.add_incoming(b1_tx, a1_rx, "1".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let (tx, mut rx) = ch.into_tx_rx();
+        drop(tx);
+        let mut received = 0;
+        while received < COUNT * PACKET_SIZE {
+            received += rx.recv().await.unwrap().unwrap().len();
+        }
+        received_tx.send(Instant::now()).unwrap();
+        assert_eq!(rx.recv().await.unwrap(), None);
+
+        task.await.unwrap().unwrap();
+    };
+
+    let client_task = async move {
+        let (task, outgoing, control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+        assert_eq!(control.retransmit_policy(), RetransmitPolicy::SameLink);
+        control.set_retransmit_policy(RetransmitPolicy::Fastest);
+        assert_eq!(control.retransmit_policy(), RetransmitPolicy::Fastest);
+
+        let (link0, link1) = future::try_join(
+            control.add(a0_tx, b0_rx, "0".to_string(), &[]),
+            control.add(a1_tx, b1_rx, "1".to_string(), &[]),
+        )
+        .await
+        .unwrap();
+
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, rx) = ch.into_tx_rx();
+        drop(rx);
+        sleep(Duration::from_millis(500)).await;
+
+        println!("client: stalling link 0 while sending");
+        tokio::spawn(async move { a0_control.pause_for(PAUSE).await.unwrap() });
+        sleep(Duration::from_millis(100)).await;
+
+        let start = Instant::now();
+        for _ in 0..COUNT {
+            tx.send(vec![1; PACKET_SIZE].into()).await.unwrap();
+            sleep(Duration::from_millis(20)).await;
+        }
+        let elapsed = received_rx.await.unwrap() - start;
+        println!("client: data received after {} ms", elapsed.as_millis());
+        assert!(elapsed < PAUSE - Duration::from_millis(500), "data was held back by stalled link");
+
+        let resent = link0.stats().total_resent + link1.stats().total_resent;
+        println!("client: {resent} packets reissued");
+        assert!(resent > 0);
+
+        drop(tx);
+        task.await.unwrap().unwrap();
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn redundant_send_mode() {
+    const PACKET_SIZE: usize = 100;
+    const COUNT: usize = 20;
+
+    let cfg = Cfg::default();
+    let slow = test_channel::Cfg { latency: Some(Duration::from_millis(300)), ..Default::default() };
+    let (a0_tx, a0_rx, _a0_control) = test_channel::channel(slow.clone());
+    let (b0_tx, b0_rx, _b0_control) = test_channel::channel(slow);
+    let (a1_tx, a1_rx, _a1_control) = test_channel::channel(Default::default());
+    let (b1_tx, b1_rx, _b1_control) = test_channel::channel(Default::default());
+
+    let server_cfg = cfg.clone();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming(b0_tx, a0_rx, "0".to_string(), &[]).await.unwrap();
+        server.add_incoming(b1_tx, a1_rx, "1".to_string(), &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let (tx, mut rx) = ch.into_tx_rx();
+        drop(tx);
+        let mut received = 0;
+        while let Some(data) = rx.recv().await.unwrap() {
+            assert!(data.iter().all(|&b| b == 1));
+            received += data.len();
+        }
+        assert_eq!(received, 2 * COUNT * PACKET_SIZE, "duplicate data was delivered");
+
+        task.await.unwrap().unwrap();
+    };
+
+    let client_task = async move {
+        let (task, outgoing, control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+        control.set_send_mode(SendMode::Redundant);
+        assert_eq!(control.send_mode(), SendMode::Redundant);
+
+        future::try_join(
+            control.add(a0_tx, b0_rx, "0".to_string(), &[]),
+            control.add(a1_tx, b1_rx, "1".to_string(), &[]),
+        )
+        .await
+        .unwrap();
+
+        let ch = outgoing.connect().await.unwrap();
+        let (tx, rx) = ch.into_tx_rx();
+        drop(rx);
+
+        // Wait for the test of the slow link to complete.
+        sleep(Duration::from_secs(2)).await;
+
+        println!("client: sending with copies");
+        for _ in 0..COUNT {
+            tx.send(vec![1; PACKET_SIZE].into()).await.unwrap();
+            sleep(Duration::from_millis(20)).await;
+        }
+        sleep(Duration::from_secs(1)).await;
+
+        let stats = control.stats();
+        println!("client: {} copies sent, {} copies won", stats.reissued, stats.reissued_won);
+        assert!(stats.reissued > 0);
+        assert!(stats.reissued_won > 0, "no copy over the fast link arrived first");
+
+        println!("client: switching to aggregate mode");
+        control.set_send_mode(SendMode::Aggregate);
+        for _ in 0..COUNT {
+            tx.send(vec![1; PACKET_SIZE].into()).await.unwrap();
+            sleep(Duration::from_millis(20)).await;
+        }
+        sleep(Duration::from_secs(1)).await;
+        assert_eq!(control.stats().reissued, stats.reissued, "copies sent in aggregate mode");
+
+        drop(tx);
+        task.await.unwrap().unwrap();
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
@@ -1,18 +1,29 @@
 //! Single-link tests.
 
+use bytes::Bytes;
 use futures::join;
 use std::{
     future::IntoFuture,
+    io::{self, IoSlice},
     num::{NonZeroU32, NonZeroUsize},
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll},
     time::Duration,
 };
-use tokio::time::timeout;
+use tokio::{
+    io::{duplex, split, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf},
+    time::{sleep, timeout, Instant},
+};
 
 use crate::test_data::send_and_verify;
 use aggligator::{
     alc::{RecvError, SendError},
-    cfg::Cfg,
-    connect::{connect, Server},
+    cfg::{Cfg, LinkPing},
+    connect::{connect, IncomingError, Server},
+    control::AddLinkError,
+    id::ConnId,
+    TaskError,
 };
 
 mod test_channel;
@@ -26,6 +37,7 @@ async fn single_link_test(
     let (link_b_tx, link_b_rx, link_b_control) = test_channel::channel(channel_cfg);
 
     let server_cfg = cfg.clone();
+    let conn_stats_interval = server_cfg.conn_stats_interval;
     let server_task = async move {
         println!("server: starting");
         let server = Server::new(server_cfg);
@@ -92,6 +104,18 @@ async fn single_link_test(
         assert!(speed as usize >= expected_speed, "server too slow");
 
         println!("server: link status: {:?}", link.disconnect_reason());
+        if fail_link.is_none() {
+            println!("server: checking connection statistics");
+            let mut conn_stats = control.conn_stats();
+            conn_stats.borrow_and_update();
+            timeout(conn_stats_interval * 3, conn_stats.changed()).await.unwrap().unwrap();
+            let conn_stats = conn_stats.borrow().clone();
+            let link_stats = conn_stats.link(&"incoming").expect("no statistics for link");
+            println!("server: link statistics: {link_stats:?}");
+            assert!(link_stats.total_sent > 0);
+            assert!(link_stats.total_recved > 0);
+        }
+
         if fail_link.is_some() {
             link.disconnected().await;
             match link.disconnect_reason() {
@@ -376,3 +400,192 @@ async fn failed_link() {
 
     single_link_test(ch_cfg, alc_cfg, 16384, 1000, 0, None, Some(100)).await;
 }
+
+/// Writer supporting vectored writes, so that data is passed to it without copying.
+struct VectoredWriter<W>(W);
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for VectoredWriter<W> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>, cx: &mut Context, bufs: &[IoSlice],
+    ) -> Poll<io::Result<usize>> {
+        let data: Vec<u8> = bufs.iter().flat_map(|buf| buf.iter().copied()).collect();
+        Pin::new(&mut self.0).poll_write(cx, &data)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+fn vectored_io<S: AsyncRead + AsyncWrite>(stream: S) -> (ReadHalf<S>, VectoredWriter<WriteHalf<S>>) {
+    let (read, write) = split(stream);
+    (read, VectoredWriter(write))
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn write_bytes() {
+    const SIZE: usize = 1_000_000;
+
+    let data: Bytes = (0..SIZE).map(|n| n as u8).collect();
+    let (client_io, server_io) = duplex(4096);
+    let (client_read, client_write) = vectored_io(client_io);
+    let (server_read, server_write) = vectored_io(server_io);
+
+    let cfg = Cfg::default();
+    let server_cfg = cfg.clone();
+    let server_data = data.clone();
+    let server_task = async move {
+        let server = Server::new(server_cfg);
+        let mut listener = server.listen().unwrap();
+        server.add_incoming_io(server_read, server_write, "incoming", &[]).await.unwrap();
+
+        let incoming = listener.next().await.unwrap();
+        let (task, ch, _control) = incoming.accept();
+        let task = tokio::spawn(task.into_future());
+
+        let mut stream = ch.into_stream();
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.unwrap();
+        assert!(received == server_data, "received data differs");
+        stream.shutdown().await.unwrap();
+        drop(stream);
+
+        task.await.unwrap().unwrap();
+    };
+
+    let client_task = async move {
+        let (task, outgoing, control) = connect(cfg);
+        let task = tokio::spawn(task.into_future());
+        control.add_io(client_read, client_write, "outgoing", &[]).await.unwrap();
+
+        let mut stream = outgoing.connect().await.unwrap().into_stream();
+        stream.write_bytes(data.slice(..SIZE / 2)).await.unwrap();
+        let mut rest = data.slice(SIZE / 2..);
+        while !rest.is_empty() {
+            futures::future::poll_fn(|cx| Pin::new(&mut stream).poll_write_bytes(cx, &mut rest)).await.unwrap();
+        }
+        stream.shutdown().await.unwrap();
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.unwrap();
+        drop(stream);
+
+        task.await.unwrap().unwrap();
+    };
+
+    timeout(Duration::from_secs(60), async { join!(server_task, client_task) }).await.unwrap();
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn conn_id_collision() {
+    let cfg = Cfg::default();
+    let server = Server::new(cfg.clone());
+    let mut listener = server.listen().unwrap();
+
+    // The id of an outgoing connection colliding with a known connection is regenerated.
+    let ids = Mutex::new(vec![ConnId(2), ConnId(1), ConnId(1)]);
+    let client = Server::with_conn_id_generator(cfg.clone(), move || ids.lock().unwrap().pop().unwrap());
+    let (task, outgoing, control) = client.connect();
+    assert_eq!(outgoing.id(), ConnId(1));
+    let (_other_task, other_outgoing, _other_control) = client.connect();
+    assert_eq!(other_outgoing.id(), ConnId(2));
+
+    let _task = tokio::spawn(task.into_future());
+    let (client_io, server_io) = duplex(4096);
+    let (client_read, client_write) = split(client_io);
+    let (server_read, server_write) = split(server_io);
+    let server_task = async {
+        server.add_incoming_io(server_read, server_write, "incoming", &[]).await.unwrap();
+        let incoming = listener.next().await.unwrap();
+        assert_eq!(incoming.id(), ConnId(1));
+        let (task, ch, control) = incoming.accept();
+        (tokio::spawn(task.into_future()), ch, control)
+    };
+    let (client_link, (_server_task, _server_ch, server_control)) = timeout(Duration::from_secs(10), async {
+        join!(control.add_io(client_read, client_write, "outgoing", &[]), server_task)
+    })
+    .await
+    .unwrap();
+    client_link.unwrap();
+    let _ch = outgoing.connect().await.unwrap();
+
+    // A link of an unrelated connection with the same id is refused.
+    let colliding = Server::with_conn_id_generator(cfg, || ConnId(1));
+    let (colliding_task, colliding_outgoing, colliding_control) = colliding.connect();
+    assert_eq!(colliding_outgoing.id(), ConnId(1));
+    let _colliding_task = tokio::spawn(colliding_task.into_future());
+    let (client_io, server_io) = duplex(4096);
+    let (client_read, client_write) = split(client_io);
+    let (server_read, server_write) = split(server_io);
+    let (client_link, server_link) = timeout(Duration::from_secs(10), async {
+        join!(
+            colliding_control.add_io(client_read, client_write, "colliding", &[]),
+            server.add_incoming_io(server_read, server_write, "incoming colliding", &[])
+        )
+    })
+    .await
+    .unwrap();
+    assert!(matches!(client_link, Err(AddLinkError::ConnectionRefused)), "colliding link was not refused");
+    assert!(matches!(server_link, Err(IncomingError::ConnIdCollision)), "collision was not reported");
+    assert_eq!(server_control.links().len(), 1);
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn idle_timeout() {
+    const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+    let cfg = Cfg {
+        idle_timeout: Some(IDLE_TIMEOUT),
+        link_ping: LinkPing::Periodic(Duration::from_millis(100)),
+        ..Default::default()
+    };
+
+    let server = Server::new(cfg.clone());
+    let mut listener = server.listen().unwrap();
+    let (task, outgoing, control) = connect(cfg);
+    let client_task = tokio::spawn(task.into_future());
+
+    let (client_io, server_io) = duplex(4096);
+    let (client_read, client_write) = split(client_io);
+    let (server_read, server_write) = split(server_io);
+    let server_accept = async {
+        server.add_incoming_io(server_read, server_write, "incoming", &[]).await.unwrap();
+        let (task, ch, _control) = listener.next().await.unwrap().accept();
+        (tokio::spawn(task.into_future()), ch)
+    };
+    let (client_link, (server_task, server_ch)) = timeout(Duration::from_secs(10), async {
+        join!(control.add_io(client_read, client_write, "outgoing", &[]), server_accept)
+    })
+    .await
+    .unwrap();
+    client_link.unwrap();
+    let (client_tx, _client_rx) = outgoing.connect().await.unwrap().into_tx_rx();
+    let (_server_tx, mut server_rx) = server_ch.into_tx_rx();
+
+    // Data keeps the connection open beyond the idle timeout.
+    let start = Instant::now();
+    while start.elapsed() < 2 * IDLE_TIMEOUT {
+        client_tx.send(Bytes::from_static(b"data")).await.unwrap();
+        assert_eq!(server_rx.recv().await.unwrap().as_deref(), Some(&b"data"[..]));
+        sleep(IDLE_TIMEOUT / 4).await;
+    }
+
+    // Pings do not keep the connection open.
+    let idle = Instant::now();
+    assert_eq!(timeout(4 * IDLE_TIMEOUT, server_rx.recv()).await.unwrap(), Err(RecvError::IdleTimeout));
+    assert!(idle.elapsed() >= IDLE_TIMEOUT / 2, "connection closed too early");
+    assert_eq!(timeout(4 * IDLE_TIMEOUT, client_task).await.unwrap().unwrap(), Err(TaskError::IdleTimeout));
+    assert_eq!(client_tx.send(Bytes::from_static(b"data")).await, Err(SendError::IdleTimeout));
+    assert_eq!(server_task.await.unwrap(), Err(TaskError::IdleTimeout));
+}